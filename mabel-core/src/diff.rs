@@ -0,0 +1,38 @@
+//! Showing how a note would change before it is written.
+
+use std::fmt::Write;
+
+use similar::TextDiff;
+
+/// Unified diff from `old` to `new`, with `label` as both file names. Empty when they are equal.
+pub fn unified(old: &str, new: &str, label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(2)
+        .header(label, label)
+        .to_string()
+}
+
+/// `diff` (from [`unified`]) with ANSI colors, the way `git diff` shows it in a terminal.
+pub fn colorize(diff: &str) -> String {
+    let mut out = String::with_capacity(diff.len());
+    for line in diff.lines() {
+        let color = if line.starts_with("+++") || line.starts_with("---") {
+            "1"
+        } else if line.starts_with("@@") {
+            "36"
+        } else if line.starts_with('+') {
+            "32"
+        } else if line.starts_with('-') {
+            "31"
+        } else {
+            let _ = writeln!(out, "{line}");
+            continue;
+        };
+        let _ = writeln!(out, "\x1b[{color}m{line}\x1b[0m");
+    }
+    out
+}
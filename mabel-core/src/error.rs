@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid configuration: {msg}")]
+    Config { msg: String },
+
+    #[error("invalid arXiv id or URL: {input}")]
+    InvalidArxivId { input: String },
+
+    #[error("invalid PubMed/PMC id or URL: {input}")]
+    InvalidPubmedId { input: String },
+
+    #[error("invalid DOI or doi.org URL: {input}")]
+    InvalidDoi { input: String },
+
+    #[error("templating error: {0}")]
+    Template(#[from] tera::Error),
+}
@@ -0,0 +1,99 @@
+//! Text fingerprints for knowing a paper again under another file name: a 64-bit simhash of the
+//! opening of its text, so two extractions of the same PDF, or of two downloads of it with a
+//! different cover page or watermark, end up a few bits apart, and different papers about half
+//! the bits apart.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Words of the text that count; about the first three pages of a paper.
+const WORDS: usize = 1500;
+
+/// Words in each shingle hashed into the fingerprint.
+const SHINGLE: usize = 4;
+
+/// Texts shorter than this (in words) say too little to be told apart.
+const MIN_WORDS: usize = 50;
+
+/// Bits two fingerprints may differ in and still be of the same paper.
+pub const MAX_DISTANCE: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    /// The fingerprint of `text`, from its first [`WORDS`] words with case, punctuation and
+    /// layout left out; `None` for a text too short to tell apart from others.
+    pub fn of(text: &str) -> Option<Self> {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .take(WORDS)
+            .map(str::to_lowercase)
+            .collect();
+        if words.len() < MIN_WORDS {
+            return None;
+        }
+        let mut weights = [0i32; 64];
+        for shingle in words.windows(SHINGLE) {
+            let hash = fnv1a(&shingle.join(" "));
+            for (bit, weight) in weights.iter_mut().enumerate() {
+                *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+            }
+        }
+        let bits = weights
+            .iter()
+            .enumerate()
+            .filter(|(_, &w)| w > 0)
+            .fold(0u64, |bits, (bit, _)| bits | 1 << bit);
+        Some(Self(bits))
+    }
+
+    /// How many bits `self` and `other` differ in.
+    pub fn distance(self, other: Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+
+    /// Whether `self` and `other` are close enough to be the same paper.
+    pub fn matches(self, other: Self) -> bool {
+        self.distance(other) <= MAX_DISTANCE
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the standard library's hasher stays the same across releases, as
+/// fingerprints kept in the registry must.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(Self)
+    }
+}
+
+impl From<Fingerprint> for String {
+    fn from(f: Fingerprint) -> Self {
+        f.to_string()
+    }
+}
+
+impl TryFrom<String> for Fingerprint {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
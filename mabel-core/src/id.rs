@@ -0,0 +1,265 @@
+//! Paper identifiers as users type them: bare ids, prefixed ids and landing-page URLs.
+
+use std::fmt;
+
+use url::Url;
+
+use crate::{Error, Result};
+
+/// An arXiv identifier, new style (`2101.00001`, `2101.00001v2`) or old style
+/// (`hep-th/9901001`), stored without any `arXiv:` prefix or URL around it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArxivId(String);
+
+impl ArxivId {
+    /// Accepts bare ids, `arXiv:` prefixed ids, and arxiv.org `abs`/`pdf`/`html` URLs.
+    pub fn parse(input: &str) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidArxivId {
+                input: input.to_string(),
+            }
+        };
+        let s = input.trim();
+
+        if let Some(url) = Url::parse(s).ok().filter(|u| matches!(u.scheme(), "http" | "https")) {
+            if !url.host_str().unwrap_or_default().ends_with("arxiv.org") {
+                return Err(invalid());
+            }
+            // /abs/<id>, /pdf/<id>.pdf, /html/<id>; old-style ids span two segments.
+            let path = url.path().trim_matches('/');
+            let (kind, rest) = path.split_once('/').ok_or_else(invalid)?;
+            if !matches!(kind, "abs" | "pdf" | "html") {
+                return Err(invalid());
+            }
+            let rest = rest.strip_suffix(".pdf").unwrap_or(rest);
+            return Self::parse(rest).map_err(|_| invalid());
+        }
+
+        let id = match s.get(..6) {
+            | Some(prefix) if prefix.eq_ignore_ascii_case("arxiv:") => s[6..].trim(),
+            | _ => s,
+        };
+        if is_new_style(id) || is_old_style(id) {
+            Ok(Self(id.to_string()))
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Cheap check used when guessing the kind of a free-form input.
+    pub fn looks_like(input: &str) -> bool {
+        let s = input.trim();
+        s.to_ascii_lowercase().starts_with("arxiv:") || s.contains("arxiv.org/") || Self::parse(s).is_ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The id without a trailing version (`2101.00001v2` → `2101.00001`).
+    pub fn base(&self) -> &str {
+        split_version(&self.0).0
+    }
+
+    /// The version the id names (`2101.00001v2` → 2), if it names one.
+    pub fn version(&self) -> Option<u32> {
+        split_version(&self.0).1.and_then(|v| v.parse().ok())
+    }
+}
+
+impl fmt::Display for ArxivId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// `YYMM.NNNN` (until 2014) or `YYMM.NNNNN`, optionally followed by `vN`.
+fn is_new_style(id: &str) -> bool {
+    let (id, _) = split_version(id);
+    match id.split_once('.') {
+        | Some((yymm, num)) => yymm.len() == 4 && is_digits(yymm) && (4..=5).contains(&num.len()) && is_digits(num),
+        | None => false,
+    }
+}
+
+/// `archive(.SUBJECT)?/YYMMNNN`, optionally followed by `vN`.
+fn is_old_style(id: &str) -> bool {
+    let (id, _) = split_version(id);
+    match id.split_once('/') {
+        | Some((archive, num)) => {
+            let (name, subject) = archive.split_once('.').unwrap_or((archive, "AA"));
+            !name.is_empty()
+                && name.bytes().all(|b| b.is_ascii_lowercase() || b == b'-')
+                && subject.len() == 2
+                && subject.bytes().all(|b| b.is_ascii_alphabetic())
+                && num.len() == 7
+                && is_digits(num)
+        }
+        | None => false,
+    }
+}
+
+fn split_version(id: &str) -> (&str, Option<&str>) {
+    match id.rfind('v') {
+        | Some(i) if is_digits(&id[i + 1..]) => (&id[..i], Some(&id[i + 1..])),
+        | _ => (id, None),
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PubmedId {
+    /// Numeric PubMed id, e.g. `23193287`
+    Pmid(String),
+    /// PubMed Central id, always stored with its prefix, e.g. `PMC3531190`
+    Pmcid(String),
+}
+
+impl PubmedId {
+    /// Accepts `PMID:123`, `pmid 123`, bare `123`, `PMC123`, `PMCID:PMC123`, and
+    /// pubmed.ncbi.nlm.nih.gov / PMC article URLs.
+    pub fn parse(input: &str) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidPubmedId {
+                input: input.to_string(),
+            }
+        };
+        let s = input.trim();
+
+        if let Some(url) = Url::parse(s).ok().filter(|u| matches!(u.scheme(), "http" | "https")) {
+            let host = url.host_str().unwrap_or_default();
+            if !host.ends_with("ncbi.nlm.nih.gov") {
+                return Err(invalid());
+            }
+            let last = url
+                .path_segments()
+                .and_then(|mut segs| segs.rfind(|seg| !seg.is_empty()))
+                .ok_or_else(invalid)?;
+            return Self::parse(last).map_err(|_| invalid());
+        }
+
+        let lower = s.to_ascii_lowercase();
+        let rest = ["pmcid:", "pmid:", "pmid "]
+            .iter()
+            .find_map(|p| lower.strip_prefix(p).map(|_| s[p.len()..].trim()))
+            .unwrap_or(s);
+
+        if rest.len() > 3 && rest[..3].eq_ignore_ascii_case("pmc") {
+            let digits = &rest[3..];
+            if is_digits(digits) {
+                return Ok(Self::Pmcid(format!("PMC{digits}")));
+            }
+        } else if is_digits(rest) {
+            return Ok(Self::Pmid(rest.to_string()));
+        }
+        Err(invalid())
+    }
+
+    /// Cheap check used when guessing the kind of a free-form input.
+    pub fn looks_like(input: &str) -> bool {
+        let s = input.trim().to_ascii_lowercase();
+        s.starts_with("pmid") || s.starts_with("pmc") || s.contains("ncbi.nlm.nih.gov")
+    }
+}
+
+impl fmt::Display for PubmedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            | Self::Pmid(id) => write!(f, "PMID:{id}"),
+            | Self::Pmcid(id) => f.write_str(id),
+        }
+    }
+}
+
+/// A DOI, e.g. `10.1145/3442188.3445922`, stored without any `doi:` prefix or resolver URL around
+/// it. DOIs are case-insensitive; the case is kept as given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Doi(String);
+
+impl Doi {
+    /// Accepts bare DOIs, `doi:` prefixed ones, and doi.org URLs.
+    pub fn parse(input: &str) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidDoi {
+                input: input.to_string(),
+            }
+        };
+        let s = input.trim();
+
+        if let Some(url) = Url::parse(s).ok().filter(|u| matches!(u.scheme(), "http" | "https")) {
+            let host = url.host_str().unwrap_or_default();
+            if !matches!(host, "doi.org" | "dx.doi.org" | "www.doi.org") {
+                return Err(invalid());
+            }
+            let path = percent_encoding::percent_decode_str(url.path().trim_start_matches('/')).decode_utf8_lossy();
+            return Self::parse(&path).map_err(|_| invalid());
+        }
+
+        let doi = match s.get(..4) {
+            | Some(prefix) if prefix.eq_ignore_ascii_case("doi:") => s[4..].trim(),
+            | _ => s,
+        };
+        // `10.<registrant>/<suffix>`; the registrant code is dot-separated digits, the suffix
+        // anything printable.
+        let (prefix, suffix) = doi.split_once('/').ok_or_else(invalid)?;
+        let registrant = prefix.strip_prefix("10.").ok_or_else(invalid)?;
+        if registrant.split('.').all(is_digits) && !suffix.is_empty() && !suffix.contains(char::is_whitespace) {
+            Ok(Self(doi.to_string()))
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Cheap check used when guessing the kind of a free-form input.
+    pub fn looks_like(input: &str) -> bool {
+        let s = input.trim().to_ascii_lowercase();
+        s.starts_with("10.") || s.starts_with("doi:") || s.contains("doi.org/")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The arXiv paper of a DOI arXiv registered (`10.48550/arXiv.2101.00001`), which is better
+    /// resolved from arXiv itself.
+    pub fn arxiv_id(&self) -> Option<ArxivId> {
+        let suffix = self.0.strip_prefix("10.48550/")?;
+        let id = suffix.get(6..).filter(|_| suffix[..6].eq_ignore_ascii_case("arxiv."))?;
+        ArxivId::parse(id).ok()
+    }
+}
+
+impl fmt::Display for Doi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An ORCID iD in its canonical `0000-0002-1825-0097` form, from that form or an `orcid.org`
+/// URL; `None` for anything else, including iDs whose check digit is wrong.
+pub fn orcid(input: &str) -> Option<String> {
+    let s = input.trim();
+    let s = s
+        .strip_prefix("https://orcid.org/")
+        .or_else(|| s.strip_prefix("http://orcid.org/"))
+        .unwrap_or(s);
+    let chars: Vec<char> = s.chars().filter(|c| *c != '-').collect();
+    if chars.len() != 16 || s.len() != 19 || !chars[..15].iter().all(char::is_ascii_digit) {
+        return None;
+    }
+    // ISO 7064 MOD 11-2 over the first 15 digits.
+    let total = chars[..15]
+        .iter()
+        .fold(0, |acc, c| (acc + c.to_digit(10).unwrap_or_default()) * 2);
+    let check = match (12 - total % 11) % 11 {
+        | 10 => 'X',
+        | d => char::from_digit(d, 10)?,
+    };
+    (chars[15].to_ascii_uppercase() == check).then(|| {
+        let digits: String = chars[..15].iter().chain([&check]).collect();
+        format!("{}-{}-{}-{}", &digits[..4], &digits[4..8], &digits[8..12], &digits[12..])
+    })
+}
@@ -0,0 +1,27 @@
+//! The parts of mabel that neither touch the network nor the filesystem: identifier parsing,
+//! metadata models, note rendering and note diffing. Kept free of I/O so a web UI compiled to
+//! wasm32 renders notes exactly as the CLI does.
+
+#![forbid(unsafe_code)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::must_use_candidate
+)]
+
+pub mod diff;
+pub mod error;
+pub mod fingerprint;
+pub mod id;
+pub mod note;
+pub mod paper;
+pub mod region;
+pub mod render;
+pub mod sentence;
+pub mod summary;
+pub mod thread;
+pub mod venue;
+pub mod verify;
+
+pub use error::{Error, Result};
@@ -0,0 +1,739 @@
+//! Note-level types and frontmatter helpers.
+
+use std::{fmt::Write, str::FromStr};
+
+use chrono::NaiveTime;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::Error;
+
+/// Why a vault note was picked as context for a new paper.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relation {
+    /// The new paper cites it
+    Cited,
+    /// Close to the new paper by embedding, or shares topic tags with it
+    Related,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RelatedNote {
+    pub link: String,
+    pub title: String,
+    pub tldr: Option<String>,
+    pub relation: Relation,
+}
+
+/// The map of content (MOC) note for a paper's research area, named after its arXiv category.
+#[derive(Clone, Debug, Serialize)]
+pub struct Moc {
+    /// arXiv category, e.g. `cs.LG`
+    pub category: String,
+    /// Readable name of the category, e.g. `Machine Learning`
+    pub name: String,
+    /// File stem of the MOC note
+    pub link: String,
+    /// Tag the MOC's Dataview query lists papers by, e.g. `arxiv/cs-lg`
+    pub tag: String,
+}
+
+impl Moc {
+    /// The MOC of `category`, called `<name> MOC`.
+    pub fn new(category: &str, name: &str) -> Self {
+        Self {
+            category: category.to_string(),
+            name: name.to_string(),
+            link: format!("{name} MOC"),
+            tag: format!("arxiv/{}", slug::slugify(category)),
+        }
+    }
+}
+
+/// Split a note into its YAML frontmatter (without the `---` fences) and body.
+pub fn split_frontmatter(text: &str) -> (Option<&str>, &str) {
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (None, text);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, text)
+}
+
+/// A note's frontmatter as a mapping, read leniently: hand-written frontmatter a YAML parser
+/// rejects is read field by field instead, with tabs in indentation taken as two spaces, a value
+/// with an unquoted `: ` in it taken as the string it is, and only the first of a key given twice
+/// kept. A field that still cannot be read is left out. Returns the mapping with what had to be
+/// repaired or left out, one message each; `None` when the frontmatter is no mapping at all.
+pub fn read_frontmatter(yaml: &str) -> Option<(Mapping, Vec<String>)> {
+    match serde_yaml::from_str(yaml) {
+        | Ok(Value::Mapping(map)) => return Some((map, Vec::new())),
+        | Ok(Value::Null) => return Some((Mapping::new(), Vec::new())),
+        | Ok(_) => return None,
+        | Err(_) => {}
+    }
+    let mut problems = Vec::new();
+    let detabbed = detab(yaml);
+    if detabbed != yaml {
+        problems.push("tabs where YAML takes spaces".to_string());
+    }
+    let mut map = Mapping::new();
+    for (key, block) in fields(&detabbed) {
+        let (key, value) = match key {
+            | Some(key) => {
+                let field = serde_yaml::from_str::<Mapping>(block)
+                    .ok()
+                    .and_then(|m| m.into_iter().next());
+                field.unwrap_or((key, Value::Null))
+            }
+            | None if block.trim().is_empty() || block.trim_start().starts_with('#') => continue,
+            | None => {
+                let Some((key, value)) = quoted(block) else {
+                    let first = block.trim_start().lines().next().unwrap_or_default();
+                    problems.push(format!("could not read {first:?}; left out"));
+                    continue;
+                };
+                problems.push(format!("unquoted \": \" in the value of {key:?}"));
+                (Value::from(key), Value::from(value))
+            }
+        };
+        if map.contains_key(&key) {
+            let name = key.as_str().map_or_else(|| format!("{key:?}"), |k| format!("{k:?}"));
+            problems.push(format!("{name} given more than once; the first is kept"));
+            continue;
+        }
+        map.insert(key, value);
+    }
+    Some((map, problems))
+}
+
+/// `yaml` with the tabs in its indentation, and after a key's colon, as two spaces each.
+fn detab(yaml: &str) -> String {
+    yaml.split_inclusive('\n')
+        .map(|line| {
+            let rest = line.trim_start_matches([' ', '\t']);
+            let indent = &line[..line.len() - rest.len()];
+            let line = format!("{}{rest}", indent.replace('\t', "  "));
+            match line.split_once(":\t") {
+                | Some((key, value)) if !key.contains([' ', '"', '\'']) => format!("{key}: {}", value.trim_start()),
+                | _ => line,
+            }
+        })
+        .collect()
+}
+
+/// The key and value of a one-line field whose plain value has a `: ` in it, which YAML reads as
+/// a mapping inside a value.
+fn quoted(block: &str) -> Option<(&str, &str)> {
+    let line = block.trim_end();
+    let (key, value) = line.split_once(": ")?;
+    let value = value.trim();
+    let plain = !key.is_empty()
+        && !line.contains('\n')
+        && !key.starts_with(YAML_INDICATORS)
+        && !value.starts_with(YAML_INDICATORS);
+    (plain && value.contains(": ")).then_some((key.trim(), value))
+}
+
+/// Set a top-level frontmatter field to `value` (already YAML-encoded), replacing an existing
+/// `key:` line or adding one before `type:` (or at the end). Text without frontmatter is returned
+/// unchanged.
+pub fn set_frontmatter_field(text: &str, key: &str, value: &str) -> String {
+    let Some(yaml) = split_frontmatter(text).0 else {
+        return text.to_string();
+    };
+    let start = yaml.as_ptr() as usize - text.as_ptr() as usize;
+    let line = format!("{key}: {value}\n");
+    let prefix = format!("{key}:");
+    let mut lines: Vec<&str> = yaml.split_inclusive('\n').collect();
+    if let Some(i) = lines.iter().position(|l| l.starts_with(&prefix)) {
+        lines[i] = &line;
+    } else {
+        let at = lines.iter().position(|l| l.starts_with("type:")).unwrap_or(lines.len());
+        lines.insert(at, &line);
+    }
+    format!("{}{}{}", &text[..start], lines.concat(), &text[start + yaml.len()..])
+}
+
+/// The frontmatter `fresh` of a regenerated note, keeping what the user added to the note's
+/// `existing` frontmatter: fields the new one does not have, as they were written, and tags the new
+/// one lacks, after its own. Existing frontmatter a YAML parser rejects is read leniently (see
+/// [`read_frontmatter`]) and the fields kept from it are written anew, as valid YAML. Frontmatter
+/// that is not a mapping leaves `fresh` as it is.
+pub fn merge_frontmatter(existing: &str, fresh: &str) -> String {
+    let (Some((old, repaired)), Ok(Value::Mapping(new))) = (read_frontmatter(existing), serde_yaml::from_str(fresh))
+    else {
+        return fresh.to_string();
+    };
+    let key = Value::from("tags");
+    let (old_tags, mut tags) = (tag_list(old.get(&key)), tag_list(new.get(&key)));
+    let before = tags.len();
+    for tag in old_tags {
+        if !tags.iter().any(|t| same_tag(t, &tag)) {
+            tags.push(tag);
+        }
+    }
+    let mut out = String::with_capacity(existing.len() + fresh.len());
+    for (field, block) in fields(fresh) {
+        // A tags field that is not a list is left as the template wrote it.
+        if field.as_ref() != Some(&key) || tags.len() == before || !new.get(&key).is_some_and(Value::is_sequence) {
+            out.push_str(block);
+        } else if block.trim_end().contains('\n') {
+            let mut merged = Mapping::new();
+            merged.insert(key.clone(), Value::Sequence(tags.clone()));
+            out.push_str(&layout(&merged));
+        } else {
+            let _ = writeln!(out, "tags: {}", property(&Value::Sequence(tags.clone()), true));
+        }
+    }
+    let kept: Vec<String> = if repaired.is_empty() {
+        fields(existing)
+            .into_iter()
+            .filter(|(field, _)| field.as_ref().is_some_and(|f| !new.contains_key(f)))
+            .map(|(_, block)| block.to_string())
+            .collect()
+    } else {
+        let kept: Mapping = old.into_iter().filter(|(k, _)| !new.contains_key(k)).collect();
+        vec![layout(&kept)]
+    };
+    for block in kept.iter().filter(|b| !b.is_empty()) {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(block);
+    }
+    out
+}
+
+/// The top-level fields of `yaml` in order, each with its lines: the `key:` line and the indented
+/// or list lines under it. Lines that are not a field (comments, blank lines) have no key.
+fn fields(yaml: &str) -> Vec<(Option<Value>, &str)> {
+    let mut blocks: Vec<std::ops::Range<usize>> = Vec::new();
+    let mut offset = 0;
+    for line in yaml.split_inclusive('\n') {
+        if blocks.is_empty() || !line.starts_with([' ', '\t', '-']) {
+            blocks.push(offset..offset);
+        }
+        offset += line.len();
+        if let Some(block) = blocks.last_mut() {
+            block.end = offset;
+        }
+    }
+    blocks
+        .into_iter()
+        .map(|range| {
+            let block = &yaml[range];
+            let key = match serde_yaml::from_str(block) {
+                | Ok(Value::Mapping(map)) => map.into_iter().next().map(|(k, _)| k),
+                | _ => None,
+            };
+            (key, block)
+        })
+        .collect()
+}
+
+/// The tags a `tags` field holds: its items, or the one tag it names.
+fn tag_list(value: Option<&Value>) -> Vec<Value> {
+    match value {
+        | Some(Value::Sequence(items)) => items.iter().filter(|t| !t.is_null()).cloned().collect(),
+        | Some(tag @ Value::String(_)) => vec![tag.clone()],
+        | _ => Vec::new(),
+    }
+}
+
+/// Whether two tags are the same to Obsidian, which ignores case and a leading `#`.
+fn same_tag(a: &Value, b: &Value) -> bool {
+    match (a.as_str(), b.as_str()) {
+        | (Some(a), Some(b)) => {
+            a.trim()
+                .trim_start_matches('#')
+                .eq_ignore_ascii_case(b.trim().trim_start_matches('#'))
+        }
+        | _ => a == b,
+    }
+}
+
+/// Characters a plain (unquoted) YAML string cannot start with.
+const YAML_INDICATORS: [char; 19] = [
+    '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+];
+
+/// How the frontmatter of the notes mabel writes is laid out (`frontmatter_style`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FrontmatterStyle {
+    /// As the template writes it
+    #[default]
+    Template,
+    /// As Obsidian's properties editor writes it, so that editing a property leaves the rest as
+    /// it was: lists one item per line, timestamps without a UTC offset (which the editor's date
+    /// and time type rejects), and strings quoted only where YAML needs it
+    Properties,
+}
+
+impl FrontmatterStyle {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Template => "template",
+            | Self::Properties => "properties",
+        }
+    }
+
+    /// `note` with its frontmatter laid out in this style. Frontmatter that is not a YAML mapping
+    /// is left as it is.
+    pub fn apply(self, note: &str) -> String {
+        let (Self::Properties, (Some(yaml), body)) = (self, split_frontmatter(note)) else {
+            return note.to_string();
+        };
+        if yaml.trim().is_empty() {
+            return note.to_string();
+        }
+        match properties(yaml) {
+            | Some(yaml) => format!("---\n{yaml}---\n{body}"),
+            | None => {
+                tracing::warn!("the frontmatter is not a YAML mapping; it is written as the template has it");
+                note.to_string()
+            }
+        }
+    }
+}
+
+impl FromStr for FrontmatterStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            | "template" => Ok(Self::Template),
+            | "properties" => Ok(Self::Properties),
+            | _ => {
+                Err(Error::Config {
+                    msg: format!("unknown frontmatter style {s:?} (expected template or properties)"),
+                })
+            }
+        }
+    }
+}
+
+/// A property type of Obsidian's properties editor, as `.obsidian/types.json` names it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyType {
+    Text,
+    /// A list
+    Multitext,
+    Number,
+    Checkbox,
+    Date,
+    Datetime,
+    Aliases,
+    Tags,
+}
+
+impl PropertyType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Text => "text",
+            | Self::Multitext => "multitext",
+            | Self::Number => "number",
+            | Self::Checkbox => "checkbox",
+            | Self::Date => "date",
+            | Self::Datetime => "datetime",
+            | Self::Aliases => "aliases",
+            | Self::Tags => "tags",
+        }
+    }
+
+    /// `value` in the shape the editor takes for this type; values that do not fit are kept as
+    /// they are.
+    fn convert(self, value: Value) -> Value {
+        match (self, value) {
+            | (Self::Text, Value::Sequence(items)) => {
+                let items: Vec<String> = items.iter().map(|item| property(item, false)).collect();
+                Value::String(items.join(", "))
+            }
+            | (Self::Text, value @ (Value::Number(_) | Value::Bool(_))) => Value::String(property(&value, false)),
+            | (Self::Multitext | Self::Aliases | Self::Tags, Value::Null) => Value::Sequence(Vec::new()),
+            | (Self::Multitext | Self::Aliases | Self::Tags, Value::Sequence(items)) => {
+                Value::Sequence(items.into_iter().map(|item| self.item(item)).collect())
+            }
+            | (Self::Multitext | Self::Aliases | Self::Tags, value) => Value::Sequence(vec![self.item(value)]),
+            | (Self::Number, Value::String(s)) => {
+                serde_yaml::from_str::<serde_yaml::Number>(s.trim()).map_or(Value::String(s), Value::Number)
+            }
+            | (Self::Checkbox, Value::String(s)) => {
+                match s.trim().to_ascii_lowercase().as_str() {
+                    | "true" | "yes" => Value::Bool(true),
+                    | "false" | "no" => Value::Bool(false),
+                    | _ => Value::String(s),
+                }
+            }
+            | (Self::Date, Value::String(s)) => {
+                let date = chrono::DateTime::parse_from_rfc3339(&s)
+                    .map(|t| t.date_naive())
+                    .or_else(|_| chrono::NaiveDate::parse_from_str(s.get(..10).unwrap_or_default(), "%Y-%m-%d"));
+                Value::String(date.map_or(s, |d| d.format("%Y-%m-%d").to_string()))
+            }
+            | (Self::Datetime, Value::String(s)) => {
+                let time = chrono::DateTime::parse_from_rfc3339(&s)
+                    .map(|t| t.naive_local())
+                    .or_else(|_| chrono::NaiveDate::parse_from_str(&s, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN)));
+                Value::String(time.map_or(s, |t| t.format("%Y-%m-%dT%H:%M:%S").to_string()))
+            }
+            | (_, value) => value,
+        }
+    }
+
+    /// One item of a list property; tags lose the `#` the editor adds itself.
+    fn item(self, item: Value) -> Value {
+        match (self, item) {
+            | (Self::Tags, Value::String(tag)) => Value::String(tag.trim_start_matches('#').to_string()),
+            | (_, item) => item,
+        }
+    }
+}
+
+impl FromStr for PropertyType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            | "text" => Ok(Self::Text),
+            | "multitext" | "list" => Ok(Self::Multitext),
+            | "number" => Ok(Self::Number),
+            | "checkbox" => Ok(Self::Checkbox),
+            | "date" => Ok(Self::Date),
+            | "datetime" => Ok(Self::Datetime),
+            | "aliases" => Ok(Self::Aliases),
+            | "tags" => Ok(Self::Tags),
+            | _ => {
+                Err(Error::Config {
+                    msg: format!(
+                        "unknown property type {s:?} (expected text, multitext (or list), number, checkbox, date, \
+                         datetime, aliases or tags)"
+                    ),
+                })
+            }
+        }
+    }
+}
+
+/// How one frontmatter field is written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Property {
+    /// The property it is written as; `None` leaves it out
+    pub name: Option<String>,
+    pub kind: Option<PropertyType>,
+    /// Written when the note has no such field, e.g. a reading status of `to-read`
+    pub value: Option<Value>,
+}
+
+impl Property {
+    /// Put `value` into `out` as this property, unless it is left out.
+    fn write(&self, out: &mut Mapping, value: Value) {
+        let Some(name) = &self.name else {
+            return;
+        };
+        let value = match self.kind {
+            | Some(kind) => kind.convert(value),
+            | None => value,
+        };
+        out.insert(Value::String(name.clone()), value);
+    }
+}
+
+/// The `[frontmatter]` config section (`MABEL_FRONTMATTER`): which property each frontmatter field
+/// of a paper note is written as, and of which type, e.g.
+///
+/// ```toml
+/// [frontmatter]
+/// arxiv = "arxiv-id"
+/// published = { name = "date", type = "date" }
+/// status = { value = "to-read" }
+/// aliases = true
+/// model = false
+/// ```
+///
+/// A field is renamed with a string and left out with `false`. `aliases` is not a field the
+/// templates write: naming it adds the paper's citekey and arXiv id as Obsidian aliases. Fields not
+/// named stay as the template writes them. Notes are read back through the same names.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrontmatterFields {
+    fields: Vec<(String, Property)>,
+}
+
+impl FrontmatterFields {
+    /// Parse the section from YAML (or JSON, which is YAML too): a mapping of field to a property
+    /// name, `true`, `false`, or `{ name, type, value }`.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let invalid = |msg: String| {
+            Error::Config {
+                msg: format!("frontmatter fields: {msg}"),
+            }
+        };
+        if spec.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        let Value::Mapping(map) = serde_yaml::from_str(spec).map_err(|e| invalid(e.to_string()))? else {
+            return Err(invalid("expected a table of field = property".to_string()));
+        };
+        let mut fields = Vec::with_capacity(map.len());
+        for (field, spec) in map {
+            let Value::String(field) = field else {
+                return Err(invalid(format!(
+                    "field names must be strings, not {}",
+                    property(&field, true)
+                )));
+            };
+            let name = |value: Option<&Value>| {
+                match value {
+                    | None => Ok(Some(field.clone())),
+                    | Some(Value::String(name)) if !name.trim().is_empty() => Ok(Some(name.trim().to_string())),
+                    | Some(Value::Bool(false)) => Ok(None),
+                    | Some(_) => {
+                        Err(invalid(format!(
+                            "`{field}`: the property name must be a non-empty string"
+                        )))
+                    }
+                }
+            };
+            let property = match &spec {
+                | Value::Bool(true) => {
+                    Property {
+                        name: Some(field.clone()),
+                        ..Property::default()
+                    }
+                }
+                | Value::Bool(false) | Value::String(_) => {
+                    Property {
+                        name: name(Some(&spec))?,
+                        ..Property::default()
+                    }
+                }
+                | Value::Mapping(table) => {
+                    if let Some(key) = table
+                        .keys()
+                        .find(|k| !matches!(k.as_str(), Some("name" | "type" | "value")))
+                    {
+                        return Err(invalid(format!("`{field}`: unknown setting {}", property(key, true))));
+                    }
+                    let kind = match table.get("type") {
+                        | None => None,
+                        | Some(Value::String(kind)) => Some(kind.parse::<PropertyType>()?),
+                        | Some(_) => return Err(invalid(format!("`{field}`: the type must be a string"))),
+                    };
+                    Property {
+                        name: name(table.get("name"))?,
+                        kind,
+                        value: table.get("value").cloned(),
+                    }
+                }
+                | _ => {
+                    return Err(invalid(format!(
+                        "`{field}` must be a property name, true, false or a table of name, type and value"
+                    )))
+                }
+            };
+            fields.push((field, property));
+        }
+        Ok(Self { fields })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The property `field` is written as; `None` when it is left out.
+    pub fn name<'a>(&'a self, field: &'a str) -> Option<&'a str> {
+        match self.fields.iter().find(|(f, _)| f == field) {
+            | Some((_, property)) => property.name.as_deref(),
+            | None => Some(field),
+        }
+    }
+
+    /// The property names given a type, with the type.
+    pub fn types(&self) -> impl Iterator<Item = (&str, PropertyType)> {
+        self.fields
+            .iter()
+            .filter_map(|(_, p)| Some((p.name.as_deref()?, p.kind?)))
+    }
+
+    /// `note` with its frontmatter fields renamed, converted and added as configured, laid out
+    /// the way the properties editor writes it. `aliases` are what the `aliases` field holds.
+    pub fn apply(&self, note: &str, aliases: &[String]) -> String {
+        let (Some(yaml), body) = split_frontmatter(note) else {
+            return note.to_string();
+        };
+        if self.is_empty() {
+            return note.to_string();
+        }
+        let Ok(Value::Mapping(map)) = serde_yaml::from_str::<Value>(yaml) else {
+            tracing::warn!("the frontmatter is not a YAML mapping; its fields are written as the template has them");
+            return note.to_string();
+        };
+        let mut out = Mapping::new();
+        for (key, value) in &map {
+            match key.as_str().and_then(|k| self.fields.iter().find(|(f, _)| f == k)) {
+                | None => {
+                    out.insert(key.clone(), value.clone());
+                }
+                | Some((_, property)) => property.write(&mut out, value.clone()),
+            }
+        }
+        for (field, property) in &self.fields {
+            if map.contains_key(field.as_str()) {
+                continue;
+            }
+            let value = match (field.as_str(), &property.value) {
+                | (_, Some(value)) => value.clone(),
+                | ("aliases", None) if !aliases.is_empty() => {
+                    Value::Sequence(aliases.iter().cloned().map(Value::String).collect())
+                }
+                | _ => continue,
+            };
+            property.write(&mut out, value);
+        }
+        format!("---\n{}---\n{body}", layout(&out))
+    }
+
+    /// Rename the properties of a note's frontmatter back to the fields they hold, to read it.
+    pub fn restore(&self, map: &mut Mapping) {
+        for (field, property) in &self.fields {
+            let Some(name) = property.name.as_deref().filter(|n| n != field) else {
+                continue;
+            };
+            if let Some(value) = map.remove(name) {
+                map.insert(Value::String(field.clone()), value);
+            }
+        }
+    }
+}
+
+/// `arxiv = arxiv-id, published = date (date), model = left out`, for `mabel config show`.
+impl std::fmt::Display for FrontmatterFields {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (field, written)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            match &written.name {
+                | Some(name) => write!(f, "{field} = {name}")?,
+                | None => write!(f, "{field} = left out")?,
+            }
+            if let Some(kind) = written.kind {
+                write!(f, " ({})", kind.as_str())?;
+            }
+            if let Some(value) = &written.value {
+                write!(f, " [{}]", property(value, true))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Frontmatter in the properties editor's layout; `None` if it is not a mapping.
+fn properties(yaml: &str) -> Option<String> {
+    let Ok(Value::Mapping(map)) = serde_yaml::from_str(yaml) else {
+        return None;
+    };
+    Some(layout(&map))
+}
+
+/// The fields of `map`, laid out as the properties editor writes them.
+fn layout(map: &Mapping) -> String {
+    let mut out = String::new();
+    for (key, value) in map {
+        let key = property(key, false);
+        match value {
+            | Value::Sequence(items) if !items.is_empty() => {
+                let _ = writeln!(out, "{key}:");
+                for item in items {
+                    let _ = writeln!(out, "  -{}", spaced(&property(item, false)));
+                }
+            }
+            | value => {
+                let _ = writeln!(out, "{key}:{}", spaced(&property(value, false)));
+            }
+        }
+    }
+    out
+}
+
+/// ` value`, or nothing for an empty value, so that lines do not end in a space.
+fn spaced(value: &str) -> String {
+    if value.is_empty() {
+        String::new()
+    } else {
+        format!(" {value}")
+    }
+}
+
+/// One value as the properties editor writes it. The editor has no type for nested lists and
+/// objects; they are kept in flow style (`[a, b]`, `{a: b}`), which it shows as they are.
+fn property(value: &Value, flow: bool) -> String {
+    match value {
+        | Value::Null => String::new(),
+        | Value::Bool(b) => b.to_string(),
+        | Value::Number(n) => n.to_string(),
+        | Value::String(s) => text(s, flow),
+        | Value::Sequence(items) => {
+            let items: Vec<String> = items.iter().map(|item| property(item, true)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        | Value::Mapping(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", property(k, true), property(v, true)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        | Value::Tagged(tagged) => property(&tagged.value, flow),
+    }
+}
+
+/// A string, plain where YAML reads it back as the same string and double-quoted elsewhere.
+/// RFC 3339 timestamps lose their offset, keeping the local time.
+fn text(s: &str, flow: bool) -> String {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(s) {
+        return time.naive_local().format("%Y-%m-%dT%H:%M:%S").to_string();
+    }
+    let lower = s.to_ascii_lowercase();
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with(YAML_INDICATORS)
+        && !s.ends_with(':')
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.contains(char::is_control)
+        && !(flow && s.contains([',', '[', ']', '{', '}']))
+        && !matches!(
+            lower.as_str(),
+            "true" | "false" | "null" | "~" | "yes" | "no" | "on" | "off"
+        )
+        && s.parse::<f64>().is_err()
+        && !lower.starts_with("0x")
+        && !lower.starts_with("0o")
+        && !lower.starts_with(".inf")
+        && lower != ".nan";
+    if plain {
+        return s.to_string();
+    }
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            | '"' => quoted.push_str("\\\""),
+            | '\\' => quoted.push_str("\\\\"),
+            | '\n' => quoted.push_str("\\n"),
+            | '\t' => quoted.push_str("\\t"),
+            | c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04X}", u32::from(c));
+            }
+            | c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,495 @@
+use std::fmt::Write;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::venue::{self, Venue, VenueKind};
+
+/// Bibliographic metadata for a paper, independent of the source it was resolved from.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PaperMetadata {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// ORCID iD of each author, in the order of `authors` (`None` where unknown); empty when no
+    /// source had any
+    #[serde(default)]
+    pub orcids: Vec<Option<String>>,
+    pub abstract_text: Option<String>,
+    pub published: Option<NaiveDate>,
+    /// Journal or venue name, when the source provides one
+    pub journal: Option<String>,
+    /// The authors' comments on the submission (arXiv), e.g. "Accepted at NeurIPS 2024; 12 pages"
+    pub comment: Option<String>,
+    /// Where the paper was published or accepted; see [`PaperMetadata::detect_venue`]
+    #[serde(default)]
+    pub venue: Option<Venue>,
+    pub keywords: Vec<String>,
+    /// Page count and size of the paper's PDF, when it was at hand before summarizing
+    #[serde(default)]
+    pub pdf: Option<PdfInfo>,
+
+    /// Identifiers
+    pub doi: Option<String>,
+    pub arxiv_id: Option<String>,
+    pub pmid: Option<String>,
+    pub pmcid: Option<String>,
+
+    /// Landing page for the paper
+    pub url: Option<String>,
+}
+
+impl PaperMetadata {
+    /// Fill in the venue from the comments or the journal reference when it is on the built-in
+    /// list. With `journal_is_venue` (sources whose `journal` is always a journal, like PubMed),
+    /// a journal not on the list is taken as it is. The year defaults to the publication year.
+    pub fn detect_venue(&mut self, journal_is_venue: bool) {
+        if self.venue.is_some() {
+            return;
+        }
+        let journal = self.journal.as_deref();
+        let detected = self.comment.as_deref().and_then(venue::detect).or_else(|| journal.and_then(venue::detect));
+        let fallback = || {
+            journal.filter(|_| journal_is_venue).map(|name| {
+                Venue {
+                    name: name.trim().to_string(),
+                    kind: VenueKind::Journal,
+                    year: None,
+                }
+            })
+        };
+        self.venue = detected.or_else(fallback);
+        self.default_venue_year();
+    }
+
+    /// Take the venue the model read off the paper (`stated`, e.g. "ICLR 2024"), when the
+    /// metadata had none.
+    pub fn set_stated_venue(&mut self, stated: &str) {
+        if self.venue.is_none() {
+            self.venue = venue::parse(stated);
+            self.default_venue_year();
+        }
+    }
+
+    fn default_venue_year(&mut self) {
+        let published = self.published.map(|d| d.year());
+        if let Some(v) = &mut self.venue {
+            v.year = v.year.or(published);
+        }
+    }
+
+    /// ORCID iD of the author at `index` in `authors`, if known.
+    pub fn orcid(&self, index: usize) -> Option<&str> {
+        self.orcids.get(index)?.as_deref()
+    }
+
+    /// Fill in ORCID iDs from another record of the same paper, given as `(name, iD)` in that
+    /// record's author order; returns how many were added. Names match on surname and first
+    /// initial, so "Wei Zhang", "W. Zhang" and "Zhang, Wei" are one person. A name that matches
+    /// several of the other record's authors only takes the one in its own position. iDs already
+    /// known are kept.
+    pub fn merge_orcids(&mut self, other: &[(String, Option<String>)]) -> usize {
+        self.orcids.resize(self.authors.len(), None);
+        let mut added = 0;
+        for (i, name) in self.authors.iter().enumerate() {
+            if self.orcids[i].is_some() {
+                continue;
+            }
+            let found = match other.get(i) {
+                | Some((n, id)) if same_person(name, n) => id.clone(),
+                | _ => {
+                    let mut matches = other.iter().filter(|(n, _)| same_person(name, n));
+                    match (matches.next(), matches.next()) {
+                        | (Some((_, id)), None) => id.clone(),
+                        | _ => None,
+                    }
+                }
+            };
+            if found.is_some() {
+                self.orcids[i] = found;
+                added += 1;
+            }
+        }
+        if self.orcids.iter().all(Option::is_none) {
+            self.orcids.clear();
+        }
+        added
+    }
+}
+
+/// Whether two spellings of an author's name can be the same person: same surname and, where both
+/// give one, same first initial; or the same words in another order ("Zhang Wei").
+fn same_person(a: &str, b: &str) -> bool {
+    let (a, b) = (name_parts(a), name_parts(b));
+    let (Some(surname_a), Some(surname_b)) = (a.last(), b.last()) else {
+        return false;
+    };
+    let initial = |parts: &[String]| parts.first().and_then(|p| p.chars().next());
+    if surname_a == surname_b && (a.len() == 1 || b.len() == 1 || initial(&a) == initial(&b)) {
+        return true;
+    }
+    let (mut a, mut b) = (a, b);
+    a.sort();
+    b.sort();
+    a.len() > 1 && a == b
+}
+
+/// Lowercased words of a name, given names first ("Zhang, Wei" becomes `["wei", "zhang"]`).
+fn name_parts(name: &str) -> Vec<String> {
+    let ordered = match name.split_once(',') {
+        | Some((surname, given)) => format!("{given} {surname}"),
+        | None => name.to_string(),
+    };
+    ordered
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .map(|w| w.chars().filter(|c| c.is_alphanumeric() || *c == '-').flat_map(char::to_lowercase).collect())
+        .filter(|w: &String| !w.is_empty())
+        .collect()
+}
+
+/// Standard paper sizes in millimetres, portrait.
+const PAPER_SIZES: &[(&str, f32, f32)] = &[
+    ("A3", 297.0, 420.0),
+    ("A4", 210.0, 297.0),
+    ("A5", 148.0, 210.0),
+    ("B5", 176.0, 250.0),
+    ("Letter", 215.9, 279.4),
+    ("Legal", 215.9, 355.6),
+    ("Tabloid", 279.4, 431.8),
+];
+
+/// How far a page may be off a standard size and still count as it, in millimetres.
+const SIZE_TOLERANCE: f32 = 2.0;
+
+/// What a paper's PDF says about its pages.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PdfInfo {
+    pub pages: u32,
+    /// Size of the first page as it is displayed, in millimetres
+    pub width_mm: f32,
+    pub height_mm: f32,
+    /// The standard size ("A4", "Letter landscape"), else the dimensions ("180 × 240 mm")
+    pub size: String,
+}
+
+impl PdfInfo {
+    pub fn new(pages: u32, width_mm: f32, height_mm: f32) -> Self {
+        let (short, long) = (width_mm.min(height_mm), width_mm.max(height_mm));
+        let size = PAPER_SIZES
+            .iter()
+            .find(|(_, w, h)| (short - w).abs() <= SIZE_TOLERANCE && (long - h).abs() <= SIZE_TOLERANCE)
+            .map_or_else(
+                || format!("{width_mm:.0} × {height_mm:.0} mm"),
+                |(name, ..)| {
+                    if width_mm > height_mm {
+                        format!("{name} landscape")
+                    } else {
+                        (*name).to_string()
+                    }
+                },
+            );
+        let tenths = |mm: f32| (mm * 10.0).round() / 10.0;
+        Self {
+            pages,
+            width_mm: tenths(width_mm),
+            height_mm: tenths(height_mm),
+            size,
+        }
+    }
+}
+
+/// Extracted full text of a paper, split into its logical parts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PaperStructure {
+    pub title: Option<String>,
+    pub abstract_text: Option<String>,
+    pub sections: Vec<Section>,
+    pub figures: Vec<Figure>,
+    pub references: Vec<Reference>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Section {
+    pub heading: String,
+    /// 1 for top-level sections, 2 for subsections, ...
+    pub level: u8,
+    /// Paragraphs joined by blank lines
+    pub text: String,
+    /// How far the text can be trusted to be what the paper says, from 0 to 1; see
+    /// [`Section::new`]. Structures cached before this existed count as fully trusted.
+    #[serde(default = "trusted")]
+    pub confidence: f32,
+}
+
+/// Where a section's text came from, the starting point of its confidence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Extractor {
+    /// Publisher XML (PMC, local `.xml`)
+    Jats,
+    /// EPUB chapters
+    Epub,
+    /// GROBID's reading of a PDF's text layer
+    Grobid,
+    /// mabel's own reading of a PDF's text layer, by layout alone
+    PdfText,
+}
+
+impl Extractor {
+    /// Confidence before looking at the text: markup written by the publisher is taken as is,
+    /// structure inferred from PDF layout less so.
+    fn prior(self) -> f32 {
+        match self {
+            | Extractor::Jats => 1.0,
+            | Extractor::Epub => 0.95,
+            | Extractor::Grobid => 0.85,
+            | Extractor::PdfText => 0.75,
+        }
+    }
+}
+
+/// Below this confidence a section is flagged in the note as possibly garbled.
+pub const LOW_CONFIDENCE: f32 = 0.5;
+
+/// Sections shorter than this many words lose some confidence: a heading followed by a
+/// line or two is more often a split table or a misread page header than a real section.
+const DENSE_WORDS: usize = 60;
+
+fn trusted() -> f32 {
+    1.0
+}
+
+impl Section {
+    /// A section with its confidence worked out from `extractor` and the text itself: how much
+    /// of it there is, and how much of it reads as words rather than extraction debris (stray
+    /// symbols, letters split apart, glyph names).
+    pub fn new(heading: String, level: u8, text: String, extractor: Extractor) -> Self {
+        let confidence = confidence(&text, extractor);
+        Self {
+            heading,
+            level,
+            text,
+            confidence,
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn confidence(text: &str, extractor: Extractor) -> f32 {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    // A heading with only subsections under it has nothing that could be misread.
+    if tokens.is_empty() {
+        return extractor.prior();
+    }
+    let density = (tokens.len() as f32 / DENSE_WORDS as f32).min(1.0);
+    let clean = tokens.iter().filter(|t| is_word(t)).count() as f32 / tokens.len() as f32;
+    // Ordinary prose has some numbers, symbols and citations in it; only noise beyond that counts.
+    let clean = (clean / 0.85).min(1.0);
+    let score = extractor.prior() * (0.7 + 0.3 * density) * clean;
+    (score * 100.0).round() / 100.0
+}
+
+/// A token that reads as a word or number once surrounding punctuation is stripped. Lone
+/// letters other than `a`/`I` and PDF glyph names (`/uniFB01`, `(cid:12)`) do not.
+fn is_word(token: &str) -> bool {
+    if token.contains("cid:") || token.starts_with("/uni") {
+        return false;
+    }
+    let core = token.trim_matches(|c: char| !c.is_alphanumeric());
+    let mut chars = core.chars();
+    match (chars.next(), chars.next()) {
+        | (None, _) => false,
+        | (Some(c), None) => c.is_ascii_digit() || matches!(c, 'a' | 'A' | 'I'),
+        | _ => core.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '\'' | '.' | ',' | '/')),
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Figure {
+    /// e.g. "Figure 2"
+    pub label: Option<String>,
+    pub caption: String,
+    /// Source-relative path or URL of the image, if known
+    pub graphic: Option<String>,
+    /// Where the figure is in the PDF, if the extractor knows (GROBID's figure coordinates)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<FigureRegion>,
+}
+
+/// A box on a PDF page, in points from the page's top-left corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FigureRegion {
+    /// 1-based
+    pub page: u32,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl FigureRegion {
+    /// Parse GROBID's `coords` attribute, `page,x,y,width,height` boxes joined by `;`, into the
+    /// box around those on the first box's page.
+    pub fn parse(coords: &str) -> Option<Self> {
+        let boxes: Vec<[f32; 5]> = coords
+            .split(';')
+            .filter_map(|b| {
+                let v: Vec<f32> = b.split(',').filter_map(|n| n.trim().parse().ok()).collect();
+                <[f32; 5]>::try_from(v).ok()
+            })
+            .collect();
+        let page = boxes.first()?[0];
+        let on_page = boxes.iter().filter(|b| (b[0] - page).abs() < f32::EPSILON);
+        let (mut left, mut top, mut right, mut bottom) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for b in on_page {
+            left = left.min(b[1]);
+            top = top.min(b[2]);
+            right = right.max(b[1] + b[3]);
+            bottom = bottom.max(b[2] + b[4]);
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let page = page as u32;
+        (page > 0 && right > left && bottom > top).then_some(Self {
+            page,
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Reference {
+    pub label: Option<String>,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub year: Option<String>,
+    /// Journal, proceedings, or book the reference appeared in
+    pub source: Option<String>,
+    pub doi: Option<String>,
+    pub pmid: Option<String>,
+    /// arXiv identifier, e.g. "2101.00001"
+    pub arxiv: Option<String>,
+    /// The citation as printed, for when structured fields are missing
+    pub raw: String,
+}
+
+impl PaperStructure {
+    /// Concatenated body text with Markdown-style headings, suitable for prompting.
+    pub fn full_text(&self) -> String {
+        text_of(self.abstract_text.as_deref(), self.sections.iter())
+    }
+
+    /// The abstract (`abstract_text` when the structure has none), introduction and conclusion
+    /// only, with their subsections, laid out as [`PaperStructure::full_text`] does: what is
+    /// summarized when the rest of the text cannot be trusted.
+    pub fn essential_text(&self, abstract_text: Option<&str>) -> String {
+        let mut kept = Vec::new();
+        let mut under: Option<u8> = None;
+        for s in &self.sections {
+            if under.is_some_and(|level| s.level > level) {
+                kept.push(s);
+                continue;
+            }
+            under = is_essential_heading(&s.heading).then_some(s.level);
+            if under.is_some() {
+                kept.push(s);
+            }
+        }
+        text_of(self.abstract_text.as_deref().or(abstract_text), kept.into_iter())
+    }
+
+    /// The structure without the sections `stop` picks by their [`plain_heading`], nor their
+    /// subsections.
+    #[must_use]
+    pub fn without_sections(&self, stop: impl Fn(&str) -> bool) -> Self {
+        let mut sections = Vec::with_capacity(self.sections.len());
+        let mut under: Option<u8> = None;
+        for s in &self.sections {
+            if under.is_some_and(|level| s.level > level) {
+                continue;
+            }
+            under = stop(&plain_heading(&s.heading)).then_some(s.level);
+            if under.is_none() {
+                sections.push(s.clone());
+            }
+        }
+        Self {
+            title: self.title.clone(),
+            abstract_text: self.abstract_text.clone(),
+            sections,
+            figures: self.figures.clone(),
+            references: self.references.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty() && self.abstract_text.is_none()
+    }
+
+    /// Sections below `threshold` confidence, for flagging in notes and for checks that should
+    /// not hold a summary to text that may be garbled.
+    pub fn low_confidence(&self, threshold: f32) -> impl Iterator<Item = &Section> {
+        self.sections.iter().filter(move |s| s.confidence < threshold)
+    }
+
+    /// How far the extracted text as a whole can be trusted, from 0 to 1: the sections'
+    /// confidence, weighted by their length. `None` without any section text.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn quality(&self) -> Option<f32> {
+        let (weighted, total) = self.sections.iter().fold((0.0, 0usize), |(weighted, total), s| {
+            let len = s.text.len();
+            (weighted + s.confidence * len as f32, total + len)
+        });
+        (total > 0).then(|| ((weighted / total as f32) * 100.0).round() / 100.0)
+    }
+}
+
+/// `sections` under the abstract, with Markdown-style headings.
+fn text_of<'a>(abstract_text: Option<&str>, sections: impl Iterator<Item = &'a Section>) -> String {
+    let mut out = String::new();
+    if let Some(abs) = abstract_text {
+        out.push_str("## Abstract\n\n");
+        out.push_str(abs);
+        out.push_str("\n\n");
+    }
+    for s in sections {
+        let hashes = "#".repeat(usize::from(s.level.clamp(1, 4)) + 1);
+        let _ = write!(out, "{hashes} {}\n\n", s.heading);
+        if !s.text.is_empty() {
+            out.push_str(&s.text);
+            out.push_str("\n\n");
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// A heading without its numbering, in lowercase: `7 Acknowledgments` and `VII. ACKNOWLEDGMENTS`
+/// both read `acknowledgments`.
+pub fn plain_heading(heading: &str) -> String {
+    let is_numbering = |t: &str| {
+        t.chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'I' | 'V' | 'X'))
+    };
+    let heading = heading.trim();
+    match heading.split_once(' ') {
+        | Some((number, rest)) if is_numbering(number) => rest.trim_start(),
+        | _ => heading,
+    }
+    .to_lowercase()
+}
+
+/// An introduction or conclusion heading, numbered or not: `1 Introduction`, `VI. CONCLUSIONS`,
+/// `Discussion`, `Summary and Outlook`.
+fn is_essential_heading(heading: &str) -> bool {
+    let heading = plain_heading(heading);
+    [
+        "introduction",
+        "conclusion",
+        "concluding remarks",
+        "discussion",
+        "summary",
+    ]
+    .iter()
+    .any(|h| heading.starts_with(h))
+}
@@ -0,0 +1,194 @@
+//! Managed regions: the parts of a note that mabel owns and may rewrite.
+//!
+//! Templates wrap generated content in named regions:
+//!
+//! ```text
+//! <!-- mabel:begin summary -->
+//! ...
+//! <!-- mabel:end summary -->
+//! ```
+//!
+//! When an existing note is regenerated (`--overwrite`), only the text inside each region and the
+//! frontmatter are replaced; everything else the user wrote is kept, and so are the frontmatter
+//! fields and tags they added (see [`merge_frontmatter`]). Regions are matched by name, so the user
+//! may move them around the note freely. The marker syntax is configurable through
+//! `MABEL_REGION_BEGIN` / `MABEL_REGION_END`, each of which must contain `{name}`.
+
+use std::collections::HashMap;
+
+use crate::{
+    note::{merge_frontmatter, split_frontmatter},
+    Error, Result,
+};
+
+const NAME_PLACEHOLDER: &str = "{name}";
+pub const DEFAULT_BEGIN: &str = "<!-- mabel:begin {name} -->";
+pub const DEFAULT_END: &str = "<!-- mabel:end {name} -->";
+
+/// Begin/end marker patterns, e.g. `<!-- mabel:begin {name} -->`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionMarkers {
+    begin: String,
+    end: String,
+}
+
+impl Default for RegionMarkers {
+    fn default() -> Self {
+        Self {
+            begin: DEFAULT_BEGIN.to_string(),
+            end: DEFAULT_END.to_string(),
+        }
+    }
+}
+
+/// One region found in a note: the byte range of its contents (between the marker lines).
+#[derive(Clone, Debug)]
+struct Region {
+    name: String,
+    /// Start of the begin marker line
+    start: usize,
+    /// Contents, without the marker lines
+    inner: std::ops::Range<usize>,
+    /// End of the end marker line, including its newline
+    end: usize,
+}
+
+impl RegionMarkers {
+    pub fn new(begin: &str, end: &str) -> Result<Self> {
+        let invalid = |msg: &str| {
+            Error::Config {
+                msg: format!("invalid region markers ({begin:?}, {end:?}): {msg}"),
+            }
+        };
+        for marker in [begin, end] {
+            if marker.matches(NAME_PLACEHOLDER).count() != 1 {
+                return Err(invalid("each marker must contain `{name}` exactly once"));
+            }
+            if marker.contains('\n') {
+                return Err(invalid("markers must fit on one line"));
+            }
+        }
+        if begin.trim() == end.trim() {
+            return Err(invalid("begin and end markers must differ"));
+        }
+        Ok(Self {
+            begin: begin.trim().to_string(),
+            end: end.trim().to_string(),
+        })
+    }
+
+    pub fn begin(&self, name: &str) -> String {
+        self.begin.replace(NAME_PLACEHOLDER, name)
+    }
+
+    pub fn end(&self, name: &str) -> String {
+        self.end.replace(NAME_PLACEHOLDER, name)
+    }
+
+    /// Whether `line` opens a managed region.
+    pub fn is_begin(&self, line: &str) -> bool {
+        match_marker(&self.begin, line.trim()).is_some()
+    }
+
+    /// Whether `line` closes a managed region.
+    pub fn is_end(&self, line: &str) -> bool {
+        match_marker(&self.end, line.trim()).is_some()
+    }
+
+    /// Names of the well-formed regions in `text`, in order of appearance.
+    pub fn names(&self, text: &str) -> Vec<String> {
+        self.regions(text).into_iter().map(|r| r.name).collect()
+    }
+
+    /// Update `existing` with the regions and frontmatter of a freshly rendered note. Returns
+    /// `None` when `existing` has no managed regions, i.e. it is not a note mabel can update in
+    /// place.
+    ///
+    /// Regions the template no longer produces are emptied but keep their markers; regions that
+    /// are new (or that the user deleted) are appended at the end of the note.
+    pub fn merge(&self, existing: &str, rendered: &str) -> Option<String> {
+        let old = self.regions(existing);
+        if old.is_empty() {
+            return None;
+        }
+        let new = self.regions(rendered);
+        let fresh: HashMap<&str, &str> = new
+            .iter()
+            .map(|r| (r.name.as_str(), &rendered[r.inner.clone()]))
+            .collect();
+
+        let mut out = String::with_capacity(existing.len().max(rendered.len()));
+        // Frontmatter is generated metadata too: take the new one with what the user added to the
+        // old, and keep the old body layout.
+        let (old_frontmatter, old_body) = split_frontmatter(existing);
+        let (new_frontmatter, _) = split_frontmatter(rendered);
+        let mut cursor = existing.len() - old_body.len();
+        match new_frontmatter {
+            | Some(yaml) => {
+                out.push_str("---\n");
+                match old_frontmatter {
+                    | Some(old) => out.push_str(&merge_frontmatter(old, yaml)),
+                    | None => out.push_str(yaml),
+                }
+                out.push_str("---\n");
+            }
+            | None => out.push_str(&existing[..cursor]),
+        }
+        for region in &old {
+            out.push_str(&existing[cursor..region.inner.start]);
+            out.push_str(fresh.get(region.name.as_str()).copied().unwrap_or_default());
+            cursor = region.inner.end;
+        }
+        out.push_str(&existing[cursor..]);
+
+        for region in new.iter().filter(|r| !old.iter().any(|o| o.name == r.name)) {
+            if !out.ends_with("\n\n") {
+                out.push_str(if out.ends_with('\n') { "\n" } else { "\n\n" });
+            }
+            out.push_str(&rendered[region.start..region.end]);
+        }
+        Some(out)
+    }
+
+    /// Well-formed regions in `text`. A begin marker without a matching end marker, or one nested
+    /// inside another region, is left alone as ordinary text.
+    fn regions(&self, text: &str) -> Vec<Region> {
+        let mut regions = Vec::new();
+        let mut open: Option<(String, usize, usize)> = None;
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+            let trimmed = line.trim();
+            match &open {
+                | None => {
+                    if let Some(name) = match_marker(&self.begin, trimmed) {
+                        open = Some((name.to_string(), line_start, offset));
+                    }
+                }
+                | Some((name, start, inner_start)) => {
+                    if match_marker(&self.end, trimmed) == Some(name.as_str()) {
+                        regions.push(Region {
+                            name: name.clone(),
+                            start: *start,
+                            inner: *inner_start..line_start,
+                            end: offset,
+                        });
+                        open = None;
+                    }
+                }
+            }
+        }
+        if let Some((name, ..)) = open {
+            tracing::warn!(region = %name, "managed region is never closed; treating it as plain text");
+        }
+        regions
+    }
+}
+
+/// The region name if `line` is `pattern` with `{name}` filled in by a single word.
+fn match_marker<'a>(pattern: &str, line: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once(NAME_PLACEHOLDER)?;
+    let name = line.strip_prefix(prefix)?.strip_suffix(suffix)?.trim();
+    (!name.is_empty() && !name.contains(char::is_whitespace)).then_some(name)
+}
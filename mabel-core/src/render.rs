@@ -0,0 +1,315 @@
+//! Note rendering with Tera. Templates come in as strings; reading them from disk is the caller's
+//! business.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use tera::{Context, Tera, Value};
+
+use crate::{
+    note::{FrontmatterFields, FrontmatterStyle, Moc, RelatedNote},
+    paper::{PaperMetadata, Section},
+    region::RegionMarkers,
+    summary::{BookSummary, ChapterSummary, FigureImage, Summary},
+    Result,
+};
+
+pub const PAPER_TEMPLATE: &str = include_str!("../../templates/paper_note.md.tera");
+pub const BOOK_TEMPLATE: &str = include_str!("../../templates/book_note.md.tera");
+pub const MOC_TEMPLATE: &str = include_str!("../../templates/moc.md.tera");
+pub const CONCEPT_TEMPLATE: &str = include_str!("../../templates/concept.md.tera");
+pub const THREAD_TEMPLATE: &str = include_str!("../../templates/thread.md.tera");
+
+const PAPER: &str = "paper";
+const BOOK: &str = "book";
+const MOC: &str = "moc";
+const CONCEPT: &str = "concept";
+const THREAD: &str = "thread";
+
+/// Post length the `thread` filter keeps to when the template does not say: Mastodon's.
+const THREAD_CHARS: usize = 500;
+
+/// Everything the paper template can reference.
+#[derive(Debug, Serialize)]
+pub struct PaperNote<'a> {
+    #[serde(flatten)]
+    pub metadata: &'a PaperMetadata,
+    pub summary: &'a Summary,
+    /// The figures embedded in the note, linked or saved to the attachments folder
+    pub figures: &'a [FigureImage],
+    /// Vault notes on cited/related work that were given to the model
+    pub related: &'a [RelatedNote],
+    /// Extracted full-text sections, each with its confidence; empty when summarized from the
+    /// abstract
+    pub sections: &'a [Section],
+    /// Headings of the sections whose confidence is below [`crate::paper::LOW_CONFIDENCE`]
+    pub low_confidence: Vec<&'a str>,
+    /// The paper's BibTeX entry
+    pub bibtex: &'a BibEntry,
+    /// The MOC of the paper's arXiv category, when MOCs are on
+    pub moc: Option<&'a Moc>,
+    /// What the user passed on the command line
+    pub source: &'a str,
+    pub created: String,
+    pub model: &'a str,
+    pub mode: &'a str,
+}
+
+/// A BibTeX entry, under the key the paper has (or gets) in the vault's `.bib` file when there
+/// is one.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BibEntry {
+    pub key: String,
+    /// The whole entry, `@article{key, ...}` and a closing newline
+    pub entry: String,
+}
+
+/// Everything the book template can reference.
+#[derive(Debug, Serialize)]
+pub struct BookNote<'a> {
+    pub title: &'a str,
+    pub authors: &'a [String],
+    pub published: Option<String>,
+    pub publisher: Option<&'a str>,
+    pub keywords: Vec<String>,
+    pub source_path: String,
+    pub created: String,
+    pub overview: &'a str,
+    pub chapters: &'a [ChapterSummary],
+    pub selection: Option<String>,
+}
+
+impl<'a> BookNote<'a> {
+    /// `selection` is the chapter selection the summary was made from, if not the whole book.
+    pub fn new(
+        metadata: &'a PaperMetadata,
+        summary: &'a BookSummary,
+        source_path: String,
+        created: String,
+        selection: Option<String>,
+    ) -> Self {
+        let mut keywords: Vec<String> = metadata.keywords.iter().map(slug::slugify).collect();
+        for tag in &summary.tags {
+            if !keywords.contains(tag) {
+                keywords.push(tag.clone());
+            }
+        }
+        Self {
+            title: &metadata.title,
+            authors: &metadata.authors,
+            published: metadata.published.map(|d| d.to_string()),
+            publisher: metadata.journal.as_deref(),
+            keywords,
+            source_path,
+            created,
+            overview: &summary.overview,
+            chapters: &summary.chapters,
+            selection,
+        }
+    }
+}
+
+/// Everything the MOC template can reference.
+#[derive(Debug, Serialize)]
+pub struct MocNote<'a> {
+    #[serde(flatten)]
+    pub moc: &'a Moc,
+    pub created: String,
+}
+
+/// Everything the concept template can reference.
+#[derive(Debug, Serialize)]
+pub struct ConceptNote<'a> {
+    pub term: &'a str,
+    pub definition: &'a str,
+    /// File stem of the paper note the term was defined for
+    pub paper: &'a str,
+    pub created: String,
+}
+
+/// Everything the thread template can reference.
+#[derive(Debug, Serialize)]
+pub struct ThreadNote<'a> {
+    #[serde(flatten)]
+    pub metadata: &'a PaperMetadata,
+    pub summary: &'a Summary,
+    /// File stem of the paper note the thread is made from
+    pub note: &'a str,
+    /// The longest a post may be, as [`crate::thread::length`] counts
+    pub max_chars: usize,
+    pub created: String,
+}
+
+pub struct Renderer {
+    tera: Tera,
+    /// Given to every template as `vars`
+    vars: BTreeMap<String, String>,
+    style: FrontmatterStyle,
+    /// How paper notes' frontmatter fields are written
+    fields: FrontmatterFields,
+}
+
+impl Renderer {
+    /// Build a renderer around the given paper template source plus the built-in book, MOC, concept
+    /// and thread templates; fails if the template does not parse. Templates delimit managed regions with
+    /// `{{ region_begin(name="...") }}` and `{{ region_end(name="...") }}`, which expand to
+    /// `markers`.
+    pub fn new(paper_template: &str, markers: &RegionMarkers) -> Result<Self> {
+        let mut tera = Tera::default();
+        tera.autoescape_on(vec![]);
+        tera.register_filter("yaml", yaml_filter);
+        // Tera only ships `slugify` with its builtins, which do not build for wasm32.
+        tera.register_filter("slugify", slugify_filter);
+        tera.register_filter("thread", thread_filter);
+        let (begin, end) = (markers.clone(), markers.clone());
+        tera.register_function("region_begin", move |args: &HashMap<String, Value>| {
+            region_name(args).map(|name| Value::String(begin.begin(name)))
+        });
+        tera.register_function("region_end", move |args: &HashMap<String, Value>| {
+            region_name(args).map(|name| Value::String(end.end(name)))
+        });
+        tera.add_raw_template(PAPER, paper_template)?;
+        tera.add_raw_template(BOOK, BOOK_TEMPLATE)?;
+        tera.add_raw_template(MOC, MOC_TEMPLATE)?;
+        tera.add_raw_template(CONCEPT, CONCEPT_TEMPLATE)?;
+        tera.add_raw_template(THREAD, THREAD_TEMPLATE)?;
+        Ok(Self {
+            tera,
+            vars: BTreeMap::new(),
+            style: FrontmatterStyle::default(),
+            fields: FrontmatterFields::default(),
+        })
+    }
+
+    /// Values templates see as `vars`, e.g. `{{ vars.focus }}`; empty unless set.
+    #[must_use]
+    pub fn with_vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    /// Lay out the frontmatter of rendered notes in `style`.
+    #[must_use]
+    pub fn with_frontmatter_style(mut self, style: FrontmatterStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Write the frontmatter fields of paper notes as `fields` says.
+    #[must_use]
+    pub fn with_frontmatter_fields(mut self, fields: FrontmatterFields) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Use `source` instead of the built-in MOC template; fails if it does not parse.
+    pub fn with_moc_template(mut self, source: &str) -> Result<Self> {
+        self.tera.add_raw_template(MOC, source)?;
+        Ok(self)
+    }
+
+    /// Use `source` instead of the built-in concept template; fails if it does not parse.
+    pub fn with_concept_template(mut self, source: &str) -> Result<Self> {
+        self.tera.add_raw_template(CONCEPT, source)?;
+        Ok(self)
+    }
+
+    /// Use `source` instead of the built-in thread template; fails if it does not parse.
+    pub fn with_thread_template(mut self, source: &str) -> Result<Self> {
+        self.tera.add_raw_template(THREAD, source)?;
+        Ok(self)
+    }
+
+    pub fn render_paper(&self, note: &PaperNote<'_>) -> Result<String> {
+        let mut aliases: Vec<String> = Some(&note.bibtex.key)
+            .filter(|k| !k.is_empty())
+            .cloned()
+            .into_iter()
+            .collect();
+        aliases.extend(note.metadata.arxiv_id.as_ref().map(|id| format!("arXiv:{id}")));
+        let out = self.fill(PAPER, Context::from_serialize(note)?)?;
+        Ok(self.style.apply(&self.fields.apply(&out, &aliases)))
+    }
+
+    pub fn render_book(&self, note: &BookNote<'_>) -> Result<String> {
+        self.render(BOOK, Context::from_serialize(note)?)
+    }
+
+    pub fn render_moc(&self, note: &MocNote<'_>) -> Result<String> {
+        self.render(MOC, Context::from_serialize(note)?)
+    }
+
+    pub fn render_concept(&self, note: &ConceptNote<'_>) -> Result<String> {
+        self.render(CONCEPT, Context::from_serialize(note)?)
+    }
+
+    pub fn render_thread(&self, note: &ThreadNote<'_>) -> Result<String> {
+        self.render(THREAD, Context::from_serialize(note)?)
+    }
+
+    fn render(&self, name: &str, context: Context) -> Result<String> {
+        Ok(self.style.apply(&self.fill(name, context)?))
+    }
+
+    /// The template's output, as it comes out of Tera.
+    fn fill(&self, name: &str, mut context: Context) -> Result<String> {
+        context.insert("vars", &self.vars);
+        let out = self.tera.render(name, &context)?;
+        // Frontmatter is only recognised on the very first line; templates usually open with a
+        // comment block whose trailing newline Tera keeps.
+        Ok(out.trim_start().to_string())
+    }
+}
+
+/// Quote a value for YAML frontmatter. JSON scalars are valid YAML, and JSON string escaping
+/// covers the quotes, colons and `#` that break hand-quoted frontmatter.
+#[allow(clippy::unnecessary_wraps, clippy::implicit_hasher)]
+fn yaml_filter(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    Ok(Value::String(value.to_string()))
+}
+
+#[allow(clippy::implicit_hasher)]
+fn slugify_filter(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    value
+        .as_str()
+        .map(|s| Value::String(slug::slugify(s)))
+        .ok_or_else(|| tera::Error::msg("slugify expects a string"))
+}
+
+/// `paragraphs | thread(max=500)`: the posts of a thread made from a list of paragraphs, each a
+/// string or a list of lines (see [`crate::thread::posts`]).
+#[allow(clippy::implicit_hasher)]
+fn thread_filter(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let paragraphs: Vec<String> = value
+        .as_array()
+        .ok_or_else(|| tera::Error::msg("thread expects a list of paragraphs"))?
+        .iter()
+        .map(paragraph)
+        .collect();
+    let max = match args.get("max") {
+        | Some(max) => {
+            max.as_u64()
+                .and_then(|m| usize::try_from(m).ok())
+                .filter(|&m| m > 0)
+                .ok_or_else(|| tera::Error::msg("thread's `max` must be a positive number"))?
+        }
+        | None => THREAD_CHARS,
+    };
+    Ok(Value::from(crate::thread::posts(&paragraphs, max)))
+}
+
+/// A paragraph given to `thread`: a string as it is, a list as its lines.
+fn paragraph(value: &Value) -> String {
+    match value {
+        | Value::String(s) => s.clone(),
+        | Value::Array(lines) => lines.iter().map(paragraph).collect::<Vec<_>>().join("\n"),
+        | other => other.to_string(),
+    }
+}
+
+fn region_name(args: &HashMap<String, Value>) -> tera::Result<&str> {
+    args.get("name")
+        .and_then(Value::as_str)
+        .filter(|n| !n.is_empty() && !n.contains(char::is_whitespace))
+        .ok_or_else(|| tera::Error::msg("region_begin/region_end need a one-word `name` argument"))
+}
@@ -0,0 +1,90 @@
+//! Splitting prose into sentences. A full stop after an abbreviation (`et al.`, `e.g.`, `Sec.`)
+//! or an initial, or one followed by a lowercase word, does not end a sentence.
+
+/// Abbreviations common in papers, without their full stop; matched ignoring case.
+const ABBREVIATIONS: &[&str] = &[
+    "al", "app", "approx", "cf", "ch", "cor", "def", "dr", "ed", "eds", "eq", "eqs", "fig", "figs", "lem", "mr", "mrs",
+    "ms", "no", "nos", "pp", "prof", "prop", "ref", "refs", "resp", "sec", "secs", "sect", "st", "tab", "thm", "viz",
+    "vol", "vs",
+];
+
+/// The sentences of `text`: each ends in `.`, `?` or `!` followed by whitespace, or at the end.
+/// Put back together they are `text` again.
+pub fn sentences(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if matches!(c, '.' | '?' | '!')
+            && chars.peek().is_some_and(|(_, next)| next.is_whitespace())
+            && (c != '.' || ends_sentence(&text[start..i], &text[i + 1..]))
+        {
+            out.push(&text[start..=i]);
+            start = i + 1;
+        }
+    }
+    out.push(&text[start..]);
+    out
+}
+
+/// The first `count` sentences of `text`, or all of it when it has fewer.
+pub fn first(text: &str, count: usize) -> &str {
+    let end: usize = sentences(text).iter().take(count.max(1)).map(|s| s.len()).sum();
+    text[..end].trim()
+}
+
+/// Whether a full stop between `before` and `after` ends a sentence.
+fn ends_sentence(before: &str, after: &str) -> bool {
+    let word = before
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+    // "e.g", "i.e", "U.S"
+    let dotted = word.contains('.')
+        && word
+            .split('.')
+            .all(|p| !p.is_empty() && p.chars().all(char::is_alphabetic));
+    let mut chars = word.chars();
+    let initial = chars.next().is_some_and(char::is_uppercase) && chars.next().is_none();
+    let abbreviation = dotted || initial || ABBREVIATIONS.iter().any(|a| a.eq_ignore_ascii_case(word));
+    let continues = after.trim_start().starts_with(char::is_lowercase);
+    !abbreviation && !continues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_after_sentence_ends() {
+        assert_eq!(
+            sentences("It works. Does it? Yes!"),
+            ["It works.", " Does it?", " Yes!"]
+        );
+        assert_eq!(
+            sentences("Accuracy rose to 94.1%. Loss fell."),
+            ["Accuracy rose to 94.1%.", " Loss fell."]
+        );
+    }
+
+    #[test]
+    fn keeps_abbreviations_and_initials() {
+        for text in [
+            "Trained for 300 epochs (Sec. 3.2.1) in 2021.",
+            "As Smith et al. Show, it holds.",
+            "Some models, e.g. BERT, do better.",
+            "Proposed by J. Smith in 2020.",
+            "It is about 3 vs. 4 layers.",
+            "Results hold, cf. the appendix.",
+        ] {
+            assert_eq!(sentences(text), [text], "{text}");
+        }
+    }
+
+    #[test]
+    fn first_sentences() {
+        assert_eq!(first("One (Fig. 2). Two. Three.", 2), "One (Fig. 2). Two.");
+        assert_eq!(first("Only one", 3), "Only one");
+    }
+}
@@ -0,0 +1,259 @@
+//! The structured summaries the model produces and the note templates render.
+
+use serde::{Deserialize, Serialize};
+
+use crate::verify::{self, Source};
+
+/// What the model wraps a statement it is unsure of in, when asked to (`MABEL_UNCERTAINTY`).
+pub const UNCERTAIN_OPEN: &str = "{?";
+pub const UNCERTAIN_CLOSE: &str = "?}";
+
+/// What the model tells us about a paper. Fields the model leaves out stay empty, so templates
+/// should guard optional sections with `{% if %}`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Summary {
+    pub tldr: String,
+    pub summary: String,
+    pub key_points: Vec<String>,
+    pub tags: Vec<String>,
+
+    /// Study mode only
+    pub methods: Option<String>,
+    pub results: Option<String>,
+    pub limitations: Vec<String>,
+    pub glossary: Vec<GlossaryEntry>,
+    /// What reproducing the experiments would take, from a separate pass over the experimental setup
+    pub reproduction: Option<Reproduction>,
+    /// The paper's figures that can be embedded, each with alt text; filled in after the summary,
+    /// never taken from the reply
+    #[serde(skip_deserializing)]
+    pub figures: Vec<FigureImage>,
+
+    /// ELI-grad mode only: a tutorial-style walkthrough of the core idea
+    pub explanation: Option<String>,
+    pub prerequisites: Vec<Prerequisite>,
+
+    /// Technical terms the summary uses, each with a definition; only asked for with
+    /// `--define-new-terms`
+    pub terms: Vec<Term>,
+
+    /// Flashcards mode only: question and answer pairs for spaced repetition
+    pub flashcards: Vec<Flashcard>,
+
+    /// Where the paper says it was published or accepted ("ICLR 2024"); only asked for when the
+    /// metadata does not tell
+    pub venue: Option<String>,
+
+    /// How many statements the model marked as uncertain; counted by
+    /// [`Summary::resolve_uncertainty`], never taken from the reply
+    #[serde(skip_deserializing)]
+    pub uncertain: usize,
+    /// How many statements have a quote or number the paper's text does not bear out; counted by
+    /// [`Summary::verify`], never taken from the reply
+    #[serde(skip_deserializing)]
+    pub unverified: usize,
+    /// Headings of the low-confidence sections the quotes and numbers were checked against, when
+    /// some were not found: the miss may be the extraction's rather than the summary's. Never taken
+    /// from the reply
+    #[serde(skip_deserializing)]
+    pub unverified_doubtful: Vec<String>,
+    /// The extraction quality, when it was below `MABEL_MIN_EXTRACTION_QUALITY` and the summary was
+    /// written from the abstract, introduction and conclusion only; never taken from the reply
+    #[serde(skip_deserializing)]
+    pub extraction_quality: Option<f32>,
+}
+
+impl Summary {
+    /// Turn the model's `{?statement?}` marks into `*statement* (uncertain)` in every prose field,
+    /// counting the statements in [`Summary::uncertain`]. Marks without a partner are dropped;
+    /// returns how many there were.
+    pub fn resolve_uncertainty(&mut self) -> usize {
+        let mut marks = Marks::default();
+        for text in self.prose() {
+            *text = marks.resolve(text);
+        }
+        self.uncertain = marks.statements;
+        marks.unpaired
+    }
+
+    /// Mark the statements of every prose field with a quote or number `source` does not have
+    /// (see [`crate::verify`]), counting them in [`Summary::unverified`].
+    pub fn verify(&mut self, source: &Source) {
+        let mut unverified = 0;
+        for text in self.prose() {
+            let (marked, n) = verify::mark(text, source);
+            *text = marked;
+            unverified += n;
+        }
+        self.unverified = unverified;
+    }
+
+    /// The fields written in prose, as opposed to tags, names and links.
+    fn prose(&mut self) -> impl Iterator<Item = &mut String> {
+        [&mut self.tldr, &mut self.summary]
+            .into_iter()
+            .chain(&mut self.key_points)
+            .chain(&mut self.methods)
+            .chain(&mut self.results)
+            .chain(&mut self.limitations)
+            .chain(self.glossary.iter_mut().map(|g| &mut g.definition))
+            .chain(&mut self.explanation)
+            .chain(self.prerequisites.iter_mut().map(|p| &mut p.why))
+            .chain(self.terms.iter_mut().map(|t| &mut t.definition))
+            .chain(self.flashcards.iter_mut().map(|c| &mut c.answer))
+    }
+}
+
+#[derive(Default)]
+struct Marks {
+    statements: usize,
+    unpaired: usize,
+}
+
+impl Marks {
+    fn resolve(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        loop {
+            let (open, close) = (rest.find(UNCERTAIN_OPEN), rest.find(UNCERTAIN_CLOSE));
+            match (open, close) {
+                | (Some(o), c) if c.is_none_or(|c| o < c) => {
+                    out.push_str(&rest[..o]);
+                    let inside = &rest[o + UNCERTAIN_OPEN.len()..];
+                    match inside.find(UNCERTAIN_CLOSE) {
+                        | Some(end) if !inside[..end].contains(UNCERTAIN_OPEN) => {
+                            out.push_str(&self.styled(&inside[..end]));
+                            rest = &inside[end + UNCERTAIN_CLOSE.len()..];
+                        }
+                        | _ => {
+                            self.unpaired += 1;
+                            rest = inside;
+                        }
+                    }
+                }
+                | (_, Some(c)) => {
+                    out.push_str(&rest[..c]);
+                    self.unpaired += 1;
+                    rest = &rest[c + UNCERTAIN_CLOSE.len()..];
+                }
+                | _ => {
+                    out.push_str(rest);
+                    return out;
+                }
+            }
+        }
+    }
+
+    /// `*statement* (uncertain)`, with the statement's closing punctuation after the label.
+    fn styled(&mut self, statement: &str) -> String {
+        let statement = statement.trim();
+        let body = statement.trim_end_matches(['.', ',', ';', ':', '!']);
+        if body.is_empty() {
+            return statement.to_string();
+        }
+        self.statements += 1;
+        format!("*{body}* (uncertain){}", &statement[body.len()..])
+    }
+}
+
+/// A figure embedded in the note.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FigureImage {
+    /// e.g. "Figure 2"
+    pub label: Option<String>,
+    pub caption: String,
+    /// URL of the image
+    pub image: String,
+    /// What the image shows, for screen readers; one line without brackets, so it can go
+    /// straight into `![alt](image)`
+    pub alt: String,
+}
+
+/// One flashcard: a question on the paper and its answer, each on one line.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Flashcard {
+    pub question: String,
+    pub answer: String,
+}
+
+/// A topic worth knowing before reading the paper.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prerequisite {
+    pub topic: String,
+    /// Where the paper relies on it
+    pub why: String,
+    /// Vault note on the topic, when there is one
+    pub link: Option<String>,
+}
+
+/// A technical term the summary uses.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Term {
+    pub term: String,
+    /// One or two sentences
+    pub definition: String,
+    /// Vault note on the term: one already there, or the concept note written for it
+    pub link: Option<String>,
+}
+
+/// The reproduction checklist: whether the paper gives what it takes to rerun its experiments.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Reproduction {
+    /// Is the code released?
+    pub code: Check,
+    /// Are the datasets public, or at least described well enough to obtain?
+    pub data: Check,
+    /// Are the hyperparameters and training details specified?
+    pub hyperparameters: Check,
+    /// Are the compute requirements (hardware, training time) reported?
+    pub compute: Check,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Check {
+    pub status: CheckStatus,
+    /// What the paper says, e.g. the repository URL or the GPUs used
+    pub detail: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Yes,
+    Partial,
+    No,
+    /// The paper does not say
+    #[default]
+    #[serde(other)]
+    Unclear,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlossaryEntry {
+    pub term: String,
+    pub definition: String,
+}
+
+/// Per-chapter summaries of a book plus an overview across them.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BookSummary {
+    pub overview: String,
+    pub tags: Vec<String>,
+    pub chapters: Vec<ChapterSummary>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ChapterSummary {
+    pub number: usize,
+    pub title: String,
+    pub summary: String,
+    pub key_points: Vec<String>,
+}
@@ -0,0 +1,94 @@
+//! Splitting text into a thread of numbered posts that each fit a network's length limit.
+//!
+//! Lengths are counted the way Mastodon and X count them: in characters, with every link taking
+//! [`LINK_CHARS`] whatever its length.
+
+use crate::sentence::sentences;
+
+/// What a link counts for in a post.
+pub const LINK_CHARS: usize = 23;
+
+/// The posts of a thread made from `paragraphs`, each at most `max_chars` long with its number
+/// (`2/7 `) in front. Each paragraph starts a post; one too long for a post is split between its
+/// lines, then its sentences, then its words, and a word too long for a post is cut. A thread of
+/// one post is not numbered.
+pub fn posts(paragraphs: &[String], max_chars: usize) -> Vec<String> {
+    // The number takes room of its own, which depends on how many posts there turn out to be.
+    let mut digits = 1;
+    loop {
+        let room = max_chars.saturating_sub(2 * digits + 2).max(1);
+        let parts: Vec<String> = paragraphs
+            .iter()
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .flat_map(|p| split(p, room, 0))
+            .collect();
+        let count = parts.len();
+        if count < 2 {
+            return parts;
+        }
+        let needed = count.to_string().len();
+        if needed <= digits {
+            return parts
+                .into_iter()
+                .enumerate()
+                .map(|(i, part)| format!("{}/{count} {part}", i + 1))
+                .collect();
+        }
+        digits = needed;
+    }
+}
+
+/// How long `text` counts as in a post.
+pub fn length(text: &str) -> usize {
+    let links: Vec<&str> = text.split_whitespace().filter(|w| is_link(w)).collect();
+    text.chars().count() + links.len() * LINK_CHARS - links.iter().map(|l| l.chars().count()).sum::<usize>()
+}
+
+fn is_link(word: &str) -> bool {
+    word.starts_with("https://") || word.starts_with("http://")
+}
+
+/// `text` in parts of at most `room`, broken at `level` (0 lines, 1 sentences, 2 words) or finer,
+/// with as much in each part as fits.
+fn split(text: &str, room: usize, level: usize) -> Vec<String> {
+    if length(text) <= room {
+        return vec![text.to_string()];
+    }
+    let (units, separator) = match level {
+        | 0 => (text.split('\n').collect(), "\n"),
+        | 1 => (sentences(text), " "),
+        | 2 => (text.split_whitespace().collect(), " "),
+        | _ => return cut(text, room),
+    };
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for unit in units {
+        for piece in split(unit.trim(), room, level + 1) {
+            let joined = if current.is_empty() {
+                piece.clone()
+            } else {
+                format!("{current}{separator}{piece}")
+            };
+            if length(&joined) <= room {
+                current = joined;
+            } else {
+                parts.push(std::mem::replace(&mut current, piece));
+            }
+        }
+    }
+    parts.push(current);
+    // A break at a blank line leaves it at the end of one part or the start of the next.
+    parts
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `text` cut every `room` characters, for a word no post can hold.
+fn cut(text: &str, room: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.chunks(room).map(|c| c.iter().collect()).collect()
+}
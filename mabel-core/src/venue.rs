@@ -0,0 +1,349 @@
+//! Where a paper was published or accepted, read from free text such as arXiv comments ("Accepted
+//! at NeurIPS 2024") and journal references, with venue names normalized against a built-in list.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use self::Match::{Leading, Phrase, Word};
+
+/// A publication venue and the year of the edition the paper appeared in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Venue {
+    /// Canonical short name (`NeurIPS`, `Nature Communications`), or the name as stated for venues
+    /// not on the built-in list
+    pub name: String,
+    pub kind: VenueKind,
+    pub year: Option<i32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VenueKind {
+    Conference,
+    /// A workshop held at a conference; the venue is the conference
+    Workshop,
+    Journal,
+    /// Not on the built-in list and not recognisable as one of the above
+    Other,
+}
+
+impl VenueKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Conference => "conference",
+            | Self::Workshop => "workshop",
+            | Self::Journal => "journal",
+            | Self::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for VenueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How an alias has to appear in the text to count.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Match {
+    /// As a whole word, in this exact case (acronyms: "ACL" but not "acl")
+    Word,
+    /// As a whole phrase, in any case
+    Phrase,
+    /// At the very start, followed by nothing or a volume number: journal names that are also
+    /// common words ("Science", "Nature", "Cell")
+    Leading,
+}
+
+struct Known {
+    name: &'static str,
+    kind: VenueKind,
+    aliases: &'static [(&'static str, Match)],
+}
+
+const fn conference(name: &'static str, aliases: &'static [(&'static str, Match)]) -> Known {
+    Known {
+        name,
+        kind: VenueKind::Conference,
+        aliases,
+    }
+}
+
+const fn journal(name: &'static str, aliases: &'static [(&'static str, Match)]) -> Known {
+    Known {
+        name,
+        kind: VenueKind::Journal,
+        aliases,
+    }
+}
+
+/// Venues mabel knows by name. Of two names found at the same place, the one listed first wins, so
+/// a venue whose name starts with another's ("SIGGRAPH Asia", "Nature Communications") comes
+/// before it.
+const KNOWN: &[Known] = &[
+    // Machine learning
+    conference(
+        "NeurIPS",
+        &[
+            ("NeurIPS", Word),
+            ("NIPS", Word),
+            ("Neural Information Processing Systems", Phrase),
+        ],
+    ),
+    conference(
+        "ICML",
+        &[("ICML", Word), ("International Conference on Machine Learning", Phrase)],
+    ),
+    conference(
+        "ICLR",
+        &[
+            ("ICLR", Word),
+            ("International Conference on Learning Representations", Phrase),
+        ],
+    ),
+    conference(
+        "AISTATS",
+        &[("AISTATS", Word), ("Artificial Intelligence and Statistics", Phrase)],
+    ),
+    conference(
+        "UAI",
+        &[("UAI", Word), ("Uncertainty in Artificial Intelligence", Phrase)],
+    ),
+    conference("COLT", &[("COLT", Word), ("Conference on Learning Theory", Phrase)]),
+    conference("CoRL", &[("CoRL", Word), ("Conference on Robot Learning", Phrase)]),
+    conference("MLSys", &[("MLSys", Word)]),
+    conference("AAAI", &[("AAAI", Word)]),
+    conference("IJCAI", &[("IJCAI", Word)]),
+    conference("KDD", &[("KDD", Word), ("SIGKDD", Word)]),
+    // Vision and graphics
+    conference(
+        "CVPR",
+        &[("CVPR", Word), ("Computer Vision and Pattern Recognition", Phrase)],
+    ),
+    conference(
+        "ICCV",
+        &[("ICCV", Word), ("International Conference on Computer Vision", Phrase)],
+    ),
+    conference(
+        "ECCV",
+        &[("ECCV", Word), ("European Conference on Computer Vision", Phrase)],
+    ),
+    conference("WACV", &[("WACV", Word)]),
+    conference("BMVC", &[("BMVC", Word), ("British Machine Vision Conference", Phrase)]),
+    conference("SIGGRAPH Asia", &[("SIGGRAPH Asia", Phrase)]),
+    conference("SIGGRAPH", &[("SIGGRAPH", Word)]),
+    conference("MICCAI", &[("MICCAI", Word)]),
+    // Language
+    conference(
+        "EMNLP",
+        &[
+            ("EMNLP", Word),
+            ("Empirical Methods in Natural Language Processing", Phrase),
+        ],
+    ),
+    conference("NAACL", &[("NAACL", Word)]),
+    conference("EACL", &[("EACL", Word)]),
+    conference("COLING", &[("COLING", Word)]),
+    conference("LREC", &[("LREC", Word)]),
+    conference("INTERSPEECH", &[("INTERSPEECH", Word), ("Interspeech", Word)]),
+    conference("ICASSP", &[("ICASSP", Word)]),
+    conference(
+        "ACL",
+        &[("ACL", Word), ("Association for Computational Linguistics", Phrase)],
+    ),
+    // Robotics
+    conference("ICRA", &[("ICRA", Word)]),
+    conference("IROS", &[("IROS", Word)]),
+    conference("RSS", &[("Robotics: Science and Systems", Phrase)]),
+    // Web, data and information retrieval
+    conference("WWW", &[("The Web Conference", Phrase), ("WWW", Word)]),
+    conference("SIGIR", &[("SIGIR", Word)]),
+    conference("WSDM", &[("WSDM", Word)]),
+    conference("RecSys", &[("RecSys", Word)]),
+    conference("CIKM", &[("CIKM", Word)]),
+    conference("ICDM", &[("ICDM", Word)]),
+    conference("SIGMOD", &[("SIGMOD", Word)]),
+    conference("VLDB", &[("VLDB", Word)]),
+    conference("ICDE", &[("ICDE", Word)]),
+    conference("CHI", &[("CHI", Word)]),
+    conference("UIST", &[("UIST", Word)]),
+    // Systems, languages and security
+    conference("OSDI", &[("OSDI", Word)]),
+    conference("SOSP", &[("SOSP", Word)]),
+    conference("NSDI", &[("NSDI", Word)]),
+    conference("SIGCOMM", &[("SIGCOMM", Word)]),
+    conference("ASPLOS", &[("ASPLOS", Word)]),
+    conference("ISCA", &[("ISCA", Word)]),
+    conference("PLDI", &[("PLDI", Word)]),
+    conference("POPL", &[("POPL", Word)]),
+    conference("ICSE", &[("ICSE", Word)]),
+    conference("FSE", &[("ESEC/FSE", Word), ("FSE", Word)]),
+    conference("USENIX Security", &[("USENIX Security", Phrase)]),
+    conference("CCS", &[("CCS", Word)]),
+    conference("NDSS", &[("NDSS", Word)]),
+    conference(
+        "IEEE S&P",
+        &[("IEEE S&P", Phrase), ("Symposium on Security and Privacy", Phrase)],
+    ),
+    conference("CRYPTO", &[("CRYPTO", Word)]),
+    conference("EUROCRYPT", &[("EUROCRYPT", Word), ("Eurocrypt", Word)]),
+    // Theory
+    conference("STOC", &[("STOC", Word)]),
+    conference("FOCS", &[("FOCS", Word)]),
+    conference("SODA", &[("SODA", Word)]),
+    conference("ICALP", &[("ICALP", Word)]),
+    // Computational biology
+    conference("ISMB", &[("ISMB", Word)]),
+    conference("RECOMB", &[("RECOMB", Word)]),
+    // Journals
+    journal(
+        "JMLR",
+        &[("JMLR", Word), ("Journal of Machine Learning Research", Phrase)],
+    ),
+    journal(
+        "TMLR",
+        &[("TMLR", Word), ("Transactions on Machine Learning Research", Phrase)],
+    ),
+    journal(
+        "TACL",
+        &[
+            ("TACL", Word),
+            ("Transactions of the Association for Computational Linguistics", Phrase),
+        ],
+    ),
+    journal(
+        "TPAMI",
+        &[
+            ("TPAMI", Word),
+            ("IEEE Trans. Pattern Anal. Mach. Intell.", Phrase),
+            ("Transactions on Pattern Analysis and Machine Intelligence", Phrase),
+        ],
+    ),
+    journal(
+        "IJCV",
+        &[("IJCV", Word), ("International Journal of Computer Vision", Phrase)],
+    ),
+    journal(
+        "Nature Communications",
+        &[("Nature Communications", Phrase), ("Nat Commun", Phrase)],
+    ),
+    journal("Nature Methods", &[("Nature Methods", Phrase), ("Nat Methods", Phrase)]),
+    journal(
+        "Nature Machine Intelligence",
+        &[("Nature Machine Intelligence", Phrase)],
+    ),
+    journal("Nature", &[("Nature", Leading)]),
+    journal("Science Advances", &[("Science Advances", Phrase), ("Sci Adv", Phrase)]),
+    journal("Science", &[("Science", Leading)]),
+    journal("Cell", &[("Cell", Leading)]),
+    journal(
+        "PNAS",
+        &[
+            ("PNAS", Word),
+            ("Proc Natl Acad Sci", Phrase),
+            ("Proceedings of the National Academy of Sciences", Phrase),
+        ],
+    ),
+    journal(
+        "Physical Review Letters",
+        &[("Physical Review Letters", Phrase), ("Phys. Rev. Lett.", Phrase)],
+    ),
+    journal("Bioinformatics", &[("Bioinformatics", Leading)]),
+    journal("eLife", &[("eLife", Word)]),
+    journal("PLOS ONE", &[("PLOS ONE", Phrase), ("PLoS One", Phrase)]),
+];
+
+/// The known venue `text` mentions first, with the year after its name (or anywhere in `text`).
+/// `None` when no venue on the built-in list is mentioned.
+pub fn detect(text: &str) -> Option<Venue> {
+    // The first venue mentioned; of two at the same place, the one listed first.
+    let (_, at, known, len) = KNOWN
+        .iter()
+        .enumerate()
+        .flat_map(|(i, k)| {
+            k.aliases
+                .iter()
+                .filter_map(move |&(alias, how)| find(text, alias, how).map(|at| (i, at, k, alias.len())))
+        })
+        .min_by_key(|&(i, at, ..)| (at, i))?;
+    let kind = if known.kind == VenueKind::Conference && text.to_lowercase().contains("workshop") {
+        VenueKind::Workshop
+    } else {
+        known.kind
+    };
+    Some(Venue {
+        name: known.name.to_string(),
+        kind,
+        year: year_after(text, at + len).or_else(|| year_after(text, 0)),
+    })
+}
+
+/// Like [`detect`], but a venue that is not on the list is kept as stated (minus its year), as
+/// [`VenueKind::Other`]. For text that is known to name a venue, such as the model's reading of
+/// the paper.
+pub fn parse(text: &str) -> Option<Venue> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    detect(text).or_else(|| {
+        let year = year_after(text, 0);
+        let name = match year {
+            | Some(y) => text.replace(&y.to_string(), ""),
+            | None => text.to_string(),
+        };
+        let name = name.trim_matches(|c: char| c.is_whitespace() || ",;:'()".contains(c));
+        (!name.is_empty()).then(|| {
+            Venue {
+                name: name.to_string(),
+                kind: VenueKind::Other,
+                year,
+            }
+        })
+    })
+}
+
+/// Byte offset of the first occurrence of `alias` in `text` that satisfies `how`.
+fn find(text: &str, alias: &str, how: Match) -> Option<usize> {
+    match how {
+        | Leading => {
+            let rest = text.trim_start();
+            let after = rest.strip_prefix(alias)?.trim_start();
+            let ok = after.is_empty() || after.starts_with(|c: char| c.is_ascii_digit() || ",;.(".contains(c));
+            ok.then_some(text.len() - rest.len())
+        }
+        | Word | Phrase => {
+            let (haystack, needle) = if how == Phrase {
+                (text.to_lowercase(), alias.to_lowercase())
+            } else {
+                (text.to_string(), alias.to_string())
+            };
+            // Lowercasing keeps byte offsets for the ASCII aliases on the list.
+            haystack.match_indices(&needle).map(|(at, _)| at).find(|&at| {
+                let before = haystack[..at].chars().next_back();
+                let after = haystack[at + needle.len()..].chars().next();
+                !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphabetic)
+            })
+        }
+    }
+}
+
+/// The first plausible publication year at or after byte `from`: a four-digit 19xx/20xx, or a
+/// two-digit one after an apostrophe ("ICML'23").
+fn year_after(text: &str, from: usize) -> Option<i32> {
+    let bytes = text.as_bytes();
+    let digits_at = |i: usize, n: usize| i + n <= bytes.len() && bytes[i..i + n].iter().all(u8::is_ascii_digit);
+    let boundary = |i: usize| i >= bytes.len() || !bytes[i].is_ascii_alphanumeric();
+    for i in from..bytes.len() {
+        let fresh = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        if fresh && digits_at(i, 4) && boundary(i + 4) && matches!(&bytes[i..i + 2], b"19" | b"20") {
+            return text[i..i + 4].parse().ok();
+        }
+        if bytes[i] == b'\'' && digits_at(i + 1, 2) && boundary(i + 3) {
+            return text[i + 1..i + 3].parse::<i32>().ok().map(|y| 2000 + y);
+        }
+    }
+    None
+}
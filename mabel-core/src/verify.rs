@@ -0,0 +1,325 @@
+//! Checking a summary's direct quotes and numbers against the text of the paper. Models get these
+//! wrong with the most confidence: a quote reworded into words the authors never wrote, or a
+//! result that is a point or two off.
+//!
+//! A statement stands when each of its quotes is in the text and so is each of its numbers.
+//! Quotes are three words or more in straight or curly double quotes. They match fuzzily, on the
+//! pairs of neighbouring words they share with the text, so a hyphen broken across lines or a
+//! word left out does not count against them. Numbers match as given or rounded (`94.1%` matches
+//! `94.13`), and a percentage also matches as a fraction (`0.941`). Numbers below ten are not
+//! checked, since they are as often spelled out.
+//!
+//! A statement that does not stand gets [`MARK`] before its closing punctuation.
+
+use std::collections::HashSet;
+
+use crate::sentence::sentences;
+
+/// What marks a statement the paper's text does not bear out.
+pub const MARK: &str = "⚠️";
+
+/// Words a quote needs to be checked; shorter ones are more often terms than quotes.
+const MIN_QUOTE_WORDS: usize = 3;
+
+/// The share of a quote's pairs of neighbouring words the text must have.
+const QUOTE_MATCH: f64 = 0.75;
+
+/// Numbers below this are not checked.
+const MIN_NUMBER: f64 = 10.0;
+
+/// The text a summary is checked against.
+pub struct Source {
+    /// Pairs of neighbouring words, lowercase and joined by a space
+    pairs: HashSet<String>,
+    /// Every number in the text, and every percentage as a fraction too; sorted
+    numbers: Vec<f64>,
+}
+
+impl Source {
+    pub fn new(text: &str) -> Self {
+        let text = ligatures(text);
+        let pairs = words(&text).windows(2).map(|w| w.join(" ")).collect();
+        let mut numbers: Vec<f64> = numbers(&text)
+            .into_iter()
+            .flat_map(|n| [Some(n.value), n.percent.then_some(n.value / 100.0)])
+            .flatten()
+            .collect();
+        numbers.sort_by(f64::total_cmp);
+        Self { pairs, numbers }
+    }
+
+    /// Whether there is no text to check against.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    fn has_quote(&self, quote: &str) -> bool {
+        let pairs: Vec<String> = words(&ligatures(quote)).windows(2).map(|w| w.join(" ")).collect();
+        let found = pairs.iter().filter(|p| self.pairs.contains(*p)).count();
+        #[allow(clippy::cast_precision_loss)]
+        let share = found as f64 / pairs.len().max(1) as f64;
+        share >= QUOTE_MATCH
+    }
+
+    fn has_number(&self, n: &Number) -> bool {
+        self.near(n.value, n.decimals) || (n.percent && self.near(n.value / 100.0, n.decimals + 2))
+    }
+
+    /// Whether the text has a number that rounds to `value` at `decimals` places.
+    fn near(&self, value: f64, decimals: i32) -> bool {
+        let half = 0.5 * 10f64.powi(-decimals) + 1e-9;
+        let first = self.numbers.partition_point(|&n| n < value - half);
+        self.numbers.get(first).is_some_and(|&n| n <= value + half)
+    }
+}
+
+/// `text` with [`MARK`] on each statement whose quotes or numbers `source` does not have, and
+/// how many statements were marked.
+pub fn mark(text: &str, source: &Source) -> (String, usize) {
+    let mut marked = 0;
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| {
+            let unverified = unverified(line, source);
+            if unverified.is_empty() {
+                return line.to_string();
+            }
+            let mut out = String::with_capacity(line.len() + unverified.len() * 8);
+            for (i, sentence) in sentences(line).into_iter().enumerate() {
+                if !unverified.contains(&i) {
+                    out.push_str(sentence);
+                    continue;
+                }
+                marked += 1;
+                let end = sentence.trim_end();
+                let body = end.trim_end_matches(['.', '?', '!']);
+                out.push_str(body);
+                out.push(' ');
+                out.push_str(MARK);
+                out.push_str(&sentence[body.len()..]);
+            }
+            out
+        })
+        .collect();
+    (lines.join("\n"), marked)
+}
+
+/// The indices (in [`sentences`]) of the sentences of `line` with a quote or number `source`
+/// does not have. A quote with a sentence break in it counts against the sentence it ends in.
+fn unverified(line: &str, source: &Source) -> HashSet<usize> {
+    let starts: Vec<usize> = sentences(line)
+        .iter()
+        .map(|s| s.as_ptr() as usize - line.as_ptr() as usize)
+        .collect();
+    let sentence_at = |offset: usize| starts.partition_point(|&s| s <= offset).saturating_sub(1);
+    let quotes = quotes(line);
+    let mut out: HashSet<usize> = quotes
+        .iter()
+        .filter(|q| words(q.text).len() >= MIN_QUOTE_WORDS && !source.has_quote(q.text))
+        .map(|q| sentence_at(q.end))
+        .collect();
+    out.extend(
+        numbers(line)
+            .iter()
+            .filter(|n| (n.percent || n.value >= MIN_NUMBER) && !source.has_number(n))
+            .map(|n| sentence_at(n.start)),
+    );
+    out
+}
+
+struct Quote<'a> {
+    text: &'a str,
+    /// Where the closing quote is
+    end: usize,
+}
+
+/// The text between pairs of double quotes in `line`, straight or curly.
+fn quotes(line: &str) -> Vec<Quote<'_>> {
+    let mut out = Vec::new();
+    let mut open = None;
+    for (i, c) in line.char_indices() {
+        match (c, open) {
+            | ('“', _) | ('"', None) => open = Some(i + c.len_utf8()),
+            | ('”' | '"', Some(start)) => {
+                out.push(Quote {
+                    text: &line[start..i],
+                    end: i,
+                });
+                open = None;
+            }
+            | _ => {}
+        }
+    }
+    out
+}
+
+struct Number {
+    value: f64,
+    /// Digits after the decimal point
+    decimals: i32,
+    percent: bool,
+    start: usize,
+}
+
+/// The numbers in `text` that are not part of a word (`L2`, `x86`), with commas between
+/// thousands left out.
+fn numbers(text: &str) -> Vec<Number> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        // The rest of a word (`x86`, `v1.2`)
+        if i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_') {
+            while bytes.get(i).is_some_and(|&b| {
+                b.is_ascii_alphanumeric()
+                    || b == b'_'
+                    || (b == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+            }) {
+                i += 1;
+            }
+            continue;
+        }
+        let start = i;
+        let mut digits = String::new();
+        let mut decimals = None;
+        while i < bytes.len() {
+            let b = bytes[i];
+            let next_digit = bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+            if b.is_ascii_digit() {
+                digits.push(char::from(b));
+                decimals = decimals.map(|d| d + 1);
+            } else if b == b'.' && decimals.is_none() && next_digit {
+                digits.push('.');
+                decimals = Some(0);
+            } else if !(b == b',' && decimals.is_none() && is_thousands(&bytes[i + 1..])) {
+                break;
+            }
+            i += 1;
+        }
+        // Part of a word after all (`3D`), or a version or section number (`3.2.1`)
+        if bytes.get(i).is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_')
+            || (bytes.get(i) == Some(&b'.') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+        {
+            while bytes
+                .get(i)
+                .is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_')
+            {
+                i += 1;
+            }
+            continue;
+        }
+        if let Ok(value) = digits.parse() {
+            out.push(Number {
+                value,
+                decimals: decimals.unwrap_or(0),
+                percent: is_percent(&text[i..]),
+                start,
+            });
+        }
+    }
+    out
+}
+
+/// Whether `rest`, just after a number, makes it a percentage.
+fn is_percent(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    rest.starts_with('%') || rest.starts_with("percent")
+}
+
+/// Whether `rest`, just after a comma in a number, is a group of thousands.
+fn is_thousands(rest: &[u8]) -> bool {
+    rest.len() >= 3 && rest[..3].iter().all(u8::is_ascii_digit) && !rest.get(3).is_some_and(u8::is_ascii_digit)
+}
+
+/// The lowercase words of `text`, with punctuation and layout left out.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// `text` with the ligatures PDF extraction leaves in spelled out.
+fn ligatures(text: &str) -> String {
+    text.replace('ﬁ', "fi")
+        .replace('ﬂ', "fl")
+        .replace('ﬀ', "ff")
+        .replace('ﬃ', "ffi")
+        .replace('ﬄ', "ffl")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str) -> Vec<(f64, i32, bool)> {
+        numbers(text).iter().map(|n| (n.value, n.decimals, n.percent)).collect()
+    }
+
+    #[test]
+    fn numbers_in_text() {
+        assert_eq!(
+            values("reached 94.13% on 1,200 images"),
+            [(94.13, 2, true), (1200.0, 0, false)]
+        );
+        assert_eq!(values("12 percent"), [(12.0, 0, true)]);
+        assert_eq!(
+            values("a list: 1, 23 and 456"),
+            [(1.0, 0, false), (23.0, 0, false), (456.0, 0, false)]
+        );
+        assert_eq!(values("in 2021."), [(2021.0, 0, false)]);
+    }
+
+    #[test]
+    fn numbers_skip_words_and_section_numbers() {
+        assert!(values("L2 loss on x86 with 3D input").is_empty());
+        assert!(values("see Sec. 3.2.1 and v1.2.3").is_empty());
+    }
+
+    #[test]
+    fn quotes_straight_and_curly() {
+        let line = r#"They say "it just works" and “so it does”."#;
+        let found: Vec<&str> = quotes(line).iter().map(|q| q.text).collect();
+        assert_eq!(found, ["it just works", "so it does"]);
+        assert_eq!(line[quotes(line)[1].end..].chars().next(), Some('”'));
+    }
+
+    #[test]
+    fn mark_leaves_supported_statements_alone() {
+        let source =
+            Source::new("Our model reaches 94.13% accuracy on 1,200 images. We find that attention is all you need.");
+        for text in [
+            "Accuracy is 94.1%.",
+            "Accuracy is 0.941 as a fraction.",
+            "It was tested on 1200 images.",
+            "The authors write \"attention is all you need\".",
+            "It uses 3 layers.",
+        ] {
+            assert_eq!(mark(text, &source), (text.to_string(), 0), "{text}");
+        }
+    }
+
+    #[test]
+    fn mark_flags_unsupported_statements() {
+        let source = Source::new("Our model reaches 94.13% accuracy. Trained for 300 epochs.");
+        let (marked, count) = mark("Accuracy is 95.2%. Trained for 300 epochs.", &source);
+        assert_eq!(marked, format!("Accuracy is 95.2% {MARK}. Trained for 300 epochs."));
+        assert_eq!(count, 1);
+        let (marked, _) = mark("They claim \"results beat every baseline\".", &source);
+        assert_eq!(marked, format!("They claim \"results beat every baseline\" {MARK}."));
+    }
+
+    #[test]
+    fn mark_does_not_break_at_abbreviations() {
+        let source = Source::new("We train for 300 epochs.");
+        let text = "Trained for 300 epochs (Sec. 3.2.1) in 2021.";
+        assert_eq!(
+            mark(text, &source).0,
+            format!("Trained for 300 epochs (Sec. 3.2.1) in 2021 {MARK}.")
+        );
+    }
+}
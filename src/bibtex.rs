@@ -0,0 +1,259 @@
+//! BibTeX entries for resolved papers, for `mabel cite`, the block at the end of each note and
+//! the vault's `.bib` file (`MABEL_BIB_FILE`).
+
+use std::{fmt::Write as _, path::Path};
+
+use mabel_core::venue::VenueKind;
+use tokio::io::AsyncWriteExt;
+
+use crate::{config::Config, paper::PaperMetadata, render::BibEntry, MabelError, Result};
+
+/// Words skipped when picking the title word of a citekey.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "the", "on", "of", "for", "in", "to", "and", "with", "towards", "toward",
+];
+
+/// `lastnameYEARword`, e.g. `vaswani2017attention`: the first author's surname, the year and the
+/// first significant title word, lowercased ASCII.
+pub fn citekey(metadata: &PaperMetadata) -> String {
+    let surname = metadata
+        .authors
+        .first()
+        .and_then(|a| a.split_whitespace().last())
+        .map(ascii_word)
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "anon".to_string());
+    let year = metadata
+        .published
+        .map(|d| d.format("%Y").to_string())
+        .unwrap_or_default();
+    let word = metadata
+        .title
+        .split(|c: char| !c.is_alphanumeric())
+        .map(ascii_word)
+        .find(|w| !w.is_empty() && !STOP_WORDS.contains(&w.as_str()))
+        .unwrap_or_default();
+    format!("{surname}{year}{word}")
+}
+
+/// A complete entry: `@inproceedings` for a paper at a conference or workshop, `@article` for one
+/// in a journal, `@misc` (with arXiv eprint fields when available) otherwise.
+pub fn entry(key: &str, metadata: &PaperMetadata) -> String {
+    let venue = metadata.venue.as_ref();
+    let (kind, container) = match (venue.map(|v| v.kind), &metadata.journal) {
+        | (Some(VenueKind::Conference | VenueKind::Workshop), _) => {
+            ("inproceedings", venue.map(|v| ("booktitle", &v.name)))
+        }
+        // The journal reference has the volume and pages the canonical name leaves out.
+        | (_, Some(journal)) => ("article", Some(("journal", journal))),
+        | (Some(VenueKind::Journal), None) => ("article", venue.map(|v| ("journal", &v.name))),
+        | (_, None) => ("misc", None),
+    };
+    let mut fields: Vec<(&str, String)> = vec![
+        ("title", format!("{{{}}}", escape(&metadata.title))),
+        ("author", escape(&metadata.authors.join(" and "))),
+    ];
+    if let Some((name, value)) = container {
+        fields.push((name, escape(value)));
+    }
+    // A conference paper is cited with the year it was presented, not the year of its preprint.
+    match (kind, venue.and_then(|v| v.year), metadata.published) {
+        | ("inproceedings", Some(year), _) => fields.push(("year", year.to_string())),
+        | (_, _, Some(date)) => {
+            fields.push(("year", date.format("%Y").to_string()));
+            fields.push(("month", date.format("%b").to_string().to_lowercase()));
+        }
+        | (_, year, None) => fields.extend(year.map(|y| ("year", y.to_string()))),
+    }
+    if let Some(doi) = &metadata.doi {
+        fields.push(("doi", doi.clone()));
+    }
+    if let Some(arxiv) = &metadata.arxiv_id {
+        fields.push(("eprint", arxiv.clone()));
+        fields.push(("archivePrefix", "arXiv".to_string()));
+    }
+    if let Some(pmid) = &metadata.pmid {
+        fields.push(("pmid", pmid.clone()));
+    }
+    if let Some(url) = &metadata.url {
+        fields.push(("url", url.clone()));
+    }
+
+    let mut out = format!("@{kind}{{{key},\n");
+    for (name, value) in fields.iter().filter(|(_, v)| !v.is_empty()) {
+        let _ = writeln!(out, "  {name} = {{{value}}},");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// `(citekey, entry text)` for every entry in a `.bib` file. Entries start with an `@` at the
+/// beginning of a line; `@comment`, `@string` and `@preamble` blocks are skipped.
+pub fn entries(bib: &str) -> Vec<(&str, &str)> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+    for line in bib.split_inclusive('\n') {
+        if line.trim_start().starts_with('@') {
+            starts.push(offset + line.len() - line.trim_start().len());
+        }
+        offset += line.len();
+    }
+    starts
+        .iter()
+        .enumerate()
+        .filter_map(|(n, &start)| {
+            let end = starts.get(n + 1).copied().unwrap_or(bib.len());
+            let text = &bib[start..end];
+            let (kind, rest) = text[1..].split_once(['{', '('])?;
+            let kind = kind.trim().to_ascii_lowercase();
+            if kind.is_empty()
+                || !kind.chars().all(|c| c.is_ascii_alphabetic())
+                || matches!(kind.as_str(), "comment" | "string" | "preamble")
+            {
+                return None;
+            }
+            let key = rest.split(',').next()?.trim();
+            (!key.is_empty() && !key.contains(char::is_whitespace)).then_some((key, text))
+        })
+        .collect()
+}
+
+/// Append the entry unless the file already has one for this paper (under any key), and return
+/// the citekey to use (see [`key_in`]).
+pub async fn add_to_file(path: &Path, metadata: &PaperMetadata) -> Result<String> {
+    // Papers processed side by side must not both take a free key.
+    static WRITING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _writing = WRITING.lock().await;
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let existing = match tokio::fs::read_to_string(path).await {
+        | Ok(text) => text,
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        | Err(e) => return Err(io_err(e)),
+    };
+    let Some((key, present)) = key_in(&existing, metadata) else {
+        return Err(MabelError::Config {
+            msg: format!(
+                "every variant of citekey {} is taken in {}",
+                citekey(metadata),
+                path.display()
+            ),
+        });
+    };
+    if present {
+        tracing::info!(key, bib = %path.display(), "already in the bibliography");
+        return Ok(key);
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await.map_err(|source| {
+            MabelError::Io {
+                path: dir.to_path_buf(),
+                source,
+            }
+        })?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(io_err)?;
+    let separator = match existing.as_str() {
+        | "" => "",
+        | text if text.ends_with("\n\n") => "",
+        | text if text.ends_with('\n') => "\n",
+        | _ => "\n\n",
+    };
+    let text = format!("{separator}{}", entry(&key, metadata));
+    file.write_all(text.as_bytes()).await.map_err(io_err)?;
+    tracing::info!(key, bib = %path.display(), "added to the bibliography");
+    Ok(key)
+}
+
+/// The paper's key in `bib` and whether it is there: the key of its entry when `bib` has one,
+/// otherwise its citekey, with a letter suffix (`smith2020deepa`, ...) when another paper has that.
+/// `None` when every suffix is taken.
+pub fn key_in(bib: &str, metadata: &PaperMetadata) -> Option<(String, bool)> {
+    let entries = entries(bib);
+    if let Some((key, _)) = entries.iter().find(|(_, entry)| is_same_paper(entry, metadata)) {
+        return Some(((*key).to_string(), true));
+    }
+    let base = citekey(metadata);
+    std::iter::once(base.clone())
+        .chain(('a'..='z').map(|c| format!("{base}{c}")))
+        .find(|key| !entries.iter().any(|(k, _)| k == key))
+        .map(|key| (key, false))
+}
+
+/// Whether an existing entry is for this paper, judged by DOI, arXiv id or title.
+fn is_same_paper(entry: &str, metadata: &PaperMetadata) -> bool {
+    let entry = entry.to_lowercase();
+    let ids = [
+        metadata.doi.as_deref(),
+        metadata.arxiv_id.as_deref(),
+        metadata.pmid.as_deref(),
+    ];
+    if ids.into_iter().flatten().any(|id| entry.contains(&id.to_lowercase())) {
+        return true;
+    }
+    let squash = |s: &str| {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    };
+    !metadata.title.is_empty() && squash(&entry).contains(&squash(&metadata.title))
+}
+
+/// The paper's entry for its note, under the key it has in `MABEL_BIB_FILE` or will get when it is
+/// added there; the citekey when no `.bib` file is set or it cannot be read.
+pub fn for_note(cfg: &Config, metadata: &PaperMetadata) -> BibEntry {
+    let key = cfg.bib_file.as_ref().and_then(|path| {
+        match std::fs::read_to_string(path) {
+            | Ok(bib) => key_in(&bib, metadata).map(|(key, _)| key),
+            | Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            | Err(e) => {
+                tracing::warn!(bib = %path.display(), error = %e, "could not read the vault's .bib file");
+                None
+            }
+        }
+    });
+    let key = key.unwrap_or_else(|| citekey(metadata));
+    BibEntry {
+        entry: entry(&key, metadata),
+        key,
+    }
+}
+
+/// Add the paper to `MABEL_BIB_FILE`, when one is set. Best effort: a failure is only logged.
+pub async fn add_to_vault_bib(cfg: &Config, metadata: &PaperMetadata) {
+    if let Some(path) = &cfg.bib_file {
+        if let Err(e) = add_to_file(path, metadata).await {
+            tracing::warn!(bib = %path.display(), error = %e, "could not add the paper to the vault's .bib file");
+        }
+    }
+}
+
+fn ascii_word(word: &str) -> String {
+    slug::slugify(word).replace('-', "")
+}
+
+/// Escape the characters LaTeX treats specially in running text.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            | '&' | '%' | '$' | '#' | '_' => {
+                out.push('\\');
+                out.push(c);
+            }
+            | '{' | '}' => {}
+            | _ => out.push(c),
+        }
+    }
+    out
+}
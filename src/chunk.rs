@@ -0,0 +1,270 @@
+//! Splitting a paper too long for one prompt into parts, for map-reduce summaries: each part is
+//! summarized on its own, a few at a time, and the summary is then written from the notes on all
+//! of them (see [`crate::summarize::paper`]).
+//!
+//! A part is as long as the model's context window leaves room for, after the instructions and a
+//! reply of the full `max_tokens`, and never longer than [`prompt::MAX_INPUT_CHARS`];
+//! `MABEL_CHUNK_CHARS` sets it instead. Parts break between sections where they can and between
+//! paragraphs in a section too long for one part, and each part after the first starts with the
+//! last `MABEL_CHUNK_OVERLAP` characters of the one before, so what is said across a break is in
+//! both. A model missing from the context table gets parts of `MAX_INPUT_CHARS`, as long as a
+//! single prompt is.
+//!
+//! Parts then shrink to the model's [`Pace`]: how fast and how reliably it answered in its last
+//! runs, as their timing reports have it. A model replying at [`FAST_TOKENS_PER_SEC`] or faster
+//! keeps parts of the full length, a slower one (a local model on a laptop, say) gets shorter
+//! parts in proportion so each reply comes back in good time, and requests that failed shrink
+//! them further. Speed is measured on the replies that came back only: a failed request counts
+//! as a failure and nothing else.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, cost::CHARS_PER_TOKEN, llm::Llm, prompt, store, timing::RunReport, Result};
+
+/// Context windows in tokens, by model name prefix; the longest prefix that matches wins.
+const CONTEXT_WINDOWS: &[(&str, u64)] = &[
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o1-mini", 128_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude-", 200_000),
+    ("gemini-", 1_048_576),
+    ("gemini-1.5-pro", 2_097_152),
+];
+
+/// Tokens kept for the instructions around the paper's text.
+const INSTRUCTION_TOKENS: u64 = 2_000;
+
+/// The shortest part, in characters, however small the context window.
+const MIN_PART_CHARS: usize = 2_000;
+
+/// Replies at this many tokens a second or more get parts of the full length.
+const FAST_TOKENS_PER_SEC: f64 = 40.0;
+
+/// The least share of the full length a slow or failing model's parts shrink to.
+const MIN_PACE_SHARE: f64 = 0.25;
+
+/// Requests a model's pace needs before it sizes parts.
+const MIN_PACE_REQUESTS: u32 = 3;
+
+/// Runs kept per model for its pace.
+const PACE_RUNS: usize = 20;
+
+/// How fast and how reliably a model answered in its last runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pace {
+    /// Reply tokens a second, over the time spent waiting on replies that came back; `None`
+    /// before any did
+    pub tokens_per_sec: Option<f64>,
+    /// Share of requests that failed after their retries
+    pub failure_rate: f64,
+}
+
+impl Pace {
+    /// The share of the full part length the model gets.
+    fn share(self) -> f64 {
+        let speed = self.tokens_per_sec.map_or(1.0, |t| (t / FAST_TOKENS_PER_SEC).min(1.0));
+        (speed * (1.0 - self.failure_rate)).clamp(MIN_PACE_SHARE, 1.0)
+    }
+}
+
+/// One run with a model, as kept in [`Config::pace_path`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PaceRun {
+    model: String,
+    tokens_out: u64,
+    llm_ms: u64,
+    requests: u32,
+    failures: u32,
+}
+
+/// The pace of `model` over its last runs; `None` before enough of its requests were timed.
+pub async fn pace(cfg: &Config, model: &str) -> Option<Pace> {
+    let runs: Vec<PaceRun> = store::load(&cfg.pace_path())
+        .await
+        .inspect_err(|e| tracing::warn!(error = %e, "could not read the models' pace; parts keep their length"))
+        .ok()?;
+    let (mut tokens, mut ms, mut requests, mut failures) = (0, 0, 0, 0);
+    for run in runs.iter().filter(|r| r.model == model) {
+        tokens += run.tokens_out;
+        ms += run.llm_ms;
+        requests += run.requests;
+        failures += run.failures;
+    }
+    if requests < MIN_PACE_REQUESTS {
+        return None;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    Some(Pace {
+        tokens_per_sec: (ms > 0).then(|| tokens as f64 * 1000.0 / ms as f64),
+        failure_rate: f64::from(failures) / f64::from(requests),
+    })
+}
+
+/// Keep how a run with `model` went, from its timing report, dropping all but the model's last
+/// [`PACE_RUNS`] runs.
+pub async fn record_pace(cfg: &Config, model: &str, report: &RunReport) -> Result<()> {
+    // Papers processed at the same time would otherwise drop each other's runs.
+    static RECORDING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    if report.llm_requests == 0 {
+        return Ok(());
+    }
+    let _recording = RECORDING.lock().await;
+    let path = cfg.pace_path();
+    let mut runs: Vec<PaceRun> = store::load(&path).await?;
+    // A run whose replies were not counted (its summary failed) has no speed to tell.
+    let (tokens_out, llm_ms) = if report.tokens_out == 0 {
+        (0, 0)
+    } else {
+        (report.tokens_out, report.llm_ms.saturating_sub(report.llm_failed_ms))
+    };
+    runs.push(PaceRun {
+        model: model.to_string(),
+        tokens_out,
+        llm_ms,
+        requests: report.llm_requests,
+        failures: report.llm_failures,
+    });
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut kept: Vec<PaceRun> = runs
+        .iter()
+        .rev()
+        .filter(|r| {
+            let count = seen.entry(r.model.as_str()).or_default();
+            *count += 1;
+            *count <= PACE_RUNS
+        })
+        .cloned()
+        .collect();
+    kept.reverse();
+    store::replace(&path, &kept, |_| true).await?;
+    Ok(())
+}
+
+/// The context window of the model `llm` runs, in tokens; `None` when it is not known.
+pub fn context_tokens(llm: &Llm) -> Option<u64> {
+    if let Some(num_ctx) = llm.num_ctx() {
+        return Some(u64::from(num_ctx));
+    }
+    if llm.is_local() {
+        return None;
+    }
+    let model = llm.model().to_ascii_lowercase();
+    CONTEXT_WINDOWS
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|&(_, tokens)| tokens)
+}
+
+/// The longest part, in characters, for `llm` at `pace`.
+pub fn part_chars(cfg: &Config, llm: &Llm, pace: Option<Pace>) -> usize {
+    if let Some(chars) = cfg.chunk_chars {
+        return (chars as usize).clamp(MIN_PART_CHARS, prompt::MAX_INPUT_CHARS);
+    }
+    let full = match context_tokens(llm) {
+        | Some(context) => {
+            let room = context.saturating_sub(u64::from(cfg.llm.max_tokens()) + INSTRUCTION_TOKENS);
+            usize::try_from(room)
+                .unwrap_or(usize::MAX)
+                .saturating_mul(CHARS_PER_TOKEN)
+                .min(prompt::MAX_INPUT_CHARS)
+        }
+        | None => prompt::MAX_INPUT_CHARS,
+    };
+    let Some(pace) = pace else {
+        return full.max(MIN_PART_CHARS);
+    };
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let paced = (full as f64 * pace.share()) as usize;
+    tracing::debug!(
+        tokens_per_sec = pace.tokens_per_sec,
+        failure_rate = pace.failure_rate,
+        chars = paced,
+        "sized parts to the model's pace"
+    );
+    paced.max(MIN_PART_CHARS)
+}
+
+/// `text` in the parts `llm` is given it in: the whole of it when it fits one prompt or
+/// `MABEL_MAP_REDUCE` is off.
+pub fn split(cfg: &Config, llm: &Llm, pace: Option<Pace>, text: &str) -> Vec<String> {
+    let size = part_chars(cfg, llm, pace);
+    if !cfg.map_reduce || text.len() <= size {
+        return vec![text.to_string()];
+    }
+    let overlap = (cfg.chunk_overlap as usize).min(size / 4);
+    let budget = size - overlap;
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    // The part's start carried over from the one before
+    let mut carried = 0;
+    for piece in pieces(text, budget) {
+        if part.len() + piece.len() > size && !part[carried..].trim().is_empty() {
+            let overlap = tail(&part, overlap).to_string();
+            carried = overlap.len();
+            parts.push(std::mem::replace(&mut part, overlap));
+        }
+        part.push_str(piece);
+    }
+    if !part[carried..].trim().is_empty() {
+        parts.push(part);
+    }
+    tracing::debug!(parts = parts.len(), chars = size, overlap, "split the paper");
+    parts
+}
+
+/// `text` in consecutive pieces no longer than `budget`: its sections, and the paragraphs of a
+/// section longer than that, cut where they still do not fit.
+fn pieces(text: &str, budget: usize) -> Vec<&str> {
+    let mut starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices("\n#").map(|(i, _)| i + 1))
+        .collect();
+    starts.push(text.len());
+    let mut out = Vec::new();
+    for section in starts.windows(2).map(|w| &text[w[0]..w[1]]) {
+        if section.len() <= budget {
+            out.push(section);
+            continue;
+        }
+        for paragraph in section.split_inclusive("\n\n") {
+            let mut rest = paragraph;
+            while rest.len() > budget {
+                let cut = prompt::truncate(rest, budget);
+                let at = cut
+                    .rfind(char::is_whitespace)
+                    .filter(|&at| at > budget / 2)
+                    .unwrap_or(cut.len());
+                out.push(&rest[..at]);
+                rest = &rest[at..];
+            }
+            out.push(rest);
+        }
+    }
+    out
+}
+
+/// The last `chars` or so characters of `text`, starting at a word.
+fn tail(text: &str, chars: usize) -> &str {
+    let mut start = text.len().saturating_sub(chars);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let tail = &text[start..];
+    match tail.find(char::is_whitespace) {
+        | Some(at) if start > 0 => tail[at..].trim_start(),
+        | _ => tail,
+    }
+}
@@ -0,0 +1,250 @@
+//! `--ci`: running unattended, e.g. from a GitHub Actions workflow that keeps a team vault. Nothing
+//! is asked (a question is answered no unless `--yes` is given), replies are not streamed with a
+//! progress line, and logs carry no colours.
+//!
+//! What a paper logs at or above `MABEL_FAIL_ON` while it is processed fails it, even when its note
+//! was written, so the workflow stops before committing a note that came out wrong. `mabel batch
+//! --junit FILE` reports each paper as a test case for the CI's test view, with its log.
+
+use std::{
+    fmt::{self, Write as _},
+    future::Future,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::{MabelError, Result};
+
+/// The least severe log that fails a paper under `--ci` (`MABEL_FAIL_ON`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Severity {
+    /// Warnings and errors
+    #[default]
+    Warn,
+    /// Errors only
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Warn => "warn",
+            | Self::Error => "error",
+        }
+    }
+
+    /// Fail a paper for what it `logged` at this severity or above.
+    pub fn check(self, logged: &[Logged]) -> Result<()> {
+        let least = match self {
+            | Self::Warn => Level::WARN,
+            | Self::Error => Level::ERROR,
+        };
+        // More verbose levels compare greater.
+        let mut failing = logged.iter().filter(|l| l.level <= least);
+        let Some(first) = failing.next() else {
+            return Ok(());
+        };
+        Err(MabelError::FailedOn {
+            count: 1 + failing.count(),
+            level: self.as_str(),
+            first: first.message.clone(),
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            | "warn" | "warning" => Ok(Self::Warn),
+            | "error" => Ok(Self::Error),
+            | _ => {
+                Err(MabelError::Config {
+                    msg: format!("unknown severity {s:?} (expected warn or error)"),
+                })
+            }
+        }
+    }
+}
+
+/// A warning or error logged while a paper was processed.
+#[derive(Clone, Debug)]
+pub struct Logged {
+    pub level: Level,
+    /// The message with its fields, as the log line shows them
+    pub message: String,
+}
+
+impl fmt::Display for Logged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.level, self.message)
+    }
+}
+
+tokio::task_local! {
+    static LOGGED: Arc<Mutex<Vec<Logged>>>;
+}
+
+/// Run `work`, keeping the warnings and errors it logs. Only those logged from the task it runs on
+/// are kept, not those of tasks it spawns.
+pub async fn collect<T>(work: impl Future<Output = T>) -> (T, Vec<Logged>) {
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let out = LOGGED.scope(logged.clone(), work).await;
+    let logged = std::mem::take(&mut *logged.lock().unwrap_or_else(PoisonError::into_inner));
+    (out, logged)
+}
+
+/// The log layer that hands what it is given to [`collect`]; filter it to warnings and errors.
+pub struct Collector;
+
+impl<S: Subscriber> Layer<S> for Collector {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        let _ = LOGGED.try_with(|logged| {
+            let mut message = Message::default();
+            event.record(&mut message);
+            logged.lock().unwrap_or_else(PoisonError::into_inner).push(Logged {
+                level,
+                message: message.text,
+            });
+        });
+    }
+}
+
+/// A log event's message followed by its other fields.
+#[derive(Default)]
+struct Message {
+    text: String,
+}
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            // The message goes first, whichever order the fields come in.
+            self.text = format!("{value:?}{}", self.text);
+        } else {
+            let _ = write!(self.text, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{value}"));
+        } else {
+            let _ = write!(self.text, " {}={value}", field.name());
+        }
+    }
+}
+
+/// How a paper went, for the `JUnit` report.
+#[derive(Debug)]
+pub enum Verdict {
+    /// The note was written there
+    Passed(String),
+    Skipped(String),
+    Failed(String),
+}
+
+/// One paper in the `JUnit` report.
+#[derive(Debug)]
+pub struct Case {
+    pub name: String,
+    /// The list the paper came from
+    pub source: String,
+    pub time: Duration,
+    pub verdict: Verdict,
+    pub logged: Vec<Logged>,
+}
+
+/// Write the `JUnit` XML report of `cases`, one test suite named `suite`.
+pub fn write_junit(path: &Path, suite: &str, cases: &[Case], timestamp: &str) -> Result<()> {
+    let count = |f: fn(&Verdict) -> bool| cases.iter().filter(|c| f(&c.verdict)).count();
+    let failures = count(|v| matches!(v, Verdict::Failed(_)));
+    let skipped = count(|v| matches!(v, Verdict::Skipped(_)));
+    let time: f64 = cases.iter().map(|c| c.time.as_secs_f64()).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"mabel\" tests=\"{}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{time:.3}\">",
+        cases.len()
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" errors=\"0\" skipped=\"{skipped}\" \
+         time=\"{time:.3}\" timestamp=\"{}\">",
+        escape(suite),
+        cases.len(),
+        escape(timestamp)
+    );
+    for case in cases {
+        let _ = writeln!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">",
+            escape(&case.name),
+            escape(&case.source),
+            case.time.as_secs_f64()
+        );
+        match &case.verdict {
+            | Verdict::Passed(path) => {
+                let _ = writeln!(xml, "      <system-out>{}</system-out>", escape(path));
+            }
+            | Verdict::Skipped(why) => {
+                let _ = writeln!(xml, "      <skipped message=\"{}\"/>", escape(why));
+            }
+            | Verdict::Failed(why) => {
+                let _ = writeln!(
+                    xml,
+                    "      <failure message=\"{}\">{}</failure>",
+                    escape(why),
+                    escape(why)
+                );
+            }
+        }
+        if !case.logged.is_empty() {
+            let log: Vec<String> = case.logged.iter().map(ToString::to_string).collect();
+            let _ = writeln!(xml, "      <system-err>{}</system-err>", escape(&log.join("\n")));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|source| {
+            MabelError::Io {
+                path: dir.to_path_buf(),
+                source,
+            }
+        })?;
+    }
+    std::fs::write(path, xml).map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            | '&' => out.push_str("&amp;"),
+            | '<' => out.push_str("&lt;"),
+            | '>' => out.push_str("&gt;"),
+            | '"' => out.push_str("&quot;"),
+            | '\'' => out.push_str("&apos;"),
+            // Control characters other than tab and newline are not allowed in XML 1.0.
+            | c if c.is_control() && c != '\t' && c != '\n' => {}
+            | c => out.push(c),
+        }
+    }
+    out
+}
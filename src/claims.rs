@@ -0,0 +1,165 @@
+//! Claim knowledge base: atomic claims extracted from each processed paper, kept in one JSONL file
+//! in the vault (`.mabel/claims.jsonl`) so they can be searched across papers.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    llm::{self, Llm, Usage},
+    paper::PaperMetadata,
+    prompt, store, MabelError, Result,
+};
+
+/// What kind of support the paper offers for a claim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Evidence {
+    Experimental,
+    Theoretical,
+    Observational,
+    /// Survey or meta-analysis of other work
+    Review,
+    Anecdotal,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strength {
+    Strong,
+    Moderate,
+    Weak,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Evidence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Experimental => "experimental",
+            | Self::Theoretical => "theoretical",
+            | Self::Observational => "observational",
+            | Self::Review => "review",
+            | Self::Anecdotal => "anecdotal",
+            | Self::Other => "other",
+        }
+    }
+}
+
+impl Strength {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Strong => "strong",
+            | Self::Moderate => "moderate",
+            | Self::Weak => "weak",
+            | Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// One claim as the model returns it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Claim {
+    pub statement: String,
+    #[serde(default = "Claim::default_evidence")]
+    pub evidence: Evidence,
+    #[serde(default = "Claim::default_strength")]
+    pub strength: Strength,
+}
+
+impl Claim {
+    fn default_evidence() -> Evidence {
+        Evidence::Other
+    }
+
+    fn default_strength() -> Strength {
+        Strength::Unknown
+    }
+}
+
+/// A stored claim, with enough about its paper to find the note again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClaimRecord {
+    pub statement: String,
+    pub evidence: Evidence,
+    pub strength: Strength,
+    /// Wikilink target of the paper's note
+    pub note: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arxiv: Option<String>,
+    pub extracted: String,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    #[serde(default)]
+    claims: Vec<Claim>,
+}
+
+/// Ask the model for the paper's claims.
+pub async fn extract(llm: &Llm, metadata: &PaperMetadata, text: &str) -> Result<(Vec<Claim>, Usage)> {
+    if text.trim().is_empty() {
+        return Err(MabelError::Extraction {
+            reason: format!("no text to extract claims from for {:?}", metadata.title),
+        });
+    }
+    let completion = llm.complete(&prompt::claims(metadata, text)).await?;
+    let candidate = llm::json_object(&completion.text);
+    let mut claims = serde_json::from_str::<Reply>(candidate).map_or_else(
+        |e| {
+            tracing::warn!(error = %e, "claims reply was not the requested JSON");
+            Vec::new()
+        },
+        |r| r.claims,
+    );
+    claims.retain(|c| !c.statement.trim().is_empty());
+    Ok((claims, completion.usage))
+}
+
+/// Every record in the store; a missing store is empty.
+pub async fn load(path: &Path) -> Result<Vec<ClaimRecord>> {
+    store::load(path).await
+}
+
+/// Store `records` for the note `note`, replacing whatever was stored for it before.
+pub async fn replace(path: &Path, note: &str, records: &[ClaimRecord]) -> Result<()> {
+    store::replace(path, records, |r| r.note == note).await.map(drop)
+}
+
+/// Records matching `query`, best first: more query words present, then stronger claims. Words
+/// match loosely, so "transformers" finds "transformer".
+pub fn search<'a>(records: &'a [ClaimRecord], query: &str) -> Vec<&'a ClaimRecord> {
+    let words: Vec<String> = query.split_whitespace().map(stem).filter(|w| !w.is_empty()).collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let mut hits: Vec<(usize, &ClaimRecord)> = records
+        .iter()
+        .filter_map(|r| {
+            let text = r.statement.to_lowercase();
+            let matched = words.iter().filter(|w| text.contains(w.as_str())).count();
+            // Require most of the query, not just one common word.
+            (matched * 2 >= words.len() && matched > 0).then_some((matched, r))
+        })
+        .collect();
+    hits.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.strength.cmp(&b.1.strength)));
+    hits.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Lowercase and drop a plural/verb `s`, which is as much stemming as claim search needs.
+fn stem(word: &str) -> String {
+    let word: String = word
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    match word.strip_suffix('s') {
+        | Some(stem) if stem.len() >= 3 && !stem.ends_with('s') => stem.to_string(),
+        | _ => word,
+    }
+}
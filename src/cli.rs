@@ -1 +1,865 @@
-use crate::{MabelError, Result};
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+/// Turn research papers into Obsidian notes.
+#[derive(Debug, Parser)]
+#[command(name = "mabel", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Flags shared by every subcommand; all but `--var` fall back to an environment variable.
+#[derive(Debug, Args)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct GlobalArgs {
+    /// Obsidian vault root [env: `OBSIDIAN_VAULT_PATH`]
+    #[arg(long, global = true)]
+    pub vault_path: Option<PathBuf>,
+
+    /// Folder inside the vault for paper notes [env: `OBSIDIAN_SUBDIR`] [default: Papers]
+    #[arg(long, global = true)]
+    pub vault_subdir: Option<String>,
+
+    /// Where downloads and intermediate artifacts are kept [env: `MABEL_CACHE_DIR`]
+    #[arg(long, global = true)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Use the settings of the `[profile.NAME]` tables in the config files over the files' own
+    /// [env: `MABEL_PROFILE`]
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Summarize with a local Ollama model instead of OpenAI [env: `MABEL_BACKEND=ollama`]
+    #[arg(long, global = true)]
+    pub ollama: bool,
+
+    /// Summarize with an Anthropic Claude model instead of OpenAI; the default when only
+    /// `ANTHROPIC_API_KEY` is set [env: `MABEL_BACKEND=anthropic`]
+    #[arg(long, global = true, conflicts_with = "ollama")]
+    pub anthropic: bool,
+
+    /// Summarize with a Google Gemini model instead of OpenAI; the default when only
+    /// `GEMINI_API_KEY` is set [env: `MABEL_BACKEND=gemini`]
+    #[arg(long, global = true, conflicts_with_all = ["ollama", "anthropic"])]
+    pub gemini: bool,
+
+    /// Ollama base URL [env: `OLLAMA_HOST`]
+    #[arg(long, global = true)]
+    pub ollama_host: Option<String>,
+
+    /// Model name for the selected backend [env: `OPENAI_MODEL` / `OLLAMA_MODEL` / `ANTHROPIC_MODEL` /
+    /// `GEMINI_MODEL`]
+    #[arg(long, global = true)]
+    pub model: Option<String>,
+
+    /// Wait for whole replies instead of streaming them with a progress line in the terminal
+    /// (OpenAI and Ollama) [env: `MABEL_STREAM=false`]
+    #[arg(long, global = true)]
+    pub no_stream: bool,
+
+    /// Run unattended, as in CI: never ask (questions are answered no unless `--yes` is given),
+    /// don't stream or colour the output, and fail a paper on any warning it logs, or only on
+    /// errors with `MABEL_FAIL_ON=error` [env: `MABEL_CI`]
+    #[arg(long, global = true)]
+    pub ci: bool,
+
+    /// OpenAI API key [env: `OPENAI_API_KEY`]
+    #[arg(long, global = true)]
+    pub openai_key: Option<String>,
+
+    /// GROBID service for PDF extraction [env: `GROBID_URL`]
+    #[arg(long, global = true)]
+    pub grobid_url: Option<String>,
+
+    /// Note template (Tera) [env: `MABEL_TEMPLATE`]
+    #[arg(long, global = true)]
+    pub template: Option<PathBuf>,
+
+    /// Output style: `concise`, `study`, `eli-grad`, `skim` or `flashcards` [env: `MABEL_MODE`]
+    #[arg(long, global = true)]
+    pub mode: Option<String>,
+
+    /// System prompt template (Tera) for the mode's summaries; `mabel template prompts` writes out
+    /// the built-in ones [env: `MABEL_PROMPT_TEMPLATE`]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub prompt_template: Option<PathBuf>,
+
+    /// Download PDFs at no more than this many bytes per second, e.g. `500K` or `2M`
+    /// [env: `MABEL_MAX_BANDWIDTH`]
+    #[arg(long, global = true, value_name = "RATE")]
+    pub max_bandwidth: Option<String>,
+
+    /// On a metered connection: write notes from the abstract and queue the PDFs for
+    /// `mabel flush-queue` [env: `MABEL_METERED`]
+    #[arg(long, global = true)]
+    pub metered: bool,
+
+    /// Context for this run, e.g. `--var focus="latency on mobile"`; repeatable. The model sees it
+    /// with the summary prompt, and note and prompt templates as `vars.focus`
+    #[arg(long = "var", global = true, value_name = "KEY=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+}
+
+/// A `--var`: a name templates can use (letters, digits and `_`) and any value.
+fn parse_var(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw.split_once('=').ok_or("expected KEY=VALUE")?;
+    let key = key.trim();
+    let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("{key:?} is not a usable name: use letters, digits and _"));
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Process one paper into a vault note
+    #[command(alias = "add")]
+    Note(NoteArgs),
+    /// Process several papers in one run, a few at a time, and list how each went
+    Batch(BatchArgs),
+    /// Process the PDFs dropped into a folder, such as the browser's downloads, as they arrive
+    Watch(WatchArgs),
+    /// Download the PDFs put off on a metered connection and rewrite their notes from the full text
+    FlushQueue(FlushQueueArgs),
+    /// Search notes already in the vault
+    Search(SearchArgs),
+    /// List the papers processed on this machine, most recent first
+    List(ListArgs),
+    /// Show what the paper index has on a paper: its note, when it was processed, the model and tokens
+    Info(InfoArgs),
+    /// Re-check the metadata of processed arXiv papers and update their notes' frontmatter
+    Update(UpdateArgs),
+    /// Refresh the citation counts and Altmetric scores in paper notes' frontmatter
+    RefreshCitations(RefreshCitationsArgs),
+    /// List the notes due for a refresh (new arXiv versions, older prompts, old citation counts)
+    /// in a report note
+    Freshness(FreshnessArgs),
+    /// Append a timestamped comment of your own to a paper's note
+    Annotate(AnnotateArgs),
+    /// Read a paper section by section with the model explaining each one and answering questions,
+    /// and keep the session in the paper's note
+    Read(ReadArgs),
+    /// Take over a hand-written paper note, so `update` and `note --overwrite` keep it current
+    Adopt(AdoptArgs),
+    /// Search or build the knowledge base of claims extracted from papers
+    Claims {
+        #[command(subcommand)]
+        action: ClaimsAction,
+    },
+    /// Rank recent arXiv papers by how well they match what you have read, into a digest note
+    Digest(DigestArgs),
+    /// Ask the digest for more or fewer papers like one
+    Feedback(FeedbackArgs),
+    /// Print a paper's BibTeX entry, or add it to a LaTeX project's `.bib` file
+    Cite(CiteArgs),
+    /// Write an annotated bibliography of the papers whose notes carry a tag, taking the
+    /// annotations from the notes' summaries
+    Bibliography(BibliographyArgs),
+    /// Synthesize one thematic brief across several papers (what they share, where they disagree,
+    /// what they leave open) into a note linking each paper's note
+    Brief(BriefArgs),
+    /// Rename a tag or link target across mabel's notes
+    Refactor {
+        #[command(subcommand)]
+        action: RefactorAction,
+    },
+    /// Inspect or clear the download cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Move mabel's state (registry, index, cache and config) to another machine
+    State {
+        #[command(subcommand)]
+        action: StateAction,
+    },
+    /// Inspect the resolved configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print or validate note templates
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+    /// Run an HTTP server that accepts papers to process
+    Serve(ServeArgs),
+    /// Run a long-running mode in the background at login (systemd, launchd or a Windows task)
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Summarize one paper with several models/temperatures and compare the notes side by side
+    Experiment(ExperimentArgs),
+    /// Compare prompt templates over a corpus, scored by an LLM judge
+    Eval(EvalArgs),
+    /// Measure how well the available PDF extractors do on a folder of papers
+    Bench {
+        #[command(subcommand)]
+        action: BenchAction,
+    },
+    /// Check the install end to end on a canned paper, against a built-in mock of arXiv, GROBID
+    /// and the model: no network, no tokens (`--features test-server`)
+    Selftest(SelftestArgs),
+    /// Check the configuration, vault, cache, templates, GROBID and the model backend against the
+    /// real services, with a hint for each check that fails; spends no tokens
+    Doctor,
+    /// List `mabel-<name>` plugins found on PATH
+    Plugins,
+    /// Any other name runs the `mabel-<name>` executable from PATH with the remaining arguments
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+/// How notes are written; shared by every command that produces notes.
+#[derive(Debug, Args)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct OutputArgs {
+    /// Update an existing note instead of refusing to write: its managed regions and frontmatter
+    /// are regenerated, and the text, frontmatter fields and tags you added are kept
+    #[arg(long)]
+    pub overwrite: bool,
+
+    /// Replace an existing note wholesale, dropping whatever was added to it
+    #[arg(long)]
+    pub force_overwrite: bool,
+
+    /// Copy the source PDF next to the note
+    #[arg(long)]
+    pub copy_pdf_into_vault: bool,
+
+    /// Also write the paper's extracted full text into the vault's `Full text` folder, where
+    /// Obsidian search finds it
+    #[arg(long)]
+    pub text_sidecar: bool,
+
+    /// Also write a thread of short numbered posts on the paper into the vault's `Threads` folder,
+    /// for sharing on Mastodon or Slack (posts of at most `MABEL_THREAD_CHARS`)
+    #[arg(long)]
+    pub thread: bool,
+
+    /// Link the technical terms in the summary to their vault notes, and write a short concept
+    /// note for those without one (at most `MABEL_MAX_NEW_TERMS` per paper)
+    #[arg(long)]
+    pub define_new_terms: bool,
+
+    /// Show how an existing note would change and ask before writing it
+    #[arg(long)]
+    pub preview_diff: bool,
+
+    /// Don't ask: write after showing the diff, and summarize papers longer than
+    /// `MABEL_MAX_PAGES`
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Take the best guess at a local PDF's metadata when its title is uncertain, instead of
+    /// showing the candidates and asking
+    #[arg(long)]
+    pub no_interactive: bool,
+
+    /// What to print on stdout: note paths, or a JSON record per paper and line with its metadata,
+    /// note path, tokens, timings and warnings, for scripts and CI jobs. Logs stay on stderr
+    #[arg(long = "output", value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// What commands that write notes print on stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Note paths, for people
+    #[default]
+    Text,
+    /// JSON Lines, one record per paper (see [`crate::output`])
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct NoteArgs {
+    /// Paper to process: arXiv id/URL, PubMed id (`PMID:…`), PMC id (`PMC…`), DOI (`10.…`), a
+    /// JATS XML / EPUB / PDF file, or a PDF in a bucket (`s3://…`, `gs://…`)
+    pub input: String,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// EPUB chapters to process, e.g. `3`, `1-4,7` or `5-` (default: all)
+    #[arg(long, value_name = "LIST")]
+    pub chapters: Option<String>,
+
+    /// Only resolve metadata and print it as JSON to stdout; no LLM call, no note written
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// Papers to process, in any form `mabel note` accepts
+    #[arg(required_unless_present = "file")]
+    pub inputs: Vec<String>,
+
+    /// Also process the papers listed in FILE, one per line (`-` reads stdin); blank lines and
+    /// lines starting with `#` are skipped. Repeat it for several lists, which take turns; papers
+    /// given on the command line go first
+    #[arg(long, short, value_name = "FILE")]
+    pub file: Vec<PathBuf>,
+
+    /// Papers worked on at once. Their downloads, extractions and model requests each have a limit
+    /// of their own (`MABEL_MAX_CONCURRENT_DOWNLOADS`, `..._EXTRACTIONS`, `..._LLM`), so one
+    /// paper's PDF downloads while another's summary is written
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Write a `JUnit` XML report to FILE, with a test case for each paper, for CI test views
+    #[arg(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+
+    /// Send the summary requests through the OpenAI Batch API, at half the price. Batches can take
+    /// up to a day; stop the run and start it again to pick up where it was
+    #[arg(long)]
+    pub economy: bool,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// The folder to watch
+    pub dir: PathBuf,
+
+    /// Link each processed PDF into the vault instead of moving it there
+    #[arg(long)]
+    pub link: bool,
+
+    /// Process only PDFs that arrive from now on, not those already in the folder
+    #[arg(long)]
+    pub new_only: bool,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct FlushQueueArgs {
+    /// Only list the queued papers
+    #[arg(long)]
+    pub list: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    /// Words that must all appear in a note (case-insensitive)
+    pub query: String,
+
+    /// Maximum number of notes to list
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct UpdateArgs {
+    /// Metadata requests in flight at once
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Show each note's frontmatter changes and ask before writing them
+    #[arg(long)]
+    pub preview_diff: bool,
+
+    /// Write without asking after showing the diff
+    #[arg(long, requires = "preview_diff")]
+    pub yes: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RefreshCitationsArgs {
+    /// Lookups in flight at once
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Refresh citation counts only, without asking Altmetric
+    #[arg(long)]
+    pub no_altmetric: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct FreshnessArgs {
+    /// Citation counts older than this many months are due for a refresh
+    #[arg(long, default_value_t = 6)]
+    pub months: u32,
+
+    /// arXiv requests in flight at once
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Skip the check for newer arXiv versions, which needs the network
+    #[arg(long)]
+    pub no_arxiv: bool,
+
+    /// Print the list instead of writing the report note
+    #[arg(long)]
+    pub print: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AnnotateArgs {
+    /// The paper: arXiv id, PMID/PMCID, DOI, note title, or path to the note
+    pub target: String,
+
+    /// Text to add under the note's "My notes" section
+    #[arg(long, short)]
+    pub note: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ReadArgs {
+    /// The paper: arXiv id, PMID/PMCID, DOI, URL or PDF path; its note must be written already
+    pub input: String,
+
+    /// Start at the first section whose heading contains TEXT, not at the abstract
+    #[arg(long, value_name = "TEXT")]
+    pub from: Option<String>,
+
+    /// Leave the note as it is; the session is only shown
+    #[arg(long)]
+    pub no_save: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AdoptArgs {
+    /// The note, somewhere in the vault
+    pub note: PathBuf,
+
+    /// arXiv id of the paper, when the note's first arXiv link is not the paper it is about
+    #[arg(long, value_name = "ID")]
+    pub arxiv: Option<String>,
+
+    /// Show the changes to the note and ask before writing them
+    #[arg(long)]
+    pub preview_diff: bool,
+
+    /// Write without asking after showing the diff
+    #[arg(long, requires = "preview_diff")]
+    pub yes: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DigestArgs {
+    /// arXiv category to draw from, e.g. `cs.LG`; repeatable (default: `MABEL_DIGEST_CATEGORIES`)
+    #[arg(long = "category", value_name = "CAT")]
+    pub categories: Vec<String>,
+
+    /// Only papers submitted in the last this many days
+    #[arg(long, default_value_t = 7)]
+    pub days: u32,
+
+    /// Papers in the digest
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+
+    /// Print the ranking instead of writing a digest note
+    #[arg(long)]
+    pub print: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct FeedbackArgs {
+    /// arXiv id or URL of the paper
+    pub id: String,
+
+    /// Rank papers like this one higher
+    #[arg(long, conflicts_with = "less", required_unless_present = "less")]
+    pub more: bool,
+
+    /// Rank papers like this one lower
+    #[arg(long)]
+    pub less: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Show only the most recent N
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct InfoArgs {
+    /// arXiv id, DOI or PubMed id of the paper, or part of its title
+    pub id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CiteArgs {
+    /// Paper to cite, in any form `mabel note` accepts
+    pub input: String,
+
+    /// LaTeX project folder (its `.bib` file is found automatically) or a `.bib` file to append to
+    #[arg(long, value_name = "PATH")]
+    pub into: Option<PathBuf>,
+
+    /// Also copy the `\cite{}` command to the clipboard
+    #[arg(long)]
+    pub copy: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BibliographyArgs {
+    /// Tag of the papers to list, as their notes' frontmatter has it (`thesis-ch2` also takes
+    /// nested tags like `thesis-ch2/related-work`)
+    #[arg(long, value_name = "TAG")]
+    pub list: String,
+
+    /// Write the Markdown to FILE instead of printing it
+    #[arg(long, short, value_name = "FILE")]
+    pub out: Option<PathBuf>,
+
+    /// Also write it as a LaTeX `thebibliography` environment to FILE (it needs the url package)
+    #[arg(long, value_name = "FILE")]
+    pub latex: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct BriefArgs {
+    /// Papers to compare, by arXiv id, DOI, PubMed id or part of the title; each needs a note
+    #[arg(required = true, num_args = 2.., value_name = "ID")]
+    pub papers: Vec<String>,
+
+    /// Title of the brief, which names its note in `Briefs/`
+    #[arg(long)]
+    pub title: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ExperimentArgs {
+    /// Paper to process, in any form `mabel note` accepts (books are not supported)
+    pub input: String,
+
+    /// Temperatures to try, comma-separated (default: the configured one)
+    #[arg(long, value_delimiter = ',', value_name = "LIST")]
+    pub temps: Vec<f32>,
+
+    /// Models to try on the configured backend, comma-separated (default: the configured one)
+    #[arg(long, value_delimiter = ',', value_name = "LIST")]
+    pub models: Vec<String>,
+
+    /// Folder for the candidate notes (default: a new folder under `<cache>/experiments`)
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct EvalArgs {
+    /// Prompt templates (Tera) to compare, comma-separated; each renders the whole user message
+    #[arg(long, value_delimiter = ',', required = true, value_name = "LIST")]
+    pub prompts: Vec<PathBuf>,
+
+    /// Folder of papers to run every prompt over (JATS XML, or `.txt`/`.md` with the title first)
+    #[arg(long)]
+    pub corpus: PathBuf,
+
+    /// Rubric file, one `criterion: description` per line (default: built-in rubric)
+    #[arg(long)]
+    pub rubric: Option<PathBuf>,
+
+    /// Model used as the judge (default: the configured model)
+    #[arg(long)]
+    pub judge_model: Option<String>,
+
+    /// Folder for `results.csv` and `report.md` (default: a new folder under `<cache>/evals`)
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BenchAction {
+    /// Run every available extractor over the PDFs in a folder and compare coverage, speed and
+    /// the structure each finds
+    Extract(BenchExtractArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct BenchExtractArgs {
+    /// Folder of PDFs (not searched recursively)
+    pub dir: PathBuf,
+
+    /// Only the first this many PDFs, by file name
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct SelftestArgs {
+    /// Keep the scratch vaults the canned paper's notes are written to, and print where they are
+    #[arg(long)]
+    pub keep: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StateAction {
+    /// Pack the vault's `.mabel` folder, the cache and the personal config file (without its
+    /// secrets) into FILE, a zstd-compressed tar archive
+    Export {
+        file: PathBuf,
+        /// Include the cached PDFs, which are otherwise only listed
+        #[arg(long)]
+        pdfs: bool,
+    },
+    /// Unpack an archive from `state export` into this machine's vault, cache and config file
+    Import {
+        file: PathBuf,
+        /// Replace files that are here already; the registry gains the archive's papers either way
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheAction {
+    /// Print the cache directory
+    Path,
+    /// List cached downloads with their sizes
+    List,
+    /// Delete cached downloads
+    Clear {
+        /// Actually delete; without this only the files that would be removed are listed
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ClaimsAction {
+    /// Find stored claims matching some words, with the notes they come from
+    Search {
+        query: String,
+        /// Maximum number of claims to list
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Extract (or re-extract) the claims of one paper into the store
+    Extract {
+        /// Paper, in any form `mabel note` accepts (books are not supported)
+        input: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RefactorAction {
+    /// Rename a frontmatter tag; nested tags (`old/...`) move along with it
+    RenameTag {
+        old: String,
+        new: String,
+        /// Write the changes; without this only a diff is printed
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Point `[[old]]` wikilinks (with any alias or heading) at `new`
+    RenameLink {
+        old: String,
+        new: String,
+        /// Write the changes; without this only a diff is printed
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Print the configuration resolved from flags and environment, secrets redacted
+    Show,
+    /// Print one setting: the value in effect, or with a target flag the value stored in that file
+    Get {
+        /// File key (`openai_model`) or the name `config show` prints (`llm.model`)
+        key: String,
+        #[command(flatten)]
+        target: ConfigTarget,
+    },
+    /// Store a setting in a config file after checking the value is valid for it
+    Set {
+        /// File key (`openai_model`) or the name `config show` prints (`llm.model`)
+        key: String,
+        value: String,
+        #[command(flatten)]
+        target: ConfigTarget,
+    },
+    /// Remove a setting from a config file
+    Unset {
+        key: String,
+        #[command(flatten)]
+        target: ConfigTarget,
+    },
+}
+
+/// Which config file `config get/set/unset` works on; the personal file when none is given.
+#[derive(Debug, Args)]
+#[group(multiple = false)]
+pub struct ConfigTarget {
+    /// The personal config file (`MABEL_CONFIG` or `~/.config/mabel/config.toml`)
+    #[arg(long)]
+    pub global: bool,
+
+    /// The team file at the vault root, `mabel.toml`
+    #[arg(long)]
+    pub team: bool,
+
+    /// A `[profile.NAME]` table in the personal file, applied with `--profile NAME`
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+impl ConfigTarget {
+    pub fn is_given(&self) -> bool {
+        self.global || self.team || self.profile.is_some()
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TemplateAction {
+    /// Print the built-in note template, as a starting point for `--template`
+    Show {
+        /// Print the book (EPUB) template instead of the paper template
+        #[arg(long, conflicts_with_all = ["moc", "concept", "thread"])]
+        book: bool,
+        /// Print the template new MOCs are made from (`MABEL_MOCS`)
+        #[arg(long, conflicts_with_all = ["concept", "thread"])]
+        moc: bool,
+        /// Print the template concept notes are made from (`--define-new-terms`)
+        #[arg(long, conflicts_with = "thread")]
+        concept: bool,
+        /// Print the template threads on papers are made from (`--thread`)
+        #[arg(long)]
+        thread: bool,
+    },
+    /// Check that a template parses (defaults to the configured one)
+    Check { path: Option<PathBuf> },
+    /// Write the built-in summary prompt templates into a folder, as a starting point for
+    /// `MABEL_PROMPT_DIR`; files already there are left alone
+    Prompts { dir: PathBuf },
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8787")]
+    pub bind: SocketAddr,
+
+    /// Papers processed at once; the rest wait their turn
+    #[arg(long, default_value_t = 2)]
+    pub concurrency: usize,
+}
+
+/// Long-running modes that can be installed as a service.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ServiceMode {
+    /// `mabel serve`
+    Serve,
+    /// `mabel watch` on the folder given with `--dir`
+    Watch,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ServiceAction {
+    /// Write the service definition for a mode, then enable and start it
+    Install {
+        /// What the service runs (a positional argument, since `--mode` is the note style)
+        #[arg(value_enum, value_name = "MODE")]
+        kind: ServiceMode,
+
+        /// Address for `serve` to listen on
+        #[arg(long, default_value = "127.0.0.1:8787")]
+        bind: SocketAddr,
+
+        /// The folder for `watch` to watch
+        #[arg(long, required_if_eq("kind", "watch"))]
+        dir: Option<PathBuf>,
+
+        /// Only print the service definition
+        #[arg(long)]
+        print: bool,
+    },
+    /// Stop a service and remove its definition
+    Uninstall {
+        #[arg(value_enum, value_name = "MODE")]
+        kind: ServiceMode,
+    },
+}
+
+impl Command {
+    /// Note-writing options, for commands that write notes.
+    pub fn output(&self) -> Option<&OutputArgs> {
+        match self {
+            | Self::Note(args) => Some(&args.output),
+            | Self::Batch(args) => Some(&args.output),
+            | Self::Watch(args) => Some(&args.output),
+            | _ => None,
+        }
+    }
+
+    /// Whether the command talks to the LLM (and therefore needs credentials).
+    pub fn needs_llm(&self) -> bool {
+        match self {
+            | Self::Note(args) => !args.json,
+            | Self::Batch(_)
+            | Self::Watch(_)
+            | Self::Read(_)
+            | Self::Digest(_)
+            | Self::Brief(_)
+            | Self::Serve(_)
+            | Self::Experiment(_)
+            | Self::Eval(_) => true,
+            | Self::FlushQueue(args) => !args.list,
+            | Self::Claims { action } => matches!(action, ClaimsAction::Extract { .. }),
+            | _ => false,
+        }
+    }
+
+    /// Whether the command reads or writes the vault (and therefore needs a vault path).
+    pub fn needs_vault(&self) -> bool {
+        match self {
+            | Self::Note(args) => !args.json,
+            | Self::Batch(_)
+            | Self::Watch(_)
+            | Self::FlushQueue(_)
+            | Self::Search(_)
+            | Self::Update(_)
+            | Self::RefreshCitations(_)
+            | Self::Freshness(_)
+            | Self::Annotate(_)
+            | Self::Read(_)
+            | Self::Adopt(_)
+            | Self::Digest(_)
+            | Self::Feedback(_)
+            | Self::Bibliography(_)
+            | Self::Brief(_)
+            | Self::Claims { .. }
+            | Self::Refactor { .. }
+            | Self::State { .. }
+            | Self::Serve(_) => true,
+            // The service runs against the vault configured now.
+            | Self::Service { action } => matches!(action, ServiceAction::Install { .. }),
+            // The index is in the cache directory, not the vault.
+            | Self::List(_)
+            | Self::Info(_)
+            | Self::Cite(_)
+            | Self::Cache { .. }
+            | Self::Config { .. }
+            | Self::Template { .. }
+            | Self::Experiment(_)
+            | Self::Eval(_)
+            | Self::Bench { .. }
+            | Self::Selftest(_)
+            | Self::Doctor
+            | Self::Plugins
+            | Self::External(_) => false,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Time zone handling for everything user-facing: note filenames, frontmatter timestamps, and the
+//! day boundaries of daily digest/inbox notes.
+//!
+//! Internally we keep `DateTime<Utc>`; conversion to the configured zone happens only at the edges.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::MabelError;
+
+/// The zone configured via `MABEL_TZ`: an IANA name such as `Europe/Berlin`, `UTC`, or `local`
+/// for the system zone (the default).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Zone {
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    /// Current time in this zone.
+    pub fn now(self) -> DateTime<FixedOffset> {
+        self.from_utc(Utc::now())
+    }
+
+    /// Today's date in this zone.
+    pub fn today(self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    /// Express a UTC instant in this zone.
+    pub fn from_utc(self, t: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            | Self::Local => t.with_timezone(&Local).fixed_offset(),
+            | Self::Named(tz) => t.with_timezone(&tz).fixed_offset(),
+        }
+    }
+
+    /// The calendar date of a UTC instant in this zone.
+    pub fn date_of(self, t: DateTime<Utc>) -> NaiveDate {
+        self.from_utc(t).date_naive()
+    }
+
+    /// Half-open UTC interval `[start, end)` covering `day` in this zone.
+    ///
+    /// Days are not always 24 hours long (DST), so day windows such as the digest's are built from
+    /// this instead of adding a fixed duration to midnight.
+    pub fn day_bounds(self, day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let next = day.succ_opt().unwrap_or(day);
+        (self.start_of_day(day), self.start_of_day(next))
+    }
+
+    fn start_of_day(self, day: NaiveDate) -> DateTime<Utc> {
+        match self {
+            | Self::Local => start_of_day_in(&Local, day),
+            | Self::Named(tz) => start_of_day_in(&tz, day),
+        }
+    }
+
+    /// `YYYY-MM-DD` in this zone, for filenames and date-only frontmatter fields.
+    pub fn date_stamp(self, t: DateTime<Utc>) -> String {
+        self.date_of(t).format("%Y-%m-%d").to_string()
+    }
+
+    /// RFC 3339 timestamp with this zone's offset, for frontmatter `created`/`updated` fields.
+    pub fn timestamp(self, t: DateTime<Utc>) -> String {
+        self.from_utc(t).to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+    }
+}
+
+/// Midnight of `day` in `tz`, or the first instant of that day when midnight is skipped by a DST
+/// transition (e.g. `America/Santiago`).
+fn start_of_day_in<Z: TimeZone>(tz: &Z, day: NaiveDate) -> DateTime<Utc> {
+    let mut time = day.and_time(NaiveTime::MIN);
+    for _ in 0..4 {
+        if let Some(t) = tz.from_local_datetime(&time).earliest() {
+            return t.with_timezone(&Utc);
+        }
+        time += Duration::minutes(30);
+    }
+    // Unreachable for real zones; treat the day as starting at UTC midnight.
+    Utc.from_utc_datetime(&day.and_time(NaiveTime::MIN))
+}
+
+impl FromStr for Zone {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() || s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Self::Named(Tz::UTC));
+        }
+        s.parse::<Tz>().map(Self::Named).map_err(|_| {
+            MabelError::Config {
+                msg: format!(
+                    "unknown time zone {s:?} (expected an IANA name like \"Europe/Berlin\", \"UTC\" or \"local\")"
+                ),
+            }
+        })
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            | Self::Local => f.write_str("local"),
+            | Self::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! `mabel adopt <note.md>`: take over a paper note written by hand. The note's arXiv link says
+//! which paper it is about; mabel looks the paper up, adds the frontmatter fields and the (empty)
+//! managed regions its template would produce, and records the note in the registry.
+//!
+//! Nothing the note already has is changed. From then on `mabel update` keeps its metadata current
+//! and `mabel note <id> --overwrite` fills in the regions, leaving the hand-written text around them
+//! alone.
+
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::{
+    bibtex,
+    cli::AdoptArgs,
+    config::Config,
+    http,
+    note::{self, Overwrite},
+    region::RegionMarkers,
+    registry::{Entry, Registry},
+    render::{self, FrontmatterFields, PaperNote},
+    source::arxiv::{ArxivId, ArxivResolver},
+    summarize::Summary,
+    vault::{self, split_frontmatter},
+    MabelError, Result,
+};
+
+pub async fn run(cfg: &Config, args: &AdoptArgs) -> Result<()> {
+    let path = &args.note;
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.clone(),
+            source,
+        }
+    };
+    let text = tokio::fs::read_to_string(path).await.map_err(io_err)?;
+    let relative = vault_relative(cfg, path)?;
+    let id = match &args.arxiv {
+        | Some(id) => ArxivId::parse(id)?,
+        | None => {
+            arxiv_link(&text, &cfg.frontmatter).ok_or_else(|| {
+                MabelError::Config {
+                    msg: format!("no arXiv link in {}; name the paper with --arxiv", path.display()),
+                }
+            })?
+        }
+    };
+    let paper = ArxivResolver::new(http::client(cfg)?, cfg).resolve(&id).await?;
+    let metadata = &paper.metadata;
+
+    let registry = Registry::load(&cfg.registry_path())?;
+    if let Some(entry) = registry.find(metadata) {
+        if entry.note != relative && cfg.vault_path.join(&entry.note).is_file() {
+            return Err(MabelError::Config {
+                msg: format!("arXiv:{} already has a note: {}", id.base(), entry.note.display()),
+            });
+        }
+    }
+
+    let created = cfg.timezone.timestamp(chrono::Utc::now());
+    let rendered = render::from_config(cfg)?.render_paper(&PaperNote {
+        metadata,
+        summary: &Summary::default(),
+        figures: &[],
+        related: &[],
+        sections: &[],
+        low_confidence: Vec::new(),
+        bibtex: &bibtex::for_note(cfg, metadata),
+        moc: None,
+        source: id.as_str(),
+        created: created.clone(),
+        model: "",
+        mode: cfg.mode.as_str(),
+    })?;
+    let (adopted, fields) = add_frontmatter(path, &text, &rendered)?;
+    let (adopted, regions) = add_regions(&adopted, &rendered, &cfg.region_markers);
+    if adopted != text {
+        if args.preview_diff
+            && !note::confirm_write(
+                path,
+                &adopted,
+                Overwrite::Force,
+                &cfg.region_markers,
+                args.yes,
+                cfg.can_ask(),
+            )
+            .await?
+        {
+            return Err(MabelError::NoteDeclined { path: path.clone() });
+        }
+        tokio::fs::write(path, &adopted).await.map_err(io_err)?;
+    }
+    let entry = Entry::new(metadata, relative.clone(), id.as_str(), "", created);
+    Registry::update(&cfg.registry_path(), |r| r.record(entry))?;
+    bibtex::add_to_vault_bib(cfg, metadata).await;
+    vault::register_property_types(&cfg.vault_path, &cfg.frontmatter);
+
+    println!("adopted {} as arXiv:{} ({})", relative.display(), id, metadata.title);
+    if !fields.is_empty() {
+        println!("  frontmatter: {}", fields.join(", "));
+    }
+    if !regions.is_empty() {
+        println!("  managed regions: {}", regions.join(", "));
+    }
+    Ok(())
+}
+
+/// The note's path inside the vault, as the registry keeps it.
+fn vault_relative(cfg: &Config, path: &Path) -> Result<PathBuf> {
+    let canonical = |p: &Path| {
+        std::fs::canonicalize(p).map_err(|source| {
+            MabelError::Io {
+                path: p.to_path_buf(),
+                source,
+            }
+        })
+    };
+    let (note, vault) = (canonical(path)?, canonical(&cfg.vault_path)?);
+    note.strip_prefix(&vault).map(Path::to_path_buf).map_err(|_| {
+        MabelError::Config {
+            msg: format!("{} is not in the vault at {}", path.display(), cfg.vault_path.display()),
+        }
+    })
+}
+
+/// The paper a note is about: its `arxiv` frontmatter field, else the first arXiv link or
+/// `arXiv:` id in the text.
+fn arxiv_link(text: &str, fields: &FrontmatterFields) -> Option<ArxivId> {
+    let frontmatter = split_frontmatter(text)
+        .0
+        .and_then(|yaml| vault::frontmatter(yaml, fields))
+        .unwrap_or_default();
+    if let Some(id) = frontmatter.arxiv.as_deref().and_then(|a| ArxivId::parse(a).ok()) {
+        return Some(id);
+    }
+    // Words as Markdown links and autolinks split them, e.g. `[paper](https://arxiv.org/abs/…)`.
+    text.split(|c: char| c.is_whitespace() || "()<>[]{}\"'`|".contains(c))
+        .map(|word| word.trim_end_matches(['.', ',', ';', ':']))
+        .find_map(|word| {
+            if let Some(at) = word.find("arxiv.org/") {
+                ArxivId::parse(&format!("https://{}", &word[at..])).ok()
+            } else if word.to_ascii_lowercase().starts_with("arxiv:") {
+                ArxivId::parse(word).ok()
+            } else {
+                None
+            }
+        })
+}
+
+/// Add the frontmatter fields of `rendered` that `text` lacks, after the ones it has; a note
+/// without frontmatter gets all of them. Fields the template leaves empty are skipped. Returns the
+/// new text and the names of the added fields.
+fn add_frontmatter(path: &Path, text: &str, rendered: &str) -> Result<(String, Vec<String>)> {
+    // Hand-written frontmatter is read leniently; what it has is left as it was written.
+    let existing = match split_frontmatter(text).0 {
+        | Some(yaml) => {
+            let (map, repaired) = mabel_core::note::read_frontmatter(yaml).ok_or_else(|| {
+                MabelError::Config {
+                    msg: format!("{}: the frontmatter is not a YAML mapping", path.display()),
+                }
+            })?;
+            vault::warn_repaired(path, &repaired);
+            map
+        }
+        | None => Mapping::new(),
+    };
+    let missing: Vec<(&str, String)> = entries(split_frontmatter(rendered).0.unwrap_or_default())
+        .into_iter()
+        .filter(|(key, block)| !existing.contains_key(*key) && !is_empty(block))
+        .collect();
+    let names = missing.iter().map(|(key, _)| (*key).to_string()).collect();
+    let added: String = missing.into_iter().map(|(_, block)| block).collect();
+    let adopted = match split_frontmatter(text).0 {
+        | _ if added.is_empty() => text.to_string(),
+        | Some(yaml) => {
+            let end = yaml.as_ptr() as usize - text.as_ptr() as usize + yaml.len();
+            format!("{}{added}{}", &text[..end], &text[end..])
+        }
+        | None => format!("---\n{added}---\n{text}"),
+    };
+    Ok((adopted, names))
+}
+
+/// Top-level `key: value` entries of frontmatter, each with its continuation lines (block lists,
+/// multi-line strings).
+fn entries(yaml: &str) -> Vec<(&str, String)> {
+    let mut out: Vec<(&str, String)> = Vec::new();
+    for line in yaml.split_inclusive('\n') {
+        let top_level = !line.starts_with([' ', '\t', '-', '#']);
+        match line.split_once(':') {
+            | Some((key, _)) if top_level && !key.trim().is_empty() => out.push((key.trim(), line.to_string())),
+            | _ => {
+                if let Some((_, block)) = out.last_mut() {
+                    block.push_str(line);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Whether an entry's value is null, an empty string or an empty list.
+fn is_empty(block: &str) -> bool {
+    match serde_yaml::from_str::<Value>(block) {
+        | Ok(Value::Mapping(map)) => {
+            map.values().all(|v| {
+                match v {
+                    | Value::Null => true,
+                    | Value::String(s) => s.trim().is_empty(),
+                    | Value::Sequence(items) => items.is_empty(),
+                    | _ => false,
+                }
+            })
+        }
+        | _ => false,
+    }
+}
+
+/// Append an empty managed region for each region of `rendered` that `text` lacks. Returns the new
+/// text and the names of the added regions.
+fn add_regions(text: &str, rendered: &str, markers: &RegionMarkers) -> (String, Vec<String>) {
+    let present = markers.names(text);
+    let missing: Vec<String> = markers
+        .names(rendered)
+        .into_iter()
+        .filter(|name| !present.contains(name))
+        .collect();
+    let mut out = text.to_string();
+    for name in &missing {
+        if !out.ends_with("\n\n") {
+            out.push_str(if out.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        out.push_str(&markers.begin(name));
+        out.push('\n');
+        out.push_str(&markers.end(name));
+        out.push('\n');
+    }
+    (out, missing)
+}
@@ -0,0 +1,76 @@
+//! `mabel annotate <id> --note "..."`: add your own thoughts to a paper's note without opening
+//! Obsidian. Comments go under a "My notes" heading outside the managed regions, so regenerating
+//! the note keeps them.
+
+use std::path::{Path, PathBuf};
+
+use crate::{cli::AnnotateArgs, config::Config, note, region::RegionMarkers, vault, MabelError, Result};
+
+const HEADING: &str = "## My notes";
+
+pub async fn run(cfg: &Config, args: &AnnotateArgs) -> Result<()> {
+    let comment = args.note.trim();
+    if comment.is_empty() {
+        return Err(MabelError::Config {
+            msg: "--note is empty".to_string(),
+        });
+    }
+    let path = locate(cfg, &args.target).await?;
+    let existing = tokio::fs::read_to_string(&path).await.map_err(|source| {
+        MabelError::Io {
+            path: path.clone(),
+            source,
+        }
+    })?;
+    let stamp = cfg.timezone.now().format("%Y-%m-%d %H:%M").to_string();
+    let updated = append_comment(&existing, &stamp, comment, &cfg.region_markers);
+    note::write(&path, &updated, true).await?;
+    println!("{}", path.strip_prefix(&cfg.vault_path).unwrap_or(&path).display());
+    Ok(())
+}
+
+/// A path to an existing note is used as is; anything else is looked up in the notes folder.
+async fn locate(cfg: &Config, target: &str) -> Result<PathBuf> {
+    let path = Path::new(target.trim());
+    if path.is_file() && path.extension().is_some_and(|x| x == "md") {
+        return Ok(path.to_path_buf());
+    }
+    let (dir, fields) = (cfg.vault_notes_dir(), cfg.frontmatter.clone());
+    let notes = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+        .await
+        .unwrap_or_default();
+    vault::find(&notes, target).map(|n| n.path.clone()).ok_or_else(|| {
+        MabelError::Config {
+            msg: format!("no note for {target:?} in {}", cfg.vault_notes_dir().display()),
+        }
+    })
+}
+
+/// Add a `### <stamp>` entry at the end of the "My notes" section, creating the section at the end
+/// of the note if it does not exist yet. The section ends at the next heading of the same or
+/// higher level, or at the next managed region.
+fn append_comment(note: &str, stamp: &str, comment: &str, markers: &RegionMarkers) -> String {
+    let entry = format!("### {stamp}\n\n{comment}\n");
+    let mut offset = 0;
+    let mut in_section = false;
+    for line in note.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if !in_section {
+            in_section = trimmed == HEADING;
+        } else if is_section_end(trimmed, markers) {
+            let (before, after) = note.split_at(offset);
+            return format!("{}\n\n{entry}\n{after}", before.trim_end());
+        }
+        offset += line.len();
+    }
+    let body = note.trim_end();
+    if in_section {
+        format!("{body}\n\n{entry}")
+    } else {
+        format!("{body}\n\n{HEADING}\n\n{entry}")
+    }
+}
+
+fn is_section_end(line: &str, markers: &RegionMarkers) -> bool {
+    line.starts_with("# ") || line.starts_with("## ") || markers.is_begin(line)
+}
@@ -0,0 +1,357 @@
+//! `mabel batch <input>... [--file FILE]...`: process many papers in one run, a few at a time. A
+//! paper that fails does not end the batch; how each one went is listed once all are done.
+//!
+//! The papers run side by side, `--concurrency` of them at once, with their downloads, extractions
+//! and model requests each kept to a limit of their own (see [`crate::limits`]).
+//!
+//! Papers given on the command line are taken first; the lists in `--file`s then take turns (see
+//! [`crate::schedule`]), so a short list is not left until a long one is done.
+//!
+//! Under `--ci` a paper also fails on the warnings it logs (see [`crate::ci`]); `--junit` writes
+//! how each paper went as a `JUnit` report.
+//!
+//! `--economy` sends the summary prompts through the OpenAI Batch API (see [`crate::llm::batch`]):
+//! the papers are run until their prompts are queued, the queue goes out as a batch, and once it is
+//! done they are run again with its replies. A paper in parts takes two batches, its parts' and
+//! then its summary's.
+
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinSet;
+
+use crate::{
+    ci::{self, Case, Logged, Severity, Verdict},
+    cli::{BatchArgs, OutputFormat},
+    config::Config,
+    cost,
+    llm::{batch::BatchQueue, Usage},
+    output::{self, is_skip, Record, Status},
+    pipeline::{NoteOutcome, Pipeline},
+    schedule::{Priority, Scheduler},
+    MabelError, Result,
+};
+
+/// A paper to process, with the list it came from.
+#[derive(Clone)]
+struct Paper {
+    input: String,
+    source: String,
+    priority: Priority,
+}
+
+/// How a paper's task ended.
+struct Done {
+    result: Result<NoteOutcome>,
+    logged: Vec<Logged>,
+    time: Duration,
+}
+
+/// Most rounds of an `--economy` run; each but the last may wait for a batch.
+const ECONOMY_ROUNDS: usize = 3;
+
+/// The papers by how they went, and what they cost.
+#[derive(Default)]
+struct Tally {
+    written: usize,
+    skipped: usize,
+    failed: usize,
+    usage: Usage,
+    spent: Option<f64>,
+}
+
+pub async fn run(mut cfg: Config, args: &BatchArgs) -> Result<()> {
+    let inputs = inputs(args).await?;
+    let format = args.output.format;
+    if inputs.is_empty() {
+        output::say(format, "no papers to process");
+        return Ok(());
+    }
+    // A diff is shown and asked about one note at a time.
+    let concurrency = if cfg.preview_diff { 1 } else { args.concurrency.max(1) };
+    // Questions from papers side by side would print over each other.
+    cfg.non_interactive |= concurrency > 1;
+    let fail_on = cfg.ci.then_some(cfg.fail_on);
+    let started = cfg.timezone.timestamp(chrono::Utc::now());
+    let total = inputs.len();
+    let results = if args.economy {
+        // A skeleton written while the summary waits for a batch would stand in the note's way.
+        cfg.tiered = false;
+        let queue = Arc::new(BatchQueue::load(&cfg).await?);
+        let pipeline = Arc::new(Pipeline::new(cfg)?.with_batch(queue.clone())?);
+        economy(&pipeline, &queue, &inputs, concurrency, format).await?
+    } else {
+        let pipeline = Arc::new(Pipeline::new(cfg)?);
+        let all: Vec<usize> = (0..total).collect();
+        let mut results: Vec<Option<Done>> = inputs.iter().map(|_| None).collect();
+        for (i, paper) in process(&pipeline, &inputs, &all, concurrency).await {
+            results[i] = Some(paper);
+        }
+        results
+    };
+
+    output::say(format, "");
+    let (tally, cases) = summarize(&inputs, results, fail_on, format);
+    let cost = tally
+        .spent
+        .map(|c| format!(", about {}", cost::format(c)))
+        .unwrap_or_default();
+    output::say(
+        format,
+        format_args!(
+            "{} written, {} skipped, {} failed ({} tokens{cost})",
+            tally.written,
+            tally.skipped,
+            tally.failed,
+            tally.usage.total()
+        ),
+    );
+    if let Some(path) = &args.junit {
+        ci::write_junit(path, "mabel batch", &cases, &started)?;
+    }
+    if tally.failed > 0 {
+        return Err(MabelError::BatchFailed {
+            failed: tally.failed,
+            total,
+        });
+    }
+    Ok(())
+}
+
+/// Run the papers `which` of `inputs`, `concurrency` at a time; how each went, by its index. A
+/// task that panicked is left out.
+async fn process(
+    pipeline: &Arc<Pipeline>,
+    inputs: &[Paper],
+    which: &[usize],
+    concurrency: usize,
+) -> Vec<(usize, Done)> {
+    let scheduler = Scheduler::new(concurrency);
+    let mut tasks = JoinSet::new();
+    for &i in which {
+        let (pipeline, scheduler, paper) = (pipeline.clone(), scheduler.clone(), inputs[i].clone());
+        tasks.spawn(async move {
+            let _slot = scheduler.slot(&paper.source, paper.priority).await;
+            let start = Instant::now();
+            let (result, logged) = ci::collect(Box::pin(pipeline.run(&paper.input))).await;
+            let time = start.elapsed();
+            (i, Done { result, logged, time })
+        });
+    }
+
+    let total = which.len();
+    let mut results = Vec::with_capacity(total);
+    let mut done = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((i, paper)) = joined else {
+            continue;
+        };
+        done += 1;
+        match &paper.result {
+            | Ok(outcome) => tracing::info!(done, total, path = %outcome.path.display(), "note written"),
+            | Err(e) if is_pending(e) => tracing::info!(done, total, input = inputs[i].input, "waiting for a batch"),
+            | Err(e) => tracing::warn!(done, total, input = inputs[i].input, error = %e.root(), "paper failed"),
+        }
+        results.push((i, paper));
+    }
+    results
+}
+
+/// `--economy`: run the papers, submit the prompts they queued as a batch and wait for it, and run
+/// those that waited again, until none waits. Batches an earlier run left are waited for first.
+async fn economy(
+    pipeline: &Arc<Pipeline>,
+    queue: &BatchQueue,
+    inputs: &[Paper],
+    concurrency: usize,
+    format: OutputFormat,
+) -> Result<Vec<Option<Done>>> {
+    let llm = pipeline.llm();
+    let mut results: Vec<Option<Done>> = inputs.iter().map(|_| None).collect();
+    let mut waiting: Vec<usize> = (0..inputs.len()).collect();
+    for round in 1..=ECONOMY_ROUNDS {
+        for batch in queue.in_flight() {
+            output::say(
+                format,
+                format_args!("waiting for OpenAI batch {batch}; stop with Ctrl-C and run this again to pick it up"),
+            );
+            llm.wait_batch(&batch).await?;
+        }
+        let mut still = Vec::new();
+        for (i, done) in process(pipeline, inputs, &waiting, concurrency).await {
+            if done.result.as_ref().is_err_and(is_pending) {
+                still.push(i);
+            }
+            results[i] = Some(done);
+        }
+        waiting = still;
+        if waiting.is_empty() || round == ECONOMY_ROUNDS {
+            break;
+        }
+        if let Some(batch) = llm.submit_batch().await? {
+            output::say(
+                format,
+                format_args!("submitted OpenAI batch {batch} for {} papers", waiting.len()),
+            );
+        }
+    }
+    queue.clear_answered().await?;
+    Ok(results)
+}
+
+fn is_pending(e: &MabelError) -> bool {
+    matches!(e.root(), MabelError::BatchPending)
+}
+
+/// List how each paper went, failing those that logged at `fail_on` or above (under `--ci`): a line
+/// for each, or a JSON record with `--output json`.
+fn summarize(
+    inputs: &[Paper],
+    results: Vec<Option<Done>>,
+    fail_on: Option<Severity>,
+    format: OutputFormat,
+) -> (Tally, Vec<Case>) {
+    let show = |line: String, record: Record| {
+        match format {
+            | OutputFormat::Text => println!("{line}"),
+            | OutputFormat::Json => record.print(),
+        }
+    };
+    let mut tally = Tally::default();
+    let mut cases = Vec::with_capacity(inputs.len());
+    for (paper, done) in inputs.iter().zip(results) {
+        let input = &paper.input;
+        let Some(Done { result, logged, time }) = done else {
+            tally.failed += 1;
+            show(
+                format!("failed   {input}: the task panicked"),
+                Record::failed(input, Status::Failed, "the task panicked", &[]),
+            );
+            cases.push(case(
+                paper,
+                Duration::ZERO,
+                Verdict::Failed("the task panicked".to_string()),
+                Vec::new(),
+            ));
+            continue;
+        };
+        if let Ok(outcome) = &result {
+            tally.usage += outcome.usage;
+            if let Some(cost) = outcome.cost {
+                *tally.spent.get_or_insert(0.0) += cost;
+            }
+        }
+        let failed_on = fail_on.and_then(|severity| severity.check(&logged).err());
+        let verdict = match (result, failed_on) {
+            | (Ok(outcome), None) => {
+                tally.written += 1;
+                show(
+                    format!("ok       {}", outcome.path.display()),
+                    Record::written(input, &outcome, &logged),
+                );
+                Verdict::Passed(outcome.path.display().to_string())
+            }
+            | (Ok(outcome), Some(e)) => {
+                tally.failed += 1;
+                let why = format!("{} was written, but {e}", outcome.path.display());
+                let record = Record {
+                    status: Status::Failed,
+                    error: Some(e.to_string()),
+                    ..Record::written(input, &outcome, &logged)
+                };
+                show(format!("failed   {input}: {why}"), record);
+                Verdict::Failed(why)
+            }
+            // The user said no to these, or would have been asked.
+            | (Err(e), None) if is_skip(&e) => {
+                tally.skipped += 1;
+                show(
+                    format!("skipped  {}", describe(input, &e)),
+                    Record::failed(input, Status::Skipped, &e, &logged),
+                );
+                Verdict::Skipped(e.to_string())
+            }
+            | (Err(e), _) => {
+                tally.failed += 1;
+                show(
+                    format!("failed   {}", describe(input, &e)),
+                    Record::failed(input, Status::Failed, &e, &logged),
+                );
+                Verdict::Failed(e.to_string())
+            }
+        };
+        cases.push(case(paper, time, verdict, logged));
+    }
+    (tally, cases)
+}
+
+fn case(paper: &Paper, time: Duration, verdict: Verdict, logged: Vec<Logged>) -> Case {
+    Case {
+        name: paper.input.clone(),
+        source: paper.source.clone(),
+        time,
+        verdict,
+        logged,
+    }
+}
+
+/// The papers to process: those on the command line, then those in each `--file`, each once.
+async fn inputs(args: &BatchArgs) -> Result<Vec<Paper>> {
+    let mut inputs: Vec<Paper> = args
+        .inputs
+        .iter()
+        .map(|input| {
+            Paper {
+                input: input.clone(),
+                source: "command line".to_string(),
+                priority: Priority::High,
+            }
+        })
+        .collect();
+    for path in &args.file {
+        let text = read_list(path).await?;
+        let source = path.display().to_string();
+        inputs.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|input| {
+                    Paper {
+                        input: input.to_string(),
+                        source: source.clone(),
+                        priority: Priority::Normal,
+                    }
+                }),
+        );
+    }
+    let mut seen = std::collections::HashSet::new();
+    inputs.retain(|paper| seen.insert(paper.input.clone()));
+    Ok(inputs)
+}
+
+async fn read_list(path: &Path) -> Result<String> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    if path == Path::new("-") {
+        return tokio::task::spawn_blocking(|| std::io::read_to_string(std::io::stdin()))
+            .await
+            .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+            .map_err(io_err);
+    }
+    tokio::fs::read_to_string(path).await.map_err(io_err)
+}
+
+/// The error with the paper it is about; pipeline errors name it already.
+fn describe(input: &str, e: &MabelError) -> String {
+    match e {
+        | MabelError::Staged { .. } => e.to_string(),
+        | _ => format!("{input}: {e}"),
+    }
+}
@@ -0,0 +1,255 @@
+//! `mabel bench extract <pdf-dir>`: run every PDF extractor this build and machine have over a
+//! folder of PDFs, one PDF at a time so the timings are comparable, and print how each did: how
+//! many PDFs it read, how fast, and how much of the papers' structure it found. What it is for is
+//! deciding whether a GROBID server is worth running for a corpus, against mabel's built-in
+//! extractor and the plain text layer `pdftotext` gives.
+//!
+//! Sections in plain text are counted by their headings (`3 Results`, `IV. METHODS`,
+//! `Related Work`), so they are an estimate; `pdftotext` finds no references.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    cli::{BenchAction, BenchExtractArgs},
+    config::Config,
+    extract::is_heading,
+    paper::PaperStructure,
+    skim, MabelError, Result,
+};
+
+#[derive(Clone, Copy)]
+enum Extractor {
+    Grobid,
+    /// mabel's own, [`crate::extract::pdftext`]
+    Builtin,
+    Pdftotext,
+}
+
+/// What an extractor got out of one PDF.
+struct Reading {
+    words: usize,
+    has_abstract: bool,
+    sections: usize,
+    /// `None` where the extractor does not parse references
+    references: Option<usize>,
+}
+
+/// How an extractor did over the corpus.
+struct Tally {
+    name: &'static str,
+    failed: usize,
+    secs: Vec<f64>,
+    readings: Vec<Reading>,
+}
+
+pub async fn run(cfg: &Config, action: &BenchAction) -> Result<()> {
+    match action {
+        | BenchAction::Extract(args) => extract(cfg, args).await,
+    }
+}
+
+async fn extract(cfg: &Config, args: &BenchExtractArgs) -> Result<()> {
+    let mut pdfs = pdfs(&args.dir)?;
+    if let Some(limit) = args.limit {
+        pdfs.truncate(limit);
+    }
+    if pdfs.is_empty() {
+        println!("no PDFs in {}", args.dir.display());
+        return Ok(());
+    }
+
+    let mut tallies = Vec::new();
+    for extractor in [Extractor::Grobid, Extractor::Builtin, Extractor::Pdftotext] {
+        if let Err(reason) = available(cfg, extractor).await {
+            println!("{:<10} skipped: {reason}", extractor.name());
+            continue;
+        }
+        let mut tally = Tally {
+            name: extractor.name(),
+            failed: 0,
+            secs: Vec::new(),
+            readings: Vec::new(),
+        };
+        for (i, pdf) in pdfs.iter().enumerate() {
+            tracing::info!(extractor = tally.name, done = i, total = pdfs.len(), pdf = %pdf.display(), "extracting");
+            let started = Instant::now();
+            match extractor.read(cfg, pdf).await {
+                | Ok(reading) => {
+                    tally.secs.push(started.elapsed().as_secs_f64());
+                    tally.readings.push(reading);
+                }
+                | Err(e) => {
+                    tracing::warn!(extractor = tally.name, pdf = %pdf.display(), error = %e, "extraction failed");
+                    tally.failed += 1;
+                }
+            }
+        }
+        tallies.push(tally);
+    }
+    if tallies.is_empty() {
+        return Err(MabelError::Extraction {
+            reason: "no extractor is available".to_string(),
+        });
+    }
+    print!("{}", report(pdfs.len(), &tallies));
+    Ok(())
+}
+
+/// The PDFs directly in `dir`, by file name.
+fn pdfs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir).map_err(|source| {
+        MabelError::Io {
+            path: dir.to_path_buf(),
+            source,
+        }
+    })?;
+    let mut pdfs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf")))
+        .collect();
+    pdfs.sort();
+    Ok(pdfs)
+}
+
+/// Whether `extractor` can run here; if not, why.
+async fn available(cfg: &Config, extractor: Extractor) -> std::result::Result<(), String> {
+    match extractor {
+        | Extractor::Grobid if cfg!(feature = "grobid") && cfg.grobid_url.is_some() => Ok(()),
+        | Extractor::Grobid if cfg!(feature = "grobid") => Err("GROBID_URL is not set".to_string()),
+        | Extractor::Grobid => Err("this build has no GROBID support (`--features grobid`)".to_string()),
+        | Extractor::Builtin if cfg!(feature = "pdf") => Ok(()),
+        | Extractor::Builtin => Err("this build has no built-in extractor (`--features pdf`)".to_string()),
+        | Extractor::Pdftotext => {
+            tokio::process::Command::new("pdftotext")
+                .arg("-v")
+                .output()
+                .await
+                .map(|_| ())
+                .map_err(|_| "`pdftotext` from poppler-utils is not on PATH".to_string())
+        }
+    }
+}
+
+impl Extractor {
+    fn name(self) -> &'static str {
+        match self {
+            | Self::Grobid => "grobid",
+            | Self::Builtin => "mabel",
+            | Self::Pdftotext => "pdftotext",
+        }
+    }
+
+    #[cfg_attr(not(feature = "grobid"), allow(unused_variables))]
+    async fn read(self, cfg: &Config, pdf: &Path) -> Result<Reading> {
+        match self {
+            #[cfg(feature = "grobid")]
+            | Self::Grobid => {
+                let server = cfg
+                    .grobid_url
+                    .as_ref()
+                    .ok_or(MabelError::MissingEnv { key: "GROBID_URL" })?;
+                let extracted = crate::extract::grobid::process(cfg, server, pdf).await?;
+                Ok(structured(&extracted.structure))
+            }
+            #[cfg(not(feature = "grobid"))]
+            | Self::Grobid => {
+                Err(MabelError::Config {
+                    msg: "this build has no GROBID support".to_string(),
+                })
+            }
+            #[cfg(feature = "pdf")]
+            | Self::Builtin => {
+                let extracted = crate::extract::pdftext::process(pdf, cfg.max_memory).await?;
+                Ok(structured(&extracted.structure))
+            }
+            #[cfg(not(feature = "pdf"))]
+            | Self::Builtin => {
+                Err(MabelError::Config {
+                    msg: "this build has no built-in extractor".to_string(),
+                })
+            }
+            | Self::Pdftotext => Ok(plain(&skim::text_layer(pdf).await?)),
+        }
+    }
+}
+
+#[cfg_attr(not(any(feature = "grobid", feature = "pdf")), allow(dead_code))]
+fn structured(structure: &PaperStructure) -> Reading {
+    let abstract_text = structure.abstract_text.as_deref().unwrap_or_default();
+    let words = abstract_text.split_whitespace().count()
+        + structure
+            .sections
+            .iter()
+            .map(|s| s.text.split_whitespace().count())
+            .sum::<usize>();
+    Reading {
+        words,
+        has_abstract: !abstract_text.trim().is_empty(),
+        sections: structure.sections.len(),
+        references: Some(structure.references.len()),
+    }
+}
+
+fn plain(text: &str) -> Reading {
+    let first_page = text.split('\x0c').next().unwrap_or_default();
+    Reading {
+        words: text.split_whitespace().count(),
+        has_abstract: first_page
+            .lines()
+            .any(|l| l.trim_start().to_lowercase().starts_with("abstract")),
+        sections: text.lines().filter(|l| is_heading(l)).count(),
+        references: None,
+    }
+}
+
+fn report(total: usize, tallies: &[Tally]) -> String {
+    let mut out = format!(
+        "\n{:<10} {:>9} {:>10} {:>10} {:>10} {:>9} {:>11} {:>11}\n",
+        "extractor", "read", "median s", "total s", "words", "abstract", "sections", "references"
+    );
+    for t in tallies {
+        let read = t.readings.len();
+        let references: Option<Vec<usize>> = t.readings.iter().map(|r| r.references).collect();
+        let _ = writeln!(
+            out,
+            "{:<10} {:>9} {:>10.2} {:>10.1} {:>10.0} {:>9} {:>11.1} {:>11}",
+            t.name,
+            format!("{read}/{total}"),
+            median(&t.secs),
+            t.secs.iter().sum::<f64>(),
+            mean(t.readings.iter().map(|r| r.words)),
+            format!("{}/{read}", t.readings.iter().filter(|r| r.has_abstract).count()),
+            mean(t.readings.iter().map(|r| r.sections)),
+            references.map_or("-".to_string(), |r| format!("{:.1}", mean(r.into_iter()))),
+        );
+        if t.failed > 0 {
+            let _ = writeln!(out, "{:<10} {} failed; see the log", "", t.failed);
+        }
+    }
+    out.push_str("\nwords, sections and references are means per PDF read; plain-text sections are estimated\n");
+    out
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    match sorted.len() {
+        | 0 => 0.0,
+        | n if n % 2 == 1 => sorted[n / 2],
+        | n => f64::midpoint(sorted[n / 2 - 1], sorted[n / 2]),
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean(values: impl Iterator<Item = usize>) -> f64 {
+    let (sum, count) = values.fold((0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum as f64 / f64::from(count)
+    }
+}
@@ -0,0 +1,311 @@
+//! `mabel bibliography --list <tag>`: an annotated bibliography of the papers whose notes carry
+//! the tag, a citation and a three-sentence annotation each, in Markdown and optionally LaTeX.
+//!
+//! The citation comes from the note's frontmatter. The annotation is the first three sentences of
+//! the note's `## Summary`; only notes without one (hand-written notes, say) are sent to the model,
+//! and if that fails the note's TL;DR or its first sentences stand in.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use mabel_core::sentence;
+use serde_yaml::Value;
+
+use crate::{
+    bibtex,
+    cli::BibliographyArgs,
+    config::Config,
+    llm::Llm,
+    paper::PaperMetadata,
+    prompt,
+    region::RegionMarkers,
+    vault::{self, VaultNote},
+    MabelError, Result,
+};
+
+/// Sentences in an annotation.
+const SENTENCES: usize = 3;
+
+/// Authors named before "et al.".
+const MAX_AUTHORS: usize = 3;
+
+/// One paper in the bibliography.
+struct Entry {
+    metadata: PaperMetadata,
+    /// Year as the note has it, which may be all it has of the date
+    year: Option<String>,
+    annotation: String,
+}
+
+pub async fn run(cfg: &Config, args: &BibliographyArgs) -> Result<()> {
+    let notes: Vec<VaultNote> = vault::scan(&cfg.vault_path, &cfg.frontmatter)
+        .into_iter()
+        .filter(|n| n.frontmatter.tags.iter().any(|t| has_tag(t, &args.list)))
+        .collect();
+    if notes.is_empty() {
+        println!("no notes tagged {}", args.list);
+        return Ok(());
+    }
+
+    let mut entries = Vec::with_capacity(notes.len());
+    let mut llm = None;
+    let mut from_model = 0;
+    for note in &notes {
+        let text = std::fs::read_to_string(&note.path).map_err(|source| {
+            MabelError::Io {
+                path: note.path.clone(),
+                source,
+            }
+        })?;
+        let (yaml, body) = vault::split_frontmatter(&text);
+        let (metadata, year) = citation(yaml, note);
+        let annotation = match summary(body, &cfg.region_markers) {
+            | Some(summary) => sentence::first(&summary, SENTENCES).to_string(),
+            | None => {
+                let llm = match &llm {
+                    | Some(llm) => llm,
+                    | None => llm.insert(Llm::from_config(cfg)),
+                };
+                from_model += 1;
+                annotate(llm.as_ref(), &metadata, body, note).await
+            }
+        };
+        entries.push(Entry {
+            metadata,
+            year,
+            annotation,
+        });
+    }
+    entries.sort_by_cached_key(|e| {
+        let surname = e.metadata.authors.first().and_then(|a| a.split_whitespace().last());
+        (
+            surname.unwrap_or_default().to_lowercase(),
+            e.year.clone(),
+            e.metadata.title.to_lowercase(),
+        )
+    });
+    tracing::info!(
+        papers = entries.len(),
+        from_model,
+        list = %args.list,
+        "annotated bibliography"
+    );
+
+    let markdown = markdown(&args.list, &entries);
+    match &args.out {
+        | Some(out) => write(out, &markdown)?,
+        | None => print!("{markdown}"),
+    }
+    if let Some(latex) = &args.latex {
+        write(latex, &latex_bibliography(&args.list, &entries))?;
+    }
+    Ok(())
+}
+
+/// Whether a note's `tag` is `list`, or nested under it (`list/...`); a leading `#` is ignored.
+fn has_tag(tag: &str, list: &str) -> bool {
+    let (tag, list) = (tag.trim().trim_start_matches('#'), list.trim().trim_start_matches('#'));
+    tag.eq_ignore_ascii_case(list)
+        || tag
+            .get(..list.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(list) && tag[list.len()..].starts_with('/'))
+}
+
+/// The paper's metadata as the note's frontmatter gives it, and its year. Fields that are missing
+/// or of an unexpected type are left out.
+fn citation(yaml: Option<&str>, note: &VaultNote) -> (PaperMetadata, Option<String>) {
+    let fields = yaml
+        .and_then(mabel_core::note::read_frontmatter)
+        .map_or(Value::Null, |(map, _)| Value::Mapping(map));
+    let text = |key: &str| {
+        match fields.get(key)? {
+            | Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+            | Value::Number(n) => Some(n.to_string()),
+            | _ => None,
+        }
+    };
+    let authors = match fields.get("authors") {
+        | Some(Value::Sequence(names)) => {
+            names
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|a| a.trim().to_string())
+                .filter(|a| !a.is_empty())
+                .collect()
+        }
+        | Some(Value::String(names)) => names.split(';').map(|a| a.trim().to_string()).collect(),
+        | _ => Vec::new(),
+    };
+    let published = text("published").and_then(|p| NaiveDate::parse_from_str(&p, "%Y-%m-%d").ok());
+    let year = text("year").or_else(|| published.map(|d| d.format("%Y").to_string()));
+    let metadata = PaperMetadata {
+        title: note.title().to_string(),
+        authors,
+        // The citekey takes the year from here.
+        published: published.or_else(|| {
+            year.as_deref()
+                .and_then(|y| y.parse().ok())
+                .and_then(|y| NaiveDate::from_ymd_opt(y, 1, 1))
+        }),
+        journal: text("venue").or_else(|| text("journal")),
+        doi: text("doi"),
+        arxiv_id: text("arxiv"),
+        pmid: text("pmid"),
+        url: text("url"),
+        ..PaperMetadata::default()
+    };
+    (metadata, year)
+}
+
+/// The paragraphs under the note's `## Summary` heading, on one line; `None` when it has none.
+fn summary(body: &str, markers: &RegionMarkers) -> Option<String> {
+    let lines: Vec<&str> = body
+        .lines()
+        .skip_while(|l| !l.trim().eq_ignore_ascii_case("## Summary"))
+        .skip(1)
+        .take_while(|l| !l.trim_start().starts_with('#') && !markers.is_begin(l) && !markers.is_end(l))
+        .collect();
+    let text = crate::xml::collapse_whitespace(&lines.join(" "));
+    (!text.is_empty()).then_some(text)
+}
+
+/// An annotation for a note without a summary, from the model; the note's TL;DR or first
+/// sentences when the model is not configured or fails.
+async fn annotate(llm: Result<&Llm, &MabelError>, metadata: &PaperMetadata, body: &str, note: &VaultNote) -> String {
+    let answer = match llm {
+        | Ok(llm) => {
+            llm.complete(&prompt::annotation(metadata, body))
+                .await
+                .map_err(|e| e.root().to_string())
+        }
+        | Err(e) => Err(e.root().to_string()),
+    };
+    match answer {
+        | Ok(reply) => sentence::first(&crate::xml::collapse_whitespace(&reply.text), SENTENCES).to_string(),
+        | Err(e) => {
+            tracing::warn!(
+                note = %note.path.display(),
+                error = %e,
+                "could not annotate the note with the model; using its own text"
+            );
+            let own = note.tldr.clone().unwrap_or_else(|| {
+                let prose: Vec<&str> = body
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with(['#', '>', '<', '-', '|', '!']))
+                    .collect();
+                crate::xml::collapse_whitespace(&prose.join(" "))
+            });
+            sentence::first(&own, SENTENCES).to_string()
+        }
+    }
+}
+
+/// "A, B, and C" or "A, B, C et al.".
+fn authors(names: &[String]) -> String {
+    match names {
+        | [] => "Anonymous".to_string(),
+        | [one] => one.clone(),
+        | [a, b] => format!("{a} and {b}"),
+        | [rest @ .., last] if names.len() <= MAX_AUTHORS => format!("{}, and {last}", rest.join(", ")),
+        | _ => format!("{} et al.", names[..MAX_AUTHORS].join(", ")),
+    }
+}
+
+/// Where the paper can be found: its DOI, arXiv page or URL.
+fn link(metadata: &PaperMetadata) -> Option<String> {
+    metadata
+        .doi
+        .as_ref()
+        .map(|doi| format!("https://doi.org/{doi}"))
+        .or_else(|| {
+            metadata
+                .arxiv_id
+                .as_ref()
+                .map(|id| format!("https://arxiv.org/abs/{id}"))
+        })
+        .or_else(|| metadata.url.clone())
+}
+
+fn markdown(list: &str, entries: &[Entry]) -> String {
+    let mut out = format!("# Annotated bibliography: {list}\n");
+    for entry in entries {
+        let m = &entry.metadata;
+        let year = entry.year.as_deref().unwrap_or("n.d.");
+        let _ = write!(
+            out,
+            "\n{} ({year}). {}.",
+            authors(&m.authors),
+            m.title.trim_end_matches('.')
+        );
+        if let Some(venue) = &m.journal {
+            let _ = write!(out, " *{venue}*.");
+        }
+        if let Some(link) = link(m) {
+            let _ = write!(out, " <{link}>");
+        }
+        out.push('\n');
+        if !entry.annotation.is_empty() {
+            let _ = writeln!(out, "\n> {}", entry.annotation);
+        }
+    }
+    out
+}
+
+/// A `thebibliography` environment, keyed by the citekeys `mabel cite` gives.
+fn latex_bibliography(list: &str, entries: &[Entry]) -> String {
+    let mut out = format!(
+        "% Annotated bibliography of the papers tagged {list}, written by mabel; it needs \
+         \\usepackage{{url}}.\n\\begin{{thebibliography}}{{{}}}\n",
+        entries.len()
+    );
+    let mut keys = HashSet::new();
+    for entry in entries {
+        let m = &entry.metadata;
+        let base = bibtex::citekey(m);
+        // Two papers with the same key would leave one of them uncitable.
+        let mut key = base.clone();
+        let mut n = 1;
+        while !keys.insert(key.clone()) {
+            n += 1;
+            key = format!("{base}{n}");
+        }
+        let _ = write!(
+            out,
+            "\n\\bibitem{{{key}}}\n{}.\n\\newblock \\emph{{{}}}.\n",
+            bibtex::escape(&authors(&m.authors)).trim_end_matches('.'),
+            bibtex::escape(m.title.trim_end_matches('.'))
+        );
+        let year = entry.year.as_deref().unwrap_or("n.d.");
+        match &m.journal {
+            | Some(venue) => {
+                let _ = writeln!(out, "\\newblock {}, {year}.", bibtex::escape(venue));
+            }
+            | None => {
+                let _ = writeln!(out, "\\newblock {year}.");
+            }
+        }
+        if let Some(link) = link(m) {
+            let _ = writeln!(out, "\\newblock \\url{{{link}}}");
+        }
+        if !entry.annotation.is_empty() {
+            let _ = writeln!(out, "\n{}", bibtex::escape(&entry.annotation));
+        }
+    }
+    out.push_str("\n\\end{thebibliography}\n");
+    out
+}
+
+fn write(path: &Path, text: &str) -> Result<()> {
+    let io_err = |path: PathBuf| move |source| MabelError::Io { path, source };
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(io_err(dir.to_path_buf()))?;
+    }
+    std::fs::write(path, text).map_err(io_err(path.to_path_buf()))?;
+    tracing::info!(path = %path.display(), "bibliography written");
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! `mabel brief <id>... --title "State of X"`: one thematic brief across several papers, on what
+//! they take for granted, where their results disagree and what they leave open, written to
+//! `Briefs/<title>.md` with links to each paper's note.
+//!
+//! The model reads the papers' notes rather than the papers, so each paper needs a note first. It
+//! cites the papers by number, and the numbers become links to their notes.
+
+use std::{collections::HashSet, fmt::Write as _, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    cli::BriefArgs,
+    config::Config,
+    index::Index,
+    llm::{self, Llm},
+    note::{self, Overwrite},
+    prompt,
+    region::RegionMarkers,
+    vault, MabelError, Result,
+};
+
+/// The managed region the brief is written in, so regenerating it keeps text added around it.
+const REGION: &str = "brief";
+
+/// A paper in the brief.
+struct Paper {
+    title: String,
+    /// The note's file name without `.md`, which is what links to it
+    link: String,
+    note: PathBuf,
+}
+
+/// The brief as the model gives it.
+#[derive(Debug, Default, Deserialize)]
+struct Brief {
+    #[serde(default)]
+    overview: String,
+    #[serde(default)]
+    shared_assumptions: Vec<String>,
+    #[serde(default)]
+    conflicts: Vec<String>,
+    #[serde(default)]
+    open_questions: Vec<String>,
+}
+
+pub async fn run(cfg: &Config, args: &BriefArgs) -> Result<()> {
+    let papers = locate(cfg, &args.papers)?;
+    let mut notes = Vec::with_capacity(papers.len());
+    for paper in &papers {
+        let text = tokio::fs::read_to_string(&paper.note).await.map_err(|source| {
+            MabelError::Io {
+                path: paper.note.clone(),
+                source,
+            }
+        })?;
+        let (_, body) = vault::split_frontmatter(&text);
+        notes.push((paper.title.clone(), body.to_string()));
+    }
+
+    let llm = Llm::from_config(cfg)?;
+    let completion = llm.complete(&prompt::brief(&args.title, &notes)).await?;
+    let trimmed = completion.text.trim();
+    let candidate = llm::json_object(trimmed);
+    let brief = serde_json::from_str::<Brief>(candidate).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "brief reply was not the requested JSON; keeping it as the overview");
+        Brief {
+            overview: trimmed.to_string(),
+            ..Brief::default()
+        }
+    });
+    tracing::info!(
+        papers = papers.len(),
+        tokens_in = completion.usage.prompt_tokens,
+        tokens_out = completion.usage.completion_tokens,
+        "wrote brief"
+    );
+
+    let text = cfg.frontmatter_style.apply(&render(
+        &args.title,
+        &cfg.timezone.timestamp(chrono::Utc::now()),
+        &brief,
+        &papers,
+        &cfg.region_markers,
+    ));
+    let path = cfg.briefs_dir().join(format!("{}.md", note::file_stem(&args.title)));
+    note::write_managed(&path, &text, Overwrite::Merge, &cfg.region_markers).await?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// The indexed note of each paper, in the order given. A paper named twice is taken once.
+fn locate(cfg: &Config, ids: &[String]) -> Result<Vec<Paper>> {
+    let index = Index::open(&cfg.index_path())?;
+    let mut seen = HashSet::new();
+    let mut papers = Vec::with_capacity(ids.len());
+    for id in ids {
+        let missing = || {
+            MabelError::Config {
+                msg: format!("no note for {id:?}; run `mabel note {id}` first"),
+            }
+        };
+        let found = index.find(id)?.into_iter().next().ok_or_else(missing)?;
+        if !found.note.is_file() {
+            return Err(missing());
+        }
+        if !seen.insert(found.key.clone()) {
+            continue;
+        }
+        papers.push(Paper {
+            title: found.title,
+            link: found
+                .note
+                .file_stem()
+                .map_or_else(|| note::file_stem(id), |s| s.to_string_lossy().into_owned()),
+            note: found.note,
+        });
+    }
+    if papers.len() < 2 {
+        return Err(MabelError::Config {
+            msg: "a brief needs at least two different papers".to_string(),
+        });
+    }
+    Ok(papers)
+}
+
+/// The brief note: frontmatter linking the papers, then the brief in a managed region.
+fn render(title: &str, created: &str, brief: &Brief, papers: &[Paper], markers: &RegionMarkers) -> String {
+    let links: Vec<String> = papers.iter().map(|p| format!("[[{}]]", p.link)).collect();
+    let mut out = format!(
+        "---\ntype: brief\ncreated: {}\npapers: [{}]\n---\n\n# {title}\n\n",
+        serde_json::Value::from(created),
+        links
+            .iter()
+            .map(|l| serde_json::Value::from(l.as_str()).to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    out.push_str(&markers.begin(REGION));
+    out.push('\n');
+    let overview = brief.overview.trim();
+    if !overview.is_empty() {
+        let _ = write!(out, "\n{}\n", cite(overview, &links));
+    }
+    for (heading, items) in [
+        ("Shared assumptions", &brief.shared_assumptions),
+        ("Conflicting results", &brief.conflicts),
+        ("Open questions", &brief.open_questions),
+    ] {
+        let items: Vec<&str> = items.iter().map(|i| i.trim()).filter(|i| !i.is_empty()).collect();
+        if items.is_empty() {
+            continue;
+        }
+        let _ = write!(out, "\n## {heading}\n\n");
+        for item in items {
+            let _ = writeln!(out, "- {}", cite(item, &links));
+        }
+    }
+    out.push_str("\n## Papers\n\n");
+    for link in &links {
+        let _ = writeln!(out, "- {link}");
+    }
+    out.push('\n');
+    out.push_str(&markers.end(REGION));
+    out.push('\n');
+    out
+}
+
+/// Replace the model's `[n]` citations with the links to the papers' notes; numbers that name no
+/// paper are left as they are.
+fn cite(text: &str, links: &[String]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let link = after.find(']').and_then(|close| {
+            let n: usize = after[..close].trim().parse().ok()?;
+            Some((links.get(n.checked_sub(1)?)?, close))
+        });
+        match link {
+            | Some((link, close)) => {
+                out.push_str(link);
+                rest = &after[close + 1..];
+            }
+            | None => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    // `[1][2]` reads as two links, not one run of brackets.
+    out.replace("]][[", "]], [[")
+}
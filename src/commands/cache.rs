@@ -0,0 +1,68 @@
+//! `mabel cache path|list|clear`
+
+use walkdir::WalkDir;
+
+use crate::{cli::CacheAction, config::Config, MabelError, Result};
+
+pub fn run(cfg: &Config, action: &CacheAction) -> Result<()> {
+    match action {
+        | CacheAction::Path => println!("{}", cfg.cache_dir.display()),
+        | CacheAction::List => {
+            let mut total = 0;
+            for (path, size) in cached_files(cfg) {
+                total += size;
+                println!("{:>10}  {}", human_size(size), path.display());
+            }
+            println!("{:>10}  total", human_size(total));
+        }
+        | CacheAction::Clear { yes } => {
+            for (path, _) in cached_files(cfg) {
+                if *yes {
+                    std::fs::remove_file(&path).map_err(|source| {
+                        MabelError::Io {
+                            path: path.clone(),
+                            source,
+                        }
+                    })?;
+                    println!("removed {}", path.display());
+                } else {
+                    println!("would remove {}", path.display());
+                }
+            }
+            if !yes {
+                eprintln!("(dry run; pass --yes to delete)");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every regular file under the cache directory, with its size in bytes.
+fn cached_files(cfg: &Config) -> Vec<(std::path::PathBuf, u64)> {
+    WalkDir::new(&cfg.cache_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| {
+            let size = e.metadata().map(|m| m.len()).unwrap_or(0);
+            (e.into_path(), size)
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,148 @@
+//! `mabel refresh-citations`: bring the `citations` (OpenAlex) and `altmetric` (Altmetric attention
+//! score) frontmatter fields of every paper note up to date, for Dataview tables of the most cited
+//! papers in the vault.
+//!
+//! Metadata lookups only, several at once; no model is involved and nothing else in the notes
+//! changes. The registry keeps when each paper was looked up, for `mabel freshness`.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use reqwest::Client;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    cli::RefreshCitationsArgs,
+    config::Config,
+    http,
+    registry::Registry,
+    render::FrontmatterFields,
+    source::{altmetric, openalex},
+    vault::{self, VaultNote},
+    MabelError, Result,
+};
+
+/// What the lookups found; `None` where a service does not know the paper.
+struct Counts {
+    citations: Option<u64>,
+    altmetric: Option<u64>,
+}
+
+pub async fn run(cfg: &Config, args: &RefreshCitationsArgs) -> Result<()> {
+    let http = http::client(cfg)?;
+    let limit = Arc::new(Semaphore::new(args.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for note in vault::scan(&cfg.vault_notes_dir(), &cfg.frontmatter) {
+        let fm = &note.frontmatter;
+        let Some(doi) = openalex::lookup_doi(fm.doi.as_deref(), fm.arxiv.as_deref()) else {
+            continue;
+        };
+        let (http, limit, altmetric, retries) = (http.clone(), limit.clone(), !args.no_altmetric, cfg.http_retries);
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            let counts = lookup(&http, &note, &doi, altmetric, retries).await;
+            (note, counts)
+        });
+    }
+    if tasks.is_empty() {
+        println!("no notes with a DOI or arXiv id");
+        return Ok(());
+    }
+
+    let (mut unchanged, mut updated, mut failed) = (0, 0, 0);
+    let mut checked: HashSet<PathBuf> = HashSet::new();
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((note, counts)) = joined else {
+            failed += 1;
+            continue;
+        };
+        match counts {
+            | Ok(counts) => {
+                let changed = update_note(&note, &counts, &cfg.frontmatter).await?;
+                checked.insert(note.path.clone());
+                if changed.is_empty() {
+                    unchanged += 1;
+                } else {
+                    updated += 1;
+                    let shown = note.path.strip_prefix(&cfg.vault_path).unwrap_or(&note.path);
+                    println!("updated {} ({})", shown.display(), changed.join(", "));
+                }
+            }
+            | Err(e) => {
+                failed += 1;
+                tracing::warn!(note = %note.link, error = %e, "citation lookup failed");
+            }
+        }
+    }
+
+    let now = cfg.timezone.timestamp(chrono::Utc::now());
+    Registry::update(&cfg.registry_path(), |registry| {
+        for entry in registry.papers.values_mut() {
+            if checked.contains(&cfg.vault_path.join(&entry.note)) {
+                entry.citations_checked = Some(now.clone());
+            }
+        }
+    })?;
+    println!("{updated} updated, {unchanged} unchanged, {failed} failed");
+    Ok(())
+}
+
+/// Citation count of the paper OpenAlex knows as `doi`, and its Altmetric score when `altmetric`
+/// is on. A failed Altmetric lookup only warns, so the citation count is still written.
+async fn lookup(http: &Client, note: &VaultNote, doi: &str, altmetric: bool, retries: u32) -> Result<Counts> {
+    let citations = openalex::citations(http, doi, retries).await?;
+    let fm = &note.frontmatter;
+    let altmetric = if altmetric {
+        altmetric::score(http, fm.doi.as_deref(), fm.arxiv.as_deref(), retries)
+            .await
+            .inspect_err(|e| tracing::warn!(note = %note.link, error = %e, "Altmetric lookup failed"))
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+    Ok(Counts { citations, altmetric })
+}
+
+/// Write the counts that differ from the note's into its frontmatter; returns what changed, as
+/// `field old → new`.
+async fn update_note(note: &VaultNote, counts: &Counts, fields: &FrontmatterFields) -> Result<Vec<String>> {
+    let path: &Path = &note.path;
+    let counted = [
+        ("citations", counts.citations, note.frontmatter.citations),
+        ("altmetric", counts.altmetric, note.frontmatter.altmetric),
+    ];
+    let changed: Vec<_> = counted
+        .into_iter()
+        .filter_map(|(key, fresh, old)| fresh.filter(|f| old != Some(*f)).map(|f| (key, f, old)))
+        .collect();
+    if changed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let mut text = tokio::fs::read_to_string(path).await.map_err(io_err)?;
+    vault::warn_repaired(path, &note.repaired);
+    for (key, fresh, _) in &changed {
+        if let Some(property) = fields.name(key) {
+            text = vault::set_frontmatter_field(&text, property, &fresh.to_string());
+        }
+    }
+    tokio::fs::write(path, text).await.map_err(io_err)?;
+    Ok(changed
+        .into_iter()
+        .map(|(key, fresh, old)| {
+            match old {
+                | Some(old) => format!("{key} {old} → {fresh}"),
+                | None => format!("{key} {fresh}"),
+            }
+        })
+        .collect())
+}
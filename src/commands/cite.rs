@@ -0,0 +1,98 @@
+//! `mabel cite <id> [--into <project>] [--copy]`: BibTeX for a paper, appended to a LaTeX
+//! project's bibliography without duplicating entries.
+
+use std::path::{Path, PathBuf};
+
+use tokio::io::AsyncWriteExt;
+use walkdir::WalkDir;
+
+use crate::{bibtex, cli::CiteArgs, config::Config, http, source::Input, MabelError, Result};
+
+/// Created in the project folder when it has no `.bib` file yet.
+const DEFAULT_BIB: &str = "references.bib";
+
+pub async fn run(cfg: &Config, args: &CiteArgs) -> Result<()> {
+    let http = http::client(cfg)?;
+    let paper = Input::parse(&args.input)?.resolve(cfg, &http).await?;
+    let metadata = &paper.metadata;
+
+    let key = match &args.into {
+        | Some(into) => bibtex::add_to_file(&bib_file(into)?, metadata).await?,
+        | None => {
+            let key = bibtex::citekey(metadata);
+            print!("{}", bibtex::entry(&key, metadata));
+            key
+        }
+    };
+    let cite = format!("\\cite{{{key}}}");
+    println!("{cite}");
+    if args.copy {
+        copy_to_clipboard(&cite).await;
+    }
+    Ok(())
+}
+
+/// The `.bib` file to use for `--into`: the path itself if it names a `.bib` file, otherwise the
+/// only `.bib` file in the project folder (or a new `references.bib`).
+fn bib_file(into: &Path) -> Result<PathBuf> {
+    if into.extension().is_some_and(|x| x == "bib") {
+        return Ok(into.to_path_buf());
+    }
+    if !into.is_dir() {
+        return Err(MabelError::Config {
+            msg: format!("--into {} is neither a folder nor a .bib file", into.display()),
+        });
+    }
+    let found: Vec<PathBuf> = WalkDir::new(into)
+        .max_depth(3)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "bib"))
+        .map(walkdir::DirEntry::into_path)
+        .collect();
+    match found.as_slice() {
+        | [] => Ok(into.join(DEFAULT_BIB)),
+        | [one] => Ok(one.clone()),
+        | many => {
+            let names: Vec<String> = many.iter().map(|p| p.display().to_string()).collect();
+            Err(MabelError::Config {
+                msg: format!(
+                    "{} has several .bib files ({}); pass the one to use with --into",
+                    into.display(),
+                    names.join(", ")
+                ),
+            })
+        }
+    }
+}
+
+/// Best effort: try the usual clipboard tools for each platform and warn if none works.
+async fn copy_to_clipboard(text: &str) {
+    const TOOLS: &[&[&str]] = &[
+        &["pbcopy"],
+        &["wl-copy"],
+        &["xclip", "-selection", "clipboard"],
+        &["xsel", "--clipboard", "--input"],
+        &["clip"],
+    ];
+    for tool in TOOLS {
+        let Ok(mut child) = tokio::process::Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes()).await;
+        }
+        if child.wait().await.is_ok_and(|s| s.success()) {
+            tracing::info!(tool = tool[0], "copied to clipboard");
+            return;
+        }
+    }
+    tracing::warn!("no clipboard tool found (pbcopy, wl-copy, xclip, xsel or clip)");
+}
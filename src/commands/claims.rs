@@ -0,0 +1,32 @@
+//! `mabel claims search|extract`
+
+use crate::{claims, cli::ClaimsAction, config::Config, pipeline::Pipeline, Result};
+
+pub async fn run(cfg: Config, action: &ClaimsAction) -> Result<()> {
+    match action {
+        | ClaimsAction::Search { query, limit } => {
+            let records = claims::load(&cfg.claims_path()).await?;
+            if records.is_empty() {
+                println!("the claim store is empty; run `mabel claims extract <paper>` or set MABEL_CLAIMS=1");
+                return Ok(());
+            }
+            for record in claims::search(&records, query).into_iter().take(*limit) {
+                println!(
+                    "- {} ({}, {}) [[{}]]",
+                    record.statement,
+                    record.evidence.as_str(),
+                    record.strength.as_str(),
+                    record.note
+                );
+            }
+        }
+        | ClaimsAction::Extract { input } => {
+            let pipeline = Pipeline::new(cfg)?;
+            let paper = pipeline.resolve_paper(input).await?;
+            let (count, usage) = pipeline.extract_claims(&paper).await?;
+            tracing::info!(tokens = usage.total(), "claims extracted");
+            println!("{count} claims stored for {}", paper.metadata.title);
+        }
+    }
+    Ok(())
+}
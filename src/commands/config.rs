@@ -0,0 +1,361 @@
+//! `mabel config show|get|set|unset`
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cli::{ConfigAction, ConfigTarget},
+    config::{Config, LlmBackend},
+    config_file, cost,
+    http::ServiceAuth,
+    secret::Secret,
+    MabelError, Result,
+};
+
+pub fn run(cfg: &Config, action: &ConfigAction) -> Result<()> {
+    match action {
+        | ConfigAction::Show => show(cfg),
+        | ConfigAction::Get { key, target } => get(cfg, key, target)?,
+        | ConfigAction::Set { key, value, target } => set(cfg, key, Some(value), target)?,
+        | ConfigAction::Unset { key, target } => set(cfg, key, None, target)?,
+    }
+    Ok(())
+}
+
+fn show(cfg: &Config) {
+    let rows = rows(cfg);
+    let width = rows.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    for (key, value) in rows {
+        println!("{key:<width$}  {value}");
+    }
+}
+
+/// The resolved configuration as `config show` prints it.
+#[allow(clippy::too_many_lines)]
+fn rows(cfg: &Config) -> Vec<(&'static str, String)> {
+    let opt = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    let mut rows: Vec<(&str, String)> = vec![
+        ("vault_path", cfg.vault_path.display().to_string()),
+        ("vault_subdir", cfg.vault_subdir.clone()),
+        ("copy_pdf_into_vault", cfg.copy_pdf_into_vault.to_string()),
+        ("text_sidecar", cfg.text_sidecar.to_string()),
+        ("thread", cfg.thread.to_string()),
+        ("thread_chars", cfg.thread_chars.to_string()),
+        (
+            "thread_template",
+            opt(cfg.thread_template.as_ref().map(|p| p.display().to_string())),
+        ),
+        ("cache_dir", cfg.cache_dir.display().to_string()),
+        ("overwrite_note", cfg.overwrite_note.as_str().to_string()),
+        ("preview_diff", cfg.preview_diff.to_string()),
+        ("ci", cfg.ci.to_string()),
+        ("fail_on", cfg.fail_on.as_str().to_string()),
+        ("llm.backend", cfg.llm.name().to_string()),
+        ("llm.model", cfg.llm.model().to_string()),
+    ];
+    rows.extend(llm_rows(&cfg.llm));
+    if !cfg.routing.is_empty() {
+        rows.push(("llm.routing", cfg.routing.to_string()));
+    }
+    rows.extend([
+        ("max_pages", opt(cfg.max_pages.map(|n| n.to_string()))),
+        ("uncertainty", cfg.uncertainty.to_string()),
+        ("max_uncertain", opt(cfg.max_uncertain.map(|n| n.to_string()))),
+        ("verify_claims", cfg.verify_claims.to_string()),
+        ("max_unverified", opt(cfg.max_unverified.map(|n| n.to_string()))),
+        ("min_extraction_quality", cfg.min_extraction_quality.to_string()),
+        ("stop_sections", cfg.stop_sections.to_string()),
+        ("max_cost_per_paper", opt(cfg.max_cost_per_paper.map(cost::format))),
+        ("max_cost_per_day", opt(cfg.max_cost_per_day.map(cost::format))),
+        ("map_reduce", cfg.map_reduce.to_string()),
+        ("chunk_chars", opt(cfg.chunk_chars.map(|n| n.to_string()))),
+        ("chunk_overlap", cfg.chunk_overlap.to_string()),
+        ("grobid_url", opt(cfg.grobid_url.as_ref().map(ToString::to_string))),
+        ("grobid_auth", auth_summary(&cfg.grobid_auth)),
+        ("grobid_consolidate_header", cfg.grobid_consolidate_header.as_str().to_string()),
+        ("grobid_consolidate_citations", cfg.grobid_consolidate_citations.as_str().to_string()),
+        ("pdf_fallback", cfg.pdf_fallback.to_string()),
+        ("tiered", cfg.tiered.to_string()),
+        ("ncbi_api_key", redact(cfg.ncbi_api_key.as_ref())),
+        ("ncbi_email", opt(cfg.ncbi_email.clone())),
+        ("unpaywall_email", opt(cfg.unpaywall_email.clone())),
+        ("orcid", cfg.orcid.to_string()),
+        ("arxiv_api_url", cfg.arxiv_api_url.to_string()),
+        ("arxiv_pdf_url", cfg.arxiv_pdf_url.to_string()),
+        ("http_timeout_secs", cfg.http_timeout.as_secs().to_string()),
+        ("http_retries", cfg.http_retries.to_string()),
+        ("rate_limit_per_min", cfg.rate_limit_per_min.to_string()),
+        ("max_concurrent_downloads", cfg.max_concurrent_downloads.to_string()),
+        ("max_concurrent_extractions", cfg.max_concurrent_extractions.to_string()),
+        ("max_concurrent_llm", cfg.max_concurrent_llm.to_string()),
+        ("max_bandwidth", opt(cfg.max_bandwidth.map(|b| format!("{b} bytes/s")))),
+        ("max_memory", opt(cfg.max_memory.map(|m| format!("{m} bytes")))),
+        ("metered", cfg.metered.to_string()),
+        ("vault_context", cfg.vault_context.to_string()),
+        ("extract_claims", cfg.extract_claims.to_string()),
+        ("leaderboards", cfg.leaderboards.to_string()),
+        ("figure_alt", cfg.figure_alt.as_str().to_string()),
+        ("pdf_figures", cfg.pdf_figures.to_string()),
+        ("attachments_dir", cfg.attachments_dir.display().to_string()),
+        ("mocs", cfg.mocs.to_string()),
+        ("moc_template", opt(cfg.moc_template.as_ref().map(|p| p.display().to_string()))),
+        ("define_new_terms", cfg.define_new_terms.to_string()),
+        ("max_new_terms", cfg.max_new_terms.to_string()),
+        (
+            "concept_template",
+            opt(cfg.concept_template.as_ref().map(|p| p.display().to_string())),
+        ),
+        ("anki_dir", opt(cfg.anki_dir.as_ref().map(|p| p.display().to_string()))),
+        ("bib_file", opt(cfg.bib_file.as_ref().map(|p| p.display().to_string()))),
+        ("webhook_url", opt(cfg.webhook_url.as_ref().map(ToString::to_string))),
+        ("webhook_secret", redact(cfg.webhook_secret.as_ref())),
+        ("serve_users", opt(cfg.serve_users.as_ref().map(|p| p.display().to_string()))),
+        ("embedding_model", opt(cfg.embedding_model.clone())),
+        (
+            "digest_categories",
+            opt((!cfg.digest_categories.is_empty()).then(|| cfg.digest_categories.join(","))),
+        ),
+        ("template_path", cfg.template_path.display().to_string()),
+        (
+            "frontmatter_schema",
+            opt(cfg.frontmatter_schema.as_ref().map(|p| p.display().to_string())),
+        ),
+        ("frontmatter_style", cfg.frontmatter_style.as_str().to_string()),
+        (
+            "frontmatter",
+            opt((!cfg.frontmatter.is_empty()).then(|| cfg.frontmatter.to_string())),
+        ),
+        ("mode", cfg.mode.as_str().to_string()),
+        (
+            "prompt_dir",
+            opt(cfg.prompt_dir.as_ref().map(|p| p.display().to_string())),
+        ),
+        (
+            "prompt_template",
+            opt(cfg.prompt_template.as_ref().map(|p| p.display().to_string())),
+        ),
+        ("region_begin", cfg.region_markers.begin("{name}")),
+        ("region_end", cfg.region_markers.end("{name}")),
+        ("timezone", cfg.timezone.to_string()),
+    ]);
+    let files: Vec<String> = cfg.config_files.iter().map(|p| p.display().to_string()).collect();
+    rows.push(("config_files", opt((!files.is_empty()).then(|| files.join(", ")))));
+    rows.push(("profile", opt(cfg.profile.clone())));
+    rows
+}
+
+/// The rows of the settings particular to the LLM backend.
+fn llm_rows(llm: &LlmBackend) -> Vec<(&'static str, String)> {
+    let opt = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+    let mut rows = Vec::new();
+    match llm {
+        | LlmBackend::OpenAi {
+            api_key,
+            base_url,
+            max_tokens,
+            temperature,
+            auth,
+            stream,
+            ..
+        } => {
+            rows.push(("llm.api_key", redact(Some(api_key))));
+            rows.push(("llm.base_url", opt(base_url.as_ref().map(ToString::to_string))));
+            rows.push(("llm.max_tokens", max_tokens.to_string()));
+            rows.push(("llm.temperature", temperature.to_string()));
+            rows.push(("llm.auth", auth_summary(auth)));
+            rows.push(("llm.stream", stream.to_string()));
+        }
+        | LlmBackend::Ollama {
+            host,
+            max_tokens,
+            temperature,
+            auth,
+            keep_alive,
+            num_ctx,
+            preload,
+            pull,
+            stream,
+            ..
+        } => {
+            rows.push(("llm.host", host.to_string()));
+            rows.push(("llm.max_tokens", max_tokens.to_string()));
+            rows.push(("llm.temperature", temperature.to_string()));
+            rows.push(("llm.auth", auth_summary(auth)));
+            rows.push(("llm.keep_alive", opt(keep_alive.map(|k| k.to_string()))));
+            rows.push(("llm.num_ctx", opt(num_ctx.map(|n| n.to_string()))));
+            rows.push(("llm.preload", preload.to_string()));
+            rows.push(("llm.pull", pull.to_string()));
+            rows.push(("llm.stream", stream.to_string()));
+        }
+        | LlmBackend::Anthropic {
+            api_key,
+            max_tokens,
+            temperature,
+            ..
+        }
+        | LlmBackend::Gemini {
+            api_key,
+            max_tokens,
+            temperature,
+            ..
+        } => {
+            rows.push(("llm.api_key", redact(Some(api_key))));
+            rows.push(("llm.max_tokens", max_tokens.to_string()));
+            rows.push(("llm.temperature", temperature.to_string()));
+        }
+    }
+    rows
+}
+
+/// `config show` names that differ from the file key they are set by.
+const ALIASES: &[(&str, &str)] = &[
+    ("llm.backend", "backend"),
+    ("llm.api_key", "openai_api_key"),
+    ("llm.base_url", "openai_base_url"),
+    ("llm.host", "ollama_host"),
+    ("llm.keep_alive", "ollama_keep_alive"),
+    ("llm.num_ctx", "ollama_num_ctx"),
+    ("llm.preload", "ollama_preload"),
+    ("llm.pull", "ollama_pull"),
+    ("llm.max_tokens", "max_tokens"),
+    ("llm.temperature", "temperature"),
+    ("llm.stream", "stream"),
+    ("llm.routing", "routing"),
+    ("copy_pdf_into_vault", "copy_pdf"),
+    ("overwrite_note", "overwrite"),
+    ("template_path", "template"),
+];
+
+/// The file key for `name`, which may be a `config show` name. `llm.model` and `llm.api_key` are
+/// those of the backend in use.
+fn file_key(cfg: &Config, name: &str) -> Result<&'static str> {
+    let name = match name {
+        | "llm.model" if matches!(cfg.llm, LlmBackend::Ollama { .. }) => "ollama_model",
+        | "llm.model" if matches!(cfg.llm, LlmBackend::Anthropic { .. }) => "anthropic_model",
+        | "llm.api_key" if matches!(cfg.llm, LlmBackend::Anthropic { .. }) => "anthropic_api_key",
+        | "llm.model" if matches!(cfg.llm, LlmBackend::Gemini { .. }) => "gemini_model",
+        | "llm.api_key" if matches!(cfg.llm, LlmBackend::Gemini { .. }) => "gemini_api_key",
+        | "llm.model" => "openai_model",
+        | _ => ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, key)| key),
+    };
+    config_file::key(name).ok_or_else(|| {
+        MabelError::Config {
+            msg: format!("unknown setting `{name}` (see `mabel config show` for the names)"),
+        }
+    })
+}
+
+/// The file `target` names, and the profile in it if any.
+fn target_file<'a>(cfg: &Config, target: &'a ConfigTarget) -> Result<(PathBuf, Option<&'a str>)> {
+    if target.team {
+        if cfg.vault_path.as_os_str().is_empty() {
+            return Err(MabelError::MissingEnv {
+                key: "OBSIDIAN_VAULT_PATH",
+            });
+        }
+        return Ok((cfg.vault_path.join(config_file::TEAM_FILE), None));
+    }
+    let path = config_file::user_path().ok_or_else(|| {
+        MabelError::Config {
+            msg: "no config folder on this platform; set MABEL_CONFIG to the file to use".to_string(),
+        }
+    })?;
+    Ok((path, target.profile.as_deref()))
+}
+
+fn get(cfg: &Config, name: &str, target: &ConfigTarget) -> Result<()> {
+    if target.is_given() {
+        let key = file_key(cfg, name)?;
+        let (path, profile) = target_file(cfg, target)?;
+        match config_file::read_value(&path, profile, key)? {
+            | Some(toml::Value::String(s)) => println!("{s}"),
+            | Some(value) => println!("{value}"),
+            | None => {
+                return Err(MabelError::Config {
+                    msg: format!("`{key}` is not set in {}", path.display()),
+                })
+            }
+        }
+        return Ok(());
+    }
+    let rows = rows(cfg);
+    let row = |n: &str| rows.iter().find(|(k, _)| *k == n).map(|(_, v)| v.clone());
+    // The value in effect: the `config show` row for the name or its alias, else the variable the
+    // key sets (secrets redacted).
+    let key = file_key(cfg, name)?;
+    let shown = row(name)
+        .or_else(|| row(key))
+        .or_else(|| ALIASES.iter().find(|(_, k)| *k == key).and_then(|(alias, _)| row(alias)));
+    let value = shown.unwrap_or_else(|| {
+        let value = config_file::env_var(key).and_then(|var| std::env::var(var).ok());
+        if config_file::is_personal(key) {
+            redact(value.map(Secret::new).as_ref())
+        } else {
+            value.unwrap_or_else(|| "-".to_string())
+        }
+    });
+    println!("{value}");
+    Ok(())
+}
+
+fn set(cfg: &Config, name: &str, value: Option<&String>, target: &ConfigTarget) -> Result<()> {
+    let key = file_key(cfg, name)?;
+    let (path, profile) = target_file(cfg, target)?;
+    if target.team && config_file::is_personal(key) {
+        return Err(MabelError::Config {
+            msg: format!("`{key}` is a personal setting and is ignored in the team file; set it with --global"),
+        });
+    }
+    let value = value
+        .map(|raw| {
+            let raw = if config_file::is_path(key) {
+                file_path(cfg, raw, target.team)
+            } else {
+                raw.clone()
+            };
+            config_file::parse_value(key, &raw)
+        })
+        .transpose()?;
+    let changed = config_file::write_value(&path, profile, key, value.as_ref())?;
+    let place = match profile {
+        | Some(name) => format!("[profile.{name}] in {}", path.display()),
+        | None => path.display().to_string(),
+    };
+    match (&value, changed) {
+        | (Some(value), _) => println!("{key} = {value} in {place}"),
+        | (None, true) => println!("removed {key} from {place}"),
+        | (None, false) => println!("{key} was not set in {place}"),
+    }
+    Ok(())
+}
+
+/// A path given on the command line, as the file should hold it: relative to the vault in the team
+/// file when it lies inside the vault, absolute otherwise (files resolve relative paths against
+/// their own folder, not the directory `mabel` was run from).
+fn file_path(cfg: &Config, raw: &str, team: bool) -> String {
+    let path = Path::new(raw);
+    if raw.starts_with('~') || path.is_absolute() {
+        return raw.to_string();
+    }
+    let absolute = std::env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path));
+    match absolute.strip_prefix(&cfg.vault_path) {
+        | Ok(inside) if team => inside.display().to_string(),
+        | _ => absolute.display().to_string(),
+    }
+}
+
+/// Show that a secret is set without printing it.
+fn redact(secret: Option<&Secret>) -> String {
+    match secret {
+        | Some(s) if !s.is_empty() => format!("set ({} chars)", s.expose().chars().count()),
+        | _ => "-".to_string(),
+    }
+}
+
+/// Which headers and credentials a service gets, without their values.
+fn auth_summary(auth: &ServiceAuth) -> String {
+    if auth.is_empty() {
+        "-".to_string()
+    } else {
+        auth.describe()
+    }
+}
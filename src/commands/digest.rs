@@ -0,0 +1,166 @@
+//! `mabel digest`: recent arXiv papers in your categories, ranked by how well they match the papers
+//! you have read (see [`crate::recommend`]), into `Digests/Recommendations <date>.md`.
+
+use std::collections::HashSet;
+
+use crate::{
+    cli::DigestArgs,
+    config::Config,
+    http,
+    llm::Llm,
+    note::{self, Overwrite},
+    recommend::{self, embedding_text, Example, Feedback, Item, Profile, Recommendation},
+    registry::Registry,
+    source::arxiv::{ArxivId, ArxivResolver},
+    vault, MabelError, Result,
+};
+
+/// Newest submissions fetched per run; the date filter and ranking work within these.
+const MAX_CANDIDATES: usize = 300;
+
+pub async fn run(cfg: &Config, args: &DigestArgs) -> Result<()> {
+    let categories = if args.categories.is_empty() {
+        cfg.digest_categories.clone()
+    } else {
+        args.categories.clone()
+    };
+    if categories.is_empty() {
+        return Err(MabelError::Config {
+            msg: "no arXiv categories to draw from: pass --category or set MABEL_DIGEST_CATEGORIES".to_string(),
+        });
+    }
+    let llm = Llm::from_config(cfg)?;
+    let model = cfg
+        .embedding_model
+        .clone()
+        .unwrap_or_else(|| llm.default_embedding_model().to_string());
+
+    let registry = Registry::load(&cfg.registry_path())?;
+    let feedback = recommend::load_feedback(&cfg.feedback_path()).await?;
+    let profile = profile(cfg, &llm, &model, &registry, &feedback).await?;
+
+    // Candidates: recent papers not processed and not judged yet.
+    let seen: HashSet<String> = registry
+        .papers
+        .keys()
+        .cloned()
+        .chain(feedback.iter().map(|f| format!("arxiv:{}", f.paper)))
+        .collect();
+    // arXiv reports submission dates in UTC, so the window opens on the UTC date of the first
+    // local day's start rather than on that local date itself.
+    let first_day = cfg.timezone.today() - chrono::Duration::days(i64::from(args.days));
+    let since = cfg.timezone.day_bounds(first_day).0.date_naive();
+    let candidates: Vec<_> = ArxivResolver::new(http::client(cfg)?, cfg)
+        .recent(&categories, MAX_CANDIDATES)
+        .await?
+        .into_iter()
+        .filter_map(|p| {
+            let id = p.metadata.arxiv_id.as_deref().and_then(|a| ArxivId::parse(a).ok())?;
+            let key = format!("arxiv:{}", id.base());
+            let recent = p.metadata.published.is_none_or(|d| d >= since);
+            (recent && !seen.contains(&key)).then_some((key, id, p))
+        })
+        .collect();
+    tracing::info!(count = candidates.len(), "ranking new papers");
+    let items: Vec<Item> = candidates
+        .iter()
+        .map(|(key, _, p)| {
+            Item {
+                key: key.clone(),
+                text: embedding_text(&p.metadata.title, p.metadata.abstract_text.as_deref()),
+            }
+        })
+        .collect();
+    let vectors = recommend::embed(&llm, &model, &cfg.embeddings_cache_path(&model), &items).await?;
+
+    let mut ranked: Vec<Recommendation> = candidates
+        .into_iter()
+        .zip(vectors)
+        .map(|((_, id, p), vector)| {
+            let (score, nearest) = profile.score(&vector);
+            let md = p.metadata;
+            Recommendation {
+                arxiv: id.base().to_string(),
+                url: md.url.unwrap_or_else(|| format!("https://arxiv.org/abs/{}", id.base())),
+                title: md.title,
+                authors: md.authors,
+                summary: md.abstract_text.unwrap_or_default(),
+                score,
+                like: nearest.map(|e| {
+                    e.link
+                        .as_ref()
+                        .map_or_else(|| format!("\"{}\"", e.title), |l| format!("[[{l}]]"))
+                }),
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ranked.truncate(args.limit);
+
+    if args.print {
+        for r in &ranked {
+            println!("{:.3}\t{}\t{}", r.score, r.arxiv, r.title);
+        }
+        return Ok(());
+    }
+    let date = cfg.timezone.today().format("%Y-%m-%d").to_string();
+    let text = cfg.frontmatter_style.apply(&recommend::digest(
+        &date,
+        &cfg.timezone.timestamp(chrono::Utc::now()),
+        &categories,
+        profile.liked.len(),
+        &ranked,
+        &cfg.region_markers,
+    ));
+    let path = cfg.digests_dir().join(format!("Recommendations {date}.md"));
+    note::write_managed(&path, &text, Overwrite::Merge, &cfg.region_markers).await?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// The reading profile: registry papers whose notes are marked read, plus feedback.
+async fn profile(cfg: &Config, llm: &Llm, model: &str, registry: &Registry, feedback: &[Feedback]) -> Result<Profile> {
+    let (dir, fields) = (cfg.vault_path.clone(), cfg.frontmatter.clone());
+    let notes = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+        .await
+        .unwrap_or_default();
+    let mut liked: Vec<(Item, Option<String>, String)> = Vec::new();
+    for entry in registry.papers.values() {
+        let path = cfg.vault_path.join(&entry.note);
+        let Some(note) = notes.iter().find(|n| n.path == path).filter(|n| n.is_read()) else {
+            continue;
+        };
+        let item = Item {
+            key: entry.key(),
+            text: embedding_text(&entry.title, note.tldr.as_deref()),
+        };
+        liked.push((item, Some(note.link.clone()), entry.title.clone()));
+    }
+    let (more, less): (Vec<&Feedback>, Vec<&Feedback>) = feedback.iter().partition(|f| f.more);
+    let item = |f: &Feedback| {
+        Item {
+            key: format!("arxiv:{}", f.paper),
+            text: f.text.clone(),
+        }
+    };
+    liked.extend(more.into_iter().map(|f| (item(f), None, f.title.clone())));
+    if liked.is_empty() {
+        return Err(MabelError::Config {
+            msg: "nothing to rank by yet: mark paper notes `status: read` or use `mabel feedback <id> --more`"
+                .to_string(),
+        });
+    }
+
+    let cache = cfg.embeddings_cache_path(model);
+    let items: Vec<Item> = liked.iter().map(|(i, ..)| i.clone()).collect();
+    let vectors = recommend::embed(llm, model, &cache, &items).await?;
+    let less: Vec<Item> = less.into_iter().map(item).collect();
+    Ok(Profile {
+        liked: liked
+            .into_iter()
+            .zip(vectors)
+            .map(|((_, link, title), vector)| Example { link, title, vector })
+            .collect(),
+        disliked: recommend::embed(llm, model, &cache, &less).await?,
+    })
+}
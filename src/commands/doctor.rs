@@ -0,0 +1,268 @@
+//! `mabel doctor`: check what mabel depends on, each end to end: the configuration loads, the
+//! vault and cache directory can be written, the templates parse, GROBID is up, and the model
+//! backend takes the credentials and has the model. Every check that fails comes with a hint.
+//!
+//! Unlike `selftest`, which runs against mocks, this talks to the configured services, but it
+//! spends no tokens: the backends are only asked about the model.
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Config, LlmBackend},
+    llm::Llm,
+    prompt::Prompts,
+    render,
+    schema::Schema,
+    MabelError, Result,
+};
+
+/// How a check went.
+enum Outcome {
+    Passed(String),
+    /// Not applicable to this configuration or build
+    Skipped(String),
+    Failed {
+        why: String,
+        hint: String,
+    },
+}
+
+impl Outcome {
+    fn of(result: Result<String>, hint: impl FnOnce(&MabelError) -> String) -> Self {
+        match result {
+            | Ok(detail) => Self::Passed(detail),
+            | Err(e) => {
+                Self::Failed {
+                    hint: hint(&e),
+                    why: e.root().to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// `loaded` is the configuration, or why it did not load.
+pub async fn run(loaded: Result<Config>) -> Result<()> {
+    let cfg = match loaded {
+        | Ok(cfg) => cfg,
+        | Err(e) => {
+            let hint = match &e {
+                | MabelError::MissingEnv { key } => {
+                    format!("set {key} in the environment or a .env file, or with `mabel config set`")
+                }
+                | _ => "fix the setting named above; `mabel config show` lists the settings".to_string(),
+            };
+            report(
+                "configuration",
+                &Outcome::Failed {
+                    why: e.root().to_string(),
+                    hint,
+                },
+            );
+            return Err(MabelError::DoctorFailed { failed: 1, total: 1 });
+        }
+    };
+
+    let checks = [
+        ("configuration", Outcome::Passed(config_files(&cfg))),
+        ("vault", vault(&cfg)),
+        ("cache directory", cache_dir(&cfg)),
+        ("note template", note_template(&cfg)),
+        ("prompt templates", prompt_templates(&cfg)),
+        ("frontmatter schema", schema(&cfg)),
+        ("GROBID", grobid(&cfg).await),
+        ("model", model(&cfg).await),
+    ];
+    let (mut failed, mut total) = (0, 0);
+    for (name, outcome) in &checks {
+        report(name, outcome);
+        match outcome {
+            | Outcome::Passed(_) => total += 1,
+            | Outcome::Skipped(_) => {}
+            | Outcome::Failed { .. } => {
+                total += 1;
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(MabelError::DoctorFailed { failed, total });
+    }
+    println!("all {total} checks passed");
+    Ok(())
+}
+
+fn report(name: &str, outcome: &Outcome) {
+    match outcome {
+        | Outcome::Passed(detail) => println!("ok    {name}: {detail}"),
+        | Outcome::Skipped(why) => println!("skip  {name}: {why}"),
+        | Outcome::Failed { why, hint } => {
+            println!("FAIL  {name}: {why}");
+            println!("      hint: {hint}");
+        }
+    }
+}
+
+fn config_files(cfg: &Config) -> String {
+    let profile = cfg
+        .profile
+        .as_ref()
+        .map(|p| format!(" (profile {p})"))
+        .unwrap_or_default();
+    if cfg.config_files.is_empty() {
+        return format!("no config files; settings come from the environment{profile}");
+    }
+    let files: Vec<String> = cfg.config_files.iter().map(|p| p.display().to_string()).collect();
+    format!("read {}{profile}", files.join(", "))
+}
+
+fn vault(cfg: &Config) -> Outcome {
+    if cfg.vault_path.as_os_str().is_empty() {
+        return Outcome::Failed {
+            why: "OBSIDIAN_VAULT_PATH is not set".to_string(),
+            hint: "set it, or `vault_path` with `mabel config set`, to your vault's folder".to_string(),
+        };
+    }
+    Outcome::of(writable(&cfg.vault_path), |_| {
+        "check the folder is your vault and that you can write to it (OBSIDIAN_VAULT_PATH)".to_string()
+    })
+}
+
+fn cache_dir(cfg: &Config) -> Outcome {
+    Outcome::of(writable(&cfg.cache_dir), |_| {
+        "set MABEL_CACHE_DIR to a folder you can write to".to_string()
+    })
+}
+
+/// Write and remove a file in `dir`.
+fn writable(dir: &Path) -> Result<String> {
+    let probe = dir.join(format!(".mabel-doctor-{}", std::process::id()));
+    let io_err = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| MabelError::Io { path, source }
+    };
+    if !dir.is_dir() {
+        return Err(MabelError::Io {
+            path: dir.to_path_buf(),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "no such folder"),
+        });
+    }
+    std::fs::write(&probe, b"").map_err(io_err(&probe))?;
+    std::fs::remove_file(&probe).map_err(io_err(&probe))?;
+    Ok(format!("{} is writable", dir.display()))
+}
+
+fn note_template(cfg: &Config) -> Outcome {
+    let templates: Vec<&PathBuf> = [
+        Some(&cfg.template_path),
+        cfg.moc_template.as_ref(),
+        cfg.concept_template.as_ref(),
+        cfg.thread_template.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let result = render::from_config(cfg).map(|_| {
+        let names: Vec<String> = templates.iter().map(|p| p.display().to_string()).collect();
+        if cfg.template_path.exists() {
+            format!("{} parses", names.join(", "))
+        } else {
+            "the built-in template parses".to_string()
+        }
+    });
+    Outcome::of(result, |e| {
+        match e {
+            | MabelError::TemplateMissing { .. } => {
+                "pass an existing file to --template (MABEL_TEMPLATE), or unset it for the built-in one".to_string()
+            }
+            | _ => "fix the template; `mabel template show` prints the built-in one to start from".to_string(),
+        }
+    })
+}
+
+fn prompt_templates(cfg: &Config) -> Outcome {
+    if cfg.prompt_dir.is_none() && cfg.prompt_template.is_none() {
+        return Outcome::Skipped("the built-in prompts are used".to_string());
+    }
+    Outcome::of(Prompts::from_config(cfg).map(|_| "they parse".to_string()), |_| {
+        "fix the templates in MABEL_PROMPT_DIR or MABEL_PROMPT_TEMPLATE".to_string()
+    })
+}
+
+fn schema(cfg: &Config) -> Outcome {
+    let Some(path) = &cfg.frontmatter_schema else {
+        return Outcome::Skipped("MABEL_FRONTMATTER_SCHEMA is not set".to_string());
+    };
+    Outcome::of(Schema::load(path).map(|_| format!("{} parses", path.display())), |_| {
+        "fix the schema file, or unset MABEL_FRONTMATTER_SCHEMA".to_string()
+    })
+}
+
+#[cfg_attr(not(feature = "grobid"), allow(clippy::unused_async))]
+async fn grobid(cfg: &Config) -> Outcome {
+    let Some(url) = &cfg.grobid_url else {
+        return Outcome::Skipped("GROBID_URL is not set; PDFs are read with the built-in extractor".to_string());
+    };
+    #[cfg(feature = "grobid")]
+    {
+        let hint = "start GROBID (it listens on port 8070) or check GROBID_URL; unset it to read PDFs with the \
+                    built-in extractor";
+        let alive = async {
+            match crate::extract::grobid::is_alive(cfg, url).await {
+                | Ok(true) => Outcome::Passed(format!("{url} is alive")),
+                | Ok(false) => {
+                    Outcome::Failed {
+                        why: format!("{url} is up but not ready for documents yet"),
+                        hint: "wait for GROBID to finish starting, then run this again".to_string(),
+                    }
+                }
+                | Err(e) => Outcome::of(Err(e), |_| hint.to_string()),
+            }
+        };
+        within(cfg, alive, hint).await
+    }
+    #[cfg(not(feature = "grobid"))]
+    Outcome::Skipped(format!(
+        "this build has no GROBID support (`--features grobid`), so {url} is not used"
+    ))
+}
+
+async fn model(cfg: &Config) -> Outcome {
+    let hint = match &cfg.llm {
+        | LlmBackend::OpenAi { base_url: None, .. } => "check OPENAI_API_KEY and OPENAI_MODEL".to_string(),
+        | LlmBackend::OpenAi { .. } => "check OPENAI_BASE_URL, OPENAI_API_KEY and OPENAI_MODEL".to_string(),
+        | LlmBackend::Ollama { model, .. } => {
+            format!("start the server with `ollama serve` or check OLLAMA_HOST, then `ollama pull {model}`")
+        }
+        | LlmBackend::Anthropic { .. } => "check ANTHROPIC_API_KEY and ANTHROPIC_MODEL".to_string(),
+        | LlmBackend::Gemini { .. } => "check GEMINI_API_KEY and GEMINI_MODEL".to_string(),
+    };
+    let llm = match Llm::from_config(cfg) {
+        | Ok(llm) => llm,
+        | Err(e) => return Outcome::of(Err(e), |_| hint),
+    };
+    let check = async {
+        let answered = llm
+            .check()
+            .await
+            .map(|()| format!("{} answers on {}", llm.model(), cfg.llm.name()));
+        Outcome::of(answered, |_| hint.clone())
+    };
+    within(cfg, check, &hint).await
+}
+
+/// Run a check that asks a server, giving up after the HTTP timeout (`MABEL_HTTP_TIMEOUT_SECS`).
+async fn within(cfg: &Config, check: impl Future<Output = Outcome>, hint: &str) -> Outcome {
+    match tokio::time::timeout(cfg.http_timeout, check).await {
+        | Ok(outcome) => outcome,
+        | Err(_) => {
+            Outcome::Failed {
+                why: format!("no answer within {} seconds", cfg.http_timeout.as_secs()),
+                hint: hint.to_string(),
+            }
+        }
+    }
+}
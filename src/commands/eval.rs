@@ -0,0 +1,197 @@
+//! `mabel eval --prompts a.tera,b.tera --corpus fixtures/`
+//!
+//! Runs every prompt over every corpus document, has a judge model score each output against a
+//! rubric, and writes `results.csv` (one row per document × prompt) plus a Markdown `report.md`
+//! with per-prompt averages.
+
+use std::{fmt::Write, path::Path};
+
+use crate::{
+    cli::EvalArgs,
+    config::Config,
+    eval::{self, Criterion, Judgement},
+    llm::{Llm, Usage},
+    prompt, MabelError, Result,
+};
+
+struct Row {
+    document: String,
+    prompt: String,
+    judgement: Judgement,
+    usage: Usage,
+}
+
+pub async fn run(cfg: Config, args: &EvalArgs) -> Result<()> {
+    let rubric = match &args.rubric {
+        | Some(path) => eval::parse_rubric(&read(path).await?)?,
+        | None => eval::default_rubric(),
+    };
+    let mut prompts = Vec::with_capacity(args.prompts.len());
+    for path in &args.prompts {
+        prompts.push((prompt_label(path), read(path).await?));
+    }
+    let corpus = eval::load_corpus(&args.corpus).await?;
+
+    let llm = Llm::from_config(&cfg)?;
+    let judge = Llm::from_backend(
+        &cfg.llm.with_overrides(args.judge_model.as_deref(), Some(0.0)),
+        cfg.http_retries,
+    )?;
+
+    let mut rows = Vec::new();
+    for doc in &corpus {
+        for (label, template) in &prompts {
+            tracing::info!(document = %doc.name, prompt = %label, "evaluating");
+            let candidate = llm
+                .complete(&prompt::from_template(
+                    template,
+                    &cfg.mode,
+                    &doc.metadata,
+                    &doc.text,
+                    &cfg.vars,
+                )?)
+                .await?;
+            let verdict = judge
+                .complete(&prompt::judge(&rubric, &doc.text, &candidate.text))
+                .await?;
+            let mut usage = candidate.usage;
+            usage += verdict.usage;
+            rows.push(Row {
+                document: doc.name.clone(),
+                prompt: label.clone(),
+                judgement: eval::parse_judgement(&verdict.text, &rubric),
+                usage,
+            });
+        }
+    }
+
+    let out_dir = args.out.clone().unwrap_or_else(|| {
+        cfg.cache_dir
+            .join("evals")
+            .join(cfg.timezone.now().format("%Y%m%d-%H%M%S").to_string())
+    });
+    tokio::fs::create_dir_all(&out_dir).await.map_err(|source| {
+        MabelError::Io {
+            path: out_dir.clone(),
+            source,
+        }
+    })?;
+    write(&out_dir.join("results.csv"), &csv(&rubric, &rows)).await?;
+    let report = out_dir.join("report.md");
+    let labels: Vec<&str> = prompts.iter().map(|(l, _)| l.as_str()).collect();
+    write(&report, &markdown(&rubric, &labels, &rows)).await?;
+    println!("{}", report.display());
+    Ok(())
+}
+
+/// Prompts are labelled by file stem in the report.
+fn prompt_label(path: &Path) -> String {
+    path.file_stem()
+        .map_or_else(|| path.display().to_string(), |s| s.to_string_lossy().into_owned())
+}
+
+fn csv(rubric: &[Criterion], rows: &[Row]) -> String {
+    let mut out = String::from("document,prompt");
+    for c in rubric {
+        let _ = write!(out, ",{}", csv_field(&c.name));
+    }
+    out.push_str(",total,tokens,comment\n");
+    for row in rows {
+        let _ = write!(out, "{},{}", csv_field(&row.document), csv_field(&row.prompt));
+        for score in &row.judgement.scores {
+            let _ = write!(out, ",{}", score.map(|s| s.to_string()).unwrap_or_default());
+        }
+        let _ = writeln!(
+            out,
+            ",{},{},{}",
+            row.judgement.total(),
+            row.usage.total(),
+            csv_field(&row.judgement.comment)
+        );
+    }
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn markdown(rubric: &[Criterion], labels: &[&str], rows: &[Row]) -> String {
+    let mut out = String::from("# Prompt evaluation\n\n## Averages\n\n| Prompt |");
+    for c in rubric {
+        let _ = write!(out, " {} |", c.name);
+    }
+    out.push_str(" Total | Tokens |\n|---|");
+    out.push_str(&"---|".repeat(rubric.len() + 2));
+    out.push('\n');
+
+    for label in labels {
+        let mine: Vec<&Row> = rows.iter().filter(|r| r.prompt == *label).collect();
+        let _ = write!(out, "| {label} |");
+        for i in 0..rubric.len() {
+            let scores: Vec<f64> = mine
+                .iter()
+                .filter_map(|r| r.judgement.scores[i])
+                .map(f64::from)
+                .collect();
+            let _ = write!(out, " {} |", mean(&scores));
+        }
+        let totals: Vec<f64> = mine.iter().map(|r| f64::from(r.judgement.total())).collect();
+        let tokens: u64 = mine.iter().map(|r| r.usage.total()).sum();
+        let _ = writeln!(out, " {} | {tokens} |", mean(&totals));
+    }
+
+    out.push_str("\n## Per document (total score)\n\n| Document |");
+    for label in labels {
+        let _ = write!(out, " {label} |");
+    }
+    out.push_str("\n|---|");
+    out.push_str(&"---|".repeat(labels.len()));
+    out.push('\n');
+    let mut documents: Vec<&str> = rows.iter().map(|r| r.document.as_str()).collect();
+    documents.dedup();
+    for doc in documents {
+        let _ = write!(out, "| {doc} |");
+        for label in labels {
+            let total = rows
+                .iter()
+                .find(|r| r.document == doc && r.prompt == *label)
+                .map(|r| r.judgement.total().to_string())
+                .unwrap_or_default();
+            let _ = write!(out, " {total} |");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean(values: &[f64]) -> String {
+    if values.is_empty() {
+        return "-".to_string();
+    }
+    format!("{:.2}", values.iter().sum::<f64>() / values.len() as f64)
+}
+
+async fn read(path: &Path) -> Result<String> {
+    tokio::fs::read_to_string(path).await.map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+async fn write(path: &Path, contents: &str) -> Result<()> {
+    tokio::fs::write(path, contents).await.map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
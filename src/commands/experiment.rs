@@ -0,0 +1,121 @@
+//! `mabel experiment <input> --temps 0.0,0.7 --models a,b`
+//!
+//! Summarizes one paper once per model × temperature, writes every candidate note to a scratch
+//! folder and adds an `index.md` comparing them. Nothing is written to the vault.
+
+use std::fmt::Write;
+
+use crate::{
+    chunk,
+    cli::ExperimentArgs,
+    config::Config,
+    llm::{Llm, Usage},
+    note,
+    pipeline::{self, Pipeline},
+    summarize, MabelError, Result,
+};
+
+struct Candidate {
+    file: String,
+    model: String,
+    temperature: f32,
+    usage: Usage,
+    tldr: String,
+}
+
+pub async fn run(cfg: Config, args: &ExperimentArgs) -> Result<()> {
+    let models: Vec<Option<&str>> = if args.models.is_empty() {
+        vec![None]
+    } else {
+        args.models.iter().map(|m| Some(m.as_str())).collect()
+    };
+    let temps: Vec<Option<f32>> = if args.temps.is_empty() {
+        vec![None]
+    } else {
+        args.temps.iter().copied().map(Some).collect()
+    };
+
+    let pipeline = Pipeline::new(cfg)?;
+    let cfg = pipeline.config();
+    let paper = pipeline.resolve_paper(&args.input).await?;
+    let text = pipeline::paper_text(&paper);
+    let related = pipeline.related_notes(&paper).await;
+    let out_dir = args.out.clone().unwrap_or_else(|| {
+        let stamp = cfg.timezone.now().format("%Y%m%d-%H%M%S");
+        cfg.cache_dir
+            .join("experiments")
+            .join(format!("{}-{stamp}", note::file_stem(&paper.metadata.title)))
+    });
+    tokio::fs::create_dir_all(&out_dir).await.map_err(|source| {
+        MabelError::Io {
+            path: out_dir.clone(),
+            source,
+        }
+    })?;
+
+    let mut candidates = Vec::new();
+    for model in &models {
+        for temp in &temps {
+            let backend = cfg.llm.with_overrides(*model, *temp);
+            let llm = Llm::from_backend(&backend, cfg.http_retries)?;
+            tracing::info!(
+                model = backend.model(),
+                temperature = backend.temperature(),
+                "summarizing candidate"
+            );
+            let (summary, usage) = summarize::paper(
+                &llm,
+                pipeline.prompts(),
+                &paper.metadata,
+                &chunk::split(cfg, &llm, chunk::pace(cfg, llm.model()).await, &text),
+                &related,
+                &cfg.vars,
+                cfg.uncertainty,
+            )
+            .await?;
+            let rendered = pipeline.render_paper(&paper, &summary, &related, None, &args.input, backend.model())?;
+
+            let file = format!(
+                "{:02} {} t{}.md",
+                candidates.len() + 1,
+                note::file_stem(backend.model()),
+                backend.temperature()
+            );
+            note::write(&out_dir.join(&file), &rendered, true).await?;
+            candidates.push(Candidate {
+                file,
+                model: backend.model().to_string(),
+                temperature: backend.temperature(),
+                usage,
+                tldr: summary.tldr,
+            });
+        }
+    }
+
+    let index = out_dir.join("index.md");
+    note::write(&index, &comparison_index(&paper.metadata.title, &candidates), true).await?;
+    println!("{}", index.display());
+    Ok(())
+}
+
+/// A Markdown table linking every candidate, plus each TL;DR for a quick side-by-side read.
+fn comparison_index(title: &str, candidates: &[Candidate]) -> String {
+    let mut out =
+        format!("# Experiment: {title}\n\n| # | Model | Temperature | Tokens | Note |\n|---|---|---|---|---|\n");
+    for (i, c) in candidates.iter().enumerate() {
+        let stem = c.file.trim_end_matches(".md");
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | [[{stem}]] |",
+            i + 1,
+            c.model,
+            c.temperature,
+            c.usage.total()
+        );
+    }
+    out.push_str("\n## TL;DRs\n");
+    for (i, c) in candidates.iter().enumerate() {
+        let _ = write!(out, "\n**{}. {} @ {}**: {}\n", i + 1, c.model, c.temperature, c.tldr);
+    }
+    out
+}
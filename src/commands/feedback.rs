@@ -0,0 +1,27 @@
+//! `mabel feedback <id> --more|--less`: steer the recommendation digest.
+
+use crate::{
+    cli::FeedbackArgs,
+    config::Config,
+    http,
+    recommend::{self, Feedback},
+    source::arxiv::{ArxivId, ArxivResolver},
+    Result,
+};
+
+pub async fn run(cfg: &Config, args: &FeedbackArgs) -> Result<()> {
+    let id = ArxivId::parse(&args.id)?;
+    let paper = ArxivResolver::new(http::client(cfg)?, cfg).resolve(&id).await?;
+    let md = &paper.metadata;
+    let feedback = Feedback {
+        paper: id.base().to_string(),
+        title: md.title.clone(),
+        text: recommend::embedding_text(&md.title, md.abstract_text.as_deref()),
+        more: args.more,
+        given: cfg.timezone.timestamp(chrono::Utc::now()),
+    };
+    recommend::record_feedback(&cfg.feedback_path(), feedback).await?;
+    let direction = if args.more { "more" } else { "fewer" };
+    println!("{direction} papers like \"{}\" from now on", md.title);
+    Ok(())
+}
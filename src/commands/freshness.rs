@@ -0,0 +1,267 @@
+//! `mabel freshness`: the paper notes due for a refresh, most urgent first, into `To refresh.md`.
+//!
+//! A note is due when arXiv has a newer version of its paper than the one it was written from,
+//! when it was written with older summary prompts (see [`crate::prompt::VERSION`]), or when its
+//! citation count was last looked up more than `--months` ago. The arXiv check is one metadata
+//! request per paper, several at once; the rest comes from the registry.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Months, NaiveDate};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    cli::FreshnessArgs,
+    config::Config,
+    http,
+    note::{self, Overwrite},
+    prompt,
+    region::RegionMarkers,
+    registry::{Entry, Registry},
+    source::arxiv::{ArxivId, ArxivResolver},
+    vault, Result,
+};
+
+/// Region holding the generated list, so notes the user adds around it survive a rerun.
+const REGION: &str = "freshness";
+
+/// Why a note is due for a refresh.
+enum Reason {
+    /// arXiv has version `to`; the note is from version `from`
+    NewVersion { from: u32, to: u32 },
+    /// Written with an older prompt version; `None` when the registry does not say which
+    Prompt(Option<u32>),
+    /// The citation count was last looked up on this day; `None` when the registry does not say
+    Citations(Option<NaiveDate>),
+}
+
+impl Reason {
+    /// How much the reason counts towards a note's place in the report: a new version can change
+    /// what the paper says, new prompts only how the note says it.
+    fn weight(&self) -> u32 {
+        match self {
+            | Self::NewVersion { .. } => 3,
+            | Self::Prompt(_) => 2,
+            | Self::Citations(_) => 1,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            | Self::NewVersion { from, to } => format!("arXiv has v{to}; the note is from v{from}"),
+            | Self::Prompt(Some(version)) => {
+                format!("written with prompt version {version} (now {})", prompt::VERSION)
+            }
+            | Self::Prompt(None) => "written before prompt versions were recorded".to_string(),
+            | Self::Citations(Some(day)) => format!("citation count from {day}"),
+            | Self::Citations(None) => "citation count of unknown age".to_string(),
+        }
+    }
+
+    /// Whether refreshing means processing the paper again, rather than `mabel refresh-citations`.
+    fn rewrites(&self) -> bool {
+        !matches!(self, Self::Citations(_))
+    }
+}
+
+/// A note due for a refresh.
+struct Stale<'a> {
+    entry: &'a Entry,
+    reasons: Vec<Reason>,
+}
+
+impl Stale<'_> {
+    fn priority(&self) -> u32 {
+        self.reasons.iter().map(Reason::weight).sum()
+    }
+
+    /// What to pass to `mabel note` to write the note again: the arXiv id without a version, so
+    /// the newest is fetched, else what the paper was processed from.
+    fn source(&self) -> String {
+        self.entry
+            .arxiv
+            .as_deref()
+            .and_then(|a| ArxivId::parse(a).ok())
+            .map_or_else(|| self.entry.source.clone(), |id| id.base().to_string())
+    }
+}
+
+pub async fn run(cfg: &Config, args: &FreshnessArgs) -> Result<()> {
+    let registry = Registry::load(&cfg.registry_path())?;
+    let present: Vec<(&String, &Entry)> = registry
+        .papers
+        .iter()
+        .filter(|(_, entry)| cfg.vault_path.join(&entry.note).is_file())
+        .collect();
+    let newest = if args.no_arxiv {
+        HashMap::new()
+    } else {
+        newest_versions(cfg, args, &present).await?
+    };
+    let (dir, fields) = (cfg.vault_notes_dir(), cfg.frontmatter.clone());
+    let cited: HashSet<PathBuf> = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|n| n.frontmatter.citations.is_some())
+        .map(|n| n.path)
+        .collect();
+    let today = cfg.timezone.today();
+    let cutoff = today.checked_sub_months(Months::new(args.months)).unwrap_or(today);
+
+    let mut stale = Vec::new();
+    for (key, entry) in present {
+        let mut reasons = Vec::new();
+        let from = entry.arxiv_version.or_else(|| {
+            entry
+                .arxiv
+                .as_deref()
+                .and_then(|a| ArxivId::parse(a).ok())
+                .and_then(|id| id.version())
+        });
+        if let (Some(from), Some(&to)) = (from, newest.get(key)) {
+            if from < to {
+                reasons.push(Reason::NewVersion { from, to });
+            }
+        }
+        // Adopted notes were written by hand, with no prompt at all.
+        if !entry.model.is_empty() && entry.prompt_version.is_none_or(|v| v < prompt::VERSION) {
+            reasons.push(Reason::Prompt(entry.prompt_version));
+        }
+        if cited.contains(&cfg.vault_path.join(&entry.note)) {
+            let checked = entry
+                .citations_checked
+                .as_deref()
+                .and_then(|c| DateTime::parse_from_rfc3339(c).ok())
+                .map(|t| t.date_naive());
+            if checked.is_none_or(|day| day < cutoff) {
+                reasons.push(Reason::Citations(checked));
+            }
+        }
+        if !reasons.is_empty() {
+            stale.push(Stale { entry, reasons });
+        }
+    }
+    // Most urgent first; of equally urgent notes, the oldest.
+    stale.sort_by(|a, b| {
+        b.priority()
+            .cmp(&a.priority())
+            .then_with(|| a.entry.processed.cmp(&b.entry.processed))
+    });
+
+    if args.print {
+        for s in &stale {
+            let reasons: Vec<String> = s.reasons.iter().map(Reason::describe).collect();
+            println!("{}\t{}\t{}", s.priority(), s.entry.note.display(), reasons.join("; "));
+        }
+        return Ok(());
+    }
+    let checks = Checks {
+        arxiv: !args.no_arxiv,
+        cutoff,
+    };
+    let text = cfg.frontmatter_style.apply(&report(
+        &cfg.timezone.timestamp(chrono::Utc::now()),
+        &checks,
+        &stale,
+        &cfg.region_markers,
+    ));
+    let path = cfg.freshness_report_path();
+    note::write_managed(&path, &text, Overwrite::Merge, &cfg.region_markers).await?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// The newest arXiv version of each paper in `papers` that is on arXiv, by registry key. Papers
+/// whose check fails are left out, with a warning.
+async fn newest_versions(
+    cfg: &Config,
+    args: &FreshnessArgs,
+    papers: &[(&String, &Entry)],
+) -> Result<HashMap<String, u32>> {
+    let http = http::client(cfg)?;
+    let limit = Arc::new(Semaphore::new(args.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (key, entry) in papers {
+        let Some(id) = entry.arxiv.as_deref().and_then(|a| ArxivId::parse(a).ok()) else {
+            continue;
+        };
+        // Without a version, arXiv answers with the newest.
+        let id = ArxivId::parse(id.base())?;
+        let (resolver, limit, key) = (ArxivResolver::new(http.clone(), cfg), limit.clone(), (*key).clone());
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            (key, resolver.resolve(&id).await)
+        });
+    }
+    let mut newest = HashMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((key, resolved)) = joined else {
+            continue;
+        };
+        match resolved {
+            | Ok(paper) => {
+                let version = paper
+                    .metadata
+                    .arxiv_id
+                    .as_deref()
+                    .and_then(|a| ArxivId::parse(a).ok())
+                    .and_then(|id| id.version());
+                if let Some(version) = version {
+                    newest.insert(key, version);
+                }
+            }
+            | Err(e) => tracing::warn!(paper = %key, error = %e, "arXiv version check failed"),
+        }
+    }
+    Ok(newest)
+}
+
+/// What the report checked for.
+struct Checks {
+    arxiv: bool,
+    /// Citation counts looked up before this day are due
+    cutoff: NaiveDate,
+}
+
+fn report(created: &str, checks: &Checks, stale: &[Stale<'_>], markers: &RegionMarkers) -> String {
+    let mut out = format!(
+        "---\ntype: freshness\ncreated: {}\n---\n\n# To refresh\n\n",
+        serde_json::Value::from(created)
+    );
+    out.push_str(&markers.begin(REGION));
+    out.push('\n');
+    let arxiv = if checks.arxiv { "newer arXiv versions, " } else { "" };
+    let _ = writeln!(
+        out,
+        "\nChecked for {arxiv}prompts older than the current version ({}) and citation counts from before {}.\n",
+        prompt::VERSION,
+        checks.cutoff
+    );
+    if stale.is_empty() {
+        out.push_str("Every note is up to date.\n");
+    }
+    for (i, s) in stale.iter().enumerate() {
+        let link = s.entry.note.file_stem().unwrap_or_default().to_string_lossy();
+        let processed = s.entry.processed.get(..10).unwrap_or(&s.entry.processed);
+        let _ = writeln!(out, "{}. [[{link}]] · processed {processed}", i + 1);
+        for reason in &s.reasons {
+            let _ = writeln!(out, "   - {}", reason.describe());
+        }
+        let refresh = if s.reasons.iter().any(Reason::rewrites) {
+            format!("mabel note {} --overwrite", s.source())
+        } else {
+            "mabel refresh-citations".to_string()
+        };
+        let _ = writeln!(out, "   - refresh: `{refresh}`");
+    }
+    out.push('\n');
+    out.push_str(&markers.end(REGION));
+    out.push('\n');
+    out
+}
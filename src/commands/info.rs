@@ -0,0 +1,55 @@
+//! `mabel info <id>`: what the paper index has on a paper, to see whether (and how) it was
+//! processed before running it again, and where the last run's time and money went.
+
+use crate::{cli::InfoArgs, config::Config, index::Index, registry::Registry, Result};
+
+pub fn run(cfg: &Config, args: &InfoArgs) -> Result<()> {
+    let papers = Index::open(&cfg.index_path())?.find(&args.id)?;
+    if papers.is_empty() {
+        println!(
+            "{} is not in the index; it has not been processed on this machine",
+            args.id.trim()
+        );
+        return Ok(());
+    }
+    let registry = Registry::load(&cfg.registry_path())?;
+    for (i, paper) in papers.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let note = if paper.note.is_file() {
+            paper.note.display().to_string()
+        } else {
+            format!("{} (no longer there)", paper.note.display())
+        };
+        let rows = [
+            ("title", paper.title.clone()),
+            ("arxiv", paper.arxiv.clone().unwrap_or_else(|| "-".to_string())),
+            ("doi", paper.doi.clone().unwrap_or_else(|| "-".to_string())),
+            ("note", note),
+            ("processed", paper.processed.clone()),
+            ("model", paper.model.clone()),
+            (
+                "tokens",
+                format!(
+                    "{} ({} prompt, {} completion)",
+                    paper.usage.total(),
+                    paper.usage.prompt_tokens,
+                    paper.usage.completion_tokens
+                ),
+            ),
+            (
+                "last run",
+                registry
+                    .papers
+                    .get(&paper.key)
+                    .and_then(|e| e.report.as_ref())
+                    .map_or_else(|| "-".to_string(), ToString::to_string),
+            ),
+        ];
+        for (key, value) in rows {
+            println!("{key:<9}  {value}");
+        }
+    }
+    Ok(())
+}
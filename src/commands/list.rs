@@ -0,0 +1,23 @@
+//! `mabel list [--limit N]`: the papers processed on this machine, from the paper index, most
+//! recent first.
+
+use crate::{cli::ListArgs, config::Config, index::Index, Result};
+
+pub fn run(cfg: &Config, args: &ListArgs) -> Result<()> {
+    let mut papers = Index::open(&cfg.index_path())?.list()?;
+    if papers.is_empty() {
+        println!("no papers processed yet");
+        return Ok(());
+    }
+    papers.truncate(args.limit.unwrap_or(usize::MAX));
+    let ids: Vec<&str> = papers
+        .iter()
+        .map(|p| p.arxiv.as_deref().or(p.doi.as_deref()).unwrap_or(&p.key))
+        .collect();
+    let width = ids.iter().map(|id| id.chars().count()).max().unwrap_or(0);
+    for (paper, id) in papers.iter().zip(ids) {
+        let date = paper.processed.get(..10).unwrap_or(&paper.processed);
+        println!("{date}  {id:<width$}  {}", paper.title);
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! One module per `mabel` subcommand.
+
+use crate::{
+    cli::{Cli, Command},
+    config::Config,
+    limits, Result,
+};
+
+pub mod adopt;
+pub mod annotate;
+pub mod batch;
+pub mod bench;
+pub mod bibliography;
+pub mod brief;
+pub mod cache;
+pub mod citations;
+pub mod cite;
+pub mod claims;
+pub mod config;
+#[cfg(feature = "embeddings")]
+pub mod digest;
+pub mod doctor;
+pub mod eval;
+pub mod experiment;
+pub mod feedback;
+pub mod freshness;
+pub mod info;
+pub mod list;
+pub mod note;
+pub mod plugin;
+pub mod queue;
+pub mod read;
+pub mod refactor;
+pub mod search;
+#[cfg(feature = "test-server")]
+pub mod selftest;
+#[cfg(feature = "server")]
+pub mod serve;
+#[cfg(feature = "server")]
+pub mod service;
+#[cfg(feature = "state")]
+pub mod state;
+pub mod template;
+pub mod update;
+pub mod watch;
+
+/// Load the configuration and run the selected subcommand.
+pub async fn run(cli: &Cli) -> Result<()> {
+    let cfg = match Config::load(cli) {
+        | Ok(cfg) => cfg,
+        // `doctor` reports a configuration that does not load instead of stopping at it.
+        | Err(e) if matches!(cli.command, Command::Doctor) => return doctor::run(Err(e)).await,
+        | Err(e) => return Err(e),
+    };
+    limits::init(&cfg);
+    match &cli.command {
+        | Command::Note(args) => note::run(cfg, args).await,
+        | Command::Batch(args) => batch::run(cfg, args).await,
+        | Command::Watch(args) => watch::run(cfg, args).await,
+        | Command::FlushQueue(args) => queue::run(cfg, args).await,
+        | Command::Search(args) => search::run(&cfg, args),
+        | Command::List(args) => list::run(&cfg, args),
+        | Command::Info(args) => info::run(&cfg, args),
+        | Command::Update(args) => update::run(&cfg, args).await,
+        | Command::RefreshCitations(args) => citations::run(&cfg, args).await,
+        | Command::Freshness(args) => freshness::run(&cfg, args).await,
+        | Command::Annotate(args) => annotate::run(&cfg, args).await,
+        | Command::Read(args) => read::run(cfg, args).await,
+        | Command::Adopt(args) => adopt::run(&cfg, args).await,
+        | Command::Claims { action } => claims::run(cfg, action).await,
+        #[cfg(feature = "embeddings")]
+        | Command::Digest(args) => digest::run(&cfg, args).await,
+        #[cfg(not(feature = "embeddings"))]
+        | Command::Digest(_) => Err(not_built("recommendations", "embeddings")),
+        | Command::Feedback(args) => feedback::run(&cfg, args).await,
+        | Command::Cite(args) => cite::run(&cfg, args).await,
+        | Command::Bibliography(args) => bibliography::run(&cfg, args).await,
+        | Command::Brief(args) => brief::run(&cfg, args).await,
+        | Command::Refactor { action } => refactor::run(&cfg, action),
+        | Command::Cache { action } => cache::run(&cfg, action),
+        #[cfg(feature = "state")]
+        | Command::State { action } => state::run(&cfg, action),
+        #[cfg(not(feature = "state"))]
+        | Command::State { .. } => Err(not_built("state export and import", "state")),
+        | Command::Config { action } => config::run(&cfg, action),
+        | Command::Template { action } => template::run(&cfg, action),
+        #[cfg(feature = "server")]
+        | Command::Serve(args) => serve::run(cfg, args).await,
+        #[cfg(feature = "server")]
+        | Command::Service { action } => service::run(&cfg, action),
+        #[cfg(not(feature = "server"))]
+        | Command::Serve(_) | Command::Service { .. } => Err(not_built("server", "server")),
+        | Command::Experiment(args) => experiment::run(cfg, args).await,
+        | Command::Eval(args) => eval::run(cfg, args).await,
+        | Command::Bench { action } => bench::run(&cfg, action).await,
+        #[cfg(feature = "test-server")]
+        | Command::Selftest(args) => selftest::run(&cfg, args).await,
+        #[cfg(not(feature = "test-server"))]
+        | Command::Selftest(_) => {
+            Err(crate::MabelError::Config {
+                msg: "this build has no mock server to test against (`--features test-server`)".to_string(),
+            })
+        }
+        | Command::Doctor => doctor::run(Ok(cfg)).await,
+        | Command::Plugins => plugin::list(),
+        | Command::External(args) => plugin::run(&cfg, args).await,
+    }
+}
+
+/// The error for a command whose cargo `feature` this build was compiled without.
+#[cfg(not(all(feature = "embeddings", feature = "state", feature = "server")))]
+fn not_built(what: &str, feature: &str) -> crate::MabelError {
+    crate::MabelError::Config {
+        msg: format!("this build has no {what} (`--features {feature}`)"),
+    }
+}
@@ -0,0 +1,81 @@
+//! `mabel note <input>`
+
+use serde::Serialize;
+
+use crate::{
+    ci,
+    cli::{NoteArgs, OutputFormat},
+    config::Config,
+    cost, http,
+    output::{Record, Status},
+    paper::PaperMetadata,
+    pipeline::Pipeline,
+    source::{Input, ResolvedPaper},
+    Result,
+};
+
+/// What `note --json` prints: the resolved metadata plus what a full run would have to work with.
+#[derive(Debug, Serialize)]
+struct MetadataReport<'a> {
+    #[serde(flatten)]
+    metadata: &'a PaperMetadata,
+    pdf_url: Option<String>,
+    /// Whether structured full text (e.g. PMC JATS) is available without PDF extraction
+    full_text: bool,
+}
+
+pub async fn run(cfg: Config, args: &NoteArgs) -> Result<()> {
+    if args.json {
+        return print_metadata(&cfg, &args.input).await;
+    }
+
+    let fail_on = cfg.ci.then_some(cfg.fail_on);
+    let pipeline = Pipeline::new(cfg)?;
+    let (outcome, logged) = ci::collect(Box::pin(pipeline.run(&args.input))).await;
+    let json = args.output.format == OutputFormat::Json;
+    let outcome = match outcome {
+        | Ok(outcome) => outcome,
+        | Err(e) => {
+            if json {
+                Record::failed(&args.input, Status::of(&e), &e, &logged).print();
+            }
+            return Err(e);
+        }
+    };
+    tracing::info!(
+        title = %outcome.title,
+        tokens = outcome.usage.total(),
+        cost = outcome.cost.map(cost::format),
+        "note written"
+    );
+    let checked = fail_on.map_or(Ok(()), |severity| severity.check(&logged));
+    if !json {
+        println!("{}", outcome.path.display());
+        return checked;
+    }
+    let written = Record::written(&args.input, &outcome, &logged);
+    match &checked {
+        | Ok(()) => written.print(),
+        | Err(e) => {
+            Record {
+                status: Status::Failed,
+                error: Some(e.to_string()),
+                ..written
+            }
+            .print();
+        }
+    }
+    checked
+}
+
+async fn print_metadata(cfg: &Config, input: &str) -> Result<()> {
+    let http = http::client(cfg)?;
+    let paper: ResolvedPaper = Input::parse(input)?.resolve(cfg, &http).await?;
+    let report = MetadataReport {
+        metadata: &paper.metadata,
+        pdf_url: paper.pdf_url.as_ref().map(ToString::to_string),
+        full_text: paper.structure.as_ref().is_some_and(|s| !s.sections.is_empty()),
+    };
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
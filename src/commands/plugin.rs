@@ -0,0 +1,130 @@
+//! External subcommands, cargo-style: `mabel foo --bar` runs `mabel-foo --bar` from PATH.
+//!
+//! Plugins get the resolved configuration through the environment, so they do not have to
+//! re-implement flag/env/default resolution:
+//! - `MABEL_CONFIG_JSON`: the whole configuration as JSON (secrets omitted)
+//! - `MABEL_VAULT_PATH`, `MABEL_NOTES_DIR`, `MABEL_CACHE_DIR`: the most commonly needed paths
+//!
+//! Stdin, stdout and stderr are inherited, and mabel exits with the plugin's exit code.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{config::Config, MabelError, Result};
+
+const PREFIX: &str = "mabel-";
+
+/// The configuration as handed to plugins.
+#[derive(Debug, Serialize)]
+struct PluginConfig<'a> {
+    version: &'static str,
+    vault_path: &'a Path,
+    notes_dir: PathBuf,
+    cache_dir: &'a Path,
+    llm_backend: &'static str,
+    llm_model: &'a str,
+    mode: &'static str,
+    timezone: String,
+    template_path: &'a Path,
+    grobid_url: Option<String>,
+}
+
+impl<'a> PluginConfig<'a> {
+    fn new(cfg: &'a Config) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            vault_path: &cfg.vault_path,
+            notes_dir: cfg.vault_notes_dir(),
+            cache_dir: &cfg.cache_dir,
+            llm_backend: cfg.llm.name(),
+            llm_model: cfg.llm.model(),
+            mode: cfg.mode.as_str(),
+            timezone: cfg.timezone.to_string(),
+            template_path: &cfg.template_path,
+            grobid_url: cfg.grobid_url.as_ref().map(ToString::to_string),
+        }
+    }
+}
+
+/// `mabel <name> [args...]` for a name that is not a built-in command.
+pub async fn run(cfg: &Config, args: &[String]) -> Result<()> {
+    let (name, rest) = args.split_first().ok_or_else(|| {
+        MabelError::Config {
+            msg: "missing subcommand".to_string(),
+        }
+    })?;
+    let exe = discover().remove(name.as_str()).ok_or_else(|| {
+        MabelError::Config {
+            msg: format!("no such command `{name}` (and no `{PREFIX}{name}` plugin on PATH)"),
+        }
+    })?;
+
+    let status = tokio::process::Command::new(&exe)
+        .args(rest)
+        .env("MABEL_CONFIG_JSON", serde_json::to_string(&PluginConfig::new(cfg))?)
+        .env("MABEL_VAULT_PATH", &cfg.vault_path)
+        .env("MABEL_NOTES_DIR", cfg.vault_notes_dir())
+        .env("MABEL_CACHE_DIR", &cfg.cache_dir)
+        .status()
+        .await
+        .map_err(|source| {
+            MabelError::Io {
+                path: exe.clone(),
+                source,
+            }
+        })?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// `mabel plugins`
+pub fn list() -> Result<()> {
+    for (name, path) in discover() {
+        println!("{name:<20} {}", path.display());
+    }
+    Ok(())
+}
+
+/// Every `mabel-*` executable on PATH, by plugin name. Earlier PATH entries win, as in a shell.
+fn discover() -> BTreeMap<String, PathBuf> {
+    let mut found = BTreeMap::new();
+    let Some(path) = env::var_os("PATH") else {
+        return found;
+    };
+    for dir in env::split_paths(&path) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let file_name = file_name.to_string_lossy();
+            let Some(name) = file_name.strip_prefix(PREFIX) else {
+                continue;
+            };
+            let name = name.strip_suffix(env::consts::EXE_SUFFIX).unwrap_or(name);
+            if !name.is_empty() && is_executable(&entry.path()) {
+                found.entry(name.to_string()).or_insert_with(|| entry.path());
+            }
+        }
+    }
+    found
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
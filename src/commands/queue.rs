@@ -0,0 +1,54 @@
+//! `mabel flush-queue`: download the PDFs of papers noted from their abstract on a metered
+//! connection and rewrite their notes from the full text. The managed regions are replaced, so
+//! anything written around them stays. Papers that fail stay queued for the next flush.
+
+use crate::{
+    cli::FlushQueueArgs,
+    config::Config,
+    llm::Usage,
+    note::Overwrite,
+    pipeline::Pipeline,
+    queue::{self, Queued},
+    Result,
+};
+
+pub async fn run(mut cfg: Config, args: &FlushQueueArgs) -> Result<()> {
+    let queued = queue::load(&cfg).await?;
+    if queued.is_empty() {
+        println!("the queue is empty");
+        return Ok(());
+    }
+    if args.list {
+        for Queued {
+            title, note, queued, ..
+        } in &queued
+        {
+            println!("{queued}  {title}  ({})", note.display());
+        }
+        return Ok(());
+    }
+    // Flushing is for when the connection is good again, and the notes are there to be replaced.
+    cfg.metered = false;
+    cfg.overwrite_note = Overwrite::Merge;
+    let pipeline = Pipeline::new(cfg)?;
+    let (mut usage, mut failed) = (Usage::default(), 0);
+    for paper in &queued {
+        match pipeline.run(&paper.input).await {
+            | Ok(outcome) => {
+                usage += outcome.usage;
+                println!("{}", outcome.path.display());
+            }
+            | Err(e) => {
+                eprintln!("{e}");
+                failed += 1;
+            }
+        }
+    }
+    tracing::info!(
+        notes = queued.len() - failed,
+        failed,
+        tokens = usage.total(),
+        "queue flushed"
+    );
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! `mabel read <id>`: go through a paper a section at a time in the terminal. Each section is
+//! shown with a short explanation from the model, and questions typed at the prompt are answered
+//! on the section. When the session ends, its explanations and questions are added to the paper's
+//! note under a "Reading session" heading, outside the managed regions, so regenerating the note
+//! keeps them.
+
+use std::{fmt::Write as _, io::Write as _};
+
+use crate::{
+    cli::ReadArgs,
+    config::Config,
+    llm::{Llm, Prompt, Usage},
+    note,
+    paper::PaperStructure,
+    pipeline::Pipeline,
+    prompt, MabelError, Result,
+};
+
+/// A section as it went in the session.
+struct Read {
+    heading: String,
+    explanation: Option<String>,
+    /// Questions and their answers, in the order asked
+    asked: Vec<(String, String)>,
+}
+
+/// What the reader typed at the prompt.
+enum Reply {
+    Next,
+    Stop,
+    Question(String),
+}
+
+pub async fn run(cfg: Config, args: &ReadArgs) -> Result<()> {
+    if !cfg.can_ask() {
+        return Err(MabelError::Config {
+            msg: "`mabel read` needs a terminal to take questions, and cannot run with --ci".to_string(),
+        });
+    }
+    let pipeline = Pipeline::new(cfg)?;
+    let paper = pipeline.resolve_paper(&args.input).await?;
+    let metadata = &paper.metadata;
+    let note_path = match pipeline.existing_note(metadata) {
+        | Some(path) => Some(path),
+        | None if args.no_save => None,
+        | None => {
+            return Err(MabelError::Config {
+                msg: format!(
+                    "no note for {:?} yet: write it with `mabel note {}`, or pass --no-save",
+                    metadata.title, args.input
+                ),
+            });
+        }
+    };
+    let sections = sections(&paper.structure.unwrap_or_default());
+    if sections.is_empty() {
+        return Err(MabelError::Config {
+            msg: format!("no full text for {:?} to read section by section", metadata.title),
+        });
+    }
+    let start = match &args.from {
+        | Some(from) => {
+            let from = from.to_lowercase();
+            sections
+                .iter()
+                .position(|(heading, _)| heading.to_lowercase().contains(&from))
+                .ok_or_else(|| {
+                    MabelError::Config {
+                        msg: format!("no section heading in {:?} contains {from:?}", metadata.title),
+                    }
+                })?
+        }
+        | None => 0,
+    };
+
+    println!("{}\n", metadata.title);
+    eprintln!("Enter moves on to the next section and q stops; anything else is a question on the section.");
+    let llm = pipeline.llm();
+    let mut usage = Usage::default();
+    let mut session = Vec::new();
+    'sections: for (i, (heading, text)) in sections.iter().enumerate().skip(start) {
+        println!("\n[{}/{}] {heading}\n\n{text}\n", i + 1, sections.len());
+        let explanation = complete(llm, &prompt::section(metadata, heading, text), &mut usage).await;
+        if let Some(explanation) = &explanation {
+            println!("{explanation}\n");
+        }
+        let mut read = Read {
+            heading: heading.clone(),
+            explanation,
+            asked: Vec::new(),
+        };
+        loop {
+            match reply().await {
+                | Reply::Next => break,
+                | Reply::Stop => {
+                    session.push(read);
+                    break 'sections;
+                }
+                | Reply::Question(question) => {
+                    let asked = prompt::section_question(metadata, heading, text, &read.asked, &question);
+                    if let Some(answer) = complete(llm, &asked, &mut usage).await {
+                        println!("\n{answer}\n");
+                        read.asked.push((question, answer));
+                    }
+                }
+            }
+        }
+        session.push(read);
+    }
+    tracing::info!(tokens = usage.total(), "reading session done");
+
+    let Some(path) = note_path.filter(|_| !args.no_save) else {
+        return Ok(());
+    };
+    let stamp = pipeline.config().timezone.now().format("%Y-%m-%d %H:%M").to_string();
+    let existing = tokio::fs::read_to_string(&path).await.map_err(|source| {
+        MabelError::Io {
+            path: path.clone(),
+            source,
+        }
+    })?;
+    let updated = format!("{}\n\n{}", existing.trim_end(), transcript(&stamp, &session));
+    note::write(&path, &updated, true).await?;
+    let vault = &pipeline.config().vault_path;
+    println!(
+        "session saved to {}",
+        path.strip_prefix(vault).unwrap_or(&path).display()
+    );
+    Ok(())
+}
+
+/// The paper's sections that have text, as heading and text, starting with the abstract.
+fn sections(structure: &PaperStructure) -> Vec<(String, String)> {
+    let abstract_text = structure
+        .abstract_text
+        .as_ref()
+        .map(|text| ("Abstract".to_string(), text.trim().to_string()));
+    abstract_text
+        .into_iter()
+        .chain(
+            structure
+                .sections
+                .iter()
+                .map(|s| (s.heading.clone(), s.text.trim().to_string())),
+        )
+        .filter(|(_, text)| !text.is_empty())
+        .collect()
+}
+
+/// The model's reply to `prompt`, or `None` with a warning when there is none; the session goes on
+/// either way.
+async fn complete(llm: &Llm, prompt: &Prompt, usage: &mut Usage) -> Option<String> {
+    match llm.complete(prompt).await {
+        | Ok(completion) => {
+            *usage += completion.usage;
+            Some(completion.text.trim().to_string()).filter(|text| !text.is_empty())
+        }
+        | Err(e) => {
+            tracing::warn!(error = %e.root(), "the model did not answer");
+            None
+        }
+    }
+}
+
+/// Read the reader's next line; the end of input stops the session.
+async fn reply() -> Reply {
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "? ");
+    let _ = stderr.flush();
+    let line = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .map(|read| (read > 0).then_some(line))
+    })
+    .await
+    .ok()
+    .and_then(std::result::Result::ok)
+    .flatten();
+    match line.as_deref().map(str::trim) {
+        | None | Some("q" | "quit") => Reply::Stop,
+        | Some("") => Reply::Next,
+        | Some(question) => Reply::Question(question.to_string()),
+    }
+}
+
+/// The session as a section of the note.
+fn transcript(stamp: &str, session: &[Read]) -> String {
+    let mut out = format!("## Reading session {stamp}\n");
+    for read in session {
+        let _ = write!(out, "\n### {}\n", read.heading);
+        if let Some(explanation) = &read.explanation {
+            let _ = write!(out, "\n{explanation}\n");
+        }
+        for (question, answer) in &read.asked {
+            let _ = write!(out, "\n**Q:** {question}\n\n{answer}\n");
+        }
+    }
+    out
+}
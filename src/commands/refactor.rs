@@ -0,0 +1,242 @@
+//! `mabel refactor rename-tag|rename-link`: keep tags and wikilinks consistent across the notes
+//! mabel manages. Without `--yes` the changes are only shown as a diff.
+//!
+//! Only notes mabel wrote are touched: those with managed regions, or `type: paper|book` in their
+//! frontmatter. Tags are rewritten in the frontmatter only; links anywhere outside code blocks.
+
+use std::{fmt::Write as _, path::PathBuf};
+
+use mabel_core::diff;
+use walkdir::WalkDir;
+
+use crate::{cli::RefactorAction, config::Config, vault::split_frontmatter, MabelError, Result};
+
+pub fn run(cfg: &Config, action: &RefactorAction) -> Result<()> {
+    match action {
+        | RefactorAction::RenameTag { old, new, yes } => {
+            let (old, new) = (clean_tag(old)?, clean_tag(new)?);
+            apply(cfg, *yes, |text| rename_tag(text, &old, &new))
+        }
+        | RefactorAction::RenameLink { old, new, yes } => {
+            let (old, new) = (clean_link(old)?, clean_link(new)?);
+            if !note_paths(cfg).iter().any(|p| stem_eq(p, &new)) {
+                tracing::warn!(link = %new, "no note with that name in the notes folder yet");
+            }
+            apply(cfg, *yes, |text| rename_link(text, &old, &new))
+        }
+    }
+}
+
+/// Run `rewrite` over every managed note and either print the diff or write the result.
+fn apply(cfg: &Config, yes: bool, rewrite: impl Fn(&str) -> String) -> Result<()> {
+    let mut changed = 0;
+    for path in note_paths(cfg) {
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if !is_managed(cfg, &text) {
+            continue;
+        }
+        let updated = rewrite(&text);
+        if updated == text {
+            continue;
+        }
+        changed += 1;
+        let shown = path
+            .strip_prefix(&cfg.vault_path)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        if yes {
+            std::fs::write(&path, updated).map_err(|source| MabelError::Io { path, source })?;
+            println!("updated {shown}");
+        } else {
+            print!("{}", diff::unified(&text, &updated, &shown));
+        }
+    }
+    match (changed, yes) {
+        | (0, _) => println!("no notes to change"),
+        | (n, true) => println!("{n} note(s) updated"),
+        | (n, false) => println!("{n} note(s) would change; pass --yes to write them"),
+    }
+    Ok(())
+}
+
+fn note_paths(cfg: &Config) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = WalkDir::new(cfg.vault_notes_dir())
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "md"))
+        .map(walkdir::DirEntry::into_path)
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn is_managed(cfg: &Config, text: &str) -> bool {
+    if !cfg.region_markers.names(text).is_empty() {
+        return true;
+    }
+    split_frontmatter(text).0.is_some_and(|yaml| {
+        yaml.lines()
+            .any(|l| matches!(l.trim_end(), "type: paper" | "type: book"))
+    })
+}
+
+fn stem_eq(path: &std::path::Path, name: &str) -> bool {
+    path.file_stem()
+        .is_some_and(|s| s.to_string_lossy().eq_ignore_ascii_case(name))
+}
+
+fn clean_tag(tag: &str) -> Result<String> {
+    let tag = tag.trim().trim_start_matches('#');
+    if tag.is_empty() || tag.contains(char::is_whitespace) || tag.contains(',') {
+        return Err(MabelError::Config {
+            msg: format!("not a valid tag: {tag:?}"),
+        });
+    }
+    Ok(tag.to_string())
+}
+
+fn clean_link(link: &str) -> Result<String> {
+    let link = link.trim().trim_start_matches("[[").trim_end_matches("]]");
+    let link = link.strip_suffix(".md").unwrap_or(link);
+    // Links are matched by note name; a folder prefix is kept as written in each link.
+    let link = link.rsplit('/').next().unwrap_or(link);
+    if link.is_empty() || link.contains(['|', '#', '^', '[', ']']) {
+        return Err(MabelError::Config {
+            msg: format!("not a valid link target: {link:?}"),
+        });
+    }
+    Ok(link.to_string())
+}
+
+/// `tag` renamed if it is `old` or nested under it (Obsidian tags are case-insensitive).
+fn renamed_tag(tag: &str, old: &str, new: &str) -> Option<String> {
+    if tag.eq_ignore_ascii_case(old) {
+        return Some(new.to_string());
+    }
+    let prefix = tag.get(..=old.len())?;
+    (prefix.ends_with('/') && prefix[..old.len()].eq_ignore_ascii_case(old))
+        .then(|| format!("{new}{}", &tag[old.len()..]))
+}
+
+/// Rename `old` in the frontmatter `tags`, in either flow (`tags: [a, b]`) or block (`- a`) style,
+/// dropping entries that become duplicates.
+fn rename_tag(text: &str, old: &str, new: &str) -> String {
+    let Some(yaml) = split_frontmatter(text).0 else {
+        return text.to_string();
+    };
+    let start = yaml.as_ptr() as usize - text.as_ptr() as usize;
+    let mut out_yaml = String::with_capacity(yaml.len());
+    let mut lines = yaml.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let Some(value) = line.strip_prefix("tags:") else {
+            out_yaml.push_str(line);
+            continue;
+        };
+        let value = value.trim();
+        if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            let items: Vec<&str> = inner.split(',').map(str::trim).filter(|i| !i.is_empty()).collect();
+            let rewritten = rewrite_items(&items, old, new);
+            if rewritten == items {
+                out_yaml.push_str(line);
+            } else {
+                let _ = writeln!(out_yaml, "tags: [{}]", rewritten.join(", "));
+            }
+        } else if value.is_empty() {
+            out_yaml.push_str(line);
+            let mut block = Vec::new();
+            while let Some(item) = lines.next_if(|l| l.trim_start().starts_with("- ")) {
+                block.push(item);
+            }
+            let indent = block.first().map_or("  ", |l| &l[..l.len() - l.trim_start().len()]);
+            let items: Vec<&str> = block.iter().map(|l| l.trim().trim_start_matches("- ").trim()).collect();
+            let rewritten = rewrite_items(&items, old, new);
+            if rewritten == items {
+                block.iter().for_each(|l| out_yaml.push_str(l));
+            } else {
+                for item in rewritten {
+                    let _ = writeln!(out_yaml, "{indent}- {item}");
+                }
+            }
+        } else if let Some(tag) = renamed_tag(value.trim_start_matches('#'), old, new) {
+            let _ = writeln!(out_yaml, "tags: {tag}");
+        } else {
+            out_yaml.push_str(line);
+        }
+    }
+    format!("{}{out_yaml}{}", &text[..start], &text[start + yaml.len()..])
+}
+
+/// Rename matching items, keeping any quotes, and drop duplicates created by the rename.
+fn rewrite_items(items: &[&str], old: &str, new: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::with_capacity(items.len());
+    let mut seen: Vec<String> = Vec::new();
+    for item in items {
+        let quote = item
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\'') && item.len() > 1 && item.ends_with(*c));
+        let bare = if quote.is_some() {
+            &item[1..item.len() - 1]
+        } else {
+            item
+        };
+        let hash = if bare.starts_with('#') { "#" } else { "" };
+        let tag = bare.trim_start_matches('#');
+        let renamed = renamed_tag(tag, old, new);
+        let key = renamed.as_deref().unwrap_or(tag).to_lowercase();
+        if seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+        out.push(match (renamed, quote) {
+            | (Some(t), Some(q)) => format!("{q}{hash}{t}{q}"),
+            | (Some(t), None) => format!("{hash}{t}"),
+            | (None, _) => (*item).to_string(),
+        });
+    }
+    out
+}
+
+/// Point `[[old]]`, `[[old|alias]]`, `[[old#heading]]`, `![[old]]` and `[[folder/old]]` at `new`,
+/// leaving fenced code blocks alone.
+fn rename_link(text: &str, old: &str, new: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        if in_fence || !line.contains("[[") {
+            out.push_str(line);
+            continue;
+        }
+        let mut rest = line;
+        while let Some(open) = rest.find("[[") {
+            let Some(close) = rest[open + 2..].find("]]") else {
+                break;
+            };
+            let inner = &rest[open + 2..open + 2 + close];
+            let target_len = inner.find(['|', '#', '^']).unwrap_or(inner.len());
+            let (target, tail) = inner.split_at(target_len);
+            let (folder, name) = target.rsplit_once('/').map_or(("", target), |(f, n)| (f, n));
+            out.push_str(&rest[..open + 2]);
+            if name.trim().eq_ignore_ascii_case(old) {
+                if !folder.is_empty() {
+                    out.push_str(folder);
+                    out.push('/');
+                }
+                out.push_str(new);
+                out.push_str(tail);
+            } else {
+                out.push_str(inner);
+            }
+            out.push_str("]]");
+            rest = &rest[open + 2 + close + 2..];
+        }
+        out.push_str(rest);
+    }
+    out
+}
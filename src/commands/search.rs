@@ -0,0 +1,36 @@
+//! `mabel search <query>`: plain-text search over the notes folder.
+
+use std::path::PathBuf;
+
+use walkdir::WalkDir;
+
+use crate::{cli::SearchArgs, config::Config, Result};
+
+pub fn run(cfg: &Config, args: &SearchArgs) -> Result<()> {
+    let words: Vec<String> = args.query.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return Ok(());
+    }
+
+    let root = cfg.vault_notes_dir();
+    let mut hits: Vec<(usize, PathBuf)> = WalkDir::new(&root)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "md"))
+        .filter_map(|e| {
+            let text = std::fs::read_to_string(e.path()).ok()?.to_lowercase();
+            let counts: Vec<usize> = words.iter().map(|w| text.matches(w.as_str()).count()).collect();
+            counts
+                .iter()
+                .all(|&c| c > 0)
+                .then(|| (counts.iter().sum(), e.into_path()))
+        })
+        .collect();
+
+    // Most matches first, then alphabetical for a stable listing.
+    hits.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    for (_, path) in hits.into_iter().take(args.limit) {
+        println!("{}", path.strip_prefix(&cfg.vault_path).unwrap_or(&path).display());
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+//! `mabel selftest`: check an install by processing a canned paper against a mock of the arXiv
+//! API, the PDF host, GROBID and an OpenAI-compatible model, all served from this process (see
+//! [`mock`]). Nothing leaves the machine and no tokens are spent.
+//!
+//! The paper goes through each PDF extractor the build has, GROBID and then the built-in one, each
+//! time into a scratch vault of its own. The run keeps the configured template, frontmatter and
+//! region settings, so those are checked too; everything that would reach another service (ORCID
+//! lookups, the webhook, claims) is off. Only builds with the `test-server` feature have it.
+
+use std::path::Path;
+
+use crate::{
+    cli::SelftestArgs,
+    config::{Config, FigureAlt, LlmBackend, Mode},
+    http::ServiceAuth,
+    note::Overwrite,
+    pipeline::Pipeline,
+    routing::RoutingPolicy,
+    secret::Secret,
+    MabelError, Result,
+};
+
+mod mock;
+
+use mock::{Hits, MockServer};
+
+/// How the canned paper's full text is read in a run.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Extractor {
+    Grobid,
+    Builtin,
+    /// The build reads no PDFs: the note is written from the abstract
+    None,
+}
+
+impl Extractor {
+    fn name(self) -> &'static str {
+        match self {
+            | Self::Grobid => "grobid",
+            | Self::Builtin => "builtin",
+            | Self::None => "abstract",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            | Self::Grobid => "with GROBID",
+            | Self::Builtin => "with the built-in extractor",
+            | Self::None => "from the abstract (this build reads no PDFs)",
+        }
+    }
+}
+
+/// A check and, when it failed, why.
+type Check = (&'static str, std::result::Result<(), String>);
+
+pub async fn run(cfg: &Config, args: &SelftestArgs) -> Result<()> {
+    let scratch = std::env::temp_dir().join(format!("mabel-selftest-{}", std::process::id()));
+    let mut extractors = Vec::new();
+    if cfg!(feature = "grobid") {
+        extractors.push(Extractor::Grobid);
+    }
+    if cfg!(feature = "pdf") {
+        extractors.push(Extractor::Builtin);
+    }
+    if extractors.is_empty() {
+        extractors.push(Extractor::None);
+    }
+
+    let (mut failed, mut total) = (0, 0);
+    for extractor in extractors {
+        println!("{} {}", mock::ARXIV_ID, extractor.describe());
+        for (name, result) in process(cfg, extractor, &scratch.join(extractor.name())).await {
+            total += 1;
+            match result {
+                | Ok(()) => println!("  ok    {name}"),
+                | Err(why) => {
+                    failed += 1;
+                    println!("  FAIL  {name}: {why}");
+                }
+            }
+        }
+    }
+    if args.keep {
+        println!("scratch vaults kept in {}", scratch.display());
+    } else if let Err(e) = std::fs::remove_dir_all(&scratch) {
+        tracing::warn!(dir = %scratch.display(), error = %e, "could not remove the scratch vaults");
+    }
+    if failed > 0 {
+        return Err(MabelError::SelftestFailed { failed, total });
+    }
+    println!("all {total} checks passed");
+    Ok(())
+}
+
+/// Process the canned paper into a scratch vault under `dir` and check each step was taken.
+async fn process(cfg: &Config, extractor: Extractor, dir: &Path) -> Vec<Check> {
+    let server = match mock::start().await {
+        | Ok(server) => server,
+        | Err(e) => return vec![("mock server", Err(e.to_string()))],
+    };
+    let outcome = match scratch_config(cfg, &server, extractor, dir).and_then(Pipeline::new) {
+        | Ok(pipeline) => pipeline.run(mock::ARXIV_ID).await,
+        | Err(e) => Err(e),
+    };
+
+    let hits = &server.hits;
+    let expect = |taken: bool, why: &str| if taken { Ok(()) } else { Err(why.to_string()) };
+    let mut checks = vec![(
+        "arXiv metadata",
+        expect(Hits::get(&hits.arxiv) > 0, "the arXiv API was not asked"),
+    )];
+    if extractor != Extractor::None {
+        checks.push((
+            "PDF download",
+            expect(Hits::get(&hits.pdf) > 0, "the PDF was not downloaded"),
+        ));
+    }
+    if extractor == Extractor::Grobid {
+        checks.push((
+            "GROBID extraction",
+            expect(Hits::get(&hits.grobid) > 0, "GROBID was not asked"),
+        ));
+    }
+    checks.push(("summary", expect(Hits::get(&hits.llm) > 0, "the model was not asked")));
+    if extractor != Extractor::None {
+        checks.push((
+            "full text in the summary request",
+            expect(
+                Hits::get(&hits.full_text) > 0,
+                "the model was not given the paper's body",
+            ),
+        ));
+    }
+    let note = match outcome {
+        | Ok(outcome) => {
+            match std::fs::read_to_string(&outcome.path) {
+                | Ok(text) if !text.contains(mock::TITLE) => Err(format!("{} lacks the title", outcome.path.display())),
+                | Ok(text) if !text.contains(mock::TLDR) => {
+                    Err(format!(
+                        "{} lacks the summary's TL;DR (does the template leave it out?)",
+                        outcome.path.display()
+                    ))
+                }
+                | Ok(_) => Ok(()),
+                | Err(e) => Err(format!("{}: {e}", outcome.path.display())),
+            }
+        }
+        | Err(e) => Err(e.root().to_string()),
+    };
+    checks.push(("note", note));
+    checks
+}
+
+/// The configuration with every service pointed at the mock server and the vault and cache in
+/// `dir`.
+fn scratch_config(cfg: &Config, server: &MockServer, extractor: Extractor, dir: &Path) -> Result<Config> {
+    let mut cfg = cfg.clone();
+    cfg.vault_path = dir.join("vault");
+    cfg.cache_dir = dir.join("cache");
+    for path in [&cfg.vault_path, &cfg.cache_dir] {
+        std::fs::create_dir_all(path).map_err(|source| {
+            MabelError::Io {
+                path: path.clone(),
+                source,
+            }
+        })?;
+    }
+    cfg.llm = LlmBackend::OpenAi {
+        api_key: Secret::new("selftest"),
+        base_url: Some(server.openai_base_url()?),
+        model: "selftest".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        auth: ServiceAuth::default(),
+        stream: false,
+    };
+    cfg.routing = RoutingPolicy::default();
+    cfg.mode = Mode::Concise;
+    cfg.arxiv_api_url = server.arxiv_api_url()?;
+    cfg.arxiv_pdf_url = server.arxiv_pdf_url()?;
+    cfg.grobid_url = match extractor {
+        | Extractor::Grobid => Some(server.grobid_url()?),
+        | _ => None,
+    };
+    cfg.grobid_auth = ServiceAuth::default();
+    // A GROBID failure is a failure here, not a reason to quietly use the built-in extractor.
+    cfg.pdf_fallback = extractor == Extractor::Builtin;
+    cfg.overwrite_note = Overwrite::Merge;
+    cfg.preview_diff = false;
+    cfg.assume_yes = true;
+    cfg.tiered = false;
+    cfg.metered = false;
+    cfg.max_pages = None;
+    cfg.max_bandwidth = None;
+    cfg.http_retries = 0;
+    cfg.orcid = false;
+    cfg.vault_context = false;
+    cfg.extract_claims = false;
+    cfg.leaderboards = false;
+    cfg.figure_alt = FigureAlt::Off;
+    cfg.mocs = false;
+    cfg.anki_dir = None;
+    cfg.webhook_url = None;
+    Ok(cfg)
+}
@@ -0,0 +1,186 @@
+//! The mock server `mabel selftest` runs against, on a free port on localhost. Routes:
+//! - `GET /arxiv/api/query`: an Atom feed with one canned paper, [`ARXIV_ID`], whose PDF link
+//!   points back at this server.
+//! - `GET /pdf/{id}`: the canned paper's PDF, with a text layer for the built-in extractor.
+//! - `POST /grobid/api/processFulltextDocument`: the paper's TEI.
+//! - `POST /openai/v1/chat/completions`: a canned summary, as an OpenAI-compatible server replies.
+//!
+//! Every route counts its requests in [`Hits`], so the self-test can tell which steps were taken.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::header,
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use tokio::task::JoinHandle;
+use url::Url;
+
+use crate::{MabelError, Result};
+
+/// The canned paper's arXiv id.
+pub const ARXIV_ID: &str = "2401.00001";
+
+/// The canned paper's title, as the arXiv feed, the PDF and the TEI all have it.
+pub const TITLE: &str = "Checking a Paper Pipeline End to End";
+
+/// The TL;DR of the canned summary.
+pub const TLDR: &str = "A canned paper that walks through every step of turning a paper into a note.";
+
+/// Text only the paper's body has: a summary request with it was given the full text, not just
+/// the abstract.
+pub const BODY_TEXT: &str = "Every step answered as expected";
+
+/// The Atom feed, with `{base}` for this server's URL.
+const FEED: &str = include_str!("feed.xml");
+
+const PDF: &[u8] = include_bytes!("paper.pdf");
+
+const TEI: &str = include_str!("paper.tei.xml");
+
+/// Requests each route has answered.
+#[derive(Debug, Default)]
+pub struct Hits {
+    pub arxiv: AtomicUsize,
+    pub pdf: AtomicUsize,
+    pub grobid: AtomicUsize,
+    pub llm: AtomicUsize,
+    /// Summary requests that had the paper's body in them
+    pub full_text: AtomicUsize,
+}
+
+impl Hits {
+    pub fn get(counter: &AtomicUsize) -> usize {
+        counter.load(Ordering::Relaxed)
+    }
+
+    fn hit(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone)]
+struct Mock {
+    base: Url,
+    hits: Arc<Hits>,
+}
+
+/// A running mock server; it stops when dropped.
+pub struct MockServer {
+    pub url: Url,
+    pub hits: Arc<Hits>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Where the arXiv API is served, for `MABEL_ARXIV_API_URL`.
+    pub fn arxiv_api_url(&self) -> Result<Url> {
+        Ok(self.url.join("arxiv/api/query")?)
+    }
+
+    /// Where PDFs are served, for `MABEL_ARXIV_PDF_URL`.
+    pub fn arxiv_pdf_url(&self) -> Result<Url> {
+        Ok(self.url.join("pdf/")?)
+    }
+
+    /// Where GROBID is served, for `GROBID_URL`.
+    pub fn grobid_url(&self) -> Result<Url> {
+        Ok(self.url.join("grobid")?)
+    }
+
+    /// Where the chat endpoint is served, for `OPENAI_BASE_URL`.
+    pub fn openai_base_url(&self) -> Result<Url> {
+        Ok(self.url.join("openai/v1")?)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Start the server on a free port.
+pub async fn start() -> Result<MockServer> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.map_err(|e| {
+        MabelError::Config {
+            msg: format!("cannot start the mock server: {e}"),
+        }
+    })?;
+    let addr = listener.local_addr().map_err(|e| {
+        MabelError::Config {
+            msg: format!("cannot start the mock server: {e}"),
+        }
+    })?;
+    let url = Url::parse(&format!("http://{addr}/"))?;
+    let hits = Arc::new(Hits::default());
+    let app = Router::new()
+        .route("/arxiv/api/query", get(feed))
+        .route("/pdf/{id}", get(pdf))
+        .route("/grobid/api/processFulltextDocument", post(grobid))
+        .route("/openai/v1/chat/completions", post(chat))
+        .with_state(Mock {
+            base: url.clone(),
+            hits: hits.clone(),
+        });
+    let task = tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::warn!(error = %e, "mock server stopped");
+        }
+    });
+    tracing::debug!(%url, "mock server listening");
+    Ok(MockServer { url, hits, task })
+}
+
+async fn feed(State(mock): State<Mock>) -> impl IntoResponse {
+    Hits::hit(&mock.hits.arxiv);
+    (
+        [(header::CONTENT_TYPE, "application/atom+xml")],
+        FEED.replace("{base}", mock.base.as_str()),
+    )
+}
+
+async fn pdf(State(mock): State<Mock>) -> impl IntoResponse {
+    Hits::hit(&mock.hits.pdf);
+    ([(header::CONTENT_TYPE, "application/pdf")], PDF)
+}
+
+/// The PDF comes as a multipart upload; which PDF does not matter.
+async fn grobid(State(mock): State<Mock>, _pdf: Bytes) -> impl IntoResponse {
+    Hits::hit(&mock.hits.grobid);
+    ([(header::CONTENT_TYPE, "application/xml")], TEI)
+}
+
+async fn chat(State(mock): State<Mock>, Json(request): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    Hits::hit(&mock.hits.llm);
+    let prompt = request["messages"].to_string();
+    if prompt.contains(BODY_TEXT) {
+        Hits::hit(&mock.hits.full_text);
+    }
+    let summary = serde_json::json!({
+        "tldr": TLDR,
+        "summary": "The paper is served by a mock server so that every step, from metadata to the \
+                    note, can be checked without network access or tokens.",
+        "key_points": ["Metadata, PDF, full text and summary each have a known answer."],
+        "tags": ["selftest"],
+    });
+    Json(serde_json::json!({
+        "id": "selftest",
+        "object": "chat.completion",
+        "created": 0,
+        "model": request["model"],
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": summary.to_string()},
+            "finish_reason": "stop",
+        }],
+        "usage": {"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0},
+    }))
+}
@@ -0,0 +1,366 @@
+//! `mabel serve`: a small HTTP API so other tools (browser bookmarklets, shortcuts) can queue papers.
+//!
+//! Routes:
+//! - `GET /health`
+//! - `POST /notes` with `{"input": "<anything mabel note accepts>"}`; responds with the note path.
+//!   With `MABEL_TIERED` on, a paper that needs PDF extraction is answered once its note skeleton
+//!   is written, with `"pending": true`; the summary follows in the background.
+//! - `POST /jobs` with the same body; responds at once with the job id and processes the paper
+//!   in the background.
+//!
+//! Papers are processed `--concurrency` at a time, in turns (see [`crate::schedule`]). A request
+//! can give its `"source"` (such as `"feed"` for an import; `"manual"` if left out) and its
+//! `"priority"`: `"high"`, `"normal"` or `"low"`. A paper from `/notes` is high priority unless
+//! the request says otherwise, as someone is waiting on the reply; one from `/jobs` is normal.
+//! Each user's sources take turns of their own.
+//! - `GET /jobs`: papers submitted since the server started, newest first, with their errors.
+//! - `GET /notes`: the most recently written notes in the vault, with their TL;DRs.
+//! - `GET /ui`: a dashboard over the above with a box to submit papers. The page is compiled into
+//!   the binary; there is nothing to build or serve separately.
+//!
+//! With a users file (`MABEL_SERVE_USERS`, see [`users`]) one server serves a whole lab: every
+//! route but `/health` and `/ui` needs a user's token, papers go to that user's vault with their
+//! settings, and a user over quota gets 429 Too Many Requests.
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use axum::{
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, StatusCode},
+    response::Html,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::ServeArgs,
+    config::Config,
+    pipeline::NoteOutcome,
+    schedule::{Priority, Scheduler},
+    vault, MabelError, Result,
+};
+
+mod users;
+
+use users::{User, Users};
+
+const DASHBOARD: &str = include_str!("serve/dashboard.html");
+
+/// Jobs kept for the dashboard; older ones are dropped.
+const MAX_JOBS: usize = 200;
+
+/// Notes listed under "Recent notes".
+const RECENT_NOTES: usize = 20;
+
+#[derive(Debug, Deserialize)]
+struct NoteRequest {
+    input: String,
+    source: Option<String>,
+    priority: Option<Priority>,
+}
+
+#[derive(Clone)]
+struct AppState {
+    users: Arc<Users>,
+    jobs: Arc<Jobs>,
+    scheduler: Arc<Scheduler>,
+}
+
+/// The user a request is from, by its `Authorization` header.
+struct Caller(Arc<User>);
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> std::result::Result<Self, Self::Rejection> {
+        let authorization = parts.headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+        state
+            .users
+            .authenticate(authorization)
+            .map(Caller)
+            .ok_or_else(|| (StatusCode::UNAUTHORIZED, "missing or unknown API token".to_string()))
+    }
+}
+
+/// One submitted paper and how far it got.
+#[derive(Clone, Debug, Serialize)]
+struct Job {
+    id: u64,
+    /// Who submitted it; users only see their own jobs
+    #[serde(skip)]
+    user: String,
+    input: String,
+    source: String,
+    priority: Priority,
+    status: JobStatus,
+    title: Option<String>,
+    path: Option<PathBuf>,
+    error: Option<String>,
+    submitted: DateTime<Utc>,
+    finished: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    /// Waiting for its turn
+    Queued,
+    Running,
+    /// The skeleton note is written; the summary is still being worked on
+    Pending,
+    Done,
+    Failed,
+}
+
+/// Papers submitted since the server started, newest first.
+#[derive(Default)]
+struct Jobs {
+    next: AtomicU64,
+    list: Mutex<VecDeque<Job>>,
+}
+
+impl Jobs {
+    fn start(&self, user: &User, input: &str, source: &str, priority: Priority) -> u64 {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut list = self.list.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        list.push_front(Job {
+            id,
+            user: user.name.clone(),
+            input: input.to_string(),
+            source: source.to_string(),
+            priority,
+            status: JobStatus::Queued,
+            title: None,
+            path: None,
+            error: None,
+            submitted: Utc::now(),
+            finished: None,
+        });
+        list.truncate(MAX_JOBS);
+        id
+    }
+
+    fn running(&self, id: u64) {
+        let mut list = self.list.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(job) = list.iter_mut().find(|j| j.id == id) {
+            job.status = JobStatus::Running;
+        }
+    }
+
+    fn update(&self, id: u64, result: std::result::Result<(&NoteOutcome, bool), String>) {
+        let mut list = self.list.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let Some(job) = list.iter_mut().find(|j| j.id == id) else {
+            return;
+        };
+        match result {
+            | Ok((outcome, pending)) => {
+                job.title = Some(outcome.title.clone());
+                job.path = Some(outcome.path.clone());
+                job.status = if pending {
+                    JobStatus::Pending
+                } else {
+                    JobStatus::Done
+                };
+            }
+            | Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+            }
+        }
+        if job.status != JobStatus::Pending {
+            job.finished = Some(Utc::now());
+        }
+    }
+
+    fn snapshot(&self, user: &User) -> Vec<Job> {
+        let list = self.list.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        list.iter().filter(|j| j.user == user.name).cloned().collect()
+    }
+}
+
+/// A note in the "Recent notes" list.
+#[derive(Debug, Serialize)]
+struct RecentNote {
+    title: String,
+    path: PathBuf,
+    tldr: Option<String>,
+    modified: Option<DateTime<Utc>>,
+}
+
+pub async fn run(mut cfg: Config, args: &ServeArgs) -> Result<()> {
+    // A question at the server's console would hold the request that asked it.
+    cfg.non_interactive = true;
+    let state = AppState {
+        users: Arc::new(Users::load(cfg)?),
+        jobs: Arc::new(Jobs::default()),
+        scheduler: Scheduler::new(args.concurrency),
+    };
+    let app = Router::new()
+        .route("/health", get(|| async { "ok" }))
+        .route("/notes", post(create_note).get(recent_notes))
+        .route("/jobs", post(submit_job).get(list_jobs))
+        .route("/ui", get(|| async { Html(DASHBOARD) }))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(args.bind).await.map_err(|e| {
+        MabelError::Config {
+            msg: format!("cannot listen on {}: {e}", args.bind),
+        }
+    })?;
+    tracing::info!(addr = %args.bind, "listening");
+    axum::serve(listener, app).await.map_err(|e| {
+        MabelError::Config {
+            msg: format!("server error: {e}"),
+        }
+    })
+}
+
+async fn create_note(
+    State(state): State<AppState>,
+    Caller(user): Caller,
+    Json(req): Json<NoteRequest>,
+) -> std::result::Result<Json<NoteOutcome>, (StatusCode, String)> {
+    user.admit().map_err(|e| (StatusCode::TOO_MANY_REQUESTS, e))?;
+    let job = Submission::new(&state, &user, &req, Priority::High);
+    match process(&state, &user, job).await {
+        | Ok(outcome) => Ok(Json(outcome)),
+        | Err(e) => {
+            let status = match e.root() {
+                | MabelError::InvalidArxivId { .. }
+                | MabelError::InvalidPubmedId { .. }
+                | MabelError::InvalidDoi { .. }
+                | MabelError::UnsupportedInput { .. } => StatusCode::BAD_REQUEST,
+                | MabelError::NoteExists { .. } | MabelError::NoteUnmanaged { .. } => StatusCode::CONFLICT,
+                | _ => StatusCode::BAD_GATEWAY,
+            };
+            Err((status, e.to_string()))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Submitted {
+    id: u64,
+}
+
+async fn submit_job(
+    State(state): State<AppState>,
+    Caller(user): Caller,
+    Json(req): Json<NoteRequest>,
+) -> std::result::Result<(StatusCode, Json<Submitted>), (StatusCode, String)> {
+    user.admit().map_err(|e| (StatusCode::TOO_MANY_REQUESTS, e))?;
+    let job = Submission::new(&state, &user, &req, Priority::Normal);
+    let id = job.id;
+    tokio::spawn(async move {
+        // Failures are recorded on the job.
+        let _ = process(&state, &user, job).await;
+    });
+    Ok((StatusCode::ACCEPTED, Json(Submitted { id })))
+}
+
+/// A job as submitted, waiting for its turn.
+struct Submission {
+    id: u64,
+    input: String,
+    /// The source turns are taken by: the request's, and the user's on a shared server
+    turns: String,
+    priority: Priority,
+}
+
+impl Submission {
+    /// Record the job for `req`, with `priority` unless the request gives one.
+    fn new(state: &AppState, user: &User, req: &NoteRequest, priority: Priority) -> Self {
+        let source = req
+            .source
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("manual");
+        let priority = req.priority.unwrap_or(priority);
+        Self {
+            id: state.jobs.start(user, &req.input, source, priority),
+            input: req.input.clone(),
+            turns: format!("{}/{source}", user.name),
+            priority,
+        }
+    }
+}
+
+/// Run `job` for `user` once its turn comes, keeping its status current through a background
+/// summary pass as well, and charge the tokens it took to the user. The turn lasts until the
+/// summary is done.
+async fn process(state: &AppState, user: &Arc<User>, job: Submission) -> Result<NoteOutcome> {
+    let slot = state.scheduler.slot(&job.turns, job.priority).await;
+    let (id, input) = (job.id, job.input.as_str());
+    state.jobs.running(id);
+    match user.pipeline.clone().run_detached(input).await {
+        | Ok(detached) => {
+            user.charge(detached.outcome.usage.total());
+            state.jobs.update(id, Ok((&detached.outcome, detached.rest.is_some())));
+            if let Some(rest) = detached.rest {
+                let (jobs, user) = (state.jobs.clone(), user.clone());
+                tokio::spawn(async move {
+                    let _slot = slot;
+                    match rest.await {
+                        | Ok(Ok(outcome)) => {
+                            user.charge(outcome.usage.total());
+                            jobs.update(id, Ok((&outcome, false)));
+                        }
+                        | Ok(Err(e)) => jobs.update(id, Err(e.to_string())),
+                        | Err(e) => jobs.update(id, Err(format!("summary task failed: {e}"))),
+                    }
+                });
+            }
+            Ok(detached.outcome)
+        }
+        | Err(e) => {
+            tracing::warn!(user = %user.name, input, error = %e, "note request failed");
+            state.jobs.update(id, Err(e.to_string()));
+            Err(e)
+        }
+    }
+}
+
+async fn list_jobs(State(state): State<AppState>, Caller(user): Caller) -> Json<Vec<Job>> {
+    Json(state.jobs.snapshot(&user))
+}
+
+/// The notes in the user's paper folder, most recently written first.
+async fn recent_notes(Caller(user): Caller) -> Json<Vec<RecentNote>> {
+    let cfg = user.pipeline.config();
+    let (dir, fields) = (cfg.vault_notes_dir(), cfg.frontmatter.clone());
+    let notes = tokio::task::spawn_blocking(move || {
+        let mut notes: Vec<(Option<SystemTime>, vault::VaultNote)> = vault::scan(&dir, &fields)
+            .into_iter()
+            .map(|n| (std::fs::metadata(&n.path).and_then(|m| m.modified()).ok(), n))
+            .collect();
+        notes.sort_by(|a, b| b.0.cmp(&a.0));
+        notes.truncate(RECENT_NOTES);
+        notes
+    })
+    .await
+    .unwrap_or_default();
+    Json(
+        notes
+            .into_iter()
+            .map(|(modified, n)| {
+                RecentNote {
+                    title: n.title().to_string(),
+                    tldr: n.tldr.clone(),
+                    path: n.path,
+                    modified: modified.map(DateTime::<Utc>::from),
+                }
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,205 @@
+//! Users of a shared server: a TOML file (`MABEL_SERVE_USERS`) that gives each member of a lab an
+//! API token, their own vault and settings, and optional quotas. Requests then need an
+//! `Authorization: Bearer <token>` header and only see their own jobs and notes.
+//!
+//! ```toml
+//! [users.alice]
+//! token = "a long random string"
+//! vault_path = "/srv/vaults/alice"      # relative paths are relative to this file
+//! vault_subdir = "Papers"
+//! mode = "study"                        # concise, study, eli-grad, skim or flashcards
+//! model = "gpt-4o"
+//! temperature = 0.3
+//! template = "templates/alice.md.tera"
+//! webhook_url = "https://example.org/hooks/alice"
+//! papers_per_hour = 20                  # quotas, over the last hour and day
+//! tokens_per_day = 2000000
+//! ```
+//!
+//! Settings left out are the server's own. Quotas are counted since the server started.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    config::{self, Config},
+    pipeline::Pipeline,
+    MabelError, Result,
+};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsersFile {
+    #[serde(default)]
+    users: BTreeMap<String, UserSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserSpec {
+    token: String,
+    vault_path: PathBuf,
+    vault_subdir: Option<String>,
+    mode: Option<String>,
+    model: Option<String>,
+    temperature: Option<f32>,
+    template: Option<PathBuf>,
+    webhook_url: Option<String>,
+    papers_per_hour: Option<usize>,
+    tokens_per_day: Option<u64>,
+}
+
+/// Who a request is from, with the pipeline that writes their notes.
+pub struct User {
+    /// Empty on a server without a users file
+    pub name: String,
+    pub pipeline: Arc<Pipeline>,
+    papers_per_hour: Option<usize>,
+    tokens_per_day: Option<u64>,
+    /// When papers were submitted, over the last hour
+    submitted: Mutex<VecDeque<Instant>>,
+    /// Tokens spent, over the last day
+    spent: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+/// Everyone the server accepts requests from.
+pub enum Users {
+    /// No users file: anyone who can reach the server, writing to the server's vault
+    Open(Arc<User>),
+    /// By SHA-256 of their token
+    Tokens(HashMap<Vec<u8>, Arc<User>>),
+}
+
+impl Users {
+    /// The users in `cfg.serve_users`, or an open server when there is no such file.
+    pub fn load(cfg: Config) -> Result<Self> {
+        let Some(path) = cfg.serve_users.clone() else {
+            return Ok(Self::Open(Arc::new(User::new(String::new(), cfg, None, None)?)));
+        };
+        let invalid = |msg: String| {
+            MabelError::Config {
+                msg: format!("{}: {msg}", path.display()),
+            }
+        };
+        let text = std::fs::read_to_string(&path).map_err(|source| {
+            MabelError::Io {
+                path: path.clone(),
+                source,
+            }
+        })?;
+        let file: UsersFile = toml::from_str(&text).map_err(|e| invalid(e.message().to_string()))?;
+        if file.users.is_empty() {
+            return Err(invalid("no [users.NAME] tables".to_string()));
+        }
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut users = HashMap::new();
+        for (name, spec) in file.users {
+            if spec.token.trim().len() < 16 {
+                return Err(invalid(format!("the token of {name} is shorter than 16 characters")));
+            }
+            let hash = Sha256::digest(spec.token.trim().as_bytes()).to_vec();
+            let user_cfg = spec.apply(&cfg, dir).map_err(|e| invalid(format!("{name}: {e}")))?;
+            let user = User::new(name.clone(), user_cfg, spec.papers_per_hour, spec.tokens_per_day)?;
+            tracing::info!(user = %name, vault = %user.pipeline.config().vault_path.display(), "serving");
+            if users.insert(hash, Arc::new(user)).is_some() {
+                return Err(invalid(format!("{name} has the same token as another user")));
+            }
+        }
+        Ok(Self::Tokens(users))
+    }
+
+    /// The user the `Authorization` header value belongs to.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Option<Arc<User>> {
+        match self {
+            | Self::Open(user) => Some(user.clone()),
+            | Self::Tokens(users) => {
+                let token = authorization?.strip_prefix("Bearer ")?.trim();
+                users.get(Sha256::digest(token.as_bytes()).as_slice()).cloned()
+            }
+        }
+    }
+}
+
+impl UserSpec {
+    /// The server's configuration with this user's settings on top.
+    fn apply(&self, base: &Config, dir: &Path) -> Result<Config> {
+        let mut cfg = base.clone();
+        cfg.vault_path = dir.join(shellexpand::tilde(&self.vault_path.to_string_lossy()).as_ref());
+        config::prepare_vault(&cfg.vault_path)?;
+        if let Some(subdir) = &self.vault_subdir {
+            cfg.vault_subdir.clone_from(subdir);
+        }
+        if let Some(mode) = &self.mode {
+            cfg.mode = mode.parse()?;
+        }
+        cfg.llm = cfg.llm.with_overrides(self.model.as_deref(), self.temperature);
+        if let Some(template) = &self.template {
+            cfg.template_path = dir.join(template);
+        }
+        if let Some(url) = &self.webhook_url {
+            cfg.webhook_url = Some(Url::parse(url)?);
+        }
+        Ok(cfg)
+    }
+}
+
+impl User {
+    fn new(name: String, cfg: Config, papers_per_hour: Option<usize>, tokens_per_day: Option<u64>) -> Result<Self> {
+        Ok(Self {
+            name,
+            pipeline: Arc::new(Pipeline::new(cfg)?),
+            papers_per_hour,
+            tokens_per_day,
+            submitted: Mutex::default(),
+            spent: Mutex::default(),
+        })
+    }
+
+    /// Count a submitted paper against the user's quotas, or say which quota it would exceed.
+    pub fn admit(&self) -> std::result::Result<(), String> {
+        let now = Instant::now();
+        if let Some(limit) = self.tokens_per_day {
+            let mut spent = self.spent.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+            while spent.front().is_some_and(|(at, _)| now.duration_since(*at) > DAY) {
+                spent.pop_front();
+            }
+            let total: u64 = spent.iter().map(|(_, tokens)| tokens).sum();
+            if total >= limit {
+                return Err(format!("quota of {limit} tokens per day used up"));
+            }
+        }
+        let mut submitted = self.submitted.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        while submitted.front().is_some_and(|at| now.duration_since(*at) > HOUR) {
+            submitted.pop_front();
+        }
+        if let Some(limit) = self.papers_per_hour {
+            if submitted.len() >= limit {
+                let wait = HOUR.saturating_sub(now.duration_since(submitted[0])).as_secs() / 60 + 1;
+                return Err(format!(
+                    "quota of {limit} papers per hour reached; try again in {wait} min"
+                ));
+            }
+        }
+        submitted.push_back(now);
+        Ok(())
+    }
+
+    /// Record tokens spent on the user's behalf.
+    pub fn charge(&self, tokens: u64) {
+        if tokens > 0 && self.tokens_per_day.is_some() {
+            let mut spent = self.spent.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+            spent.push_back((Instant::now(), tokens));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! `mabel service install|uninstall serve|watch`: keep a long-running mode going across reboots
+//! without hand-written unit files. Linux gets a systemd user unit, macOS a launchd agent and
+//! Windows a logon task that starts a small `.cmd` wrapper.
+//!
+//! A service sees neither the shell environment nor `.env`, so the vault path (and the folder
+//! `watch` watches) goes on its command line and everything else has to come from the config files
+//! (see [`crate::config_file`]).
+
+use std::{
+    fmt::Write as _,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cli::{ServiceAction, ServiceMode},
+    config::Config,
+    config_file, MabelError, Result,
+};
+
+/// Variables passed through to the service when set now.
+const PASSED_ENV: &[&str] = &["MABEL_CONFIG", "MABEL_LOG"];
+
+#[derive(Clone, Copy)]
+enum Platform {
+    Systemd,
+    Launchd,
+    Windows,
+}
+
+/// What to run, independent of the platform.
+struct Service {
+    /// `mabel-serve` or `mabel-watch`
+    name: String,
+    program: PathBuf,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    /// Output of services whose platform does not keep logs itself
+    log: PathBuf,
+}
+
+pub fn run(cfg: &Config, action: &ServiceAction) -> Result<()> {
+    let platform = Platform::current();
+    match action {
+        | ServiceAction::Install { kind, bind, dir, print } => {
+            let service = Service::new(cfg, *kind, *bind, dir.as_deref())?;
+            let contents = platform.render(&service);
+            if *print {
+                print!("{contents}");
+                return Ok(());
+            }
+            let path = platform.path(&service.name)?;
+            for dir in [path.parent(), service.log.parent()].into_iter().flatten() {
+                std::fs::create_dir_all(dir).map_err(|source| io_err(dir, source))?;
+            }
+            std::fs::write(&path, contents).map_err(|source| io_err(&path, source))?;
+            platform.activate(&service.name, &path)?;
+            println!("installed {} ({})", service.name, path.display());
+            if let Some(user) = config_file::user_path().filter(|p| !cfg.config_files.contains(p)) {
+                eprintln!(
+                    "note: the service does not see your shell environment; put API keys and settings in {}",
+                    user.display()
+                );
+            }
+        }
+        | ServiceAction::Uninstall { kind } => {
+            let name = service_name(*kind);
+            let path = platform.path(&name)?;
+            platform.deactivate(&name, &path);
+            match std::fs::remove_file(&path) {
+                | Ok(()) => println!("removed {name} ({})", path.display()),
+                | Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("{name} is not installed"),
+                | Err(source) => return Err(io_err(&path, source)),
+            }
+            if matches!(platform, Platform::Systemd) {
+                let _ = exec("systemctl", &["--user", "daemon-reload"]);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn service_name(mode: ServiceMode) -> String {
+    match mode {
+        | ServiceMode::Serve => "mabel-serve".to_string(),
+        | ServiceMode::Watch => "mabel-watch".to_string(),
+    }
+}
+
+impl Service {
+    fn new(cfg: &Config, mode: ServiceMode, bind: SocketAddr, dir: Option<&Path>) -> Result<Self> {
+        let program = std::env::current_exe().map_err(|e| {
+            MabelError::Config {
+                msg: format!("cannot locate the mabel executable: {e}"),
+            }
+        })?;
+        let vault = std::path::absolute(&cfg.vault_path).map_err(|source| io_err(&cfg.vault_path, source))?;
+        let mut args = vec!["--vault-path".to_string(), vault.display().to_string()];
+        match mode {
+            | ServiceMode::Serve => args.extend(["serve".to_string(), "--bind".to_string(), bind.to_string()]),
+            | ServiceMode::Watch => {
+                let dir = dir.ok_or_else(|| {
+                    MabelError::Config {
+                        msg: "`service install watch` needs the folder to watch (--dir)".to_string(),
+                    }
+                })?;
+                let dir = std::path::absolute(dir).map_err(|source| io_err(dir, source))?;
+                args.extend(["watch".to_string(), dir.display().to_string()]);
+            }
+        }
+        let env = PASSED_ENV
+            .iter()
+            .filter_map(|&key| std::env::var(key).ok().map(|v| (key.to_string(), v)))
+            .collect();
+        let name = service_name(mode);
+        Ok(Self {
+            log: cfg.cache_dir.join("logs").join(format!("{name}.log")),
+            name,
+            program,
+            args,
+            env,
+        })
+    }
+}
+
+impl Platform {
+    fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Systemd
+        }
+    }
+
+    /// Where the service definition is installed.
+    fn path(self, name: &str) -> Result<PathBuf> {
+        let missing = || {
+            MabelError::Config {
+                msg: "cannot find the user configuration folder".to_string(),
+            }
+        };
+        Ok(match self {
+            | Self::Systemd => {
+                dirs::config_dir()
+                    .ok_or_else(missing)?
+                    .join("systemd/user")
+                    .join(format!("{name}.service"))
+            }
+            | Self::Launchd => {
+                dirs::home_dir()
+                    .ok_or_else(missing)?
+                    .join("Library/LaunchAgents")
+                    .join(format!("{}.plist", launchd_label(name)))
+            }
+            | Self::Windows => {
+                dirs::config_dir()
+                    .ok_or_else(missing)?
+                    .join("mabel")
+                    .join(format!("{name}.cmd"))
+            }
+        })
+    }
+
+    fn render(self, service: &Service) -> String {
+        match self {
+            | Self::Systemd => systemd_unit(service),
+            | Self::Launchd => launchd_plist(service),
+            | Self::Windows => windows_cmd(service),
+        }
+    }
+
+    fn activate(self, name: &str, path: &Path) -> Result<()> {
+        match self {
+            | Self::Systemd => {
+                exec("systemctl", &["--user", "daemon-reload"])?;
+                exec("systemctl", &["--user", "enable", "--now", &format!("{name}.service")])
+            }
+            | Self::Launchd => exec("launchctl", &["load", "-w", &path.to_string_lossy()]),
+            | Self::Windows => {
+                let action = format!("\"{}\"", path.display());
+                exec(
+                    "schtasks",
+                    &["/Create", "/F", "/TN", name, "/SC", "ONLOGON", "/TR", &action],
+                )?;
+                exec("schtasks", &["/Run", "/TN", name])
+            }
+        }
+    }
+
+    /// Stop the service; one that is not running or not installed is fine.
+    fn deactivate(self, name: &str, path: &Path) {
+        match self {
+            | Self::Systemd => {
+                let _ = exec("systemctl", &["--user", "disable", "--now", &format!("{name}.service")]);
+            }
+            | Self::Launchd => {
+                if path.exists() {
+                    let _ = exec("launchctl", &["unload", "-w", &path.to_string_lossy()]);
+                }
+            }
+            | Self::Windows => {
+                let _ = exec("schtasks", &["/End", "/TN", name]);
+                let _ = exec("schtasks", &["/Delete", "/F", "/TN", name]);
+            }
+        }
+    }
+}
+
+fn systemd_unit(service: &Service) -> String {
+    let mut exec_start = systemd_quote(&service.program.to_string_lossy());
+    for arg in &service.args {
+        exec_start.push(' ');
+        exec_start.push_str(&systemd_quote(arg));
+    }
+    let mut out = format!(
+        "[Unit]\nDescription=mabel ({})\nAfter=network-online.target\n\n[Service]\nExecStart={exec_start}\n",
+        service.name
+    );
+    for (key, value) in &service.env {
+        let _ = writeln!(out, "Environment={}", systemd_quote(&format!("{key}={value}")));
+    }
+    out.push_str("Restart=on-failure\nRestartSec=10\n\n[Install]\nWantedBy=default.target\n");
+    out
+}
+
+/// Quote one word of a systemd command line, escaping specifiers and variable expansion.
+fn systemd_quote(word: &str) -> String {
+    let escaped = word
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+fn launchd_label(name: &str) -> String {
+    format!("io.github.maxwellherron5.{name}")
+}
+
+fn launchd_plist(service: &Service) -> String {
+    let string = |s: &str| format!("<string>{}</string>", quick_xml::escape::escape(s));
+    let mut out = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n",
+    ));
+    let _ = writeln!(out, "  <key>Label</key>\n  {}", string(&launchd_label(&service.name)));
+    out.push_str("  <key>ProgramArguments</key>\n  <array>\n");
+    let _ = writeln!(out, "    {}", string(&service.program.to_string_lossy()));
+    for arg in &service.args {
+        let _ = writeln!(out, "    {}", string(arg));
+    }
+    out.push_str("  </array>\n");
+    if !service.env.is_empty() {
+        out.push_str("  <key>EnvironmentVariables</key>\n  <dict>\n");
+        for (key, value) in &service.env {
+            let _ = writeln!(out, "    <key>{key}</key>\n    {}", string(value));
+        }
+        out.push_str("  </dict>\n");
+    }
+    let log = service.log.to_string_lossy();
+    let _ = writeln!(out, "  <key>StandardOutPath</key>\n  {}", string(&log));
+    let _ = writeln!(out, "  <key>StandardErrorPath</key>\n  {}", string(&log));
+    out.push_str("  <key>RunAtLoad</key>\n  <true/>\n  <key>KeepAlive</key>\n  <true/>\n</dict>\n</plist>\n");
+    out
+}
+
+fn windows_cmd(service: &Service) -> String {
+    // Batch files expand `%`; everything else is safe inside double quotes.
+    let quote = |s: &str| format!("\"{}\"", s.replace('%', "%%"));
+    let mut out = String::from("@echo off\r\n");
+    for (key, value) in &service.env {
+        let _ = write!(out, "set {}\r\n", quote(&format!("{key}={value}")));
+    }
+    let _ = write!(out, "{}", quote(&service.program.to_string_lossy()));
+    for arg in &service.args {
+        let _ = write!(out, " {}", quote(arg));
+    }
+    let _ = write!(out, " >> {} 2>&1\r\n", quote(&service.log.to_string_lossy()));
+    out
+}
+
+fn exec(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program).args(args).status().map_err(|e| {
+        MabelError::Config {
+            msg: format!("could not run {program}: {e}"),
+        }
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(MabelError::Config {
+            msg: format!("`{program} {}` failed ({status})", args.join(" ")),
+        })
+    }
+}
+
+fn io_err(path: &Path, source: std::io::Error) -> MabelError {
+    MabelError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
@@ -0,0 +1,300 @@
+//! `mabel state export|import <file>`: move what mabel keeps about the papers it has processed to
+//! another machine, so they need not be processed again there. The archive is a zstd-compressed
+//! tar holding:
+//! - `manifest.json`, first: what is in the archive, and the cached PDFs left out of it;
+//! - `config.toml`: the personal config file without its secrets (see
+//!   [`config_file::without_secrets`]);
+//! - `vault/.mabel/`: the registry, claims, results, queue and feedback from the vault;
+//! - `cache/`: the cache directory with the index, embeddings and extracted text, but without the
+//!   cached PDFs unless `--pdfs` is given.
+//!
+//! The notes are not in it; the vault moves the way it always does. On import, files go to this
+//! machine's vault, cache directory and config file. Those that are here already are kept unless
+//! `--overwrite` is given, except the registry, which gains the archive's papers either way.
+
+use std::{
+    collections::btree_map::Entry,
+    fs::File,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use super::cache::human_size;
+use crate::{cli::StateAction, config::Config, config_file, registry::Registry, MabelError, Result};
+
+/// Version of the archive layout; an archive from a newer mabel is refused.
+const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+const CONFIG: &str = "config.toml";
+
+/// What an archive holds.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    /// Version of the mabel that wrote it
+    mabel: String,
+    exported: String,
+    files: Vec<Packed>,
+    /// Cached PDFs not in the archive
+    #[serde(default)]
+    left_out: Vec<Packed>,
+}
+
+/// A file, by its path in the archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct Packed {
+    path: String,
+    bytes: u64,
+}
+
+pub fn run(cfg: &Config, action: &StateAction) -> Result<()> {
+    match action {
+        | StateAction::Export { file, pdfs } => export(cfg, file, *pdfs),
+        | StateAction::Import { file, overwrite } => import(cfg, file, *overwrite),
+    }
+}
+
+fn export(cfg: &Config, file: &Path, pdfs: bool) -> Result<()> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: file.to_path_buf(),
+            source,
+        }
+    };
+    let config = config_file::user_path()
+        .map(|path| config_file::without_secrets(&path))
+        .transpose()?
+        .flatten();
+    let mut packed = Vec::new();
+    let mut manifest = Manifest {
+        format: FORMAT,
+        mabel: env!("CARGO_PKG_VERSION").to_string(),
+        exported: cfg.timezone.timestamp(chrono::Utc::now()),
+        files: Vec::new(),
+        left_out: Vec::new(),
+    };
+    if let Some(config) = &config {
+        manifest.files.push(Packed {
+            path: CONFIG.to_string(),
+            bytes: config.len() as u64,
+        });
+    }
+    let archive = std::path::absolute(file).map_err(io_err)?;
+    for (root, prefix) in [
+        (cfg.vault_path.join(".mabel"), "vault/.mabel"),
+        (cfg.cache_dir.clone(), "cache"),
+    ] {
+        for (path, bytes) in files_under(&root).into_iter().filter(|(path, _)| *path != archive) {
+            let relative = path.strip_prefix(&root).unwrap_or(&path);
+            let name = relative
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let item = Packed {
+                path: format!("{prefix}/{name}"),
+                bytes,
+            };
+            if prefix == "cache" && relative.starts_with("papers") && !pdfs {
+                manifest.left_out.push(item);
+            } else {
+                manifest.files.push(item);
+                packed.push(path);
+            }
+        }
+    }
+
+    let out = File::create(file).map_err(io_err)?;
+    let mut tar = tar::Builder::new(zstd::Encoder::new(out, 0).map_err(io_err)?);
+    append(&mut tar, MANIFEST, &serde_json::to_vec_pretty(&manifest)?).map_err(io_err)?;
+    if let Some(config) = &config {
+        append(&mut tar, CONFIG, config.as_bytes()).map_err(io_err)?;
+    }
+    // The config file comes first in the manifest too.
+    let names = manifest.files.iter().skip(usize::from(config.is_some()));
+    for (path, item) in packed.iter().zip(names) {
+        tar.append_path_with_name(path, &item.path).map_err(|source| {
+            MabelError::Io {
+                path: path.clone(),
+                source,
+            }
+        })?;
+    }
+    tar.into_inner().and_then(zstd::Encoder::finish).map_err(io_err)?;
+
+    let total: u64 = manifest.files.iter().map(|f| f.bytes).sum();
+    println!(
+        "exported {} files ({}) to {}",
+        manifest.files.len(),
+        human_size(total),
+        file.display()
+    );
+    if config.is_some() {
+        println!("the config file is in it without its secrets; set those again on the other machine");
+    }
+    if !manifest.left_out.is_empty() {
+        let skipped: u64 = manifest.left_out.iter().map(|f| f.bytes).sum();
+        println!(
+            "{} cached PDFs ({}) left out; pass --pdfs to take them along",
+            manifest.left_out.len(),
+            human_size(skipped)
+        );
+    }
+    Ok(())
+}
+
+fn import(cfg: &Config, file: &Path, overwrite: bool) -> Result<()> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: file.to_path_buf(),
+            source,
+        }
+    };
+    let not_state = || {
+        MabelError::Config {
+            msg: format!("{} is not an archive from `mabel state export`", file.display()),
+        }
+    };
+    let input = File::open(file).map_err(io_err)?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(input).map_err(|_| not_state())?);
+    let mut entries = archive.entries().map_err(io_err)?;
+    let manifest: Manifest = match entries.next() {
+        | Some(Ok(entry)) if entry.path().is_ok_and(|p| p == Path::new(MANIFEST)) => {
+            serde_json::from_reader(entry).map_err(|_| not_state())?
+        }
+        | _ => return Err(not_state()),
+    };
+    if manifest.format > FORMAT {
+        return Err(MabelError::Config {
+            msg: format!(
+                "{} was exported by mabel {}, which this one cannot read; update mabel to import it",
+                file.display(),
+                manifest.mabel
+            ),
+        });
+    }
+
+    let (mut written, mut kept, mut added) = (0, 0, None);
+    let mut config = None;
+    for entry in entries {
+        let mut entry = entry.map_err(io_err)?;
+        let name = entry.path().map_err(io_err)?.into_owned();
+        let Some(target) = target(cfg, &name) else {
+            tracing::warn!(entry = %name.display(), "unexpected file in the archive skipped");
+            continue;
+        };
+        let exists = target.exists();
+        if exists && !overwrite && target == cfg.registry_path() {
+            let mut text = String::new();
+            entry.read_to_string(&mut text).map_err(io_err)?;
+            added = Some(merge_registry(&target, serde_json::from_str(&text)?)?);
+            continue;
+        }
+        if exists && !overwrite {
+            tracing::info!(path = %target.display(), "kept the file that is here");
+            kept += 1;
+            continue;
+        }
+        if let Some(dir) = target.parent() {
+            std::fs::create_dir_all(dir).map_err(|source| {
+                MabelError::Io {
+                    path: dir.to_path_buf(),
+                    source,
+                }
+            })?;
+        }
+        entry.unpack(&target).map_err(|source| {
+            MabelError::Io {
+                path: target.clone(),
+                source,
+            }
+        })?;
+        if name == Path::new(CONFIG) {
+            config = Some(target);
+        }
+        written += 1;
+    }
+
+    println!(
+        "imported {written} files exported {} into {} and {}",
+        manifest.exported,
+        cfg.vault_path.display(),
+        cfg.cache_dir.display()
+    );
+    if kept > 0 {
+        println!("kept {kept} files that were here already; pass --overwrite to replace them");
+    }
+    if let Some(added) = added {
+        println!("added {added} papers to the registry here");
+    }
+    if let Some(config) = config {
+        println!(
+            "wrote {}: check its paths, which are the other machine's, and set its secrets again",
+            config.display()
+        );
+    }
+    if !manifest.left_out.is_empty() {
+        println!(
+            "{} cached PDFs were left out of the archive; they are downloaded again when needed",
+            manifest.left_out.len()
+        );
+    }
+    Ok(())
+}
+
+/// Every regular file under `root`, with its size in bytes.
+fn files_under(root: &Path) -> Vec<(PathBuf, u64)> {
+    WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| {
+            let bytes = e.metadata().map(|m| m.len()).unwrap_or(0);
+            (e.into_path(), bytes)
+        })
+        .collect()
+}
+
+/// Add a file to the archive from memory.
+fn append<W: std::io::Write>(tar: &mut tar::Builder<W>, name: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(chrono::Utc::now().timestamp().try_into().unwrap_or_default());
+    header.set_cksum();
+    tar.append_data(&mut header, name, data)
+}
+
+/// Where the archive's file `name` goes on this machine; `None` for a name outside the layout.
+fn target(cfg: &Config, name: &Path) -> Option<PathBuf> {
+    if name.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    if name == Path::new(CONFIG) {
+        return config_file::user_path();
+    }
+    if let Ok(rest) = name.strip_prefix("vault/.mabel") {
+        return Some(cfg.vault_path.join(".mabel").join(rest));
+    }
+    name.strip_prefix("cache").ok().map(|rest| cfg.cache_dir.join(rest))
+}
+
+/// Add the papers of `imported` that the registry at `path` lacks; returns how many.
+fn merge_registry(path: &Path, imported: Registry) -> Result<usize> {
+    Registry::update(path, |registry| {
+        let mut added = 0;
+        for (key, paper) in imported.papers {
+            if let Entry::Vacant(slot) = registry.papers.entry(key) {
+                slot.insert(paper);
+                added += 1;
+            }
+        }
+        added
+    })
+}
@@ -0,0 +1,107 @@
+//! `mabel template show|check|prompts`
+
+use std::path::Path;
+
+use crate::{
+    bibtex,
+    cli::TemplateAction,
+    config::Config,
+    paper::PaperMetadata,
+    prompt,
+    render::{self, PaperNote, Renderer},
+    summarize::Summary,
+    MabelError, Result,
+};
+
+pub fn run(cfg: &Config, action: &TemplateAction) -> Result<()> {
+    match action {
+        | TemplateAction::Show {
+            book,
+            moc,
+            concept,
+            thread,
+        } => {
+            print!(
+                "{}",
+                if *book {
+                    render::BOOK_TEMPLATE
+                } else if *moc {
+                    render::MOC_TEMPLATE
+                } else if *concept {
+                    render::CONCEPT_TEMPLATE
+                } else if *thread {
+                    render::THREAD_TEMPLATE
+                } else {
+                    render::PAPER_TEMPLATE
+                }
+            );
+        }
+        | TemplateAction::Check { path } => {
+            let path = path.as_deref().unwrap_or(&cfg.template_path);
+            let regions = check(cfg, path)?;
+            if regions.is_empty() {
+                println!(
+                    "{}: ok (no managed regions; --overwrite will replace whole notes)",
+                    path.display()
+                );
+            } else {
+                println!("{}: ok (managed regions: {})", path.display(), regions.join(", "));
+            }
+        }
+        | TemplateAction::Prompts { dir } => write_prompts(dir)?,
+    }
+    Ok(())
+}
+
+/// Write the built-in prompt templates into `dir`, keeping any file already there.
+fn write_prompts(dir: &Path) -> Result<()> {
+    let io_err = |path: &Path, source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    std::fs::create_dir_all(dir).map_err(|e| io_err(dir, e))?;
+    for (name, source) in prompt::TEMPLATES {
+        let path = dir.join(name);
+        if path.exists() {
+            println!("{}: exists, left alone", path.display());
+            continue;
+        }
+        std::fs::write(&path, source).map_err(|e| io_err(&path, e))?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Parse the template and render it once against sample data, which also catches references to
+/// variables that do not exist. Returns the managed regions the template produces.
+fn check(cfg: &Config, path: &Path) -> Result<Vec<String>> {
+    let renderer = Renderer::new(&render::load_template(path)?, &cfg.region_markers)?
+        .with_frontmatter_style(cfg.frontmatter_style)
+        .with_frontmatter_fields(cfg.frontmatter.clone());
+    let metadata = PaperMetadata {
+        title: "Sample paper".to_string(),
+        authors: vec!["A. Author".to_string()],
+        ..PaperMetadata::default()
+    };
+    let summary = Summary {
+        tldr: "Sample.".to_string(),
+        ..Summary::default()
+    };
+    let sample = renderer.render_paper(&PaperNote {
+        metadata: &metadata,
+        summary: &summary,
+        figures: &[],
+        related: &[],
+        sections: &[],
+        low_confidence: Vec::new(),
+        bibtex: &bibtex::for_note(cfg, &metadata),
+        moc: None,
+        source: "sample",
+        created: cfg.timezone.timestamp(chrono::Utc::now()),
+        model: cfg.llm.model(),
+        mode: cfg.mode.as_str(),
+    })?;
+    Ok(cfg.region_markers.names(&sample))
+}
@@ -0,0 +1,189 @@
+//! `mabel update`: refresh the metadata of processed arXiv papers, e.g. to pick up the DOI and
+//! journal once a preprint is published.
+//!
+//! Requests are conditional on the validators stored in the registry, so papers whose metadata
+//! has not changed cost a 304 without a body, and several run at once.
+
+use std::sync::Arc;
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    cli::UpdateArgs,
+    config::Config,
+    http::{self, Validators},
+    note::{self, Overwrite},
+    paper::PaperMetadata,
+    registry::Registry,
+    source::arxiv::{ArxivId, ArxivResolver},
+    vault, MabelError, Result,
+};
+
+/// What one re-check found.
+struct Checked {
+    key: String,
+    result: Result<Option<(PaperMetadata, Validators)>>,
+}
+
+/// What [`update_note`] did with the fresh metadata.
+enum NoteUpdate {
+    /// Nothing in the note differed (or the note is gone).
+    Unchanged,
+    /// These fields were written.
+    Written(Vec<&'static str>),
+    /// The user declined the `--preview-diff` change; the note was left as it was.
+    Declined,
+}
+
+pub async fn run(cfg: &Config, args: &UpdateArgs) -> Result<()> {
+    let registry = Registry::load(&cfg.registry_path())?;
+    let http = http::client(cfg)?;
+    let limit = Arc::new(Semaphore::new(args.concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (key, entry) in &registry.papers {
+        let Some(id) = entry.arxiv.as_deref().and_then(|a| ArxivId::parse(a).ok()) else {
+            continue;
+        };
+        // The newest version, not the one that was processed.
+        let id = ArxivId::parse(id.base())?;
+        let validators = entry.validators.clone().unwrap_or_default();
+        let (resolver, limit, key) = (ArxivResolver::new(http.clone(), cfg), limit.clone(), key.clone());
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            let result = resolver
+                .refresh(&id, &validators)
+                .await
+                .map(|found| found.map(|(paper, validators)| (paper.metadata, validators)));
+            Checked { key, result }
+        });
+    }
+    if tasks.is_empty() {
+        println!("no arXiv papers in the registry");
+        return Ok(());
+    }
+
+    let (mut unchanged, mut updated, mut skipped, mut failed) = (0, 0, 0, 0);
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let Ok(checked) = joined else {
+            failed += 1;
+            continue;
+        };
+        match checked.result {
+            | Ok(None) => {
+                unchanged += 1;
+                results.push((checked.key, None));
+            }
+            | Ok(Some((metadata, validators))) => {
+                let entry = &registry.papers[&checked.key];
+                let path = cfg.vault_path.join(&entry.note);
+                match update_note(cfg, args, &path, &metadata).await {
+                    | Err(e) => {
+                        // Leave the validators alone so the next run fetches this one again.
+                        failed += 1;
+                        tracing::warn!(paper = %checked.key, error = %e, "note update failed");
+                        continue;
+                    }
+                    | Ok(NoteUpdate::Unchanged) => unchanged += 1,
+                    | Ok(NoteUpdate::Written(changed)) => {
+                        updated += 1;
+                        println!("updated {} ({})", entry.note.display(), changed.join(", "));
+                    }
+                    | Ok(NoteUpdate::Declined) => {
+                        // Keep the old validators so the change is offered again next time.
+                        skipped += 1;
+                        results.push((checked.key, None));
+                        continue;
+                    }
+                }
+                results.push((checked.key, Some((metadata, validators))));
+            }
+            | Err(e) => {
+                failed += 1;
+                tracing::warn!(paper = %checked.key, error = %e, "metadata check failed");
+            }
+        }
+    }
+
+    let now = cfg.timezone.timestamp(chrono::Utc::now());
+    Registry::update(&cfg.registry_path(), |registry| {
+        for (key, fresh) in results {
+            let Some(entry) = registry.papers.get_mut(&key) else {
+                continue;
+            };
+            entry.checked = Some(now.clone());
+            if let Some((metadata, validators)) = fresh {
+                entry.doi = metadata.doi.or(entry.doi.take());
+                entry.arxiv = metadata.arxiv_id.or(entry.arxiv.take());
+                entry.validators = Some(validators);
+            }
+        }
+    })?;
+    println!("{updated} updated, {unchanged} unchanged, {skipped} skipped, {failed} failed");
+    Ok(())
+}
+
+/// Write newly available metadata into the note's frontmatter. Only fields that gain a
+/// (different) value are touched, so hand edits elsewhere are kept. With `--preview-diff` the
+/// change is shown first and nothing is written if the user says no.
+async fn update_note(
+    cfg: &Config,
+    args: &UpdateArgs,
+    path: &std::path::Path,
+    metadata: &PaperMetadata,
+) -> Result<NoteUpdate> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let Ok(text) = tokio::fs::read_to_string(path).await else {
+        tracing::warn!(path = %path.display(), "note listed in the registry no longer exists");
+        return Ok(NoteUpdate::Unchanged);
+    };
+    let (current, repaired) = vault::split_frontmatter(&text)
+        .0
+        .and_then(|yaml| vault::frontmatter_repaired(yaml, &cfg.frontmatter))
+        .unwrap_or_default();
+    let fields = [
+        ("doi", metadata.doi.as_ref(), current.doi.as_ref()),
+        ("journal", metadata.journal.as_ref(), current.journal.as_ref()),
+        ("arxiv", metadata.arxiv_id.as_ref(), current.arxiv.as_ref()),
+        ("url", metadata.url.as_ref(), current.url.as_ref()),
+    ];
+    let mut changed = Vec::new();
+    let mut updated = text.clone();
+    for (key, fresh, old) in fields {
+        let Some(fresh) = fresh.filter(|f| !f.trim().is_empty()) else {
+            continue;
+        };
+        if old.is_some_and(|o| o.trim() == fresh.trim()) {
+            continue;
+        }
+        let Some(property) = cfg.frontmatter.name(key) else {
+            continue;
+        };
+        updated = vault::set_frontmatter_field(&updated, property, &serde_json::to_string(fresh.trim())?);
+        changed.push(key);
+    }
+    if changed.is_empty() {
+        return Ok(NoteUpdate::Unchanged);
+    }
+    vault::warn_repaired(path, &repaired);
+    if args.preview_diff
+        && !note::confirm_write(
+            path,
+            &updated,
+            Overwrite::Force,
+            &cfg.region_markers,
+            args.yes,
+            cfg.can_ask(),
+        )
+        .await?
+    {
+        return Ok(NoteUpdate::Declined);
+    }
+    tokio::fs::write(path, updated).await.map_err(io_err)?;
+    Ok(NoteUpdate::Written(changed))
+}
@@ -0,0 +1,298 @@
+//! `mabel watch <dir>`: process the PDFs that land in a folder, such as the browser's downloads,
+//! as they arrive, and file each one in the vault's `PDFs` folder under its note's name.
+//!
+//! A PDF is taken once nothing has happened to it for [`QUIET`], so one still being downloaded is
+//! left alone, and PDFs are processed one at a time. What was processed is kept in the cache by
+//! the PDF's content (see [`Config::watch_state_path`]): on a restart the PDFs already in the
+//! folder are gone through, and only those without a note yet are processed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ci,
+    cli::{OutputFormat, WatchArgs},
+    clock::Zone,
+    config::Config,
+    memory,
+    output::{self, Record, Status},
+    pdf,
+    pipeline::Pipeline,
+    store, vault, MabelError, Result,
+};
+
+/// How long a PDF has to be left alone before it is taken for whole.
+const QUIET: Duration = Duration::from_secs(3);
+
+/// How often PDFs that have gone quiet are looked for.
+const TICK: Duration = Duration::from_secs(1);
+
+/// A PDF `mabel watch` has processed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Watched {
+    /// SHA-256 of the PDF, as hex
+    sha256: String,
+    /// Where the PDF was found
+    file: PathBuf,
+    /// The note written from it; `None` when processing failed, which is tried again on the next
+    /// start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    processed: String,
+}
+
+/// What processing a PDF takes.
+struct Watch {
+    pipeline: Pipeline,
+    state: PathBuf,
+    pdfs: PathBuf,
+    link: bool,
+    timezone: Zone,
+    format: OutputFormat,
+}
+
+pub async fn run(cfg: Config, args: &WatchArgs) -> Result<()> {
+    let dir = std::path::absolute(&args.dir).map_err(|source| {
+        MabelError::Io {
+            path: args.dir.clone(),
+            source,
+        }
+    })?;
+    if !dir.is_dir() {
+        return Err(MabelError::Config {
+            msg: format!("{} is not a folder", dir.display()),
+        });
+    }
+    let watch_err = |source| {
+        MabelError::Watch {
+            dir: dir.clone(),
+            source,
+        }
+    };
+    let (tx, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .map_err(watch_err)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive).map_err(watch_err)?;
+
+    let watch = Watch {
+        state: cfg.watch_state_path(),
+        pdfs: cfg.pdfs_dir(),
+        link: args.link,
+        timezone: cfg.timezone,
+        format: args.output.format,
+        pipeline: Pipeline::new(cfg)?,
+    };
+    let mut done: HashSet<String> = store::load::<Watched>(&watch.state)
+        .await?
+        .into_iter()
+        .filter(|w| w.note.is_some())
+        .map(|w| w.sha256)
+        .collect();
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    if !args.new_only {
+        // What arrived while nothing was watching.
+        let entries = std::fs::read_dir(&dir).map_err(|source| {
+            MabelError::Io {
+                path: dir.clone(),
+                source,
+            }
+        })?;
+        for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+            if is_pdf_name(&path) {
+                pending.insert(path, Instant::now().checked_sub(QUIET).unwrap_or_else(Instant::now));
+            }
+        }
+    }
+
+    output::say(watch.format, format_args!("watching {} for PDFs", dir.display()));
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                match event {
+                    | Some(Ok(event)) => arrived(&mut pending, event),
+                    | Some(Err(e)) => tracing::warn!(error = %e, "file watch error"),
+                    | None => return Ok(()),
+                }
+            }
+            _ = tick.tick() => {
+                let quiet: Vec<PathBuf> = pending
+                    .iter()
+                    .filter(|(_, seen)| seen.elapsed() >= QUIET)
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                for path in quiet {
+                    pending.remove(&path);
+                    process(&watch, &path, &mut done).await;
+                }
+            }
+        }
+    }
+}
+
+/// Note the PDFs an event is about: each change puts a PDF's quiet period back to the start.
+fn arrived(pending: &mut HashMap<PathBuf, Instant>, event: notify::Event) {
+    match event.kind {
+        | EventKind::Create(_) | EventKind::Modify(_) => {
+            for path in event.paths.into_iter().filter(|p| is_pdf_name(p)) {
+                pending.insert(path, Instant::now());
+            }
+        }
+        | EventKind::Remove(_) => {
+            for path in &event.paths {
+                pending.remove(path);
+            }
+        }
+        | _ => {}
+    }
+}
+
+fn is_pdf_name(path: &Path) -> bool {
+    path.extension().is_some_and(|x| x.eq_ignore_ascii_case("pdf"))
+}
+
+/// Process the PDF at `path` unless a note was written from the same PDF before. Failures are
+/// logged and recorded; watching goes on.
+async fn process(watch: &Watch, path: &Path, done: &mut HashSet<String>) {
+    // Gone again, or moved on by its name.
+    if !path.is_file() {
+        return;
+    }
+    let sha256 = match sha256(path).await {
+        | Ok(sha256) => sha256,
+        | Err(e) => {
+            tracing::warn!(pdf = %path.display(), error = %e, "could not read the PDF");
+            return;
+        }
+    };
+    let head = memory::read_head(path, pdf::HEADER_WINDOW as u64).await;
+    if !head.is_ok_and(|head| pdf::is_pdf(&head)) {
+        tracing::warn!(pdf = %path.display(), "not a PDF; skipped");
+        return;
+    }
+    if !done.insert(sha256.clone()) {
+        tracing::debug!(pdf = %path.display(), "processed before");
+        return;
+    }
+
+    tracing::info!(pdf = %path.display(), "new PDF");
+    let input = path.to_string_lossy();
+    let (result, logged) = ci::collect(Box::pin(watch.pipeline.run(&input))).await;
+    let (note, error) = match result {
+        | Ok(outcome) => {
+            match watch.format {
+                | OutputFormat::Text => println!("{}", outcome.path.display()),
+                | OutputFormat::Json => Record::written(&input, &outcome, &logged).print(),
+            }
+            match file(watch, path, &outcome.path).await {
+                | Ok(filed) => tracing::info!(pdf = %filed.display(), "PDF filed in the vault"),
+                | Err(e) => tracing::warn!(pdf = %path.display(), error = %e, "could not file the PDF in the vault"),
+            }
+            (Some(outcome.path), None)
+        }
+        | Err(e) => {
+            tracing::warn!(pdf = %path.display(), error = %e.root(), "PDF failed");
+            if watch.format == OutputFormat::Json {
+                Record::failed(&input, Status::of(&e), &e, &logged).print();
+            }
+            (None, Some(e.root().to_string()))
+        }
+    };
+    let record = Watched {
+        sha256: sha256.clone(),
+        file: path.to_path_buf(),
+        note,
+        error,
+        processed: watch.timezone.timestamp(chrono::Utc::now()),
+    };
+    if let Err(e) = store::replace(&watch.state, &[record], |w| w.sha256 == sha256).await {
+        tracing::warn!(error = %e, "could not record the PDF as processed");
+    }
+}
+
+/// Move (or with `--link`, link) the PDF into the vault's PDFs folder under its note's name, and
+/// point the note's `pdf` field at it. Returns where it went.
+async fn file(watch: &Watch, pdf: &Path, note: &Path) -> Result<PathBuf> {
+    let stem = note.file_stem().unwrap_or_default().to_string_lossy();
+    let filed = watch.pdfs.join(format!("{stem}.pdf"));
+    let io_err = |source| {
+        MabelError::Io {
+            path: filed.clone(),
+            source,
+        }
+    };
+    tokio::fs::create_dir_all(&watch.pdfs).await.map_err(io_err)?;
+    // The PDF of an earlier version of the paper.
+    if tokio::fs::symlink_metadata(&filed).await.is_ok() {
+        tokio::fs::remove_file(&filed).await.map_err(io_err)?;
+    }
+    if watch.link {
+        link(pdf, &filed).await.map_err(io_err)?;
+    } else if tokio::fs::rename(pdf, &filed).await.is_err() {
+        // A rename cannot cross file systems.
+        tokio::fs::copy(pdf, &filed).await.map_err(io_err)?;
+        tokio::fs::remove_file(pdf).await.map_err(|source| {
+            MabelError::Io {
+                path: pdf.to_path_buf(),
+                source,
+            }
+        })?;
+    }
+    let text = tokio::fs::read_to_string(note).await.map_err(|source| {
+        MabelError::Io {
+            path: note.to_path_buf(),
+            source,
+        }
+    })?;
+    let value = serde_json::to_string(&format!("[[{stem}.pdf]]"))?;
+    tokio::fs::write(note, vault::set_frontmatter_field(&text, "pdf", &value))
+        .await
+        .map_err(|source| {
+            MabelError::Io {
+                path: note.to_path_buf(),
+                source,
+            }
+        })?;
+    Ok(filed)
+}
+
+#[cfg(unix)]
+async fn link(pdf: &Path, filed: &Path) -> std::io::Result<()> {
+    tokio::fs::symlink(pdf, filed).await
+}
+
+#[cfg(windows)]
+async fn link(pdf: &Path, filed: &Path) -> std::io::Result<()> {
+    tokio::fs::symlink_file(pdf, filed).await
+}
+
+/// SHA-256 of the file at `path`, read a chunk at a time.
+async fn sha256(path: &Path) -> std::io::Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut chunk = vec![0; memory::CHUNK];
+        loop {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(hex::encode(hasher.finalize()));
+            }
+            hasher.update(&chunk[..read]);
+        }
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
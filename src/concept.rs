@@ -0,0 +1,78 @@
+//! Concept notes for the technical terms a summary uses (`--define-new-terms`). Each term is
+//! linked to the vault's note on it, found anywhere in the vault by its title or file name; a term
+//! without one gets a short note in `Concepts/` from the concept template, with the definition the
+//! model gave and a link back to the paper. Only [`Config::max_new_terms`] are written per paper, so
+//! one paper cannot flood the vault; terms past that are listed without a link.
+//!
+//! Like a MOC, a concept note that exists is never touched again.
+
+use std::path::PathBuf;
+
+use crate::{
+    config::Config,
+    note,
+    render::{ConceptNote, Renderer},
+    summarize::Term,
+    vault::{self, VaultNote},
+    MabelError, Result,
+};
+
+/// A concept note to write once the paper's note is.
+#[derive(Debug)]
+pub struct Planned {
+    pub term: String,
+    pub definition: String,
+    pub path: PathBuf,
+}
+
+/// Point each term at its note in `notes`, or at a new concept note while there are fewer than
+/// [`Config::max_new_terms`]. Returns the concept notes to write.
+pub fn plan(cfg: &Config, notes: &[VaultNote], terms: &mut [Term]) -> Vec<Planned> {
+    let mut planned: Vec<Planned> = Vec::new();
+    for term in terms {
+        if let Some(existing) = vault::find_topic(notes, &term.term) {
+            term.link = Some(existing.link.clone());
+            continue;
+        }
+        let stem = note::file_stem(&term.term);
+        let path = cfg.concepts_dir().join(format!("{stem}.md"));
+        if planned.iter().any(|p| p.path == path) {
+            term.link = Some(stem);
+        } else if planned.len() < cfg.max_new_terms as usize {
+            term.link = Some(stem);
+            planned.push(Planned {
+                term: term.term.clone(),
+                definition: term.definition.clone(),
+                path,
+            });
+        }
+    }
+    planned
+}
+
+/// Write a planned concept note; `paper` is the file stem of the note it came up in.
+pub async fn create(cfg: &Config, renderer: &Renderer, planned: &Planned, paper: &str) -> Result<()> {
+    let path = &planned.path;
+    let text = renderer.render_concept(&ConceptNote {
+        term: &planned.term,
+        definition: &planned.definition,
+        paper,
+        created: cfg.timezone.timestamp(chrono::Utc::now()),
+    })?;
+    let dir = cfg.concepts_dir();
+    tokio::fs::create_dir_all(&dir).await.map_err(|source| {
+        MabelError::Io {
+            path: dir.clone(),
+            source,
+        }
+    })?;
+    match note::write(path, &text, false).await {
+        // Another paper got there first.
+        | Err(MabelError::NoteExists { .. }) => Ok(()),
+        | Err(e) => Err(e),
+        | Ok(()) => {
+            tracing::info!(path = %path.display(), term = planned.term, "created concept note");
+            Ok(())
+        }
+    }
+}
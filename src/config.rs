@@ -1,28 +1,77 @@
-use crate::{MabelError, Result};
-use dirs;
+use crate::{
+    ci::Severity,
+    cli::{Cli, Command, ConfigAction},
+    clock::Zone,
+    config_file,
+    extract::epub::ChapterSelection,
+    http::{self, ServiceAuth},
+    memory,
+    note::Overwrite,
+    region::{self, RegionMarkers},
+    render::{FrontmatterFields, FrontmatterStyle},
+    routing::RoutingPolicy,
+    secret::{self, Secret},
+    stop_sections::StopSections,
+    source::arxiv::{API_URL, PDF_URL},
+    MabelError, Result,
+};
 use std::{
-    env,
+    collections::BTreeMap,
+    env, fmt,
     fs::{self, OpenOptions},
-    io::Write,
+    io::{IsTerminal, Write},
     path::{Path, PathBuf},
+    str::FromStr,
     time::Duration as StdDuration,
 };
 use url::Url;
 
+/// Template used when `--template` is not given; if it does not exist the built-in one is used.
+pub const DEFAULT_TEMPLATE_PATH: &str = "templates/paper_note.md.tera";
+
 /// What LLM backend to use.
 #[derive(Clone, Debug)]
 pub enum LlmBackend {
     OpenAi {
-        api_key: String,
+        api_key: Secret,
+        /// OpenAI-compatible server to use instead of api.openai.com (`OPENAI_BASE_URL`)
+        base_url: Option<Url>,
         model: String, // e.g., "gpt-4o-mini"
         max_tokens: u32,
         temperature: f32,
+        auth: ServiceAuth,
+        /// Stream replies, showing progress on a terminal (`MABEL_STREAM`)
+        stream: bool,
     },
     Ollama {
         host: Url,     // e.g., http://localhost:11434
         model: String, // e.g., "llama3:8b-instruct"
         max_tokens: u32,
         temperature: f32,
+        auth: ServiceAuth,
+        /// How long the server keeps the model loaded after a request (`OLLAMA_KEEP_ALIVE`);
+        /// the server's own default when unset
+        keep_alive: Option<KeepAlive>,
+        /// Context window in tokens (`OLLAMA_NUM_CTX`), instead of the model's default
+        num_ctx: Option<u32>,
+        /// Load the model while the first paper is resolved (`OLLAMA_PRELOAD`)
+        preload: bool,
+        /// Pull the model when the server does not have it, without asking (`OLLAMA_PULL`)
+        pull: bool,
+        /// Stream replies, showing progress on a terminal (`MABEL_STREAM`)
+        stream: bool,
+    },
+    Anthropic {
+        api_key: Secret,
+        model: String, // e.g., "claude-sonnet-4-5"
+        max_tokens: u32,
+        temperature: f32,
+    },
+    Gemini {
+        api_key: Secret,
+        model: String, // e.g., "gemini-2.5-flash"
+        max_tokens: u32,
+        temperature: f32,
     },
 }
 
@@ -33,161 +82,1091 @@ pub enum Mode {
     Concise,
     /// Longer method/results/glossary
     Study,
+    /// Tutorial-style explanation for a first-year grad student, with analogies and the topics to
+    /// know first
+    EliGrad,
+    /// Five-bullet first impression from a few page images, for triage (see [`crate::skim`])
+    Skim,
+    /// Question and answer cards for spaced repetition, in the note and optionally for Anki (see
+    /// [`crate::flashcards`])
+    Flashcards,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            | Mode::Concise => "concise",
+            | Mode::Study => "study",
+            | Mode::EliGrad => "eli-grad",
+            | Mode::Skim => "skim",
+            | Mode::Flashcards => "flashcards",
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            | "concise" => Ok(Self::Concise),
+            | "study" => Ok(Self::Study),
+            | "eli-grad" => Ok(Self::EliGrad),
+            | "skim" => Ok(Self::Skim),
+            | "flashcards" => Ok(Self::Flashcards),
+            | _ => {
+                Err(MabelError::Config {
+                    msg: format!("unknown mode {s:?} (expected concise, study, eli-grad, skim or flashcards)"),
+                })
+            }
+        }
+    }
+}
+
+/// GROBID's `consolidateHeader`/`consolidateCitations` setting: whether parsed metadata is looked
+/// up in Crossref (or the biblio-glutton instance GROBID is configured with) to correct it and add
+/// identifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consolidation {
+    /// Keep what was parsed from the PDF
+    None,
+    /// Replace the parsed fields with the matched record
+    Full,
+    /// Keep the parsed fields and only add the matched DOI
+    DoiOnly,
+}
+
+impl Consolidation {
+    /// Value of the GROBID request parameter.
+    pub fn as_param(self) -> &'static str {
+        match self {
+            | Self::None => "0",
+            | Self::Full => "1",
+            | Self::DoiOnly => "2",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::None => "none",
+            | Self::Full => "full",
+            | Self::DoiOnly => "doi",
+        }
+    }
+}
+
+impl FromStr for Consolidation {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            | "0" | "none" | "off" | "false" => Ok(Self::None),
+            | "1" | "full" | "on" | "true" => Ok(Self::Full),
+            | "2" | "doi" => Ok(Self::DoiOnly),
+            | _ => {
+                Err(MabelError::Config {
+                    msg: format!("unknown GROBID consolidation {s:?} (expected none, full or doi; or 0, 1, 2)"),
+                })
+            }
+        }
+    }
+}
+
+/// Where the alt text of the figures embedded in paper notes comes from (`MABEL_FIGURE_ALT`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FigureAlt {
+    /// Leave figures out of notes
+    Off,
+    /// The first sentence of the caption
+    Caption,
+    /// The model's description of the image (it must accept images), or the caption where that
+    /// fails
+    Vision,
+}
+
+impl FigureAlt {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Off => "off",
+            | Self::Caption => "caption",
+            | Self::Vision => "vision",
+        }
+    }
+}
+
+impl FromStr for FigureAlt {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            | "off" | "none" | "false" => Ok(Self::Off),
+            | "caption" => Ok(Self::Caption),
+            | "vision" => Ok(Self::Vision),
+            | _ => {
+                Err(MabelError::Config {
+                    msg: format!("unknown figure alt text source {s:?} (expected off, caption or vision)"),
+                })
+            }
+        }
+    }
+}
+
+/// How long Ollama keeps a model in memory after a request: `30s`, `10m`, `2h` or a number of
+/// seconds, `0` to unload it at once, and `-1` to keep it for as long as the server runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeepAlive {
+    Forever,
+    Secs(u64),
+}
+
+impl fmt::Display for KeepAlive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            | Self::Forever => f.write_str("-1"),
+            | Self::Secs(secs) => write!(f, "{secs}s"),
+        }
+    }
+}
+
+impl FromStr for KeepAlive {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        // Ollama takes any negative duration to mean for ever.
+        if trimmed.strip_prefix('-').is_some_and(|n| n.parse::<u64>().is_ok()) {
+            return Ok(Self::Forever);
+        }
+        let (number, unit) = match trimmed.char_indices().last() {
+            | Some((at, 's')) => (&trimmed[..at], 1),
+            | Some((at, 'm')) => (&trimmed[..at], 60),
+            | Some((at, 'h')) => (&trimmed[..at], 60 * 60),
+            | _ => (trimmed, 1),
+        };
+        number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .map(Self::Secs)
+            .ok_or_else(|| {
+                MabelError::Config {
+                    msg: format!("invalid keep-alive {s:?}: expected e.g. 30s, 10m or 2h, 0, or -1 for ever"),
+                }
+            })
+    }
+}
+
+impl LlmBackend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            | LlmBackend::OpenAi { .. } => "openai",
+            | LlmBackend::Ollama { .. } => "ollama",
+            | LlmBackend::Anthropic { .. } => "anthropic",
+            | LlmBackend::Gemini { .. } => "gemini",
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        match self {
+            | LlmBackend::OpenAi { model, .. }
+            | LlmBackend::Ollama { model, .. }
+            | LlmBackend::Anthropic { model, .. }
+            | LlmBackend::Gemini { model, .. } => model,
+        }
+    }
+
+    pub fn max_tokens(&self) -> u32 {
+        match self {
+            | LlmBackend::OpenAi { max_tokens, .. }
+            | LlmBackend::Ollama { max_tokens, .. }
+            | LlmBackend::Anthropic { max_tokens, .. }
+            | LlmBackend::Gemini { max_tokens, .. } => *max_tokens,
+        }
+    }
+
+    pub fn temperature(&self) -> f32 {
+        match self {
+            | LlmBackend::OpenAi { temperature, .. }
+            | LlmBackend::Ollama { temperature, .. }
+            | LlmBackend::Anthropic { temperature, .. }
+            | LlmBackend::Gemini { temperature, .. } => *temperature,
+        }
+    }
+
+    /// Same backend with a different model and/or temperature.
+    #[must_use]
+    pub fn with_overrides(&self, model: Option<&str>, temp: Option<f32>) -> Self {
+        let mut out = self.clone();
+        match &mut out {
+            | LlmBackend::OpenAi {
+                model: m,
+                temperature: t,
+                ..
+            }
+            | LlmBackend::Ollama {
+                model: m,
+                temperature: t,
+                ..
+            }
+            | LlmBackend::Anthropic {
+                model: m,
+                temperature: t,
+                ..
+            }
+            | LlmBackend::Gemini {
+                model: m,
+                temperature: t,
+                ..
+            } => {
+                if let Some(model) = model {
+                    *m = model.to_string();
+                }
+                if let Some(temp) = temp {
+                    *t = temp;
+                }
+            }
+        }
+        out
+    }
 }
 
 #[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
     /// Obsidian
     pub vault_path: PathBuf,
     pub vault_subdir: String,
     pub copy_pdf_into_vault: bool,
+    /// Write each paper's extracted full text into the vault too (`--text-sidecar`,
+    /// `MABEL_TEXT_SIDECAR`); see [`crate::fulltext`]
+    pub text_sidecar: bool,
+    /// Write a thread of short posts on each paper next to its note (`--thread`, `MABEL_THREAD`);
+    /// see [`crate::thread`]
+    pub thread: bool,
+    /// The longest a thread post may be, links counting 23 (`MABEL_THREAD_CHARS`; 500, Mastodon's)
+    pub thread_chars: u32,
+    /// Template threads are made from (`MABEL_THREAD_TEMPLATE`; the built-in one if None)
+    pub thread_template: Option<PathBuf>,
 
     /// Cache & IO
     pub cache_dir: PathBuf,
-    pub overwrite_note: bool,
+    /// What writing a note does to one that is already there (`--overwrite`,
+    /// `MABEL_OVERWRITE_NOTE`, `--force-overwrite`)
+    pub overwrite_note: Overwrite,
+    /// Show the diff and ask before replacing an existing note (`--preview-diff`); `--yes` skips
+    /// the question
+    pub preview_diff: bool,
+    pub assume_yes: bool,
+    /// Show the metadata candidates of a local PDF whose title is uncertain and ask which is right
+    /// (not under `--no-interactive`); see [`crate::source::local`]
+    pub confirm_metadata: bool,
+    /// Run unattended (`--ci`, `MABEL_CI`): never ask, and fail papers on what they log at
+    /// `fail_on` or above; see [`crate::ci`]
+    pub ci: bool,
+    /// Least severe log that fails a paper under `--ci` (`MABEL_FAIL_ON`)
+    pub fail_on: Severity,
+    /// Never ask, whatever stdin is: set by commands whose papers nobody at the terminal is
+    /// waiting on one at a time (`serve`, and `batch` with papers side by side)
+    pub non_interactive: bool,
 
     /// LLM
     pub llm: LlmBackend,
+    /// Per-paper model overrides (`MABEL_ROUTING`)
+    pub routing: RoutingPolicy,
+    /// Context given for this run with `--var key=value`, for the summary prompts and the note
+    /// templates
+    pub vars: BTreeMap<String, String>,
+    /// Longest paper, in PDF pages, summarized without asking first (`MABEL_MAX_PAGES`, 0 for no
+    /// limit)
+    pub max_pages: Option<u32>,
+    /// Have the model mark the statements it is unsure of, which notes show in italics with
+    /// "(uncertain)" (`MABEL_UNCERTAINTY`)
+    pub uncertainty: bool,
+    /// Most statements a summary may have marked uncertain before its note is not written
+    /// (`MABEL_MAX_UNCERTAIN`, 0 for no limit)
+    pub max_uncertain: Option<u32>,
+    /// Check the summary's direct quotes and numbers against the paper's text, marking the
+    /// statements it does not bear out with ⚠️ (`MABEL_VERIFY_CLAIMS`, on when `max_unverified` is
+    /// set)
+    pub verify_claims: bool,
+    /// Most statements a summary may have marked unverified before its note is not written, each
+    /// counting for as much as the extracted text can be trusted (`MABEL_MAX_UNVERIFIED`, 0 for no
+    /// limit)
+    pub max_unverified: Option<u32>,
+    /// Lowest extraction quality (the sections' confidence, weighted by length) a paper is
+    /// summarized in full at; below it the summary is written from the abstract, introduction and
+    /// conclusion only, under a warning (`MABEL_MIN_EXTRACTION_QUALITY`, 0 to always summarize in
+    /// full)
+    pub min_extraction_quality: f32,
+    /// Sections left out of the text sent to the model (`MABEL_STOP_SECTIONS`); see
+    /// [`crate::stop_sections`]
+    pub stop_sections: StopSections,
+    /// Most a paper's summary may cost, in US dollars, before it is refused
+    /// (`MABEL_MAX_COST_PER_PAPER`, 0 for no limit); see [`crate::cost`]
+    pub max_cost_per_paper: Option<f64>,
+    /// Most the papers processed in a day may cost, in US dollars (`MABEL_MAX_COST_PER_DAY`, 0 for
+    /// no limit)
+    pub max_cost_per_day: Option<f64>,
+    /// Summarize a paper too long for one prompt in parts, then from the notes on them, instead of
+    /// cutting it short (`MABEL_MAP_REDUCE`); see [`crate::chunk`]
+    pub map_reduce: bool,
+    /// Longest part, in characters (`MABEL_CHUNK_CHARS`; from the model's context window if None)
+    pub chunk_chars: Option<u32>,
+    /// Characters each part repeats from the end of the one before (`MABEL_CHUNK_OVERLAP`)
+    pub chunk_overlap: u32,
 
     /// Extraction
     pub grobid_url: Option<Url>,
+    /// Headers and credentials for a GROBID behind a proxy (`GROBID_HEADERS`, ...)
+    pub grobid_auth: ServiceAuth,
+    /// Consolidation of the paper's own metadata (`MABEL_GROBID_CONSOLIDATE_HEADER`)
+    pub grobid_consolidate_header: Consolidation,
+    /// Consolidation of each reference (`MABEL_GROBID_CONSOLIDATE_CITATIONS`); slow, one lookup per
+    /// reference, but gives references the DOIs that link them to vault notes
+    pub grobid_consolidate_citations: Consolidation,
+    /// Read PDFs with the built-in extractor when there is no GROBID server or it fails
+    /// (`MABEL_PDF_FALLBACK`); see [`crate::extract::pdftext`]
+    pub pdf_fallback: bool,
+    /// Write the note from the metadata first and fill in the summary once PDF extraction and
+    /// summarization finish (`MABEL_TIERED`)
+    pub tiered: bool,
+
+    /// NCBI E-utilities (PubMed/PMC); a key raises the rate limit from 3 to 10 req/s
+    pub ncbi_api_key: Option<Secret>,
+    pub ncbi_email: Option<String>,
+    /// Contact address Unpaywall requires to look up open-access PDFs of DOI inputs, also sent to
+    /// Crossref (`UNPAYWALL_EMAIL`)
+    pub unpaywall_email: Option<String>,
+
+    /// Look up authors' ORCID iDs in OpenAlex (`MABEL_ORCID`)
+    pub orcid: bool,
+    /// arXiv API metadata comes from (`MABEL_ARXIV_API_URL`; `export.arxiv.org` by default)
+    pub arxiv_api_url: Url,
+    /// Folder arXiv PDFs are downloaded from by id (`MABEL_ARXIV_PDF_URL`; `arxiv.org/pdf/` by
+    /// default)
+    pub arxiv_pdf_url: Url,
+
+    /// Books: which EPUB chapters to process (all if None)
+    pub chapters: Option<ChapterSelection>,
 
     /// HTTP/runtime
     pub http_timeout: StdDuration,
+    /// Times a request is sent again when it failed in a way that may pass (`MABEL_HTTP_RETRIES`);
+    /// see [`crate::retry`]
     pub http_retries: u32,
+    /// Model requests started in any minute, at most (`MABEL_RATE_PER_MIN`; 0 for no limit); see
+    /// [`crate::limits`]
     pub rate_limit_per_min: u32,
+    /// PDF downloads at once, across the papers of a run (`MABEL_MAX_CONCURRENT_DOWNLOADS`)
+    pub max_concurrent_downloads: u32,
+    /// PDF extractions at once (`MABEL_MAX_CONCURRENT_EXTRACTIONS`)
+    pub max_concurrent_extractions: u32,
+    /// Model requests at once (`MABEL_MAX_CONCURRENT_LLM`)
+    pub max_concurrent_llm: u32,
+    /// Bytes per second PDFs are downloaded at, at most (`MABEL_MAX_BANDWIDTH`)
+    pub max_bandwidth: Option<u64>,
+    /// Most memory a PDF may take, in bytes (`MABEL_MAX_MEMORY`); see [`crate::memory`]
+    pub max_memory: Option<u64>,
+    /// Don't download PDFs: notes are written from the abstract and their papers queued for
+    /// `mabel flush-queue` (`MABEL_METERED`); see [`crate::queue`]
+    pub metered: bool,
+
+    /// Show the model existing vault notes on cited/related work (`MABEL_VAULT_CONTEXT`)
+    pub vault_context: bool,
+    /// Extract claims into the claim store after writing each paper note (`MABEL_CLAIMS`)
+    pub extract_claims: bool,
+    /// Extract results and update the topic leaderboards after writing each paper note
+    /// (`MABEL_LEADERBOARDS`)
+    pub leaderboards: bool,
+    /// Embed the paper's figures in its note, with alt text from this source (`MABEL_FIGURE_ALT`);
+    /// see [`crate::figures`]
+    pub figure_alt: FigureAlt,
+    /// Crop figures out of the PDF, for papers whose source does not link their images
+    /// (`MABEL_PDF_FIGURES`); needs poppler's `pdftoppm` and `pdfimages`
+    pub pdf_figures: bool,
+    /// Where figures cropped from PDFs are saved, in a folder per note, relative to the vault unless
+    /// absolute (`MABEL_ATTACHMENTS_DIR`, default `Attachments`)
+    pub attachments_dir: PathBuf,
+    /// Link each arXiv paper to the MOC of its primary category, creating the MOC when the vault
+    /// has none (`MABEL_MOCS`); see [`crate::moc`]
+    pub mocs: bool,
+    /// Template new MOCs are made from (`MABEL_MOC_TEMPLATE`; the built-in one if None)
+    pub moc_template: Option<PathBuf>,
+    /// Link the technical terms each summary uses to their vault notes, writing a concept note for
+    /// those without one (`--define-new-terms`, `MABEL_DEFINE_NEW_TERMS`); see [`crate::concept`]
+    pub define_new_terms: bool,
+    /// Most concept notes written per paper (`MABEL_MAX_NEW_TERMS`)
+    pub max_new_terms: u32,
+    /// Template concept notes are made from (`MABEL_CONCEPT_TEMPLATE`; the built-in one if None)
+    pub concept_template: Option<PathBuf>,
+    /// Also write each flashcards-mode note's cards to this folder, for Anki (`MABEL_ANKI_DIR`);
+    /// see [`crate::flashcards`]
+    pub anki_dir: Option<PathBuf>,
+    /// Add each paper's BibTeX entry to this `.bib` file, relative to the vault unless absolute
+    /// (`MABEL_BIB_FILE`, e.g. `references.bib`); see [`crate::bibtex`]
+    pub bib_file: Option<PathBuf>,
+
+    /// POST every note written, with its metadata and summary, to this URL (`MABEL_WEBHOOK_URL`)
+    pub webhook_url: Option<Url>,
+    /// Key the webhook body is signed with (`MABEL_WEBHOOK_SECRET`); see [`crate::webhook`]
+    pub webhook_secret: Option<Secret>,
+
+    /// Users of a shared `mabel serve`, with their tokens, vaults and quotas (`MABEL_SERVE_USERS`);
+    /// see [`crate::commands::serve`]
+    pub serve_users: Option<PathBuf>,
+
+    /// Recommendations: embedding model (`MABEL_EMBEDDING_MODEL`; the backend's default if None)
+    pub embedding_model: Option<String>,
+    /// arXiv categories the digest draws from (`MABEL_DIGEST_CATEGORIES`, comma-separated)
+    pub digest_categories: Vec<String>,
 
     /// Rendering
     pub template_path: PathBuf,
+    /// Schema paper notes' frontmatter is checked against before writing
+    /// (`MABEL_FRONTMATTER_SCHEMA`); see [`crate::schema`]
+    pub frontmatter_schema: Option<PathBuf>,
+    /// How notes' frontmatter is laid out (`MABEL_FRONTMATTER_STYLE`)
+    pub frontmatter_style: FrontmatterStyle,
+    /// The properties paper notes' frontmatter fields are written as, and their types: the
+    /// `[frontmatter]` config section (`MABEL_FRONTMATTER`)
+    pub frontmatter: FrontmatterFields,
     pub mode: Mode,
+    /// Summary prompt templates replacing the built-in ones of the same name (`MABEL_PROMPT_DIR`);
+    /// see [`crate::prompt::Prompts`]
+    pub prompt_dir: Option<PathBuf>,
+    /// System prompt template replacing the mode's (`--prompt-template`, `MABEL_PROMPT_TEMPLATE`)
+    pub prompt_template: Option<PathBuf>,
+    /// Delimiters of the regions mabel rewrites when updating a note
+    pub region_markers: RegionMarkers,
+    /// Zone for dates in filenames/frontmatter and daily-note day boundaries
+    pub timezone: Zone,
+
+    /// Config files that were read, user file first (see [`crate::config_file`])
+    pub config_files: Vec<PathBuf>,
+    /// Profile whose settings were applied from them (`--profile`, `MABEL_PROFILE`)
+    pub profile: Option<String>,
 }
 
 impl Config {
-    /// Build from CLI flags + env; do path and permission checks.
-    pub fn load(cli: &crate::cli::Cli) -> Result<Self> {
+    /// Build from CLI flags + env + config files; do path and permission checks.
+    #[allow(clippy::too_many_lines)]
+    pub fn load(cli: &Cli) -> Result<Self> {
         let _ = dotenvy::dotenv();
+        let flags = &cli.global;
+        let output = cli.command.output();
 
-        let vault_path = cli
+        if let Some(profile) = &flags.profile {
+            env::set_var("MABEL_PROFILE", profile);
+        }
+        let profile = env::var("MABEL_PROFILE").ok().filter(|p| !p.is_empty());
+        let mut config_files = Vec::new();
+        if let Some(path) = config_file::user_path() {
+            if config_file::apply(&path, false)? {
+                config_files.push(path);
+            }
+        }
+        // The team file lives in the vault, so it can only be found once the vault is known.
+        if let Some(vault) = flags
             .vault_path
             .clone()
             .or_else(|| env::var("OBSIDIAN_VAULT_PATH").ok().map(PathBuf::from))
-            .ok_or(MabelError::MissingEnv {
-                key: "OBSIDIAN_VAULT_PATH",
-            })?;
-        let vault_path = expand_path(&vault_path);
+        {
+            let path = expand_path(&vault).join(config_file::TEAM_FILE);
+            if config_file::apply(&path, true)? {
+                config_files.push(path);
+            }
+        }
+        // `config set --profile NAME` may be what creates the profile.
+        let edits_profile = matches!(
+            &cli.command,
+            Command::Config {
+                action: ConfigAction::Get { .. } | ConfigAction::Set { .. } | ConfigAction::Unset { .. }
+            }
+        );
+        if let Some(name) = profile.as_ref().filter(|_| !edits_profile) {
+            config_file::check_profile(&config_files, name)?;
+        }
 
-        ensure_dir_exists(&vault_path).map_err(|e| MabelError::Io {
-            path: vault_path.clone(),
-            source: e,
-        })?;
-        ensure_writable(&vault_path).map_err(|_| MabelError::VaultNotWritable {
-            path: vault_path.clone(),
-        })?;
+        let vault_path = flags
+            .vault_path
+            .clone()
+            .or_else(|| env::var("OBSIDIAN_VAULT_PATH").ok().map(PathBuf::from));
+        // Commands that never touch the vault (`note --json`, cache, ...) work without one.
+        let vault_path = match vault_path {
+            | Some(path) => expand_path(&path),
+            | None if !cli.command.needs_vault() => PathBuf::new(),
+            | None => {
+                return Err(MabelError::MissingEnv {
+                    key: "OBSIDIAN_VAULT_PATH",
+                })
+            }
+        };
 
-        let vault_subdir = cli
+        if cli.command.needs_vault() {
+            prepare_vault(&vault_path)?;
+        }
+
+        let vault_subdir = flags
             .vault_subdir
             .clone()
             .or_else(|| env::var("OBSIDIAN_SUBDIR").ok())
             .unwrap_or_else(|| "Papers".to_string());
 
-        let copy_pdf_into_vault = cli.copy_pdf_into_vault || env_bool("MABEL_COPY_PDF", false);
+        let copy_pdf_into_vault = output.is_some_and(|o| o.copy_pdf_into_vault) || env_bool("MABEL_COPY_PDF", false);
+        let text_sidecar = output.is_some_and(|o| o.text_sidecar) || env_bool("MABEL_TEXT_SIDECAR", false);
+        let thread = output.is_some_and(|o| o.thread) || env_bool("MABEL_THREAD", false);
+        let thread_chars = env_u32("MABEL_THREAD_CHARS", 500).max(1);
 
-        let cache_dir = cli
+        let cache_dir = flags
             .cache_dir
             .clone()
             .or_else(|| env::var("MABEL_CACHE_DIR").ok().map(PathBuf::from))
-            .unwrap_or_else(|| default_cache_dir());
+            .unwrap_or_else(default_cache_dir);
         let cache_dir = expand_path(&cache_dir);
         ensure_dir_exists(&cache_dir).map_err(|e| MabelError::Io {
             path: cache_dir.clone(),
             source: e,
         })?;
 
-        let overwrite_note = cli.overwrite || env_bool("MABEL_OVERWRITE_NOTE", false);
+        let overwrite_note = if output.is_some_and(|o| o.force_overwrite) {
+            Overwrite::Force
+        } else if output.is_some_and(|o| o.overwrite) || env_bool("MABEL_OVERWRITE_NOTE", false) {
+            Overwrite::Merge
+        } else {
+            Overwrite::Refuse
+        };
+        let preview_diff = output.is_some_and(|o| o.preview_diff);
+        let assume_yes = output.is_some_and(|o| o.yes);
+        let confirm_metadata = output.is_some_and(|o| !o.no_interactive);
+        let ci = flags.ci || env_bool("MABEL_CI", false);
+        let fail_on = env::var("MABEL_FAIL_ON")
+            .ok()
+            .map(|v| v.parse::<Severity>())
+            .transpose()?
+            .unwrap_or_default();
+        let vars = flags.vars.iter().cloned().collect();
 
-        let llm = if cli.ollama {
-            let host = cli
+        // Secret commands may prompt to unlock a password manager, so they only run for commands
+        // that use the secrets.
+        let fetch_secrets = cli.command.needs_llm() || matches!(cli.command, Command::Cite(_) | Command::Doctor);
+
+        let backend = env::var("MABEL_BACKEND").ok();
+        // With an Anthropic or Gemini key and no OpenAI one, that is the backend meant.
+        let is_set = |keys: [&str; 2]| keys.iter().any(|k| env::var(k).is_ok());
+        let no_openai_key =
+            backend.is_none() && flags.openai_key.is_none() && !is_set(["OPENAI_API_KEY", "OPENAI_API_KEY_CMD"]);
+        let only_anthropic_key =
+            no_openai_key && !flags.gemini && is_set(["ANTHROPIC_API_KEY", "ANTHROPIC_API_KEY_CMD"]);
+        let only_gemini_key = no_openai_key && is_set(["GEMINI_API_KEY", "GEMINI_API_KEY_CMD"]);
+        // A CI log has no terminal to draw the progress line in.
+        let stream = !flags.no_stream && !ci && env_bool("MABEL_STREAM", true);
+        let llm = if flags.ollama || backend.as_deref() == Some("ollama") {
+            let host = flags
                 .ollama_host
                 .clone()
                 .or_else(|| env::var("OLLAMA_HOST").ok())
                 .unwrap_or_else(|| "http://localhost:11434".to_string());
             let host = Url::parse(&host)?;
-            let model = cli
+            let model = flags
                 .model
                 .clone()
                 .or_else(|| env::var("OLLAMA_MODEL").ok())
                 .unwrap_or_else(|| "llama3:8b-instruct".to_string());
+            let keep_alive = env::var("OLLAMA_KEEP_ALIVE")
+                .ok()
+                .filter(|k| !k.trim().is_empty())
+                .map(|k| k.parse())
+                .transpose()?;
             LlmBackend::Ollama {
                 host,
                 model,
                 max_tokens: env_u32("MABEL_MAX_TOKENS", 800),
                 temperature: env_f32("MABEL_TEMPERATURE", 0.2),
+                auth: ServiceAuth::from_env("OLLAMA", fetch_secrets)?,
+                keep_alive,
+                num_ctx: env::var("OLLAMA_NUM_CTX").ok().and_then(|n| n.parse().ok()),
+                preload: env_bool("OLLAMA_PRELOAD", true),
+                pull: env_bool("OLLAMA_PULL", false),
+                stream,
             }
-        } else {
-            let api_key = cli
-                .openai_key
+        } else if flags.anthropic || backend.as_deref() == Some("anthropic") || only_anthropic_key {
+            let key = "ANTHROPIC_API_KEY";
+            let api_key = if fetch_secrets {
+                secret::from_env(key)?.ok_or(MabelError::MissingEnv { key })?
+            } else {
+                env::var(key).map(Secret::new).unwrap_or_default()
+            };
+            let model = flags
+                .model
                 .clone()
-                .or_else(|| env::var("OPENAI_API_KEY").ok())
-                .ok_or(MabelError::MissingEnv { key: "OPENAI_API_KEY" })?;
-            let model = cli
+                .or_else(|| env::var("ANTHROPIC_MODEL").ok())
+                .unwrap_or_else(|| "claude-sonnet-4-5".to_string());
+            LlmBackend::Anthropic {
+                api_key,
+                model,
+                max_tokens: env_u32("MABEL_MAX_TOKENS", 800),
+                temperature: env_f32("MABEL_TEMPERATURE", 0.2),
+            }
+        } else if flags.gemini || backend.as_deref() == Some("gemini") || only_gemini_key {
+            let key = "GEMINI_API_KEY";
+            let api_key = if fetch_secrets {
+                secret::from_env(key)?.ok_or(MabelError::MissingEnv { key })?
+            } else {
+                env::var(key).map(Secret::new).unwrap_or_default()
+            };
+            let model = flags
+                .model
+                .clone()
+                .or_else(|| env::var("GEMINI_MODEL").ok())
+                .unwrap_or_else(|| "gemini-2.5-flash".to_string());
+            LlmBackend::Gemini {
+                api_key,
+                model,
+                max_tokens: env_u32("MABEL_MAX_TOKENS", 800),
+                temperature: env_f32("MABEL_TEMPERATURE", 0.2),
+            }
+        } else {
+            // Commands that never call the model (cache, search, ...) work without a key.
+            let api_key = match flags.openai_key.clone() {
+                | Some(key) => Secret::new(key),
+                | None if !fetch_secrets => env::var("OPENAI_API_KEY").map(Secret::new).unwrap_or_default(),
+                | None => secret::from_env("OPENAI_API_KEY")?.ok_or(MabelError::MissingEnv { key: "OPENAI_API_KEY" })?,
+            };
+            let model = flags
                 .model
                 .clone()
                 .or_else(|| env::var("OPENAI_MODEL").ok())
                 .unwrap_or_else(|| "gpt-4o-mini".to_string());
+            let base_url = env::var("OPENAI_BASE_URL")
+                .ok()
+                .filter(|u| !u.is_empty())
+                .map(|u| Url::parse(&u))
+                .transpose()?;
             LlmBackend::OpenAi {
                 api_key,
+                base_url,
                 model,
                 max_tokens: env_u32("MABEL_MAX_TOKENS", 800),
                 temperature: env_f32("MABEL_TEMPERATURE", 0.2),
+                auth: ServiceAuth::from_env("OPENAI", fetch_secrets)?,
+                stream,
             }
         };
 
-        let grobid_url = cli
+        let routing = env::var("MABEL_ROUTING")
+            .ok()
+            .map(|r| r.parse::<RoutingPolicy>())
+            .transpose()?
+            .unwrap_or_default();
+        let max_pages = Some(env_u32("MABEL_MAX_PAGES", 100)).filter(|&n| n > 0);
+        let uncertainty = env_bool("MABEL_UNCERTAINTY", false);
+        let max_uncertain = Some(env_u32("MABEL_MAX_UNCERTAIN", 0)).filter(|&n| n > 0);
+        let max_unverified = Some(env_u32("MABEL_MAX_UNVERIFIED", 0)).filter(|&n| n > 0);
+        let verify_claims = env_bool("MABEL_VERIFY_CLAIMS", max_unverified.is_some());
+        let min_extraction_quality = env_f32("MABEL_MIN_EXTRACTION_QUALITY", 0.5);
+        let stop_sections = env::var("MABEL_STOP_SECTIONS")
+            .ok()
+            .map(|s| s.parse::<StopSections>())
+            .transpose()?
+            .unwrap_or_default();
+        let max_cost_per_paper = Some(env_f64("MABEL_MAX_COST_PER_PAPER", 0.0)).filter(|&c| c > 0.0);
+        let max_cost_per_day = Some(env_f64("MABEL_MAX_COST_PER_DAY", 0.0)).filter(|&c| c > 0.0);
+        let map_reduce = env_bool("MABEL_MAP_REDUCE", true);
+        let chunk_chars = Some(env_u32("MABEL_CHUNK_CHARS", 0)).filter(|&n| n > 0);
+        let chunk_overlap = env_u32("MABEL_CHUNK_OVERLAP", 500);
+
+        let grobid_url = flags
             .grobid_url
             .clone()
             .or_else(|| env::var("GROBID_URL").ok())
             .map(|s| Url::parse(&s))
             .transpose()?;
+        let grobid_auth = ServiceAuth::from_env("GROBID", fetch_secrets)?;
+        let consolidation = |key, default| {
+            env::var(key)
+                .ok()
+                .map(|v| v.parse::<Consolidation>())
+                .transpose()
+                .map(|c| c.unwrap_or(default))
+        };
+        // GROBID's own defaults.
+        let grobid_consolidate_header = consolidation("MABEL_GROBID_CONSOLIDATE_HEADER", Consolidation::Full)?;
+        let grobid_consolidate_citations = consolidation("MABEL_GROBID_CONSOLIDATE_CITATIONS", Consolidation::None)?;
+        let pdf_fallback = env_bool("MABEL_PDF_FALLBACK", true);
+        let tiered = env_bool("MABEL_TIERED", false);
+
+        let ncbi_api_key = if fetch_secrets {
+            secret::from_env("NCBI_API_KEY")?
+        } else {
+            env::var("NCBI_API_KEY").ok().map(Secret::new)
+        };
+        let ncbi_email = env::var("NCBI_EMAIL").ok();
+        let unpaywall_email = env::var("UNPAYWALL_EMAIL").ok().filter(|e| !e.trim().is_empty());
+        let orcid = env_bool("MABEL_ORCID", true);
+        let arxiv_api_url = Url::parse(&env::var("MABEL_ARXIV_API_URL").unwrap_or_else(|_| API_URL.to_string()))?;
+        // Ids are joined onto it, which would replace a last segment without the slash.
+        let arxiv_pdf_url = env::var("MABEL_ARXIV_PDF_URL")
+            .map_or_else(|_| PDF_URL.to_string(), |u| format!("{}/", u.trim_end_matches('/')));
+        let arxiv_pdf_url = Url::parse(&arxiv_pdf_url)?;
+        let webhook_url = env::var("MABEL_WEBHOOK_URL")
+            .ok()
+            .map(|s| Url::parse(&s))
+            .transpose()?;
+        let webhook_secret = if fetch_secrets {
+            secret::from_env("MABEL_WEBHOOK_SECRET")?
+        } else {
+            env::var("MABEL_WEBHOOK_SECRET").ok().map(Secret::new)
+        };
+
+        let chapters = match &cli.command {
+            | Command::Note(args) => args.chapters.as_deref().map(str::parse).transpose()?,
+            | _ => None,
+        };
 
         let http_timeout = StdDuration::from_secs(env_u64("MABEL_HTTP_TIMEOUT_SECS", 20));
         let http_retries = env_u32("MABEL_HTTP_RETRIES", 2);
         let rate_limit_per_min = env_u32("MABEL_RATE_PER_MIN", 30);
+        let max_concurrent_downloads = env_u32("MABEL_MAX_CONCURRENT_DOWNLOADS", 4).max(1);
+        let max_concurrent_extractions = env_u32("MABEL_MAX_CONCURRENT_EXTRACTIONS", 2).max(1);
+        let max_concurrent_llm = env_u32("MABEL_MAX_CONCURRENT_LLM", 4).max(1);
+        let max_bandwidth = flags
+            .max_bandwidth
+            .clone()
+            .or_else(|| env::var("MABEL_MAX_BANDWIDTH").ok())
+            .filter(|b| !b.trim().is_empty())
+            .map(|b| http::parse_bandwidth(&b))
+            .transpose()?;
+        let max_memory = env::var("MABEL_MAX_MEMORY")
+            .ok()
+            .filter(|m| !m.trim().is_empty())
+            .map(|m| memory::parse_ceiling(&m))
+            .transpose()?;
+        let metered = flags.metered || env_bool("MABEL_METERED", false);
 
-        let template_path = cli
+        let vault_context = env_bool("MABEL_VAULT_CONTEXT", false);
+        let extract_claims = env_bool("MABEL_CLAIMS", false);
+        let leaderboards = env_bool("MABEL_LEADERBOARDS", false);
+        let figure_alt = env::var("MABEL_FIGURE_ALT")
+            .ok()
+            .map(|v| v.parse::<FigureAlt>())
+            .transpose()?
+            .unwrap_or(FigureAlt::Caption);
+        let pdf_figures = env_bool("MABEL_PDF_FIGURES", false);
+        let attachments_dir = vault_path.join(expand_path(Path::new(
+            env::var("MABEL_ATTACHMENTS_DIR")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .as_deref()
+                .map_or("Attachments", str::trim),
+        )));
+        let mocs = env_bool("MABEL_MOCS", false);
+        let define_new_terms = output.is_some_and(|o| o.define_new_terms) || env_bool("MABEL_DEFINE_NEW_TERMS", false);
+        let max_new_terms = env_u32("MABEL_MAX_NEW_TERMS", 3);
+
+        let embedding_model = env::var("MABEL_EMBEDDING_MODEL").ok().filter(|m| !m.is_empty());
+        let digest_categories = env::var("MABEL_DIGEST_CATEGORIES")
+            .map(|v| {
+                v.split([',', '\n'])
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let template_path = flags
             .template
             .clone()
-            .unwrap_or_else(|| PathBuf::from("templates/paper_note.md.tera"));
+            .or_else(|| env::var("MABEL_TEMPLATE").ok().map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_TEMPLATE_PATH));
         let template_path = expand_path(&template_path);
+        let frontmatter_schema = env::var("MABEL_FRONTMATTER_SCHEMA")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
+        let frontmatter_style = env::var("MABEL_FRONTMATTER_STYLE")
+            .ok()
+            .map(|v| v.parse::<FrontmatterStyle>())
+            .transpose()?
+            .unwrap_or_default();
+        let frontmatter = env::var("MABEL_FRONTMATTER")
+            .ok()
+            .map(|v| FrontmatterFields::parse(&v))
+            .transpose()?
+            .unwrap_or_default();
+        let prompt_dir = env::var("MABEL_PROMPT_DIR")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
+        let prompt_template = flags
+            .prompt_template
+            .clone()
+            .or_else(|| env::var("MABEL_PROMPT_TEMPLATE").ok().map(PathBuf::from))
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|p| expand_path(&p));
+        let moc_template = env::var("MABEL_MOC_TEMPLATE")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
+        let concept_template = env::var("MABEL_CONCEPT_TEMPLATE")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
+        let thread_template = env::var("MABEL_THREAD_TEMPLATE")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
+        let anki_dir = env::var("MABEL_ANKI_DIR")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
+        let bib_file = env::var("MABEL_BIB_FILE")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| vault_path.join(expand_path(Path::new(s.trim()))));
+        let serve_users = env::var("MABEL_SERVE_USERS")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| expand_path(Path::new(&s)));
 
-        let mode = match cli.mode.as_deref() {
-            | Some("study") => Mode::Study,
-            | _ => Mode::Concise,
-        };
+        let mode = flags
+            .mode
+            .clone()
+            .or_else(|| env::var("MABEL_MODE").ok())
+            .filter(|m| !m.trim().is_empty())
+            .map(|m| m.parse::<Mode>())
+            .transpose()?
+            .unwrap_or(Mode::Concise);
+
+        let region_markers = RegionMarkers::new(
+            &env::var("MABEL_REGION_BEGIN").unwrap_or_else(|_| region::DEFAULT_BEGIN.to_string()),
+            &env::var("MABEL_REGION_END").unwrap_or_else(|_| region::DEFAULT_END.to_string()),
+        )?;
+
+        let timezone = env::var("MABEL_TZ")
+            .ok()
+            .map(|tz| tz.parse::<Zone>())
+            .transpose()?
+            .unwrap_or_default();
 
         Ok(Self {
             vault_path,
             vault_subdir,
             copy_pdf_into_vault,
+            text_sidecar,
+            thread,
+            thread_chars,
+            thread_template,
             cache_dir,
             overwrite_note,
+            preview_diff,
+            assume_yes,
+            confirm_metadata,
+            ci,
+            non_interactive: false,
+            fail_on,
             llm,
+            routing,
+            vars,
+            max_pages,
+            uncertainty,
+            max_uncertain,
+            verify_claims,
+            max_unverified,
+            min_extraction_quality,
+            stop_sections,
+            max_cost_per_paper,
+            max_cost_per_day,
+            map_reduce,
+            chunk_chars,
+            chunk_overlap,
             grobid_url,
+            grobid_auth,
+            grobid_consolidate_header,
+            grobid_consolidate_citations,
+            pdf_fallback,
+            tiered,
+            ncbi_api_key,
+            ncbi_email,
+            unpaywall_email,
+            orcid,
+            arxiv_api_url,
+            arxiv_pdf_url,
+            chapters,
             http_timeout,
             http_retries,
             rate_limit_per_min,
+            max_concurrent_downloads,
+            max_concurrent_extractions,
+            max_concurrent_llm,
+            max_bandwidth,
+            max_memory,
+            metered,
+            vault_context,
+            extract_claims,
+            leaderboards,
+            figure_alt,
+            pdf_figures,
+            attachments_dir,
+            mocs,
+            moc_template,
+            define_new_terms,
+            max_new_terms,
+            concept_template,
+            anki_dir,
+            bib_file,
+            webhook_url,
+            webhook_secret,
+            serve_users,
+            embedding_model,
+            digest_categories,
             template_path,
+            frontmatter_schema,
+            frontmatter_style,
+            frontmatter,
             mode,
+            prompt_dir,
+            prompt_template,
+            region_markers,
+            timezone,
+            config_files,
+            profile,
         })
     }
 
+    /// Whether PDFs are read for their full text: by GROBID when a server is configured, by the
+    /// built-in extractor otherwise (see [`crate::extract::read_pdf`]).
+    pub fn reads_pdfs(&self) -> bool {
+        (cfg!(feature = "grobid") && self.grobid_url.is_some()) || (cfg!(feature = "pdf") && self.pdf_fallback)
+    }
+
+    /// Whether there is someone to ask: not under `--ci` or [`Config::non_interactive`], and stdin
+    /// is a terminal.
+    pub fn can_ask(&self) -> bool {
+        !self.ci && !self.non_interactive && std::io::stdin().is_terminal()
+    }
+
     /// Full path inside the vault where notes should be written.
     pub fn vault_notes_dir(&self) -> PathBuf {
         self.vault_path.join(&self.vault_subdir)
     }
 
-    /// Cache path for a given arXiv ID’s PDF.
-    pub fn cached_pdf_path(&self, arxiv_id: &str) -> PathBuf {
-        self.cache_dir.join("papers").join(format!("{arxiv_id}.pdf"))
+    /// The claim knowledge base; kept in the vault so it travels with the notes.
+    pub fn claims_path(&self) -> PathBuf {
+        self.vault_path.join(".mabel").join("claims.jsonl")
+    }
+
+    /// Results extracted for the leaderboards (see [`crate::results`]).
+    pub fn results_path(&self) -> PathBuf {
+        self.vault_path.join(".mabel").join("results.jsonl")
+    }
+
+    /// Leaderboard notes, one per topic; outside the notes folder so they are not taken for papers.
+    pub fn leaderboards_dir(&self) -> PathBuf {
+        self.vault_path.join("Leaderboards")
+    }
+
+    /// Papers whose PDF waits for `mabel flush-queue` (see [`crate::queue`]).
+    pub fn queue_path(&self) -> PathBuf {
+        self.vault_path.join(".mabel").join("queue.jsonl")
+    }
+
+    /// Maps of content created for research areas (see [`crate::moc`]).
+    pub fn mocs_dir(&self) -> PathBuf {
+        self.vault_path.join("MOCs")
+    }
+
+    /// Concept notes written for technical terms (see [`crate::concept`]).
+    pub fn concepts_dir(&self) -> PathBuf {
+        self.vault_path.join("Concepts")
+    }
+
+    /// `mabel feedback` verdicts on recommended papers (see [`crate::recommend`]).
+    pub fn feedback_path(&self) -> PathBuf {
+        self.vault_path.join(".mabel").join("feedback.jsonl")
+    }
+
+    /// The `mabel freshness` report; outside the notes folder so it is not taken for a paper.
+    pub fn freshness_report_path(&self) -> PathBuf {
+        self.vault_path.join("To refresh.md")
+    }
+
+    /// Folder for recommendation digest notes.
+    pub fn digests_dir(&self) -> PathBuf {
+        self.vault_path.join("Digests")
+    }
+
+    /// Folder for `mabel brief` notes.
+    pub fn briefs_dir(&self) -> PathBuf {
+        self.vault_path.join("Briefs")
+    }
+
+    /// Cached embeddings for `model`, so unchanged papers are not embedded again.
+    pub fn embeddings_cache_path(&self, model: &str) -> PathBuf {
+        self.cache_dir
+            .join("embeddings")
+            .join(format!("{}.json", sanitize_filename::sanitize(model)))
+    }
+
+    /// The registry of processed papers (see [`crate::registry`]).
+    pub fn registry_path(&self) -> PathBuf {
+        self.vault_path.join(".mabel").join("registry.json")
+    }
+
+    /// PDFs `mabel watch` has processed, by content, so a restart does not process them again.
+    pub fn watch_state_path(&self) -> PathBuf {
+        self.cache_dir.join("watch.jsonl")
+    }
+
+    /// Folder `mabel watch` files processed PDFs into; outside the notes folder so they are not
+    /// taken for notes.
+    pub fn pdfs_dir(&self) -> PathBuf {
+        self.vault_path.join("PDFs")
+    }
+
+    /// Requests sent in OpenAI batches and their replies, for `mabel batch --economy` (see
+    /// [`crate::llm::batch`]).
+    pub fn batch_state_path(&self) -> PathBuf {
+        self.cache_dir.join("openai-batch.jsonl")
+    }
+
+    /// How fast and how reliably each model answered lately, for sizing its parts (see
+    /// [`crate::chunk::pace`]).
+    pub fn pace_path(&self) -> PathBuf {
+        self.cache_dir.join("pace.jsonl")
+    }
+
+    /// What papers cost today, for `MABEL_MAX_COST_PER_DAY` (see [`crate::cost`]).
+    pub fn spend_path(&self) -> PathBuf {
+        self.cache_dir.join("spend.jsonl")
+    }
+
+    /// The index of papers processed on this machine (see [`crate::index`]).
+    pub fn index_path(&self) -> PathBuf {
+        self.cache_dir.join("mabel.db")
+    }
+
+    /// Folder for the threads on papers (see [`crate::thread`]); outside the notes folder so they
+    /// are not taken for papers.
+    pub fn threads_dir(&self) -> PathBuf {
+        self.vault_path.join("Threads")
+    }
+
+    /// Folder for the full-text sidecars of paper notes (see [`crate::fulltext`]); outside the
+    /// notes folder so they are not taken for papers.
+    pub fn full_text_dir(&self) -> PathBuf {
+        self.vault_path.join("Full text")
+    }
+
+    /// Cached extracted text of a paper, by [`crate::fulltext::key`]; the structure sits next to
+    /// it as `.json`.
+    pub fn cached_text_path(&self, key: &str) -> PathBuf {
+        self.cache_dir.join("text").join(format!("{key}.md"))
+    }
+
+    /// Cache path of a paper's PDF, by its arXiv id or, failing that, its DOI (filename-safe).
+    pub fn cached_pdf_path(&self, name: &str) -> PathBuf {
+        self.cache_dir.join("papers").join(format!("{name}.pdf"))
     }
 }
 
@@ -197,6 +1176,21 @@ fn expand_path(p: &Path) -> PathBuf {
     PathBuf::from(shellexpand::tilde(&s).into_owned())
 }
 
+/// Create the vault folder if needed and check that notes can be written to it.
+pub fn prepare_vault(path: &Path) -> Result<()> {
+    ensure_dir_exists(path).map_err(|e| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source: e,
+        }
+    })?;
+    ensure_writable(path).map_err(|_| {
+        MabelError::VaultNotWritable {
+            path: path.to_path_buf(),
+        }
+    })
+}
+
 fn ensure_dir_exists(dir: &Path) -> std::io::Result<()> {
     if !dir.exists() {
         fs::create_dir_all(dir)?;
@@ -206,7 +1200,7 @@ fn ensure_dir_exists(dir: &Path) -> std::io::Result<()> {
 
 fn ensure_writable(dir: &Path) -> std::io::Result<()> {
     let test = dir.join(".mabel_write_check");
-    let mut f = OpenOptions::new().create(true).write(true).open(&test)?;
+    let mut f = OpenOptions::new().create(true).write(true).truncate(true).open(&test)?;
     f.write_all(b"ok")?;
     let _ = fs::remove_file(test);
     Ok(())
@@ -221,8 +1215,7 @@ fn default_cache_dir() -> PathBuf {
 fn env_bool(key: &str, default: bool) -> bool {
     env::var(key)
         .ok()
-        .map(|v| matches!(v.as_str(), "1" | "true" | "TRUE" | "yes" | "on"))
-        .unwrap_or(default)
+        .map_or(default, |v| matches!(v.as_str(), "1" | "true" | "TRUE" | "yes" | "on"))
 }
 fn env_u32(key: &str, default: u32) -> u32 {
     env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
@@ -230,6 +1223,9 @@ fn env_u32(key: &str, default: u32) -> u32 {
 fn env_u64(key: &str, default: u64) -> u64 {
     env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
 }
+fn env_f64(key: &str, default: f64) -> f64 {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
 fn env_f32(key: &str, default: f32) -> f32 {
     env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
 }
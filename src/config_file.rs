@@ -0,0 +1,699 @@
+//! Config files, layered under the environment: a personal `config.toml` and a team `mabel.toml`
+//! committed at the vault root, so a lab can share templates, modes and models while everyone
+//! keeps their own keys and paths.
+//!
+//! Each file key stands in for an environment variable (`openai_model` for `OPENAI_MODEL`, ...).
+//! A file only fills in variables that are not set yet, the way `.env` does, which gives the
+//! precedence: flags > environment and `.env` > user file > team file > defaults.
+//!
+//! A `[profile.NAME]` table holds settings that apply on top of the file's own with `--profile NAME`
+//! (or `MABEL_PROFILE`), say one per vault or workflow; naming a profile no file has is an error.
+//! `mabel config set/get/unset` edit the files in place, checking values and keeping comments.
+//!
+//! ```toml
+//! # <vault>/mabel.toml
+//! template = "templates/lab_note.md.tera"   # relative to the vault
+//! mode = "study"
+//! openai_model = "gpt-4o"
+//!
+//! [profile.thesis]
+//! mode = "concise"
+//!
+//! [frontmatter]                             # see `FrontmatterFields`
+//! arxiv = "arxiv-id"
+//! published = { name = "date", type = "date" }
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use url::Url;
+
+use crate::{
+    ci::Severity,
+    clock::Zone,
+    config::{Consolidation, FigureAlt, KeepAlive, Mode},
+    render::{FrontmatterFields, FrontmatterStyle},
+    routing::RoutingPolicy,
+    stop_sections::StopSections,
+    MabelError, Result,
+};
+
+/// Team defaults, at the root of the vault.
+pub const TEAM_FILE: &str = "mabel.toml";
+
+/// File key and the environment variable it sets.
+const KEYS: &[(&str, &str)] = &[
+    ("vault_path", "OBSIDIAN_VAULT_PATH"),
+    ("vault_subdir", "OBSIDIAN_SUBDIR"),
+    ("cache_dir", "MABEL_CACHE_DIR"),
+    ("copy_pdf", "MABEL_COPY_PDF"),
+    ("text_sidecar", "MABEL_TEXT_SIDECAR"),
+    ("thread", "MABEL_THREAD"),
+    ("thread_chars", "MABEL_THREAD_CHARS"),
+    ("thread_template", "MABEL_THREAD_TEMPLATE"),
+    ("overwrite", "MABEL_OVERWRITE_NOTE"),
+    ("fail_on", "MABEL_FAIL_ON"),
+    ("backend", "MABEL_BACKEND"),
+    ("openai_api_key", "OPENAI_API_KEY"),
+    ("openai_api_key_cmd", "OPENAI_API_KEY_CMD"),
+    ("openai_model", "OPENAI_MODEL"),
+    ("openai_base_url", "OPENAI_BASE_URL"),
+    ("openai_headers", "OPENAI_HEADERS"),
+    ("openai_headers_cmd", "OPENAI_HEADERS_CMD"),
+    ("openai_bearer_token", "OPENAI_BEARER_TOKEN"),
+    ("openai_bearer_token_cmd", "OPENAI_BEARER_TOKEN_CMD"),
+    ("openai_basic_auth", "OPENAI_BASIC_AUTH"),
+    ("openai_basic_auth_cmd", "OPENAI_BASIC_AUTH_CMD"),
+    ("anthropic_api_key", "ANTHROPIC_API_KEY"),
+    ("anthropic_api_key_cmd", "ANTHROPIC_API_KEY_CMD"),
+    ("anthropic_model", "ANTHROPIC_MODEL"),
+    ("gemini_api_key", "GEMINI_API_KEY"),
+    ("gemini_api_key_cmd", "GEMINI_API_KEY_CMD"),
+    ("gemini_model", "GEMINI_MODEL"),
+    ("ollama_host", "OLLAMA_HOST"),
+    ("ollama_model", "OLLAMA_MODEL"),
+    ("ollama_headers", "OLLAMA_HEADERS"),
+    ("ollama_headers_cmd", "OLLAMA_HEADERS_CMD"),
+    ("ollama_bearer_token", "OLLAMA_BEARER_TOKEN"),
+    ("ollama_bearer_token_cmd", "OLLAMA_BEARER_TOKEN_CMD"),
+    ("ollama_basic_auth", "OLLAMA_BASIC_AUTH"),
+    ("ollama_basic_auth_cmd", "OLLAMA_BASIC_AUTH_CMD"),
+    ("ollama_keep_alive", "OLLAMA_KEEP_ALIVE"),
+    ("ollama_num_ctx", "OLLAMA_NUM_CTX"),
+    ("ollama_preload", "OLLAMA_PRELOAD"),
+    ("ollama_pull", "OLLAMA_PULL"),
+    ("max_tokens", "MABEL_MAX_TOKENS"),
+    ("stream", "MABEL_STREAM"),
+    ("temperature", "MABEL_TEMPERATURE"),
+    ("routing", "MABEL_ROUTING"),
+    ("max_pages", "MABEL_MAX_PAGES"),
+    ("uncertainty", "MABEL_UNCERTAINTY"),
+    ("max_uncertain", "MABEL_MAX_UNCERTAIN"),
+    ("verify_claims", "MABEL_VERIFY_CLAIMS"),
+    ("max_unverified", "MABEL_MAX_UNVERIFIED"),
+    ("min_extraction_quality", "MABEL_MIN_EXTRACTION_QUALITY"),
+    ("stop_sections", "MABEL_STOP_SECTIONS"),
+    ("max_cost_per_paper", "MABEL_MAX_COST_PER_PAPER"),
+    ("max_cost_per_day", "MABEL_MAX_COST_PER_DAY"),
+    ("map_reduce", "MABEL_MAP_REDUCE"),
+    ("chunk_chars", "MABEL_CHUNK_CHARS"),
+    ("chunk_overlap", "MABEL_CHUNK_OVERLAP"),
+    ("grobid_url", "GROBID_URL"),
+    ("grobid_headers", "GROBID_HEADERS"),
+    ("grobid_headers_cmd", "GROBID_HEADERS_CMD"),
+    ("grobid_bearer_token", "GROBID_BEARER_TOKEN"),
+    ("grobid_bearer_token_cmd", "GROBID_BEARER_TOKEN_CMD"),
+    ("grobid_basic_auth", "GROBID_BASIC_AUTH"),
+    ("grobid_basic_auth_cmd", "GROBID_BASIC_AUTH_CMD"),
+    ("grobid_consolidate_header", "MABEL_GROBID_CONSOLIDATE_HEADER"),
+    ("grobid_consolidate_citations", "MABEL_GROBID_CONSOLIDATE_CITATIONS"),
+    ("pdf_fallback", "MABEL_PDF_FALLBACK"),
+    ("tiered", "MABEL_TIERED"),
+    ("ncbi_api_key", "NCBI_API_KEY"),
+    ("ncbi_api_key_cmd", "NCBI_API_KEY_CMD"),
+    ("ncbi_email", "NCBI_EMAIL"),
+    ("unpaywall_email", "UNPAYWALL_EMAIL"),
+    ("orcid", "MABEL_ORCID"),
+    ("arxiv_api_url", "MABEL_ARXIV_API_URL"),
+    ("arxiv_pdf_url", "MABEL_ARXIV_PDF_URL"),
+    ("http_timeout_secs", "MABEL_HTTP_TIMEOUT_SECS"),
+    ("http_retries", "MABEL_HTTP_RETRIES"),
+    ("rate_limit_per_min", "MABEL_RATE_PER_MIN"),
+    ("max_concurrent_downloads", "MABEL_MAX_CONCURRENT_DOWNLOADS"),
+    ("max_concurrent_extractions", "MABEL_MAX_CONCURRENT_EXTRACTIONS"),
+    ("max_concurrent_llm", "MABEL_MAX_CONCURRENT_LLM"),
+    ("max_bandwidth", "MABEL_MAX_BANDWIDTH"),
+    ("max_memory", "MABEL_MAX_MEMORY"),
+    ("metered", "MABEL_METERED"),
+    ("vault_context", "MABEL_VAULT_CONTEXT"),
+    ("extract_claims", "MABEL_CLAIMS"),
+    ("leaderboards", "MABEL_LEADERBOARDS"),
+    ("figure_alt", "MABEL_FIGURE_ALT"),
+    ("pdf_figures", "MABEL_PDF_FIGURES"),
+    ("attachments_dir", "MABEL_ATTACHMENTS_DIR"),
+    ("mocs", "MABEL_MOCS"),
+    ("moc_template", "MABEL_MOC_TEMPLATE"),
+    ("define_new_terms", "MABEL_DEFINE_NEW_TERMS"),
+    ("max_new_terms", "MABEL_MAX_NEW_TERMS"),
+    ("concept_template", "MABEL_CONCEPT_TEMPLATE"),
+    ("anki_dir", "MABEL_ANKI_DIR"),
+    ("bib_file", "MABEL_BIB_FILE"),
+    ("webhook_url", "MABEL_WEBHOOK_URL"),
+    ("webhook_secret", "MABEL_WEBHOOK_SECRET"),
+    ("webhook_secret_cmd", "MABEL_WEBHOOK_SECRET_CMD"),
+    ("serve_users", "MABEL_SERVE_USERS"),
+    ("embedding_model", "MABEL_EMBEDDING_MODEL"),
+    ("digest_categories", "MABEL_DIGEST_CATEGORIES"),
+    ("template", "MABEL_TEMPLATE"),
+    ("frontmatter_schema", "MABEL_FRONTMATTER_SCHEMA"),
+    ("frontmatter_style", "MABEL_FRONTMATTER_STYLE"),
+    ("frontmatter", "MABEL_FRONTMATTER"),
+    ("mode", "MABEL_MODE"),
+    ("prompt_dir", "MABEL_PROMPT_DIR"),
+    ("prompt_template", "MABEL_PROMPT_TEMPLATE"),
+    ("region_begin", "MABEL_REGION_BEGIN"),
+    ("region_end", "MABEL_REGION_END"),
+    ("timezone", "MABEL_TZ"),
+];
+
+/// Keys that belong to one person and are ignored in the team file. Secret commands are among
+/// them: a shared file must not be able to make everyone's machine run a command. Proxy headers
+/// are too, as they usually carry credentials, and so is the webhook, which would send everyone's
+/// notes wherever the file says. The server's users file holds access tokens. Bandwidth settings
+/// depend on the connection someone is on, and the Anki folder is outside the vault.
+const PERSONAL: &[&str] = &[
+    "vault_path",
+    "cache_dir",
+    "openai_api_key",
+    "openai_api_key_cmd",
+    "openai_headers",
+    "openai_headers_cmd",
+    "openai_bearer_token",
+    "openai_bearer_token_cmd",
+    "openai_basic_auth",
+    "openai_basic_auth_cmd",
+    "anthropic_api_key",
+    "anthropic_api_key_cmd",
+    "gemini_api_key",
+    "gemini_api_key_cmd",
+    "ollama_headers",
+    "ollama_headers_cmd",
+    "ollama_bearer_token",
+    "ollama_bearer_token_cmd",
+    "ollama_basic_auth",
+    "ollama_basic_auth_cmd",
+    "grobid_headers",
+    "grobid_headers_cmd",
+    "grobid_bearer_token",
+    "grobid_bearer_token_cmd",
+    "grobid_basic_auth",
+    "grobid_basic_auth_cmd",
+    "ncbi_api_key",
+    "ncbi_api_key_cmd",
+    "ncbi_email",
+    "unpaywall_email",
+    "webhook_url",
+    "webhook_secret",
+    "webhook_secret_cmd",
+    "serve_users",
+    "max_bandwidth",
+    "max_memory",
+    "anki_dir",
+    "metered",
+];
+
+/// Keys holding secrets, which `mabel state export` leaves out. The commands that fetch a secret
+/// stay: they fetch it on the new machine too.
+const SECRETS: &[&str] = &[
+    "openai_api_key",
+    "openai_headers",
+    "openai_bearer_token",
+    "openai_basic_auth",
+    "anthropic_api_key",
+    "gemini_api_key",
+    "ollama_headers",
+    "ollama_bearer_token",
+    "ollama_basic_auth",
+    "grobid_headers",
+    "grobid_bearer_token",
+    "grobid_basic_auth",
+    "ncbi_api_key",
+    "webhook_secret",
+];
+
+/// Keys holding paths; relative values are taken relative to the file's folder.
+const PATHS: &[&str] = &[
+    "vault_path",
+    "cache_dir",
+    "template",
+    "frontmatter_schema",
+    "serve_users",
+    "moc_template",
+    "concept_template",
+    "thread_template",
+    "anki_dir",
+    "prompt_dir",
+    "prompt_template",
+];
+
+/// Table holding the named profiles.
+const PROFILES: &str = "profile";
+
+/// The personal config file: `MABEL_CONFIG`, or `config.toml` in the platform config folder
+/// (`~/.config/mabel` on Linux).
+pub fn user_path() -> Option<PathBuf> {
+    env::var("MABEL_CONFIG")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|d| d.join("mabel").join("config.toml")))
+}
+
+/// Set the environment variables for the keys in `path` that are not set already. A missing file
+/// is not an error; returns whether the file was read.
+pub fn apply(path: &Path, team: bool) -> Result<bool> {
+    let text = match std::fs::read_to_string(path) {
+        | Ok(text) => text,
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        | Err(source) => {
+            return Err(MabelError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    let table: toml::Table = toml::from_str(&text).map_err(|e| {
+        MabelError::Config {
+            msg: format!("{}: {e}", path.display()),
+        }
+    })?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    // The selected profile's settings come first so they win over the file's own.
+    let profile = env::var("MABEL_PROFILE")
+        .ok()
+        .and_then(|name| table.get(PROFILES)?.get(&name)?.as_table().cloned());
+    for (key, value) in profile.iter().flatten().chain(&table) {
+        if key == PROFILES {
+            continue;
+        }
+        let Some(&(_, var)) = KEYS.iter().find(|(k, _)| k == key) else {
+            tracing::warn!(key, file = %path.display(), "unknown config key ignored");
+            continue;
+        };
+        if team && PERSONAL.contains(&key.as_str()) {
+            tracing::warn!(key, file = %path.display(), "personal setting ignored in the team config");
+            continue;
+        }
+        let value = match value {
+            | toml::Value::String(s) => s.clone(),
+            | toml::Value::Integer(n) => n.to_string(),
+            | toml::Value::Float(n) => n.to_string(),
+            | toml::Value::Boolean(b) => b.to_string(),
+            // Lists (`digest_categories = ["cs.LG", "cs.CL"]`, `grobid_headers = ["X-Key: ..."]`)
+            // become one item per line.
+            | toml::Value::Array(items) if items.iter().all(toml::Value::is_str) => {
+                items
+                    .iter()
+                    .filter_map(toml::Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            // The `[frontmatter]` section goes into its variable as JSON.
+            | toml::Value::Table(fields) if key == "frontmatter" => serde_json::to_string(fields)?,
+            | _ => {
+                return Err(MabelError::Config {
+                    msg: format!(
+                        "{}: `{key}` must be a string, number, boolean or list of strings",
+                        path.display()
+                    ),
+                })
+            }
+        };
+        let value = if PATHS.contains(&key.as_str()) && !value.starts_with('~') && Path::new(&value).is_relative() {
+            dir.join(&value).display().to_string()
+        } else {
+            value
+        };
+        if env::var_os(var).is_none() {
+            env::set_var(var, value);
+        }
+    }
+    Ok(true)
+}
+
+/// Fail unless one of `files` has a `[profile.NAME]` table for `name`, so a misspelt profile does
+/// not quietly leave the files' own settings in effect.
+pub fn check_profile(files: &[PathBuf], name: &str) -> Result<()> {
+    let mut known = Vec::new();
+    for path in files {
+        let table = parse_file(path, &read_file(path)?)?;
+        if let Some(profiles) = table.get(PROFILES).and_then(toml::Value::as_table) {
+            if profiles.contains_key(name) {
+                return Ok(());
+            }
+            known.extend(profiles.keys().cloned());
+        }
+    }
+    let known = if known.is_empty() {
+        "there are none".to_string()
+    } else {
+        known.sort();
+        known.dedup();
+        format!("there are {}", known.join(", "))
+    };
+    Err(MabelError::Config {
+        msg: format!("no [profile.{name}] in the config files; {known}"),
+    })
+}
+
+/// The file key `name` stands for, if it is one.
+pub fn key(name: &str) -> Option<&'static str> {
+    KEYS.iter().find(|(k, _)| *k == name).map(|(k, _)| *k)
+}
+
+/// The environment variable a file key sets.
+pub fn env_var(key: &str) -> Option<&'static str> {
+    KEYS.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+pub fn is_personal(key: &str) -> bool {
+    PERSONAL.contains(&key)
+}
+
+pub fn is_path(key: &str) -> bool {
+    PATHS.contains(&key)
+}
+
+/// The config file at `path` with its secrets taken out, in its profiles too; `None` when there
+/// is no file. Comments are not kept.
+pub fn without_secrets(path: &Path) -> Result<Option<String>> {
+    let text = read_file(path)?;
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let mut table = parse_file(path, &text)?;
+    table.retain(|key, _| !SECRETS.contains(&key));
+    if let Some(profiles) = table.get_mut(PROFILES).and_then(toml::Value::as_table_mut) {
+        for (_, profile) in profiles.iter_mut() {
+            if let Some(profile) = profile.as_table_mut() {
+                profile.retain(|key, _| !SECRETS.contains(&key));
+            }
+        }
+    }
+    Ok(Some(toml::to_string(&table).map_err(|e| {
+        MabelError::Config {
+            msg: format!("{}: {e}", path.display()),
+        }
+    })?))
+}
+
+/// Check `raw` as a value for `key` and convert it to the TOML type the key takes, so a value
+/// that would fail (or be silently misread) when mabel starts is refused when it is set.
+#[allow(clippy::too_many_lines)]
+pub fn parse_value(key: &str, raw: &str) -> Result<toml::Value> {
+    let raw = raw.trim();
+    let invalid = |expected: &str| {
+        MabelError::Config {
+            msg: format!("invalid value {raw:?} for `{key}`: expected {expected}"),
+        }
+    };
+    let value = match key {
+        | "copy_pdf" | "text_sidecar" | "thread" | "overwrite" | "tiered" | "orcid" | "vault_context"
+        | "extract_claims" | "leaderboards" | "mocs" | "define_new_terms" | "metered" | "uncertainty"
+        | "ollama_preload" | "ollama_pull" | "stream" | "pdf_fallback" | "map_reduce" | "pdf_figures"
+        | "verify_claims" => {
+            match raw.to_ascii_lowercase().as_str() {
+                | "true" | "1" | "yes" | "on" => toml::Value::Boolean(true),
+                | "false" | "0" | "no" | "off" => toml::Value::Boolean(false),
+                | _ => return Err(invalid("true or false")),
+            }
+        }
+        | "max_tokens"
+        | "max_pages"
+        | "max_uncertain"
+        | "max_unverified"
+        | "http_timeout_secs"
+        | "http_retries"
+        | "rate_limit_per_min"
+        | "ollama_num_ctx"
+        | "max_new_terms"
+        | "chunk_chars"
+        | "chunk_overlap"
+        | "thread_chars"
+        | "max_concurrent_downloads"
+        | "max_concurrent_extractions"
+        | "max_concurrent_llm" => {
+            let n: u32 = raw.parse().map_err(|_| invalid("a whole number"))?;
+            toml::Value::Integer(i64::from(n))
+        }
+        | "temperature" | "min_extraction_quality" => {
+            let max = if key == "temperature" { 2 } else { 1 };
+            let t: f64 = raw.parse().map_err(|_| invalid("a number"))?;
+            if !(0.0..=f64::from(max)).contains(&t) {
+                return Err(invalid(&format!("a number from 0 to {max}")));
+            }
+            toml::Value::Float(t)
+        }
+        | "max_cost_per_paper" | "max_cost_per_day" => {
+            let c: f64 = raw.parse().map_err(|_| invalid("an amount in US dollars"))?;
+            if !c.is_finite() || c < 0.0 {
+                return Err(invalid("an amount in US dollars, 0 for no limit"));
+            }
+            toml::Value::Float(c)
+        }
+        | "max_bandwidth" => {
+            crate::http::parse_bandwidth(raw)?;
+            toml::Value::String(raw.to_string())
+        }
+        | "max_memory" => {
+            crate::memory::parse_ceiling(raw)?;
+            toml::Value::String(raw.to_string())
+        }
+        | "ollama_keep_alive" => {
+            raw.parse::<KeepAlive>()?;
+            toml::Value::String(raw.to_string())
+        }
+        | "ollama_host" | "grobid_url" | "openai_base_url" | "arxiv_api_url" | "arxiv_pdf_url" | "webhook_url" => {
+            Url::parse(raw).map_err(|_| invalid("a URL such as http://localhost:8070"))?;
+            toml::Value::String(raw.to_string())
+        }
+        | "backend" => {
+            match raw {
+                | "openai" | "ollama" | "anthropic" | "gemini" => toml::Value::String(raw.to_string()),
+                | _ => return Err(invalid("openai, ollama, anthropic or gemini")),
+            }
+        }
+        | "mode" => toml::Value::String(raw.parse::<Mode>()?.as_str().to_string()),
+        | "grobid_consolidate_header" | "grobid_consolidate_citations" => {
+            toml::Value::String(raw.parse::<Consolidation>()?.as_str().to_string())
+        }
+        | "figure_alt" => toml::Value::String(raw.parse::<FigureAlt>()?.as_str().to_string()),
+        | "fail_on" => toml::Value::String(raw.parse::<Severity>()?.as_str().to_string()),
+        | "frontmatter_style" => toml::Value::String(raw.parse::<FrontmatterStyle>()?.as_str().to_string()),
+        // Given inline as YAML or JSON (`{arxiv: arxiv-id}`), kept as a TOML table.
+        | "frontmatter" => {
+            FrontmatterFields::parse(raw)?;
+            serde_yaml::from_str(raw).map_err(|e| {
+                MabelError::Config {
+                    msg: format!("frontmatter fields: {e}"),
+                }
+            })?
+        }
+        | "routing" => {
+            raw.parse::<RoutingPolicy>()?;
+            toml::Value::String(raw.to_string())
+        }
+        | "stop_sections" => {
+            raw.parse::<StopSections>()?;
+            toml::Value::String(raw.to_string())
+        }
+        | "timezone" => {
+            raw.parse::<Zone>()?;
+            toml::Value::String(raw.to_string())
+        }
+        | "digest_categories" => list(raw.split(',')),
+        | "grobid_headers" | "ollama_headers" | "openai_headers" => {
+            if raw.split(';').any(|h| !h.trim().is_empty() && !h.contains(':')) {
+                return Err(invalid("`Name: value` pairs separated by `;`"));
+            }
+            list(raw.split(';'))
+        }
+        | _ => toml::Value::String(raw.to_string()),
+    };
+    Ok(value)
+}
+
+fn list<'a>(items: impl Iterator<Item = &'a str>) -> toml::Value {
+    toml::Value::Array(
+        items
+            .map(str::trim)
+            .filter(|i| !i.is_empty())
+            .map(|i| toml::Value::String(i.to_string()))
+            .collect(),
+    )
+}
+
+/// The value of `key` stored in `path`, at its root or in `profile`.
+pub fn read_value(path: &Path, profile: Option<&str>, key: &str) -> Result<Option<toml::Value>> {
+    let table = parse_file(path, &read_file(path)?)?;
+    let section = match profile {
+        | Some(name) => table.get(PROFILES).and_then(|p| p.get(name)).and_then(toml::Value::as_table),
+        | None => Some(&table),
+    };
+    Ok(section.and_then(|s| s.get(key)).cloned())
+}
+
+/// Set `key` to `value` in `path` (at its root or in `profile`), or remove it when `value` is
+/// `None`. The line holding the key is edited in place so comments and layout survive; the file is
+/// created if missing. Returns whether anything changed.
+pub fn write_value(path: &Path, profile: Option<&str>, key: &str, value: Option<&toml::Value>) -> Result<bool> {
+    if let Some(name) = profile {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(MabelError::Config {
+                msg: format!("invalid profile name {name:?}: use letters, digits, `-` and `_`"),
+            });
+        }
+    }
+    let text = read_file(path)?;
+    let mut table = parse_file(path, &text)?;
+    let section = profile.map(|name| format!("{PROFILES}.{name}"));
+
+    // Where the target section's lines are, and the key's line if it has one.
+    let lines: Vec<&str> = text.lines().collect();
+    let mut current: Option<String> = None;
+    let (mut header, mut last, mut found) = (None, None, None);
+    for (i, line) in lines.iter().enumerate() {
+        let t = line.trim();
+        if t.starts_with('[') {
+            current = Some(t.trim_matches(['[', ']']).replace([' ', '"'], ""));
+            if current == section {
+                header = Some(i);
+            }
+            continue;
+        }
+        if current == section {
+            if !t.is_empty() {
+                last = Some(i);
+            }
+            if line_key(t) == Some(key) {
+                found = Some(i);
+            }
+        }
+    }
+
+    let new_line = value.map(|v| format!("{key} = {v}"));
+    let mut out: Vec<String> = lines.iter().map(|l| (*l).to_string()).collect();
+    match (found, new_line) {
+        // A value spread over several lines cannot be replaced line by line.
+        | (Some(i), _) if toml::from_str::<toml::Table>(lines[i]).is_err() => {
+            return rewrite(path, &mut table, profile, key, value);
+        }
+        | (Some(i), Some(line)) => out[i] = line,
+        | (Some(i), None) => {
+            out.remove(i);
+        }
+        | (None, None) => return Ok(false),
+        | (None, Some(line)) => {
+            match (section.is_some(), header, last) {
+                | (_, _, Some(i)) => out.insert(i + 1, line),
+                | (true, Some(h), None) => out.insert(h + 1, line),
+                | (true, None, _) => {
+                    if out.last().is_some_and(|l| !l.trim().is_empty()) {
+                        out.push(String::new());
+                    }
+                    out.push(format!("[{}]", section.unwrap_or_default()));
+                    out.push(line);
+                }
+                | (false, _, None) => out.insert(0, line),
+            }
+        }
+    }
+    let mut edited = out.join("\n");
+    edited.push('\n');
+    if read_back(&edited, profile, key) != value.cloned() {
+        return rewrite(path, &mut table, profile, key, value);
+    }
+    save(path, &edited)?;
+    Ok(true)
+}
+
+/// The key a `key = value` line sets, if it is one.
+fn line_key(line: &str) -> Option<&str> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let (key, _) = line.split_once('=')?;
+    Some(key.trim().trim_matches('"'))
+}
+
+fn read_back(text: &str, profile: Option<&str>, key: &str) -> Option<toml::Value> {
+    let table: toml::Table = toml::from_str(text).ok()?;
+    let section = match profile {
+        | Some(name) => table.get(PROFILES)?.get(name)?.as_table()?,
+        | None => &table,
+    };
+    section.get(key).cloned()
+}
+
+/// Fallback for layouts the line edit cannot handle: reserialize the whole file. Comments are lost.
+fn rewrite(
+    path: &Path,
+    table: &mut toml::Table,
+    profile: Option<&str>,
+    key: &str,
+    value: Option<&toml::Value>,
+) -> Result<bool> {
+    let section = match profile {
+        | Some(name) => {
+            let profiles = table
+                .entry(PROFILES)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            let Some(profiles) = profiles.as_table_mut() else {
+                return Err(MabelError::Config {
+                    msg: format!("{}: `{PROFILES}` must be a table", path.display()),
+                });
+            };
+            let Some(section) = profiles
+                .entry(name)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+            else {
+                return Err(MabelError::Config {
+                    msg: format!("{}: `{PROFILES}.{name}` must be a table", path.display()),
+                });
+            };
+            section
+        }
+        | None => table,
+    };
+    let changed = match value {
+        | Some(v) => section.insert(key.to_string(), v.clone()).as_ref() != Some(v),
+        | None => section.remove(key).is_some(),
+    };
+    if changed {
+        tracing::warn!(file = %path.display(), "rewrote the config file; its comments were not kept");
+        let text = toml::to_string(table).map_err(|e| {
+            MabelError::Config {
+                msg: format!("{}: {e}", path.display()),
+            }
+        })?;
+        save(path, &text)?;
+    }
+    Ok(changed)
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        | Ok(text) => Ok(text),
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        | Err(source) => {
+            Err(MabelError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    }
+}
+
+fn parse_file(path: &Path, text: &str) -> Result<toml::Table> {
+    toml::from_str(text).map_err(|e| {
+        MabelError::Config {
+            msg: format!("{}: {e}", path.display()),
+        }
+    })
+}
+
+fn save(path: &Path, text: &str) -> Result<()> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(io_err)?;
+    }
+    std::fs::write(path, text).map_err(io_err)
+}
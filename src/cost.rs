@@ -0,0 +1,219 @@
+//! What model requests cost, estimated from the tokens they used and a price table per model, and
+//! the budgets that cap it: `MABEL_MAX_COST_PER_PAPER` and `MABEL_MAX_COST_PER_DAY`, in US dollars.
+//!
+//! Before a paper is summarized, the most its summary requests can cost (the prompts, plus replies
+//! of the full `max_tokens`) is checked against both budgets, and a paper that would go over one is
+//! refused with [`MabelError::Guardrail`]. What each paper did cost is kept for the day in the
+//! cache directory (see [`Config::spend_path`]), so the daily budget holds across runs. Papers
+//! summarized at the same time count each other's most too, from the check until their cost is
+//! kept (see [`Reservation`]), so a batch cannot go over the day's budget together. Local
+//! models cost nothing; a hosted model missing from the table has no estimate, and budgets cannot
+//! hold it back.
+
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    llm::{Llm, Usage},
+    store, MabelError, Result,
+};
+
+/// Prices in US dollars per million tokens, prompt and completion, by model name prefix; the
+/// longest prefix that matches wins.
+const PRICES: &[(&str, Price)] = &[
+    ("gpt-5", Price::new(1.25, 10.0)),
+    ("gpt-5-mini", Price::new(0.25, 2.0)),
+    ("gpt-5-nano", Price::new(0.05, 0.4)),
+    ("gpt-4.1", Price::new(2.0, 8.0)),
+    ("gpt-4.1-mini", Price::new(0.4, 1.6)),
+    ("gpt-4.1-nano", Price::new(0.1, 0.4)),
+    ("gpt-4o", Price::new(2.5, 10.0)),
+    ("gpt-4o-mini", Price::new(0.15, 0.6)),
+    ("gpt-4-turbo", Price::new(10.0, 30.0)),
+    ("gpt-4", Price::new(30.0, 60.0)),
+    ("gpt-3.5-turbo", Price::new(0.5, 1.5)),
+    ("o1", Price::new(15.0, 60.0)),
+    ("o1-mini", Price::new(1.1, 4.4)),
+    ("o3", Price::new(2.0, 8.0)),
+    ("o3-mini", Price::new(1.1, 4.4)),
+    ("o4-mini", Price::new(1.1, 4.4)),
+    ("claude-3-haiku", Price::new(0.25, 1.25)),
+    ("claude-3-5-haiku", Price::new(0.8, 4.0)),
+    ("claude-haiku-4-5", Price::new(1.0, 5.0)),
+    ("claude-3-5-sonnet", Price::new(3.0, 15.0)),
+    ("claude-3-7-sonnet", Price::new(3.0, 15.0)),
+    ("claude-sonnet-4", Price::new(3.0, 15.0)),
+    ("claude-3-opus", Price::new(15.0, 75.0)),
+    ("claude-opus-4", Price::new(15.0, 75.0)),
+    ("claude-opus-4-5", Price::new(5.0, 25.0)),
+    ("gemini-1.5-flash", Price::new(0.075, 0.3)),
+    ("gemini-1.5-pro", Price::new(1.25, 5.0)),
+    ("gemini-2.0-flash", Price::new(0.1, 0.4)),
+    ("gemini-2.0-flash-lite", Price::new(0.075, 0.3)),
+    ("gemini-2.5-flash", Price::new(0.3, 2.5)),
+    ("gemini-2.5-flash-lite", Price::new(0.1, 0.4)),
+    ("gemini-2.5-pro", Price::new(1.25, 10.0)),
+];
+
+/// What the OpenAI Batch API charges of the usual price.
+const BATCH_DISCOUNT: f64 = 0.5;
+
+/// Characters per token, near enough for English prose to estimate a prompt before sending it.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// What a model charges, in US dollars per million tokens.
+#[derive(Clone, Copy, Debug)]
+pub struct Price {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl Price {
+    const FREE: Self = Self::new(0.0, 0.0);
+
+    const fn new(prompt: f64, completion: f64) -> Self {
+        Self { prompt, completion }
+    }
+
+    /// What `usage` costs at this price.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cost(self, usage: Usage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt + usage.completion_tokens as f64 * self.completion) / 1e6
+    }
+}
+
+/// What the model `llm` runs charges; `None` for a hosted model not in the table. Requests that
+/// may wait for an OpenAI batch are taken to cost half, though those that are not batched (a
+/// paper's figures and claims, say) cost the full price.
+pub fn price(llm: &Llm) -> Option<Price> {
+    if llm.is_local() {
+        return Some(Price::FREE);
+    }
+    let model = llm.model().to_ascii_lowercase();
+    let price = PRICES
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|&(_, price)| price)?;
+    if llm.is_batched() {
+        return Some(Price::new(
+            price.prompt * BATCH_DISCOUNT,
+            price.completion * BATCH_DISCOUNT,
+        ));
+    }
+    Some(price)
+}
+
+/// What `usage` cost on `llm`, when its price is known.
+pub fn estimate(llm: &Llm, usage: Usage) -> Option<f64> {
+    price(llm).map(|price| price.cost(usage))
+}
+
+/// Roughly how many tokens `chars` characters of prompt take.
+pub fn tokens(chars: usize) -> u64 {
+    (chars / CHARS_PER_TOKEN) as u64
+}
+
+/// A cost in dollars, for logs and summaries.
+pub fn format(cost: f64) -> String {
+    if cost > 0.0 && cost < 0.0001 {
+        "<$0.0001".to_string()
+    } else if cost < 0.01 {
+        format!("${cost:.4}")
+    } else {
+        format!("${cost:.2}")
+    }
+}
+
+/// What one paper cost, as kept for the daily budget.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Spent {
+    /// The day, in the configured time zone
+    day: String,
+    title: String,
+    model: String,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: f64,
+}
+
+/// What papers have cost today.
+pub async fn spent_today(cfg: &Config) -> Result<f64> {
+    let today = cfg.timezone.today().to_string();
+    let spent: Vec<Spent> = store::load(&cfg.spend_path()).await?;
+    Ok(spent.iter().filter(|s| s.day == today).map(|s| s.cost).sum())
+}
+
+/// Keep what a paper cost, dropping what was kept for earlier days.
+pub async fn record(cfg: &Config, title: &str, model: &str, usage: Usage, cost: f64) -> Result<()> {
+    // Papers processed at the same time would otherwise drop each other's records.
+    static RECORDING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _recording = RECORDING.lock().await;
+    let today = cfg.timezone.today().to_string();
+    let spent = Spent {
+        day: today.clone(),
+        title: title.to_string(),
+        model: model.to_string(),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        cost,
+    };
+    store::replace(&cfg.spend_path(), &[spent], |s: &Spent| s.day != today).await?;
+    Ok(())
+}
+
+/// What papers being summarized in this process may still cost, on top of what is kept.
+static RESERVED: Mutex<f64> = Mutex::new(0.0);
+
+/// The most a paper whose budgets were checked may cost, counted against the day's budget until
+/// this is dropped, once what it did cost is kept.
+#[derive(Debug, Default)]
+#[must_use]
+pub struct Reservation(f64);
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.0 > 0.0 {
+            *RESERVED.lock().unwrap_or_else(PoisonError::into_inner) -= self.0;
+        }
+    }
+}
+
+/// Refuse a request that may cost up to `expected` when that would go over the paper's or the
+/// day's budget, counting what the papers checked before it and not yet kept may cost; otherwise
+/// reserve `expected` until the returned [`Reservation`] is dropped.
+pub async fn check_budgets(cfg: &Config, expected: f64) -> Result<Reservation> {
+    // Papers checked at the same time would otherwise each see the other's cost as unspent.
+    static CHECKING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    if let Some(max) = cfg.max_cost_per_paper.filter(|&max| expected > max) {
+        return Err(MabelError::Guardrail {
+            reason: format!(
+                "the summary may cost up to {}, more than MABEL_MAX_COST_PER_PAPER ({})",
+                format(expected),
+                format(max)
+            ),
+        });
+    }
+    let Some(max) = cfg.max_cost_per_day else {
+        return Ok(Reservation::default());
+    };
+    let _checking = CHECKING.lock().await;
+    let spent = spent_today(cfg).await?;
+    let mut reserved = RESERVED.lock().unwrap_or_else(PoisonError::into_inner);
+    if spent + *reserved + expected > max {
+        return Err(MabelError::Guardrail {
+            reason: format!(
+                "{} spent today, {} more for papers being summarized, and the summary may cost up to {} more, past \
+                 MABEL_MAX_COST_PER_DAY ({})",
+                format(spent),
+                format(*reserved),
+                format(expected),
+                format(max)
+            ),
+        });
+    }
+    *reserved += expected;
+    Ok(Reservation(expected))
+}
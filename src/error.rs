@@ -1,6 +1,9 @@
 use chrono::ParseError as ChronoParseError;
 use reqwest::StatusCode;
-use std::path::PathBuf;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 use url::Url;
 
@@ -24,12 +27,63 @@ pub enum MabelError {
         source: std::io::Error,
     },
 
+    #[error("paper index {path}: {source}")]
+    Index {
+        path: PathBuf,
+        #[source]
+        source: rusqlite::Error,
+    },
+
+    #[error("cannot watch {dir}: {source}")]
+    Watch {
+        dir: PathBuf,
+        #[source]
+        source: notify::Error,
+    },
+
     #[error("vault path not writable: {path}")]
     VaultNotWritable { path: PathBuf },
 
     #[error("template not found or unreadable: {path}")]
     TemplateMissing { path: PathBuf },
 
+    #[error("note already exists: {path} (pass --overwrite to update it)")]
+    NoteExists { path: PathBuf },
+
+    #[error(
+        "{path} has no managed regions to update; `mabel adopt` it to keep your text, or pass --force-overwrite to \
+         replace it"
+    )]
+    NoteUnmanaged { path: PathBuf },
+
+    #[error("left {path} unchanged")]
+    NoteDeclined { path: PathBuf },
+
+    #[error("{failed} of {total} papers failed")]
+    BatchFailed { failed: usize, total: usize },
+
+    #[error("logged {count} messages at {level} or above, which --ci fails on (MABEL_FAIL_ON); the first: {first}")]
+    FailedOn {
+        count: usize,
+        level: &'static str,
+        first: String,
+    },
+
+    #[error("{failed} of {total} self-test checks failed")]
+    SelftestFailed { failed: usize, total: usize },
+
+    #[error("{failed} of {total} doctor checks failed")]
+    DoctorFailed { failed: usize, total: usize },
+
+    #[error("the PDF has {pages} pages, more than MABEL_MAX_PAGES ({max}); pass --yes to summarize it anyway")]
+    TooManyPages { pages: u32, max: u32 },
+
+    #[error("{path} looks like a paper already processed, into {note}; pass --yes to process it anyway")]
+    Duplicate { path: PathBuf, note: PathBuf },
+
+    #[error("frontmatter does not match the schema {schema}: {}", problems.join("; "))]
+    FrontmatterSchema { schema: PathBuf, problems: Vec<String> },
+
     // ------------------- HTTP / network -------------------
     #[error("HTTP request failed for {url}: {source}")]
     Http {
@@ -44,8 +98,13 @@ pub enum MabelError {
         status: StatusCode,
         /// Optional 1–2 KB snippet of the body for diagnostics.
         body_snip: String,
+        /// Seconds the server asked to wait before trying again (`Retry-After`)
+        retry_after: Option<u32>,
     },
 
+    #[error("not downloading {url} on a metered connection; `mabel flush-queue` fetches it later")]
+    DownloadDeferred { url: Url },
+
     #[error("URL parse error: {0}")]
     Url(#[from] url::ParseError),
 
@@ -73,6 +132,15 @@ pub enum MabelError {
     #[error("invalid arXiv id or URL: {input}")]
     InvalidArxivId { input: String },
 
+    #[error("invalid PubMed/PMC id or URL: {input}")]
+    InvalidPubmedId { input: String },
+
+    #[error("invalid DOI or doi.org URL: {input}")]
+    InvalidDoi { input: String },
+
+    #[error("don't know how to ingest {input:?}: expected an identifier or a supported file")]
+    UnsupportedInput { input: String },
+
     #[error("extraction failed: {reason}")]
     Extraction { reason: String },
 
@@ -83,7 +151,120 @@ pub enum MabelError {
     Guardrail { reason: String },
 
     // ------------------- LLM backends -------------------
+    #[error("the request waits for an OpenAI batch (`mabel batch --economy`)")]
+    BatchPending,
+
+    #[error("OpenAI batch {batch}: {reason}")]
+    Batch { batch: String, reason: String },
+
     #[cfg(feature = "openai")]
     #[error("OpenAI API error: {0}")]
     OpenAi(#[from] async_openai::error::OpenAIError),
+
+    #[cfg(feature = "ollama")]
+    #[error("Ollama error: {0}")]
+    Ollama(#[from] ollama_rs::error::OllamaError),
+
+    // ------------------- Context -------------------
+    /// An error from the pipeline, with the paper it was processing and the stage it was in. Match
+    /// on [`MabelError::root`] to tell what went wrong.
+    #[error("{paper}: {stage} failed{}: {source}", at(artifact.as_deref()))]
+    Staged {
+        stage: Stage,
+        /// The input as given: an identifier, URL or file
+        paper: String,
+        /// The file being read or written, when there is one
+        artifact: Option<PathBuf>,
+        #[source]
+        source: Box<MabelError>,
+    },
+}
+
+/// Where in the pipeline a [`MabelError::Staged`] error happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Parsing the input and looking up its metadata
+    Resolve,
+    /// Reading the text of a local file
+    Extract,
+    Summarize,
+    Render,
+    Write,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            | Self::Resolve => "metadata lookup",
+            | Self::Extract => "text extraction",
+            | Self::Summarize => "summary",
+            | Self::Render => "rendering",
+            | Self::Write => "writing the note",
+        })
+    }
+}
+
+fn at(artifact: Option<&Path>) -> String {
+    artifact.map(|p| format!(" ({})", p.display())).unwrap_or_default()
+}
+
+impl MabelError {
+    /// The error beneath any [`MabelError::Staged`] context.
+    pub fn root(&self) -> &Self {
+        match self {
+            | Self::Staged { source, .. } => source.root(),
+            | other => other,
+        }
+    }
+
+    /// The stage the error happened in, if known.
+    pub fn stage(&self) -> Option<Stage> {
+        match self {
+            | Self::Staged { stage, .. } => Some(*stage),
+            | _ => None,
+        }
+    }
+}
+
+/// Attach pipeline context to a result's error. An error that already has context keeps it, as
+/// the innermost is the most precise.
+pub trait StageContext<T> {
+    fn stage(self, stage: Stage, paper: &str) -> Result<T>;
+
+    /// Like [`StageContext::stage`], naming the file involved.
+    fn stage_at(self, stage: Stage, paper: &str, artifact: &Path) -> Result<T>;
+}
+
+impl<T, E: Into<MabelError>> StageContext<T> for Result<T, E> {
+    fn stage(self, stage: Stage, paper: &str) -> Result<T> {
+        self.map_err(|e| staged(e.into(), stage, paper, None))
+    }
+
+    fn stage_at(self, stage: Stage, paper: &str, artifact: &Path) -> Result<T> {
+        self.map_err(|e| staged(e.into(), stage, paper, Some(artifact)))
+    }
+}
+
+fn staged(e: MabelError, stage: Stage, paper: &str, artifact: Option<&Path>) -> MabelError {
+    if matches!(e, MabelError::Staged { .. }) {
+        return e;
+    }
+    MabelError::Staged {
+        stage,
+        paper: paper.trim().to_string(),
+        artifact: artifact.map(Path::to_path_buf),
+        source: Box::new(e),
+    }
+}
+
+impl From<mabel_core::Error> for MabelError {
+    fn from(e: mabel_core::Error) -> Self {
+        match e {
+            | mabel_core::Error::Config { msg } => Self::Config { msg },
+            | mabel_core::Error::InvalidArxivId { input } => Self::InvalidArxivId { input },
+            | mabel_core::Error::InvalidPubmedId { input } => Self::InvalidPubmedId { input },
+            | mabel_core::Error::InvalidDoi { input } => Self::InvalidDoi { input },
+            | mabel_core::Error::Template(e) => Self::Template(e),
+        }
+    }
 }
@@ -0,0 +1,191 @@
+//! Prompt evaluation: a corpus of papers, a rubric, and an LLM judge that scores candidate
+//! outputs against it. Driven by `mabel eval`.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{extract::jats, llm, paper::PaperMetadata, source::local, MabelError, Result};
+
+/// One thing the judge scores, on a 1–5 scale.
+#[derive(Clone, Debug)]
+pub struct Criterion {
+    pub name: String,
+    pub description: String,
+}
+
+/// Used when no `--rubric` file is given.
+pub fn default_rubric() -> Vec<Criterion> {
+    [
+        (
+            "faithfulness",
+            "Every claim is supported by the source; nothing is invented or distorted.",
+        ),
+        ("coverage", "The main contribution, method and results are all present."),
+        (
+            "concision",
+            "No filler, repetition or generic statements that would fit any paper.",
+        ),
+        (
+            "usefulness",
+            "A researcher skimming the note later would understand why the paper matters.",
+        ),
+    ]
+    .into_iter()
+    .map(|(name, description)| {
+        Criterion {
+            name: name.to_string(),
+            description: description.to_string(),
+        }
+    })
+    .collect()
+}
+
+/// Parse a rubric file: one `name: description` per line; blank lines and `#` comments are
+/// ignored.
+pub fn parse_rubric(text: &str) -> Result<Vec<Criterion>> {
+    let rubric: Vec<Criterion> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|line| {
+            let (name, description) = line.split_once(':').ok_or_else(|| {
+                MabelError::Config {
+                    msg: format!("rubric line {line:?} is not of the form `name: description`"),
+                }
+            })?;
+            Ok(Criterion {
+                name: name.trim().to_string(),
+                description: description.trim().to_string(),
+            })
+        })
+        .collect::<Result<_>>()?;
+    if rubric.is_empty() {
+        return Err(MabelError::Config {
+            msg: "rubric has no criteria".to_string(),
+        });
+    }
+    Ok(rubric)
+}
+
+/// A paper in the evaluation corpus.
+#[derive(Clone, Debug)]
+pub struct CorpusDoc {
+    /// File name, used to label rows in the report
+    pub name: String,
+    pub metadata: PaperMetadata,
+    pub text: String,
+}
+
+/// Load every usable file in `dir`: JATS XML (as for `mabel note`) and plain `.txt`/`.md` files,
+/// whose first line is taken as the title.
+pub async fn load_corpus(dir: &Path) -> Result<Vec<CorpusDoc>> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: dir.to_path_buf(),
+            source,
+        }
+    };
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(io_err)?;
+    while let Some(entry) = entries.next_entry().await.map_err(io_err)? {
+        paths.push(entry.path());
+    }
+    paths.sort();
+
+    let mut docs = Vec::new();
+    for path in paths.iter().filter(|p| p.is_file()) {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        if jats::FILE_EXTENSIONS.contains(&ext.as_str()) {
+            let paper = local::resolve_jats_file(path).await?;
+            let text = crate::pipeline::paper_text(&paper);
+            docs.push(CorpusDoc {
+                name,
+                metadata: paper.metadata,
+                text,
+            });
+        } else if matches!(ext.as_str(), "txt" | "md") {
+            let raw = tokio::fs::read_to_string(path).await.map_err(|source| {
+                MabelError::Io {
+                    path: path.clone(),
+                    source,
+                }
+            })?;
+            let (title, body) = raw.split_once('\n').unwrap_or((raw.as_str(), ""));
+            docs.push(CorpusDoc {
+                name,
+                metadata: PaperMetadata {
+                    title: title.trim_start_matches('#').trim().to_string(),
+                    ..PaperMetadata::default()
+                },
+                text: body.trim().to_string(),
+            });
+        } else {
+            tracing::debug!(path = %path.display(), "skipping file that is not a corpus document");
+        }
+    }
+    if docs.is_empty() {
+        return Err(MabelError::Config {
+            msg: format!("no corpus documents (.xml/.nxml/.txt/.md) in {}", dir.display()),
+        });
+    }
+    Ok(docs)
+}
+
+/// The judge's verdict on one output; `scores` is aligned with the rubric.
+#[derive(Clone, Debug, Default)]
+pub struct Judgement {
+    pub scores: Vec<Option<u8>>,
+    pub comment: String,
+}
+
+impl Judgement {
+    pub fn total(&self) -> u32 {
+        self.scores.iter().flatten().map(|&s| u32::from(s)).sum()
+    }
+}
+
+#[derive(Deserialize)]
+struct JudgeReply {
+    #[serde(default)]
+    scores: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    comment: String,
+}
+
+/// Parse the judge's JSON reply. Scores outside 1–5 or missing criteria are recorded as `None`
+/// rather than failing the whole run.
+pub fn parse_judgement(reply: &str, rubric: &[Criterion]) -> Judgement {
+    let trimmed = reply.trim();
+    let candidate = llm::json_object(trimmed);
+    let Ok(parsed) = serde_json::from_str::<JudgeReply>(candidate) else {
+        tracing::warn!("judge reply was not the requested JSON");
+        return Judgement {
+            scores: vec![None; rubric.len()],
+            comment: trimmed.to_string(),
+        };
+    };
+    let scores = rubric
+        .iter()
+        .map(|c| {
+            parsed
+                .scores
+                .get(&c.name)
+                .and_then(serde_json::Value::as_u64)
+                .and_then(|s| u8::try_from(s).ok())
+                .filter(|s| (1..=5).contains(s))
+        })
+        .collect();
+    Judgement {
+        scores,
+        comment: parsed.comment,
+    }
+}
@@ -0,0 +1,415 @@
+//! EPUB extraction for textbook chapters and long reports.
+//!
+//! An EPUB is a zip of XHTML documents plus an OPF package file that lists them in reading order
+//! (the spine). Each non-trivial spine document becomes one chapter, i.e. one top-level
+//! [`Section`], titled from the table of contents where possible.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::NaiveDate;
+use zip::ZipArchive;
+
+use crate::{
+    paper::{Extractor, PaperMetadata, PaperStructure, Section},
+    xml::{self, Element},
+    MabelError, Result,
+};
+
+pub const FILE_EXTENSIONS: &[&str] = &["epub"];
+
+/// Spine documents with less text than this are covers, title pages, copyright notices, ...
+const MIN_CHAPTER_CHARS: usize = 200;
+
+/// Elements whose text we take as one paragraph.
+const BLOCKS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "blockquote",
+    "pre",
+    "dt",
+    "dd",
+    "figcaption",
+];
+
+/// One chapter as found in the spine.
+#[derive(Clone, Debug)]
+pub struct Chapter {
+    /// 1-based position among non-trivial spine documents
+    pub number: usize,
+    pub title: String,
+    pub text: String,
+}
+
+/// A parsed EPUB: package metadata plus chapters in reading order.
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    pub metadata: PaperMetadata,
+    pub chapters: Vec<Chapter>,
+}
+
+impl Book {
+    /// Map the selected chapters to a structure with one top-level section per chapter.
+    pub fn to_structure(&self, selection: Option<&ChapterSelection>) -> PaperStructure {
+        let sections = self
+            .chapters
+            .iter()
+            .filter(|c| selection.is_none_or(|s| s.contains(c.number)))
+            .map(|c| Section::new(c.title.clone(), 1, c.text.clone(), Extractor::Epub))
+            .collect();
+        PaperStructure {
+            title: Some(self.metadata.title.clone()).filter(|t| !t.is_empty()),
+            abstract_text: self.metadata.abstract_text.clone(),
+            sections,
+            ..PaperStructure::default()
+        }
+    }
+}
+
+/// Which chapters to keep, as given by `--chapters 1-3,5,9-`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChapterSelection {
+    /// Inclusive ranges; `None` as the end means "to the last chapter"
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl ChapterSelection {
+    pub fn contains(&self, chapter: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| chapter >= start && end.is_none_or(|end| chapter <= end))
+    }
+}
+
+impl FromStr for ChapterSelection {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |why: &str| {
+            MabelError::Config {
+                msg: format!("invalid chapter selection {s:?}: {why}"),
+            }
+        };
+        let number = |n: &str| -> Result<usize> {
+            n.trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| invalid("chapters are numbered from 1"))
+        };
+
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let range = match part.split_once('-') {
+                | Some((start, "")) => (number(start)?, None),
+                | Some((start, end)) => {
+                    let (start, end) = (number(start)?, number(end)?);
+                    if end < start {
+                        return Err(invalid("range end is before its start"));
+                    }
+                    (start, Some(end))
+                }
+                | None => {
+                    let n = number(part)?;
+                    (n, Some(n))
+                }
+            };
+            ranges.push(range);
+        }
+        if ranges.is_empty() {
+            return Err(invalid("no chapters given"));
+        }
+        Ok(Self { ranges })
+    }
+}
+
+impl fmt::Display for ChapterSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|&(start, end)| {
+                match end {
+                    | Some(end) if end == start => start.to_string(),
+                    | Some(end) => format!("{start}-{end}"),
+                    | None => format!("{start}-"),
+                }
+            })
+            .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+/// Read an EPUB from disk. This is blocking zip/XML work; call it from `spawn_blocking`.
+pub fn read(path: &Path) -> Result<Book> {
+    let io_err = |source: std::io::Error| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let file = File::open(path).map_err(io_err)?;
+    let mut archive = ZipArchive::new(file).map_err(|e| malformed(path, &e.to_string()))?;
+
+    let container = read_entry(&mut archive, path, "META-INF/container.xml")?;
+    let container = xml::parse(&container, "EPUB container")?;
+    let opf_path = container
+        .find("rootfile")
+        .and_then(|r| r.attr("full-path"))
+        .ok_or_else(|| malformed(path, "container.xml names no package file"))?
+        .to_string();
+    let opf_dir = Path::new(&opf_path).parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let opf = read_entry(&mut archive, path, &opf_path)?;
+    let opf = xml::parse(&opf, "EPUB package")?;
+
+    let manifest: HashMap<&str, (&str, &str, &str)> = opf
+        .child("manifest")
+        .map(|m| {
+            m.children_named("item")
+                .filter_map(|item| {
+                    Some((
+                        item.attr("id")?,
+                        (
+                            item.attr("href")?,
+                            item.attr("media-type").unwrap_or_default(),
+                            item.attr("properties").unwrap_or_default(),
+                        ),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let toc = table_of_contents(&mut archive, path, &opf, &opf_dir, &manifest);
+
+    let mut chapters = Vec::new();
+    let spine = opf
+        .child("spine")
+        .map(|s| s.children_named("itemref").collect::<Vec<_>>());
+    for itemref in spine.unwrap_or_default() {
+        if itemref.attr("linear") == Some("no") {
+            continue;
+        }
+        let Some(&(href, media_type, _)) = itemref.attr("idref").and_then(|id| manifest.get(id)) else {
+            continue;
+        };
+        if !media_type.contains("html") {
+            continue;
+        }
+        let entry = resolve_href(&opf_dir, href);
+        let doc = read_entry(&mut archive, path, &entry)?;
+        // Malformed chapters are skipped rather than failing the whole book.
+        let Ok(html) = xml::parse(&doc, "EPUB chapter") else {
+            tracing::warn!(chapter = %entry, "skipping unparseable EPUB chapter");
+            continue;
+        };
+        let text = chapter_text(&html);
+        if text.len() < MIN_CHAPTER_CHARS {
+            continue;
+        }
+        let number = chapters.len() + 1;
+        let title = toc
+            .get(&entry)
+            .cloned()
+            .or_else(|| first_heading(&html))
+            .unwrap_or_else(|| format!("Chapter {number}"));
+        chapters.push(Chapter { number, title, text });
+    }
+
+    if chapters.is_empty() {
+        return Err(MabelError::Extraction {
+            reason: format!("{}: no readable chapters", path.display()),
+        });
+    }
+
+    Ok(Book {
+        metadata: package_metadata(&opf),
+        chapters,
+    })
+}
+
+fn package_metadata(opf: &Element) -> PaperMetadata {
+    let mut md = PaperMetadata::default();
+    let Some(meta) = opf.child("metadata") else {
+        return md;
+    };
+    md.title = meta.find_text("title").unwrap_or_default();
+    md.authors = meta.find_all("creator").into_iter().map(Element::text).collect();
+    md.abstract_text = meta.find_text("description");
+    md.journal = meta.find_text("publisher");
+    md.keywords = meta.find_all("subject").into_iter().map(Element::text).collect();
+    md.published = meta.find_text("date").and_then(|d| {
+        let year_first = d.get(..10).unwrap_or(&d);
+        NaiveDate::parse_from_str(year_first, "%Y-%m-%d")
+            .ok()
+            .or_else(|| d.get(..4)?.parse().ok().and_then(|y| NaiveDate::from_ymd_opt(y, 1, 1)))
+    });
+    for id in meta.find_all("identifier") {
+        let value = id.text();
+        if let Some(doi) = value
+            .strip_prefix("doi:")
+            .or_else(|| value.strip_prefix("https://doi.org/"))
+        {
+            md.doi = Some(doi.to_string());
+        }
+    }
+    md
+}
+
+/// Map spine document paths to their table-of-contents titles, from the EPUB 3 nav document or
+/// the EPUB 2 NCX, whichever is present.
+fn table_of_contents<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    path: &Path,
+    opf: &Element,
+    opf_dir: &Path,
+    manifest: &HashMap<&str, (&str, &str, &str)>,
+) -> HashMap<String, String> {
+    let mut toc = HashMap::new();
+
+    let nav_href = manifest
+        .values()
+        .find(|(_, _, props)| props.split_whitespace().any(|p| p == "nav"))
+        .map(|(href, _, _)| *href);
+    let ncx_href = opf
+        .child("spine")
+        .and_then(|s| s.attr("toc"))
+        .and_then(|id| manifest.get(id))
+        .map(|(href, _, _)| *href);
+
+    if let Some(href) = nav_href {
+        let nav_path = resolve_href(opf_dir, href);
+        let nav_dir = Path::new(&nav_path).parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(doc) = read_entry(archive, path, &nav_path)
+            .ok()
+            .and_then(|d| xml::parse(&d, "EPUB nav").ok())
+        {
+            let toc_nav = doc
+                .find_all("nav")
+                .into_iter()
+                .find(|n| n.attr("type") == Some("toc"))
+                .or_else(|| doc.find("nav"));
+            for a in toc_nav.map(|n| n.find_all("a")).unwrap_or_default() {
+                if let Some(target) = a.attr("href") {
+                    toc.entry(resolve_href(&nav_dir, target)).or_insert_with(|| a.text());
+                }
+            }
+        }
+    } else if let Some(href) = ncx_href {
+        let ncx_path = resolve_href(opf_dir, href);
+        let ncx_dir = Path::new(&ncx_path).parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(doc) = read_entry(archive, path, &ncx_path)
+            .ok()
+            .and_then(|d| xml::parse(&d, "EPUB NCX").ok())
+        {
+            for point in doc.find_all("navPoint") {
+                let label = point.child("navLabel").map(Element::text);
+                let src = point.child("content").and_then(|c| c.attr("src"));
+                if let (Some(label), Some(src)) = (label, src) {
+                    toc.entry(resolve_href(&ncx_dir, src)).or_insert(label);
+                }
+            }
+        }
+    }
+    toc.retain(|_, title| !title.is_empty());
+    toc
+}
+
+fn chapter_text(html: &Element) -> String {
+    let mut paras = Vec::new();
+    if let Some(body) = html.find("body") {
+        collect_blocks(body, &mut paras);
+    }
+    paras.join("\n\n")
+}
+
+fn collect_blocks(el: &Element, out: &mut Vec<String>) {
+    let has_loose_text = el
+        .children
+        .iter()
+        .any(|n| matches!(n, xml::Node::Text(t) if !t.trim().is_empty()));
+    if BLOCKS.contains(&el.name.as_str()) || has_loose_text {
+        let text = el.text_without(&["script", "style"]);
+        if !text.is_empty() {
+            out.push(text);
+        }
+        return;
+    }
+    for child in el.elements() {
+        if !matches!(child.name.as_str(), "script" | "style" | "nav") {
+            collect_blocks(child, out);
+        }
+    }
+}
+
+fn first_heading(html: &Element) -> Option<String> {
+    ["h1", "h2", "h3", "title"].iter().find_map(|h| html.find_text(h))
+}
+
+/// Resolve an href relative to the document that contains it, dropping any `#fragment` and
+/// decoding `%XX` escapes so it matches the zip entry name.
+fn resolve_href(base_dir: &Path, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut parts: Vec<String> = Vec::new();
+    for comp in base_dir.join(percent_decode(href)).components() {
+        match comp {
+            | std::path::Component::ParentDir => {
+                parts.pop();
+            }
+            | std::path::Component::Normal(p) => parts.push(p.to_string_lossy().into_owned()),
+            | _ => {}
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn read_entry<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>, path: &Path, name: &str) -> Result<String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| malformed(path, &format!("missing entry {name}")))?;
+    let mut out = String::new();
+    entry.read_to_string(&mut out).map_err(|source| {
+        MabelError::Io {
+            path: PathBuf::from(format!("{}!{name}", path.display())),
+            source,
+        }
+    })?;
+    Ok(out)
+}
+
+fn malformed(path: &Path, why: &str) -> MabelError {
+    MabelError::Extraction {
+        reason: format!("{} is not a valid EPUB: {why}", path.display()),
+    }
+}
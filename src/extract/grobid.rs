@@ -0,0 +1,355 @@
+//! GROBID extraction: a PDF goes to a GROBID server, TEI comes back and is mapped to a
+//! [`PaperStructure`].
+//!
+//! With consolidation on, GROBID looks the header and/or each reference up in Crossref (or a
+//! biblio-glutton instance) and adds the DOI, PMID and arXiv id it finds. Those identifiers are
+//! what links a reference to a vault note reliably, so a full GROBID with citation consolidation
+//! gives far better related-work links than the printed reference strings alone.
+
+use std::{path::Path, time::Duration};
+
+use reqwest::{
+    multipart::{Form, Part},
+    Body,
+};
+use url::Url;
+
+use super::Extracted;
+use crate::{
+    config::Config,
+    http, memory,
+    paper::{Extractor, Figure, FigureRegion, PaperMetadata, PaperStructure, Reference, Section},
+    xml::{self, Element},
+    MabelError, Result,
+};
+
+/// Full-text processing takes far longer than a metadata lookup, especially with citation
+/// consolidation (one lookup per reference).
+const MIN_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Send the PDF at `pdf` to GROBID's `processFulltextDocument` with the configured consolidation
+/// and any headers or credentials the server's proxy needs. The PDF is streamed from the file, so
+/// a large one is never in memory whole; a retried request streams it again.
+pub async fn process(cfg: &Config, server: &Url, pdf: &Path) -> Result<Extracted> {
+    let client = http::client_for(cfg, &cfg.grobid_auth)?;
+    let url = endpoint(server, "processFulltextDocument")?;
+    let pdf_info = crate::pdf::inspect(&memory::read_within(pdf, cfg.max_memory).await?);
+    let (client, url) = (&client, &url);
+    let tei = crate::retry::run(cfg.http_retries, move || {
+        async move {
+            let resp = client
+                .post(url.clone())
+                .timeout(cfg.http_timeout.max(MIN_TIMEOUT))
+                .multipart(form(cfg, url, pdf).await?)
+                .send()
+                .await
+                .map_err(|e| http::request_error(url, e))?;
+            http::check_status(resp)
+                .await?
+                .text()
+                .await
+                .map_err(|e| http::request_error(url, e))
+        }
+    })
+    .await?;
+    let root = parse_tei(&tei)?;
+    Ok(Extracted {
+        structure: extract(&root),
+        metadata: PaperMetadata {
+            pdf: pdf_info,
+            ..metadata(&root)
+        },
+    })
+}
+
+/// The `processFulltextDocument` form for the PDF at `pdf`, streamed from the file.
+async fn form(cfg: &Config, url: &Url, pdf: &Path) -> Result<Form> {
+    let (chunks, len) = memory::chunks(pdf).await?;
+    let part = Part::stream_with_length(Body::wrap_stream(chunks), len)
+        .file_name("paper.pdf")
+        .mime_str("application/pdf")
+        .map_err(|e| http::request_error(url, e))?;
+    Ok(Form::new()
+        .part("input", part)
+        .text("consolidateHeader", cfg.grobid_consolidate_header.as_param())
+        .text("consolidateCitations", cfg.grobid_consolidate_citations.as_param())
+        .text("includeRawCitations", "1")
+        .text("teiCoordinates", "figure")
+        .text("teiCoordinates", "graphic"))
+}
+
+/// Whether the server is ready for documents, as its `isalive` endpoint tells; an error when it
+/// cannot be asked. Asked once: a health check reports what it finds rather than waiting it out.
+pub async fn is_alive(cfg: &Config, server: &Url) -> Result<bool> {
+    let client = http::client_for(cfg, &cfg.grobid_auth)?;
+    let answer = http::get_text(&client, endpoint(server, "isalive")?, 0).await?;
+    Ok(answer.trim() == "true")
+}
+
+/// The endpoint `name` under `server`, which may be mounted below a path (`http://host/grobid`).
+fn endpoint(server: &Url, name: &str) -> Result<Url> {
+    let mut base = server.clone();
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    Ok(base.join(&format!("api/{name}"))?)
+}
+
+/// Parse a TEI document and return its `<TEI>` element.
+pub fn parse_tei(doc: &str) -> Result<Element> {
+    let root = xml::parse(doc, "GROBID TEI")?;
+    if root.name == "TEI" {
+        return Ok(root);
+    }
+    root.find("TEI").cloned().ok_or_else(|| {
+        MabelError::GrobidMalformed {
+            reason: format!("no <TEI> element (root is <{}>)", root.name),
+        }
+    })
+}
+
+/// Map a `<TEI>` document to the internal structure.
+pub fn extract(tei: &Element) -> PaperStructure {
+    let header = tei.child("teiHeader");
+    let title = header
+        .and_then(|h| h.path(&["fileDesc", "titleStmt", "title"]))
+        .map(Element::text)
+        .filter(|t| !t.is_empty());
+    let abstract_text = header.and_then(abstract_text);
+
+    let body = tei.path(&["text", "body"]);
+    let sections = body.map(sections).unwrap_or_default();
+    let figures = body
+        .map(|b| b.find_all("figure").into_iter().map(figure).collect())
+        .unwrap_or_default();
+
+    let references = tei
+        .path(&["text", "back"])
+        .map(|b| b.find_all("biblStruct").into_iter().map(reference).collect())
+        .unwrap_or_default();
+
+    PaperStructure {
+        title,
+        abstract_text,
+        sections,
+        figures,
+        references,
+    }
+}
+
+/// The paper's own bibliographic record from `<sourceDesc>`; with header consolidation this is
+/// the matched record, identifiers included.
+pub fn metadata(tei: &Element) -> PaperMetadata {
+    let Some(header) = tei.child("teiHeader") else {
+        return PaperMetadata::default();
+    };
+    let bibl = header.path(&["fileDesc", "sourceDesc", "biblStruct"]);
+    let mut md = PaperMetadata {
+        title: header
+            .path(&["fileDesc", "titleStmt", "title"])
+            .map(Element::text)
+            .unwrap_or_default(),
+        abstract_text: abstract_text(header),
+        keywords: header
+            .path(&["profileDesc", "textClass", "keywords"])
+            .map(|k| k.find_all("term").into_iter().map(Element::text).collect())
+            .unwrap_or_default(),
+        ..PaperMetadata::default()
+    };
+    let Some(bibl) = bibl else {
+        return md;
+    };
+    md.authors = bibl
+        .child("analytic")
+        .map(|a| a.find_all("persName").into_iter().filter_map(person_name).collect())
+        .unwrap_or_default();
+    md.journal = bibl
+        .child("monogr")
+        .and_then(|m| m.children_named("title").find(|t| t.attr("level") == Some("j")))
+        .map(Element::text)
+        .filter(|t| !t.is_empty());
+    md.published = bibl
+        .find_all("date")
+        .into_iter()
+        .find_map(|d| d.attr("when"))
+        .and_then(parse_when);
+    for (kind, value) in identifiers(bibl) {
+        match kind {
+            | Id::Doi => md.doi = Some(value),
+            | Id::Pmid => md.pmid = Some(value),
+            | Id::Arxiv => md.arxiv_id = Some(value),
+        }
+    }
+    md
+}
+
+fn abstract_text(header: &Element) -> Option<String> {
+    let abs = header.path(&["profileDesc", "abstract"])?;
+    let paras: Vec<String> = abs
+        .find_all("p")
+        .into_iter()
+        .map(Element::text)
+        .filter(|t| !t.is_empty())
+        .collect();
+    let text = if paras.is_empty() {
+        abs.text()
+    } else {
+        paras.join("\n\n")
+    };
+    Some(text).filter(|t| !t.is_empty())
+}
+
+/// Body `<div>`s as sections. GROBID keeps the document flat; the numbering in `<head n="2.1">`
+/// is the only hint of nesting.
+fn sections(body: &Element) -> Vec<Section> {
+    body.children_named("div")
+        .filter_map(|div| {
+            let head = div.child("head");
+            let heading = head.map(Element::text).filter(|t| !t.is_empty());
+            let text = paragraphs(div);
+            if heading.is_none() && text.is_empty() {
+                return None;
+            }
+            let level = head
+                .and_then(|h| h.attr("n"))
+                .map_or(1, |n| n.trim_end_matches('.').matches('.').count() + 1);
+            Some(Section::new(
+                heading.unwrap_or_else(|| "Untitled section".to_string()),
+                u8::try_from(level).unwrap_or(u8::MAX),
+                text,
+                Extractor::Grobid,
+            ))
+        })
+        .collect()
+}
+
+/// Paragraphs (and formulas) of a `<div>`, joined by blank lines.
+fn paragraphs(div: &Element) -> String {
+    div.elements()
+        .filter(|e| matches!(e.name.as_str(), "p" | "formula" | "list"))
+        .map(Element::text)
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn figure(fig: &Element) -> Figure {
+    let label = fig
+        .child("head")
+        .map(Element::text)
+        .filter(|t| !t.is_empty())
+        .or_else(|| fig.find_text("label"));
+    Figure {
+        label,
+        caption: fig.child("figDesc").map(Element::text).unwrap_or_default(),
+        graphic: fig.find("graphic").and_then(|g| g.attr("url")).map(str::to_string),
+        region: fig
+            .find("graphic")
+            .and_then(|g| g.attr("coords"))
+            .or_else(|| fig.attr("coords"))
+            .and_then(FigureRegion::parse),
+    }
+}
+
+fn reference(bibl: &Element) -> Reference {
+    let analytic = bibl.child("analytic");
+    let monogr = bibl.child("monogr");
+    let title_in = |el: Option<&Element>| {
+        el.and_then(|e| e.child("title"))
+            .map(Element::text)
+            .filter(|t| !t.is_empty())
+    };
+
+    let mut out = Reference {
+        label: bibl.attr("n").map(str::to_string),
+        // Articles have their title in <analytic> and the venue in <monogr>; books only a <monogr>.
+        title: title_in(analytic).or_else(|| title_in(monogr)),
+        source: analytic.and(title_in(monogr)),
+        authors: analytic
+            .or(monogr)
+            .map(|e| {
+                e.children_named("author")
+                    .filter_map(|a| a.child("persName").and_then(person_name))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        year: bibl
+            .find_all("date")
+            .into_iter()
+            .find_map(|d| d.attr("when"))
+            .map(|w| w.chars().take(4).collect()),
+        raw: bibl
+            .children_named("note")
+            .find(|n| n.attr("type") == Some("raw_reference"))
+            .map(Element::text)
+            .unwrap_or_default(),
+        ..Reference::default()
+    };
+    for (kind, value) in identifiers(bibl) {
+        match kind {
+            | Id::Doi => out.doi = Some(value),
+            | Id::Pmid => out.pmid = Some(value),
+            | Id::Arxiv => out.arxiv = Some(value),
+        }
+    }
+    if out.raw.is_empty() {
+        let parts = [
+            Some(out.authors.join(", ")).filter(|a| !a.is_empty()),
+            out.title.clone(),
+            out.source.clone(),
+            out.year.clone(),
+        ];
+        out.raw = parts.into_iter().flatten().collect::<Vec<_>>().join(". ");
+    }
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Id {
+    Doi,
+    Pmid,
+    Arxiv,
+}
+
+/// `<idno>`s anywhere in a `<biblStruct>` (GROBID puts them in `<analytic>` or `<monogr>`, or
+/// directly in the `<biblStruct>` after consolidation).
+fn identifiers(bibl: &Element) -> Vec<(Id, String)> {
+    bibl.find_all("idno")
+        .into_iter()
+        .filter_map(|idno| {
+            let kind = match idno.attr("type")?.to_ascii_lowercase().as_str() {
+                | "doi" => Id::Doi,
+                | "pmid" => Id::Pmid,
+                | "arxiv" => Id::Arxiv,
+                | _ => return None,
+            };
+            let value = idno.text();
+            let value = match kind {
+                | Id::Arxiv => value.trim_start_matches("arXiv:").trim().to_string(),
+                | Id::Doi | Id::Pmid => value,
+            };
+            Some((kind, value)).filter(|(_, v)| !v.is_empty())
+        })
+        .collect()
+}
+
+fn person_name(name: &Element) -> Option<String> {
+    let given: Vec<String> = name.children_named("forename").map(Element::text).collect();
+    let surname = name.find_text("surname");
+    let given = Some(given.join(" ")).filter(|g| !g.is_empty());
+    match (given, surname) {
+        | (Some(g), Some(s)) => Some(format!("{g} {s}")),
+        | (None, Some(s)) => Some(s),
+        | (Some(g), None) => Some(g),
+        | (None, None) => None,
+    }
+}
+
+/// TEI `when` dates: `2020`, `2020-05` or `2020-05-17`.
+fn parse_when(when: &str) -> Option<chrono::NaiveDate> {
+    let mut parts = when.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next().and_then(|m| m.parse().ok()).unwrap_or(1);
+    let day = parts.next().and_then(|d| d.parse().ok()).unwrap_or(1);
+    chrono::NaiveDate::from_ymd_opt(year, month, day)
+}
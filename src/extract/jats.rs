@@ -0,0 +1,291 @@
+//! JATS (Journal Article Tag Suite) extraction.
+//!
+//! JATS is the XML format behind PubMed Central and most publisher full-text feeds. Everything we
+//! need is explicitly tagged, so unlike PDF extraction this is a straight mapping of the tree.
+
+use chrono::NaiveDate;
+
+use crate::{
+    paper::{Extractor, Figure, PaperMetadata, PaperStructure, Reference, Section},
+    xml::{self, Element},
+    MabelError, Result,
+};
+
+/// Elements that are extracted separately and should not leak into paragraph text.
+const FLOATING: &[&str] = &["fig", "table-wrap", "disp-formula", "fn-group"];
+
+/// File extensions we treat as JATS when given a local path.
+pub const FILE_EXTENSIONS: &[&str] = &["xml", "nxml", "jats"];
+
+/// Parse a JATS document and return its `<article>` element.
+///
+/// PMC's efetch wraps articles in `<pmc-articleset>`; bare publisher files have `<article>` as
+/// the root.
+pub fn parse_article(doc: &str) -> Result<Element> {
+    let root = xml::parse(doc, "JATS article")?;
+    if root.name == "article" {
+        return Ok(root);
+    }
+    root.find("article").cloned().ok_or_else(|| {
+        MabelError::Extraction {
+            reason: format!("JATS document has no <article> element (root is <{}>)", root.name),
+        }
+    })
+}
+
+/// Whether the article carries a full-text `<body>` (PMC returns front matter only for articles
+/// whose publisher does not permit XML redistribution).
+pub fn has_body(article: &Element) -> bool {
+    article.child("body").is_some_and(|b| b.elements().next().is_some())
+}
+
+/// Map an `<article>` to the internal structure.
+pub fn extract(article: &Element) -> PaperStructure {
+    let meta = article.path(&["front", "article-meta"]);
+
+    let title = meta
+        .and_then(|m| m.path(&["title-group", "article-title"]))
+        .map(Element::text)
+        .filter(|t| !t.is_empty());
+    let abstract_text = meta.and_then(abstract_text);
+
+    let mut sections = Vec::new();
+    if let Some(body) = article.child("body") {
+        collect_sections(body, 1, &mut sections);
+    }
+
+    // Publisher feeds often collect figures in <floats-group> instead of inlining them.
+    let figures = ["body", "floats-group"]
+        .iter()
+        .filter_map(|name| article.child(name))
+        .flat_map(|el| el.find_all("fig"))
+        .map(figure)
+        .collect();
+
+    let references = article
+        .child("back")
+        .map(|b| b.find_all("ref").into_iter().map(reference).collect())
+        .unwrap_or_default();
+
+    PaperStructure {
+        title,
+        abstract_text,
+        sections,
+        figures,
+        references,
+    }
+}
+
+/// Bibliographic metadata from the article's `<front>`.
+pub fn metadata(article: &Element) -> PaperMetadata {
+    let mut md = PaperMetadata::default();
+    let Some(meta) = article.path(&["front", "article-meta"]) else {
+        return md;
+    };
+
+    md.title = meta
+        .path(&["title-group", "article-title"])
+        .map(Element::text)
+        .unwrap_or_default();
+    md.abstract_text = abstract_text(meta);
+    md.journal = article
+        .path(&["front", "journal-meta"])
+        .and_then(|j| j.find_text("journal-title"));
+
+    for contrib in meta.find_all("contrib") {
+        if contrib.attr("contrib-type").is_some_and(|t| t != "author") {
+            continue;
+        }
+        let Some(name) = contrib
+            .child("name")
+            .and_then(person_name)
+            .or_else(|| contrib.find_text("collab"))
+        else {
+            continue;
+        };
+        md.authors.push(name);
+        md.orcids.push(
+            contrib
+                .children_named("contrib-id")
+                .find(|id| id.attr("contrib-id-type") == Some("orcid"))
+                .and_then(|id| mabel_core::id::orcid(&id.text())),
+        );
+    }
+    if md.orcids.iter().all(Option::is_none) {
+        md.orcids.clear();
+    }
+
+    for id in meta.children_named("article-id") {
+        let value = id.text();
+        match id.attr("pub-id-type") {
+            | Some("doi") => md.doi = Some(value),
+            | Some("pmid") => md.pmid = Some(value),
+            | Some("pmc" | "pmcid") => md.pmcid = Some(normalize_pmcid(&value)),
+            | _ => {}
+        }
+    }
+
+    md.published = publication_date(meta);
+    md.keywords = meta.find_all("kwd").into_iter().map(Element::text).collect();
+    md.url = md
+        .pmcid
+        .as_ref()
+        .map(|id| format!("https://pmc.ncbi.nlm.nih.gov/articles/{id}/"))
+        .or_else(|| md.doi.as_ref().map(|doi| format!("https://doi.org/{doi}")));
+    md
+}
+
+/// The text of the main `<abstract>` under `meta`, one paragraph (or labelled section of a
+/// structured abstract) per line pair.
+pub fn abstract_text(meta: &Element) -> Option<String> {
+    // Prefer the main abstract over graphical/teaser variants.
+    let abs = meta
+        .children_named("abstract")
+        .find(|a| a.attr("abstract-type").is_none())
+        .or_else(|| meta.child("abstract"))?;
+
+    let structured: Vec<String> = abs
+        .children_named("sec")
+        .map(|sec| {
+            let body = sec.text_without(&["title"]);
+            match sec.child("title").map(Element::text) {
+                | Some(label) if !label.is_empty() => format!("{label}: {body}"),
+                | _ => body,
+            }
+        })
+        .collect();
+
+    let text = if structured.is_empty() {
+        paragraphs(abs).unwrap_or_else(|| abs.text_without(&["title"]))
+    } else {
+        structured.join("\n\n")
+    };
+    Some(text).filter(|t| !t.is_empty())
+}
+
+fn collect_sections(parent: &Element, level: u8, out: &mut Vec<Section>) {
+    // Paragraphs sitting directly in <body> before the first <sec>.
+    if level == 1 {
+        if let Some(text) = paragraphs(parent) {
+            out.push(Section::new("Main text".to_string(), level, text, Extractor::Jats));
+        }
+    }
+
+    for sec in parent.children_named("sec") {
+        let heading = sec
+            .child("title")
+            .map(Element::text)
+            .filter(|t| !t.is_empty())
+            .or_else(|| sec.attr("sec-type").map(str::to_string))
+            .unwrap_or_else(|| "Untitled section".to_string());
+        out.push(Section::new(
+            heading,
+            level,
+            paragraphs(sec).unwrap_or_default(),
+            Extractor::Jats,
+        ));
+        collect_sections(sec, level.saturating_add(1), out);
+    }
+}
+
+/// Direct-child paragraphs (and lists) of `el`, joined by blank lines.
+fn paragraphs(el: &Element) -> Option<String> {
+    let paras: Vec<String> = el
+        .elements()
+        .filter(|e| matches!(e.name.as_str(), "p" | "list" | "disp-quote"))
+        .map(|p| p.text_without(FLOATING))
+        .filter(|t| !t.is_empty())
+        .collect();
+    if paras.is_empty() {
+        None
+    } else {
+        Some(paras.join("\n\n"))
+    }
+}
+
+fn figure(fig: &Element) -> Figure {
+    Figure {
+        label: fig.child("label").map(Element::text).filter(|t| !t.is_empty()),
+        caption: fig.child("caption").map(Element::text).unwrap_or_default(),
+        graphic: fig.find("graphic").and_then(|g| g.attr("href")).map(str::to_string),
+        region: None,
+    }
+}
+
+fn reference(r: &Element) -> Reference {
+    let citation = r
+        .elements()
+        .find(|e| matches!(e.name.as_str(), "element-citation" | "mixed-citation" | "citation"));
+
+    let mut out = Reference {
+        label: r.child("label").map(Element::text).filter(|t| !t.is_empty()),
+        raw: citation.map_or_else(|| r.text_without(&["label"]), Element::text),
+        ..Reference::default()
+    };
+    let Some(c) = citation else {
+        return out;
+    };
+
+    out.title = c.find_text("article-title").or_else(|| c.find_text("chapter-title"));
+    out.source = c.find_text("source");
+    out.year = c.find_text("year");
+    out.authors = c
+        .find_all("name")
+        .into_iter()
+        .filter_map(person_name)
+        .chain(c.find_all("collab").into_iter().map(Element::text))
+        .collect();
+    for id in c.find_all("pub-id") {
+        match id.attr("pub-id-type") {
+            | Some("doi") => out.doi = Some(id.text()),
+            | Some("pmid") => out.pmid = Some(id.text()),
+            | _ => {}
+        }
+    }
+
+    // element-citation carries no punctuation between its parts, so rebuild a readable line.
+    if c.name == "element-citation" {
+        let parts = [
+            Some(out.authors.join(", ")).filter(|a| !a.is_empty()),
+            out.title.clone(),
+            out.source.clone(),
+            out.year.clone(),
+        ];
+        out.raw = parts.into_iter().flatten().collect::<Vec<_>>().join(". ");
+    }
+    out
+}
+
+fn person_name(name: &Element) -> Option<String> {
+    let surname = name.find_text("surname");
+    let given = name.find_text("given-names");
+    match (given, surname) {
+        | (Some(g), Some(s)) => Some(format!("{g} {s}")),
+        | (None, Some(s)) => Some(s),
+        | (Some(g), None) => Some(g),
+        | (None, None) => None,
+    }
+}
+
+fn publication_date(meta: &Element) -> Option<NaiveDate> {
+    let dates: Vec<&Element> = meta.children_named("pub-date").collect();
+    let preferred = dates
+        .iter()
+        .find(|d| matches!(d.attr("pub-type"), Some("epub")) || matches!(d.attr("date-type"), Some("pub")))
+        .or_else(|| dates.first())?;
+
+    let year: i32 = preferred.find_text("year")?.parse().ok()?;
+    let month: u32 = preferred.find_text("month").and_then(|m| m.parse().ok()).unwrap_or(1);
+    let day: u32 = preferred.find_text("day").and_then(|d| d.parse().ok()).unwrap_or(1);
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// PMC ids appear both as `PMC123` and bare `123`; we always store the prefixed form.
+pub fn normalize_pmcid(id: &str) -> String {
+    let id = id.trim();
+    if id.len() > 3 && id[..3].eq_ignore_ascii_case("pmc") {
+        format!("PMC{}", &id[3..])
+    } else {
+        format!("PMC{id}")
+    }
+}
@@ -0,0 +1,156 @@
+//! Full-text extractors that turn a source document into a [`crate::paper::PaperStructure`].
+//!
+//! PDFs go to GROBID when a server is configured, and otherwise to the built-in extractor
+//! ([`pdftext`]), which also stands in when GROBID fails; see [`read_pdf`]. Both read the PDF from
+//! a file, within the memory ceiling (see [`crate::memory`]).
+
+use std::path::Path;
+
+use crate::{
+    config::Config,
+    limits,
+    paper::{PaperMetadata, PaperStructure},
+    timing::{self, Step},
+    MabelError, Result,
+};
+
+pub mod epub;
+#[cfg(feature = "grobid")]
+pub mod grobid;
+pub mod jats;
+#[cfg(feature = "pdf")]
+pub mod pdftext;
+
+/// Headings papers use without numbering them.
+const PLAIN_HEADINGS: &[&str] = &[
+    "abstract",
+    "introduction",
+    "background",
+    "related work",
+    "method",
+    "methods",
+    "methodology",
+    "materials and methods",
+    "experiments",
+    "results",
+    "discussion",
+    "conclusion",
+    "conclusions",
+    "acknowledgments",
+    "acknowledgements",
+    "references",
+    "bibliography",
+    "appendix",
+];
+
+/// What an extractor made of a PDF.
+#[derive(Clone, Debug, Default)]
+pub struct Extracted {
+    pub structure: PaperStructure,
+    /// Header metadata, as far as the extractor reads it (GROBID's is consolidated when
+    /// `consolidateHeader` is on)
+    pub metadata: PaperMetadata,
+}
+
+/// The full text of a PDF: from GROBID when a server is configured, else from the built-in
+/// extractor. When GROBID fails (the server is down, say), the built-in extractor has a go before
+/// the error is returned.
+pub async fn read_pdf(cfg: &Config, pdf: &Path) -> Result<Extracted> {
+    limits::run(Step::Extract, timing::time(Step::Extract, read(cfg, pdf))).await
+}
+
+async fn read(cfg: &Config, pdf: &Path) -> Result<Extracted> {
+    match grobid(cfg, pdf).await {
+        | Some(Ok(extracted)) => Ok(extracted),
+        | Some(Err(e)) => {
+            match builtin(cfg, pdf).await {
+                | Some(Ok(extracted)) => {
+                    tracing::warn!(error = %e, "GROBID extraction failed; read the PDF with the built-in extractor");
+                    Ok(extracted)
+                }
+                | _ => Err(e),
+            }
+        }
+        | None => {
+            builtin(cfg, pdf).await.unwrap_or_else(|| {
+                Err(MabelError::Config {
+                    msg: "no PDF extractor: set GROBID_URL in a build with `--features grobid`, or turn on \
+                          MABEL_PDF_FALLBACK in a build with `--features pdf`"
+                        .to_string(),
+                })
+            })
+        }
+    }
+}
+
+/// GROBID's reading of the PDF, when a server is configured.
+#[cfg(feature = "grobid")]
+async fn grobid(cfg: &Config, pdf: &Path) -> Option<Result<Extracted>> {
+    let server = cfg.grobid_url.as_ref()?;
+    Some(
+        grobid::process(cfg, server, pdf)
+            .await
+            .inspect(|_| timing::extracted_with(crate::paper::Extractor::Grobid)),
+    )
+}
+
+#[cfg(not(feature = "grobid"))]
+#[allow(clippy::unused_async)]
+async fn grobid(cfg: &Config, _pdf: &Path) -> Option<Result<Extracted>> {
+    if cfg.grobid_url.is_some() {
+        tracing::warn!("GROBID_URL is set, but this build has no GROBID support (`--features grobid`)");
+    }
+    None
+}
+
+/// The built-in extractor's reading of the PDF, unless it is turned off.
+#[cfg(feature = "pdf")]
+async fn builtin(cfg: &Config, pdf: &Path) -> Option<Result<Extracted>> {
+    if !cfg.pdf_fallback {
+        return None;
+    }
+    Some(
+        pdftext::process(pdf, cfg.max_memory)
+            .await
+            .inspect(|_| timing::extracted_with(crate::paper::Extractor::PdfText)),
+    )
+}
+
+#[cfg(not(feature = "pdf"))]
+#[allow(clippy::unused_async)]
+async fn builtin(_cfg: &Config, _pdf: &Path) -> Option<Result<Extracted>> {
+    None
+}
+
+/// A line that reads like a section heading: `3 Results`, `2.1 Training`, `IV. METHODS` or
+/// `Related Work`.
+pub fn is_heading(line: &str) -> bool {
+    let line = line.trim();
+    if line.is_empty() || line.len() > 60 || line.ends_with(['.', ',', ';']) {
+        return false;
+    }
+    let (numbering, rest) = split_numbering(line);
+    if !numbering.is_empty() {
+        // A numbered heading is short and starts with a capital.
+        return rest.split_whitespace().count() <= 8 && rest.starts_with(|c: char| c.is_uppercase());
+    }
+    PLAIN_HEADINGS.contains(&rest.to_lowercase().as_str())
+}
+
+/// A heading's section number (`2.1`, `IV.`), if it has one, and the rest of it.
+pub fn split_numbering(heading: &str) -> (&str, &str) {
+    let heading = heading.trim();
+    let numbering = heading
+        .split_whitespace()
+        .next()
+        .filter(|n| {
+            let n = n.trim_end_matches('.');
+            !n.is_empty()
+                && (n
+                    .split('.')
+                    .all(|p| !p.is_empty() && p.len() <= 2 && p.bytes().all(|b| b.is_ascii_digit()))
+                    || n.bytes().all(|b| b"IVX".contains(&b)))
+        })
+        .map_or(0, str::len);
+    (&heading[..numbering], heading[numbering..].trim())
+}
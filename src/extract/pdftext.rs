@@ -0,0 +1,622 @@
+//! The built-in PDF extractor, for when there is no GROBID server. The text layer is read with
+//! `pdf-extract`, its characters are put back together into lines in reading order (the left
+//! column before the right one on two-column pages), and the title, abstract, sections, figure
+//! captions and references are told apart by font size and wording alone.
+//!
+//! GROBID's trained models do all of this better, and parse references into fields; what this
+//! gives is a paper's text in the right order under the right headings, without a server.
+
+use std::{collections::HashMap, path::Path};
+
+use pdf_extract::{Document, MediaBox, OutputDev, OutputError, Transform};
+
+use super::{is_heading, split_numbering, Extracted};
+use crate::{
+    memory,
+    paper::{Extractor, Figure, PaperMetadata, PaperStructure, Reference, Section},
+    MabelError, Result,
+};
+
+/// A title is set at least this much larger than the body text.
+const TITLE_SCALE: f64 = 1.15;
+
+/// An unnumbered heading is set at least this much larger than the body text.
+const HEADING_SCALE: f64 = 1.1;
+
+/// Lines further apart than this many times their size start a new paragraph.
+const PARAGRAPH_GAP: f64 = 1.6;
+
+/// Text this much smaller than the body is a footnote, a table or a figure's lettering.
+const SMALL_SCALE: f64 = 0.85;
+
+/// The same text at the top or bottom of this many pages is a running head or foot.
+const RUNNING_PAGES: usize = 3;
+
+/// Each column of a two-column page holds at least this share of the page's characters.
+const COLUMN_SHARE: f64 = 0.3;
+
+/// A character where the page shows it; `y` runs down from the top of the page.
+struct Glyph {
+    x: f64,
+    y: f64,
+    end: f64,
+    size: f64,
+    text: String,
+}
+
+#[derive(Default)]
+struct Page {
+    width: f64,
+    height: f64,
+    glyphs: Vec<Glyph>,
+}
+
+/// A line of text on a page.
+#[derive(Clone, Debug)]
+struct Line {
+    page: usize,
+    x0: f64,
+    x1: f64,
+    y: f64,
+    /// The mean size of its characters
+    size: f64,
+    chars: usize,
+    text: String,
+}
+
+/// Consecutive lines that belong together.
+struct Paragraph {
+    text: String,
+    size: f64,
+    lines: usize,
+}
+
+/// Where the paragraphs being read go.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Part {
+    /// Authors, affiliations and the like, before the abstract
+    Front,
+    Abstract,
+    Body,
+    References,
+}
+
+/// Collects the characters `pdf-extract` finds, page by page.
+#[derive(Default)]
+struct Collector {
+    pages: Vec<Page>,
+}
+
+impl OutputDev for Collector {
+    fn begin_page(
+        &mut self,
+        _page_num: u32,
+        media_box: &MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> std::result::Result<(), OutputError> {
+        self.pages.push(Page {
+            width: media_box.urx - media_box.llx,
+            height: media_box.ury - media_box.lly,
+            glyphs: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> std::result::Result<(), OutputError> {
+        let Some(page) = self.pages.last_mut() else {
+            return Ok(());
+        };
+        // Rotated text is a margin stamp (arXiv's) or an axis label, not the paper's prose.
+        if trm.m12.abs() > trm.m11.abs() {
+            return Ok(());
+        }
+        // The side of a square with the area of the transformed em box.
+        let size = (font_size * (trm.m11 + trm.m21) * font_size * (trm.m12 + trm.m22))
+            .abs()
+            .sqrt();
+        page.glyphs.push(Glyph {
+            x: trm.m31,
+            y: page.height - trm.m32,
+            end: trm.m31 + width * size,
+            size,
+            text: unligature(char),
+        });
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// Read the PDF at `path` into a structure. The parser needs all of it in memory, so a PDF too
+/// large for `max_memory` (see [`crate::memory`]) is turned down. Parsing runs on a blocking
+/// thread; a PDF that makes the parser panic fails like any other unreadable one.
+pub async fn process(path: &Path, max_memory: Option<u64>) -> Result<Extracted> {
+    let len = memory::file_len(path).await?;
+    if !memory::extractor_fits(len, max_memory) {
+        return Err(MabelError::Extraction {
+            reason: format!(
+                "the PDF is {} MB, more than the built-in extractor can read within MABEL_MAX_MEMORY",
+                len / (1024 * 1024)
+            ),
+        });
+    }
+    let pdf = tokio::fs::read(path).await.map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    let info = crate::pdf::inspect(&pdf);
+    let pages = tokio::task::spawn_blocking(move || read(&pdf)).await.map_err(|_| {
+        MabelError::Extraction {
+            reason: "the built-in extractor could not parse the PDF".to_string(),
+        }
+    })??;
+    let lines = reading_order(&pages);
+    if lines.is_empty() {
+        return Err(MabelError::Extraction {
+            reason: "the PDF has no text layer; it may be a scan".to_string(),
+        });
+    }
+    let (title, structure) = structure(&lines);
+    tracing::debug!(
+        lines = lines.len(),
+        sections = structure.sections.len(),
+        references = structure.references.len(),
+        "read the PDF with the built-in extractor"
+    );
+    Ok(Extracted {
+        structure,
+        metadata: PaperMetadata {
+            title: title.unwrap_or_default(),
+            pdf: info,
+            ..PaperMetadata::default()
+        },
+    })
+}
+
+fn read(pdf: &[u8]) -> Result<Vec<Page>> {
+    let failed = |e: &dyn std::fmt::Display| {
+        MabelError::Extraction {
+            reason: format!("the built-in extractor could not read the PDF: {e}"),
+        }
+    };
+    let mut doc = Document::load_mem(pdf).map_err(|e| failed(&e))?;
+    let mut collector = Collector::default();
+    // Many PDFs are encrypted only against editing, with an empty password.
+    if doc.is_encrypted() {
+        pdf_extract::output_doc_encrypted(&mut doc, &mut collector, "")
+    } else {
+        pdf_extract::output_doc(&doc, &mut collector)
+    }
+    .map_err(|e| failed(&e))?;
+    Ok(collector.pages)
+}
+
+/// Every page's lines in reading order, without running heads, feet and page numbers.
+fn reading_order(pages: &[Page]) -> Vec<Line> {
+    let mut lines: Vec<Vec<Line>> = pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| {
+            let mut lines = page_lines(page, i + 1);
+            order(&mut lines, page.width);
+            lines
+        })
+        .collect();
+    drop_running(&mut lines);
+    lines.into_iter().flatten().collect()
+}
+
+/// The lines of a page, in the order their text comes in. A glyph on the line before, and not
+/// far to the right of it, continues it; anything else starts a new line, so the two columns'
+/// lines stay apart even where the content stream runs across them.
+fn page_lines(page: &Page, number: usize) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    for glyph in &page.glyphs {
+        if glyph.text.trim().is_empty() {
+            if let Some(line) = lines.last_mut() {
+                if !line.text.ends_with(' ') {
+                    line.text.push(' ');
+                }
+            }
+            continue;
+        }
+        let continues = lines.last().is_some_and(|l| {
+            let size = l.size.max(glyph.size);
+            (glyph.y - l.y).abs() < size * 0.5 && glyph.x > l.x1 - size && glyph.x < l.x1 + size * 2.0
+        });
+        match lines.last_mut() {
+            | Some(line) if continues => {
+                if glyph.x > line.x1 + line.size * 0.15 && !line.text.ends_with(' ') {
+                    line.text.push(' ');
+                }
+                line.text.push_str(&glyph.text);
+                line.x1 = line.x1.max(glyph.end);
+                #[allow(clippy::cast_precision_loss)]
+                let n = line.chars as f64;
+                line.size = (line.size * n + glyph.size) / (n + 1.0);
+                line.chars += 1;
+            }
+            | _ => {
+                lines.push(Line {
+                    page: number,
+                    x0: glyph.x,
+                    x1: glyph.end,
+                    y: glyph.y,
+                    size: glyph.size,
+                    chars: 1,
+                    text: glyph.text.clone(),
+                });
+            }
+        }
+    }
+    lines.retain_mut(|l| {
+        l.text = l.text.trim().to_string();
+        !l.text.is_empty()
+    });
+    lines
+}
+
+/// Put a page's lines in reading order: top to bottom, and on a two-column page the left column
+/// before the right one, between the lines that span both (a title block, a wide figure or
+/// table), which keep their place.
+fn order(lines: &mut Vec<Line>, width: f64) {
+    #[derive(PartialEq)]
+    enum Side {
+        Left,
+        Right,
+        Wide,
+    }
+    let (mid, slack) = (width / 2.0, width * 0.02);
+    let side = |l: &Line| {
+        if l.x1 <= mid + slack {
+            Side::Left
+        } else if l.x0 >= mid - slack {
+            Side::Right
+        } else {
+            Side::Wide
+        }
+    };
+    lines.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x0.total_cmp(&b.x0)));
+
+    let chars = |s: Side| lines.iter().filter(|l| side(l) == s).map(|l| l.chars).sum::<usize>();
+    let total = lines.iter().map(|l| l.chars).sum::<usize>().max(1);
+    #[allow(clippy::cast_precision_loss)]
+    let share = |n: usize| n as f64 / total as f64;
+    if share(chars(Side::Left)) < COLUMN_SHARE || share(chars(Side::Right)) < COLUMN_SHARE {
+        return;
+    }
+    let mut ordered = Vec::with_capacity(lines.len());
+    let mut band: Vec<Line> = Vec::new();
+    for line in lines.drain(..) {
+        if side(&line) == Side::Wide {
+            // Stable: each column keeps its top-to-bottom order.
+            band.sort_by_key(|l| side(l) == Side::Right);
+            ordered.append(&mut band);
+            ordered.push(line);
+        } else {
+            band.push(line);
+        }
+    }
+    band.sort_by_key(|l| side(l) == Side::Right);
+    ordered.append(&mut band);
+    *lines = ordered;
+}
+
+/// Drop page numbers and running heads and feet: the top and bottom line of a page when it is
+/// only a number, or when the same text, numbers aside, is there on several pages.
+fn drop_running(pages: &mut [Vec<Line>]) {
+    let key = |l: &Line| {
+        l.text
+            .chars()
+            .filter(|c| !c.is_ascii_digit())
+            .collect::<String>()
+            .trim()
+            .to_lowercase()
+    };
+    let edges = |lines: &[Line]| -> Vec<usize> {
+        let top = (0..lines.len()).min_by(|&a, &b| lines[a].y.total_cmp(&lines[b].y));
+        let bottom = (0..lines.len()).max_by(|&a, &b| lines[a].y.total_cmp(&lines[b].y));
+        let mut edges: Vec<usize> = top.into_iter().chain(bottom).collect();
+        edges.dedup();
+        edges
+    };
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for lines in pages.iter() {
+        for i in edges(lines) {
+            *counts.entry(key(&lines[i])).or_default() += 1;
+        }
+    }
+    let recurring = RUNNING_PAGES.min(pages.len()).max(2);
+    for lines in pages.iter_mut() {
+        let mut running = edges(lines);
+        running.retain(|&i| {
+            let key = key(&lines[i]);
+            key.is_empty() || counts.get(&key).is_some_and(|&n| n >= recurring)
+        });
+        let mut i = 0;
+        lines.retain(|_| {
+            i += 1;
+            !running.contains(&(i - 1))
+        });
+    }
+}
+
+/// The title and the structure of the paper whose lines these are.
+fn structure(lines: &[Line]) -> (Option<String>, PaperStructure) {
+    let body = body_size(lines);
+    let (title, skip) = title(lines, body);
+    let paragraphs = paragraphs(&lines[skip..]);
+
+    let mut part = Part::Front;
+    let mut front = Vec::new();
+    let mut abstract_paras = Vec::new();
+    let mut sections: Vec<(String, u8, Vec<String>)> = Vec::new();
+    let mut figures = Vec::new();
+    let mut references = Vec::new();
+    for p in paragraphs {
+        if p.lines == 1 && (is_heading(&p.text) || is_unnumbered_heading(&p, body)) {
+            let (numbering, name) = split_numbering(&p.text);
+            match name.to_lowercase().as_str() {
+                | "abstract" => part = Part::Abstract,
+                | "references" | "bibliography" => part = Part::References,
+                | _ => {
+                    part = Part::Body;
+                    let level = if numbering.is_empty() {
+                        1
+                    } else {
+                        numbering.trim_end_matches('.').matches('.').count() + 1
+                    };
+                    sections.push((name.to_string(), u8::try_from(level).unwrap_or(u8::MAX), Vec::new()));
+                }
+            }
+            continue;
+        }
+        if let Some(rest) = after_word(&p.text, "abstract") {
+            part = Part::Abstract;
+            abstract_paras.push(rest.to_string());
+            continue;
+        }
+        if let Some((label, caption)) = caption(&p.text) {
+            if label.starts_with("Fig") {
+                figures.push(Figure {
+                    label: Some(label),
+                    caption,
+                    graphic: None,
+                    region: None,
+                });
+            }
+            continue;
+        }
+        match part {
+            | Part::Front => front.push(p.text),
+            | Part::Abstract => abstract_paras.push(p.text),
+            | Part::Body if p.size < body * SMALL_SCALE => {}
+            | Part::Body => {
+                match sections.last_mut() {
+                    | Some((_, _, paras)) => paras.push(p.text),
+                    | None => sections.push(("Untitled section".to_string(), 1, vec![p.text])),
+                }
+            }
+            | Part::References => references.push(reference(p.text)),
+        }
+    }
+    // A paper without headings is all front matter by now.
+    if sections.is_empty() && !front.is_empty() {
+        sections.push(("Untitled section".to_string(), 1, front));
+    }
+
+    let structure = PaperStructure {
+        title: title.clone(),
+        abstract_text: Some(abstract_paras.join("\n\n")).filter(|a| !a.trim().is_empty()),
+        sections: sections
+            .into_iter()
+            .map(|(heading, level, paras)| Section::new(heading, level, paras.join("\n\n"), Extractor::PdfText))
+            .collect(),
+        figures,
+        references,
+    };
+    (title, structure)
+}
+
+/// The size most of the text is set at.
+fn body_size(lines: &[Line]) -> f64 {
+    let mut chars: HashMap<i64, usize> = HashMap::new();
+    for line in lines {
+        #[allow(clippy::cast_possible_truncation)]
+        let half_points = (line.size * 2.0).round() as i64;
+        *chars.entry(half_points).or_default() += line.chars;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    chars
+        .into_iter()
+        .max_by_key(|&(size, n)| (n, size))
+        .map_or(10.0, |(size, _)| size as f64 / 2.0)
+}
+
+/// The title: the largest text in the top half of the first page, when it is set larger than the
+/// body. Returns it and how many lines it takes up.
+fn title(lines: &[Line], body: f64) -> (Option<String>, usize) {
+    let candidates = lines
+        .iter()
+        .take_while(|l| l.page == 1)
+        .filter(|l| l.size >= body * TITLE_SCALE && l.text.chars().any(char::is_alphabetic));
+    let Some(largest) = candidates.map(|l| l.size).max_by(f64::total_cmp) else {
+        return (None, 0);
+    };
+    let Some(start) = lines.iter().position(|l| (l.size - largest).abs() < 0.5) else {
+        return (None, 0);
+    };
+    let end = lines[start..]
+        .iter()
+        .position(|l| (l.size - largest).abs() >= 0.5)
+        .map_or(lines.len(), |n| start + n);
+    let title = lines[start..end]
+        .iter()
+        .map(|l| l.text.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    // Lines above the title (a venue banner, an arXiv notice) go with it.
+    (Some(title), end)
+}
+
+/// Group lines into paragraphs. A paragraph ends at a gap, a change of size, an indented line
+/// after a sentence's end, a heading or a caption; a reference list entry (`[12]`, `12.`) starts
+/// a new one.
+fn paragraphs(lines: &[Line]) -> Vec<Paragraph> {
+    let mut out: Vec<Paragraph> = Vec::new();
+    let mut prev: Option<&Line> = None;
+    let mut left = f64::MAX;
+    for line in lines {
+        let starts = prev.is_none_or(|p| {
+            let gap = line.y - p.y;
+            let same_flow = line.page == p.page && gap > 0.0;
+            (same_flow && gap > p.size * PARAGRAPH_GAP)
+                || (line.size - p.size).abs() > p.size * 0.2
+                || (p.text.ends_with(['.', ':', '?', '!']) && line.x0 > left + line.size * 0.8)
+                || is_heading(&p.text)
+                || is_heading(&line.text)
+                || caption(&line.text).is_some()
+                || reference_label(&line.text).is_some()
+        });
+        match out.last_mut() {
+            | Some(paragraph) if !starts => {
+                join_line(&mut paragraph.text, &line.text);
+                paragraph.lines += 1;
+                left = left.min(line.x0);
+            }
+            | _ => {
+                out.push(Paragraph {
+                    text: line.text.clone(),
+                    size: line.size,
+                    lines: 1,
+                });
+                left = line.x0;
+            }
+        }
+        prev = Some(line);
+    }
+    out
+}
+
+/// Append a line to a paragraph, joining a word hyphenated across the break.
+fn join_line(text: &mut String, line: &str) {
+    let hyphenated = text.ends_with('-')
+        && text[..text.len() - 1].ends_with(char::is_alphabetic)
+        && line.starts_with(char::is_lowercase);
+    if hyphenated {
+        text.pop();
+    } else {
+        text.push(' ');
+    }
+    text.push_str(line);
+}
+
+/// A short line set larger than the body, not ending like a sentence: a heading without a
+/// number.
+fn is_unnumbered_heading(p: &Paragraph, body: f64) -> bool {
+    p.size >= body * HEADING_SCALE
+        && p.text.split_whitespace().count() <= 10
+        && !p.text.ends_with(['.', ',', ';'])
+        && p.text.starts_with(char::is_uppercase)
+}
+
+/// The text after `word` (any case) at the start of `text`, followed by a space or punctuation:
+/// `Abstract—We propose …`.
+fn after_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let head = text.get(..word.len())?;
+    if !head.eq_ignore_ascii_case(word) {
+        return None;
+    }
+    let rest = &text[word.len()..];
+    if rest.starts_with(char::is_alphanumeric) {
+        return None;
+    }
+    let rest = rest.trim_start_matches(|c: char| !c.is_alphanumeric());
+    (!rest.is_empty()).then_some(rest)
+}
+
+/// A caption's label and text: `Figure 3: …`, `Fig. 3. …`, `Table 2 …`.
+fn caption(text: &str) -> Option<(String, String)> {
+    let kind = ["Figure", "FIGURE", "Fig.", "Table", "TABLE"]
+        .into_iter()
+        .find(|k| text.starts_with(k))?;
+    let rest = text[kind.len()..].trim_start();
+    let number_len = rest.find(|c: char| !(c.is_ascii_alphanumeric())).unwrap_or(rest.len());
+    let number = &rest[..number_len];
+    if !number.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let after = rest[number_len..].trim_start();
+    // "Figure 3 shows …" is prose that mentions a figure.
+    if !after.starts_with([':', '.', '|']) && !after.is_empty() {
+        return None;
+    }
+    let label = format!("{} {number}", if kind.starts_with('T') { "Table" } else { "Figure" });
+    Some((label, after.trim_start_matches([':', '.', '|']).trim().to_string()))
+}
+
+/// The label of a reference list entry: `[12]` or `12.`.
+fn reference_label(text: &str) -> Option<&str> {
+    let label = text.split_whitespace().next()?;
+    let number = label
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .or_else(|| label.strip_suffix('.'))?;
+    (!number.is_empty() && number.len() <= 3 && number.bytes().all(|b| b.is_ascii_digit())).then_some(label)
+}
+
+fn reference(raw: String) -> Reference {
+    let label = reference_label(&raw).map(|l| l.trim_matches(['[', ']', '.']).to_string());
+    let raw = match &label {
+        | Some(_) => {
+            raw.split_once(' ')
+                .map_or(raw.as_str(), |(_, rest)| rest.trim())
+                .to_string()
+        }
+        | None => raw,
+    };
+    Reference {
+        label,
+        raw,
+        ..Reference::default()
+    }
+}
+
+/// Ligature glyphs as the letters they stand for, so that words match.
+fn unligature(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            match c {
+                | 'ﬀ' => "ff".to_string(),
+                | 'ﬁ' => "fi".to_string(),
+                | 'ﬂ' => "fl".to_string(),
+                | 'ﬃ' => "ffi".to_string(),
+                | 'ﬄ' => "ffl".to_string(),
+                | c => c.to_string(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,441 @@
+//! Figures embedded in paper notes, each with alt text so that a note exported to HTML or PDF reads
+//! well with a screen reader.
+//!
+//! Figures whose image has a URL are linked: those the source links to absolutely, and the
+//! figures of PMC articles, which PMC serves next to the article. With `MABEL_PDF_FIGURES` the
+//! others are cropped out of the PDF and saved as PNGs in the vault's attachments folder, in a
+//! folder named after the note: from the box GROBID gives each figure, or, with the built-in
+//! extractor, as the largest image on the page with the figure's caption. The alt text is the
+//! first sentence of the caption; with `MABEL_FIGURE_ALT=vision` the model describes the image
+//! instead, and the caption stands in wherever it cannot.
+
+use std::path::{Path, PathBuf};
+
+use reqwest::Client;
+use url::Url;
+
+use crate::{
+    config::{Config, FigureAlt},
+    http,
+    llm::{self, Llm, Usage},
+    paper::{Figure, FigureRegion, PaperMetadata},
+    prompt, skim,
+    source::{self, ResolvedPaper},
+    summarize::FigureImage,
+    xml, MabelError, Result,
+};
+
+/// Where PMC serves an article's figures: `{PMC_ARTICLES}{pmcid}/bin/{graphic}.jpg`.
+const PMC_ARTICLES: &str = "https://www.ncbi.nlm.nih.gov/pmc/articles/";
+
+/// Extensions JATS `graphic` references may carry; PMC's copies are JPEG whatever the original.
+const IMAGE_EXTENSIONS: &[&str] = &[".tif", ".tiff", ".jpg", ".jpeg", ".png", ".gif", ".eps"];
+
+/// Longest alt text; screen readers read alt text in one go, without a way to skip through it.
+const MAX_ALT_CHARS: usize = 150;
+
+/// Largest image shown to the model.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Resolution figures are cropped from the PDF at: sharp on a laptop screen, a few hundred KB each.
+const CROP_DPI: f32 = 150.0;
+
+/// Points of page kept around a figure's box, which GROBID draws tight.
+const CROP_MARGIN: f32 = 4.0;
+
+/// Most figures saved from one PDF.
+const MAX_PDF_FIGURES: usize = 12;
+
+/// Smallest image, in pixels on either side, taken for a figure; smaller ones are logos and icons.
+const MIN_IMAGE_SIDE: u32 = 150;
+
+/// The figures of `paper` that can be embedded in its note at `note`, with alt text. A figure the
+/// model cannot describe keeps the alt text from its caption; after the model fails once, the rest
+/// do too. Those cropped from the PDF are saved on the way.
+pub async fn describe(
+    cfg: &Config,
+    http: &Client,
+    llm: &Llm,
+    paper: &ResolvedPaper,
+    note: &Path,
+) -> (Vec<FigureImage>, Usage) {
+    let mut usage = Usage::default();
+    let Some(structure) = paper.structure.as_ref().filter(|_| cfg.figure_alt != FigureAlt::Off) else {
+        return (Vec::new(), usage);
+    };
+    let mut vision = cfg.figure_alt == FigureAlt::Vision;
+    if vision && cfg.metered && !structure.figures.is_empty() {
+        tracing::info!("metered connection: figure alt text is from the captions");
+        vision = false;
+    }
+    let mut from_pdf = FromPdf::new(cfg, note);
+    let mut figures = Vec::new();
+    for figure in &structure.figures {
+        let (image, png) = match image_url(figure, &paper.metadata) {
+            // Parentheses would end the Markdown link early.
+            | Some(url) => (url.as_str().replace('(', "%28").replace(')', "%29"), None),
+            | None => {
+                match from_pdf.save(cfg, http, paper, figure).await {
+                    | Some((path, png)) => (link(cfg, &path), Some(png)),
+                    | None => continue,
+                }
+            }
+        };
+        let caption = caption(figure);
+        let mut alt = caption_alt(figure, &caption);
+        let shown = match png {
+            | _ if !vision => None,
+            | Some(png) => Some(png),
+            | None => fetch_linked(cfg, http, &image).await,
+        };
+        if let Some(shown) = shown {
+            match vision_alt(llm, &paper.metadata, figure, shown).await {
+                | Ok(Some((described, described_usage))) => {
+                    usage += described_usage;
+                    alt = described;
+                }
+                | Ok(None) => {}
+                | Err(e) => {
+                    tracing::warn!(error = %e, "the model could not describe a figure; alt text is from the captions");
+                    vision = false;
+                }
+            }
+        }
+        figures.push(FigureImage {
+            label: figure.label.clone(),
+            caption,
+            image,
+            alt,
+        });
+    }
+    from_pdf.clean_up().await;
+    if !figures.is_empty() {
+        tracing::debug!(count = figures.len(), "embedding figures");
+    }
+    (figures, usage)
+}
+
+/// A saved figure's link target: its path in the vault, with the characters that would break a
+/// Markdown link escaped.
+fn link(cfg: &Config, path: &Path) -> String {
+    let relative = path.strip_prefix(&cfg.vault_path).unwrap_or(path);
+    relative
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+/// Figures cropped out of the paper's PDF. The PDF and its text layer are only fetched for the
+/// first figure that needs them; once cropping fails for want of the PDF or of poppler, no other
+/// figure is tried.
+struct FromPdf {
+    /// The note's folder in the attachments folder
+    dir: PathBuf,
+    /// Where `pdfimages` writes, removed when done
+    scratch: PathBuf,
+    pdf: Option<PathBuf>,
+    /// The text layer's pages, for the built-in extractor's figures, which have no box
+    pages: Option<Vec<String>>,
+    saved: usize,
+    given_up: bool,
+}
+
+impl FromPdf {
+    fn new(cfg: &Config, note: &Path) -> Self {
+        let stem = note
+            .file_stem()
+            .map_or_else(|| "figures".into(), |s| s.to_string_lossy());
+        Self {
+            dir: cfg.attachments_dir.join(stem.as_ref()),
+            scratch: std::env::temp_dir().join(format!("mabel-figures-{}", std::process::id())),
+            pdf: None,
+            pages: None,
+            saved: 0,
+            given_up: !cfg.pdf_figures,
+        }
+    }
+
+    /// Crop `figure` out of the PDF and save it, returning where and the PNG; `None` when it
+    /// cannot be found or saved.
+    async fn save(
+        &mut self,
+        cfg: &Config,
+        http: &Client,
+        paper: &ResolvedPaper,
+        figure: &Figure,
+    ) -> Option<(PathBuf, Vec<u8>)> {
+        if self.given_up || self.saved >= MAX_PDF_FIGURES {
+            return None;
+        }
+        if self.pdf.is_none() {
+            match source::pdf_file(cfg, http, paper).await {
+                | Ok(Some(pdf)) => self.pdf = Some(pdf),
+                | Ok(None) => {
+                    tracing::debug!("no PDF to crop figures from");
+                    self.given_up = true;
+                    return None;
+                }
+                | Err(e) => {
+                    tracing::warn!(error = %e, "could not fetch the PDF; its figures are left out");
+                    self.given_up = true;
+                    return None;
+                }
+            }
+        }
+        let png = match self.crop(figure).await {
+            | Ok(png) => png?,
+            | Err(e) => {
+                tracing::warn!(error = %e, "could not crop figures from the PDF; they are left out");
+                self.given_up = true;
+                return None;
+            }
+        };
+        self.saved += 1;
+        let path = self.dir.join(format!("figure-{}.png", self.saved));
+        let written = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(&path, &png).await
+        };
+        match written.await {
+            | Ok(()) => Some((path, png)),
+            | Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "could not save a figure");
+                self.given_up = true;
+                None
+            }
+        }
+    }
+
+    /// The figure's image: its box rendered when the extractor gave one, otherwise the largest image
+    /// on the page its caption is on. `None` when neither is found.
+    async fn crop(&mut self, figure: &Figure) -> Result<Option<Vec<u8>>> {
+        let Some(pdf) = self.pdf.clone() else {
+            return Ok(None);
+        };
+        if let Some(region) = &figure.region {
+            return render(&pdf, region).await.map(Some);
+        }
+        if self.pages.is_none() {
+            let text = skim::text_layer(&pdf).await?;
+            // pdftotext ends every page with a form feed.
+            self.pages = Some(
+                text.trim_end_matches('\x0c')
+                    .split('\x0c')
+                    .map(str::to_string)
+                    .collect(),
+            );
+        }
+        let page = figure
+            .label
+            .as_deref()
+            .and_then(|label| caption_page(self.pages.as_deref().unwrap_or_default(), label));
+        match page {
+            | Some(page) => largest_image(&pdf, page, &self.scratch).await,
+            | None => Ok(None),
+        }
+    }
+
+    async fn clean_up(&self) {
+        if self.pages.is_some() {
+            let _ = tokio::fs::remove_dir_all(&self.scratch).await;
+        }
+    }
+}
+
+/// `region` of `pdf` rendered as a PNG.
+async fn render(pdf: &Path, region: &FigureRegion) -> Result<Vec<u8>> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let pixels = |points: f32| ((points * CROP_DPI / 72.0).round().max(0.0) as u32).to_string();
+    let page = region.page.to_string();
+    let (x, y) = (pixels(region.x - CROP_MARGIN), pixels(region.y - CROP_MARGIN));
+    let (width, height) = (
+        pixels(region.width + 2.0 * CROP_MARGIN),
+        pixels(region.height + 2.0 * CROP_MARGIN),
+    );
+    let dpi = CROP_DPI.to_string();
+    let args = [
+        "-png".as_ref(),
+        "-r".as_ref(),
+        dpi.as_ref(),
+        "-f".as_ref(),
+        page.as_ref(),
+        "-l".as_ref(),
+        page.as_ref(),
+        "-x".as_ref(),
+        x.as_ref(),
+        "-y".as_ref(),
+        y.as_ref(),
+        "-W".as_ref(),
+        width.as_ref(),
+        "-H".as_ref(),
+        height.as_ref(),
+        "-singlefile".as_ref(),
+        pdf.as_os_str(),
+    ];
+    skim::run("pdftoppm", &args).await
+}
+
+/// The 1-based page with a line starting with `label` ("Figure 2", not "Figure 21"), taken as
+/// the caption.
+fn caption_page(pages: &[String], label: &str) -> Option<usize> {
+    let label = label.trim().trim_end_matches([':', '.']);
+    let pos = pages.iter().position(|page| {
+        page.lines().any(|line| {
+            line.trim_start()
+                .strip_prefix(label)
+                .is_some_and(|rest| !rest.starts_with(|c: char| c.is_ascii_alphanumeric()))
+        })
+    })?;
+    Some(pos + 1)
+}
+
+/// The largest image embedded in `page` of `pdf`, as a PNG, if one is big enough to be a figure.
+async fn largest_image(pdf: &Path, page: usize, scratch: &Path) -> Result<Option<Vec<u8>>> {
+    let io = |source| {
+        MabelError::Io {
+            path: scratch.to_path_buf(),
+            source,
+        }
+    };
+    tokio::fs::create_dir_all(scratch).await.map_err(io)?;
+    let (number, root) = (page.to_string(), scratch.join(format!("page-{page}")));
+    let args = [
+        "-png".as_ref(),
+        "-f".as_ref(),
+        number.as_ref(),
+        "-l".as_ref(),
+        number.as_ref(),
+        pdf.as_os_str(),
+        root.as_os_str(),
+    ];
+    skim::run("pdfimages", &args).await?;
+    let prefix = format!("page-{page}-");
+    let mut largest: Option<(u64, Vec<u8>)> = None;
+    let mut entries = tokio::fs::read_dir(scratch).await.map_err(io)?;
+    while let Some(entry) = entries.next_entry().await.map_err(io)? {
+        if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+        let png = tokio::fs::read(entry.path()).await.map_err(io)?;
+        let Some((width, height)) = png_size(&png) else {
+            continue;
+        };
+        let area = u64::from(width) * u64::from(height);
+        if width.min(height) >= MIN_IMAGE_SIDE && largest.as_ref().is_none_or(|(a, _)| area > *a) {
+            largest = Some((area, png));
+        }
+    }
+    Ok(largest.map(|(_, png)| png))
+}
+
+/// Width and height from a PNG's header.
+fn png_size(png: &[u8]) -> Option<(u32, u32)> {
+    if !png.starts_with(b"\x89PNG\r\n\x1a\n") || png.get(12..16) != Some(b"IHDR") {
+        return None;
+    }
+    let be = |at: usize| {
+        png.get(at..at + 4)
+            .and_then(|b| b.try_into().ok())
+            .map(u32::from_be_bytes)
+    };
+    Some((be(16)?, be(20)?))
+}
+
+/// Where the figure's image can be fetched: its own absolute URL, or for a PMC article the copy
+/// PMC serves. Local references of files that are not in the vault are no use.
+fn image_url(figure: &Figure, metadata: &PaperMetadata) -> Option<Url> {
+    let graphic = figure.graphic.as_deref()?.trim();
+    if let Ok(url) = Url::parse(graphic) {
+        return matches!(url.scheme(), "http" | "https").then_some(url);
+    }
+    let pmcid = metadata.pmcid.as_deref()?;
+    let lower = graphic.to_ascii_lowercase();
+    // Names such as `pone.0012345.g001` have dots of their own.
+    let name = IMAGE_EXTENSIONS
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map_or(graphic, |ext| &graphic[..graphic.len() - ext.len()]);
+    Url::parse(&format!("{PMC_ARTICLES}{pmcid}/bin/{name}.jpg")).ok()
+}
+
+/// The caption on one line, without the label many captions start with.
+fn caption(figure: &Figure) -> String {
+    let caption = xml::collapse_whitespace(&figure.caption);
+    match figure.label.as_deref().and_then(|label| caption.strip_prefix(label)) {
+        | Some(rest) => rest.trim_start_matches([':', '.', '|', ' ']).to_string(),
+        | None => caption,
+    }
+}
+
+/// The first sentence of the caption; the label for a figure without one.
+fn caption_alt(figure: &Figure, caption: &str) -> String {
+    let sentence = mabel_core::sentence::first(caption, 1);
+    if sentence.is_empty() {
+        return clean(figure.label.as_deref().unwrap_or("Figure"));
+    }
+    clean(sentence)
+}
+
+/// Alt text that fits in `![alt](image)`: one line, no brackets, and no `|`, which Obsidian reads
+/// as a size; cut at a word boundary past [`MAX_ALT_CHARS`].
+fn clean(text: &str) -> String {
+    let text: String = xml::collapse_whitespace(text)
+        .chars()
+        .map(|c| {
+            match c {
+                | '[' => '(',
+                | ']' => ')',
+                | '|' => '/',
+                | c => c,
+            }
+        })
+        .collect();
+    if text.chars().count() <= MAX_ALT_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(MAX_ALT_CHARS - 1).collect();
+    let cut = cut.rfind(' ').map_or(cut.as_str(), |i| &cut[..i]);
+    format!("{}…", cut.trim_end_matches([',', ';', ':', ' ']))
+}
+
+/// The model's alt text for `figure`, shown as `image`; `None` when the reply is empty.
+async fn vision_alt(
+    llm: &Llm,
+    metadata: &PaperMetadata,
+    figure: &Figure,
+    image: Vec<u8>,
+) -> Result<Option<(String, Usage)>> {
+    let completion = llm.complete(&prompt::figure_alt(metadata, figure, image)).await?;
+    let described = clean(completion.text.trim().trim_matches('"'));
+    Ok(Some((described, completion.usage)).filter(|(d, _)| !d.is_empty()))
+}
+
+/// The linked image at `image`, for the model; `None`, with a warning, when it cannot be fetched.
+async fn fetch_linked(cfg: &Config, http: &Client, image: &str) -> Option<Vec<u8>> {
+    let url = Url::parse(image).ok()?;
+    match fetch_image(cfg, http, &url).await {
+        | Ok(image) => Some(image),
+        | Err(e) => {
+            tracing::warn!(image = %url, error = %e, "could not fetch the figure; its alt text is from the caption");
+            None
+        }
+    }
+}
+
+/// The image at `url`, if it is one the model can be shown.
+async fn fetch_image(cfg: &Config, http: &Client, url: &Url) -> Result<Vec<u8>> {
+    let image = http::get_bytes(http, url.clone(), cfg.max_bandwidth, cfg.http_retries).await?;
+    if image.len() > MAX_IMAGE_BYTES {
+        return Err(MabelError::Extraction {
+            reason: format!("the image is larger than {} MB", MAX_IMAGE_BYTES / (1024 * 1024)),
+        });
+    }
+    if llm::image_type(&image).is_none() {
+        return Err(MabelError::Extraction {
+            reason: "not a PNG, JPEG, GIF or WebP image".to_string(),
+        });
+    }
+    Ok(image)
+}
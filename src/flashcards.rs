@@ -0,0 +1,44 @@
+//! Flashcards mode's cards for Anki. With `MABEL_ANKI_DIR` set, each flashcards-mode note also gets
+//! a tab-separated file of its cards there, named like the note, which Anki's File > Import reads
+//! as it is: the header sets the separator, the note type, the deck and the tags column. Anki
+//! matches imported notes on their first field, so importing a paper's file again updates its
+//! cards instead of adding them twice.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{summarize::Summary, MabelError, Result};
+
+/// Deck the cards go to; each paper's cards carry its topic tags.
+const DECK: &str = "Papers";
+
+/// Write the cards of the note at `note` to `dir`, replacing an earlier export of the same note.
+pub async fn export(dir: &Path, note: &Path, summary: &Summary) -> Result<PathBuf> {
+    let stem = note.file_stem().unwrap_or_default().to_string_lossy();
+    let path = dir.join(format!("{stem}.tsv"));
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.clone(),
+            source,
+        }
+    };
+    tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+    tokio::fs::write(&path, tsv(summary)).await.map_err(io_err)?;
+    Ok(path)
+}
+
+fn tsv(summary: &Summary) -> String {
+    let tags = std::iter::once("mabel")
+        .chain(summary.tags.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut out = format!("#separator:tab\n#html:false\n#notetype:Basic\n#deck:{DECK}\n#tags column:3\n");
+    for card in &summary.flashcards {
+        // Sides are one line already; a tab would start a new field.
+        let (question, answer) = (card.question.replace('\t', " "), card.answer.replace('\t', " "));
+        let _ = writeln!(out, "{question}\t{answer}\t{tags}");
+    }
+    out
+}
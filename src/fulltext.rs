@@ -0,0 +1,106 @@
+//! The extracted full text of each paper, kept after its note is written: as Markdown and as the
+//! extracted structure in the cache, so later runs need not send the PDF through GROBID again,
+//! and optionally as a Markdown note in the vault's `Full text` folder, so Obsidian's search
+//! finds what the paper says and not only what its summary does.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    note,
+    paper::{PaperMetadata, PaperStructure},
+    source::{arxiv::ArxivId, ResolvedPaper},
+    MabelError, Result,
+};
+
+/// What is cached per paper: the structure, plus the identifiers extraction found, which fill gaps
+/// in a later run's metadata just as a fresh extraction would.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cached {
+    pub metadata: PaperMetadata,
+    pub structure: PaperStructure,
+}
+
+/// The cache key of a paper: its arXiv id, PMCID or DOI, filename-safe. `None` for papers without
+/// any, whose text is not cached. Like the PDF cache, an arXiv paper keeps the text of the version
+/// first extracted.
+pub fn key(metadata: &PaperMetadata) -> Option<String> {
+    if let Some(arxiv) = metadata.arxiv_id.as_deref().and_then(|a| ArxivId::parse(a).ok()) {
+        return Some(arxiv_key(&arxiv));
+    }
+    let key = match &metadata.pmcid {
+        | Some(pmcid) => format!("pmc-{pmcid}"),
+        | None => format!("doi-{}", metadata.doi.as_deref()?.to_lowercase()),
+    };
+    Some(sanitize_filename::sanitize(key.replace('/', "_")))
+}
+
+/// The cache key of an arXiv paper, which is known before its metadata is.
+pub fn arxiv_key(id: &ArxivId) -> String {
+    format!("arxiv-{}", id.base().replace('/', "_"))
+}
+
+/// The cached extraction of the paper with cache key `key`, if any.
+pub async fn load(cfg: &Config, key: &str) -> Option<Cached> {
+    let text = tokio::fs::read_to_string(cfg.cached_text_path(key).with_extension("json"))
+        .await
+        .ok()?;
+    serde_json::from_str(&text)
+        .inspect_err(|e| tracing::warn!(error = %e, key, "ignoring unreadable cached full text"))
+        .ok()
+}
+
+/// Keep the full text of `paper`, whose note is `note_path`: in the cache, and in the vault when
+/// `--text-sidecar` is on. Papers summarized from their abstract have nothing to keep.
+pub async fn save(cfg: &Config, paper: &ResolvedPaper, note_path: &Path) -> Result<()> {
+    let Some(structure) = paper.structure.as_ref().filter(|s| !s.is_empty()) else {
+        return Ok(());
+    };
+    let text = structure.full_text();
+    if let Some(key) = key(&paper.metadata) {
+        let cached = Cached {
+            metadata: paper.metadata.clone(),
+            structure: structure.clone(),
+        };
+        let path = cfg.cached_text_path(&key);
+        write(&path, &format!("# {}\n\n{text}\n", paper.metadata.title)).await?;
+        write(&path.with_extension("json"), &serde_json::to_string(&cached)?).await?;
+    }
+    if cfg.text_sidecar {
+        let stem = note_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let sidecar = sidecar_path(cfg, &stem);
+        let body = format!(
+            "---\ntype: fulltext\npaper: {}\n---\n\n# {} (full text)\n\n{text}\n",
+            serde_json::Value::from(format!("[[{stem}]]")),
+            paper.metadata.title
+        );
+        write(&sidecar, &body).await?;
+        tracing::info!(path = %sidecar.display(), "full text written");
+    }
+    Ok(())
+}
+
+/// The vault sidecar of the note with file stem `stem`. Named apart from the note so `[[links]]` to
+/// the note stay unambiguous.
+pub fn sidecar_path(cfg: &Config, stem: &str) -> PathBuf {
+    cfg.full_text_dir()
+        .join(format!("{} (full text).md", note::file_stem(stem)))
+}
+
+async fn write(path: &Path, contents: &str) -> Result<()> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+    }
+    tokio::fs::write(path, contents).await.map_err(io_err)
+}
@@ -0,0 +1,434 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+        RETRY_AFTER,
+    },
+    Client, ClientBuilder, RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+use crate::{
+    config::Config,
+    retry,
+    secret::{self, Secret},
+    MabelError, Result,
+};
+
+const USER_AGENT: &str = concat!(
+    "mabel/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/maxwellherron5/mabel)"
+);
+
+/// How long a download under `MABEL_MAX_BANDWIDTH` may take; the client's own timeout would cut
+/// off a large PDF fetched slowly on purpose.
+const THROTTLED_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Longest body excerpt carried in `MabelError::HttpStatus`.
+const BODY_SNIP_LEN: usize = 1024;
+
+/// Query parameters that carry credentials; their values are hidden in errors.
+const SECRET_PARAMS: &[&str] = &["api_key", "key", "token"];
+
+/// Cache validators from a previous response, sent back to make the next request conditional.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_response(resp: &Response) -> Self {
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Extra headers and credentials sent with every request to one service, for self-hosted GROBID,
+/// Ollama or OpenAI-compatible servers behind an authenticating reverse proxy. Read from
+/// `<SERVICE>_HEADERS` (`Name: value` pairs separated by `;` or newlines), `<SERVICE>_BEARER_TOKEN`
+/// and `<SERVICE>_BASIC_AUTH` (`user:password`); each can also come from a `_CMD` secret command.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServiceAuth {
+    pub headers: Vec<(String, Secret)>,
+    pub bearer: Option<Secret>,
+    pub basic: Option<Secret>,
+}
+
+impl ServiceAuth {
+    /// The settings for `service` (`GROBID`, `OLLAMA`, `OPENAI`). Secret commands only run when
+    /// `fetch_secrets` is set.
+    pub fn from_env(service: &str, fetch_secrets: bool) -> Result<Self> {
+        let read = |key: String| {
+            if fetch_secrets {
+                secret::from_env(&key)
+            } else {
+                Ok(std::env::var(&key).ok().map(Secret::new))
+            }
+        };
+        let headers = match read(format!("{service}_HEADERS"))? {
+            | Some(list) => parse_headers(&format!("{service}_HEADERS"), list.expose())?,
+            | None => Vec::new(),
+        };
+        let auth = Self {
+            headers,
+            bearer: read(format!("{service}_BEARER_TOKEN"))?.filter(|s| !s.is_empty()),
+            basic: read(format!("{service}_BASIC_AUTH"))?.filter(|s| !s.is_empty()),
+        };
+        if auth.bearer.is_some() && auth.basic.is_some() {
+            return Err(MabelError::Config {
+                msg: format!("{service}_BEARER_TOKEN and {service}_BASIC_AUTH both set; use one"),
+            });
+        }
+        Ok(auth)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.bearer.is_none() && self.basic.is_none()
+    }
+
+    /// What is configured, without the values, e.g. `bearer, headers: X-Api-Key`.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.bearer.is_some() {
+            parts.push("bearer".to_string());
+        }
+        if self.basic.is_some() {
+            parts.push("basic".to_string());
+        }
+        if !self.headers.is_empty() {
+            let names: Vec<&str> = self.headers.iter().map(|(n, _)| n.as_str()).collect();
+            parts.push(format!("headers: {}", names.join(", ")));
+        }
+        parts.join("; ")
+    }
+
+    /// The headers to send, values marked sensitive so they stay out of debug output.
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let invalid = |name: &str| {
+            MabelError::Config {
+                msg: format!("invalid value for HTTP header {name}"),
+            }
+        };
+        let mut map = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let key = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                MabelError::Config {
+                    msg: format!("invalid HTTP header name {name:?}"),
+                }
+            })?;
+            let mut value = HeaderValue::from_str(value.expose()).map_err(|_| invalid(name))?;
+            value.set_sensitive(true);
+            map.insert(key, value);
+        }
+        let authorization = match (&self.bearer, &self.basic) {
+            | (Some(token), _) => Some(format!("Bearer {}", token.expose())),
+            | (None, Some(credentials)) => Some(format!("Basic {}", BASE64.encode(credentials.expose()))),
+            | (None, None) => None,
+        };
+        if let Some(authorization) = authorization {
+            let mut value = HeaderValue::from_str(&authorization).map_err(|_| invalid("Authorization"))?;
+            value.set_sensitive(true);
+            map.insert(AUTHORIZATION, value);
+        }
+        Ok(map)
+    }
+}
+
+fn parse_headers(key: &str, list: &str) -> Result<Vec<(String, Secret)>> {
+    list.split(['\n', ';'])
+        .map(str::trim)
+        .filter(|h| !h.is_empty())
+        .map(|h| {
+            let (name, value) = h.split_once(':').ok_or_else(|| {
+                MabelError::Config {
+                    msg: format!("{key}: expected `Name: value`, got {:?}", h.split(':').next().unwrap_or(h)),
+                }
+            })?;
+            Ok((name.trim().to_string(), Secret::new(value.trim())))
+        })
+        .collect()
+}
+
+/// Result of a conditional GET.
+#[derive(Debug)]
+pub enum Conditional {
+    NotModified,
+    Modified { body: String, validators: Validators },
+}
+
+/// Build the HTTP client shared by every resolver and backend. Responses are compressed where the
+/// server supports it, and connections to the same host are reused (over HTTP/2 when offered).
+pub fn client(cfg: &Config) -> Result<Client> {
+    build(builder().timeout(cfg.http_timeout))
+}
+
+/// Like [`client`], but sending `auth` with every request.
+pub fn client_for(cfg: &Config, auth: &ServiceAuth) -> Result<Client> {
+    build(builder().timeout(cfg.http_timeout).default_headers(auth.header_map()?))
+}
+
+/// A client for an LLM backend: no overall timeout, since long completions are normal, and
+/// `auth` sent with every request.
+pub fn llm_client(auth: &ServiceAuth) -> Result<Client> {
+    build(builder().default_headers(auth.header_map()?))
+}
+
+fn builder() -> ClientBuilder {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+}
+
+fn build(builder: ClientBuilder) -> Result<Client> {
+    builder.build().map_err(|e| {
+        MabelError::Config {
+            msg: format!("failed to build HTTP client: {e}"),
+        }
+    })
+}
+
+/// Send the request `build` makes to `url`, failing on non-2xx statuses other than the 304 only a
+/// conditional request gets; made again up to `retries` times while it fails in a way that may
+/// pass (see [`retry`]).
+pub async fn send(retries: u32, url: &Url, build: impl Fn() -> RequestBuilder) -> Result<Response> {
+    let build = &build;
+    retry::run(retries, move || {
+        async move {
+            let resp = build().send().await.map_err(|e| request_error(url, e))?;
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                return Ok(resp);
+            }
+            check_status(resp).await
+        }
+    })
+    .await
+}
+
+/// GET `url` and return the body as text, failing on non-2xx statuses.
+pub async fn get_text(client: &Client, url: Url, retries: u32) -> Result<String> {
+    let resp = send(retries, &url, || client.get(url.clone())).await?;
+    resp.text().await.map_err(|e| request_error(&url, e))
+}
+
+/// GET `url` and return the raw body, failing on non-2xx statuses. With `max_bandwidth`, the body
+/// is read at no more than that many bytes per second.
+pub async fn get_bytes(client: &Client, url: Url, max_bandwidth: Option<u64>, retries: u32) -> Result<Vec<u8>> {
+    let resp = send(retries, &url, || throttled(client.get(url.clone()), max_bandwidth)).await?;
+    read_body(resp, &url, max_bandwidth).await
+}
+
+/// Give a download that [`read_body`] reads at `max_bandwidth` the time that takes.
+pub fn throttled(req: RequestBuilder, max_bandwidth: Option<u64>) -> RequestBuilder {
+    match max_bandwidth {
+        | Some(_) => req.timeout(THROTTLED_TIMEOUT),
+        | None => req,
+    }
+}
+
+/// The body of `resp`, read at no more than `max_bandwidth` bytes per second when set. Reading
+/// pauses whenever it gets ahead of the limit; the connection's receive window then throttles the
+/// server too.
+pub async fn read_body(mut resp: Response, url: &Url, max_bandwidth: Option<u64>) -> Result<Vec<u8>> {
+    let Some(limit) = max_bandwidth else {
+        let body = resp.bytes().await.map_err(|e| request_error(url, e))?;
+        return Ok(body.to_vec());
+    };
+    let start = Instant::now();
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await.map_err(|e| request_error(url, e))? {
+        body.extend_from_slice(&chunk);
+        pace(start, u64::try_from(body.len()).unwrap_or(u64::MAX), limit).await;
+    }
+    Ok(body)
+}
+
+/// GET `url` into the file at `path`; see [`write_body`].
+pub async fn download(client: &Client, url: Url, path: &Path, max_bandwidth: Option<u64>, retries: u32) -> Result<u64> {
+    let resp = send(retries, &url, || throttled(client.get(url.clone()), max_bandwidth)).await?;
+    write_body(resp, &url, path, max_bandwidth).await
+}
+
+/// Write the body of `resp` to the file at `path` as it comes in, so a download takes no more
+/// memory than one chunk of it whatever its size; at no more than `max_bandwidth` bytes per second
+/// when set, as [`read_body`] does. Returns the bytes written.
+pub async fn write_body(mut resp: Response, url: &Url, path: &Path, max_bandwidth: Option<u64>) -> Result<u64> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let mut file = tokio::fs::File::create(path).await.map_err(io_err)?;
+    let start = Instant::now();
+    let mut written = 0;
+    while let Some(chunk) = resp.chunk().await.map_err(|e| request_error(url, e))? {
+        file.write_all(&chunk).await.map_err(io_err)?;
+        written += u64::try_from(chunk.len()).unwrap_or(u64::MAX);
+        if let Some(limit) = max_bandwidth {
+            pace(start, written, limit).await;
+        }
+    }
+    file.flush().await.map_err(io_err)?;
+    Ok(written)
+}
+
+/// Pause a download that has `read` bytes after starting at `start` until it is back within
+/// `limit` bytes per second.
+async fn pace(start: Instant, read: u64, limit: u64) {
+    let due = Duration::from_millis(read.saturating_mul(1000) / limit);
+    if let Some(ahead) = due.checked_sub(start.elapsed()) {
+        tokio::time::sleep(ahead).await;
+    }
+}
+
+/// Parse a bandwidth such as `500K`, `2M` or `80000` into bytes per second. `K`, `M` and `G`
+/// are powers of 1024, as for `curl --limit-rate`; a trailing `B` or `/s` is allowed.
+pub fn parse_bandwidth(raw: &str) -> Result<u64> {
+    let invalid = || {
+        MabelError::Config {
+            msg: format!("invalid bandwidth {raw:?}: expected bytes per second, e.g. 500K or 2M"),
+        }
+    };
+    let trimmed = raw.trim();
+    let trimmed = trimmed.strip_suffix("/s").unwrap_or(trimmed);
+    parse_bytes(trimmed).ok_or_else(invalid)
+}
+
+/// A number of bytes such as `512M`, `1.5G` or `80000`, with `K`, `M` and `G` as powers of 1024
+/// and an optional trailing `B`; `None` unless it is at least one byte.
+pub fn parse_bytes(raw: &str) -> Option<u64> {
+    let trimmed = raw.trim();
+    let trimmed = trimmed.strip_suffix(['B', 'b']).unwrap_or(trimmed);
+    let (number, unit) = match trimmed.char_indices().last() {
+        | Some((at, 'k' | 'K')) => (&trimmed[..at], 1 << 10),
+        | Some((at, 'm' | 'M')) => (&trimmed[..at], 1 << 20),
+        | Some((at, 'g' | 'G')) => (&trimmed[..at], 1 << 30),
+        | _ => (trimmed, 1),
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    if !number.is_finite() || number < 1.0 {
+        return None;
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let bytes = (number * f64::from(unit)) as u64;
+    Some(bytes)
+}
+
+/// GET `url` with `If-None-Match`/`If-Modified-Since` from `validators`, so an unchanged resource
+/// costs a bodiless 304 instead of a full download.
+pub async fn get_text_conditional(
+    client: &Client,
+    url: Url,
+    validators: &Validators,
+    retries: u32,
+) -> Result<Conditional> {
+    let resp = send(retries, &url, || {
+        let mut req = client.get(url.clone());
+        if let Some(etag) = &validators.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = &validators.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, date);
+        }
+        req
+    })
+    .await?;
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Conditional::NotModified);
+    }
+    let validators = Validators::from_response(&resp);
+    let body = resp.text().await.map_err(|e| request_error(&url, e))?;
+    Ok(Conditional::Modified { body, validators })
+}
+
+/// Pass through successful responses; turn anything else into `MabelError::HttpStatus`.
+pub async fn check_status(resp: Response) -> Result<Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let url = redact(resp.url());
+    let retry_after = resp.headers().get(RETRY_AFTER).and_then(retry_after);
+    let body = resp.text().await.unwrap_or_default();
+    Err(MabelError::HttpStatus {
+        url,
+        status,
+        body_snip: body_snip(&body),
+        retry_after,
+    })
+}
+
+/// The seconds in a `Retry-After` value: a number of them, or the HTTP date to wait until.
+fn retry_after(value: &HeaderValue) -> Option<u32> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(secs);
+    }
+    let until = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = (until.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds();
+    Some(u32::try_from(secs.max(0)).unwrap_or(u32::MAX))
+}
+
+/// A transport error for `url`, with credentials removed from the URL.
+pub fn request_error(url: &Url, source: reqwest::Error) -> MabelError {
+    MabelError::Http {
+        url: redact(url),
+        source: source.without_url(),
+    }
+}
+
+/// `url` with the values of credential parameters replaced, for error messages and logs.
+pub fn redact(url: &Url) -> Url {
+    if !url.query_pairs().any(|(k, _)| SECRET_PARAMS.contains(&k.as_ref())) {
+        return url.clone();
+    }
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if SECRET_PARAMS.contains(&k.as_ref()) {
+                "redacted".into()
+            } else {
+                v
+            };
+            (k.into_owned(), v.into_owned())
+        })
+        .collect();
+    let mut out = url.clone();
+    out.query_pairs_mut().clear().extend_pairs(pairs);
+    out
+}
+
+fn body_snip(body: &str) -> String {
+    let body = body.trim();
+    if body.is_empty() {
+        return String::new();
+    }
+    let end = body
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&i| i <= BODY_SNIP_LEN)
+        .last()
+        .unwrap_or(0);
+    format!(": {}", &body[..end])
+}
@@ -0,0 +1,176 @@
+//! The paper index: a SQLite database in the cache directory (`~/.mabel/mabel.db`) with a row for
+//! every paper processed on this machine, whichever vault its note went to, and what processing it
+//! took. `mabel list` and `mabel info` read it, to check what is already in before running a paper
+//! again.
+//!
+//! The registry (see [`crate::registry`]) is the vault's own record and what mabel works from; the
+//! index is a log kept beside it, so writing to it never fails a run.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rusqlite::{params, Connection, Row};
+
+use crate::{
+    llm::Usage,
+    source::{arxiv::ArxivId, crossref::Doi, pubmed::PubmedId},
+    MabelError, Result,
+};
+
+/// How long a write waits for another process (a batch run, the server) to finish its own.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS papers (
+    key               TEXT PRIMARY KEY,
+    title             TEXT NOT NULL,
+    arxiv             TEXT,
+    doi               TEXT,
+    note              TEXT NOT NULL,
+    processed         TEXT NOT NULL,
+    model             TEXT NOT NULL,
+    prompt_tokens     INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS papers_doi ON papers (lower(doi));
+";
+
+const COLUMNS: &str = "key, title, arxiv, doi, note, processed, model, prompt_tokens, completion_tokens";
+
+/// A processed paper, as the index has it.
+#[derive(Clone, Debug)]
+pub struct Indexed {
+    /// The registry's key for the paper (see [`crate::registry::Entry::key`])
+    pub key: String,
+    pub title: String,
+    pub arxiv: Option<String>,
+    pub doi: Option<String>,
+    /// The note, as an absolute path
+    pub note: PathBuf,
+    /// When the note was last written, as RFC 3339
+    pub processed: String,
+    pub model: String,
+    /// Tokens used the last time the paper was processed
+    pub usage: Usage,
+}
+
+pub struct Index {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl Index {
+    /// Open the index at `path`, creating it if need be.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|source| {
+                MabelError::Io {
+                    path: dir.to_path_buf(),
+                    source,
+                }
+            })?;
+        }
+        let failed = |source| {
+            MabelError::Index {
+                path: path.to_path_buf(),
+                source,
+            }
+        };
+        let conn = Connection::open(path).map_err(failed)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(failed)?;
+        conn.execute_batch(SCHEMA).map_err(failed)?;
+        Ok(Self {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Add a paper, or replace its row when it was processed before.
+    pub fn record(&self, paper: &Indexed) -> Result<()> {
+        self.conn
+            .execute(
+                &format!("INSERT OR REPLACE INTO papers ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"),
+                params![
+                    paper.key,
+                    paper.title,
+                    paper.arxiv,
+                    paper.doi,
+                    paper.note.to_string_lossy(),
+                    paper.processed,
+                    paper.model,
+                    paper.usage.prompt_tokens,
+                    paper.usage.completion_tokens,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|e| self.failed(e))
+    }
+
+    /// Every paper, most recently processed first.
+    pub fn list(&self) -> Result<Vec<Indexed>> {
+        self.query(&format!("SELECT {COLUMNS} FROM papers ORDER BY processed DESC"), [])
+    }
+
+    /// The papers `id` names: an arXiv id, a DOI, a PubMed id, an index key, or else part of a
+    /// title (any case).
+    pub fn find(&self, id: &str) -> Result<Vec<Indexed>> {
+        let id = id.trim();
+        // A paper with an arXiv or PubMed id is keyed by that, even when it also has a DOI.
+        let (key, doi) = if let Ok(arxiv) = ArxivId::parse(id) {
+            (format!("arxiv:{}", arxiv.base()), None)
+        } else if let Ok(doi) = Doi::parse(id) {
+            let doi = doi.as_str().to_lowercase();
+            (format!("doi:{doi}"), Some(doi))
+        } else if let Ok(pubmed) = PubmedId::parse(id) {
+            match pubmed {
+                | PubmedId::Pmid(pmid) => (format!("pmid:{pmid}"), None),
+                | PubmedId::Pmcid(pmcid) => (format!("pmcid:{pmcid}"), None),
+            }
+        } else {
+            (id.to_string(), None)
+        };
+        let found = self.query(
+            &format!("SELECT {COLUMNS} FROM papers WHERE key = ?1 OR lower(doi) = ?2 ORDER BY processed DESC"),
+            params![key, doi],
+        )?;
+        if !found.is_empty() || key != id {
+            return Ok(found);
+        }
+        let pattern = format!("%{}%", id.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        self.query(
+            &format!("SELECT {COLUMNS} FROM papers WHERE title LIKE ?1 ESCAPE '\\' ORDER BY processed DESC"),
+            [pattern],
+        )
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Indexed>> {
+        let mut statement = self.conn.prepare(sql).map_err(|e| self.failed(e))?;
+        let rows = statement.query_map(params, indexed).map_err(|e| self.failed(e))?;
+        rows.collect::<rusqlite::Result<_>>().map_err(|e| self.failed(e))
+    }
+
+    fn failed(&self, source: rusqlite::Error) -> MabelError {
+        MabelError::Index {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+fn indexed(row: &Row<'_>) -> rusqlite::Result<Indexed> {
+    Ok(Indexed {
+        key: row.get(0)?,
+        title: row.get(1)?,
+        arxiv: row.get(2)?,
+        doi: row.get(3)?,
+        note: PathBuf::from(row.get::<_, String>(4)?),
+        processed: row.get(5)?,
+        model: row.get(6)?,
+        usage: Usage {
+            prompt_tokens: row.get(7)?,
+            completion_tokens: row.get(8)?,
+        },
+    })
+}
@@ -1,8 +1,60 @@
 #![forbid(unsafe_code)]
 #![deny(clippy::all, clippy::pedantic)]
-#![allow(clippy::module_name_repetitions, clippy::missing_errors_doc)]
+#![allow(
+    clippy::module_name_repetitions,
+    clippy::missing_errors_doc,
+    clippy::must_use_candidate
+)]
 
+pub mod bibtex;
+pub mod chunk;
+pub mod ci;
+pub mod claims;
 pub mod cli;
+pub mod clock;
+pub mod commands;
+pub mod concept;
 pub mod config;
+pub mod config_file;
+pub mod cost;
 pub mod error;
+pub mod eval;
+pub mod extract;
+pub mod figures;
+pub mod flashcards;
+pub mod fulltext;
+pub mod http;
+pub mod index;
+pub mod limits;
+pub mod llm;
+pub mod memory;
+pub mod moc;
+pub mod note;
+pub mod output;
+pub mod pdf;
+pub mod pipeline;
+pub mod prompt;
+pub mod queue;
+pub mod recommend;
+pub mod registry;
+pub mod render;
+pub mod reproduction;
+pub mod results;
+pub mod retry;
+pub mod routing;
+pub mod schedule;
+pub mod schema;
+pub mod secret;
+pub mod skim;
+pub mod source;
+pub mod stop_sections;
+pub mod store;
+pub mod summarize;
+pub mod thread;
+pub mod timing;
+pub mod vault;
+pub mod webhook;
+pub mod xml;
+
 pub use error::{MabelError, Result};
+pub use mabel_core::{paper, region};
@@ -0,0 +1,85 @@
+//! How much of each step runs at once across the papers of a run, so `mabel batch` can work on many
+//! papers side by side without flooding any one service: PDF downloads
+//! (`MABEL_MAX_CONCURRENT_DOWNLOADS`), PDF extractions (`MABEL_MAX_CONCURRENT_EXTRACTIONS`) and
+//! model requests (`MABEL_MAX_CONCURRENT_LLM`) each have their own limit, so one paper's download
+//! goes on while another's summary is written. Model requests also keep to `rate_limit_per_min`
+//! (`MABEL_RATE_PER_MIN`) in any minute.
+//!
+//! The limits are the process's, set once from the configuration (see [`init`]); time spent
+//! waiting for a turn is not counted in the run report.
+
+use std::{collections::VecDeque, future::Future, sync::OnceLock, time::Duration};
+
+use tokio::{
+    sync::{Mutex, Semaphore},
+    time::Instant,
+};
+
+use crate::{config::Config, timing::Step};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+struct Limits {
+    downloads: Semaphore,
+    extractions: Semaphore,
+    llm: Semaphore,
+    /// Model requests started at most per minute; 0 for no limit
+    per_min: usize,
+    /// When the model requests of the last minute started
+    started: Mutex<VecDeque<Instant>>,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// Take the limits from `cfg`. Only the first call counts; steps run before it are not limited.
+pub fn init(cfg: &Config) {
+    let permits = |n: u32| Semaphore::new(usize::try_from(n.max(1)).unwrap_or(usize::MAX));
+    let _ = LIMITS.set(Limits {
+        downloads: permits(cfg.max_concurrent_downloads),
+        extractions: permits(cfg.max_concurrent_extractions),
+        llm: permits(cfg.max_concurrent_llm),
+        per_min: usize::try_from(cfg.rate_limit_per_min).unwrap_or(usize::MAX),
+        started: Mutex::new(VecDeque::new()),
+    });
+}
+
+/// Run `work` as `step` once it has a turn. `work` is boxed, like [`crate::timing::time`]'s.
+pub async fn run<T>(step: Step, work: impl Future<Output = T>) -> T {
+    let work = Box::pin(work);
+    let Some(limits) = LIMITS.get() else {
+        return work.await;
+    };
+    // Metadata lookups are small; of the fetches only PDF downloads are run through here.
+    let semaphore = match step {
+        | Step::Fetch => &limits.downloads,
+        | Step::Extract => &limits.extractions,
+        | Step::Llm => &limits.llm,
+    };
+    // The semaphores are never closed.
+    let _permit = semaphore.acquire().await.ok();
+    if matches!(step, Step::Llm) {
+        limits.keep_rate().await;
+    }
+    work.await
+}
+
+impl Limits {
+    /// Wait until a model request may start without going over `per_min` in a minute.
+    async fn keep_rate(&self) {
+        if self.per_min == 0 {
+            return;
+        }
+        let mut started = self.started.lock().await;
+        let now = Instant::now();
+        while started.front().is_some_and(|&t| now.duration_since(t) >= MINUTE) {
+            started.pop_front();
+        }
+        if started.len() >= self.per_min {
+            if let Some(oldest) = started.pop_front() {
+                tracing::info!(per_min = self.per_min, "waiting for the model request rate limit");
+                tokio::time::sleep_until(oldest + MINUTE).await;
+            }
+        }
+        started.push_back(Instant::now());
+    }
+}
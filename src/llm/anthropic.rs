@@ -0,0 +1,186 @@
+//! Claude models through Anthropic's Messages API (`api.anthropic.com/v1/messages`).
+//!
+//! The API has no JSON mode, so a prompt's `json` flag only rests on its instructions; replies
+//! are parsed leniently anyway (see [`crate::summarize`]). Anthropic has no embedding models.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{Completion, Prompt, Usage};
+use crate::{
+    http::{self, ServiceAuth},
+    secret::Secret,
+    MabelError, Result,
+};
+
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+const MODELS_URL: &str = "https://api.anthropic.com/v1/models/";
+const API_VERSION: &str = "2023-06-01";
+
+#[derive(Clone, Debug)]
+pub struct AnthropicClient {
+    http: Client,
+    api_key: Secret,
+    model: String,
+    max_tokens: u32,
+    temperature: f32,
+    retries: u32,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    temperature: f32,
+    system: &'a str,
+    messages: [Message<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'static str,
+    content: Vec<Content<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Content<'a> {
+    Text { text: &'a str },
+    Image { source: ImageSource },
+}
+
+#[derive(Serialize)]
+struct ImageSource {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media_type: &'static str,
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    content: Vec<ResponseBlock>,
+    usage: Option<ResponseUsage>,
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct ResponseUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl AnthropicClient {
+    pub fn new(api_key: &Secret, model: &str, max_tokens: u32, temperature: f32) -> Result<Self> {
+        Ok(Self {
+            http: http::llm_client(&ServiceAuth::default())?,
+            api_key: api_key.clone(),
+            model: model.to_string(),
+            max_tokens,
+            temperature,
+            retries: 0,
+        })
+    }
+
+    /// Send each request up to `retries` times again while it fails in a way that may pass.
+    #[must_use]
+    pub fn retrying(self, retries: u32) -> Self {
+        Self { retries, ..self }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub async fn complete(&self, prompt: &Prompt) -> Result<Completion> {
+        let mut content = vec![Content::Text { text: &prompt.user }];
+        content.extend(prompt.images.iter().map(|image| {
+            Content::Image {
+                source: ImageSource {
+                    kind: "base64",
+                    media_type: super::image_type(image).unwrap_or("image/png"),
+                    data: BASE64.encode(image),
+                },
+            }
+        }));
+        let request = Request {
+            model: &self.model,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            system: &prompt.system,
+            messages: [Message { role: "user", content }],
+        };
+        let body = serde_json::to_vec(&request)?;
+
+        let response: Response = serde_json::from_str(&self.send(body).await?)?;
+        if response.stop_reason.as_deref() == Some("max_tokens") {
+            tracing::warn!(
+                max_tokens = self.max_tokens,
+                "the reply was cut off at MABEL_MAX_TOKENS"
+            );
+        }
+        let usage = response
+            .usage
+            .map(|u| {
+                Usage {
+                    prompt_tokens: u.input_tokens,
+                    completion_tokens: u.output_tokens,
+                }
+            })
+            .unwrap_or_default();
+        let text: String = response
+            .content
+            .into_iter()
+            .filter(|b| b.kind == "text")
+            .map(|b| b.text)
+            .collect();
+        if text.is_empty() {
+            return Err(MabelError::Extraction {
+                reason: format!("{} returned no message content", self.model),
+            });
+        }
+        Ok(Completion { text, usage })
+    }
+
+    /// Check the key by looking the model up, which costs no tokens.
+    pub async fn check(&self) -> Result<()> {
+        let url = Url::parse(MODELS_URL)?.join(&self.model)?;
+        let resp = self
+            .http
+            .get(url.clone())
+            .header("x-api-key", self.api_key.expose())
+            .header("anthropic-version", API_VERSION)
+            .send()
+            .await
+            .map_err(|e| http::request_error(&url, e))?;
+        http::check_status(resp).await?;
+        Ok(())
+    }
+
+    /// POST the request, trying again while the API is rate-limiting or overloaded (529); returns
+    /// the response body.
+    async fn send(&self, body: Vec<u8>) -> Result<String> {
+        let url = Url::parse(API_URL)?;
+        let resp = http::send(self.retries, &url, || {
+            self.http
+                .post(url.clone())
+                .header("x-api-key", self.api_key.expose())
+                .header("anthropic-version", API_VERSION)
+                .header("content-type", "application/json")
+                .body(body.clone())
+        })
+        .await?;
+        resp.text().await.map_err(|e| http::request_error(&url, e))
+    }
+}
@@ -0,0 +1,167 @@
+//! Requests put off for the OpenAI Batch API, which answers within a day at half the price (`mabel
+//! batch --economy`).
+//!
+//! A prompt marked [`Prompt::batch`](super::Prompt::batch) that has no reply yet is not sent: its
+//! request body is queued, and the call fails with [`MabelError::BatchPending`]. Once the queue is
+//! submitted and the batch is done, the same prompt finds its reply here, so running the paper
+//! again goes through the normal pipeline with the model's answers already in. Requests are keyed
+//! by a hash of their body, and what was sent is kept in the cache directory (see
+//! [`Config::batch_state_path`]), so a run stopped while it waits picks the batch up again.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{Completion, Usage};
+use crate::{config::Config, store, MabelError, Result};
+
+/// A request sent in a batch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Sent {
+    /// The request's `custom_id`
+    key: String,
+    batch: String,
+    /// `None` while the batch runs
+    #[serde(default)]
+    reply: Option<Reply>,
+}
+
+/// What the batch gave for a request.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Reply {
+    Answered { text: String, usage: Usage },
+    Failed { reason: String },
+}
+
+#[derive(Default)]
+struct State {
+    sent: Vec<Sent>,
+    /// Request bodies by key, waiting to be submitted
+    pending: Vec<(String, serde_json::Value)>,
+}
+
+pub struct BatchQueue {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl std::fmt::Debug for BatchQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchQueue")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BatchQueue {
+    /// The requests sent by earlier runs. Those that failed are forgotten, so they are sent again.
+    pub async fn load(cfg: &Config) -> Result<Self> {
+        let path = cfg.batch_state_path();
+        let mut sent: Vec<Sent> = store::load(&path).await?;
+        sent.retain(|s| !matches!(s.reply, Some(Reply::Failed { .. })));
+        Ok(Self {
+            path,
+            state: Mutex::new(State {
+                sent,
+                pending: Vec::new(),
+            }),
+        })
+    }
+
+    /// The key of a request body: its hash, so the same prompt to the same model has the same key.
+    pub fn key(body: &serde_json::Value) -> String {
+        hex::encode(Sha256::digest(body.to_string().as_bytes()))
+    }
+
+    /// The reply to the request `key`, or [`MabelError::BatchPending`] after queueing `body` for the
+    /// next batch (unless a running batch has it already).
+    pub fn complete(&self, key: &str, body: serde_json::Value) -> Result<Completion> {
+        let mut state = self.state();
+        match state.sent.iter().find(|s| s.key == key) {
+            | Some(Sent {
+                reply: Some(Reply::Answered { text, usage }),
+                ..
+            }) => {
+                return Ok(Completion {
+                    text: text.clone(),
+                    usage: *usage,
+                })
+            }
+            | Some(Sent {
+                batch,
+                reply: Some(Reply::Failed { reason }),
+                ..
+            }) => {
+                return Err(MabelError::Batch {
+                    batch: batch.clone(),
+                    reason: reason.clone(),
+                })
+            }
+            | Some(_) => {}
+            | None if state.pending.iter().any(|(k, _)| k == key) => {}
+            | None => state.pending.push((key.to_string(), body)),
+        }
+        Err(MabelError::BatchPending)
+    }
+
+    /// The batches sent that have not been answered yet.
+    pub fn in_flight(&self) -> Vec<String> {
+        let state = self.state();
+        let running: BTreeSet<&String> = state
+            .sent
+            .iter()
+            .filter(|s| s.reply.is_none())
+            .map(|s| &s.batch)
+            .collect();
+        running.into_iter().cloned().collect()
+    }
+
+    /// The requests waiting to be submitted, which are no longer queued.
+    pub fn take_pending(&self) -> Vec<(String, serde_json::Value)> {
+        std::mem::take(&mut self.state().pending)
+    }
+
+    /// Keep that the requests `keys` went out in `batch`.
+    pub async fn sent(&self, batch: &str, keys: impl IntoIterator<Item = String>) -> Result<()> {
+        self.state().sent.extend(keys.into_iter().map(|key| {
+            Sent {
+                key,
+                batch: batch.to_string(),
+                reply: None,
+            }
+        }));
+        self.save().await
+    }
+
+    /// Keep the replies `batch` came back with; a request it has no reply for failed with `missing`.
+    pub async fn answer(&self, batch: &str, mut replies: HashMap<String, Reply>, missing: &str) -> Result<()> {
+        for sent in self.state().sent.iter_mut().filter(|s| s.batch == batch) {
+            sent.reply = Some(replies.remove(&sent.key).unwrap_or_else(|| {
+                Reply::Failed {
+                    reason: missing.to_string(),
+                }
+            }));
+        }
+        self.save().await
+    }
+
+    /// Forget the requests that have been answered, once the run that needed them is done.
+    pub async fn clear_answered(&self) -> Result<()> {
+        self.state().sent.retain(|s| s.reply.is_none());
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<()> {
+        let sent = self.state().sent.clone();
+        store::replace(&self.path, &sent, |_| true).await.map(drop)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
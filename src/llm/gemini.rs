@@ -0,0 +1,297 @@
+//! Google's Gemini models through the Gemini API (`generativelanguage.googleapis.com`).
+//!
+//! A prompt's `json` flag asks for a JSON reply (`responseMimeType`). Gemini models read a million
+//! tokens or more, so a paper is split into parts only past [`crate::prompt::MAX_INPUT_CHARS`] (see
+//! [`crate::chunk`]). Embeddings come from the API's embedding models, `text-embedding-004` unless
+//! `MABEL_EMBEDDING_MODEL` says otherwise.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{Completion, Prompt, Usage};
+use crate::{
+    http::{self, ServiceAuth},
+    secret::Secret,
+    MabelError, Result,
+};
+
+const API_URL: &str = "https://generativelanguage.googleapis.com/v1beta/";
+
+/// Texts per embedding request, the most the API takes.
+const EMBED_BATCH: usize = 100;
+
+#[derive(Clone, Debug)]
+pub struct GeminiClient {
+    http: Client,
+    api_key: Secret,
+    model: String,
+    max_tokens: u32,
+    temperature: f32,
+    retries: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    system_instruction: Content<'a>,
+    contents: [Content<'a>; 1],
+    generation_config: GenerationConfig,
+}
+
+#[derive(Serialize)]
+struct Content<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Part<'a> {
+    Text(&'a str),
+    InlineData(Blob),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Blob {
+    mime_type: &'static str,
+    data: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    max_output_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<ResponseUsage>,
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<ResponseContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseContent {
+    #[serde(default)]
+    parts: Vec<ResponsePart>,
+}
+
+#[derive(Deserialize)]
+struct ResponsePart {
+    #[serde(default)]
+    text: String,
+    /// Set on the model's thinking, which is not part of the reply
+    #[serde(default)]
+    thought: bool,
+}
+
+#[derive(Deserialize)]
+struct ResponseUsage {
+    #[serde(rename = "promptTokenCount", default)]
+    prompt: u64,
+    #[serde(rename = "candidatesTokenCount", default)]
+    candidates: u64,
+    /// Thinking tokens, billed as completion tokens
+    #[serde(rename = "thoughtsTokenCount", default)]
+    thoughts: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    requests: Vec<EmbedContent<'a>>,
+}
+
+#[derive(Serialize)]
+struct EmbedContent<'a> {
+    model: &'a str,
+    content: Content<'a>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    #[serde(default)]
+    embeddings: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    values: Vec<f32>,
+}
+
+impl GeminiClient {
+    pub fn new(api_key: &Secret, model: &str, max_tokens: u32, temperature: f32) -> Result<Self> {
+        Ok(Self {
+            http: http::llm_client(&ServiceAuth::default())?,
+            api_key: api_key.clone(),
+            model: model.to_string(),
+            max_tokens,
+            temperature,
+            retries: 0,
+        })
+    }
+
+    /// Send each request up to `retries` times again while it fails in a way that may pass.
+    #[must_use]
+    pub fn retrying(self, retries: u32) -> Self {
+        Self { retries, ..self }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub async fn complete(&self, prompt: &Prompt) -> Result<Completion> {
+        let mut parts = vec![Part::Text(&prompt.user)];
+        parts.extend(prompt.images.iter().map(|image| {
+            Part::InlineData(Blob {
+                mime_type: super::image_type(image).unwrap_or("image/png"),
+                data: BASE64.encode(image),
+            })
+        }));
+        let request = Request {
+            system_instruction: Content {
+                role: None,
+                parts: vec![Part::Text(&prompt.system)],
+            },
+            contents: [Content {
+                role: Some("user"),
+                parts,
+            }],
+            generation_config: GenerationConfig {
+                max_output_tokens: self.max_tokens,
+                temperature: self.temperature,
+                response_mime_type: prompt.json.then_some("application/json"),
+            },
+        };
+        let body = serde_json::to_vec(&request)?;
+
+        let action = format!("models/{}:generateContent", self.model);
+        let response: Response = serde_json::from_str(&self.send(&action, body).await?)?;
+        if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+            return Err(MabelError::Extraction {
+                reason: format!("{} refused the prompt ({reason})", self.model),
+            });
+        }
+        let usage = response
+            .usage_metadata
+            .map(|u| {
+                Usage {
+                    prompt_tokens: u.prompt,
+                    completion_tokens: u.candidates + u.thoughts,
+                }
+            })
+            .unwrap_or_default();
+        let Some(candidate) = response.candidates.into_iter().next() else {
+            return Err(MabelError::Extraction {
+                reason: format!("{} returned no candidates", self.model),
+            });
+        };
+        if candidate.finish_reason.as_deref() == Some("MAX_TOKENS") {
+            tracing::warn!(
+                max_tokens = self.max_tokens,
+                "the reply was cut off at MABEL_MAX_TOKENS"
+            );
+        }
+        let text: String = candidate
+            .content
+            .map(|c| c.parts)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| !p.thought)
+            .map(|p| p.text)
+            .collect();
+        if text.is_empty() {
+            let reason = candidate.finish_reason.unwrap_or_else(|| "no reason given".to_string());
+            return Err(MabelError::Extraction {
+                reason: format!("{} returned no message content ({reason})", self.model),
+            });
+        }
+        Ok(Completion { text, usage })
+    }
+
+    pub async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = format!("models/{}", model.trim_start_matches("models/"));
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH) {
+            let request = EmbedRequest {
+                requests: batch
+                    .iter()
+                    .map(|text| {
+                        EmbedContent {
+                            model: &model,
+                            content: Content {
+                                role: None,
+                                parts: vec![Part::Text(text)],
+                            },
+                        }
+                    })
+                    .collect(),
+            };
+            let body = serde_json::to_vec(&request)?;
+            let response: EmbedResponse =
+                serde_json::from_str(&self.send(&format!("{model}:batchEmbedContents"), body).await?)?;
+            if response.embeddings.len() != batch.len() {
+                return Err(MabelError::Extraction {
+                    reason: format!(
+                        "{model} returned {} embeddings for {} texts",
+                        response.embeddings.len(),
+                        batch.len()
+                    ),
+                });
+            }
+            vectors.extend(response.embeddings.into_iter().map(|e| e.values));
+        }
+        Ok(vectors)
+    }
+
+    /// Check the key by looking the model up, which costs no tokens.
+    pub async fn check(&self) -> Result<()> {
+        let url = Url::parse(API_URL)?.join(&format!("models/{}", self.model))?;
+        let resp = self
+            .http
+            .get(url.clone())
+            .header("x-goog-api-key", self.api_key.expose())
+            .send()
+            .await
+            .map_err(|e| http::request_error(&url, e))?;
+        http::check_status(resp).await?;
+        Ok(())
+    }
+
+    /// POST the request to `action` (`models/<model>:<method>`), trying again while the API is
+    /// rate-limiting or overloaded; returns the response body.
+    async fn send(&self, action: &str, body: Vec<u8>) -> Result<String> {
+        let url = Url::parse(API_URL)?.join(action)?;
+        let resp = http::send(self.retries, &url, || {
+            self.http
+                .post(url.clone())
+                .header("x-goog-api-key", self.api_key.expose())
+                .header("content-type", "application/json")
+                .body(body.clone())
+        })
+        .await?;
+        resp.text().await.map_err(|e| http::request_error(&url, e))
+    }
+}
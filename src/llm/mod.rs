@@ -0,0 +1,409 @@
+//! Chat-completion (and embedding) backends behind one small interface.
+//!
+//! Backends are compiled in per cargo feature (`openai`, `ollama`, `anthropic`, `gemini`); selecting
+//! one that was not built in is a configuration error rather than a panic.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, LlmBackend},
+    MabelError, Result,
+};
+
+#[cfg(feature = "anthropic")]
+pub mod anthropic;
+pub mod batch;
+#[cfg(feature = "gemini")]
+pub mod gemini;
+#[cfg(feature = "ollama")]
+pub mod ollama;
+#[cfg(feature = "openai")]
+pub mod openai;
+#[cfg(any(feature = "openai", feature = "ollama"))]
+mod progress;
+
+/// One request to the model: a system instruction plus the user content.
+#[derive(Clone, Debug, Default)]
+pub struct Prompt {
+    pub system: String,
+    pub user: String,
+    /// Ask the backend to constrain the reply to a JSON object
+    pub json: bool,
+    /// Images shown along with `user`, in a format [`image_type`] knows; the model must accept
+    /// images
+    pub images: Vec<Vec<u8>>,
+    /// The request may wait for an OpenAI batch, under `mabel batch --economy` (see [`batch`])
+    pub batch: bool,
+}
+
+/// The media type of an image the backends can send: PNG, JPEG, GIF or WebP, told by its first
+/// bytes.
+pub fn image_type(image: &[u8]) -> Option<&'static str> {
+    match image {
+        | [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        | [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+        | [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        | [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        | _ => None,
+    }
+}
+
+/// Token accounting as reported by the backend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.prompt_tokens += rhs.prompt_tokens;
+        self.completion_tokens += rhs.completion_tokens;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Usage,
+}
+
+/// The configured backend.
+#[derive(Clone, Debug)]
+pub enum Llm {
+    #[cfg(feature = "openai")]
+    OpenAi(openai::OpenAiClient),
+    #[cfg(feature = "ollama")]
+    Ollama(ollama::OllamaClient),
+    #[cfg(feature = "anthropic")]
+    Anthropic(anthropic::AnthropicClient),
+    #[cfg(feature = "gemini")]
+    Gemini(gemini::GeminiClient),
+}
+
+/// The JSON object in a model's reply: from its first `{` to its last `}`, so prose or a code
+/// fence around it is left out. The whole reply, trimmed, when there is none.
+pub fn json_object(reply: &str) -> &str {
+    let trimmed = reply.trim();
+    match (trimmed.find('{'), trimmed.rfind('}')) {
+        | (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+        | _ => trimmed,
+    }
+}
+
+impl Llm {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        Self::from_backend(&cfg.llm, cfg.http_retries)
+    }
+
+    /// A client for `backend` that sends each request up to `retries` times again while it fails
+    /// in a way that may pass (see [`crate::retry`]).
+    #[cfg_attr(
+        not(any(feature = "openai", feature = "ollama", feature = "anthropic", feature = "gemini")),
+        allow(unused_variables)
+    )]
+    pub fn from_backend(backend: &LlmBackend, retries: u32) -> Result<Self> {
+        match backend {
+            #[cfg(feature = "openai")]
+            | LlmBackend::OpenAi {
+                api_key,
+                base_url,
+                model,
+                max_tokens,
+                temperature,
+                auth,
+                stream,
+            } => {
+                let client = openai::OpenAiClient::new(
+                    api_key.expose(),
+                    base_url.as_ref(),
+                    auth,
+                    model,
+                    *max_tokens,
+                    *temperature,
+                )?;
+                Ok(Self::OpenAi(client.streaming(*stream).retrying(retries)))
+            }
+            #[cfg(feature = "ollama")]
+            | LlmBackend::Ollama {
+                host,
+                model,
+                max_tokens,
+                temperature,
+                auth,
+                keep_alive,
+                num_ctx,
+                stream,
+                ..
+            } => {
+                let client =
+                    ollama::OllamaClient::new(host, auth, model, *max_tokens, *temperature, *keep_alive, *num_ctx)?;
+                Ok(Self::Ollama(client.streaming(*stream).retrying(retries)))
+            }
+            #[cfg(feature = "anthropic")]
+            | LlmBackend::Anthropic {
+                api_key,
+                model,
+                max_tokens,
+                temperature,
+            } => {
+                let client = anthropic::AnthropicClient::new(api_key, model, *max_tokens, *temperature)?;
+                Ok(Self::Anthropic(client.retrying(retries)))
+            }
+            #[cfg(feature = "gemini")]
+            | LlmBackend::Gemini {
+                api_key,
+                model,
+                max_tokens,
+                temperature,
+            } => {
+                let client = gemini::GeminiClient::new(api_key, model, *max_tokens, *temperature)?;
+                Ok(Self::Gemini(client.retrying(retries)))
+            }
+            #[allow(unreachable_patterns)]
+            | backend => {
+                Err(MabelError::Config {
+                    msg: format!("mabel was built without support for the {} backend", backend.name()),
+                })
+            }
+        }
+    }
+
+    /// Model name, for frontmatter and logs.
+    pub fn model(&self) -> &str {
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(ref c) => c.model(),
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => c.model(),
+            #[cfg(feature = "anthropic")]
+            | Self::Anthropic(ref c) => c.model(),
+            #[cfg(feature = "gemini")]
+            | Self::Gemini(ref c) => c.model(),
+        }
+    }
+
+    /// Put the prompts marked [`Prompt::batch`] off for the OpenAI Batch API, in `queue`; only the
+    /// OpenAI backend has one.
+    pub fn batched(self, queue: Arc<batch::BatchQueue>) -> Result<Self> {
+        match self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(c) => Ok(Self::OpenAi(c.batched(queue))),
+            #[allow(unreachable_patterns)]
+            | other => {
+                drop(queue);
+                Err(MabelError::Config {
+                    msg: format!(
+                        "--economy takes the OpenAI Batch API, which the {} model is not on",
+                        other.model()
+                    ),
+                })
+            }
+        }
+    }
+
+    /// Whether prompts may wait for an OpenAI batch, whose requests cost half.
+    pub fn is_batched(&self) -> bool {
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(ref c) => c.is_batched(),
+            #[allow(unreachable_patterns)]
+            | _ => false,
+        }
+    }
+
+    /// Submit the requests put off since the last batch; returns the new batch's id, or `None` when
+    /// there were none.
+    #[cfg_attr(not(feature = "openai"), allow(clippy::unused_async))]
+    pub async fn submit_batch(&self) -> Result<Option<String>> {
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(ref c) => c.submit_batch().await,
+            #[allow(unreachable_patterns)]
+            | _ => Ok(None),
+        }
+    }
+
+    /// Wait for the batch `id` to end and keep its replies.
+    #[cfg_attr(not(feature = "openai"), allow(clippy::unused_async))]
+    pub async fn wait_batch(&self, id: &str) -> Result<()> {
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(ref c) => c.wait_batch(id).await,
+            #[allow(unreachable_patterns)]
+            | _ => {
+                let _ = id;
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the model runs on a server of the user's own, where requests cost nothing.
+    pub fn is_local(&self) -> bool {
+        match *self {
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(_) => true,
+            #[allow(unreachable_patterns)]
+            | _ => false,
+        }
+    }
+
+    #[cfg_attr(
+        not(any(feature = "openai", feature = "ollama", feature = "anthropic", feature = "gemini")),
+        allow(clippy::unused_async)
+    )]
+    pub async fn complete(&self, prompt: &Prompt) -> Result<Completion> {
+        tracing::debug!(
+            model = self.model(),
+            chars = prompt.user.len(),
+            images = prompt.images.len(),
+            "sending prompt"
+        );
+        let reply = async {
+            match *self {
+                #[cfg(feature = "openai")]
+                | Self::OpenAi(ref c) => c.complete(prompt).await.inspect(|c| self.log_usage(c.usage)),
+                #[cfg(feature = "ollama")]
+                | Self::Ollama(ref c) => c.complete(prompt).await.inspect(|c| self.log_usage(c.usage)),
+                #[cfg(feature = "anthropic")]
+                | Self::Anthropic(ref c) => c.complete(prompt).await.inspect(|c| self.log_usage(c.usage)),
+                #[cfg(feature = "gemini")]
+                | Self::Gemini(ref c) => c.complete(prompt).await.inspect(|c| self.log_usage(c.usage)),
+            }
+        };
+        crate::limits::run(
+            crate::timing::Step::Llm,
+            crate::timing::time(crate::timing::Step::Llm, crate::timing::counted(reply)),
+        )
+        .await
+    }
+
+    #[cfg_attr(
+        not(any(feature = "openai", feature = "ollama", feature = "anthropic", feature = "gemini")),
+        allow(dead_code)
+    )]
+    fn log_usage(&self, usage: Usage) {
+        tracing::debug!(
+            model = self.model(),
+            prompt_tokens = usage.prompt_tokens,
+            completion_tokens = usage.completion_tokens,
+            cost = crate::cost::estimate(self, usage).map(crate::cost::format),
+            "reply received"
+        );
+    }
+
+    /// The context window the model was given in tokens, where the backend takes one
+    /// (`OLLAMA_NUM_CTX`).
+    pub fn num_ctx(&self) -> Option<u32> {
+        match *self {
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => c.num_ctx(),
+            #[allow(unreachable_patterns)]
+            | _ => None,
+        }
+    }
+
+    /// Load the model ahead of the first prompt, where it runs on a server of the user's own; hosted
+    /// models are always loaded.
+    #[cfg_attr(not(feature = "ollama"), allow(clippy::unused_async))]
+    pub async fn preload(&self) -> Result<()> {
+        match *self {
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => {
+                tracing::debug!(model = c.model(), "preloading the model");
+                c.preload().await
+            }
+            #[allow(unreachable_patterns)]
+            | _ => Ok(()),
+        }
+    }
+
+    /// Whether the model has to be pulled to the user's own server before it can answer; hosted
+    /// models are always there.
+    #[cfg_attr(not(feature = "ollama"), allow(clippy::unused_async))]
+    pub async fn needs_pull(&self) -> Result<bool> {
+        match *self {
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => c.has_model().await.map(|has| !has),
+            #[allow(unreachable_patterns)]
+            | _ => Ok(false),
+        }
+    }
+
+    /// Pull the model to the user's own server (see [`Llm::needs_pull`]).
+    #[cfg_attr(not(feature = "ollama"), allow(clippy::unused_async))]
+    pub async fn pull(&self) -> Result<()> {
+        match *self {
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => c.pull().await,
+            #[allow(unreachable_patterns)]
+            | _ => Ok(()),
+        }
+    }
+
+    /// Check that the backend answers, takes the credentials and has the model, without spending
+    /// tokens (`mabel doctor`).
+    #[cfg_attr(
+        not(any(feature = "openai", feature = "ollama", feature = "anthropic", feature = "gemini")),
+        allow(clippy::unused_async)
+    )]
+    pub async fn check(&self) -> Result<()> {
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(ref c) => c.check().await,
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => c.check().await,
+            #[cfg(feature = "anthropic")]
+            | Self::Anthropic(ref c) => c.check().await,
+            #[cfg(feature = "gemini")]
+            | Self::Gemini(ref c) => c.check().await,
+        }
+    }
+
+    /// Embedding model used when `MABEL_EMBEDDING_MODEL` is not set; Anthropic has none.
+    #[cfg(feature = "embeddings")]
+    pub fn default_embedding_model(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(_) => "text-embedding-3-small",
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(_) => "nomic-embed-text",
+            #[cfg(feature = "anthropic")]
+            | Self::Anthropic(_) => "",
+            #[cfg(feature = "gemini")]
+            | Self::Gemini(_) => "text-embedding-004",
+        }
+    }
+
+    /// Embed `texts` with `model`; one vector per text, in order.
+    #[cfg(feature = "embeddings")]
+    #[cfg_attr(
+        not(any(feature = "openai", feature = "ollama", feature = "gemini")),
+        allow(clippy::unused_async)
+    )]
+    pub async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        tracing::debug!(model, count = texts.len(), "requesting embeddings");
+        match *self {
+            #[cfg(feature = "openai")]
+            | Self::OpenAi(ref c) => c.embed(model, texts).await,
+            #[cfg(feature = "ollama")]
+            | Self::Ollama(ref c) => c.embed(model, texts).await,
+            #[cfg(feature = "gemini")]
+            | Self::Gemini(ref c) => c.embed(model, texts).await,
+            #[cfg(feature = "anthropic")]
+            | Self::Anthropic(_) => {
+                Err(MabelError::Config {
+                    msg: "Anthropic has no embedding models: use another backend for this".to_string(),
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Local models through an Ollama server.
+//!
+//! Loading a model from disk can take longer than a summary does, so the keep-alive
+//! (`OLLAMA_KEEP_ALIVE`) is sent with every request, and the model can be loaded ahead of the
+//! first one (see [`OllamaClient::preload`]). A changed context window (`OLLAMA_NUM_CTX`) makes the
+//! server load the model again, so every request, the preload included, asks for the same one.
+//! Replies are streamed when they can be shown coming in (see [`Progress`]). A model the server
+//! does not have yet can be pulled to it (see [`OllamaClient::pull`]).
+
+use std::io::IsTerminal;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use ollama_rs::{
+    error::OllamaError,
+    generation::{
+        chat::{request::ChatMessageRequest, ChatMessage, ChatMessageFinalResponseData},
+        completion::request::GenerationRequest,
+        embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest},
+        images::Image,
+        parameters::{self, FormatType, TimeUnit},
+    },
+    models::ModelOptions,
+    Ollama,
+};
+use url::Url;
+
+use super::{progress::Progress, Completion, Prompt, Usage};
+use crate::{
+    config::KeepAlive,
+    http::{self, ServiceAuth},
+    retry, MabelError, Result,
+};
+
+#[derive(Clone, Debug)]
+pub struct OllamaClient {
+    client: Ollama,
+    model: String,
+    max_tokens: u32,
+    temperature: f32,
+    keep_alive: Option<KeepAlive>,
+    num_ctx: Option<u32>,
+    stream: bool,
+    retries: u32,
+}
+
+impl OllamaClient {
+    /// A client for the server at `host`, sending `auth` with every request.
+    pub fn new(
+        host: &Url,
+        auth: &ServiceAuth,
+        model: &str,
+        max_tokens: u32,
+        temperature: f32,
+        keep_alive: Option<KeepAlive>,
+        num_ctx: Option<u32>,
+    ) -> Result<Self> {
+        let client = if auth.is_empty() {
+            Ollama::from_url(host.clone())
+        } else {
+            let port = host.port_or_known_default().unwrap_or(11434);
+            Ollama::new_with_client(host.clone(), port, http::llm_client(auth)?)
+        };
+        Ok(Self {
+            client,
+            model: model.to_string(),
+            max_tokens,
+            temperature,
+            keep_alive,
+            num_ctx,
+            stream: false,
+            retries: 0,
+        })
+    }
+
+    /// Stream replies where there is a terminal to show them on.
+    #[must_use]
+    pub fn streaming(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Send each request up to `retries` times again while the server cannot be reached or does
+    /// not answer in time.
+    #[must_use]
+    pub fn retrying(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn num_ctx(&self) -> Option<u32> {
+        self.num_ctx
+    }
+
+    pub async fn complete(&self, prompt: &Prompt) -> Result<Completion> {
+        let mut user = ChatMessage::user(prompt.user.clone());
+        if !prompt.images.is_empty() {
+            user = user.with_images(
+                prompt
+                    .images
+                    .iter()
+                    .map(|image| Image::from_base64(BASE64.encode(image)))
+                    .collect(),
+            );
+        }
+        let messages = vec![ChatMessage::system(prompt.system.clone()), user];
+        let options = self
+            .options()
+            .temperature(self.temperature)
+            .num_predict(i32::try_from(self.max_tokens).unwrap_or(i32::MAX));
+        let mut request = ChatMessageRequest::new(self.model.clone(), messages).options(options);
+        if prompt.json {
+            request = request.format(FormatType::Json);
+        }
+        if let Some(keep_alive) = self.keep_alive() {
+            request = request.keep_alive(keep_alive);
+        }
+
+        if let Some(progress) = self.stream.then(|| Progress::start(&self.model)).flatten() {
+            return self.complete_streamed(request, progress).await;
+        }
+
+        let response = retry::run(self.retries, || {
+            let request = request.clone();
+            async move { Ok(self.client.send_chat_messages(request).await?) }
+        })
+        .await?;
+        Ok(Completion {
+            text: response.message.content,
+            usage: response.final_data.as_ref().map(usage).unwrap_or_default(),
+        })
+    }
+
+    /// [`OllamaClient::complete`], with the reply shown on `progress` as it comes in.
+    async fn complete_streamed(&self, request: ChatMessageRequest, mut progress: Progress) -> Result<Completion> {
+        let mut stream = retry::run(self.retries, || {
+            let request = request.clone();
+            async move { Ok(self.client.send_chat_messages_stream(request).await?) }
+        })
+        .await?;
+        let (mut text, mut total) = (String::new(), Usage::default());
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|()| {
+                MabelError::Extraction {
+                    reason: format!("the reply from {} broke off", self.model),
+                }
+            })?;
+            progress.push(&chunk.message.content);
+            text.push_str(&chunk.message.content);
+            // The last chunk has the token counts.
+            total = chunk.final_data.as_ref().map_or(total, usage);
+        }
+        Ok(Completion { text, usage: total })
+    }
+
+    /// Load the model into memory, which a request without a prompt does, so the first summary does
+    /// not wait for it.
+    pub async fn preload(&self) -> Result<()> {
+        // A model that is not pulled yet is seen to before the first summary.
+        if !self.has_model().await? {
+            return Ok(());
+        }
+        let mut request = GenerationRequest::new(self.model.clone(), "").options(self.options());
+        if let Some(keep_alive) = self.keep_alive() {
+            request = request.keep_alive(keep_alive);
+        }
+        self.client.generate(request).await?;
+        Ok(())
+    }
+
+    /// Check the server is up and has the model.
+    pub async fn check(&self) -> Result<()> {
+        if self.has_model().await? {
+            return Ok(());
+        }
+        Err(MabelError::Config {
+            msg: format!("OLLAMA_MODEL {} is not pulled on the server", self.model),
+        })
+    }
+
+    /// Whether the server has the model (`/api/tags`); a name without a tag means `:latest`.
+    pub async fn has_model(&self) -> Result<bool> {
+        let models = self.client.list_local_models().await?;
+        let latest = format!("{}:latest", self.model);
+        Ok(models.iter().any(|m| m.name == self.model || m.name == latest))
+    }
+
+    /// Have the server download the model, showing how far it got on a terminal and logging each
+    /// step elsewhere.
+    pub async fn pull(&self) -> Result<()> {
+        let failed = |reason: &str| {
+            MabelError::Config {
+                msg: format!("could not pull OLLAMA_MODEL {}: {reason}", self.model),
+            }
+        };
+        let mut stream = self
+            .client
+            .pull_model_stream(self.model.clone(), false)
+            .await
+            .map_err(|e| {
+                match e {
+                    | OllamaError::Other(reason) => failed(reason.trim()),
+                    | e => e.into(),
+                }
+            })?;
+        let bar = std::io::stderr().is_terminal().then(|| {
+            let bar = ProgressBar::new(0);
+            if let Ok(style) = ProgressStyle::with_template("{prefix} {msg} {wide_bar} {bytes}/{total_bytes}") {
+                bar.set_style(style);
+            }
+            bar.set_prefix(format!("pulling {}", self.model));
+            bar
+        });
+        let mut step = String::new();
+        while let Some(status) = stream.next().await {
+            let status = match status {
+                | Ok(status) => status,
+                | Err(OllamaError::InternalError(e)) => return Err(failed(&e.message)),
+                // A line of progress cut in two by the transport; the next one says as much.
+                | Err(e) => {
+                    tracing::debug!(error = %e, "unreadable pull status");
+                    continue;
+                }
+            };
+            match &bar {
+                | Some(bar) => {
+                    bar.set_message(status.message.clone());
+                    bar.set_length(status.total.unwrap_or(0));
+                    bar.set_position(status.completed.unwrap_or(0));
+                }
+                | None if status.message != step => {
+                    tracing::info!(model = %self.model, step = %status.message, "pulling the model");
+                }
+                | None => {}
+            }
+            step = status.message;
+        }
+        if let Some(bar) = bar {
+            bar.finish_and_clear();
+        }
+        if !self.has_model().await? {
+            return Err(failed(&format!("the server stopped at {step:?}")));
+        }
+        tracing::info!(model = %self.model, "model pulled");
+        Ok(())
+    }
+
+    /// One embedding per text, in order.
+    pub async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let response = retry::run(self.retries, || {
+            let mut request =
+                GenerateEmbeddingsRequest::new(model.to_string(), EmbeddingsInput::Multiple(texts.to_vec()));
+            if let Some(keep_alive) = self.keep_alive() {
+                request = request.keep_alive(keep_alive);
+            }
+            async move { Ok(self.client.generate_embeddings(request).await?) }
+        })
+        .await?;
+        Ok(response.embeddings)
+    }
+
+    /// Options every request shares.
+    fn options(&self) -> ModelOptions {
+        let options = ModelOptions::default();
+        match self.num_ctx {
+            | Some(num_ctx) => options.num_ctx(u64::from(num_ctx)),
+            | None => options,
+        }
+    }
+
+    fn keep_alive(&self) -> Option<parameters::KeepAlive> {
+        self.keep_alive.map(|k| {
+            match k {
+                | KeepAlive::Forever => parameters::KeepAlive::Indefinitely,
+                | KeepAlive::Secs(0) => parameters::KeepAlive::UnloadOnCompletion,
+                | KeepAlive::Secs(time) => {
+                    parameters::KeepAlive::Until {
+                        time,
+                        unit: TimeUnit::Seconds,
+                    }
+                }
+            }
+        })
+    }
+}
+
+fn usage(d: &ChatMessageFinalResponseData) -> Usage {
+    Usage {
+        prompt_tokens: d.prompt_eval_count,
+        completion_tokens: d.eval_count,
+    }
+}
@@ -0,0 +1,372 @@
+//! OpenAI chat completions and embeddings. Replies are streamed when they can be shown coming in
+//! (see [`Progress`]), or put off for the Batch API under `mabel batch --economy` (see
+//! [`BatchQueue`]).
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_openai::{
+    config::OpenAIConfig,
+    types::{
+        BatchCompletionWindow, BatchEndpoint, BatchRequest, BatchRequestInput, BatchRequestInputMethod,
+        BatchRequestOutput, BatchStatus, ChatCompletionRequestMessageContentPartImage,
+        ChatCompletionRequestMessageContentPartText, ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
+        ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions, CompletionUsage,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, CreateChatCompletionResponse,
+        CreateEmbeddingRequestArgs, CreateFileRequestArgs, FileInput, FilePurpose, ImageUrl, ResponseFormat,
+    },
+    Client,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::StreamExt;
+use url::Url;
+
+use super::{
+    batch::{BatchQueue, Reply},
+    progress::Progress,
+    Completion, Prompt, Usage,
+};
+use crate::{
+    http::{self, ServiceAuth},
+    retry, MabelError, Result,
+};
+
+#[derive(Clone, Debug)]
+pub struct OpenAiClient {
+    client: Client<OpenAIConfig>,
+    model: String,
+    max_tokens: u32,
+    temperature: f32,
+    stream: bool,
+    batch: Option<Arc<BatchQueue>>,
+    retries: u32,
+}
+
+/// How often a running batch is asked how it is doing.
+const BATCH_POLL: Duration = Duration::from_secs(60);
+
+impl OpenAiClient {
+    /// A client for api.openai.com, or for the OpenAI-compatible server at `base_url`, sending
+    /// `auth` with every request.
+    pub fn new(
+        api_key: &str,
+        base_url: Option<&Url>,
+        auth: &ServiceAuth,
+        model: &str,
+        max_tokens: u32,
+        temperature: f32,
+    ) -> Result<Self> {
+        let mut config = OpenAIConfig::new().with_api_key(api_key);
+        if let Some(base_url) = base_url {
+            config = config.with_api_base(base_url.as_str().trim_end_matches('/'));
+        }
+        let mut client = Client::with_config(config).with_backoff(retry::backoff(Some(Duration::ZERO)));
+        if !auth.is_empty() {
+            client = client.with_http_client(http::llm_client(auth)?);
+        }
+        Ok(Self {
+            client,
+            model: model.to_string(),
+            max_tokens,
+            temperature,
+            stream: false,
+            batch: None,
+            retries: 0,
+        })
+    }
+
+    /// Stream replies where there is a terminal to show them on.
+    #[must_use]
+    pub fn streaming(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// Send each request up to `retries` times again while it fails in a way that may pass. The
+    /// client itself retries rate-limited and failed requests, on the same schedule for about as
+    /// long; a request that could not be sent at all is retried here.
+    #[must_use]
+    pub fn retrying(mut self, retries: u32) -> Self {
+        self.client = self.client.with_backoff(retry::backoff(Some(retry::budget(retries))));
+        self.retries = retries;
+        self
+    }
+
+    /// Put prompts marked for it off for the Batch API, in `queue`.
+    #[must_use]
+    pub fn batched(mut self, queue: Arc<BatchQueue>) -> Self {
+        self.batch = Some(queue);
+        self
+    }
+
+    pub fn is_batched(&self) -> bool {
+        self.batch.is_some()
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub async fn complete(&self, prompt: &Prompt) -> Result<Completion> {
+        let messages = vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(prompt.system.as_str())
+                .build()?
+                .into(),
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(user_content(prompt))
+                .build()?
+                .into(),
+        ];
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request
+            .model(&self.model)
+            .messages(messages)
+            .max_completion_tokens(self.max_tokens)
+            .temperature(self.temperature);
+        if prompt.json {
+            request.response_format(ResponseFormat::JsonObject);
+        }
+
+        let request = request.build()?;
+        if let (Some(queue), true) = (&self.batch, prompt.batch) {
+            let body = serde_json::to_value(&request)?;
+            return queue.complete(&BatchQueue::key(&body), body);
+        }
+        if let Some(progress) = self.stream.then(|| Progress::start(&self.model)).flatten() {
+            return self.complete_streamed(request, progress).await;
+        }
+
+        let response = retry::run(self.retries, || {
+            let request = request.clone();
+            async move { Ok(self.client.chat().create(request).await?) }
+        })
+        .await?;
+        self.completion(response)
+    }
+
+    fn completion(&self, response: CreateChatCompletionResponse) -> Result<Completion> {
+        let usage = response.usage.as_ref().map(usage).unwrap_or_default();
+        let text = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .ok_or_else(|| self.no_content())?;
+        Ok(Completion { text, usage })
+    }
+
+    /// [`OpenAiClient::complete`], with the reply shown on `progress` as it comes in.
+    async fn complete_streamed(
+        &self,
+        mut request: CreateChatCompletionRequest,
+        mut progress: Progress,
+    ) -> Result<Completion> {
+        request.stream_options = Some(ChatCompletionStreamOptions { include_usage: true });
+        let mut stream = retry::run(self.retries, || {
+            let request = request.clone();
+            async move { Ok(self.client.chat().create_stream(request).await?) }
+        })
+        .await?;
+        let (mut text, mut total) = (String::new(), Usage::default());
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            // Only the last chunk has the usage.
+            total = chunk.usage.as_ref().map_or(total, usage);
+            for piece in chunk
+                .choices
+                .into_iter()
+                .filter(|c| c.index == 0)
+                .filter_map(|c| c.delta.content)
+            {
+                progress.push(&piece);
+                text.push_str(&piece);
+            }
+        }
+        if text.is_empty() {
+            return Err(self.no_content());
+        }
+        Ok(Completion { text, usage: total })
+    }
+
+    fn no_content(&self) -> MabelError {
+        MabelError::Extraction {
+            reason: format!("{} returned no message content", self.model),
+        }
+    }
+
+    /// Submit the requests put off since the last batch as a new one; returns its id, or `None`
+    /// when there were none.
+    pub async fn submit_batch(&self) -> Result<Option<String>> {
+        let Some(queue) = &self.batch else {
+            return Ok(None);
+        };
+        let pending = queue.take_pending();
+        if pending.is_empty() {
+            return Ok(None);
+        }
+        let mut lines = String::new();
+        for (key, body) in &pending {
+            let line = BatchRequestInput {
+                custom_id: key.clone(),
+                method: BatchRequestInputMethod::POST,
+                url: BatchEndpoint::V1ChatCompletions,
+                body: Some(body.clone()),
+            };
+            lines.push_str(&serde_json::to_string(&line)?);
+            lines.push('\n');
+        }
+        let file = CreateFileRequestArgs::default()
+            .file(FileInput::from_vec_u8(
+                "mabel-batch.jsonl".to_string(),
+                lines.into_bytes(),
+            ))
+            .purpose(FilePurpose::Batch)
+            .build()?;
+        let file = self.client.files().create(file).await?;
+        let batch = self
+            .client
+            .batches()
+            .create(BatchRequest {
+                input_file_id: file.id,
+                endpoint: BatchEndpoint::V1ChatCompletions,
+                completion_window: BatchCompletionWindow::W24H,
+                metadata: None,
+            })
+            .await?;
+        tracing::info!(batch = batch.id, requests = pending.len(), "batch submitted");
+        queue.sent(&batch.id, pending.into_iter().map(|(key, _)| key)).await?;
+        Ok(Some(batch.id))
+    }
+
+    /// Wait for the batch `id` to end, then keep its replies for the prompts that were put off.
+    pub async fn wait_batch(&self, id: &str) -> Result<()> {
+        let Some(queue) = &self.batch else {
+            return Ok(());
+        };
+        let batch = loop {
+            let batch = self.client.batches().retrieve(id).await?;
+            match batch.status {
+                | BatchStatus::Completed | BatchStatus::Failed | BatchStatus::Expired | BatchStatus::Cancelled => {
+                    break batch
+                }
+                | status => {
+                    let counts = batch.request_counts.as_ref();
+                    tracing::info!(
+                        batch = id,
+                        status = ?status,
+                        done = counts.map(|c| c.completed + c.failed),
+                        total = counts.map(|c| c.total),
+                        "waiting for the batch"
+                    );
+                    tokio::time::sleep(BATCH_POLL).await;
+                }
+            }
+        };
+        let mut replies = HashMap::new();
+        for file in [&batch.output_file_id, &batch.error_file_id].into_iter().flatten() {
+            let content = self.client.files().content(file).await?;
+            for line in String::from_utf8_lossy(&content)
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+            {
+                let output: BatchRequestOutput = serde_json::from_str(line)?;
+                replies.insert(output.custom_id.clone(), self.reply(output));
+            }
+        }
+        let missing = match &batch.status {
+            | BatchStatus::Completed => "the batch has no reply for it".to_string(),
+            | status => {
+                let why = batch
+                    .errors
+                    .iter()
+                    .flat_map(|e| &e.data)
+                    .next()
+                    .map(|e| e.message.as_str());
+                format!(
+                    "the batch ended {status:?}{}",
+                    why.map(|w| format!(": {w}")).unwrap_or_default()
+                )
+            }
+        };
+        tracing::info!(batch = id, status = ?batch.status, replies = replies.len(), "batch done");
+        queue.answer(id, replies, &missing).await
+    }
+
+    /// The reply to one request of a batch.
+    fn reply(&self, output: BatchRequestOutput) -> Reply {
+        let failed = |reason: String| Reply::Failed { reason };
+        match (output.response, output.error) {
+            | (Some(response), _) if response.status_code == 200 => {
+                match serde_json::from_value(response.body).map_err(MabelError::from) {
+                    | Ok(body) => {
+                        match self.completion(body) {
+                            | Ok(Completion { text, usage }) => Reply::Answered { text, usage },
+                            | Err(e) => failed(e.to_string()),
+                        }
+                    }
+                    | Err(e) => failed(e.to_string()),
+                }
+            }
+            | (Some(response), _) => {
+                let message = response
+                    .body
+                    .pointer("/error/message")
+                    .and_then(serde_json::Value::as_str);
+                failed(format!(
+                    "HTTP {}{}",
+                    response.status_code,
+                    message.map(|m| format!(": {m}")).unwrap_or_default()
+                ))
+            }
+            | (None, Some(error)) => failed(format!("{}: {}", error.code, error.message)),
+            | (None, None) => failed("no reply".to_string()),
+        }
+    }
+
+    /// Check the key by looking the model up, which costs no tokens.
+    pub async fn check(&self) -> Result<()> {
+        self.client.models().retrieve(&self.model).await?;
+        Ok(())
+    }
+
+    /// One embedding per text, in order.
+    pub async fn embed(&self, model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(model)
+            .input(texts.to_vec())
+            .build()?;
+        let mut response = retry::run(self.retries, || {
+            let request = request.clone();
+            async move { Ok(self.client.embeddings().create(request).await?) }
+        })
+        .await?;
+        response.data.sort_by_key(|e| e.index);
+        Ok(response.data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+fn usage(u: &CompletionUsage) -> Usage {
+    Usage {
+        prompt_tokens: u64::from(u.prompt_tokens),
+        completion_tokens: u64::from(u.completion_tokens),
+    }
+}
+
+/// The user message: plain text, or text followed by the images as data URLs.
+fn user_content(prompt: &Prompt) -> ChatCompletionRequestUserMessageContent {
+    if prompt.images.is_empty() {
+        return prompt.user.as_str().into();
+    }
+    let mut parts = vec![ChatCompletionRequestUserMessageContentPart::from(
+        ChatCompletionRequestMessageContentPartText::from(prompt.user.as_str()),
+    )];
+    parts.extend(prompt.images.iter().map(|image| {
+        let media_type = super::image_type(image).unwrap_or("image/png");
+        ChatCompletionRequestMessageContentPartImage {
+            image_url: ImageUrl::from(format!("data:{media_type};base64,{}", BASE64.encode(image))),
+        }
+        .into()
+    }));
+    parts.into()
+}
@@ -0,0 +1,64 @@
+//! The line shown on a terminal while a streamed reply comes in: a spinner, the tokens received so
+//! far and the end of the text, so a long summary is visibly under way. Lines of concurrent
+//! requests (`mabel batch`) are stacked.
+
+use std::{io::IsTerminal, sync::OnceLock, time::Duration};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// How much of the reply's end is shown.
+const TAIL_CHARS: usize = 60;
+
+pub struct Progress {
+    bar: ProgressBar,
+    tokens: u64,
+    tail: String,
+}
+
+impl Progress {
+    /// A progress line for a reply from `model`; `None` when stderr is not a terminal, where
+    /// replies are not streamed.
+    pub fn start(model: &str) -> Option<Self> {
+        static LINES: OnceLock<MultiProgress> = OnceLock::new();
+        if !std::io::stderr().is_terminal() {
+            return None;
+        }
+        let bar = LINES.get_or_init(MultiProgress::new).add(ProgressBar::new_spinner());
+        if let Ok(style) = ProgressStyle::with_template("{spinner} {prefix} {elapsed:>3} {msg}") {
+            bar.set_style(style);
+        }
+        bar.set_prefix(model.to_string());
+        bar.enable_steady_tick(Duration::from_millis(120));
+        Some(Self {
+            bar,
+            tokens: 0,
+            tail: String::new(),
+        })
+    }
+
+    /// Show a piece of the reply. Backends stream about a token at a time, so pieces are counted
+    /// as tokens until the backend reports the real count.
+    pub fn push(&mut self, piece: &str) {
+        if piece.is_empty() {
+            return;
+        }
+        self.tokens += 1;
+        self.tail.push_str(piece);
+        let chars = self.tail.chars().count();
+        if chars > TAIL_CHARS {
+            self.tail = self.tail.chars().skip(chars - TAIL_CHARS).collect();
+        }
+        let tail: String = self
+            .tail
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+        self.bar.set_message(format!("{} tokens  …{tail}", self.tokens));
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -1,3 +1,25 @@
-fn main() {
-    todo!();
+use std::io::IsTerminal;
+
+use clap::Parser;
+use mabel::{ci, cli::Cli, commands};
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    // Logs go to stderr so stdout stays usable for note paths and search results.
+    let filter = EnvFilter::try_from_env("MABEL_LOG").unwrap_or_else(|_| EnvFilter::new("mabel=info"));
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal() && !cli.global.ci)
+                .with_filter(filter),
+        )
+        .with(ci::Collector.with_filter(LevelFilter::WARN))
+        .init();
+
+    commands::run(&cli).await?;
+    Ok(())
 }
@@ -0,0 +1,106 @@
+//! How much of a PDF is held in memory at once, for small machines such as a Raspberry Pi serving
+//! the vault, where a 300 MB scan of a proceedings volume would not fit.
+//!
+//! PDFs are downloaded into the cache and sent to GROBID a [`CHUNK`] at a time, so those steps take
+//! the same memory whatever the PDF's size. What needs the PDF in memory keeps under the ceiling
+//! set with `MABEL_MAX_MEMORY`: reading a PDF's page tree and declared metadata ([`crate::pdf`])
+//! makes do with its start and end, where producers put those, and gives up on a compressed stream
+//! that decodes past a fixed size; the built-in extractor ([`crate::extract::pdftext`]) turns down
+//! a PDF it would need more for.
+
+use std::path::Path;
+
+use futures::Stream;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+
+use crate::{MabelError, Result};
+
+/// Size of the pieces a PDF is read and sent in.
+pub const CHUNK: usize = 64 * 1024;
+
+/// The built-in extractor's peak memory as a multiple of the PDF's size: the file, its parsed
+/// objects and the decoded page contents.
+const EXTRACTOR_OVERHEAD: u64 = 4;
+
+/// Parse a memory ceiling such as `512M` or `2G`.
+pub fn parse_ceiling(raw: &str) -> Result<u64> {
+    crate::http::parse_bytes(raw).ok_or_else(|| {
+        MabelError::Config {
+            msg: format!("invalid memory ceiling {raw:?}: expected bytes, e.g. 512M or 2G"),
+        }
+    })
+}
+
+/// Whether the built-in extractor can read a PDF of `len` bytes within `ceiling`.
+pub fn extractor_fits(len: u64, ceiling: Option<u64>) -> bool {
+    ceiling.is_none_or(|ceiling| len.saturating_mul(EXTRACTOR_OVERHEAD) <= ceiling)
+}
+
+/// The size of the file at `path`.
+pub async fn file_len(path: &Path) -> Result<u64> {
+    tokio::fs::metadata(path).await.map(|m| m.len()).map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })
+}
+
+/// The first `len` bytes of the file at `path`, or all of a shorter one.
+pub async fn read_head(path: &Path, len: u64) -> Result<Vec<u8>> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let file = tokio::fs::File::open(path).await.map_err(io_err)?;
+    let mut head = Vec::new();
+    file.take(len).read_to_end(&mut head).await.map_err(io_err)?;
+    Ok(head)
+}
+
+/// The file at `path`, or when it is larger than `ceiling`, its first and last `ceiling / 2`
+/// bytes back to back.
+pub async fn read_within(path: &Path, ceiling: Option<u64>) -> Result<Vec<u8>> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    let len = file_len(path).await?;
+    let half = match ceiling {
+        | Some(ceiling) if len > ceiling => ceiling / 2,
+        | _ => return tokio::fs::read(path).await.map_err(io_err),
+    };
+    tracing::debug!(path = %path.display(), len, "reading only the start and end of a large PDF");
+    let mut file = tokio::fs::File::open(path).await.map_err(io_err)?;
+    let mut head = Vec::new();
+    (&mut file).take(half).read_to_end(&mut head).await.map_err(io_err)?;
+    file.seek(SeekFrom::Start(len - half)).await.map_err(io_err)?;
+    let mut tail = Vec::new();
+    file.take(half).read_to_end(&mut tail).await.map_err(io_err)?;
+    head.extend(tail);
+    Ok(head)
+}
+
+/// The file at `path` a [`CHUNK`] at a time, for a request body, with its size.
+pub async fn chunks(path: &Path) -> Result<(impl Stream<Item = std::io::Result<Vec<u8>>>, u64)> {
+    let len = file_len(path).await?;
+    let file = tokio::fs::File::open(path).await.map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    let stream = futures::stream::try_unfold(file, |mut file| {
+        async move {
+            let mut chunk = vec![0; CHUNK];
+            let read = file.read(&mut chunk).await?;
+            chunk.truncate(read);
+            Ok((read > 0).then_some((chunk, file)))
+        }
+    });
+    Ok((stream, len))
+}
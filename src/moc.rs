@@ -0,0 +1,149 @@
+//! Maps of content (MOCs) for research areas (`MABEL_MOCS`). A paper from arXiv belongs in the MOC
+//! of its primary category. When the vault has no note by that name yet, one is created in `MOCs/`
+//! from the MOC template, whose Dataview query lists every paper tagged with the category, so the
+//! first paper from a new area sets up its index. Paper notes link to their MOC and carry its tag.
+//!
+//! A MOC that exists is never touched again: it is the user's to rename, move or rewrite, and is
+//! found anywhere in the vault by its title or file name.
+
+use std::path::PathBuf;
+
+pub use mabel_core::note::Moc;
+
+use crate::{
+    config::Config,
+    note,
+    paper::PaperMetadata,
+    render::{MocNote, Renderer},
+    vault::{self, VaultNote},
+    MabelError, Result,
+};
+
+/// Names of the arXiv categories, from the arXiv category taxonomy. Aliases (`cs.NA` for
+/// `math.NA`, `eess.SY` for `cs.SY`, ...) are left out so no two categories share a MOC name;
+/// categories not listed go by their code.
+const CATEGORY_NAMES: &[(&str, &str)] = &[
+    ("cs.AI", "Artificial Intelligence"),
+    ("cs.AR", "Hardware Architecture"),
+    ("cs.CC", "Computational Complexity"),
+    ("cs.CE", "Computational Engineering, Finance, and Science"),
+    ("cs.CG", "Computational Geometry"),
+    ("cs.CL", "Computation and Language"),
+    ("cs.CR", "Cryptography and Security"),
+    ("cs.CV", "Computer Vision and Pattern Recognition"),
+    ("cs.CY", "Computers and Society"),
+    ("cs.DB", "Databases"),
+    ("cs.DC", "Distributed, Parallel, and Cluster Computing"),
+    ("cs.DL", "Digital Libraries"),
+    ("cs.DM", "Discrete Mathematics"),
+    ("cs.DS", "Data Structures and Algorithms"),
+    ("cs.ET", "Emerging Technologies"),
+    ("cs.FL", "Formal Languages and Automata Theory"),
+    ("cs.GR", "Graphics"),
+    ("cs.GT", "Computer Science and Game Theory"),
+    ("cs.HC", "Human-Computer Interaction"),
+    ("cs.IR", "Information Retrieval"),
+    ("cs.IT", "Information Theory"),
+    ("cs.LG", "Machine Learning"),
+    ("cs.LO", "Logic in Computer Science"),
+    ("cs.MA", "Multiagent Systems"),
+    ("cs.MM", "Multimedia"),
+    ("cs.MS", "Mathematical Software"),
+    ("cs.NE", "Neural and Evolutionary Computing"),
+    ("cs.NI", "Networking and Internet Architecture"),
+    ("cs.OS", "Operating Systems"),
+    ("cs.PF", "Performance"),
+    ("cs.PL", "Programming Languages"),
+    ("cs.RO", "Robotics"),
+    ("cs.SC", "Symbolic Computation"),
+    ("cs.SD", "Sound"),
+    ("cs.SE", "Software Engineering"),
+    ("cs.SI", "Social and Information Networks"),
+    ("cs.SY", "Systems and Control"),
+    ("stat.AP", "Applied Statistics"),
+    ("stat.CO", "Statistical Computation"),
+    ("stat.ME", "Statistical Methodology"),
+    ("stat.ML", "Machine Learning (Statistics)"),
+    ("stat.TH", "Statistics Theory"),
+    ("eess.AS", "Audio and Speech Processing"),
+    ("eess.IV", "Image and Video Processing"),
+    ("eess.SP", "Signal Processing"),
+    ("math.CO", "Combinatorics"),
+    ("math.DS", "Dynamical Systems"),
+    ("math.LO", "Mathematical Logic"),
+    ("math.NA", "Numerical Analysis"),
+    ("math.OC", "Optimization and Control"),
+    ("math.PR", "Probability"),
+    ("q-bio.BM", "Biomolecules"),
+    ("q-bio.GN", "Genomics"),
+    ("q-bio.NC", "Neurons and Cognition"),
+    ("q-bio.QM", "Quantitative Methods"),
+    ("q-fin.CP", "Computational Finance"),
+    ("q-fin.ST", "Statistical Finance"),
+    ("econ.EM", "Econometrics"),
+    ("quant-ph", "Quantum Physics"),
+    ("gr-qc", "General Relativity and Quantum Cosmology"),
+    ("hep-th", "High Energy Physics - Theory"),
+    ("hep-ph", "High Energy Physics - Phenomenology"),
+    ("astro-ph.CO", "Cosmology and Nongalactic Astrophysics"),
+    ("cond-mat.dis-nn", "Disordered Systems and Neural Networks"),
+    ("cond-mat.stat-mech", "Statistical Mechanics"),
+    ("physics.comp-ph", "Computational Physics"),
+];
+
+/// A paper's MOC, and where to create it when the vault does not have it yet.
+pub struct Planned {
+    pub moc: Moc,
+    /// `None` when the vault already has the MOC
+    pub create: Option<PathBuf>,
+}
+
+/// The MOC of the paper's primary arXiv category (the first of its keywords): the note by that
+/// name anywhere in `notes`, or a new one in the MOC folder. Papers not from arXiv have none.
+pub fn plan(cfg: &Config, notes: &[VaultNote], metadata: &PaperMetadata) -> Option<Planned> {
+    metadata.arxiv_id.as_ref()?;
+    let category = metadata.keywords.first().filter(|c| is_category(c))?;
+    let name = CATEGORY_NAMES
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(category))
+        .map_or(category.as_str(), |(_, name)| name);
+    let mut moc = Moc::new(category, name);
+    let file = sanitize_filename::sanitize(format!("{}.md", moc.link));
+    let create = match vault::find_topic(notes, &moc.link) {
+        | Some(existing) => {
+            moc.link.clone_from(&existing.link);
+            None
+        }
+        | None => Some(cfg.mocs_dir().join(file)),
+    };
+    Some(Planned { moc, create })
+}
+
+/// Create the planned MOC note, unless the vault has it already.
+pub async fn create(cfg: &Config, renderer: &Renderer, planned: &Planned) -> Result<()> {
+    let Some(path) = &planned.create else {
+        return Ok(());
+    };
+    let text = renderer.render_moc(&MocNote {
+        moc: &planned.moc,
+        created: cfg.timezone.timestamp(chrono::Utc::now()),
+    })?;
+    match note::write(path, &text, false).await {
+        // Another paper of the same area got there first.
+        | Err(MabelError::NoteExists { .. }) => Ok(()),
+        | Err(e) => Err(e),
+        | Ok(()) => {
+            tracing::info!(path = %path.display(), category = planned.moc.category, "created MOC");
+            Ok(())
+        }
+    }
+}
+
+/// Whether `keyword` reads as an arXiv category, e.g. `cs.LG`, `hep-th` or `cond-mat.stat-mech`.
+fn is_category(keyword: &str) -> bool {
+    let (archive, subject) = keyword.split_once('.').unwrap_or((keyword, "x"));
+    !archive.is_empty()
+        && archive.bytes().all(|b| b.is_ascii_lowercase() || b == b'-')
+        && !subject.is_empty()
+        && subject.bytes().all(|b| b.is_ascii_alphabetic() || b == b'-')
+}
@@ -0,0 +1,185 @@
+//! Writing rendered notes into the vault.
+
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use mabel_core::diff;
+
+use crate::{config::Config, region::RegionMarkers, MabelError, Result};
+
+/// Longest file stem we produce; long titles are cut at a word boundary.
+const MAX_STEM_CHARS: usize = 120;
+
+/// What writing a note does to one that is already there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Leave it, and fail with [`MabelError::NoteExists`]
+    #[default]
+    Refuse,
+    /// Regenerate its managed regions and frontmatter, keeping the text, frontmatter fields and
+    /// tags the user added (`--overwrite`; see [`RegionMarkers::merge`])
+    Merge,
+    /// Replace it with the new note (`--force-overwrite`)
+    Force,
+}
+
+impl Overwrite {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            | Self::Refuse => "no",
+            | Self::Merge => "merge",
+            | Self::Force => "force",
+        }
+    }
+}
+
+/// Vault path for a note titled `title`.
+pub fn note_path(cfg: &Config, title: &str) -> PathBuf {
+    cfg.vault_notes_dir().join(format!("{}.md", file_stem(title)))
+}
+
+/// A filename-safe version of `title` that still reads like the title in Obsidian's file list.
+pub fn file_stem(title: &str) -> String {
+    // Obsidian treats these as link syntax even where the filesystem allows them.
+    let cleaned: String = title
+        .chars()
+        .map(|c| {
+            if matches!(c, '[' | ']' | '#' | '^' | '|') {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect();
+    let cleaned = sanitize_filename::sanitize(crate::xml::collapse_whitespace(&cleaned));
+    let stem = cleaned.trim().trim_end_matches('.');
+    if stem.is_empty() {
+        return "Untitled".to_string();
+    }
+    if stem.chars().count() <= MAX_STEM_CHARS {
+        return stem.to_string();
+    }
+    let cut: String = stem.chars().take(MAX_STEM_CHARS).collect();
+    match cut.rfind(' ') {
+        | Some(i) if i > MAX_STEM_CHARS / 2 => cut[..i].trim_end().to_string(),
+        | _ => cut,
+    }
+}
+
+/// Write `contents` to `path`, refusing to replace an existing note unless `overwrite` is set.
+pub async fn write(path: &Path, contents: &str, overwrite: bool) -> Result<()> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    if !overwrite && tokio::fs::try_exists(path).await.map_err(io_err)? {
+        return Err(MabelError::NoteExists {
+            path: path.to_path_buf(),
+        });
+    }
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+    }
+    tokio::fs::write(path, contents).await.map_err(io_err)
+}
+
+/// Show how writing `contents` with [`write_managed`] as `overwrite` says would change the note at
+/// `path` and ask whether to go ahead; `yes` answers for the user, and without it there must be someone to ask
+/// (see [`crate::config::Config::can_ask`]). A note that does not exist yet or would not change
+/// needs no answer.
+pub async fn confirm_write(
+    path: &Path,
+    contents: &str,
+    overwrite: Overwrite,
+    markers: &RegionMarkers,
+    yes: bool,
+    can_ask: bool,
+) -> Result<bool> {
+    let Ok(existing) = tokio::fs::read_to_string(path).await else {
+        return Ok(true);
+    };
+    let updated = match overwrite {
+        | Overwrite::Merge => merged(path, &existing, contents, markers)?,
+        | Overwrite::Refuse | Overwrite::Force => contents.to_string(),
+    };
+    let shown = path.display().to_string();
+    let changes = diff::unified(&existing, &updated, &shown);
+    if changes.is_empty() {
+        return Ok(true);
+    }
+    if std::io::stderr().is_terminal() {
+        eprint!("{}", diff::colorize(&changes));
+    } else {
+        eprint!("{changes}");
+    }
+    if yes {
+        return Ok(true);
+    }
+    if !can_ask {
+        return Err(MabelError::Config {
+            msg: "--preview-diff needs a terminal, and no --ci, to ask for confirmation; pass --yes to write anyway"
+                .to_string(),
+        });
+    }
+    Ok(ask(&format!("Write these changes to {shown}?")).await)
+}
+
+/// Ask a yes/no question on the terminal; anything but yes is no.
+pub async fn ask(question: &str) -> bool {
+    let answer = ask_line(&format!("{question} [y/N]")).await;
+    matches!(answer.to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Ask a question on the terminal and return the answer, trimmed; empty when there was none.
+/// Questions from notes processed at the same time are asked one after another.
+pub async fn ask_line(question: &str) -> String {
+    static ASKING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _asking = ASKING.lock().await;
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "{question} ");
+    let _ = stderr.flush();
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await
+    .ok()
+    .and_then(std::result::Result::ok)
+    .unwrap_or_default();
+    answer.trim().to_string()
+}
+
+/// Write a generated note, doing with an existing one what `overwrite` says.
+pub async fn write_managed(path: &Path, contents: &str, overwrite: Overwrite, markers: &RegionMarkers) -> Result<()> {
+    match overwrite {
+        | Overwrite::Refuse => write(path, contents, false).await,
+        | Overwrite::Force => write(path, contents, true).await,
+        | Overwrite::Merge => {
+            let Ok(existing) = tokio::fs::read_to_string(path).await else {
+                return write(path, contents, true).await;
+            };
+            let updated = merged(path, &existing, contents, markers)?;
+            tracing::info!(path = %path.display(), "updating managed regions of existing note");
+            write(path, &updated, true).await
+        }
+    }
+}
+
+/// The `existing` note at `path` updated with the regions and frontmatter of `contents`. A note
+/// without managed regions is one the user wrote, or took over from mabel, so it is not replaced
+/// unless `contents` has no regions either: then the template owns the whole note.
+fn merged(path: &Path, existing: &str, contents: &str, markers: &RegionMarkers) -> Result<String> {
+    match markers.merge(existing, contents) {
+        | Some(updated) => Ok(updated),
+        | None if markers.names(contents).is_empty() => Ok(contents.to_string()),
+        | None => {
+            Err(MabelError::NoteUnmanaged {
+                path: path.to_path_buf(),
+            })
+        }
+    }
+}
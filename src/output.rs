@@ -0,0 +1,127 @@
+//! `--output json`: what `mabel note`, `batch` and `watch` print on stdout for scripts and CI jobs.
+//! Each paper gets one JSON record on a line of its own (JSON Lines) once it is done, written or
+//! not; logs, questions and the lines meant for people go to stderr, so stdout holds nothing but
+//! the records:
+//!
+//! ```text
+//! mabel batch --output json -f reading-list.txt | jq -r 'select(.status == "failed") | .input'
+//! ```
+
+use std::{fmt::Display, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    ci::Logged, cli::OutputFormat, paper::PaperMetadata, pipeline::NoteOutcome, timing::RunReport, MabelError,
+};
+
+/// How a paper went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Written,
+    /// A skeleton was written; its summary is still on its way
+    Pending,
+    /// The user said no, or would have been asked
+    Skipped,
+    Failed,
+}
+
+impl Status {
+    /// How a paper that ended in `e` went.
+    pub fn of(e: &MabelError) -> Self {
+        if is_skip(e) {
+            Self::Skipped
+        } else {
+            Self::Failed
+        }
+    }
+}
+
+/// One paper's record.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    /// The paper as it was given
+    pub input: &'a str,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<&'a PaperMetadata>,
+    /// Times, tokens and cost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<RunReport>,
+    /// The warnings and errors logged while the paper was processed
+    pub warnings: Vec<String>,
+}
+
+impl<'a> Record<'a> {
+    /// The record of a note written from `input`.
+    pub fn written(input: &'a str, outcome: &'a NoteOutcome, logged: &[Logged]) -> Self {
+        let report = outcome.report.clone().unwrap_or_else(|| {
+            RunReport {
+                tokens_in: outcome.usage.prompt_tokens,
+                tokens_out: outcome.usage.completion_tokens,
+                cost: outcome.cost,
+                ..RunReport::default()
+            }
+        });
+        Self {
+            input,
+            status: if outcome.pending {
+                Status::Pending
+            } else {
+                Status::Written
+            },
+            error: None,
+            note: Some(&outcome.path),
+            metadata: Some(&outcome.metadata),
+            report: Some(report),
+            warnings: warnings(logged),
+        }
+    }
+
+    /// The record of a paper that was not written, or was but failed on what it logged.
+    pub fn failed(input: &'a str, status: Status, error: impl Display, logged: &[Logged]) -> Self {
+        Self {
+            input,
+            status,
+            error: Some(error.to_string()),
+            note: None,
+            metadata: None,
+            report: None,
+            warnings: warnings(logged),
+        }
+    }
+
+    /// Print the record as a line of stdout.
+    pub fn print(&self) {
+        match serde_json::to_string(self) {
+            | Ok(json) => println!("{json}"),
+            | Err(e) => tracing::error!(input = self.input, error = %e, "could not write the JSON record"),
+        }
+    }
+}
+
+/// A note the user declined to overwrite under `--preview-diff`, a paper too long to summarize
+/// without asking, or a PDF of a paper already processed.
+pub fn is_skip(e: &MabelError) -> bool {
+    matches!(
+        e.root(),
+        MabelError::NoteDeclined { .. } | MabelError::TooManyPages { .. } | MabelError::Duplicate { .. }
+    )
+}
+
+fn warnings(logged: &[Logged]) -> Vec<String> {
+    logged.iter().map(ToString::to_string).collect()
+}
+
+/// Print a line meant for people: on stdout, or on stderr when stdout is for JSON records.
+pub fn say(format: OutputFormat, line: impl Display) {
+    match format {
+        | OutputFormat::Text => println!("{line}"),
+        | OutputFormat::Json => eprintln!("{line}"),
+    }
+}
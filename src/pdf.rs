@@ -0,0 +1,487 @@
+//! What a PDF says about itself before any text is extracted: how many pages it has and how large
+//! they are, read from its page tree. This takes milliseconds, so a 300-page thesis is noticed
+//! before it is sent to GROBID or a model.
+//!
+//! Only as much of the format is understood as the page tree needs: objects, including those in
+//! compressed object streams, and the `/Pages` and `/Page` dictionaries. Encrypted PDFs, and any
+//! that cannot be read this way, have no [`PdfInfo`]. The title and authors a PDF declares come
+//! from its XMP packet or its document information dictionary; see [`declared`].
+
+use std::{collections::HashMap, io::Read};
+
+use flate2::read::ZlibDecoder;
+
+use crate::{paper::PdfInfo, xml};
+
+/// Points per millimetre.
+const PT_PER_MM: f32 = 72.0 / 25.4;
+
+/// How far into a file its `%PDF-` header may be.
+pub const HEADER_WINDOW: usize = 1024;
+
+/// How deep a page tree is followed to its first page.
+const MAX_DEPTH: usize = 32;
+
+/// The most a compressed stream is decoded to, in bytes. Object streams holding a page tree
+/// are far smaller; a stream that inflates past this is a compression bomb, not a PDF to read
+/// within `MABEL_MAX_MEMORY`.
+const MAX_STREAM: u64 = 16 * 1024 * 1024;
+
+/// The page count and first-page size of `pdf`, when it has a readable page tree.
+pub fn inspect(pdf: &[u8]) -> Option<PdfInfo> {
+    if !is_pdf(pdf) || find(pdf, b"/Encrypt").is_some() {
+        return None;
+    }
+    let objects = objects(pdf);
+    let root = objects
+        .values()
+        .find(|o| type_is(o, b"Catalog"))
+        .and_then(|catalog| reference(catalog, b"/Pages"))
+        .and_then(|id| objects.get(&id))
+        .filter(|o| type_is(o, b"Pages"))
+        // Without a catalog, the root is the page tree node with the most pages under it.
+        .or_else(|| {
+            objects
+                .values()
+                .filter(|o| type_is(o, b"Pages"))
+                .max_by_key(|o| integer(o, b"/Count").unwrap_or(0))
+        })?;
+    let pages = u32::try_from(integer(root, b"/Count")?).ok().filter(|&n| n > 0)?;
+    let (width, height) = first_page_size(&objects, root)?;
+    Some(PdfInfo::new(pages, width / PT_PER_MM, height / PT_PER_MM))
+}
+
+/// Whether `pdf` starts like a PDF: its `%PDF-` header is within the first kilobyte.
+pub fn is_pdf(pdf: &[u8]) -> bool {
+    pdf[..pdf.len().min(HEADER_WINDOW)].windows(5).any(|w| w == b"%PDF-")
+}
+
+/// What a PDF says about the paper in it. Many PDFs say nothing, or only name the file they were
+/// made from, which is not taken for a title.
+#[derive(Clone, Debug, Default)]
+pub struct Declared {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub doi: Option<String>,
+}
+
+/// The metadata `pdf` declares: from its XMP packet where it has an uncompressed one, as most
+/// producers write it, with gaps filled from the document information dictionary.
+pub fn declared(pdf: &[u8]) -> Declared {
+    let mut declared = xmp(pdf).unwrap_or_default();
+    if find(pdf, b"/Encrypt").is_none() {
+        let objects = objects(pdf);
+        let info = rfind(pdf, b"/Info")
+            .and_then(|at| leading_reference(pdf[at + b"/Info".len()..].trim_ascii_start()))
+            .and_then(|id| objects.get(&id));
+        if let Some(info) = info {
+            let field = |key: &[u8]| string(info, key).filter(|s| !s.is_empty());
+            declared.title = declared.title.or_else(|| field(b"/Title"));
+            if declared.authors.is_empty() {
+                declared.authors = field(b"/Author").map(|a| split_authors(&a)).unwrap_or_default();
+            }
+            declared.description = declared.description.or_else(|| field(b"/Subject"));
+            if declared.keywords.is_empty() {
+                declared.keywords = field(b"/Keywords")
+                    .map(|k| {
+                        k.split([',', ';'])
+                            .map(str::trim)
+                            .filter(|k| !k.is_empty())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+            }
+        }
+    }
+    declared.title = declared.title.filter(|t| !is_file_name(t));
+    declared
+}
+
+/// The fields of the XMP packet: Dublin Core's title, creators, description and subjects, and
+/// PRISM's DOI.
+fn xmp(pdf: &[u8]) -> Option<Declared> {
+    let start = find(pdf, b"<x:xmpmeta")?;
+    let end = start + find(&pdf[start..], b"</x:xmpmeta>")? + b"</x:xmpmeta>".len();
+    let packet = xml::parse(&String::from_utf8_lossy(&pdf[start..end]), "XMP metadata").ok()?;
+    let items = |name: &str| -> Vec<String> {
+        packet
+            .find(name)
+            .map(|e| {
+                e.find_all("li")
+                    .into_iter()
+                    .map(xml::Element::text)
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let doi = packet.find_text("doi").or_else(|| {
+        items("identifier")
+            .into_iter()
+            .find_map(|id| id.strip_prefix("doi:").map(str::to_string))
+    });
+    Some(Declared {
+        title: items("title").into_iter().next(),
+        authors: items("creator"),
+        description: items("description").into_iter().next(),
+        keywords: items("subject"),
+        doi,
+    })
+}
+
+/// "A. Smith and B. Jones", "A. Smith; B. Jones" or "A. Smith, B. Jones". A comma only separates
+/// authors when each side has a space in it, so "Smith, Anna" stays one author.
+fn split_authors(authors: &str) -> Vec<String> {
+    let parts: Vec<&str> = authors.split(';').flat_map(|a| a.split(" and ")).collect();
+    parts
+        .into_iter()
+        .flat_map(|a| {
+            let by_comma: Vec<&str> = a.split(',').map(str::trim).collect();
+            if by_comma.len() > 1 && by_comma.iter().all(|p| p.contains(' ')) {
+                by_comma
+            } else {
+                vec![a.trim()]
+            }
+        })
+        .filter(|a| !a.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Titles that are the name of the file the PDF was made from ("Microsoft Word - draft.docx",
+/// "paper.dvi").
+fn is_file_name(title: &str) -> bool {
+    let lower = title.trim().to_ascii_lowercase();
+    lower.starts_with("microsoft word - ")
+        || [".doc", ".docx", ".dvi", ".tex", ".pdf", ".odt", ".rtf"]
+            .iter()
+            .any(|ext| lower.ends_with(ext))
+}
+
+/// A text string value, e.g. `/Title (A Paper)` or `/Title <FEFF0041>`: literal strings with their
+/// escapes undone, hex strings decoded, and UTF-16 (with its byte order mark) or `PDFDocEncoding`,
+/// read as Latin-1, turned into text.
+fn string(body: &[u8], key: &[u8]) -> Option<String> {
+    let v = value(body, key)?;
+    let bytes = if let Some(hex) = v.strip_prefix(b"<") {
+        let digits: Vec<u8> = hex
+            .iter()
+            .take_while(|&&b| b != b'>')
+            .filter(|b| b.is_ascii_hexdigit())
+            .copied()
+            .collect();
+        // An odd last digit is followed by an implied 0.
+        digits
+            .chunks(2)
+            .filter_map(|pair| {
+                let pair = [pair[0], pair.get(1).copied().unwrap_or(b'0')];
+                u8::from_str_radix(std::str::from_utf8(&pair).ok()?, 16).ok()
+            })
+            .collect()
+    } else {
+        literal(v.strip_prefix(b"(")?)
+    };
+    let text = match bytes.strip_prefix(&[0xfe, 0xff]) {
+        | Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|p| u16::from_be_bytes([p[0], p[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        | None => bytes.iter().map(|&b| char::from(b)).collect(),
+    };
+    Some(xml::collapse_whitespace(&text))
+}
+
+/// The bytes of a literal string, given what follows its opening parenthesis.
+fn literal(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut level = 1;
+    let mut bytes = raw.iter().copied().peekable();
+    while let Some(b) = bytes.next() {
+        match b {
+            | b'(' => level += 1,
+            | b')' => {
+                level -= 1;
+                if level == 0 {
+                    break;
+                }
+            }
+            | b'\\' => {
+                let Some(escaped) = bytes.next() else {
+                    break;
+                };
+                match escaped {
+                    | b'n' => out.push(b'\n'),
+                    | b'r' => out.push(b'\r'),
+                    | b't' => out.push(b'\t'),
+                    | b'b' | b'f' | b'\n' | b'\r' => {}
+                    | b'0'..=b'7' => {
+                        let mut code = u32::from(escaped - b'0');
+                        for _ in 0..2 {
+                            match bytes.peek() {
+                                | Some(&d @ b'0'..=b'7') => {
+                                    code = code * 8 + u32::from(d - b'0');
+                                    bytes.next();
+                                }
+                                | _ => break,
+                            }
+                        }
+                        out.push(u8::try_from(code & 0xff).unwrap_or_default());
+                    }
+                    | other => out.push(other),
+                }
+                continue;
+            }
+            | _ => {}
+        }
+        out.push(b);
+    }
+    out
+}
+
+/// Width and height in points of the first page under `node`, turned as it is displayed.
+/// `/MediaBox` and `/Rotate` are inherited from the nodes above a page.
+fn first_page_size<'a>(objects: &'a HashMap<u32, Vec<u8>>, mut node: &'a [u8]) -> Option<(f32, f32)> {
+    let mut media_box = None;
+    let mut rotate = 0;
+    for _ in 0..MAX_DEPTH {
+        media_box = rectangle(node, b"/MediaBox").or(media_box);
+        rotate = integer(node, b"/Rotate").unwrap_or(rotate);
+        if !type_is(node, b"Pages") {
+            let [x0, y0, x1, y1] = media_box?;
+            let (width, height) = ((x1 - x0).abs(), (y1 - y0).abs());
+            return Some(if rotate.rem_euclid(180) == 90 {
+                (height, width)
+            } else {
+                (width, height)
+            });
+        }
+        let kids = value(node, b"/Kids")?;
+        let first = kids.strip_prefix(b"[")?;
+        node = objects.get(&leading_reference(first)?)?;
+    }
+    None
+}
+
+/// The dictionary of every object of the PDF by number, those in object streams included. Objects
+/// redefined by a later incremental update replace the earlier ones.
+fn objects(pdf: &[u8]) -> HashMap<u32, Vec<u8>> {
+    let mut objects = HashMap::new();
+    let mut streams = Vec::new();
+    let mut at = 0;
+    while let Some(offset) = find(&pdf[at..], b" obj") {
+        let end = at + offset;
+        at = end + 4;
+        let Some(id) = object_number(&pdf[..end]) else {
+            continue;
+        };
+        let body = &pdf[at..];
+        let body = &body[..find(body, b"endobj").unwrap_or(body.len())];
+        if type_is(body, b"ObjStm") {
+            streams.push(body);
+        }
+        if let Some(dict) = top_dict(body) {
+            objects.insert(id, dict.to_vec());
+        }
+    }
+    for stream in streams {
+        let packed = object_stream(stream).unwrap_or_default();
+        objects.extend(
+            packed
+                .into_iter()
+                .filter_map(|(id, body)| Some((id, top_dict(&body)?.to_vec()))),
+        );
+    }
+    objects
+}
+
+/// The objects packed into a compressed object stream (`/Type /ObjStm`).
+fn object_stream(body: &[u8]) -> Option<Vec<(u32, Vec<u8>)>> {
+    let count = usize::try_from(integer(body, b"/N")?).ok()?;
+    let first = usize::try_from(integer(body, b"/First")?).ok()?;
+    let data = stream_data(body)?;
+    let header = std::str::from_utf8(data.get(..first)?).ok()?;
+    let pairs: Vec<usize> = header.split_ascii_whitespace().filter_map(|n| n.parse().ok()).collect();
+    let entries: Vec<(u32, usize)> = pairs
+        .chunks_exact(2)
+        .take(count)
+        .map(|pair| (u32::try_from(pair[0]).unwrap_or(0), first + pair[1]))
+        .collect();
+    let mut out = Vec::with_capacity(entries.len());
+    for (i, &(id, start)) in entries.iter().enumerate() {
+        let end = entries.get(i + 1).map_or(data.len(), |&(_, next)| next);
+        out.push((id, data.get(start..end.max(start))?.to_vec()));
+    }
+    Some(out)
+}
+
+/// The decoded data of a stream object; only Flate-compressed and uncompressed streams are read,
+/// and none that decodes to more than [`MAX_STREAM`] bytes.
+fn stream_data(body: &[u8]) -> Option<Vec<u8>> {
+    let start = find(body, b"stream")? + b"stream".len();
+    let raw = &body[start..];
+    let raw = raw
+        .strip_prefix(b"\r\n")
+        .or_else(|| raw.strip_prefix(b"\n"))
+        .unwrap_or(raw);
+    let raw = &raw[..find(raw, b"endstream").unwrap_or(raw.len())];
+    let filter = value(&body[..start], b"/Filter").map(|v| {
+        let v = v.strip_prefix(b"[").map_or(v, <[u8]>::trim_ascii_start);
+        let end = v
+            .iter()
+            .skip(1)
+            .position(|&b| is_delimiter(b))
+            .map_or(v.len(), |i| i + 1);
+        &v[..end]
+    });
+    match filter {
+        | None => Some(raw.to_vec()),
+        | Some(b"/FlateDecode") => {
+            let mut out = Vec::new();
+            // A stream cut short still holds the objects before the cut.
+            let _ = ZlibDecoder::new(raw).take(MAX_STREAM + 1).read_to_end(&mut out);
+            if out.len() as u64 > MAX_STREAM {
+                tracing::debug!(
+                    limit = MAX_STREAM,
+                    "skipped a PDF stream that decodes to more than the limit"
+                );
+                return None;
+            }
+            (!out.is_empty()).then_some(out)
+        }
+        | Some(_) => None,
+    }
+}
+
+/// The object number of the `N G obj` header that ends `before`.
+fn object_number(before: &[u8]) -> Option<u32> {
+    let text = String::from_utf8_lossy(&before[before.len().saturating_sub(24)..]);
+    let mut words = text.rsplit(|c: char| c.is_ascii_whitespace());
+    let generation = words.next()?;
+    let id = words.next()?;
+    (!generation.is_empty() && generation.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| id.parse().ok())
+        .flatten()
+}
+
+/// Whether the dictionary has `/Type /<kind>`, and not a longer name starting with it.
+fn type_is(dict: &[u8], kind: &[u8]) -> bool {
+    value(dict, b"/Type").is_some_and(|v| {
+        v.strip_prefix(b"/")
+            .and_then(|name| name.strip_prefix(kind))
+            .is_some_and(|rest| rest.first().is_none_or(|&b| is_delimiter(b)))
+    })
+}
+
+/// What follows `key` in the top-level dictionary of `body`, up to its end.
+fn value<'a>(body: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let dict = top_dict(body)?;
+    let mut at = 0;
+    while let Some(offset) = find(&dict[at..], key) {
+        let pos = at + offset;
+        let after = pos + key.len();
+        at = after;
+        if dict.get(after).is_some_and(|&b| !is_delimiter(b)) || depth(&dict[..pos]) != 1 {
+            continue;
+        }
+        return Some(dict[after..].trim_ascii_start());
+    }
+    None
+}
+
+/// The `<< ... >>` an object starts with, if it is a dictionary or a stream.
+fn top_dict(body: &[u8]) -> Option<&[u8]> {
+    let start = body.len() - body.trim_ascii_start().len();
+    if !body[start..].starts_with(b"<<") {
+        return None;
+    }
+    let mut level = 0;
+    let mut i = start;
+    while i + 1 < body.len() {
+        match &body[i..i + 2] {
+            | b"<<" => {
+                level += 1;
+                i += 2;
+            }
+            | b">>" => {
+                level -= 1;
+                i += 2;
+                if level == 0 {
+                    return Some(&body[start..i]);
+                }
+            }
+            | _ => i += 1,
+        }
+    }
+    Some(&body[start..])
+}
+
+/// How many dictionaries are open at the end of `prefix`.
+fn depth(prefix: &[u8]) -> i32 {
+    let mut level = 0;
+    let mut i = 0;
+    while i + 1 < prefix.len() {
+        match &prefix[i..i + 2] {
+            | b"<<" => {
+                level += 1;
+                i += 2;
+            }
+            | b">>" => {
+                level -= 1;
+                i += 2;
+            }
+            | _ => i += 1,
+        }
+    }
+    level
+}
+
+/// An integer value, e.g. `/Count 12`.
+fn integer(body: &[u8], key: &[u8]) -> Option<i64> {
+    let v = value(body, key)?;
+    let end = v
+        .iter()
+        .position(|&b| !(b.is_ascii_digit() || b"+-".contains(&b)))
+        .unwrap_or(v.len());
+    std::str::from_utf8(&v[..end]).ok()?.parse().ok()
+}
+
+/// A rectangle value, e.g. `/MediaBox [0 0 612 792]`.
+fn rectangle(body: &[u8], key: &[u8]) -> Option<[f32; 4]> {
+    let v = value(body, key)?.strip_prefix(b"[")?;
+    let inside = std::str::from_utf8(&v[..v.iter().position(|&b| b == b']')?]).ok()?;
+    let numbers: Vec<f32> = inside.split_ascii_whitespace().filter_map(|n| n.parse().ok()).collect();
+    numbers.try_into().ok()
+}
+
+/// An indirect reference value, e.g. `/Pages 3 0 R`.
+fn reference(body: &[u8], key: &[u8]) -> Option<u32> {
+    leading_reference(value(body, key)?)
+}
+
+/// The object number of the `N G R` reference `text` starts with.
+fn leading_reference(text: &[u8]) -> Option<u32> {
+    let text = String::from_utf8_lossy(&text[..text.len().min(32)]);
+    let mut words = text
+        .split(|c: char| c.is_ascii() && is_delimiter(c as u8))
+        .filter(|w| !w.is_empty());
+    let (id, _generation, r) = (words.next()?, words.next()?, words.next()?);
+    (r == "R").then(|| id.parse().ok()).flatten()
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"/<>[]()%".contains(&b)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
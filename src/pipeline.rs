@@ -0,0 +1,1161 @@
+//! The end-to-end flow behind `mabel note`: resolve the input, summarize it, render the note and
+//! write it into the vault.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use mabel_core::{fingerprint::Fingerprint, verify::Source};
+use reqwest::Client;
+use serde::Serialize;
+use tokio::{sync::OnceCell, task::JoinHandle};
+
+use crate::{
+    bibtex, chunk,
+    claims::{self, ClaimRecord},
+    concept,
+    config::{Config, LlmBackend, Mode},
+    cost,
+    error::{Stage, StageContext},
+    figures, flashcards, fulltext, http,
+    index::{Index, Indexed},
+    llm::{batch::BatchQueue, Llm, Usage},
+    moc::{self, Moc},
+    note::{self, Overwrite},
+    paper::{PaperMetadata, PaperStructure, LOW_CONFIDENCE},
+    prompt::{self, Prompts},
+    queue::{self, Queued},
+    registry::{Entry, Registry},
+    render::{self, BookNote, PaperNote, Renderer},
+    reproduction,
+    results::{self, ResultRecord},
+    schema::Schema,
+    skim,
+    source::{self, local, Input, ResolvedPaper},
+    summarize::{self, Summary},
+    thread,
+    timing::{self, RunReport},
+    vault::{self, RelatedNote, VaultNote},
+    webhook::{self, Payload},
+    MabelError, Result,
+};
+
+/// Most vault notes shown to the model as context for one paper.
+const MAX_RELATED_NOTES: usize = 8;
+
+/// Result of processing one input.
+#[derive(Clone, Debug, Serialize)]
+pub struct NoteOutcome {
+    pub path: PathBuf,
+    pub title: String,
+    pub usage: Usage,
+    /// Estimated cost in US dollars, when the model's price is known (see [`crate::cost`])
+    pub cost: Option<f64>,
+    /// The note so far is a skeleton; a background task is still writing its summary
+    pub pending: bool,
+    /// What the paper was resolved to
+    #[serde(skip)]
+    pub metadata: PaperMetadata,
+    /// Where the paper's time went; `None` when it was not timed, as for books and skeletons
+    #[serde(skip)]
+    pub report: Option<RunReport>,
+}
+
+/// What [`Pipeline::run_detached`] returns: the outcome so far and, while the summary is still
+/// being written, the task writing it.
+pub struct Detached {
+    pub outcome: NoteOutcome,
+    pub rest: Option<JoinHandle<Result<NoteOutcome>>>,
+}
+
+/// Long-lived state shared by every note produced in one run (or by the server).
+pub struct Pipeline {
+    cfg: Config,
+    http: Client,
+    llm: Llm,
+    renderer: Renderer,
+    prompts: Prompts,
+    schema: Option<Schema>,
+    /// Where summary prompts wait for an OpenAI batch, under `mabel batch --economy`
+    batch: Option<Arc<BatchQueue>>,
+    /// Set once the model is known to be on the Ollama server (see [`Pipeline::ensure_model`])
+    model_ready: OnceCell<()>,
+}
+
+impl Pipeline {
+    pub fn new(cfg: Config) -> Result<Self> {
+        let http = http::client(&cfg)?;
+        let llm = Llm::from_config(&cfg)?;
+        // The model loads while the first paper is resolved and extracted.
+        #[cfg(feature = "ollama")]
+        if matches!(cfg.llm, LlmBackend::Ollama { preload: true, .. }) {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let llm = llm.clone();
+                runtime.spawn(async move {
+                    if let Err(e) = llm.preload().await {
+                        tracing::warn!(error = %e, "could not preload the model");
+                    }
+                });
+            }
+        }
+        let renderer = render::from_config(&cfg)?;
+        let prompts = Prompts::from_config(&cfg)?;
+        let schema = cfg.frontmatter_schema.as_deref().map(Schema::load).transpose()?;
+        Ok(Self {
+            cfg,
+            http,
+            llm,
+            renderer,
+            prompts,
+            schema,
+            batch: None,
+            model_ready: OnceCell::new(),
+        })
+    }
+
+    /// Put the summary prompts off for the OpenAI Batch API, in `queue` (`mabel batch --economy`).
+    pub fn with_batch(mut self, queue: Arc<BatchQueue>) -> Result<Self> {
+        self.llm = self.llm.batched(queue.clone())?;
+        self.batch = Some(queue);
+        Ok(self)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.cfg
+    }
+
+    pub fn prompts(&self) -> &Prompts {
+        &self.prompts
+    }
+
+    pub fn llm(&self) -> &Llm {
+        &self.llm
+    }
+
+    /// The note already written for a paper, wherever the index says it is or else under its title.
+    pub fn existing_note(&self, metadata: &PaperMetadata) -> Option<PathBuf> {
+        self.registered_note(metadata).or_else(|| {
+            let path = note::note_path(&self.cfg, &metadata.title);
+            path.is_file().then_some(path)
+        })
+    }
+
+    /// Process one input (identifier or file) into a note.
+    pub async fn run(&self, input: &str) -> Result<NoteOutcome> {
+        let parsed = Input::parse(input)?;
+        tracing::info!(input, "processing");
+        match &parsed {
+            | Input::EpubFile(path) => self.run_book(path).await,
+            | _ => timing::collect(Box::pin(self.run_paper(input, &parsed))).await,
+        }
+    }
+
+    /// Like [`Pipeline::run`], but a paper that needs PDF extraction returns as soon as its note
+    /// skeleton is written when `MABEL_TIERED` is on; the summary is filled in by a background task.
+    pub async fn run_detached(self: Arc<Self>, input: &str) -> Result<Detached> {
+        let done = |outcome| Detached { outcome, rest: None };
+        let parsed = Input::parse(input)?;
+        if !self.cfg.tiered || matches!(parsed, Input::EpubFile(_)) || matches!(self.cfg.mode, Mode::Skim) {
+            return self.run(input).await.map(done);
+        }
+        tracing::info!(input, "processing");
+        let header = parsed
+            .resolve_header(&self.cfg, &self.http)
+            .await
+            .stage(Stage::Resolve, input)?;
+        if !parsed.needs_extraction(&self.cfg, &header) {
+            let path = self.checked_path(input, &header)?;
+            let paper = parsed.extract_full_text(&self.cfg, &self.http, header).await;
+            return timing::collect(Box::pin(self.finish_paper(input, paper, path, self.cfg.overwrite_note)))
+                .await
+                .map(done);
+        }
+        let path = self.write_skeleton(input, &header).await?;
+        let outcome = NoteOutcome {
+            path: path.clone(),
+            title: header.metadata.title.clone(),
+            usage: Usage::default(),
+            cost: None,
+            pending: true,
+            metadata: header.metadata.clone(),
+            report: None,
+        };
+        let input = input.to_string();
+        let rest = tokio::spawn(timing::collect(Box::pin(async move {
+            let paper = parsed.extract_full_text(&self.cfg, &self.http, header).await;
+            let result = self.finish_paper(&input, paper, path, Overwrite::Merge).await;
+            if let Err(e) = &result {
+                tracing::warn!(input, error = %e, "full-text pass failed; the note keeps its skeleton");
+            }
+            result
+        })));
+        Ok(Detached {
+            outcome,
+            rest: Some(rest),
+        })
+    }
+
+    async fn run_paper(&self, input: &str, parsed: &Input) -> Result<NoteOutcome> {
+        if matches!(self.cfg.mode, Mode::Skim) {
+            // A skim looks at page images, not at extracted text.
+            let paper = parsed
+                .resolve_header(&self.cfg, &self.http)
+                .await
+                .stage(Stage::Resolve, input)?;
+            let path = self.checked_path(input, &paper)?;
+            return self.finish_paper(input, paper, path, self.cfg.overwrite_note).await;
+        }
+        // A skeleton would replace the summary before the preview of the real one could be declined.
+        if self.cfg.tiered && !self.cfg.preview_diff {
+            let header = parsed
+                .resolve_header(&self.cfg, &self.http)
+                .await
+                .stage(Stage::Resolve, input)?;
+            if parsed.needs_extraction(&self.cfg, &header) {
+                // Tiered: the metadata is in the vault within seconds, the summary follows.
+                let path = self.write_skeleton(input, &header).await?;
+                let paper = parsed.extract_full_text(&self.cfg, &self.http, header).await;
+                return self.finish_paper(input, paper, path, Overwrite::Merge).await;
+            }
+            let path = self.checked_path(input, &header)?;
+            let paper = parsed.extract_full_text(&self.cfg, &self.http, header).await;
+            return self.finish_paper(input, paper, path, self.cfg.overwrite_note).await;
+        }
+        // Extraction runs alongside the metadata lookup, so the note path can only be checked
+        // once both are done.
+        let paper = parsed
+            .resolve(&self.cfg, &self.http)
+            .await
+            .stage(Stage::Resolve, input)?;
+        let path = self.checked_path(input, &paper)?;
+        self.finish_paper(input, paper, path, self.cfg.overwrite_note).await
+    }
+
+    /// The note path for `paper`, unless a note we may not replace is already there. Checked
+    /// before spending tokens on a note we would refuse to write.
+    fn checked_path(&self, input: &str, paper: &ResolvedPaper) -> Result<PathBuf> {
+        let path = self
+            .registered_note(&paper.metadata)
+            .unwrap_or_else(|| note::note_path(&self.cfg, &paper.metadata.title));
+        self.ensure_writable(&path).stage(Stage::Write, input)?;
+        Ok(path)
+    }
+
+    /// Write the note with metadata and abstract only; its summary region says the full summary is
+    /// on its way. Returns the note path.
+    async fn write_skeleton(&self, input: &str, header: &ResolvedPaper) -> Result<PathBuf> {
+        let path = self.checked_path(input, header)?;
+        let pending = Summary {
+            tldr: "Full-text summary in progress; this note is updated when it is done. If this stays, rerun \
+                   with `--overwrite`."
+                .to_string(),
+            summary: header.metadata.abstract_text.clone().unwrap_or_default(),
+            ..Summary::default()
+        };
+        let rendered = self.render_paper(header, &pending, &[], None, input, self.llm.model())?;
+        note::write_managed(&path, &rendered, self.cfg.overwrite_note, &self.cfg.region_markers)
+            .await
+            .stage_at(Stage::Write, input, &path)?;
+        tracing::info!(path = %path.display(), "note skeleton written");
+        Ok(path)
+    }
+
+    /// Summarize a resolved paper into the note at `path`, then run the optional extra passes.
+    async fn finish_paper(
+        &self,
+        input: &str,
+        mut paper: ResolvedPaper,
+        path: PathBuf,
+        overwrite: Overwrite,
+    ) -> Result<NoteOutcome> {
+        self.ensure_model().await.stage(Stage::Summarize, input)?;
+        let pages = match self.cfg.mode {
+            | Mode::Skim => self.skim_pages(&paper).await,
+            | _ => Vec::new(),
+        };
+        self.check_length(&mut paper).await.stage(Stage::Summarize, input)?;
+        let deferred = self.pdf_deferred(&paper).await;
+        let (text, extraction_quality) = self.summary_text(&paper);
+        let routed = self.routed_llm(&paper, &text).stage(Stage::Summarize, input)?;
+        let llm = routed.as_ref().unwrap_or(&self.llm);
+        let pace = chunk::pace(&self.cfg, llm.model()).await;
+        let parts = chunk::split(&self.cfg, llm, pace, &text);
+        let _reserved = self.check_cost(llm, &parts).await.stage(Stage::Summarize, input)?;
+        let related = self.related_notes(&paper).await;
+        let (mut summary, mut usage) = self
+            .summarize(llm, &paper.metadata, &parts, &pages, &related)
+            .await
+            .stage(Stage::Summarize, input)?;
+        self.check_uncertainty(&summary).stage(Stage::Summarize, input)?;
+        self.verify_claims(&paper, &mut summary)
+            .stage(Stage::Summarize, input)?;
+        summary.extraction_quality = extraction_quality;
+        if matches!(self.cfg.mode, Mode::Study) {
+            usage += self.reproduction_checklist(llm, &paper, &mut summary).await;
+        }
+        let (figures, figure_usage) = figures::describe(&self.cfg, &self.http, llm, &paper, &path).await;
+        summary.figures = figures;
+        usage += figure_usage;
+        self.link_prerequisites(&mut summary).await;
+        let concepts = self.plan_concepts(&mut summary).await;
+        if let Some(stated) = &summary.venue {
+            paper.metadata.set_stated_venue(stated);
+        }
+        let planned = self.plan_moc(&paper).await;
+        let moc = planned.as_ref().map(|p| &p.moc);
+        let rendered = self.render_paper(&paper, &summary, &related, moc, input, llm.model())?;
+        self.write_note(&path, &rendered, overwrite)
+            .await
+            .stage_at(Stage::Write, input, &path)?;
+        let fingerprint = paper.structure.as_ref().and_then(|s| Fingerprint::of(&s.full_text()));
+        self.register(&paper.metadata, fingerprint, &path, input, llm.model());
+        bibtex::add_to_vault_bib(&self.cfg, &paper.metadata).await;
+        vault::register_property_types(&self.cfg.vault_path, &self.cfg.frontmatter);
+        self.update_queue(input, &paper.metadata.title, &path, deferred).await;
+        self.create_linked_notes(&path, planned.as_ref(), &concepts).await;
+        self.write_companions(&paper, &summary, &path).await;
+        self.notify(Payload {
+            kind: "paper",
+            title: &paper.metadata.title,
+            path: &path,
+            note: self.vault_relative(&path),
+            markdown: &rendered,
+            metadata: &paper.metadata,
+            summary: &summary,
+            source: input.trim(),
+            model: llm.model(),
+            mode: self.cfg.mode.as_str(),
+            created: self.cfg.timezone.timestamp(chrono::Utc::now()),
+        })
+        .await;
+        if self.cfg.extract_claims {
+            // The note is written by now; a failure here should not fail the run.
+            match self.store_claims(llm, &paper, &text).await {
+                | Ok((_, claim_usage)) => usage += claim_usage,
+                | Err(e) => tracing::warn!(error = %e, "claim extraction failed"),
+            }
+        }
+        if self.cfg.leaderboards {
+            match self.update_leaderboards(llm, &paper, &summary.tags, &text).await {
+                | Ok(results_usage) => usage += results_usage,
+                | Err(e) => tracing::warn!(error = %e, "leaderboard update failed"),
+            }
+        }
+        self.index(&paper.metadata, &path, llm.model(), usage).await;
+        let cost = self.record_cost(&paper.metadata.title, llm, usage).await;
+        let report = RunReport::now(usage, cost);
+        self.keep_report(&paper.metadata, &path, &report);
+        self.record_pace(llm, &report).await;
+        Ok(NoteOutcome {
+            path,
+            title: paper.metadata.title.clone(),
+            usage,
+            cost,
+            pending: false,
+            metadata: paper.metadata,
+            report: Some(report),
+        })
+    }
+
+    /// Before the first summary, check that the model is on the Ollama server, where a missing one
+    /// would only fail mid-way with an HTTP error. One that is not is pulled when `OLLAMA_PULL` or
+    /// `--yes` says so or the user agrees on a terminal; otherwise the run stops with what to do.
+    async fn ensure_model(&self) -> Result<()> {
+        let check = async {
+            if !self.llm.needs_pull().await? {
+                return Ok(());
+            }
+            let model = self.llm.model();
+            tracing::warn!(model, "the Ollama server does not have the model");
+            let question = format!("Pull {model} to the Ollama server now?");
+            let pull = matches!(self.cfg.llm, LlmBackend::Ollama { pull: true, .. })
+                || self.cfg.assume_yes
+                || (self.cfg.can_ask() && note::ask(&question).await);
+            if !pull {
+                return Err(MabelError::Config {
+                    msg: format!(
+                        "OLLAMA_MODEL {model} is not on the Ollama server; run `ollama pull {model}`, or set \
+                         OLLAMA_PULL=true to have mabel pull it"
+                    ),
+                });
+            }
+            self.llm.pull().await
+        };
+        self.model_ready.get_or_try_init(|| check).await.map(drop)
+    }
+
+    /// Fill in the page count and size of the paper's PDF if it is at hand, and make sure a paper
+    /// longer than `MABEL_MAX_PAGES` is meant to be summarized: `--yes` goes ahead, a terminal is
+    /// asked and anything else is refused.
+    async fn check_length(&self, paper: &mut ResolvedPaper) -> Result<()> {
+        if paper.metadata.pdf.is_none() {
+            paper.metadata.pdf = source::pdf_info(&self.cfg, paper).await;
+        }
+        let (Some(info), Some(max)) = (&paper.metadata.pdf, self.cfg.max_pages) else {
+            return Ok(());
+        };
+        tracing::debug!(pages = info.pages, size = info.size, "PDF");
+        if info.pages <= max {
+            return Ok(());
+        }
+        let pages = info.pages;
+        tracing::warn!(pages, max, "the paper is longer than MABEL_MAX_PAGES");
+        let question = format!("{} has {pages} pages; summarize it anyway?", paper.metadata.title);
+        let go_ahead = self.cfg.assume_yes || (self.cfg.can_ask() && note::ask(&question).await);
+        if go_ahead {
+            Ok(())
+        } else {
+            Err(MabelError::TooManyPages { pages, max })
+        }
+    }
+
+    /// The text to summarize: the paper's full text without its stop sections (see
+    /// [`crate::stop_sections`]), unless it scores below `MABEL_MIN_EXTRACTION_QUALITY`, since
+    /// mangled text makes for a confident summary of things the paper does not say. Then it is the
+    /// abstract, introduction and conclusion only, along with the score, for the note's warning.
+    fn summary_text(&self, paper: &ResolvedPaper) -> (String, Option<f32>) {
+        let kept = self.without_stop_sections(paper);
+        let full_text = || structure_text(kept.as_ref(), &paper.metadata);
+        let threshold = self.cfg.min_extraction_quality;
+        let low = kept
+            .as_ref()
+            .and_then(|s| Some((s, s.quality()?)))
+            .filter(|&(_, quality)| quality < threshold);
+        let Some((structure, quality)) = low else {
+            return (full_text(), None);
+        };
+        let essentials = structure.essential_text(paper.metadata.abstract_text.as_deref());
+        if essentials.is_empty() {
+            tracing::warn!(
+                quality,
+                threshold,
+                "the extracted text scores low, but has no abstract, introduction or conclusion to fall back on"
+            );
+            return (full_text(), None);
+        }
+        tracing::warn!(
+            quality,
+            threshold,
+            "the extracted text is too garbled to trust; summarizing the abstract, introduction and conclusion only"
+        );
+        (essentials, Some(quality))
+    }
+
+    /// The paper's extracted structure without the sections `MABEL_STOP_SECTIONS` leaves out in the
+    /// run's mode.
+    fn without_stop_sections(&self, paper: &ResolvedPaper) -> Option<PaperStructure> {
+        let structure = paper.structure.as_ref()?;
+        let (stop, mode) = (&self.cfg.stop_sections, &self.cfg.mode);
+        if stop.is_empty(mode) {
+            return Some(structure.clone());
+        }
+        let left_out: Vec<&str> = structure
+            .sections
+            .iter()
+            .filter(|s| stop.stops(mode, &crate::paper::plain_heading(&s.heading)))
+            .map(|s| s.heading.as_str())
+            .collect();
+        if !left_out.is_empty() {
+            tracing::info!(sections = ?left_out, "left out of the prompt (MABEL_STOP_SECTIONS)");
+        }
+        Some(structure.without_sections(|heading| stop.stops(mode, heading)))
+    }
+
+    /// The uncertainty guardrail: a summary with more statements the model marked as uncertain than
+    /// `MABEL_MAX_UNCERTAIN` is not written.
+    fn check_uncertainty(&self, summary: &Summary) -> Result<()> {
+        match self.cfg.max_uncertain {
+            | Some(max) if summary.uncertain > max as usize => {
+                Err(MabelError::Guardrail {
+                    reason: format!(
+                        "{} statements marked uncertain, more than MABEL_MAX_UNCERTAIN ({max})",
+                        summary.uncertain
+                    ),
+                })
+            }
+            | _ => Ok(()),
+        }
+    }
+
+    /// The claim guardrail: with `MABEL_VERIFY_CLAIMS`, the statements of `summary` with a direct
+    /// quote or number that is not in the paper's text or metadata are marked, and a summary with
+    /// more of them than `MABEL_MAX_UNVERIFIED` is not written.
+    fn verify_claims(&self, paper: &ResolvedPaper, summary: &mut Summary) -> Result<()> {
+        if !self.cfg.verify_claims {
+            return Ok(());
+        }
+        let m = &paper.metadata;
+        let published = m.published.map(|d| d.to_string());
+        let fields = [
+            &m.abstract_text,
+            &published,
+            &m.journal,
+            &m.comment,
+            &m.arxiv_id,
+            &m.doi,
+        ];
+        let mut text: Vec<String> = fields.into_iter().flatten().cloned().collect();
+        text.push(m.title.clone());
+        text.extend(paper.structure.as_ref().map(PaperStructure::full_text));
+        let source = Source::new(&text.join("\n"));
+        if source.is_empty() {
+            tracing::debug!("no text to check the summary's quotes and numbers against");
+            return Ok(());
+        }
+        summary.verify(&source);
+        if summary.unverified == 0 {
+            return Ok(());
+        }
+        tracing::info!(
+            statements = summary.unverified,
+            "statements with a quote or number not found in the paper, marked in the note"
+        );
+        // Text that may be garbled can miss what the paper does say, so misses count for as much
+        // as the extraction as a whole can be trusted, and the note names the doubtful sections.
+        let structure = paper.structure.as_ref();
+        summary.unverified_doubtful = structure
+            .into_iter()
+            .flat_map(|s| s.low_confidence(LOW_CONFIDENCE))
+            .map(|s| s.heading.clone())
+            .collect();
+        let trust = structure.and_then(PaperStructure::quality).unwrap_or(1.0);
+        #[allow(clippy::cast_precision_loss)]
+        let weighted = summary.unverified as f64 * f64::from(trust);
+        if !summary.unverified_doubtful.is_empty() {
+            tracing::info!(
+                sections = summary.unverified_doubtful.join(", "),
+                trust,
+                "some sections checked against have low extraction confidence; weighting the misses by it"
+            );
+        }
+        match self.cfg.max_unverified {
+            | Some(max) if weighted > f64::from(max) => {
+                Err(MabelError::Guardrail {
+                    reason: format!(
+                        "{} statements with a quote or number not found in the paper ({weighted:.1} weighted by the \
+                         extraction's confidence), more than MABEL_MAX_UNVERIFIED ({max})",
+                        summary.unverified
+                    ),
+                })
+            }
+            | _ => Ok(()),
+        }
+    }
+
+    /// The cost guardrails: a paper whose summary may cost more than `MABEL_MAX_COST_PER_PAPER`, or
+    /// than is left of `MABEL_MAX_COST_PER_DAY`, is not summarized. A paper in several `parts` takes
+    /// a request per part, and one more with a reply's worth of notes on each. The paper's most is
+    /// held against the day's budget until the returned reservation is dropped, after its cost is
+    /// kept.
+    async fn check_cost(&self, llm: &Llm, parts: &[String]) -> Result<cost::Reservation> {
+        if self.cfg.max_cost_per_paper.is_none() && self.cfg.max_cost_per_day.is_none() {
+            return Ok(cost::Reservation::default());
+        }
+        let Some(price) = cost::price(llm) else {
+            tracing::warn!(
+                model = llm.model(),
+                "no price known for the model; the cost budgets are not checked"
+            );
+            return Ok(cost::Reservation::default());
+        };
+        let max_tokens = u64::from(self.cfg.llm.max_tokens());
+        let chars: usize = parts.iter().map(|part| part.len().min(prompt::MAX_INPUT_CHARS)).sum();
+        let (requests, notes) = match parts.len() as u64 {
+            | 1 => (1, 0),
+            | n => (n + 1, n * max_tokens),
+        };
+        let expected = price.cost(Usage {
+            prompt_tokens: cost::tokens(chars) + notes,
+            completion_tokens: requests * max_tokens,
+        });
+        cost::check_budgets(&self.cfg, expected).await
+    }
+
+    /// Log what a paper cost and keep it for the daily budget; `None` when the model's price is
+    /// unknown.
+    async fn record_cost(&self, title: &str, llm: &Llm, usage: Usage) -> Option<f64> {
+        let spent = cost::estimate(llm, usage)?;
+        tracing::info!(cost = cost::format(spent), "estimated cost");
+        if spent > 0.0 {
+            if let Err(e) = cost::record(&self.cfg, title, llm.model(), usage, spent).await {
+                tracing::warn!(error = %e, "could not keep what the paper cost");
+            }
+        }
+        Some(spent)
+    }
+
+    /// Summarize the paper from its `parts`, or from skim mode's `pages` when there are any. The
+    /// model's pace is kept when this fails too, since failures shrink its parts.
+    async fn summarize(
+        &self,
+        llm: &Llm,
+        metadata: &PaperMetadata,
+        parts: &[String],
+        pages: &[skim::Page],
+        related: &[RelatedNote],
+    ) -> Result<(Summary, Usage)> {
+        let (prompts, vars) = (&self.prompts, &self.cfg.vars);
+        let summarized = if pages.is_empty() {
+            summarize::paper(llm, prompts, metadata, parts, related, vars, self.cfg.uncertainty).await
+        } else {
+            summarize::skim(llm, prompts, metadata, pages, vars, self.cfg.uncertainty).await
+        };
+        if summarized.is_err() {
+            self.record_pace(llm, &RunReport::now(Usage::default(), None)).await;
+        }
+        summarized
+    }
+
+    /// Keep how fast and how reliably the model answered, to size its parts next time (see
+    /// [`chunk::pace`]). Batched replies wait on the batch, so they say nothing of its pace.
+    async fn record_pace(&self, llm: &Llm, report: &RunReport) {
+        if llm.is_batched() {
+            return;
+        }
+        if let Err(e) = chunk::record_pace(&self.cfg, llm.model(), report).await {
+            tracing::warn!(error = %e, "could not keep the model's pace");
+        }
+    }
+
+    /// Whether the paper is summarized without the PDF it would be read from (its extracted full
+    /// text, or skim mode's pages) because the connection is metered.
+    async fn pdf_deferred(&self, paper: &ResolvedPaper) -> bool {
+        let wants_pdf = matches!(self.cfg.mode, Mode::Skim) || (self.cfg.reads_pdfs() && paper.structure.is_none());
+        self.cfg.metered && wants_pdf && source::pdf_uncached(&self.cfg, paper).await
+    }
+
+    /// Queue a paper noted without its PDF for `mabel flush-queue`, or take one noted in full off
+    /// the queue. The note is already written, so failures only warn.
+    async fn update_queue(&self, input: &str, title: &str, path: &Path, deferred: bool) {
+        let note = self.vault_relative(path).to_path_buf();
+        let updated = if deferred {
+            tracing::info!("metered connection: the note is from the abstract until `mabel flush-queue`");
+            let queued = Queued {
+                input: input.trim().to_string(),
+                title: title.to_string(),
+                note,
+                queued: self.cfg.timezone.timestamp(chrono::Utc::now()),
+            };
+            queue::add(&self.cfg, queued).await
+        } else {
+            queue::remove(&self.cfg, &note).await.map(|_| ())
+        };
+        if let Err(e) = updated {
+            tracing::warn!(error = %e, "could not update the download queue");
+        }
+    }
+
+    /// Skim mode: the pages of the paper's PDF worth showing the model. Without a PDF, or when the
+    /// pages take longer than [`skim::BUDGET`] to get, there are none and the skim goes by the
+    /// abstract.
+    async fn skim_pages(&self, paper: &ResolvedPaper) -> Vec<skim::Page> {
+        let sample = async {
+            match source::pdf_file(&self.cfg, &self.http, paper).await? {
+                | Some(pdf) => skim::sample(&pdf).await,
+                | None => Ok(Vec::new()),
+            }
+        };
+        match tokio::time::timeout(skim::BUDGET, sample).await {
+            | Ok(Ok(pages)) => pages,
+            | Ok(Err(e)) => {
+                tracing::warn!(error = %e, "could not render pages; skimming the abstract");
+                Vec::new()
+            }
+            | Err(_) => {
+                tracing::warn!(
+                    budget_secs = skim::BUDGET.as_secs(),
+                    "pages took too long to render; skimming the abstract"
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    /// A different model for this paper if the routing policy asks for one.
+    fn routed_llm(&self, paper: &ResolvedPaper, text: &str) -> Result<Option<Llm>> {
+        match self.cfg.routing.route(paper, text.len()) {
+            | Some(model) if model != self.llm.model() => {
+                tracing::info!(model, "routing policy selected model");
+                let llm = Llm::from_backend(&self.cfg.llm.with_overrides(Some(model), None), self.cfg.http_retries)?;
+                match &self.batch {
+                    | Some(queue) => llm.batched(queue.clone()).map(Some),
+                    | None => Ok(Some(llm)),
+                }
+            }
+            | _ => Ok(None),
+        }
+    }
+
+    /// Resolve a paper input without summarizing it. Books are not papers and are rejected.
+    pub async fn resolve_paper(&self, input: &str) -> Result<ResolvedPaper> {
+        match Input::parse(input)? {
+            | Input::EpubFile(path) => {
+                Err(MabelError::UnsupportedInput {
+                    input: format!("{} (books are only supported by `mabel note`)", path.display()),
+                })
+            }
+            | parsed => parsed.resolve(&self.cfg, &self.http).await.stage(Stage::Resolve, input),
+        }
+    }
+
+    /// Extract a paper's claims into the claim store, replacing any stored for its note before.
+    /// Returns how many claims were stored.
+    pub async fn extract_claims(&self, paper: &ResolvedPaper) -> Result<(usize, Usage)> {
+        let text = paper_text(paper);
+        let routed = self.routed_llm(paper, &text)?;
+        let llm = routed.as_ref().unwrap_or(&self.llm);
+        self.store_claims(llm, paper, &text).await
+    }
+
+    async fn store_claims(&self, llm: &Llm, paper: &ResolvedPaper, text: &str) -> Result<(usize, Usage)> {
+        let (found, usage) = claims::extract(llm, &paper.metadata, text).await?;
+        let link = note::file_stem(&paper.metadata.title);
+        let extracted = self.cfg.timezone.timestamp(chrono::Utc::now());
+        let records: Vec<ClaimRecord> = found
+            .into_iter()
+            .map(|c| {
+                ClaimRecord {
+                    statement: c.statement.trim().to_string(),
+                    evidence: c.evidence,
+                    strength: c.strength,
+                    note: link.clone(),
+                    title: paper.metadata.title.clone(),
+                    doi: paper.metadata.doi.clone(),
+                    arxiv: paper.metadata.arxiv_id.clone(),
+                    extracted: extracted.clone(),
+                }
+            })
+            .collect();
+        tracing::info!(count = records.len(), "storing claims");
+        claims::replace(&self.cfg.claims_path(), &link, &records).await?;
+        Ok((records.len(), usage))
+    }
+
+    /// Fill in the reproduction checklist from the paper's experimental setup. A paper without one,
+    /// or a failed pass, leaves the checklist out of the note.
+    async fn reproduction_checklist(&self, llm: &Llm, paper: &ResolvedPaper, summary: &mut Summary) -> Usage {
+        let Some(setup) = paper.structure.as_ref().and_then(reproduction::setup_text) else {
+            tracing::debug!("no experimental setup section; leaving out the reproduction checklist");
+            return Usage::default();
+        };
+        match reproduction::extract(llm, &paper.metadata, &setup).await {
+            | Ok((checklist, usage)) => {
+                summary.reproduction = checklist;
+                usage
+            }
+            | Err(e) => {
+                tracing::warn!(error = %e, "reproduction checklist failed");
+                Usage::default()
+            }
+        }
+    }
+
+    /// Extract the paper's results into the results store and rewrite the leaderboard of every topic
+    /// it has, or had when it was last processed.
+    async fn update_leaderboards(
+        &self,
+        llm: &Llm,
+        paper: &ResolvedPaper,
+        tags: &[String],
+        text: &str,
+    ) -> Result<Usage> {
+        let (rows, usage) = results::extract(llm, &paper.metadata, text).await?;
+        let link = note::file_stem(&paper.metadata.title);
+        let extracted = self.cfg.timezone.timestamp(chrono::Utc::now());
+        let topics: Vec<String> = tags.iter().map(slug::slugify).filter(|t| !t.is_empty()).collect();
+        let records: Vec<ResultRecord> = rows
+            .into_iter()
+            .map(|r| {
+                ResultRecord {
+                    method: r.method,
+                    dataset: r.dataset,
+                    metric: r.metric,
+                    value: r.value,
+                    higher_is_better: r.higher_is_better,
+                    proposed: r.proposed,
+                    note: link.clone(),
+                    title: paper.metadata.title.clone(),
+                    topics: topics.clone(),
+                    extracted: extracted.clone(),
+                }
+            })
+            .collect();
+        tracing::info!(count = records.len(), "storing results");
+
+        let path = self.cfg.results_path();
+        let mut affected = topics.clone();
+        for old in results::load(&path).await?.iter().filter(|r| r.note == link) {
+            affected.extend(old.topics.iter().cloned());
+        }
+        affected.sort();
+        affected.dedup();
+        let all = results::replace(&path, &link, &records).await?;
+
+        let dir = self.cfg.leaderboards_dir();
+        for topic in affected {
+            let board = dir.join(format!("{topic}.md"));
+            let has_rows = all.iter().any(|r| r.topics.contains(&topic));
+            // Only topics with results get a leaderboard; one that lost its last row is emptied.
+            if has_rows || board.exists() {
+                let text = results::leaderboard(&topic, &all, &extracted, &self.cfg.region_markers);
+                note::write_managed(&board, &text, Overwrite::Merge, &self.cfg.region_markers).await?;
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Write a rendered note, first showing the diff against the existing note and asking when
+    /// `--preview-diff` is on.
+    async fn write_note(&self, path: &Path, rendered: &str, overwrite: Overwrite) -> Result<()> {
+        let (markers, yes, can_ask) = (&self.cfg.region_markers, self.cfg.assume_yes, self.cfg.can_ask());
+        let confirmed = overwrite == Overwrite::Refuse
+            || !self.cfg.preview_diff
+            || note::confirm_write(path, rendered, overwrite, markers, yes, can_ask).await?;
+        if !confirmed {
+            return Err(MabelError::NoteDeclined {
+                path: path.to_path_buf(),
+            });
+        }
+        note::write_managed(path, rendered, overwrite, markers).await
+    }
+
+    /// Point each prerequisite (ELI-grad mode) at the vault note on its topic, if there is one.
+    async fn link_prerequisites(&self, summary: &mut Summary) {
+        if summary.prerequisites.is_empty() {
+            return;
+        }
+        // Topic notes can live anywhere in the vault, not only among the paper notes.
+        let (dir, fields) = (self.cfg.vault_path.clone(), self.cfg.frontmatter.clone());
+        let notes = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+            .await
+            .unwrap_or_default();
+        for prerequisite in &mut summary.prerequisites {
+            prerequisite.link = vault::find_topic(&notes, &prerequisite.topic).map(|n| n.link.clone());
+        }
+    }
+
+    /// Link the summary's technical terms to their vault notes (`--define-new-terms`), returning
+    /// the concept notes to write for the rest.
+    async fn plan_concepts(&self, summary: &mut Summary) -> Vec<concept::Planned> {
+        if !self.cfg.define_new_terms || summary.terms.is_empty() {
+            return Vec::new();
+        }
+        // Concept notes can live anywhere in the vault, like topic notes.
+        let (dir, fields) = (self.cfg.vault_path.clone(), self.cfg.frontmatter.clone());
+        let notes = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+            .await
+            .unwrap_or_default();
+        concept::plan(&self.cfg, &notes, &mut summary.terms)
+    }
+
+    /// Write the new MOC and concept notes the paper's note at `path` links to.
+    async fn create_linked_notes(&self, path: &Path, moc: Option<&moc::Planned>, concepts: &[concept::Planned]) {
+        if let Some(planned) = moc {
+            if let Err(e) = moc::create(&self.cfg, &self.renderer, planned).await {
+                tracing::warn!(error = %e, "could not create the MOC");
+            }
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        for planned in concepts {
+            if let Err(e) = concept::create(&self.cfg, &self.renderer, planned, &stem).await {
+                tracing::warn!(error = %e, term = planned.term, "could not create the concept note");
+            }
+        }
+    }
+
+    /// The MOC the paper belongs in, when `MABEL_MOCS` is on.
+    async fn plan_moc(&self, paper: &ResolvedPaper) -> Option<moc::Planned> {
+        if !self.cfg.mocs {
+            return None;
+        }
+        // MOCs can live anywhere in the vault, like topic notes.
+        let (dir, fields) = (self.cfg.vault_path.clone(), self.cfg.frontmatter.clone());
+        let notes = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+            .await
+            .unwrap_or_default();
+        moc::plan(&self.cfg, &notes, &paper.metadata)
+    }
+
+    /// Notes already in the vault on work this paper cites or is close to.
+    pub async fn related_notes(&self, paper: &ResolvedPaper) -> Vec<RelatedNote> {
+        if !self.cfg.vault_context {
+            return Vec::new();
+        }
+        let (dir, fields) = (self.cfg.vault_notes_dir(), self.cfg.frontmatter.clone());
+        let notes = tokio::task::spawn_blocking(move || vault::scan(&dir, &fields))
+            .await
+            .unwrap_or_default();
+        let similarity = self.note_similarity(paper, &notes).await;
+        let related = vault::related(&notes, paper, similarity.as_deref(), MAX_RELATED_NOTES);
+        if !related.is_empty() {
+            tracing::info!(count = related.len(), "including related vault notes as context");
+        }
+        related
+    }
+
+    /// How close each of `notes` is to the paper: the cosine similarity of their embeddings,
+    /// cached like the digest's. `None`, so notes are matched by tags, when the backend cannot
+    /// embed.
+    #[cfg(feature = "embeddings")]
+    async fn note_similarity(&self, paper: &ResolvedPaper, notes: &[VaultNote]) -> Option<Vec<f32>> {
+        use crate::recommend::{self, embedding_text};
+
+        let model = self
+            .cfg
+            .embedding_model
+            .clone()
+            .unwrap_or_else(|| self.llm.default_embedding_model().to_string());
+        if model.is_empty() || notes.is_empty() {
+            return None;
+        }
+        let md = &paper.metadata;
+        let mut items = vec![recommend::Item {
+            key: format!("paper:{}", md.title),
+            text: embedding_text(&md.title, md.abstract_text.as_deref()),
+        }];
+        items.extend(notes.iter().map(|n| {
+            recommend::Item {
+                key: format!("note:{}", n.path.display()),
+                text: embedding_text(n.title(), n.tldr.as_deref()),
+            }
+        }));
+        match recommend::embed(&self.llm, &model, &self.cfg.embeddings_cache_path(&model), &items).await {
+            | Ok(vectors) => {
+                let (own, rest) = vectors.split_first()?;
+                Some(rest.iter().map(|v| recommend::cosine(own, v)).collect())
+            }
+            | Err(e) => {
+                tracing::warn!(error = %e, "could not embed the vault notes; matching them by tags");
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "embeddings"))]
+    #[allow(clippy::unused_async)]
+    async fn note_similarity(&self, _paper: &ResolvedPaper, _notes: &[VaultNote]) -> Option<Vec<f32>> {
+        None
+    }
+
+    /// Write what goes along with the paper's note, once the note is: the flashcards for Anki, the
+    /// full text and the thread. None of them failing fails the run.
+    async fn write_companions(&self, paper: &ResolvedPaper, summary: &Summary, path: &Path) {
+        if let (Some(dir), false) = (&self.cfg.anki_dir, summary.flashcards.is_empty()) {
+            match flashcards::export(dir, path, summary).await {
+                | Ok(file) => tracing::info!(path = %file.display(), "wrote the flashcards for Anki"),
+                | Err(e) => tracing::warn!(error = %e, "could not write the flashcards for Anki"),
+            }
+        }
+        if let Err(e) = fulltext::save(&self.cfg, paper, path).await {
+            tracing::warn!(error = %e, "could not keep the paper's full text");
+        }
+        if self.cfg.thread {
+            match thread::write(&self.cfg, &self.renderer, &paper.metadata, summary, path).await {
+                | Ok(file) => tracing::info!(path = %file.display(), "wrote the thread"),
+                | Err(e) => tracing::warn!(error = %e, "could not write the thread"),
+            }
+        }
+    }
+
+    /// Render a paper note for a summary produced by `model`. Fails if its frontmatter does not
+    /// match the configured schema.
+    pub fn render_paper(
+        &self,
+        paper: &ResolvedPaper,
+        summary: &Summary,
+        related: &[RelatedNote],
+        moc: Option<&Moc>,
+        input: &str,
+        model: &str,
+    ) -> Result<String> {
+        let sections = paper.structure.as_ref().map_or(&[][..], |s| s.sections.as_slice());
+        let low_confidence = paper
+            .structure
+            .iter()
+            .flat_map(|s| s.low_confidence(LOW_CONFIDENCE))
+            .map(|s| s.heading.as_str())
+            .collect();
+        let rendered = self
+            .renderer
+            .render_paper(&PaperNote {
+                metadata: &paper.metadata,
+                summary,
+                figures: &summary.figures,
+                related,
+                sections,
+                low_confidence,
+                bibtex: &bibtex::for_note(&self.cfg, &paper.metadata),
+                moc,
+                source: input.trim(),
+                created: self.cfg.timezone.timestamp(chrono::Utc::now()),
+                model,
+                mode: self.cfg.mode.as_str(),
+            })
+            .stage_at(Stage::Render, input, &self.cfg.template_path)?;
+        if let Some(schema) = &self.schema {
+            schema.validate(&rendered).stage(Stage::Render, input)?;
+        }
+        Ok(rendered)
+    }
+
+    async fn run_book(&self, file: &Path) -> Result<NoteOutcome> {
+        let source = file.display().to_string();
+        let book = local::load_book(file, self.cfg.chapters.as_ref())
+            .await
+            .stage(Stage::Extract, &source)?;
+        let path = note::note_path(&self.cfg, &book.metadata.title);
+        self.ensure_writable(&path).stage(Stage::Write, &source)?;
+        self.ensure_model().await.stage(Stage::Summarize, &source)?;
+
+        let (summary, usage) = summarize::book(&self.llm, &book, &self.cfg.vars)
+            .await
+            .stage(Stage::Summarize, &source)?;
+        let rendered = self
+            .renderer
+            .render_book(&BookNote::new(
+                &book.metadata,
+                &summary,
+                source.clone(),
+                self.cfg.timezone.timestamp(chrono::Utc::now()),
+                self.cfg.chapters.as_ref().map(ToString::to_string),
+            ))
+            .stage_at(Stage::Render, &source, &self.cfg.template_path)?;
+        self.write_note(&path, &rendered, self.cfg.overwrite_note)
+            .await
+            .stage_at(Stage::Write, &source, &path)?;
+        self.register(&book.metadata, None, &path, &source, self.llm.model());
+        self.notify(Payload {
+            kind: "book",
+            title: &book.metadata.title,
+            path: &path,
+            note: self.vault_relative(&path),
+            markdown: &rendered,
+            metadata: &book.metadata,
+            summary: &summary,
+            source: &source,
+            model: self.llm.model(),
+            mode: self.cfg.mode.as_str(),
+            created: self.cfg.timezone.timestamp(chrono::Utc::now()),
+        })
+        .await;
+        self.index(&book.metadata, &path, self.llm.model(), usage).await;
+        let cost = self.record_cost(&book.metadata.title, &self.llm, usage).await;
+        Ok(NoteOutcome {
+            path,
+            title: book.metadata.title.clone(),
+            usage,
+            cost,
+            pending: false,
+            metadata: book.metadata,
+            report: None,
+        })
+    }
+
+    /// Send a written note to the webhook, if one is configured. The note is already written, so
+    /// failures only warn.
+    async fn notify<S: Serialize>(&self, payload: Payload<'_, S>) {
+        let Some(url) = &self.cfg.webhook_url else {
+            return;
+        };
+        if let Err(e) = webhook::send(&self.http, url, self.cfg.webhook_secret.as_ref(), &payload).await {
+            tracing::warn!(error = %e, "webhook delivery failed");
+        }
+    }
+
+    fn vault_relative<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(&self.cfg.vault_path).unwrap_or(path)
+    }
+
+    /// Record the note in the registry, with the fingerprint of the paper's text when it has one
+    /// (see [`source::local::check_duplicate`]). The note is already written, so failures only warn.
+    fn register(
+        &self,
+        metadata: &PaperMetadata,
+        fingerprint: Option<Fingerprint>,
+        path: &Path,
+        source: &str,
+        model: &str,
+    ) {
+        let note = self.vault_relative(path).to_path_buf();
+        let processed = self.cfg.timezone.timestamp(chrono::Utc::now());
+        let mut entry = Entry::new(metadata, note, source.trim(), model, processed);
+        entry.prompt_version = Some(prompt::VERSION);
+        entry.fingerprint = fingerprint;
+        if let Err(e) = Registry::update(&self.cfg.registry_path(), |r| r.record(entry)) {
+            tracing::warn!(error = %e, "could not update the registry");
+        }
+    }
+
+    /// Log the run report and keep it in the paper's registry record. Like the registry's, failures
+    /// only warn.
+    fn keep_report(&self, metadata: &PaperMetadata, path: &Path, report: &RunReport) {
+        report.log();
+        let key = Entry::new(metadata, self.vault_relative(path).to_path_buf(), "", "", String::new()).key();
+        let kept = Registry::update(&self.cfg.registry_path(), |r| {
+            if let Some(entry) = r.papers.get_mut(&key) {
+                entry.report = Some(report.clone());
+            }
+        });
+        if let Err(e) = kept {
+            tracing::warn!(error = %e, "could not update the registry");
+        }
+    }
+
+    /// Record the note and what it took in the paper index. Like the registry's, failures only
+    /// warn.
+    async fn index(&self, metadata: &PaperMetadata, path: &Path, model: &str, usage: Usage) {
+        let note = self.vault_relative(path).to_path_buf();
+        let paper = Indexed {
+            key: Entry::new(metadata, note, "", "", String::new()).key(),
+            title: metadata.title.clone(),
+            arxiv: metadata.arxiv_id.clone(),
+            doi: metadata.doi.clone(),
+            note: path.to_path_buf(),
+            processed: self.cfg.timezone.timestamp(chrono::Utc::now()),
+            model: model.to_string(),
+            usage,
+        };
+        // SQLite blocks, for up to its busy timeout while another run writes the index.
+        let db = self.cfg.index_path();
+        let recorded = tokio::task::spawn_blocking(move || Index::open(&db).and_then(|index| index.record(&paper)));
+        match recorded.await {
+            | Ok(Ok(())) => {}
+            | Ok(Err(e)) => tracing::warn!(error = %e, "could not update the paper index"),
+            | Err(e) => tracing::warn!(error = %e, "could not update the paper index"),
+        }
+    }
+
+    /// The paper's note when the registry knows one that still exists, such as a renamed note or
+    /// one taken over with `mabel adopt`, whatever its file name.
+    fn registered_note(&self, metadata: &PaperMetadata) -> Option<PathBuf> {
+        let registry = Registry::load(&self.cfg.registry_path()).ok()?;
+        let path = self.cfg.vault_path.join(&registry.find(metadata)?.note);
+        path.is_file().then_some(path)
+    }
+
+    fn ensure_writable(&self, path: &Path) -> Result<()> {
+        if self.cfg.overwrite_note == Overwrite::Refuse && path.exists() {
+            return Err(MabelError::NoteExists {
+                path: path.to_path_buf(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The text to summarize: structured full text when the source has it, otherwise the abstract.
+pub fn paper_text(paper: &ResolvedPaper) -> String {
+    structure_text(paper.structure.as_ref(), &paper.metadata)
+}
+
+/// [`paper_text`] for the paper with `metadata` and `structure`.
+fn structure_text(structure: Option<&PaperStructure>, metadata: &PaperMetadata) -> String {
+    match structure {
+        | Some(structure) if !structure.is_empty() => structure.full_text(),
+        | _ => {
+            tracing::warn!("no full text available; summarizing from the abstract");
+            metadata.abstract_text.clone().unwrap_or_default()
+        }
+    }
+}
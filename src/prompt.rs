@@ -0,0 +1,521 @@
+//! Prompts sent to the model. Summary prompts ask for a JSON object so the reply can be mapped
+//! onto [`Summary`](crate::summarize::Summary) fields and rendered through the note template.
+//!
+//! The summary prompts are Tera templates, one system prompt per mode (`<mode>.system.tera`,
+//! each extending `system.tera`) and a user message (`user.tera`, or a mode's own
+//! `<mode>.user.tera`); see [`Prompts`]. The built-in ones are in the repository's `prompts/`
+//! folder, and `mabel template prompts` writes them out as a starting point. The other passes
+//! (claims, results, chapters and the like) keep their prompts here.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tera::Tera;
+
+use crate::{
+    config::{Config, Mode},
+    eval::Criterion,
+    llm::Prompt,
+    paper::{Figure, PaperMetadata},
+    skim::Page,
+    summarize::{UNCERTAIN_CLOSE, UNCERTAIN_OPEN},
+    vault::{RelatedNote, Relation},
+    MabelError, Result,
+};
+
+/// Upper bound on the paper text included in a prompt, in characters (~15k tokens).
+pub const MAX_INPUT_CHARS: usize = 60_000;
+
+/// Version of the summary prompts, recorded in the registry with each note. Bump it when a change
+/// makes notes written with the old prompts worth redoing; `mabel freshness` lists those.
+pub const VERSION: u32 = 1;
+
+/// The built-in summary prompt templates, by file name.
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("system.tera", include_str!("../prompts/system.tera")),
+    ("concise.system.tera", include_str!("../prompts/concise.system.tera")),
+    ("study.system.tera", include_str!("../prompts/study.system.tera")),
+    ("eli-grad.system.tera", include_str!("../prompts/eli-grad.system.tera")),
+    ("skim.system.tera", include_str!("../prompts/skim.system.tera")),
+    (
+        "flashcards.system.tera",
+        include_str!("../prompts/flashcards.system.tera"),
+    ),
+    ("user.tera", include_str!("../prompts/user.tera")),
+];
+
+/// The summary prompts for one mode. A `.tera` file in `MABEL_PROMPT_DIR` replaces the built-in
+/// template of the same name, and `--prompt-template` the mode's system prompt; the rest stay
+/// built in. Templates see the `mode`, the paper's `title`, `authors`, `journal`, `comment` and
+/// `text`, `related` notes (`link`, `relation`, `tldr`), `ask_venue`, `define_terms`, `images`
+/// and `vars`.
+#[derive(Clone, Debug)]
+pub struct Prompts {
+    tera: Tera,
+    mode: Mode,
+    /// Ask for the summary's technical terms (`--define-new-terms`)
+    define_terms: bool,
+}
+
+/// A vault note the paper relates to, as templates see it.
+#[derive(Serialize)]
+struct RelatedContext<'a> {
+    link: &'a str,
+    relation: &'static str,
+    tldr: Option<&'a str>,
+}
+
+impl Prompts {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let mut sources: BTreeMap<String, String> = TEMPLATES
+            .iter()
+            .map(|(name, source)| ((*name).to_string(), (*source).to_string()))
+            .collect();
+        if let Some(dir) = &cfg.prompt_dir {
+            let entries = std::fs::read_dir(dir).map_err(|source| {
+                MabelError::Io {
+                    path: dir.clone(),
+                    source,
+                }
+            })?;
+            for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+                if path.extension().is_some_and(|x| x == "tera") {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    sources.insert(name, read_template(&path)?);
+                }
+            }
+        }
+        if let Some(path) = &cfg.prompt_template {
+            sources.insert(format!("{}.system.tera", cfg.mode.as_str()), read_template(path)?);
+        }
+        let mut tera = Tera::default();
+        tera.autoescape_on(Vec::new());
+        tera.add_raw_templates(sources)?;
+        Ok(Self {
+            tera,
+            mode: cfg.mode.clone(),
+            define_terms: cfg.define_new_terms,
+        })
+    }
+
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// Summarize one paper. `related` are notes already in the vault that the summary should link
+    /// to where the paper builds on them.
+    pub fn paper(
+        &self,
+        metadata: &PaperMetadata,
+        text: &str,
+        related: &[RelatedNote],
+        vars: &BTreeMap<String, String>,
+    ) -> Result<Prompt> {
+        let related: Vec<RelatedContext<'_>> = related
+            .iter()
+            .map(|note| {
+                RelatedContext {
+                    link: &note.link,
+                    relation: match note.relation {
+                        | Relation::Cited => "cited by this paper",
+                        | Relation::Related => "same topic",
+                    },
+                    tldr: note.tldr.as_deref(),
+                }
+            })
+            .collect();
+        let mut ctx = self.context(metadata, text, vars);
+        ctx.insert("related", &related);
+        ctx.insert("ask_venue", &metadata.venue.is_none());
+        ctx.insert("images", &false);
+        self.render(&ctx, Vec::new())
+    }
+
+    /// A skim of one paper from images of a few of its pages (see [`crate::skim`]).
+    pub fn skim(&self, metadata: &PaperMetadata, pages: &[Page], vars: &BTreeMap<String, String>) -> Result<Prompt> {
+        let mut shown = String::from("Pages shown:");
+        for page in pages {
+            let _ = write!(shown, " {} ({}),", page.number, page.reason);
+        }
+        shown.pop();
+        let abstract_text = metadata.abstract_text.as_deref().unwrap_or_default();
+        let mut ctx = self.context(metadata, &format!("{shown}\n\nAbstract: {abstract_text}"), vars);
+        ctx.insert("related", &Vec::<RelatedContext<'_>>::new());
+        ctx.insert("ask_venue", &false);
+        ctx.insert("images", &true);
+        self.render(&ctx, pages.iter().map(|p| p.png.clone()).collect())
+    }
+
+    fn context(&self, metadata: &PaperMetadata, text: &str, vars: &BTreeMap<String, String>) -> tera::Context {
+        let mut ctx = tera::Context::new();
+        ctx.insert("mode", self.mode.as_str());
+        ctx.insert("title", &metadata.title);
+        ctx.insert("authors", &metadata.authors);
+        ctx.insert("journal", &metadata.journal);
+        ctx.insert("comment", &metadata.comment);
+        ctx.insert("define_terms", &self.define_terms);
+        ctx.insert("text", truncate(text, MAX_INPUT_CHARS));
+        ctx.insert("vars", vars);
+        ctx
+    }
+
+    fn render(&self, ctx: &tera::Context, images: Vec<Vec<u8>>) -> Result<Prompt> {
+        let mode = self.mode.as_str();
+        let own_user = format!("{mode}.user.tera");
+        let user = if self.tera.get_template_names().any(|name| name == own_user) {
+            own_user.as_str()
+        } else {
+            "user.tera"
+        };
+        Ok(Prompt {
+            system: self
+                .tera
+                .render(&format!("{mode}.system.tera"), ctx)?
+                .trim()
+                .to_string(),
+            user: self.tera.render(user, ctx)?.trim_end().to_string(),
+            json: true,
+            images,
+            batch: true,
+        })
+    }
+}
+
+fn read_template(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|_| {
+        MabelError::TemplateMissing {
+            path: PathBuf::from(path),
+        }
+    })
+}
+
+/// Notes on one part of a paper too long for one prompt, which the summary is then written from (see
+/// [`crate::chunk`]).
+pub fn part(metadata: &PaperMetadata, number: usize, parts: usize, text: &str) -> Prompt {
+    Prompt {
+        system: "You take notes on one part of a research paper that is too long to read at once; the paper's summary \
+                 will be written from the notes on all of its parts. Note what this part says: its claims, methods, \
+                 data, results with their numbers, definitions and limitations, as plain bullet points under the \
+                 part's section headings. Leave out what it only mentions in passing, and do not guess at what other \
+                 parts say."
+            .to_string(),
+        user: with_header(metadata, &format!("Part {number} of {parts}:\n\n{text}")),
+        json: false,
+        images: Vec::new(),
+        batch: true,
+    }
+}
+
+/// Summarize one chapter of a book.
+pub fn chapter(metadata: &PaperMetadata, heading: &str, text: &str) -> Prompt {
+    let system = "You write reading notes on book chapters. Reply with a single JSON object of this shape and nothing \
+                  else:\n{\n  \"summary\": \"one paragraph\",\n  \"key_points\": [\"3-6 bullet points\"]\n}"
+        .to_string();
+    let user = format!(
+        "Book: {}\nChapter: {heading}\n\n{}",
+        metadata.title,
+        truncate(text, MAX_INPUT_CHARS)
+    );
+    Prompt {
+        system,
+        user,
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Combine chapter summaries into an overview of the (processed part of the) book.
+pub fn book_overview(metadata: &PaperMetadata, chapter_summaries: &[(String, String)]) -> Prompt {
+    let mut user = format!("Book: {}\n\n", metadata.title);
+    for (heading, summary) in chapter_summaries {
+        let _ = write!(user, "## {heading}\n\n{summary}\n\n");
+    }
+    Prompt {
+        system: "You are given summaries of consecutive book chapters. Write a two-paragraph overview of the book's \
+                 argument across them. Reply with a single JSON object of this shape and nothing else:\n{\n  \
+                 \"summary\": \"the overview\",\n  \"tags\": [\"3-6 lowercase topic tags\"]\n}"
+            .to_string(),
+        user: truncate(&user, MAX_INPUT_CHARS).to_string(),
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Pull atomic claims out of a paper for the claim knowledge base.
+pub fn claims(metadata: &PaperMetadata, text: &str) -> Prompt {
+    Prompt {
+        system: "You extract the claims a research paper makes, for a searchable knowledge base. A claim is one \
+                 self-contained, falsifiable statement that makes sense without the paper (name the method or system \
+                 instead of writing \"our method\"). List the paper's own findings and conclusions, not background it \
+                 cites. Reply with a single JSON object of this shape and nothing else:\n{\n  \"claims\": \
+                 [{\"statement\": \"...\", \"evidence\": \"experimental | theoretical | observational | review | \
+                 anecdotal\", \"strength\": \"strong | moderate | weak\"}]\n}\nUse 3-12 claims. Strength reflects how \
+                 well the paper's evidence supports the claim."
+            .to_string(),
+        user: with_header(metadata, text),
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Pull the paper's quantitative results out for the per-topic leaderboards.
+pub fn results(metadata: &PaperMetadata, text: &str) -> Prompt {
+    Prompt {
+        system: "You extract the quantitative results a research paper reports, for a leaderboard that compares \
+                 methods across papers. Take only numbers from the paper's own result tables and text: the \
+                 paper's methods and the baselines it reports numbers for. Name datasets and metrics the way the \
+                 field does (\"ImageNet\", \"top-1 accuracy\"), keep each value as written, with its unit, and \
+                 give one row per method, dataset and metric. Reply with a single JSON object of this shape and \
+                 nothing else:\n{\n  \"results\": [{\"method\": \"...\", \"dataset\": \"...\", \"metric\": \"...\", \
+                 \"value\": \"...\", \"higher_is_better\": true, \"proposed\": true}]\n}\n`proposed` is true for \
+                 the paper's own methods. Use an empty list when the paper reports no such results."
+            .to_string(),
+        user: with_header(metadata, text),
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Fill in the reproduction checklist from the paper's experimental setup (see
+/// [`crate::reproduction`]).
+pub fn reproduction(metadata: &PaperMetadata, setup: &str) -> Prompt {
+    Prompt {
+        system: "You check whether a research paper gives what it takes to reproduce its experiments. You see its \
+                 experimental setup and any statements on code or data availability. For each item answer \"yes\", \
+                 \"partial\", \"no\" or \"unclear\" (the text does not say), and give the detail in one short phrase: \
+                 the repository URL, the datasets and where to get them, which hyperparameters are given and which \
+                 are missing, the hardware and training time. Code that is only promised for later is \"no\". Reply \
+                 with a single JSON object of this shape and nothing else:\n{\n  \"code\": {\"status\": \"yes | \
+                 partial | no | unclear\", \"detail\": \"...\"},\n  \"data\": {...},\n  \"hyperparameters\": {...},\n  \
+                 \"compute\": {...}\n}"
+            .to_string(),
+        user: with_header(metadata, setup),
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Alt text for one of the paper's figures, from its image (see [`crate::figures`]).
+pub fn figure_alt(metadata: &PaperMetadata, figure: &Figure, image: Vec<u8>) -> Prompt {
+    let label = figure.label.as_deref().unwrap_or("Figure");
+    Prompt {
+        system: "You write alt text for figures in research papers, for readers using a screen reader. Say what the \
+                 image shows in one or two plain sentences of at most 150 characters: the kind of chart or diagram, \
+                 what is plotted or connected, and the trend or takeaway a sighted reader would see. Do not repeat \
+                 the caption, and do not start with \"Image of\" or \"Figure showing\". Reply with the alt text only."
+            .to_string(),
+        user: with_header(metadata, &format!("{label}. {}", figure.caption)),
+        json: false,
+        images: vec![image],
+        batch: false,
+    }
+}
+
+/// Explain one section of a paper to someone reading along (`mabel read`).
+pub fn section(metadata: &PaperMetadata, heading: &str, text: &str) -> Prompt {
+    Prompt {
+        system: "You are a reading companion for someone going through a research paper one section at a time. \
+                 Explain the section you are given in one short paragraph of plain prose: what it sets out to do, the \
+                 idea or result at its heart, and any term or notation a graduate student outside the field would \
+                 trip over. Do not summarize the sections around it and do not use headings or lists."
+            .to_string(),
+        user: with_header(metadata, &format!("Section: {heading}\n\n{text}")),
+        json: false,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Answer the reader's question on the section they are reading (`mabel read`), given what was
+/// asked and answered on it so far.
+pub fn section_question(
+    metadata: &PaperMetadata,
+    heading: &str,
+    text: &str,
+    asked: &[(String, String)],
+    question: &str,
+) -> Prompt {
+    let mut conversation = String::new();
+    for (q, a) in asked {
+        let _ = write!(conversation, "Q: {q}\nA: {a}\n\n");
+    }
+    let _ = write!(conversation, "Q: {question}");
+    // Leave room for the conversation within the same budget as a summary prompt.
+    let budget = MAX_INPUT_CHARS - conversation.len().min(MAX_INPUT_CHARS / 2);
+    Prompt {
+        system: "You are a reading companion for someone going through a research paper one section at a time. Answer \
+                 their last question in a few sentences of plain prose, from the section you are given and what you \
+                 know of the field. Say so when the paper does not answer it rather than guessing what the authors \
+                 meant."
+            .to_string(),
+        user: format!(
+            "{}\n\n# Questions\n\n{conversation}",
+            with_header(metadata, truncate(&format!("Section: {heading}\n\n{text}"), budget))
+        ),
+        json: false,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// A three-sentence annotation for an annotated bibliography, from the user's own note on the
+/// paper (`mabel bibliography`, for notes without a summary).
+pub fn annotation(metadata: &PaperMetadata, note: &str) -> Prompt {
+    Prompt {
+        system: "You write entries for an annotated bibliography. From the reader's note on a research paper, write \
+                 an annotation of exactly three sentences: what the paper sets out to do, how it goes about it, and \
+                 what it finds or why it matters. Write in the third person about the paper (\"The authors \
+                 show...\"), not about the note, and add nothing the note does not support. Reply with the three \
+                 sentences only, as one paragraph."
+            .to_string(),
+        user: with_header(metadata, note),
+        json: false,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// Synthesize one brief on `topic` from the notes of several papers, given as (title, note) pairs
+/// that the reply cites by number.
+pub fn brief(topic: &str, papers: &[(String, String)]) -> Prompt {
+    let budget = MAX_INPUT_CHARS / papers.len().max(1);
+    let mut user = format!("Topic: {topic}\n\n");
+    for (i, (title, note)) in papers.iter().enumerate() {
+        let _ = write!(user, "# [{}] {title}\n\n{}\n\n", i + 1, truncate(note, budget).trim());
+    }
+    Prompt {
+        system: "You write a thematic brief for a researcher from their notes on several papers on one topic. Do not \
+                 summarize the papers one by one: compare them. Say what they take for granted in common, where their \
+                 results or claims disagree (and why they might, such as different data, setups or definitions), and \
+                 what questions they leave open. Cite papers by their number in brackets, like [1] or [2][3], after \
+                 every statement, and add nothing the notes do not support. Reply with a single JSON object of this \
+                 shape and nothing else:\n{\n  \"overview\": \"one paragraph on where the topic stands\",\n  \
+                 \"shared_assumptions\": [\"...\"],\n  \"conflicts\": [\"...\"],\n  \"open_questions\": \
+                 [\"...\"]\n}\nUse 2-6 items per list, and an empty list when the papers give nothing for it."
+            .to_string(),
+        user,
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+/// The context the user gave for this run with `--var`, added to a summary prompt.
+pub fn with_vars(mut prompt: Prompt, vars: &BTreeMap<String, String>) -> Prompt {
+    if !vars.is_empty() {
+        prompt.system.push_str(
+            "\nThe reader gave this context for the run. Let it decide what to emphasize and explain, but not what \
+             you say the paper claims:",
+        );
+        for (key, value) in vars {
+            let _ = write!(prompt.system, "\n- {key}: {value}");
+        }
+    }
+    prompt
+}
+
+/// Ask the model to mark the statements it is unsure of (`MABEL_UNCERTAINTY`), which
+/// [`Summary::resolve_uncertainty`](crate::summarize::Summary::resolve_uncertainty) then styles.
+pub fn with_uncertainty(mut prompt: Prompt) -> Prompt {
+    let _ = write!(
+        prompt.system,
+        "\nWhere you are not sure a statement is right, because the paper is ambiguous, the text you were given is \
+         garbled or cut off, or you are inferring rather than reading it, wrap that statement in {UNCERTAIN_OPEN} and \
+         {UNCERTAIN_CLOSE}, e.g. \"{UNCERTAIN_OPEN}Training took about three days.{UNCERTAIN_CLOSE}\". Mark only \
+         those statements, not what the paper plainly says."
+    );
+    prompt
+}
+
+/// Build a prompt from a user-written Tera template that renders the whole user message.
+///
+/// Context: `title`, `authors` (list), `journal`, `mode`, `text` (already truncated) and `vars`
+/// (from `--var`).
+pub fn from_template(
+    template: &str,
+    mode: &Mode,
+    metadata: &PaperMetadata,
+    text: &str,
+    vars: &BTreeMap<String, String>,
+) -> Result<Prompt> {
+    let mut ctx = tera::Context::new();
+    ctx.insert("title", &metadata.title);
+    ctx.insert("authors", &metadata.authors);
+    ctx.insert("journal", &metadata.journal);
+    ctx.insert("mode", mode.as_str());
+    ctx.insert("text", truncate(text, MAX_INPUT_CHARS));
+    ctx.insert("vars", vars);
+    Ok(Prompt {
+        system: "You write reading notes on research papers for a researcher's personal knowledge base.".to_string(),
+        user: tera::Tera::one_off(template, &ctx, false)?,
+        json: false,
+        images: Vec::new(),
+        batch: false,
+    })
+}
+
+/// Ask a judge model to score `candidate` (a summary of `source`) against `rubric`.
+pub fn judge(rubric: &[Criterion], source: &str, candidate: &str) -> Prompt {
+    let mut system = String::from(
+        "You are a strict reviewer grading a summary of a research paper. Score each criterion from 1 (poor) to 5 \
+         (excellent):\n",
+    );
+    for c in rubric {
+        let _ = writeln!(system, "- {}: {}", c.name, c.description);
+    }
+    let names: Vec<String> = rubric.iter().map(|c| format!("\"{}\": 1-5", c.name)).collect();
+    let _ = write!(
+        system,
+        "Reply with a single JSON object and nothing else:\n{{\"scores\": {{{}}}, \"comment\": \"one sentence\"}}",
+        names.join(", ")
+    );
+    Prompt {
+        system,
+        // Leave room for the candidate within the same budget as a summary prompt.
+        user: format!(
+            "# Source\n\n{}\n\n# Summary to grade\n\n{candidate}",
+            truncate(source, MAX_INPUT_CHARS - candidate.len().min(MAX_INPUT_CHARS / 2))
+        ),
+        json: true,
+        images: Vec::new(),
+        batch: false,
+    }
+}
+
+fn with_header(metadata: &PaperMetadata, text: &str) -> String {
+    let mut out = format!("Title: {}\n", metadata.title);
+    if !metadata.authors.is_empty() {
+        let _ = writeln!(out, "Authors: {}", metadata.authors.join(", "));
+    }
+    if let Some(venue) = &metadata.journal {
+        let _ = writeln!(out, "Venue: {venue}");
+    }
+    if let Some(comment) = &metadata.comment {
+        let _ = writeln!(out, "Comments: {comment}");
+    }
+    out.push('\n');
+    out.push_str(truncate(text, MAX_INPUT_CHARS));
+    out
+}
+
+/// Cut `text` to at most `max` bytes on a char boundary.
+pub fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
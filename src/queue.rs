@@ -0,0 +1,40 @@
+//! Papers noted without their PDF on a metered connection (`MABEL_METERED`). Each waits in the
+//! vault's `.mabel/queue.jsonl` until `mabel flush-queue` downloads the PDF and rewrites the note
+//! from the full text; a paper noted in full some other way leaves the queue then too.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, store, Result};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Queued {
+    /// What the paper was given as, which `flush-queue` processes again
+    pub input: String,
+    pub title: String,
+    /// The abstract-only note, relative to the vault
+    pub note: PathBuf,
+    pub queued: String,
+}
+
+/// Every queued paper, oldest first.
+pub async fn load(cfg: &Config) -> Result<Vec<Queued>> {
+    store::load(&cfg.queue_path()).await
+}
+
+/// Queue a paper, replacing an earlier entry for the same note.
+pub async fn add(cfg: &Config, queued: Queued) -> Result<()> {
+    let note = queued.note.clone();
+    store::replace(&cfg.queue_path(), &[queued], |q| q.note == note).await?;
+    Ok(())
+}
+
+/// Take the paper of `note` off the queue; returns whether it was on it.
+pub async fn remove(cfg: &Config, note: &Path) -> Result<bool> {
+    if !load(cfg).await?.iter().any(|q| q.note == note) {
+        return Ok(false);
+    }
+    store::replace::<Queued>(&cfg.queue_path(), &[], |q| q.note == note).await?;
+    Ok(true)
+}
@@ -0,0 +1,286 @@
+//! Recommendations: recent arXiv papers ranked by how close they are to what you have read.
+//!
+//! The reading profile is the registry's papers whose notes are marked read (`status: read` or a
+//! `read` tag) plus papers given `mabel feedback --more`; papers given `--less` pull similar ones
+//! down. Papers are compared by the cosine similarity of their embeddings, which are cached per
+//! model so a weekly digest only embeds what is new.
+
+#[cfg(feature = "embeddings")]
+use std::collections::BTreeMap;
+use std::{fmt::Write as _, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "embeddings")]
+use crate::{llm::Llm, MabelError};
+use crate::{region::RegionMarkers, store, Result};
+
+/// How many of the closest liked papers a candidate's score averages over, so one interest
+/// among several is enough to rank a paper high.
+const TOP_K: usize = 3;
+
+/// Weight of the similarity to the closest `--less` paper, subtracted from the score.
+const LESS_WEIGHT: f32 = 0.5;
+
+/// Texts per embedding request.
+#[cfg(feature = "embeddings")]
+const EMBED_BATCH: usize = 64;
+
+/// Region holding the generated list, so notes the user adds around it survive a rerun.
+const REGION: &str = "recommendations";
+
+/// One `mabel feedback` verdict.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Feedback {
+    /// arXiv id without version
+    pub paper: String,
+    pub title: String,
+    /// What gets embedded: title and abstract
+    pub text: String,
+    /// `--more` (true) or `--less` (false)
+    pub more: bool,
+    pub given: String,
+}
+
+/// Every verdict given so far; a missing store is empty.
+pub async fn load_feedback(path: &Path) -> Result<Vec<Feedback>> {
+    store::load(path).await
+}
+
+/// Store a verdict, replacing an earlier one on the same paper.
+pub async fn record_feedback(path: &Path, feedback: Feedback) -> Result<()> {
+    let paper = feedback.paper.clone();
+    store::replace(path, &[feedback], |f| f.paper == paper).await?;
+    Ok(())
+}
+
+/// Text to embed under a stable key (`arxiv:<id>`, see [`crate::registry::Entry::key`]).
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub key: String,
+    pub text: String,
+}
+
+/// What a paper is embedded as: its title, then its abstract.
+pub fn embedding_text(title: &str, abstract_text: Option<&str>) -> String {
+    match abstract_text {
+        | Some(a) if !a.trim().is_empty() => format!("{title}\n\n{}", a.trim()),
+        | _ => title.to_string(),
+    }
+}
+
+/// A paper the user liked, as shown next to the recommendations it brought up.
+#[derive(Clone, Debug)]
+pub struct Example {
+    /// Wikilink target of its note, if it has one
+    pub link: Option<String>,
+    pub title: String,
+    pub vector: Vec<f32>,
+}
+
+/// What the digest ranks by.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub liked: Vec<Example>,
+    pub disliked: Vec<Vec<f32>>,
+}
+
+impl Profile {
+    /// Score of a paper with embedding `vector`, and the liked paper closest to it.
+    pub fn score(&self, vector: &[f32]) -> (f32, Option<&Example>) {
+        let mut similar: Vec<(f32, &Example)> = self.liked.iter().map(|e| (cosine(vector, &e.vector), e)).collect();
+        similar.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let top = &similar[..similar.len().min(TOP_K)];
+        #[allow(clippy::cast_precision_loss)]
+        let like = if top.is_empty() {
+            0.0
+        } else {
+            top.iter().map(|(s, _)| s).sum::<f32>() / top.len() as f32
+        };
+        let dislike = self
+            .disliked
+            .iter()
+            .map(|d| cosine(vector, d))
+            .reduce(f32::max)
+            .unwrap_or(0.0)
+            .max(0.0);
+        (like - LESS_WEIGHT * dislike, top.first().map(|(_, e)| *e))
+    }
+}
+
+/// A ranked paper, ready for the digest note.
+#[derive(Clone, Debug, Serialize)]
+pub struct Recommendation {
+    pub arxiv: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: String,
+    pub url: String,
+    pub score: f32,
+    /// The liked paper it is closest to: a wikilink to its note, or its quoted title
+    pub like: Option<String>,
+}
+
+#[cfg(feature = "embeddings")]
+#[derive(Default, Serialize, Deserialize)]
+struct Cache {
+    entries: BTreeMap<String, Cached>,
+}
+
+#[cfg(feature = "embeddings")]
+#[derive(Serialize, Deserialize)]
+struct Cached {
+    text: String,
+    vector: Vec<f32>,
+}
+
+/// Embeddings of `items` in order, from the cache at `cache_path` where the text is unchanged and
+/// from `model` otherwise.
+#[cfg(feature = "embeddings")]
+pub async fn embed(llm: &Llm, model: &str, cache_path: &Path, items: &[Item]) -> Result<Vec<Vec<f32>>> {
+    let io_err = |source| {
+        MabelError::Io {
+            path: cache_path.to_path_buf(),
+            source,
+        }
+    };
+    let mut cache: Cache = match tokio::fs::read_to_string(cache_path).await {
+        | Ok(text) => {
+            serde_json::from_str(&text).unwrap_or_else(|e| {
+                tracing::warn!(error = %e, path = %cache_path.display(), "ignoring unreadable embedding cache");
+                Cache::default()
+            })
+        }
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => Cache::default(),
+        | Err(source) => return Err(io_err(source)),
+    };
+
+    let missing: Vec<&Item> = items
+        .iter()
+        .filter(|i| cache.entries.get(&i.key).is_none_or(|c| c.text != i.text))
+        .collect();
+    if !missing.is_empty() {
+        tracing::info!(count = missing.len(), model, "embedding papers");
+        for batch in missing.chunks(EMBED_BATCH) {
+            let texts: Vec<String> = batch.iter().map(|i| i.text.clone()).collect();
+            let vectors = llm.embed(model, &texts).await?;
+            if vectors.len() != texts.len() {
+                return Err(MabelError::Extraction {
+                    reason: format!(
+                        "{model} returned {} embeddings for {} texts",
+                        vectors.len(),
+                        texts.len()
+                    ),
+                });
+            }
+            for (item, vector) in batch.iter().zip(vectors) {
+                cache.entries.insert(
+                    item.key.clone(),
+                    Cached {
+                        text: item.text.clone(),
+                        vector,
+                    },
+                );
+            }
+        }
+        if let Some(dir) = cache_path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+        }
+        tokio::fs::write(cache_path, serde_json::to_string(&cache)?)
+            .await
+            .map_err(io_err)?;
+    }
+
+    Ok(items
+        .iter()
+        .map(|i| cache.entries.get(&i.key).map(|c| c.vector.clone()).unwrap_or_default())
+        .collect())
+}
+
+/// The digest note: the ranked papers, each with the read paper it resembles most.
+pub fn digest(
+    date: &str,
+    created: &str,
+    categories: &[String],
+    basis: usize,
+    ranked: &[Recommendation],
+    markers: &RegionMarkers,
+) -> String {
+    let mut out = format!(
+        "---\ntype: digest\ncreated: {}\ncategories: [{}]\n---\n\n# Recommendations {date}\n\n",
+        serde_json::Value::from(created),
+        categories
+            .iter()
+            .map(|c| serde_json::Value::from(c.as_str()).to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    out.push_str(&markers.begin(REGION));
+    out.push('\n');
+    let _ = writeln!(
+        out,
+        "\nRanked by similarity to {basis} paper(s) you have read or asked for more of. Tune with `mabel feedback \
+         <id> --more` or `--less`.\n"
+    );
+    if ranked.is_empty() {
+        out.push_str("No new papers.\n");
+    }
+    for (i, r) in ranked.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{}. **[{}]({})** `{}` · {:.2}",
+            i + 1,
+            r.title.replace(['[', ']'], ""),
+            r.url,
+            r.arxiv,
+            r.score
+        );
+        let authors = match r.authors.as_slice() {
+            | [] => String::new(),
+            | [one] => one.clone(),
+            | [first, ..] => format!("{first} et al."),
+        };
+        let nearest = r.like.as_deref().map(|l| format!("like {l}")).unwrap_or_default();
+        let byline: Vec<&str> = [authors.as_str(), nearest.as_str()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect();
+        if !byline.is_empty() {
+            let _ = writeln!(out, "   {}", byline.join(" · "));
+        }
+        if !r.summary.is_empty() {
+            let _ = writeln!(out, "   > {}", first_sentences(&r.summary, 300));
+        }
+    }
+    out.push('\n');
+    out.push_str(&markers.end(REGION));
+    out.push('\n');
+    out
+}
+
+pub(crate) fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        0.0
+    } else {
+        dot / denom
+    }
+}
+
+/// Whole sentences of `text` up to about `max` characters, on one line.
+fn first_sentences(text: &str, max: usize) -> String {
+    let text = crate::xml::collapse_whitespace(text);
+    if text.chars().count() <= max {
+        return text;
+    }
+    let cut: String = text.chars().take(max).collect();
+    match cut.rfind(". ") {
+        | Some(i) if i > max / 3 => cut[..=i].to_string(),
+        | _ => format!("{}…", cut.trim_end()),
+    }
+}
@@ -0,0 +1,194 @@
+//! The registry: one record per paper mabel has processed, kept in the vault at
+//! `.mabel/registry.json`. It maps papers to their notes and holds per-paper state that does not
+//! belong in the note itself, such as HTTP cache validators for `mabel update`.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use mabel_core::fingerprint::Fingerprint;
+use serde::{Deserialize, Serialize};
+
+use crate::{http::Validators, paper::PaperMetadata, source::arxiv::ArxivId, timing::RunReport, MabelError, Result};
+
+/// Serializes read-modify-write cycles within this process (batch runs, the server).
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    /// By [`Entry::key`]
+    #[serde(default)]
+    pub papers: BTreeMap<String, Entry>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Entry {
+    pub title: String,
+    /// Note path relative to the vault root
+    pub note: PathBuf,
+    /// What was passed on the command line
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arxiv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pmid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pmcid: Option<String>,
+    pub model: String,
+    pub processed: String,
+    /// [`crate::prompt::VERSION`] of the prompts the note was written with; `None` for notes from
+    /// before versions were recorded, and for adopted notes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<u32>,
+    /// arXiv version the note was written from; `arxiv` moves on to the newest version once
+    /// `mabel update` has seen it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arxiv_version: Option<u32>,
+    /// Page count and size of the paper's PDF, when it was known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<String>,
+    /// Of the opening of the paper's text, to know a PDF of it under another file name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Fingerprint>,
+    /// Last metadata check by `mabel update`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked: Option<String>,
+    /// Validators of the last metadata response, for conditional re-checks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validators: Option<Validators>,
+    /// Last citation lookup by `mabel refresh-citations`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub citations_checked: Option<String>,
+    /// Where the last run's time and money went
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<RunReport>,
+}
+
+impl Entry {
+    pub fn new(metadata: &PaperMetadata, note: PathBuf, source: &str, model: &str, processed: String) -> Self {
+        Self {
+            title: metadata.title.clone(),
+            note,
+            source: source.to_string(),
+            doi: metadata.doi.clone(),
+            arxiv: metadata.arxiv_id.clone(),
+            pmid: metadata.pmid.clone(),
+            pmcid: metadata.pmcid.clone(),
+            model: model.to_string(),
+            processed,
+            prompt_version: None,
+            arxiv_version: metadata
+                .arxiv_id
+                .as_deref()
+                .and_then(|a| ArxivId::parse(a).ok())
+                .and_then(|id| id.version()),
+            pages: metadata.pdf.as_ref().map(|p| p.pages),
+            page_size: metadata.pdf.as_ref().map(|p| p.size.clone()),
+            fingerprint: None,
+            checked: None,
+            validators: None,
+            citations_checked: None,
+            report: None,
+        }
+    }
+
+    /// Stable identity of the paper: its arXiv id (without version), PubMed ids or DOI, falling back
+    /// to the note path for papers with none of them.
+    pub fn key(&self) -> String {
+        if let Some(id) = self.arxiv.as_deref().and_then(|a| ArxivId::parse(a).ok()) {
+            return format!("arxiv:{}", id.base());
+        }
+        if let Some(pmid) = &self.pmid {
+            return format!("pmid:{pmid}");
+        }
+        if let Some(pmcid) = &self.pmcid {
+            return format!("pmcid:{pmcid}");
+        }
+        if let Some(doi) = &self.doi {
+            return format!("doi:{}", doi.to_lowercase());
+        }
+        format!("note:{}", self.note.display())
+    }
+}
+
+impl Registry {
+    /// Load the registry; a vault without one has an empty registry.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            | Ok(text) => Ok(serde_json::from_str(&text)?),
+            | Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            | Err(source) => {
+                Err(MabelError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        }
+    }
+
+    /// Load, change and save the registry in one step.
+    pub fn update<T>(path: &Path, change: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut registry = Self::load(path)?;
+        let out = change(&mut registry);
+        registry.save(path)?;
+        Ok(out)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let io_err = |source| {
+            MabelError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_err)?;
+        }
+        // Write-then-rename so an interrupted run never leaves a half-written registry.
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?).map_err(io_err)?;
+        std::fs::rename(&tmp, path).map_err(io_err)
+    }
+
+    /// The record of the paper `metadata` describes, if there is one.
+    pub fn find(&self, metadata: &PaperMetadata) -> Option<&Entry> {
+        let probe = Entry::new(metadata, PathBuf::new(), "", "", String::new());
+        self.papers.get(&probe.key())
+    }
+
+    /// The record whose fingerprint is nearest `fingerprint`, if one is close enough to be the same
+    /// paper.
+    pub fn similar(&self, fingerprint: Fingerprint) -> Option<&Entry> {
+        self.papers
+            .values()
+            .filter_map(|e| Some((e, e.fingerprint?)))
+            .filter(|&(_, f)| f.matches(fingerprint))
+            .min_by_key(|&(_, f)| f.distance(fingerprint))
+            .map(|(e, _)| e)
+    }
+
+    /// Add or replace the record for a paper. Update-related state, and the page count and
+    /// fingerprint when the paper's PDF or text was not at hand this time, is kept when a paper is processed again.
+    pub fn record(&mut self, mut entry: Entry) {
+        let key = entry.key();
+        if let Some(old) = self.papers.get(&key) {
+            if entry.pages.is_none() {
+                entry.pages = old.pages;
+                entry.page_size.clone_from(&old.page_size);
+            }
+            entry.fingerprint = entry.fingerprint.or(old.fingerprint);
+            entry.checked = entry.checked.or_else(|| old.checked.clone());
+            entry.validators = entry.validators.or_else(|| old.validators.clone());
+            entry.citations_checked = entry.citations_checked.or_else(|| old.citations_checked.clone());
+        }
+        self.papers.insert(key, entry);
+    }
+}
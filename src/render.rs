@@ -0,0 +1,59 @@
+//! Note rendering with Tera.
+//!
+//! The paper template comes from `--template` (or `templates/paper_note.md.tera` in the working
+//! directory); when neither exists the built-in copy is used, so a fresh install works without any
+//! files on disk. `mabel template show` prints the built-in templates as a starting point. The
+//! rendering itself lives in `mabel-core`, so other frontends render notes the same way.
+//!
+//! With `frontmatter_style = "properties"` the frontmatter the templates write is laid out the way
+//! Obsidian's properties editor writes it back (see [`FrontmatterStyle`]), so templates need no
+//! changes for it.
+
+use std::path::Path;
+
+pub use mabel_core::{
+    note::{FrontmatterFields, FrontmatterStyle, PropertyType},
+    render::{
+        BibEntry, BookNote, ConceptNote, MocNote, PaperNote, Renderer, ThreadNote, BOOK_TEMPLATE, CONCEPT_TEMPLATE,
+        MOC_TEMPLATE, PAPER_TEMPLATE, THREAD_TEMPLATE,
+    },
+};
+
+use crate::{
+    config::{Config, DEFAULT_TEMPLATE_PATH},
+    MabelError, Result,
+};
+
+/// Renderer for the configured paper, MOC, concept and thread templates plus the built-in book
+/// template.
+pub fn from_config(cfg: &Config) -> Result<Renderer> {
+    let paper = load_template(&cfg.template_path)?;
+    let mut renderer = Renderer::new(&paper, &cfg.region_markers)?
+        .with_vars(cfg.vars.clone())
+        .with_frontmatter_style(cfg.frontmatter_style)
+        .with_frontmatter_fields(cfg.frontmatter.clone());
+    if let Some(path) = &cfg.moc_template {
+        renderer = renderer.with_moc_template(&load_template(path)?)?;
+    }
+    if let Some(path) = &cfg.concept_template {
+        renderer = renderer.with_concept_template(&load_template(path)?)?;
+    }
+    if let Some(path) = &cfg.thread_template {
+        renderer = renderer.with_thread_template(&load_template(path)?)?;
+    }
+    Ok(renderer)
+}
+
+/// Read a template from disk. A missing file is only tolerated for the default path, which falls
+/// back to the built-in template; an explicit `--template` that does not exist is an error.
+pub fn load_template(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        | Ok(source) => Ok(source),
+        | Err(_) if path == Path::new(DEFAULT_TEMPLATE_PATH) => Ok(PAPER_TEMPLATE.to_string()),
+        | Err(_) => {
+            Err(MabelError::TemplateMissing {
+                path: path.to_path_buf(),
+            })
+        }
+    }
+}
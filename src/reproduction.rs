@@ -0,0 +1,88 @@
+//! The reproduction checklist of study-mode notes: whether a paper's code and data are available,
+//! its hyperparameters specified and its compute requirements reported. A separate pass over the
+//! experimental setup fills it in, so the answers come from the sections that say it rather than
+//! from a summary of the whole paper.
+
+use std::fmt::Write as _;
+
+use crate::{
+    llm::{self, Llm, Usage},
+    paper::{PaperMetadata, PaperStructure},
+    prompt,
+    summarize::Reproduction,
+    Result,
+};
+
+/// Headings of the sections that describe how the experiments were run, lowercased.
+const SETUP_HEADINGS: &[&str] = &[
+    "experiment",
+    "setup",
+    "set-up",
+    "implementation",
+    "training",
+    "hyperparameter",
+    "hyper-parameter",
+    "reproducib",
+    "availability",
+    "dataset",
+    "compute",
+];
+
+/// Phrases of paragraphs elsewhere that point at code or data, often in the abstract or a footnote.
+const AVAILABILITY_HINTS: &[&str] = &[
+    "github.com",
+    "gitlab.com",
+    "huggingface.co",
+    "zenodo.org",
+    "code is available",
+    "code will be",
+    "publicly available",
+];
+
+/// The experimental setup of a paper: the sections whose headings say so (with their
+/// subsections), plus paragraphs elsewhere that mention where code or data can be found. `None`
+/// when the paper has no such section.
+pub fn setup_text(structure: &PaperStructure) -> Option<String> {
+    let mut out = String::new();
+    let mut within: Option<u8> = None;
+    for section in &structure.sections {
+        let heading = section.heading.to_lowercase();
+        within = within.filter(|&level| section.level > level);
+        if within.is_none() && SETUP_HEADINGS.iter().any(|h| heading.contains(h)) {
+            within = Some(section.level);
+        }
+        if within.is_some() {
+            let _ = write!(out, "## {}\n\n{}\n\n", section.heading, section.text);
+        }
+    }
+    if out.is_empty() {
+        return None;
+    }
+    let elsewhere = structure
+        .abstract_text
+        .iter()
+        .chain(structure.sections.iter().map(|s| &s.text))
+        .flat_map(|text| text.split("\n\n"))
+        .filter(|p| {
+            let p = p.to_lowercase();
+            AVAILABILITY_HINTS.iter().any(|h| p.contains(h))
+        })
+        .filter(|p| !out.contains(p.trim()))
+        .collect::<Vec<_>>();
+    if !elsewhere.is_empty() {
+        out.push_str("## Elsewhere in the paper\n\n");
+        out.push_str(&elsewhere.join("\n\n"));
+    }
+    Some(out.trim_end().to_string())
+}
+
+/// Ask the model for the checklist of the paper whose setup is `setup`; `None` when the reply is
+/// not a checklist.
+pub async fn extract(llm: &Llm, metadata: &PaperMetadata, setup: &str) -> Result<(Option<Reproduction>, Usage)> {
+    let completion = llm.complete(&prompt::reproduction(metadata, setup)).await?;
+    let candidate = llm::json_object(&completion.text);
+    let reproduction = serde_json::from_str(candidate)
+        .inspect_err(|e| tracing::warn!(error = %e, "reproduction checklist reply was not the requested JSON"))
+        .ok();
+    Ok((reproduction, completion.usage))
+}
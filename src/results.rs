@@ -0,0 +1,251 @@
+//! Leaderboards: quantitative results (method, dataset, metric, value) extracted from each
+//! processed paper into `.mabel/results.jsonl`, and one note per topic tag comparing every method
+//! reported for it across the vault.
+
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    llm::{self, Llm, Usage},
+    paper::PaperMetadata,
+    prompt,
+    region::RegionMarkers,
+    store, MabelError, Result,
+};
+
+/// Region holding the generated tables, so text the user adds around them survives updates.
+const REGION: &str = "leaderboard";
+
+/// One result as the model returns it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResultRow {
+    pub method: String,
+    pub dataset: String,
+    pub metric: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub value: String,
+    #[serde(default)]
+    pub higher_is_better: Option<bool>,
+    /// Whether the method is the paper's own rather than a baseline it compares against
+    #[serde(default)]
+    pub proposed: bool,
+}
+
+/// A stored result, with the paper it came from and the paper's topics.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResultRecord {
+    pub method: String,
+    pub dataset: String,
+    pub metric: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub higher_is_better: Option<bool>,
+    #[serde(default)]
+    pub proposed: bool,
+    /// Wikilink target of the paper's note
+    pub note: String,
+    pub title: String,
+    /// Tags of the paper; each has a leaderboard
+    pub topics: Vec<String>,
+    pub extracted: String,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    #[serde(default)]
+    results: Vec<ResultRow>,
+}
+
+/// Ask the model for the paper's results.
+pub async fn extract(llm: &Llm, metadata: &PaperMetadata, text: &str) -> Result<(Vec<ResultRow>, Usage)> {
+    if text.trim().is_empty() {
+        return Err(MabelError::Extraction {
+            reason: format!("no text to extract results from for {:?}", metadata.title),
+        });
+    }
+    let completion = llm.complete(&prompt::results(metadata, text)).await?;
+    let candidate = llm::json_object(&completion.text);
+    let mut rows = serde_json::from_str::<Reply>(candidate).map_or_else(
+        |e| {
+            tracing::warn!(error = %e, "results reply was not the requested JSON");
+            Vec::new()
+        },
+        |r| r.results,
+    );
+    rows.retain(|r| {
+        ![&r.method, &r.dataset, &r.metric, &r.value]
+            .iter()
+            .any(|f| f.trim().is_empty())
+    });
+    Ok((rows, completion.usage))
+}
+
+/// Every record in the store; a missing store is empty.
+pub async fn load(path: &Path) -> Result<Vec<ResultRecord>> {
+    store::load(path).await
+}
+
+/// Store `records` for the note `note`, replacing whatever was stored for it before. Returns the
+/// whole store.
+pub async fn replace(path: &Path, note: &str, records: &[ResultRecord]) -> Result<Vec<ResultRecord>> {
+    store::replace(path, records, |r| r.note == note).await
+}
+
+/// The leaderboard note for `topic`: one table per dataset, methods as rows and metrics as
+/// columns, with the best value of each column in bold when the metric's direction is known.
+pub fn leaderboard(topic: &str, records: &[ResultRecord], updated: &str, markers: &RegionMarkers) -> String {
+    let rows: Vec<&ResultRecord> = records.iter().filter(|r| r.topics.iter().any(|t| t == topic)).collect();
+    let mut by_dataset: BTreeMap<&str, Vec<&ResultRecord>> = BTreeMap::new();
+    for row in &rows {
+        by_dataset.entry(row.dataset.trim()).or_default().push(row);
+    }
+    let papers = {
+        let mut notes: Vec<&str> = rows.iter().map(|r| r.note.as_str()).collect();
+        notes.sort_unstable();
+        notes.dedup();
+        notes.len()
+    };
+
+    let mut out = format!(
+        "---\ntype: leaderboard\ntopic: {}\nupdated: {}\n---\n\n# Leaderboard: {topic}\n\n",
+        serde_json::Value::from(topic),
+        serde_json::Value::from(updated),
+    );
+    out.push_str(&markers.begin(REGION));
+    out.push('\n');
+    let _ = writeln!(
+        out,
+        "\n{papers} paper(s). Rewritten by mabel whenever a paper tagged `{topic}` is processed; `*` marks a paper's \
+         own method.\n"
+    );
+    if by_dataset.is_empty() {
+        out.push_str("No results yet.\n\n");
+    }
+    for (dataset, rows) in by_dataset {
+        let _ = writeln!(out, "## {}\n", cell(dataset));
+        out.push_str(&table(&rows));
+        out.push('\n');
+    }
+    out.push_str(&markers.end(REGION));
+    out.push('\n');
+    out
+}
+
+/// A table row: one method as reported by one paper, with its value for each metric column.
+struct Line<'a> {
+    proposed: bool,
+    values: Vec<Option<&'a str>>,
+}
+
+/// One dataset's table. Metrics are ordered by how many methods report them.
+fn table(rows: &[&ResultRecord]) -> String {
+    let mut metrics: Vec<(&str, usize)> = Vec::new();
+    for row in rows {
+        match metrics
+            .iter_mut()
+            .find(|(m, _)| m.eq_ignore_ascii_case(row.metric.trim()))
+        {
+            | Some((_, n)) => *n += 1,
+            | None => metrics.push((row.metric.trim(), 1)),
+        }
+    }
+    metrics.sort_by(|a, b| b.1.cmp(&a.1));
+    let metrics: Vec<&str> = metrics.into_iter().map(|(m, _)| m).collect();
+
+    let mut lines: BTreeMap<(String, &str), Line<'_>> = BTreeMap::new();
+    for row in rows {
+        let column = metrics
+            .iter()
+            .position(|m| m.eq_ignore_ascii_case(row.metric.trim()))
+            .unwrap_or_default();
+        let line = lines
+            .entry((row.method.trim().to_string(), row.note.as_str()))
+            .or_insert_with(|| {
+                Line {
+                    proposed: row.proposed,
+                    values: vec![None; metrics.len()],
+                }
+            });
+        line.values[column] = Some(row.value.trim());
+    }
+
+    let directions: Vec<Option<bool>> = metrics
+        .iter()
+        .map(|metric| {
+            rows.iter()
+                .find(|r| r.metric.trim().eq_ignore_ascii_case(metric))
+                .and_then(|r| r.higher_is_better)
+        })
+        .collect();
+    let best: Vec<Option<f64>> = (0..metrics.len())
+        .map(|column| {
+            let values = lines.values().filter_map(|l| l.values[column].and_then(numeric));
+            if directions[column]? {
+                values.reduce(f64::max)
+            } else {
+                values.reduce(f64::min)
+            }
+        })
+        .collect();
+
+    // Rank by the most reported metric, best first; rows without a value for it go last.
+    let mut lines: Vec<_> = lines.into_iter().collect();
+    if let Some(higher) = directions.first().copied().flatten() {
+        lines.sort_by(|(_, a), (_, b)| {
+            match (a.values[0].and_then(numeric), b.values[0].and_then(numeric)) {
+                | (Some(x), Some(y)) if higher => y.total_cmp(&x),
+                | (Some(x), Some(y)) => x.total_cmp(&y),
+                | (a, b) => b.is_some().cmp(&a.is_some()),
+            }
+        });
+    }
+
+    let mut out = String::from("| Method | Paper |");
+    for metric in &metrics {
+        let _ = write!(out, " {} |", cell(metric));
+    }
+    out.push_str("\n| --- | --- |");
+    out.push_str(&" --- |".repeat(metrics.len()));
+    out.push('\n');
+    for ((method, note), line) in &lines {
+        let star = if line.proposed { "*" } else { "" };
+        let _ = write!(out, "| {}{star} | [[{note}]] |", cell(method));
+        for (value, best) in line.values.iter().zip(&best) {
+            match value {
+                | Some(v) if best.is_some() && numeric(v) == *best => {
+                    let _ = write!(out, " **{}** |", cell(v));
+                }
+                | Some(v) => {
+                    let _ = write!(out, " {} |", cell(v));
+                }
+                | None => out.push_str("  |"),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// The leading number of a value such as `91.2%` or `3.1 ± 0.2`.
+fn numeric(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let end = value
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+        .map_or(value.len(), |(i, _)| i);
+    value[..end].parse().ok()
+}
+
+/// Text safe inside a Markdown table cell.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        | serde_json::Value::String(s) => s,
+        | serde_json::Value::Null => String::new(),
+        | other => other.to_string(),
+    })
+}
@@ -0,0 +1,117 @@
+//! Trying a request again when it failed in a way that may pass: the server rate-limited it (429),
+//! was overloaded or down (5xx), or did not answer in time. Each retry waits out the server's
+//! `Retry-After` when it sent one, and otherwise an exponential backoff with jitter, so papers
+//! processed side by side do not all come back at once. Any other failure is returned at once.
+//!
+//! Every request to arXiv and the other metadata sources, to GROBID and to the model goes through
+//! [`run`], up to `MABEL_HTTP_RETRIES` times again.
+
+use std::{future::Future, time::Duration};
+
+use backoff::{backoff::Backoff, ExponentialBackoff, ExponentialBackoffBuilder};
+use reqwest::StatusCode;
+
+use crate::{MabelError, Result};
+
+/// The first wait, before jitter; each one after is twice as long.
+const FIRST_WAIT: Duration = Duration::from_secs(1);
+
+/// The longest wait, whatever the server asks for.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// How far a wait may be off the schedule either way, as a fraction of it.
+const JITTER: f64 = 0.5;
+
+/// Run `attempt`, and again up to `retries` times while it fails in a way that may pass. Each
+/// failed attempt is logged with the wait before the next.
+pub async fn run<T, F, Fut>(retries: u32, mut attempt: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut schedule = backoff(None);
+    let mut tried = 0;
+    loop {
+        let e = match attempt().await {
+            | Err(e) if tried < retries => e,
+            | out => return out,
+        };
+        let Verdict::Retry { after } = verdict(&e) else {
+            return Err(e);
+        };
+        tried += 1;
+        let wait = after
+            .or_else(|| schedule.next_backoff())
+            .unwrap_or(MAX_WAIT)
+            .min(MAX_WAIT);
+        tracing::warn!(
+            attempt = tried,
+            of = retries + 1,
+            wait_ms = u64::try_from(wait.as_millis()).unwrap_or(u64::MAX),
+            error = %e,
+            "request failed; retrying"
+        );
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// The backoff schedule [`run`] follows, for clients that retry on their own (the OpenAI one
+/// does). With `budget`, retrying stops once that much time has passed.
+pub fn backoff(budget: Option<Duration>) -> ExponentialBackoff {
+    ExponentialBackoffBuilder::new()
+        .with_initial_interval(FIRST_WAIT)
+        .with_multiplier(2.0)
+        .with_randomization_factor(JITTER)
+        .with_max_interval(MAX_WAIT)
+        .with_max_elapsed_time(budget)
+        .build()
+}
+
+/// About as long as `retries` waits on the schedule take, for [`backoff`]'s budget.
+pub fn budget(retries: u32) -> Duration {
+    (0..retries)
+        .map(|n| FIRST_WAIT.saturating_mul(2u32.saturating_pow(n)).min(MAX_WAIT))
+        .sum()
+}
+
+/// Whether a request that failed with some error is worth sending again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Verdict {
+    /// After `after` when the server said how long to wait, or else on the schedule
+    Retry {
+        after: Option<Duration>,
+    },
+    Fatal,
+}
+
+fn verdict(e: &MabelError) -> Verdict {
+    match e {
+        | MabelError::HttpStatus {
+            status, retry_after, ..
+        } if retryable(*status) => {
+            Verdict::Retry {
+                after: retry_after.map(|secs| Duration::from_secs(u64::from(secs))),
+            }
+        }
+        | MabelError::Http { source, .. } => transport(source),
+        #[cfg(feature = "openai")]
+        | MabelError::OpenAi(async_openai::error::OpenAIError::Reqwest(source)) => transport(source),
+        #[cfg(feature = "ollama")]
+        | MabelError::Ollama(ollama_rs::error::OllamaError::ReqwestError(source)) => transport(source),
+        | _ => Verdict::Fatal,
+    }
+}
+
+/// Rate limits and server errors may pass; 501 (the server cannot do this at all) will not.
+fn retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || (status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED)
+}
+
+/// A request that timed out or could not connect may get through next time; one the server
+/// answered with a status is judged by [`retryable`].
+fn transport(source: &reqwest::Error) -> Verdict {
+    if source.is_timeout() || source.is_connect() || source.status().is_some_and(retryable) {
+        return Verdict::Retry { after: None };
+    }
+    Verdict::Fatal
+}
@@ -0,0 +1,136 @@
+//! Per-paper model routing: cheap models for easy inputs, large-context models for long ones.
+//!
+//! The policy is a comma-separated list of `condition=model` rules read from `MABEL_ROUTING`,
+//! tried in order; the first match picks the model and no match keeps the configured one:
+//!
+//! ```text
+//! MABEL_ROUTING="abstract=gpt-4o-mini, chars>150000=gpt-4.1, sections>40=gpt-4.1, pages>60=gpt-4.1"
+//! ```
+//!
+//! Conditions:
+//! - `abstract`: no full text is available, only the abstract
+//! - `chars>N` / `chars<N`: length of the text that would be summarized
+//! - `sections>N`: number of sections in the extracted full text
+//! - `pages>N`: number of pages of the paper's PDF, when it is known
+//! - `default`: always matches (useful as an explicit last rule)
+
+use std::{fmt, str::FromStr};
+
+use crate::{source::ResolvedPaper, MabelError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Condition {
+    AbstractOnly,
+    CharsAbove(usize),
+    CharsBelow(usize),
+    SectionsAbove(usize),
+    PagesAbove(usize),
+    Always,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Route {
+    when: Condition,
+    model: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoutingPolicy {
+    routes: Vec<Route>,
+}
+
+impl RoutingPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// The model for `paper`, given the text that will be summarized; `None` keeps the default.
+    pub fn route(&self, paper: &ResolvedPaper, text_chars: usize) -> Option<&str> {
+        let sections = paper.structure.as_ref().map_or(0, |s| s.sections.len());
+        let pages = paper.metadata.pdf.as_ref().map_or(0, |p| p.pages as usize);
+        self.routes
+            .iter()
+            .find(|r| {
+                match r.when {
+                    | Condition::AbstractOnly => sections == 0,
+                    | Condition::CharsAbove(n) => text_chars > n,
+                    | Condition::CharsBelow(n) => text_chars < n,
+                    | Condition::SectionsAbove(n) => sections > n,
+                    | Condition::PagesAbove(n) => pages > n,
+                    | Condition::Always => true,
+                }
+            })
+            .map(|r| r.model.as_str())
+    }
+}
+
+impl FromStr for RoutingPolicy {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |rule: &str, why: &str| {
+            MabelError::Config {
+                msg: format!("invalid routing rule {rule:?}: {why}"),
+            }
+        };
+        let mut routes = Vec::new();
+        for rule in s.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            let (cond, model) = rule
+                .split_once('=')
+                .ok_or_else(|| invalid(rule, "expected `condition=model`"))?;
+            let (cond, model) = (cond.trim(), model.trim());
+            if model.is_empty() {
+                return Err(invalid(rule, "missing model"));
+            }
+            let number = |v: &str| {
+                v.trim()
+                    .replace('_', "")
+                    .parse::<usize>()
+                    .map_err(|_| invalid(rule, "expected a number"))
+            };
+            let when = if cond.eq_ignore_ascii_case("abstract") {
+                Condition::AbstractOnly
+            } else if cond.eq_ignore_ascii_case("default") {
+                Condition::Always
+            } else if let Some(n) = cond.strip_prefix("chars>") {
+                Condition::CharsAbove(number(n)?)
+            } else if let Some(n) = cond.strip_prefix("chars<") {
+                Condition::CharsBelow(number(n)?)
+            } else if let Some(n) = cond.strip_prefix("sections>") {
+                Condition::SectionsAbove(number(n)?)
+            } else if let Some(n) = cond.strip_prefix("pages>") {
+                Condition::PagesAbove(number(n)?)
+            } else {
+                return Err(invalid(
+                    rule,
+                    "unknown condition (expected abstract, chars>N, chars<N, sections>N, pages>N or default)",
+                ));
+            };
+            routes.push(Route {
+                when,
+                model: model.to_string(),
+            });
+        }
+        Ok(Self { routes })
+    }
+}
+
+impl fmt::Display for RoutingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, r) in self.routes.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            match r.when {
+                | Condition::AbstractOnly => f.write_str("abstract")?,
+                | Condition::CharsAbove(n) => write!(f, "chars>{n}")?,
+                | Condition::CharsBelow(n) => write!(f, "chars<{n}")?,
+                | Condition::SectionsAbove(n) => write!(f, "sections>{n}")?,
+                | Condition::PagesAbove(n) => write!(f, "pages>{n}")?,
+                | Condition::Always => f.write_str("default")?,
+            }
+            write!(f, "={}", r.model)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Which paper goes next when more are waiting than are processed at once, in `mabel serve` and
+//! `mabel batch`. Each paper comes from a source (the dashboard, a feed import, a list file) with a
+//! [`Priority`]. A free slot goes to the highest priority waiting and, among the sources waiting
+//! at that priority, to the one served longest ago, so a paper asked for by hand takes its turn
+//! beside a 500-paper import instead of after it. A source's own papers go in the order they came.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Bulk imports, done when nothing else is waiting
+    Low,
+    #[default]
+    Normal,
+    /// Someone is waiting on it
+    High,
+}
+
+/// Hands out a fixed number of slots to papers waiting for one.
+pub struct Scheduler {
+    state: Mutex<State>,
+}
+
+struct State {
+    free: usize,
+    /// In the order they came
+    waiting: Vec<Waiter>,
+    /// When each source was last given a slot, as a count of the slots given
+    served: HashMap<String, u64>,
+    given: u64,
+}
+
+struct Waiter {
+    source: String,
+    priority: Priority,
+    slot: oneshot::Sender<Slot>,
+}
+
+/// A paper's turn to be processed; the next one waiting gets it when this is dropped.
+pub struct Slot {
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+impl Scheduler {
+    /// A scheduler processing `slots` papers at once.
+    pub fn new(slots: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                free: slots.max(1),
+                waiting: Vec::new(),
+                served: HashMap::new(),
+                given: 0,
+            }),
+        })
+    }
+
+    /// Wait for a turn for a paper from `source`.
+    pub async fn slot(self: &Arc<Self>, source: &str, priority: Priority) -> Slot {
+        let waiting = {
+            let mut state = self.lock();
+            if state.free > 0 && state.waiting.is_empty() {
+                state.free -= 1;
+                state.serve(source);
+                return self.new_slot();
+            }
+            let (slot, waiting) = oneshot::channel();
+            state.waiting.push(Waiter {
+                source: source.to_string(),
+                priority,
+                slot,
+            });
+            tracing::debug!(source, waiting = state.waiting.len(), "waiting for a turn");
+            waiting
+        };
+        // A waiter is only taken off the list to be sent its slot, so the sender is never
+        // dropped first.
+        waiting.await.unwrap_or(Slot { scheduler: None })
+    }
+
+    fn new_slot(self: &Arc<Self>) -> Slot {
+        Slot {
+            scheduler: Some(self.clone()),
+        }
+    }
+
+    /// Pass a slot given back to the next paper waiting, or keep it free.
+    fn release(self: &Arc<Self>) {
+        let mut state = self.lock();
+        while let Some(waiter) = state.next() {
+            match waiter.slot.send(self.new_slot()) {
+                | Ok(()) => return,
+                // It stopped waiting; the slot goes to the next one instead.
+                | Err(mut slot) => slot.scheduler = None,
+            }
+        }
+        state.free += 1;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl State {
+    /// Take the waiter whose turn it is off the list.
+    fn next(&mut self) -> Option<Waiter> {
+        let top = self.waiting.iter().map(|w| w.priority).max()?;
+        let (i, _) = self
+            .waiting
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.priority == top)
+            .min_by_key(|(_, w)| self.served.get(&w.source).copied().unwrap_or(0))?;
+        let waiter = self.waiting.remove(i);
+        self.serve(&waiter.source);
+        Some(waiter)
+    }
+
+    fn serve(&mut self, source: &str) {
+        self.given += 1;
+        self.served.insert(source.to_string(), self.given);
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Frontmatter schemas: a TOML file (`MABEL_FRONTMATTER_SCHEMA`) describing the frontmatter a
+//! vault's Dataview queries or linters expect. Paper notes are checked against it when rendered,
+//! and one that does not match is not written.
+//!
+//! ```toml
+//! # Fields the schema does not list are allowed unless this is false
+//! allow_unknown = true
+//!
+//! [fields.title]
+//! type = "string"          # string, integer, number, boolean, date, list or any
+//! required = true
+//!
+//! [fields.tags]
+//! type = "list"
+//! items = "string"         # type of each list item
+//! allowed = ["paper", "ml", "nlp"]
+//!
+//! [fields.year]
+//! type = "integer"
+//! nullable = true          # null (or an empty value) is accepted
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::{vault, MabelError, Result};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    #[serde(default = "yes")]
+    allow_unknown: bool,
+    #[serde(default)]
+    fields: BTreeMap<String, Field>,
+    /// The file it was read from, for error messages
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Field {
+    #[serde(rename = "type")]
+    kind: Kind,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    nullable: bool,
+    /// Type of each item of a list
+    items: Option<Kind>,
+    /// Values the field (or each item of a list) may take, compared as text
+    #[serde(default)]
+    allowed: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// `YYYY-MM-DD`, or a date and time in RFC 3339
+    Date,
+    List,
+    Any,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            | Self::String => "a string",
+            | Self::Integer => "an integer",
+            | Self::Number => "a number",
+            | Self::Boolean => "true or false",
+            | Self::Date => "a date",
+            | Self::List => "a list",
+            | Self::Any => "any value",
+        })
+    }
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl Schema {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|source| {
+            MabelError::Io {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?;
+        let mut schema: Self = toml::from_str(&text).map_err(|e| {
+            MabelError::Config {
+                msg: format!("{}: {}", path.display(), e.message()),
+            }
+        })?;
+        schema.path = path.to_path_buf();
+        Ok(schema)
+    }
+
+    /// Check the frontmatter of `note`; fails with every way it does not match.
+    pub fn validate(&self, note: &str) -> Result<()> {
+        let problems = self.problems(note);
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(MabelError::FrontmatterSchema {
+            schema: self.path.clone(),
+            problems,
+        })
+    }
+
+    /// Every way the frontmatter of `note` does not match, one sentence each.
+    pub fn problems(&self, note: &str) -> Vec<String> {
+        let (yaml, _) = vault::split_frontmatter(note);
+        let frontmatter = match yaml.map(serde_yaml::from_str::<BTreeMap<String, Value>>) {
+            | Some(Ok(map)) => map,
+            | Some(Err(e)) => return vec![format!("the frontmatter is not valid YAML: {e}")],
+            | None => BTreeMap::new(),
+        };
+        let mut problems = Vec::new();
+        for (name, field) in &self.fields {
+            match frontmatter.get(name) {
+                | None if field.required => problems.push(format!("`{name}` is required")),
+                | None => {}
+                | Some(value) => field.check(name, value, &mut problems),
+            }
+        }
+        if !self.allow_unknown {
+            for name in frontmatter.keys().filter(|k| !self.fields.contains_key(*k)) {
+                problems.push(format!("`{name}` is not in the schema"));
+            }
+        }
+        problems
+    }
+}
+
+impl Field {
+    fn check(&self, name: &str, value: &Value, problems: &mut Vec<String>) {
+        if value.is_null() {
+            if !self.nullable {
+                problems.push(format!("`{name}` is empty"));
+            }
+            return;
+        }
+        if !matches(self.kind, value) {
+            problems.push(format!("`{name}` should be {}, not {}", self.kind, describe(value)));
+            return;
+        }
+        let items: Vec<&Value> = match value {
+            | Value::Sequence(items) => items.iter().collect(),
+            | other => vec![other],
+        };
+        for (i, item) in items.iter().enumerate() {
+            let at = if value.is_sequence() {
+                format!("`{name}[{i}]`")
+            } else {
+                format!("`{name}`")
+            };
+            if value.is_sequence() {
+                match self.items {
+                    | _ if item.is_null() && self.nullable => continue,
+                    | Some(kind) if !matches(kind, item) => {
+                        problems.push(format!("{at} should be {kind}, not {}", describe(item)));
+                        continue;
+                    }
+                    | _ => {}
+                }
+            }
+            if !self.allowed.is_empty() && !self.allowed.contains(&text(item)) {
+                problems.push(format!(
+                    "{at} is {}, which is not one of: {}",
+                    describe(item),
+                    self.allowed.join(", ")
+                ));
+            }
+        }
+    }
+}
+
+fn matches(kind: Kind, value: &Value) -> bool {
+    match kind {
+        | Kind::String => value.is_string(),
+        | Kind::Integer => value.is_i64() || value.is_u64(),
+        | Kind::Number => value.is_number(),
+        | Kind::Boolean => value.is_bool(),
+        | Kind::Date => {
+            value.as_str().is_some_and(|s| {
+                chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()
+                    || chrono::DateTime::parse_from_rfc3339(s).is_ok()
+            })
+        }
+        | Kind::List => value.is_sequence(),
+        | Kind::Any => true,
+    }
+}
+
+/// A value as `allowed` lists it.
+fn text(value: &Value) -> String {
+    match value {
+        | Value::String(s) => s.clone(),
+        | other => serde_yaml::to_string(other).unwrap_or_default().trim().to_string(),
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        | Value::String(s) => format!("{s:?}"),
+        | Value::Sequence(_) => "a list".to_string(),
+        | Value::Mapping(_) => "a mapping".to_string(),
+        | other => text(other),
+    }
+}
@@ -0,0 +1,94 @@
+//! API keys, either given directly or fetched from a password manager at runtime
+//! (`openai_api_key_cmd = "op read op://Private/OpenAI/credential"`), so they never have to be
+//! stored in a config file.
+
+use std::{
+    collections::HashMap,
+    env, fmt,
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use crate::{MabelError, Result};
+
+/// Output of each secret command run so far; a password manager is asked at most once per process.
+static FETCHED: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// A secret value. `Debug` and `Display` never show it, so it stays out of logs and error messages.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// The secret in env var `key`, or else the output of the command in `<key>_CMD`.
+pub fn from_env(key: &str) -> Result<Option<Secret>> {
+    if let Ok(value) = env::var(key) {
+        return Ok(Some(Secret(value)));
+    }
+    env::var(format!("{key}_CMD"))
+        .ok()
+        .map(|cmd| from_command(&cmd))
+        .transpose()
+}
+
+/// Run `cmd` through the shell and take the first line of its output as the secret. The command
+/// keeps the terminal for stdin/stderr, so password managers can prompt to unlock.
+pub fn from_command(cmd: &str) -> Result<Secret> {
+    let mut fetched = FETCHED.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(value) = fetched.get_or_insert_with(HashMap::new).get(cmd) {
+        return Ok(Secret(value.clone()));
+    }
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let failed = |reason: String| {
+        MabelError::Config {
+            msg: format!("secret command `{cmd}` {reason}"),
+        }
+    };
+    let output = Command::new(shell)
+        .args([flag, cmd])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| failed(format!("could not be run: {e}")))?;
+    if !output.status.success() {
+        return Err(failed(format!("failed ({})", output.status)));
+    }
+    let value = String::from_utf8(output.stdout)
+        .map_err(|_| failed("printed something that is not UTF-8".to_string()))?
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    if value.is_empty() {
+        return Err(failed("printed nothing".to_string()));
+    }
+    fetched
+        .get_or_insert_with(HashMap::new)
+        .insert(cmd.to_string(), value.clone());
+    Ok(Secret(value))
+}
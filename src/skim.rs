@@ -0,0 +1,161 @@
+//! Skim mode (`--mode skim`): a first impression of a paper from a handful of its pages, shown as
+//! images to a vision model, for triaging conference proceedings where full extraction is overkill.
+//!
+//! Pages are picked from the PDF's text layer — the first page, the pages densest in figures and
+//! the one with the conclusion — and rendered at screen resolution. This uses poppler's
+//! `pdftotext`, `pdfimages` and `pdftoppm`, which must be on PATH.
+
+use std::{collections::HashMap, path::Path, process::Stdio, time::Duration};
+
+use crate::{MabelError, Result};
+
+/// How long picking and rendering pages may take before the impression is written from the
+/// abstract instead, so a skim stays within half a minute.
+pub const BUDGET: Duration = Duration::from_secs(15);
+
+/// Figure pages shown besides the first page and the conclusion.
+const FIGURE_PAGES: usize = 2;
+
+/// Enough for a model to read headings and make out plots, small enough to keep requests cheap.
+const DPI: &str = "72";
+
+/// A rendered page.
+pub struct Page {
+    /// 1-based
+    pub number: usize,
+    /// Why it was picked, as told to the model
+    pub reason: &'static str,
+    pub png: Vec<u8>,
+}
+
+/// Pick the pages of `pdf` worth a look and render them, in page order.
+pub async fn sample(pdf: &Path) -> Result<Vec<Page>> {
+    let text = text_layer(pdf).await?;
+    // pdftotext ends every page with a form feed.
+    let pages: Vec<&str> = text.trim_end_matches('\x0c').split('\x0c').collect();
+    let images = image_counts(pdf)
+        .await
+        .inspect_err(|e| tracing::debug!(error = %e, "no image counts; picking figure pages by captions only"))
+        .unwrap_or_default();
+    let mut sampled = Vec::new();
+    for (number, reason) in pick(&pages, &images) {
+        let page = number.to_string();
+        let args = [
+            "-png".as_ref(),
+            "-r".as_ref(),
+            DPI.as_ref(),
+            "-f".as_ref(),
+            page.as_ref(),
+            "-l".as_ref(),
+            page.as_ref(),
+            "-singlefile".as_ref(),
+            pdf.as_os_str(),
+        ];
+        let png = run("pdftoppm", &args).await?;
+        sampled.push(Page { number, reason, png });
+    }
+    tracing::debug!(
+        pages = ?sampled.iter().map(|p| p.number).collect::<Vec<_>>(),
+        of = pages.len(),
+        "pages picked"
+    );
+    Ok(sampled)
+}
+
+/// The text layer of `pdf` as `pdftotext` lays it out, with a form feed after every page.
+pub async fn text_layer(pdf: &Path) -> Result<String> {
+    let text = run("pdftotext", &["-layout".as_ref(), pdf.as_os_str(), "-".as_ref()]).await?;
+    Ok(String::from_utf8_lossy(&text).into_owned())
+}
+
+/// Page numbers to show and why: the first page, up to [`FIGURE_PAGES`] pages with the most
+/// figures, and the last page with a conclusion heading.
+fn pick(pages: &[&str], images: &HashMap<usize, usize>) -> Vec<(usize, &'static str)> {
+    let mut picks = vec![(1, "first page")];
+    let conclusion = (2..=pages.len())
+        .rev()
+        .find(|&n| pages[n - 1].lines().any(is_conclusion_heading));
+    let mut figures: Vec<(usize, usize)> = (2..=pages.len())
+        .filter(|&n| Some(n) != conclusion)
+        .map(|n| (n, images.get(&n).copied().unwrap_or(0) + captions(pages[n - 1])))
+        .filter(|&(_, score)| score > 0)
+        .collect();
+    // Most figures first; the earlier page on ties.
+    figures.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    picks.extend(figures.into_iter().take(FIGURE_PAGES).map(|(n, _)| (n, "figures")));
+    picks.extend(conclusion.map(|n| (n, "conclusion")));
+    picks.sort_unstable();
+    picks
+}
+
+/// A line like `5 Conclusion`, `VI. CONCLUSIONS AND FUTURE WORK` or `Discussion`.
+fn is_conclusion_heading(line: &str) -> bool {
+    let line = line.trim();
+    if line.len() > 48 {
+        return false;
+    }
+    let heading = line
+        .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | 'I' | 'V' | 'X'))
+        .trim_start()
+        .to_lowercase();
+    [
+        "conclusion",
+        "concluding remarks",
+        "discussion",
+        "summary and conclusion",
+    ]
+    .iter()
+    .any(|h| heading.starts_with(h))
+}
+
+/// Figure captions on a page: lines starting `Figure 3` or `Fig. 3`.
+fn captions(page: &str) -> usize {
+    page.lines()
+        .filter(|l| {
+            let l = l.trim_start();
+            ["Figure ", "Fig. ", "FIGURE "].iter().any(|p| {
+                l.strip_prefix(p)
+                    .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            })
+        })
+        .count()
+}
+
+/// Embedded images per page, from `pdfimages -list`. Masks are not counted.
+async fn image_counts(pdf: &Path) -> Result<HashMap<usize, usize>> {
+    let list = run("pdfimages", &["-list".as_ref(), pdf.as_os_str()]).await?;
+    let mut counts = HashMap::new();
+    // Two header lines, then `page num type ...` rows.
+    for row in String::from_utf8_lossy(&list).lines().skip(2) {
+        let mut cols = row.split_whitespace();
+        if let (Some(Ok(page)), _, Some("image")) = (cols.next().map(str::parse), cols.next(), cols.next()) {
+            *counts.entry(page).or_insert(0) += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Run a poppler tool and return what it wrote to stdout.
+pub async fn run(tool: &str, args: &[&std::ffi::OsStr]) -> Result<Vec<u8>> {
+    let output = tokio::process::Command::new(tool)
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| {
+            MabelError::Extraction {
+                reason: format!("`{tool}` from poppler-utils is needed on PATH: {e}"),
+            }
+        })?;
+    if !output.status.success() {
+        return Err(MabelError::Extraction {
+            reason: format!(
+                "`{tool}` failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    Ok(output.stdout)
+}
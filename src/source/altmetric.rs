@@ -0,0 +1,38 @@
+//! Altmetric attention scores (`api.altmetric.com`): how much a paper is discussed in news, blogs
+//! and social media. The free, keyless API is looked up by DOI, or by arXiv id for preprints.
+
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use url::Url;
+
+use super::arxiv::ArxivId;
+use crate::{http, MabelError, Result};
+
+const API_URL: &str = "https://api.altmetric.com/v1/";
+
+#[derive(Deserialize)]
+struct Record {
+    score: Option<f64>,
+}
+
+/// The attention score of the paper with DOI `doi` or else arXiv id `arxiv`, rounded as
+/// Altmetric's badge shows it; `None` when Altmetric has seen no mention of it.
+pub async fn score(http: &Client, doi: Option<&str>, arxiv: Option<&str>, retries: u32) -> Result<Option<u64>> {
+    let path = match (doi, arxiv.and_then(|a| ArxivId::parse(a).ok())) {
+        | (Some(doi), _) => format!("doi/{doi}"),
+        | (None, Some(arxiv)) => format!("arxiv/{}", arxiv.base()),
+        | (None, None) => return Ok(None),
+    };
+    let url = Url::parse(&format!("{API_URL}{path}"))?;
+    let body = match http::get_text(http, url, retries).await {
+        | Ok(body) => body,
+        | Err(MabelError::HttpStatus {
+            status: StatusCode::NOT_FOUND,
+            ..
+        }) => return Ok(None),
+        | Err(e) => return Err(e),
+    };
+    let record: Record = serde_json::from_str(&body)?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Ok(record.score.map(|s| s.max(0.0).round() as u64))
+}
@@ -0,0 +1,171 @@
+//! arXiv resolver built on the public Atom API (`export.arxiv.org/api/query`).
+//!
+//! arXiv has no structured full text, so a resolved paper carries metadata plus the PDF link.
+
+use chrono::{DateTime, NaiveDate};
+use reqwest::Client;
+use url::Url;
+
+use super::ResolvedPaper;
+use crate::{
+    config::Config,
+    http::{self, Conditional, Validators},
+    paper::PaperMetadata,
+    xml::{self, Element},
+    MabelError, Result,
+};
+
+pub use mabel_core::id::ArxivId;
+
+/// The public API; `MABEL_ARXIV_API_URL` points mabel at another.
+pub const API_URL: &str = "https://export.arxiv.org/api/query";
+
+/// Where PDFs are, by id; `MABEL_ARXIV_PDF_URL` points mabel at another host.
+pub const PDF_URL: &str = "https://arxiv.org/pdf/";
+
+/// The PDF of the paper `id`, from the configured host.
+pub fn pdf_url(cfg: &Config, id: &ArxivId) -> Result<Url> {
+    Ok(cfg.arxiv_pdf_url.join(id.as_str())?)
+}
+
+pub struct ArxivResolver {
+    http: Client,
+    api: Url,
+    retries: u32,
+}
+
+impl ArxivResolver {
+    pub fn new(http: Client, cfg: &Config) -> Self {
+        Self {
+            http,
+            api: cfg.arxiv_api_url.clone(),
+            retries: cfg.http_retries,
+        }
+    }
+
+    pub async fn resolve(&self, id: &ArxivId) -> Result<ResolvedPaper> {
+        let body = http::get_text(&self.http, self.query_url(id), self.retries).await?;
+        Self::parse_feed(&body, id)
+    }
+
+    /// Re-fetch metadata with a conditional request; `None` when nothing changed since the
+    /// response `validators` came from.
+    pub async fn refresh(&self, id: &ArxivId, validators: &Validators) -> Result<Option<(ResolvedPaper, Validators)>> {
+        match http::get_text_conditional(&self.http, self.query_url(id), validators, self.retries).await? {
+            | Conditional::NotModified => Ok(None),
+            | Conditional::Modified { body, validators } => Ok(Some((Self::parse_feed(&body, id)?, validators))),
+        }
+    }
+
+    /// The newest submissions to any of `categories` (e.g. `cs.LG`), newest first.
+    pub async fn recent(&self, categories: &[String], max_results: usize) -> Result<Vec<ResolvedPaper>> {
+        let query = categories
+            .iter()
+            .map(|c| format!("cat:{}", c.trim()))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let url = self.query(&[
+            ("search_query", query.as_str()),
+            ("sortBy", "submittedDate"),
+            ("sortOrder", "descending"),
+            ("max_results", &max_results.to_string()),
+        ]);
+        let body = http::get_text(&self.http, url, self.retries).await?;
+        let feed = xml::parse(&body, "arXiv API response")?;
+        Ok(feed
+            .children_named("entry")
+            .filter_map(|entry| {
+                let abs = entry.find_text("id")?;
+                let id = ArxivId::parse(abs.rsplit_once("/abs/")?.1).ok()?;
+                let (metadata, pdf_url) = entry_metadata(entry, &id);
+                Some(ResolvedPaper {
+                    metadata,
+                    structure: None,
+                    pdf_url,
+                })
+            })
+            .collect())
+    }
+
+    fn query_url(&self, id: &ArxivId) -> Url {
+        self.query(&[("id_list", id.as_str()), ("max_results", "1")])
+    }
+
+    fn query(&self, params: &[(&str, &str)]) -> Url {
+        let mut url = self.api.clone();
+        url.query_pairs_mut().clear().extend_pairs(params);
+        url
+    }
+
+    fn parse_feed(body: &str, id: &ArxivId) -> Result<ResolvedPaper> {
+        let feed = xml::parse(body, "arXiv API response")?;
+        let not_found = || {
+            MabelError::InvalidArxivId {
+                input: format!("{id} (no such arXiv paper)"),
+            }
+        };
+        // Unknown ids come back either as an empty feed or as a single entry whose id points at
+        // the API's error page.
+        let entry = feed
+            .find("entry")
+            .filter(|e| !e.find_text("id").unwrap_or_default().contains("/api/errors"))
+            .ok_or_else(not_found)?;
+        let (metadata, pdf_url) = entry_metadata(entry, id);
+        if metadata.title.is_empty() {
+            return Err(not_found());
+        }
+        Ok(ResolvedPaper {
+            metadata,
+            structure: None,
+            pdf_url,
+        })
+    }
+}
+
+/// Map an Atom `<entry>` to our metadata plus the PDF link.
+fn entry_metadata(entry: &Element, id: &ArxivId) -> (PaperMetadata, Option<Url>) {
+    let versioned = entry
+        .find_text("id")
+        .and_then(|u| u.rsplit_once("/abs/").map(|(_, id)| id.to_string()))
+        .unwrap_or_else(|| id.to_string());
+
+    // The primary category comes first, which is the one a paper's MOC is named after.
+    let primary = entry.child("primary_category").and_then(|c| c.attr("term"));
+    let mut md = PaperMetadata {
+        title: entry.find_text("title").unwrap_or_default(),
+        authors: entry
+            .children_named("author")
+            .filter_map(|a| a.find_text("name"))
+            .collect(),
+        abstract_text: entry.find_text("summary"),
+        published: entry.find_text("published").as_deref().and_then(parse_date),
+        journal: entry.find_text("journal_ref"),
+        comment: entry.find_text("comment"),
+        keywords: primary
+            .into_iter()
+            .chain(
+                entry
+                    .children_named("category")
+                    .filter_map(|c| c.attr("term"))
+                    .filter(|&term| Some(term) != primary),
+            )
+            .map(str::to_string)
+            .collect(),
+        doi: entry.find_text("doi"),
+        url: Some(format!("https://arxiv.org/abs/{versioned}")),
+        ..PaperMetadata::default()
+    };
+    md.arxiv_id = Some(versioned);
+
+    let pdf_url = entry
+        .children_named("link")
+        .find(|l| l.attr("title") == Some("pdf") || l.attr("type") == Some("application/pdf"))
+        .and_then(|l| l.attr("href"))
+        .and_then(|href| Url::parse(href).ok())
+        .or_else(|| Url::parse(&format!("https://arxiv.org/pdf/{id}")).ok());
+    (md, pdf_url)
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(s).ok().map(|d| d.date_naive())
+}
@@ -0,0 +1,382 @@
+//! PDFs in cloud buckets (`s3://bucket/key.pdf`, `gs://bucket/key.pdf`), such as internal tech
+//! reports. The object is fetched with credentials from the usual places into the cache, extracted
+//! with GROBID like any other PDF, and removed again.
+//!
+//! - S3: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`, else the `AWS_PROFILE`
+//!   (or `default`) profile of `~/.aws/credentials`; the region from `AWS_REGION`,
+//!   `AWS_DEFAULT_REGION` or `~/.aws/config`. `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL` point at an
+//!   S3-compatible store such as `MinIO`. Requests are signed with AWS Signature Version 4.
+//! - GCS: `GOOGLE_OAUTH_ACCESS_TOKEN`, else `gcloud auth application-default print-access-token`
+//!   (which follows `GOOGLE_APPLICATION_CREDENTIALS`), else the metadata server on Google Cloud.
+
+use std::{
+    env,
+    fmt::{self, Write as _},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::ResolvedPaper;
+use crate::{config::Config, http, secret::Secret, MabelError, Result};
+
+/// SHA-256 of an empty body, the payload hash of a GET.
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+const GCS_API_URL: &str = "https://storage.googleapis.com/storage/v1/b/";
+
+const GCE_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+#[derive(Deserialize)]
+struct GceToken {
+    access_token: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    S3,
+    Gcs,
+}
+
+/// An object in a bucket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CloudUrl {
+    pub provider: Provider,
+    pub bucket: String,
+    pub key: String,
+}
+
+impl CloudUrl {
+    pub fn looks_like(input: &str) -> bool {
+        input.starts_with("s3://") || input.starts_with("gs://")
+    }
+
+    pub fn parse(input: &str) -> Result<Self> {
+        let invalid = || {
+            MabelError::UnsupportedInput {
+                input: input.to_string(),
+            }
+        };
+        let (provider, rest) = match input.split_once("://") {
+            | Some(("s3", rest)) => (Provider::S3, rest),
+            | Some(("gs", rest)) => (Provider::Gcs, rest),
+            | _ => return Err(invalid()),
+        };
+        let (bucket, key) = rest.split_once('/').ok_or_else(invalid)?;
+        if bucket.is_empty() || key.is_empty() || key.ends_with('/') {
+            return Err(invalid());
+        }
+        Ok(Self {
+            provider,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    /// The object's file name without extension, for a title when the PDF has none.
+    pub fn file_stem(&self) -> &str {
+        let name = self.key.rsplit('/').next().unwrap_or(&self.key);
+        name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+    }
+}
+
+impl fmt::Display for CloudUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.provider {
+            | Provider::S3 => "s3",
+            | Provider::Gcs => "gs",
+        };
+        write!(f, "{scheme}://{}/{}", self.bucket, self.key)
+    }
+}
+
+/// Fetches objects from S3 and GCS.
+pub struct CloudSource {
+    http: Client,
+    /// Bytes per second objects are downloaded at, at most
+    max_bandwidth: Option<u64>,
+}
+
+impl CloudSource {
+    pub fn new(http: Client, max_bandwidth: Option<u64>) -> Self {
+        Self { http, max_bandwidth }
+    }
+
+    /// Download the object into the file at `path`; returns its size.
+    pub async fn download(&self, url: &CloudUrl, path: &Path) -> Result<u64> {
+        tracing::debug!(%url, "fetching from the bucket");
+        let (object, req) = match url.provider {
+            | Provider::S3 => {
+                let (object, region) = s3_location(url)?;
+                let credentials = AwsCredentials::from_env()?;
+                let mut req = self.http.get(object.clone());
+                for (name, value) in sign_s3(&object, &region, &credentials, Utc::now()) {
+                    req = req.header(name, value);
+                }
+                (object, req)
+            }
+            | Provider::Gcs => {
+                let object = Url::parse(&format!(
+                    "{GCS_API_URL}{}/o/{}?alt=media",
+                    encode(&url.bucket, false),
+                    encode(&url.key, false)
+                ))?;
+                let token = self.gcs_token().await?;
+                let req = self.http.get(object.clone()).bearer_auth(token.expose());
+                (object, req)
+            }
+        };
+        let req = http::throttled(req, self.max_bandwidth);
+        let resp = req.send().await.map_err(|e| http::request_error(&object, e))?;
+        http::write_body(http::check_status(resp).await?, &object, path, self.max_bandwidth).await
+    }
+
+    /// An OAuth access token for Cloud Storage from the first source that has one.
+    async fn gcs_token(&self) -> Result<Secret> {
+        if let Ok(token) = env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+            return Ok(Secret::new(token));
+        }
+        let gcloud = tokio::process::Command::new("gcloud")
+            .args(["auth", "application-default", "print-access-token"])
+            .stdin(std::process::Stdio::null())
+            .output()
+            .await;
+        if let Some(output) = gcloud.ok().filter(|o| o.status.success()) {
+            let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if !token.is_empty() {
+                return Ok(Secret::new(token));
+            }
+        }
+        let metadata = self
+            .http
+            .get(GCE_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .timeout(Duration::from_secs(2))
+            .send()
+            .await;
+        if let Ok(resp) = metadata.and_then(reqwest::Response::error_for_status) {
+            if let Ok(token) = resp.json::<GceToken>().await {
+                return Ok(Secret::new(token.access_token));
+            }
+        }
+        Err(MabelError::Config {
+            msg: "no Google Cloud credentials: set GOOGLE_OAUTH_ACCESS_TOKEN, or run `gcloud auth application-default \
+                  login`"
+                .to_string(),
+        })
+    }
+}
+
+/// Download the PDF at `url` and extract it (see [`crate::extract::read_pdf`]).
+pub async fn resolve(cfg: &Config, http: &Client, url: &CloudUrl) -> Result<ResolvedPaper> {
+    if cfg.reads_pdfs() {
+        let pdf = cfg.cached_pdf_path(&sanitize_filename::sanitize(format!(
+            "cloud-{}-{}",
+            url.bucket,
+            url.key.replace('/', "_")
+        )));
+        if let Some(dir) = pdf.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(|source| {
+                MabelError::Io {
+                    path: dir.to_path_buf(),
+                    source,
+                }
+            })?;
+        }
+        let downloaded = CloudSource::new(http.clone(), cfg.max_bandwidth)
+            .download(url, &pdf)
+            .await;
+        let extracted = match downloaded {
+            | Ok(_) => crate::extract::read_pdf(cfg, &pdf).await,
+            | Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_file(&pdf).await;
+        let extracted = extracted?;
+        let mut metadata = extracted.metadata;
+        if metadata.title.is_empty() {
+            metadata.title = url.file_stem().to_string();
+        }
+        metadata.url = Some(url.to_string());
+        return Ok(ResolvedPaper {
+            metadata,
+            structure: Some(extracted.structure),
+            pdf_url: None,
+        });
+    }
+    Err(MabelError::Config {
+        msg: format!(
+            "{url} is a PDF, and no PDF extractor is on: set GROBID_URL in a build with `--features grobid`, or turn \
+             on MABEL_PDF_FALLBACK in a build with `--features pdf`"
+        ),
+    })
+}
+
+struct AwsCredentials {
+    access_key: String,
+    secret_key: Secret,
+    session_token: Option<Secret>,
+}
+
+impl AwsCredentials {
+    /// From the environment, else from the shared credentials file.
+    fn from_env() -> Result<Self> {
+        if let (Ok(access_key), Ok(secret_key)) = (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) {
+            return Ok(Self {
+                access_key,
+                secret_key: Secret::new(secret_key),
+                session_token: env::var("AWS_SESSION_TOKEN").ok().map(Secret::new),
+            });
+        }
+        let file = aws_file("AWS_SHARED_CREDENTIALS_FILE", "credentials");
+        let text = file.and_then(|f| std::fs::read_to_string(f).ok()).unwrap_or_default();
+        let profile = aws_profile();
+        let value = |key| ini_value(&text, &profile, key);
+        match (value("aws_access_key_id"), value("aws_secret_access_key")) {
+            | (Some(access_key), Some(secret_key)) => {
+                Ok(Self {
+                    access_key,
+                    secret_key: Secret::new(secret_key),
+                    session_token: value("aws_session_token").map(Secret::new),
+                })
+            }
+            | _ => {
+                Err(MabelError::Config {
+                    msg: format!(
+                        "no AWS credentials: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or add the {profile:?} \
+                         profile to ~/.aws/credentials"
+                    ),
+                })
+            }
+        }
+    }
+}
+
+fn aws_profile() -> String {
+    env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
+}
+
+/// `~/.aws/<name>`, or the file in `var`.
+fn aws_file(var: &str, name: &str) -> Option<PathBuf> {
+    env::var(var)
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|h| h.join(".aws").join(name)))
+}
+
+/// The object's HTTPS URL and the region to sign for.
+fn s3_location(url: &CloudUrl) -> Result<(Url, String)> {
+    let region = env::var("AWS_REGION")
+        .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+        .ok()
+        .or_else(|| {
+            let text = std::fs::read_to_string(aws_file("AWS_CONFIG_FILE", "config")?).ok()?;
+            let profile = aws_profile();
+            let section = if profile == "default" {
+                profile
+            } else {
+                format!("profile {profile}")
+            };
+            ini_value(&text, &section, "region")
+        })
+        .unwrap_or_else(|| "us-east-1".to_string());
+    let key = encode(&url.key, true);
+    let endpoint = env::var("AWS_ENDPOINT_URL_S3").or_else(|_| env::var("AWS_ENDPOINT_URL"));
+    let object = match endpoint {
+        // Custom stores are addressed path-style, as MinIO and most others expect.
+        | Ok(endpoint) => format!("{}/{}/{key}", endpoint.trim_end_matches('/'), url.bucket),
+        // Bucket names with dots do not match the wildcard certificate of virtual-hosted URLs.
+        | Err(_) if url.bucket.contains('.') => format!("https://s3.{region}.amazonaws.com/{}/{key}", url.bucket),
+        | Err(_) => format!("https://{}.s3.{region}.amazonaws.com/{key}", url.bucket),
+    };
+    Ok((Url::parse(&object)?, region))
+}
+
+/// The headers that sign a GET of `url` with Signature Version 4.
+fn sign_s3(url: &Url, region: &str, credentials: &AwsCredentials, now: DateTime<Utc>) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let mut host = url.host_str().unwrap_or_default().to_string();
+    if let Some(port) = url.port() {
+        host = format!("{host}:{port}");
+    }
+    // Sorted by name, as the canonical request lists them.
+    let mut headers = vec![
+        ("host", host),
+        ("x-amz-content-sha256", EMPTY_SHA256.to_string()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.expose().to_string()));
+    }
+    let signed = headers.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(";");
+    let canonical_headers = headers.iter().fold(String::new(), |mut out, (n, v)| {
+        let _ = writeln!(out, "{n}:{}", v.trim());
+        out
+    });
+    let canonical_request = format!(
+        "GET\n{}\n{}\n{canonical_headers}\n{signed}\n{EMPTY_SHA256}",
+        url.path(),
+        url.query().unwrap_or_default()
+    );
+    let scope = format!("{date}/{region}/s3/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [date.as_str(), region, "s3", "aws4_request"].iter().fold(
+        format!("AWS4{}", credentials.secret_key.expose()).into_bytes(),
+        |key, part| hmac(&key, part.as_bytes()),
+    );
+    let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed}, Signature={signature}",
+        credentials.access_key
+    );
+    headers.retain(|(name, _)| *name != "host");
+    headers.push(("authorization", authorization));
+    headers
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode everything but unreserved characters (and `/` when `keep_slash`), as AWS and
+/// the GCS JSON API expect object names.
+fn encode(text: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') || (keep_slash && b == b'/') {
+            out.push(char::from(b));
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+    }
+    out
+}
+
+/// `key` in section `[section]` of an INI-style AWS file.
+fn ini_value(text: &str, section: &str, key: &str) -> Option<String> {
+    let mut current = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.trim());
+        } else if current == Some(section) {
+            if let Some((k, v)) = line.split_once('=') {
+                if k.trim() == key {
+                    return Some(v.trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
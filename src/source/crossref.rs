@@ -0,0 +1,255 @@
+//! DOI resolver: metadata from Crossref (`api.crossref.org`), and an open-access PDF from
+//! Unpaywall (`api.unpaywall.org`) when there is one.
+//!
+//! Unpaywall requires an email address with every request, so without `UNPAYWALL_EMAIL` it is not
+//! asked and the paper is summarized from the abstract Crossref has, if any. The address also puts
+//! Crossref requests in its "polite" pool.
+
+use chrono::NaiveDate;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use url::Url;
+
+use super::ResolvedPaper;
+use crate::{config::Config, extract::jats, http, paper::PaperMetadata, xml, MabelError, Result};
+
+pub use mabel_core::id::Doi;
+
+const CROSSREF_URL: &str = "https://api.crossref.org/works/";
+const UNPAYWALL_URL: &str = "https://api.unpaywall.org/v2/";
+
+#[derive(Deserialize)]
+struct CrossrefResponse {
+    message: Work,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    message: SearchResults,
+}
+
+#[derive(Deserialize)]
+struct SearchResults {
+    #[serde(default)]
+    items: Vec<Work>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Work {
+    #[serde(default)]
+    title: Vec<String>,
+    #[serde(default)]
+    author: Vec<Author>,
+    #[serde(rename = "abstract")]
+    abstract_jats: Option<String>,
+    #[serde(default)]
+    container_title: Vec<String>,
+    #[serde(default)]
+    subject: Vec<String>,
+    published: Option<PartialDate>,
+    issued: Option<PartialDate>,
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(rename = "DOI")]
+    doi: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    given: Option<String>,
+    family: Option<String>,
+    /// Organizations and consortia
+    name: Option<String>,
+    #[serde(rename = "ORCID")]
+    orcid: Option<String>,
+}
+
+/// `{"date-parts": [[2021, 3]]}`: year, then month and day when known.
+#[derive(Deserialize)]
+struct PartialDate {
+    #[serde(rename = "date-parts", default)]
+    parts: Vec<Vec<Option<i32>>>,
+}
+
+#[derive(Deserialize)]
+struct UnpaywallResponse {
+    best_oa_location: Option<OaLocation>,
+    #[serde(default)]
+    oa_locations: Vec<OaLocation>,
+}
+
+#[derive(Deserialize)]
+struct OaLocation {
+    url_for_pdf: Option<String>,
+}
+
+pub struct CrossrefResolver {
+    http: Client,
+    email: Option<String>,
+    retries: u32,
+}
+
+impl CrossrefResolver {
+    pub fn new(http: Client, cfg: &Config) -> Self {
+        Self {
+            http,
+            email: cfg.unpaywall_email.clone(),
+            retries: cfg.http_retries,
+        }
+    }
+
+    /// The paper's metadata, with the link to an open-access PDF when Unpaywall knows one. Failing
+    /// to ask Unpaywall only costs the PDF.
+    pub async fn resolve(&self, doi: &Doi) -> Result<ResolvedPaper> {
+        let (work, pdf_url) = tokio::join!(self.work(doi), self.open_access_pdf(doi));
+        let pdf_url = pdf_url.unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "could not ask Unpaywall for an open-access PDF");
+            None
+        });
+        Ok(ResolvedPaper {
+            metadata: work_metadata(work?, doi),
+            structure: None,
+            pdf_url,
+        })
+    }
+
+    /// The works Crossref finds for the title (or any citation-like text) `title`, best match
+    /// first; at most `rows` of them.
+    pub async fn search_title(&self, title: &str, rows: usize) -> Result<Vec<PaperMetadata>> {
+        let mut url = Url::parse(CROSSREF_URL.trim_end_matches('/'))?;
+        url.query_pairs_mut()
+            .append_pair("query.bibliographic", title)
+            .append_pair("rows", &rows.to_string());
+        if let Some(email) = &self.email {
+            url.query_pairs_mut().append_pair("mailto", email);
+        }
+        let body = http::get_text(&self.http, url, self.retries).await?;
+        let found: SearchResponse = serde_json::from_str(&body)?;
+        Ok(found
+            .message
+            .items
+            .into_iter()
+            .filter_map(|work| {
+                let doi = Doi::parse(work.doi.as_deref()?).ok()?;
+                Some(work_metadata(work, &doi))
+            })
+            .collect())
+    }
+
+    async fn work(&self, doi: &Doi) -> Result<Work> {
+        let mut url = api_url(CROSSREF_URL, doi)?;
+        if let Some(email) = &self.email {
+            url.query_pairs_mut().append_pair("mailto", email);
+        }
+        match http::get_text(&self.http, url, self.retries).await {
+            | Ok(body) => Ok(serde_json::from_str::<CrossrefResponse>(&body)?.message),
+            | Err(MabelError::HttpStatus {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }) => {
+                Err(MabelError::InvalidDoi {
+                    input: format!("{doi} (not registered with Crossref)"),
+                })
+            }
+            | Err(e) => Err(e),
+        }
+    }
+
+    /// Unpaywall's best open-access PDF of the paper; `None` without `UNPAYWALL_EMAIL` or when
+    /// the paper has none.
+    async fn open_access_pdf(&self, doi: &Doi) -> Result<Option<Url>> {
+        let Some(email) = &self.email else {
+            tracing::debug!("UNPAYWALL_EMAIL is not set; not looking for an open-access PDF");
+            return Ok(None);
+        };
+        let mut url = api_url(UNPAYWALL_URL, doi)?;
+        url.query_pairs_mut().append_pair("email", email);
+        let body = match http::get_text(&self.http, url, self.retries).await {
+            | Ok(body) => body,
+            | Err(MabelError::HttpStatus {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }) => return Ok(None),
+            | Err(e) => return Err(e),
+        };
+        let response: UnpaywallResponse = serde_json::from_str(&body)?;
+        let pdf = response
+            .best_oa_location
+            .into_iter()
+            .chain(response.oa_locations)
+            .filter_map(|l| l.url_for_pdf)
+            .find_map(|u| Url::parse(&u).ok());
+        if pdf.is_none() {
+            tracing::info!(%doi, "Unpaywall knows no open-access PDF; summarizing from the abstract");
+        }
+        Ok(pdf)
+    }
+}
+
+/// `base` followed by the DOI, each of its `/`-separated parts percent-encoded: DOIs may contain
+/// `#`, `?` and the like.
+fn api_url(base: &str, doi: &Doi) -> Result<Url> {
+    let mut url = Url::parse(base)?;
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().extend(doi.as_str().split('/'));
+    }
+    Ok(url)
+}
+
+fn work_metadata(work: Work, doi: &Doi) -> PaperMetadata {
+    let (authors, orcids): (Vec<String>, Vec<Option<String>>) = work
+        .author
+        .into_iter()
+        .filter_map(|a| {
+            let name = match (a.given, a.family) {
+                | (Some(given), Some(family)) => format!("{given} {family}"),
+                | (None, Some(family)) => family,
+                | _ => a.name?,
+            };
+            Some((name, a.orcid.as_deref().and_then(mabel_core::id::orcid)))
+        })
+        .unzip();
+    let title = work.title.first().map(|t| xml::collapse_whitespace(t));
+    let mut md = PaperMetadata {
+        title: title.unwrap_or_default(),
+        orcids: orcids.iter().any(Option::is_some).then_some(orcids).unwrap_or_default(),
+        authors,
+        abstract_text: work.abstract_jats.as_deref().and_then(abstract_text),
+        published: work.published.or(work.issued).and_then(|d| d.date()),
+        journal: work.container_title.into_iter().next(),
+        keywords: work.subject,
+        doi: Some(doi.to_string()),
+        url: Some(format!("https://doi.org/{doi}")),
+        ..PaperMetadata::default()
+    };
+    // A proceedings' title is no journal, but may still be a venue on the list.
+    md.detect_venue(work.kind == "journal-article");
+    md
+}
+
+/// Crossref abstracts are JATS fragments (`<jats:p>…</jats:p>`, often after a `<jats:title>`).
+/// Some are not well-formed; those lose their tags the crude way.
+fn abstract_text(fragment: &str) -> Option<String> {
+    let doc = format!("<front><abstract>{fragment}</abstract></front>");
+    if let Ok(front) = xml::parse(&doc, "Crossref abstract") {
+        return jats::abstract_text(&front);
+    }
+    let mut parts = fragment.split('<');
+    let untagged: Vec<&str> = parts
+        .next()
+        .into_iter()
+        .chain(parts.map(|tagged| tagged.split_once('>').map_or(tagged, |(_, text)| text)))
+        .collect();
+    Some(xml::collapse_whitespace(&untagged.join(" "))).filter(|t| !t.is_empty())
+}
+
+impl PartialDate {
+    fn date(&self) -> Option<NaiveDate> {
+        let parts = self.parts.first()?;
+        let part = |i: usize, default| parts.get(i).copied().flatten().unwrap_or(default);
+        let month = u32::try_from(part(1, 1)).ok()?;
+        let day = u32::try_from(part(2, 1)).ok()?;
+        NaiveDate::from_ymd_opt(parts.first().copied().flatten()?, month, day)
+    }
+}
@@ -0,0 +1,381 @@
+//! Papers that already live on disk.
+//!
+//! A PDF's title is read off its first page, which goes wrong often enough (a journal banner set
+//! larger than the title, a scanned cover page) that one in doubt is checked: Crossref is searched
+//! for it, and the candidates are shown to pick from or correct before the note is written. Under
+//! `--no-interactive`, `--yes`, `--ci` or without a terminal, the best guess is taken.
+//!
+//! A PDF is also checked against the papers already processed, since the same paper saved twice
+//! (`download(7).pdf`) would otherwise get a second note; see [`check_duplicate`].
+
+use std::{fmt::Write as _, path::Path};
+
+use mabel_core::fingerprint::Fingerprint;
+use reqwest::Client;
+
+use super::{crossref::CrossrefResolver, ResolvedPaper};
+use crate::{
+    config::{Config, Mode},
+    extract::{
+        epub::{self, Book, ChapterSelection},
+        jats,
+    },
+    note,
+    paper::{PaperMetadata, PaperStructure},
+    pdf,
+    registry::Registry,
+    MabelError, Result,
+};
+
+/// A paper's title has at least this many words...
+const MIN_TITLE_WORDS: usize = 3;
+
+/// ...and at most this many.
+const MAX_TITLE_WORDS: usize = 30;
+
+/// Crossref matches shown for a title in doubt.
+const CROSSREF_CANDIDATES: usize = 3;
+
+/// Metadata a PDF may have, and where it comes from.
+struct Candidate {
+    metadata: PaperMetadata,
+    from: &'static str,
+}
+
+/// Ingest a publisher/PMC JATS XML file: metadata from `<front>`, full text from `<body>`.
+pub async fn resolve_jats_file(path: &Path) -> Result<ResolvedPaper> {
+    let doc = tokio::fs::read_to_string(path).await.map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    let article = jats::parse_article(&doc)?;
+
+    let mut metadata = jats::metadata(&article);
+    if metadata.title.is_empty() {
+        // Fall back to the file name so the note still gets a sensible title.
+        metadata.title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    let structure = jats::has_body(&article).then(|| jats::extract(&article));
+
+    Ok(ResolvedPaper {
+        metadata,
+        structure,
+        pdf_url: None,
+    })
+}
+
+/// Ingest a PDF, such as a preprint passed around before it is posted anywhere. GROBID or the
+/// built-in extractor reads its header and full text; what the PDF declares about itself (its XMP
+/// packet or information dictionary) fills the gaps. Without either the PDF can only be skimmed.
+pub async fn resolve_pdf_file(cfg: &Config, http: &Client, path: &Path) -> Result<ResolvedPaper> {
+    let absolute = std::path::absolute(path).map_err(|source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    let pdf_url = url::Url::from_file_path(&absolute).map_err(|()| {
+        MabelError::Config {
+            msg: format!("{} cannot be given as a file URL", absolute.display()),
+        }
+    })?;
+    let bytes = crate::memory::read_within(path, cfg.max_memory).await?;
+    let declared = pdf::declared(&bytes);
+
+    if cfg.reads_pdfs() {
+        let extracted = crate::extract::read_pdf(cfg, path).await?;
+        check_duplicate(cfg, path, &extracted.structure).await?;
+        let metadata = settle(cfg, http, path, extracted.metadata, declared).await;
+        return Ok(ResolvedPaper {
+            metadata,
+            structure: Some(extracted.structure),
+            pdf_url: Some(pdf_url),
+        });
+    }
+    if !matches!(cfg.mode, Mode::Skim) {
+        return Err(MabelError::Config {
+            msg: format!(
+                "{} is a PDF, and no PDF extractor is on: set GROBID_URL in a build with `--features grobid`, turn on \
+                 MABEL_PDF_FALLBACK in a build with `--features pdf`, or skim it with `--mode skim`",
+                path.display()
+            ),
+        });
+    }
+    let metadata = PaperMetadata {
+        pdf: pdf::inspect(&bytes),
+        ..PaperMetadata::default()
+    };
+    Ok(ResolvedPaper {
+        metadata: settle(cfg, http, path, metadata, declared).await,
+        structure: None,
+        pdf_url: Some(pdf_url),
+    })
+}
+
+/// Stop before a PDF that looks like a paper already processed under another file name, such as
+/// `download(7).pdf`: the [`Fingerprint`] of its text is close to one in the registry whose note is
+/// still in the vault. It goes ahead with `--yes`, or when the user says so on a terminal.
+pub async fn check_duplicate(cfg: &Config, path: &Path, structure: &PaperStructure) -> Result<()> {
+    let Some(fingerprint) = Fingerprint::of(&structure.full_text()) else {
+        return Ok(());
+    };
+    let Ok(registry) = Registry::load(&cfg.registry_path()) else {
+        return Ok(());
+    };
+    let Some(entry) = registry.similar(fingerprint) else {
+        return Ok(());
+    };
+    // Processing the same file again is what --overwrite and the note's own checks are for.
+    if !cfg.vault_path.join(&entry.note).is_file() || same_file(path, Path::new(&entry.source)) {
+        return Ok(());
+    }
+    tracing::warn!(
+        pdf = %path.display(),
+        note = %entry.note.display(),
+        "the PDF looks like a paper already processed"
+    );
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let question = format!(
+        "{name} looks like \"{}\", already processed into {}. Process it anyway?",
+        entry.title,
+        entry.note.display()
+    );
+    if cfg.assume_yes || (cfg.can_ask() && note::ask(&question).await) {
+        return Ok(());
+    }
+    Err(MabelError::Duplicate {
+        path: path.to_path_buf(),
+        note: entry.note.clone(),
+    })
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    std::fs::canonicalize(a).is_ok_and(|a| std::fs::canonicalize(b).is_ok_and(|b| a == b))
+}
+
+/// The metadata of the PDF at `path`: what was `read` off it, with gaps filled from what it
+/// `declared`. When the title is in doubt, the candidates are shown to pick from, or the best
+/// guess is taken when no one is to be asked.
+async fn settle(
+    cfg: &Config,
+    http: &Client,
+    path: &Path,
+    read: PaperMetadata,
+    declared: pdf::Declared,
+) -> PaperMetadata {
+    let guess = with_declared(read.clone(), declared.clone(), path);
+    if !title_in_doubt(&read.title, declared.title.as_deref(), &guess) {
+        return guess;
+    }
+    let mut candidates = vec![Candidate {
+        metadata: guess.clone(),
+        from: "read from the PDF",
+    }];
+    if let Some(title) = declared.title.filter(|t| !same_title(t, &guess.title)) {
+        let mut metadata = PaperMetadata { title, ..guess.clone() };
+        if !declared.authors.is_empty() {
+            metadata.authors = declared.authors;
+            metadata.orcids.clear();
+        }
+        let candidate = Candidate {
+            metadata,
+            from: "the PDF's properties",
+        };
+        // The title the PDF declares is the better guess when the one read off it is no title.
+        if plausible(&candidate.metadata.title) && !plausible(&read.title) {
+            candidates.insert(0, candidate);
+        } else {
+            candidates.push(candidate);
+        }
+    }
+    let query = format!("{} {}", guess.title, guess.authors.join(" "));
+    match CrossrefResolver::new(http.clone(), cfg)
+        .search_title(&query, CROSSREF_CANDIDATES)
+        .await
+    {
+        | Ok(found) => {
+            let mut matched = false;
+            for metadata in found {
+                let candidate = Candidate {
+                    metadata: with_found(metadata, &read),
+                    from: "Crossref",
+                };
+                // A match for a title the PDF gives is the best guess: it brings the DOI along.
+                if !matched
+                    && candidates
+                        .iter()
+                        .any(|c| same_title(&c.metadata.title, &candidate.metadata.title))
+                {
+                    candidates.retain(|c| !same_title(&c.metadata.title, &candidate.metadata.title));
+                    candidates.insert(0, candidate);
+                    matched = true;
+                } else {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        | Err(e) => tracing::warn!(error = %e, "could not search Crossref for the title"),
+    }
+    if cfg.confirm_metadata && !cfg.assume_yes && cfg.can_ask() {
+        return pick(path, candidates, guess).await;
+    }
+    let best = candidates.swap_remove(0);
+    tracing::info!(
+        title = best.metadata.title,
+        from = best.from,
+        "the PDF's title is uncertain; taking the best guess"
+    );
+    best.metadata
+}
+
+/// Whether the title read off a PDF is in doubt: the extractor found none, what the PDF declares
+/// says otherwise, or it has too few or too many words for a paper's title. A DOI, from GROBID's
+/// consolidation or the PDF itself, vouches for `guess`.
+fn title_in_doubt(read: &str, declared: Option<&str>, guess: &PaperMetadata) -> bool {
+    if guess.doi.is_some() {
+        return false;
+    }
+    !plausible(read) || declared.is_some_and(|d| !same_title(d, read))
+}
+
+/// Whether `title` has as many words as a paper's title has.
+fn plausible(title: &str) -> bool {
+    (MIN_TITLE_WORDS..=MAX_TITLE_WORDS).contains(&title.split_whitespace().count())
+}
+
+/// Whether two titles are the same but for case, punctuation and spacing.
+fn same_title(a: &str, b: &str) -> bool {
+    let words = |t: &str| {
+        t.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+    };
+    words(a) == words(b)
+}
+
+/// A Crossref match's metadata, with what only the PDF has from `read`.
+fn with_found(found: PaperMetadata, read: &PaperMetadata) -> PaperMetadata {
+    PaperMetadata {
+        abstract_text: found.abstract_text.or_else(|| read.abstract_text.clone()),
+        keywords: if found.keywords.is_empty() {
+            read.keywords.clone()
+        } else {
+            found.keywords
+        },
+        pdf: read.pdf.clone(),
+        ..found
+    }
+}
+
+/// Show the candidates for the PDF at `path` and ask which is right. Enter takes the first; a
+/// typed title replaces the one read from the PDF, in `guess`.
+async fn pick(path: &Path, mut candidates: Vec<Candidate>, guess: PaperMetadata) -> PaperMetadata {
+    let mut question = format!("The title of {} is uncertain. Which is right?\n", path.display());
+    for (n, candidate) in candidates.iter().enumerate() {
+        let _ = writeln!(
+            question,
+            "  {}. {} [{}]",
+            n + 1,
+            describe(&candidate.metadata),
+            candidate.from
+        );
+    }
+    let _ = write!(
+        question,
+        "Pick 1-{}, type the right title, or press Enter for 1:",
+        candidates.len()
+    );
+    let answer = note::ask_line(&question).await;
+    match answer.parse::<usize>() {
+        | Ok(n) if (1..=candidates.len()).contains(&n) => candidates.swap_remove(n - 1).metadata,
+        | _ if answer.is_empty() => candidates.swap_remove(0).metadata,
+        | _ => PaperMetadata { title: answer, ..guess },
+    }
+}
+
+/// A candidate on one line: title, first authors, year and DOI.
+fn describe(metadata: &PaperMetadata) -> String {
+    let mut line = metadata.title.clone();
+    if let Some(first) = metadata.authors.first() {
+        let more = if metadata.authors.len() > 1 { " et al." } else { "" };
+        let _ = write!(line, " — {first}{more}");
+    }
+    if let Some(date) = metadata.published {
+        let _ = write!(line, " ({})", date.format("%Y"));
+    }
+    if let Some(doi) = &metadata.doi {
+        let _ = write!(line, ", doi:{doi}");
+    }
+    line
+}
+
+/// `metadata` with gaps filled from what the PDF declares, and the file name for a title when
+/// neither has one.
+fn with_declared(mut metadata: PaperMetadata, declared: pdf::Declared, path: &Path) -> PaperMetadata {
+    if metadata.title.is_empty() {
+        metadata.title = declared.title.unwrap_or_default();
+    }
+    if metadata.authors.is_empty() {
+        metadata.authors = declared.authors;
+        metadata.orcids.clear();
+    }
+    metadata.abstract_text = metadata.abstract_text.or(declared.description);
+    if metadata.keywords.is_empty() {
+        metadata.keywords = declared.keywords;
+    }
+    metadata.doi = metadata.doi.or(declared.doi);
+    if metadata.title.is_empty() {
+        metadata.title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    metadata
+}
+
+/// Ingest an EPUB, keeping only the selected chapters (all of them by default).
+pub async fn resolve_epub_file(path: &Path, chapters: Option<&ChapterSelection>) -> Result<ResolvedPaper> {
+    let book = load_book(path, chapters).await?;
+    Ok(ResolvedPaper {
+        structure: Some(book.to_structure(None)),
+        metadata: book.metadata,
+        pdf_url: None,
+    })
+}
+
+/// Read an EPUB and drop the chapters outside `chapters`; errors if none are left.
+pub async fn load_book(path: &Path, chapters: Option<&ChapterSelection>) -> Result<Book> {
+    let owned = path.to_path_buf();
+    let mut book = tokio::task::spawn_blocking(move || epub::read(&owned))
+        .await
+        .map_err(|e| {
+            MabelError::Extraction {
+                reason: format!("EPUB reader task failed: {e}"),
+            }
+        })??;
+
+    let total = book.chapters.len();
+    book.chapters.retain(|c| chapters.is_none_or(|s| s.contains(c.number)));
+    if book.chapters.is_empty() {
+        return Err(MabelError::Config {
+            msg: format!(
+                "chapter selection {} matches none of the {total} chapters in {}",
+                chapters.map(ToString::to_string).unwrap_or_default(),
+                path.display()
+            ),
+        });
+    }
+    if book.metadata.title.is_empty() {
+        book.metadata.title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+    Ok(book)
+}
@@ -0,0 +1,320 @@
+//! Resolvers that turn a user-supplied identifier into metadata and, when available, full text.
+
+use std::path::{Path, PathBuf};
+
+use reqwest::Client;
+
+use crate::{
+    config::Config,
+    extract::{epub, jats},
+    limits,
+    paper::{PaperMetadata, PaperStructure, PdfInfo},
+    timing::{self, Step},
+    MabelError, Result,
+};
+
+pub mod altmetric;
+pub mod arxiv;
+pub mod cloud;
+pub mod crossref;
+pub mod local;
+pub mod openalex;
+pub mod pubmed;
+
+use arxiv::{ArxivId, ArxivResolver};
+use cloud::CloudUrl;
+use crossref::{CrossrefResolver, Doi};
+use pubmed::{PubmedId, PubmedResolver};
+
+/// Everything a resolver could find out about a paper.
+#[derive(Clone, Debug, Default)]
+pub struct ResolvedPaper {
+    pub metadata: PaperMetadata,
+    /// Full text, when the source provides it in structured form (e.g. PMC JATS)
+    pub structure: Option<PaperStructure>,
+    /// Where to download a PDF from, for sources without structured full text
+    pub pdf_url: Option<url::Url>,
+}
+
+/// A user-supplied paper reference, classified by where we have to get it from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Arxiv(ArxivId),
+    Pubmed(PubmedId),
+    /// DOI, resolved through Crossref, with an open-access PDF from Unpaywall
+    Doi(Doi),
+    /// Local JATS XML file (publisher full-text export)
+    JatsFile(PathBuf),
+    /// Local EPUB (textbook chapters, long reports)
+    EpubFile(PathBuf),
+    /// Local PDF (a preprint from a colleague), read with GROBID or the built-in extractor
+    PdfFile(PathBuf),
+    /// PDF in an S3 or GCS bucket
+    Cloud(CloudUrl),
+}
+
+impl Input {
+    pub fn parse(input: &str) -> Result<Self> {
+        let trimmed = input.trim();
+        let path = Path::new(trimmed);
+        if path.is_file() && has_extension(path, jats::FILE_EXTENSIONS) {
+            return Ok(Self::JatsFile(path.to_path_buf()));
+        }
+        if path.is_file() && has_extension(path, epub::FILE_EXTENSIONS) {
+            return Ok(Self::EpubFile(path.to_path_buf()));
+        }
+        if path.is_file() && has_extension(path, &["pdf"]) {
+            return Ok(Self::PdfFile(path.to_path_buf()));
+        }
+        if CloudUrl::looks_like(trimmed) {
+            return Ok(Self::Cloud(CloudUrl::parse(trimmed)?));
+        }
+        if ArxivId::looks_like(trimmed) {
+            return Ok(Self::Arxiv(ArxivId::parse(trimmed)?));
+        }
+        if Doi::looks_like(trimmed) {
+            let doi = Doi::parse(trimmed)?;
+            return Ok(doi.arxiv_id().map_or(Self::Doi(doi), Self::Arxiv));
+        }
+        if PubmedId::looks_like(trimmed) || trimmed.bytes().all(|b| b.is_ascii_digit()) {
+            return Ok(Self::Pubmed(PubmedId::parse(trimmed)?));
+        }
+        Err(MabelError::UnsupportedInput {
+            input: input.to_string(),
+        })
+    }
+
+    /// Metadata and whatever full text can be had, PDF extraction included. An arXiv PDF's link
+    /// follows from the id, so it is downloaded and extracted while the metadata is looked up.
+    pub async fn resolve(&self, cfg: &Config, http: &Client) -> Result<ResolvedPaper> {
+        if let (Self::Arxiv(id), true) = (self, cfg.reads_pdfs()) {
+            let pdf_url = arxiv::pdf_url(cfg, id)?;
+            let (key, name) = (crate::fulltext::arxiv_key(id), arxiv_pdf_name(id));
+            let (header, extracted) = tokio::join!(
+                self.resolve_header(cfg, http),
+                extract_pdf(cfg, http, pdf_url, &key, &name)
+            );
+            return match (header, extracted) {
+                | (Ok(paper), extracted) => Ok(with_extracted(paper, extracted)),
+                | (Err(e), Ok(_)) => Err(e),
+                | (Err(e), Err(extraction)) => {
+                    tracing::warn!(error = %extraction, "PDF extraction failed too");
+                    Err(e)
+                }
+            };
+        }
+        let paper = self.resolve_header(cfg, http).await?;
+        Ok(self.extract_full_text(cfg, http, paper).await)
+    }
+
+    /// Metadata, plus full text where the source hands it over directly (PMC JATS, local files).
+    /// Papers that only have a PDF come back without it; see [`Input::extract_full_text`].
+    /// The venue is read off the metadata; authors' ORCID iDs come from OpenAlex where the source
+    /// lacks them, unless turned off.
+    pub async fn resolve_header(&self, cfg: &Config, http: &Client) -> Result<ResolvedPaper> {
+        let mut paper = match self {
+            | Self::Arxiv(id) => timing::time(Step::Fetch, ArxivResolver::new(http.clone(), cfg).resolve(id)).await?,
+            | Self::Pubmed(id) => timing::time(Step::Fetch, PubmedResolver::new(http.clone(), cfg).resolve(id)).await?,
+            | Self::Doi(doi) => {
+                timing::time(Step::Fetch, CrossrefResolver::new(http.clone(), cfg).resolve(doi)).await?
+            }
+            | Self::JatsFile(path) => local::resolve_jats_file(path).await?,
+            | Self::EpubFile(path) => return local::resolve_epub_file(path, cfg.chapters.as_ref()).await,
+            | Self::PdfFile(path) => local::resolve_pdf_file(cfg, http, path).await?,
+            | Self::Cloud(url) => cloud::resolve(cfg, http, url).await?,
+        };
+        paper
+            .metadata
+            .detect_venue(matches!(self, Self::Pubmed(_) | Self::JatsFile(_)));
+        if cfg.orcid {
+            timing::time(
+                Step::Fetch,
+                openalex::add_orcids(http, &mut paper.metadata, cfg.http_retries),
+            )
+            .await;
+        }
+        Ok(paper)
+    }
+
+    /// Whether [`Input::extract_full_text`] has a PDF to extract for `paper`, which with GROBID
+    /// takes anywhere from seconds to minutes.
+    pub fn needs_extraction(&self, cfg: &Config, paper: &ResolvedPaper) -> bool {
+        matches!(self, Self::Arxiv(_) | Self::Doi(_))
+            && cfg.reads_pdfs()
+            && paper.structure.is_none()
+            && paper.pdf_url.is_some()
+    }
+
+    /// Fill in the full text of a paper from [`Input::resolve_header`] by PDF extraction, when
+    /// PDFs are read (see [`Config::reads_pdfs`]).
+    pub async fn extract_full_text(&self, cfg: &Config, http: &Client, paper: ResolvedPaper) -> ResolvedPaper {
+        match self {
+            | Self::Arxiv(_) | Self::Doi(_) if cfg.reads_pdfs() => with_pdf_text(cfg, http, paper).await,
+            | _ => paper,
+        }
+    }
+}
+
+/// The PDF of `paper` as a local file, downloaded into the cache first if need be; `None` when it
+/// has no PDF link (from arXiv or Unpaywall).
+pub async fn pdf_file(cfg: &Config, http: &Client, paper: &ResolvedPaper) -> Result<Option<PathBuf>> {
+    if let Some(path) = local_pdf(paper) {
+        return Ok(Some(path));
+    }
+    let (Some(url), Some(name)) = (&paper.pdf_url, pdf_name(&paper.metadata)) else {
+        return Ok(None);
+    };
+    cached_pdf_file(cfg, http, url.clone(), &name).await.map(Some)
+}
+
+/// Page count and size of the paper's PDF, when it is in the cache already; nothing is downloaded
+/// for them.
+pub async fn pdf_info(cfg: &Config, paper: &ResolvedPaper) -> Option<PdfInfo> {
+    let path = local_pdf(paper).or_else(|| Some(cfg.cached_pdf_path(&pdf_name(&paper.metadata)?)))?;
+    let pdf = crate::memory::read_within(&path, cfg.max_memory).await.ok()?;
+    tokio::task::spawn_blocking(move || crate::pdf::inspect(&pdf)).await.ok().flatten()
+}
+
+/// Full text extracted from the PDF of a paper that came with only a PDF link.
+async fn with_pdf_text(cfg: &Config, http: &Client, paper: ResolvedPaper) -> ResolvedPaper {
+    let Some(pdf_url) = paper.pdf_url.clone() else {
+        return paper;
+    };
+    let (Some(key), Some(name)) = (crate::fulltext::key(&paper.metadata), pdf_name(&paper.metadata)) else {
+        return paper;
+    };
+    if paper.structure.is_some() {
+        return paper;
+    }
+    let extracted = extract_pdf(cfg, http, pdf_url, &key, &name).await;
+    with_extracted(paper, extracted)
+}
+
+/// The full text of a paper: extracted on an earlier run (see [`crate::fulltext`]), or from its
+/// PDF, cached as `pdf_name` (see [`crate::extract::read_pdf`]).
+async fn extract_pdf(
+    cfg: &Config,
+    http: &Client,
+    pdf_url: url::Url,
+    key: &str,
+    pdf_name: &str,
+) -> Result<crate::fulltext::Cached> {
+    if let Some(cached) = crate::fulltext::load(cfg, key).await {
+        tracing::debug!(key, "full text from the cache");
+        return Ok(cached);
+    }
+    let pdf = cached_pdf_file(cfg, http, pdf_url, pdf_name).await?;
+    let extracted = crate::extract::read_pdf(cfg, &pdf).await?;
+    Ok(crate::fulltext::Cached {
+        metadata: extracted.metadata,
+        structure: extracted.structure,
+    })
+}
+
+/// `paper` with the full text from [`extract_pdf`]. Identifiers GROBID consolidated fill gaps in
+/// the metadata. Failures are logged and the paper goes ahead on its abstract.
+fn with_extracted(mut paper: ResolvedPaper, extracted: Result<crate::fulltext::Cached>) -> ResolvedPaper {
+    match extracted {
+        | Ok(extracted) => {
+            let md = &mut paper.metadata;
+            md.doi = md.doi.take().or(extracted.metadata.doi);
+            md.pmid = md.pmid.take().or(extracted.metadata.pmid);
+            md.journal = md.journal.take().or(extracted.metadata.journal);
+            md.pdf = md.pdf.take().or(extracted.metadata.pdf);
+            tracing::debug!(
+                sections = extracted.structure.sections.len(),
+                references = extracted.structure.references.len(),
+                "full text from the PDF"
+            );
+            paper.structure = Some(extracted.structure);
+        }
+        | Err(e) => tracing::warn!(error = %e, "PDF extraction failed; summarizing from the abstract"),
+    }
+    paper
+}
+
+/// Whether the paper has a PDF link whose PDF is not in the cache, which on a metered connection
+/// waits for `mabel flush-queue`.
+pub async fn pdf_uncached(cfg: &Config, paper: &ResolvedPaper) -> bool {
+    if local_pdf(paper).is_some() {
+        return false;
+    }
+    match (&paper.pdf_url, pdf_name(&paper.metadata)) {
+        | (Some(_), Some(name)) => !tokio::fs::try_exists(cfg.cached_pdf_path(&name)).await.unwrap_or(false),
+        | _ => false,
+    }
+}
+
+/// Where the paper's PDF is cached, downloading it there first if it is not yet. The download is
+/// written to the cache as it comes in, next to where it goes, and moved there once it is whole.
+/// Nothing is downloaded on a metered connection.
+async fn cached_pdf_file(cfg: &Config, http: &Client, url: url::Url, name: &str) -> Result<PathBuf> {
+    let path = cfg.cached_pdf_path(name);
+    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Ok(path);
+    }
+    if cfg.metered {
+        return Err(MabelError::DownloadDeferred { url });
+    }
+    let part = path.with_extension("pdf.part");
+    let io_err = |source| {
+        MabelError::Io {
+            path: part.clone(),
+            source,
+        }
+    };
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+    }
+    let downloaded = limits::run(
+        Step::Fetch,
+        timing::time(
+            Step::Fetch,
+            crate::http::download(http, url.clone(), &part, cfg.max_bandwidth, cfg.http_retries),
+        ),
+    )
+    .await;
+    // Open-access links sometimes lead to a landing page or a login wall instead.
+    let head = match downloaded {
+        | Ok(_) => crate::memory::read_head(&part, crate::pdf::HEADER_WINDOW as u64).await,
+        | Err(e) => Err(e),
+    };
+    if !head.as_deref().is_ok_and(crate::pdf::is_pdf) {
+        let _ = tokio::fs::remove_file(&part).await;
+        head?;
+        return Err(MabelError::Extraction {
+            reason: format!("{} did not return a PDF", crate::http::redact(&url)),
+        });
+    }
+    tokio::fs::rename(&part, &path).await.map_err(io_err)?;
+    Ok(path)
+}
+
+/// The paper's PDF when it was given as a file, which is read where it is.
+fn local_pdf(paper: &ResolvedPaper) -> Option<PathBuf> {
+    paper
+        .pdf_url
+        .as_ref()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok())
+}
+
+/// What a paper's PDF is cached as: its arXiv id, or else its DOI.
+fn pdf_name(metadata: &PaperMetadata) -> Option<String> {
+    if let Some(id) = metadata.arxiv_id.as_deref().and_then(|a| ArxivId::parse(a).ok()) {
+        return Some(arxiv_pdf_name(&id));
+    }
+    let doi = metadata.doi.as_deref()?.to_lowercase().replace('/', "_");
+    Some(sanitize_filename::sanitize(format!("doi-{doi}")))
+}
+
+fn arxiv_pdf_name(id: &ArxivId) -> String {
+    // Old-style ids ("hep-th/9901001") contain a slash.
+    id.as_str().replace('/', "_")
+}
+
+fn has_extension(path: &Path, allowed: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| allowed.iter().any(|a| a.eq_ignore_ascii_case(e)))
+}
@@ -0,0 +1,95 @@
+//! Author ORCID iDs and citation counts from OpenAlex (`api.openalex.org`), which merges Crossref,
+//! ORCID and publisher records. A paper is looked up by DOI; arXiv papers without a journal DOI go
+//! by the DOI arXiv registers for every submission (`10.48550/arXiv.<id>`).
+
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use url::Url;
+
+use super::arxiv::ArxivId;
+use crate::{http, paper::PaperMetadata, MabelError, Result};
+
+const API_URL: &str = "https://api.openalex.org/works/";
+
+#[derive(Deserialize)]
+struct Work {
+    #[serde(default)]
+    authorships: Vec<Authorship>,
+    cited_by_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Authorship {
+    author: Author,
+    /// The name as printed on the paper; `author.display_name` is OpenAlex's canonical one
+    raw_author_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    display_name: Option<String>,
+    orcid: Option<String>,
+}
+
+/// The DOI OpenAlex knows a paper by: its own DOI, or the one arXiv registered for it.
+pub fn lookup_doi(doi: Option<&str>, arxiv: Option<&str>) -> Option<String> {
+    doi.map(str::to_string).or_else(|| {
+        let id = ArxivId::parse(arxiv?).ok()?;
+        Some(format!("10.48550/arXiv.{}", id.base()))
+    })
+}
+
+/// The work with DOI `doi`; `None` when OpenAlex has no such work.
+async fn work(http: &Client, doi: &str, retries: u32) -> Result<Option<Work>> {
+    let url = Url::parse(&format!("{API_URL}doi:{doi}"))?;
+    let body = match http::get_text(http, url, retries).await {
+        | Ok(body) => body,
+        | Err(MabelError::HttpStatus {
+            status: StatusCode::NOT_FOUND,
+            ..
+        }) => return Ok(None),
+        | Err(e) => return Err(e),
+    };
+    Ok(Some(serde_json::from_str(&body)?))
+}
+
+/// Authors of the work with DOI `doi` as `(name, ORCID iD)`, in byline order; `None` when
+/// OpenAlex has no such work.
+pub async fn authors(http: &Client, doi: &str, retries: u32) -> Result<Option<Vec<(String, Option<String>)>>> {
+    let Some(work) = work(http, doi, retries).await? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        work.authorships
+            .into_iter()
+            .filter_map(|a| {
+                let name = a.raw_author_name.or(a.author.display_name)?;
+                Some((name, a.author.orcid.as_deref().and_then(mabel_core::id::orcid)))
+            })
+            .collect(),
+    ))
+}
+
+/// How many works OpenAlex knows to cite the work with DOI `doi`; `None` when it has no such work.
+pub async fn citations(http: &Client, doi: &str, retries: u32) -> Result<Option<u64>> {
+    Ok(work(http, doi, retries).await?.map(|w| w.cited_by_count.unwrap_or(0)))
+}
+
+/// Fill in the ORCID iDs of `md`'s authors that the source did not give. Best effort: a paper
+/// OpenAlex does not know, or a failed lookup, leaves the metadata as it was.
+pub async fn add_orcids(http: &Client, md: &mut PaperMetadata, retries: u32) {
+    if md.authors.is_empty() || (0..md.authors.len()).all(|i| md.orcid(i).is_some()) {
+        return;
+    }
+    let Some(doi) = lookup_doi(md.doi.as_deref(), md.arxiv_id.as_deref()) else {
+        return;
+    };
+    match authors(http, &doi, retries).await {
+        | Ok(Some(found)) => {
+            let added = md.merge_orcids(&found);
+            tracing::debug!(doi, added, "ORCID iDs from OpenAlex");
+        }
+        | Ok(None) => tracing::debug!(doi, "OpenAlex has no record of the paper"),
+        | Err(e) => tracing::warn!(doi, error = %e, "ORCID lookup failed; authors stay unlinked"),
+    }
+}
@@ -0,0 +1,207 @@
+//! PubMed / PubMed Central resolver built on NCBI E-utilities.
+//!
+//! A PMID resolves to PubMed metadata; if the record links to a PMC article we additionally pull
+//! the open-access JATS full text from PMC. A PMCID goes straight to PMC and takes its metadata
+//! from the JATS front matter.
+
+use chrono::NaiveDate;
+use reqwest::Client;
+use url::Url;
+
+use super::ResolvedPaper;
+use crate::{
+    config::Config,
+    extract::jats,
+    http,
+    paper::PaperMetadata,
+    secret::Secret,
+    xml::{self, Element},
+    MabelError, Result,
+};
+
+pub use mabel_core::id::PubmedId;
+
+const EFETCH_URL: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils/efetch.fcgi";
+
+/// NCBI asks every E-utilities client to identify itself.
+const TOOL: &str = "mabel";
+
+pub struct PubmedResolver {
+    http: Client,
+    api_key: Option<Secret>,
+    email: Option<String>,
+    retries: u32,
+}
+
+impl PubmedResolver {
+    pub fn new(http: Client, cfg: &Config) -> Self {
+        Self {
+            http,
+            api_key: cfg.ncbi_api_key.clone(),
+            email: cfg.ncbi_email.clone(),
+            retries: cfg.http_retries,
+        }
+    }
+
+    pub async fn resolve(&self, id: &PubmedId) -> Result<ResolvedPaper> {
+        match id {
+            | PubmedId::Pmid(pmid) => {
+                let mut metadata = self.fetch_pubmed(pmid).await?;
+                let structure = match metadata.pmcid.clone() {
+                    | Some(pmc) => {
+                        let article = self.fetch_pmc(&pmc).await?;
+                        // PubMed's record is authoritative, but JATS often carries author
+                        // keywords that PubMed lacks.
+                        if metadata.keywords.is_empty() {
+                            metadata.keywords = jats::metadata(&article).keywords;
+                        }
+                        jats::has_body(&article).then(|| jats::extract(&article))
+                    }
+                    | None => None,
+                };
+                Ok(ResolvedPaper {
+                    metadata,
+                    structure,
+                    pdf_url: None,
+                })
+            }
+            | PubmedId::Pmcid(pmcid) => {
+                let article = self.fetch_pmc(pmcid).await?;
+                let mut metadata = jats::metadata(&article);
+                metadata.pmcid.get_or_insert_with(|| pmcid.clone());
+                let structure = jats::has_body(&article).then(|| jats::extract(&article));
+                Ok(ResolvedPaper {
+                    metadata,
+                    structure,
+                    pdf_url: None,
+                })
+            }
+        }
+    }
+
+    async fn fetch_pubmed(&self, pmid: &str) -> Result<PaperMetadata> {
+        let url = self.efetch_url("pubmed", pmid)?;
+        let body = http::get_text(&self.http, url, self.retries).await?;
+        let root = xml::parse(&body, "PubMed efetch response")?;
+        let article = root.find("PubmedArticle").ok_or_else(|| {
+            MabelError::InvalidPubmedId {
+                input: format!("PMID:{pmid} (no such PubMed record)"),
+            }
+        })?;
+        Ok(pubmed_metadata(article, pmid))
+    }
+
+    async fn fetch_pmc(&self, pmcid: &str) -> Result<Element> {
+        // efetch wants the numeric part only.
+        let numeric = pmcid.trim_start_matches("PMC");
+        let url = self.efetch_url("pmc", numeric)?;
+        let body = http::get_text(&self.http, url, self.retries).await?;
+        jats::parse_article(&body).map_err(|_| {
+            MabelError::InvalidPubmedId {
+                input: format!("{pmcid} (no such PMC article)"),
+            }
+        })
+    }
+
+    fn efetch_url(&self, db: &str, id: &str) -> Result<Url> {
+        let mut params = vec![("db", db), ("id", id), ("retmode", "xml"), ("tool", TOOL)];
+        if let Some(email) = &self.email {
+            params.push(("email", email));
+        }
+        if let Some(key) = &self.api_key {
+            params.push(("api_key", key.expose()));
+        }
+        Ok(Url::parse_with_params(EFETCH_URL, &params)?)
+    }
+}
+
+/// Map a `<PubmedArticle>` record to our metadata.
+fn pubmed_metadata(article: &Element, pmid: &str) -> PaperMetadata {
+    let citation = article.child("MedlineCitation");
+    let art = citation.and_then(|c| c.child("Article"));
+
+    let mut md = PaperMetadata {
+        pmid: Some(pmid.to_string()),
+        url: Some(format!("https://pubmed.ncbi.nlm.nih.gov/{pmid}/")),
+        ..PaperMetadata::default()
+    };
+
+    if let Some(art) = art {
+        md.title = art.find_text("ArticleTitle").unwrap_or_default();
+        md.journal = art.path(&["Journal", "Title"]).map(Element::text);
+        md.abstract_text = art.child("Abstract").map(|abs| {
+            abs.children_named("AbstractText")
+                .map(|t| {
+                    match t.attr("Label") {
+                        | Some(label) => format!("{label}: {}", t.text()),
+                        | None => t.text(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        });
+        if let Some(list) = art.child("AuthorList") {
+            md.authors = list
+                .children_named("Author")
+                .filter_map(|a| {
+                    let last = a.find_text("LastName");
+                    let fore = a.find_text("ForeName");
+                    match (fore, last) {
+                        | (Some(f), Some(l)) => Some(format!("{f} {l}")),
+                        | (None, Some(l)) => Some(l),
+                        | _ => a.find_text("CollectiveName"),
+                    }
+                })
+                .collect();
+        }
+        md.published = art
+            .child("ArticleDate")
+            .and_then(pubmed_date)
+            .or_else(|| art.path(&["Journal", "JournalIssue", "PubDate"]).and_then(pubmed_date));
+    }
+
+    if let Some(c) = citation {
+        md.keywords = c.find_all("Keyword").into_iter().map(Element::text).collect();
+    }
+
+    if let Some(ids) = article.path(&["PubmedData", "ArticleIdList"]) {
+        for id in ids.children_named("ArticleId") {
+            match id.attr("IdType") {
+                | Some("doi") => md.doi = Some(id.text()),
+                | Some("pmc") => md.pmcid = Some(jats::normalize_pmcid(&id.text())),
+                | _ => {}
+            }
+        }
+    }
+    md
+}
+
+/// PubMed dates come as `<Year>/<Month>/<Day>` where month may be numeric or `Jan`, or as a
+/// free-text `<MedlineDate>` like `1998 Dec-1999 Jan`.
+fn pubmed_date(date: &Element) -> Option<NaiveDate> {
+    let year: i32 = date
+        .find_text("Year")
+        .or_else(|| {
+            date.find_text("MedlineDate")
+                .and_then(|d| d.get(..4).map(str::to_string))
+        })?
+        .parse()
+        .ok()?;
+    let month = date.find_text("Month").and_then(|m| parse_month(&m)).unwrap_or(1);
+    let day = date.find_text("Day").and_then(|d| d.parse().ok()).unwrap_or(1);
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+fn parse_month(m: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    if let Ok(n) = m.parse() {
+        return Some(n);
+    }
+    let prefix = m.get(..3)?.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|&name| name == prefix)
+        .and_then(|i| u32::try_from(i + 1).ok())
+}
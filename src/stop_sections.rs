@@ -0,0 +1,130 @@
+//! Sections left out of the text sent to the model: the parts of a paper that say nothing about
+//! its work, like acknowledgments, ethics statements and reproducibility checklists, which only
+//! make the prompt longer and dearer. The paper's note and full-text sidecar keep them.
+//!
+//! The list is read from `MABEL_STOP_SECTIONS`, comma-separated headings; one prefixed with a mode
+//! (`study:appendix`) applies in that mode only. Setting it replaces the defaults ([`DEFAULT`]),
+//! and `none` leaves nothing out:
+//!
+//! ```text
+//! MABEL_STOP_SECTIONS="acknowledgments, funding, ethics statement, concise:appendix"
+//! ```
+//!
+//! A section stops when its heading, numbering aside, is the listed one or starts or ends with it
+//! as whole words (`appendix` stops `Appendix B: Proofs`, `paper checklist` stops `NeurIPS Paper
+//! Checklist`), and its subsections go with it.
+
+use std::{fmt, str::FromStr};
+
+use crate::{config::Mode, MabelError};
+
+/// What is left out unless `MABEL_STOP_SECTIONS` says otherwise. Appendices are kept in study
+/// mode, whose reproduction checklist looks for hyperparameters and compute there.
+pub const DEFAULT: &str = "acknowledgments, acknowledgements, acknowledgment, acknowledgement, funding, author \
+                           contributions, competing interests, conflict of interest, conflicts of interest, ethics \
+                           statement, ethical considerations, broader impact, broader impacts, impact statement, \
+                           reproducibility statement, reproducibility checklist, paper checklist, concise:appendix, \
+                           eli-grad:appendix, skim:appendix, flashcards:appendix";
+
+const MODES: &[&str] = &["concise", "study", "eli-grad", "skim", "flashcards"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Stop {
+    /// The mode the stop applies in; every mode when `None`
+    mode: Option<String>,
+    /// Lowercase
+    heading: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopSections {
+    stops: Vec<Stop>,
+}
+
+impl Default for StopSections {
+    fn default() -> Self {
+        DEFAULT.parse().unwrap_or(Self { stops: Vec::new() })
+    }
+}
+
+impl StopSections {
+    /// Whether the section with `heading` (as [`crate::paper::plain_heading`] gives it) is left out
+    /// in `mode`.
+    pub fn stops(&self, mode: &Mode, heading: &str) -> bool {
+        self.stops.iter().filter(|s| s.applies_in(mode)).any(|s| {
+            let h = s.heading.as_str();
+            heading == h
+                || heading
+                    .strip_prefix(h)
+                    .is_some_and(|rest| rest.starts_with(|c: char| !c.is_alphanumeric()))
+                || heading
+                    .strip_suffix(h)
+                    .is_some_and(|rest| rest.ends_with(|c: char| !c.is_alphanumeric()))
+        })
+    }
+
+    /// Whether nothing is left out in `mode`.
+    pub fn is_empty(&self, mode: &Mode) -> bool {
+        !self.stops.iter().any(|s| s.applies_in(mode))
+    }
+}
+
+impl Stop {
+    fn applies_in(&self, mode: &Mode) -> bool {
+        self.mode.as_deref().is_none_or(|m| m == mode.as_str())
+    }
+}
+
+impl FromStr for StopSections {
+    type Err = MabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(Self { stops: Vec::new() });
+        }
+        let mut stops = Vec::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (mode, heading) = match entry.split_once(':') {
+                | Some((mode, heading)) => {
+                    let mode = mode.trim().to_lowercase();
+                    if !MODES.contains(&mode.as_str()) {
+                        return Err(MabelError::Config {
+                            msg: format!(
+                                "invalid stop section {entry:?}: unknown mode {mode:?} (expected one of {})",
+                                MODES.join(", ")
+                            ),
+                        });
+                    }
+                    (Some(mode), heading)
+                }
+                | None => (None, entry),
+            };
+            let heading = heading.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+            if heading.is_empty() {
+                return Err(MabelError::Config {
+                    msg: format!("invalid stop section {entry:?}: missing heading"),
+                });
+            }
+            stops.push(Stop { mode, heading });
+        }
+        Ok(Self { stops })
+    }
+}
+
+impl fmt::Display for StopSections {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.stops.is_empty() {
+            return f.write_str("none");
+        }
+        for (i, s) in self.stops.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if let Some(mode) = &s.mode {
+                write!(f, "{mode}:")?;
+            }
+            f.write_str(&s.heading)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Per-note record stores: JSONL files in the vault's `.mabel` folder (claims, results), each line
+//! one record tagged with the note it came from.
+
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{MabelError, Result};
+
+/// Every record in the store; a missing store is empty and unreadable lines are skipped.
+pub async fn load<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let text = match tokio::fs::read_to_string(path).await {
+        | Ok(text) => text,
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        | Err(source) => {
+            return Err(MabelError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+    let mut skipped = 0;
+    let records = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str(l).inspect_err(|_| skipped += 1).ok())
+        .collect();
+    if skipped > 0 {
+        tracing::warn!(skipped, path = %path.display(), "ignored unreadable lines in a record store");
+    }
+    Ok(records)
+}
+
+/// Store `records`, replacing every record for which `replaced` is true. Returns the whole store
+/// as written.
+pub async fn replace<T: Serialize + DeserializeOwned + Clone>(
+    path: &Path,
+    records: &[T],
+    replaced: impl Fn(&T) -> bool,
+) -> Result<Vec<T>> {
+    let mut all: Vec<T> = load(path).await?;
+    all.retain(|r| !replaced(r));
+    all.extend_from_slice(records);
+    let mut out = String::new();
+    for record in &all {
+        out.push_str(&serde_json::to_string(record)?);
+        out.push('\n');
+    }
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    };
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(io_err)?;
+    }
+    // Write-then-rename so an interrupted run never leaves a half-written store.
+    let tmp = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp, out).await.map_err(io_err)?;
+    tokio::fs::rename(&tmp, path).await.map_err(io_err)?;
+    Ok(all)
+}
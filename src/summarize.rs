@@ -0,0 +1,249 @@
+//! Turning extracted text into the structured summary the note template renders.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt::Write};
+
+pub use mabel_core::summary::{
+    BookSummary, ChapterSummary, FigureImage, Flashcard, GlossaryEntry, Reproduction, Summary, Term, UNCERTAIN_CLOSE,
+    UNCERTAIN_OPEN,
+};
+
+use crate::{
+    extract::epub::Book,
+    llm::{self, Llm, Prompt, Usage},
+    paper::PaperMetadata,
+    prompt::{self, Prompts},
+    skim::Page,
+    vault::RelatedNote,
+    MabelError, Result,
+};
+
+/// Parts of a long paper summarized at the same time.
+const PARALLEL_PARTS: usize = 4;
+
+/// Summarize a paper from its full text (or abstract, when that is all we have), in the parts
+/// [`chunk::split`](crate::chunk::split) made of it: a paper in more than one part has each
+/// summarized first, and its summary is written from the notes on them. `vars` is the context
+/// given with `--var`.
+pub async fn paper(
+    llm: &Llm,
+    prompts: &Prompts,
+    metadata: &PaperMetadata,
+    parts: &[String],
+    related: &[RelatedNote],
+    vars: &BTreeMap<String, String>,
+    uncertainty: bool,
+) -> Result<(Summary, Usage)> {
+    if parts.iter().all(|part| part.trim().is_empty()) {
+        return Err(MabelError::Extraction {
+            reason: format!("no text to summarize for {:?}", metadata.title),
+        });
+    }
+    let mut usage = Usage::default();
+    let text = match parts {
+        | [text] => {
+            if text.len() > prompt::MAX_INPUT_CHARS {
+                tracing::warn!(
+                    chars = text.len(),
+                    limit = prompt::MAX_INPUT_CHARS,
+                    "paper text truncated to fit the prompt"
+                );
+            }
+            Cow::Borrowed(text.as_str())
+        }
+        | _ => {
+            let (notes, notes_usage) = part_notes(llm, metadata, parts, vars).await?;
+            usage += notes_usage;
+            Cow::Owned(notes)
+        }
+    };
+    let mut request = prompt::with_vars(prompts.paper(metadata, &text, related, vars)?, vars);
+    if uncertainty {
+        request = prompt::with_uncertainty(request);
+    }
+    let completion = llm.complete(&request).await?;
+    usage += completion.usage;
+    let mut summary: Summary = parse_reply(&completion.text);
+    normalize_tags(&mut summary.tags);
+    normalize_flashcards(&mut summary.flashcards);
+    normalize_terms(&mut summary.terms);
+    if uncertainty {
+        resolve_uncertainty(&mut summary);
+    }
+    Ok((summary, usage))
+}
+
+/// The notes on each part of a paper too long for one prompt, taken a few parts at a time, as the
+/// text its summary is written from.
+async fn part_notes(
+    llm: &Llm,
+    metadata: &PaperMetadata,
+    parts: &[String],
+    vars: &BTreeMap<String, String>,
+) -> Result<(String, Usage)> {
+    tracing::info!(
+        parts = parts.len(),
+        "paper too long for one prompt; summarizing it in parts"
+    );
+    let requests: Vec<Prompt> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| prompt::with_vars(prompt::part(metadata, i + 1, parts.len(), part), vars))
+        .collect();
+    let mut replies = Vec::with_capacity(parts.len());
+    let mut pending = None;
+    for batch in requests.chunks(PARALLEL_PARTS) {
+        for reply in futures::future::join_all(batch.iter().map(|request| llm.complete(request))).await {
+            match reply {
+                | Ok(reply) => replies.push(reply),
+                // Every part goes into the same OpenAI batch, not one part a batch.
+                | Err(e @ MabelError::BatchPending) => pending = Some(e),
+                | Err(e) => return Err(e),
+            }
+        }
+    }
+    if let Some(e) = pending {
+        return Err(e);
+    }
+    let mut usage = Usage::default();
+    let mut notes = format!(
+        "The paper is too long to give in full. These are notes on its {} parts, in order.\n",
+        parts.len()
+    );
+    for (i, reply) in replies.iter().enumerate() {
+        usage += reply.usage;
+        let _ = write!(
+            notes,
+            "\n## Part {} of {}\n\n{}\n",
+            i + 1,
+            parts.len(),
+            reply.text.trim()
+        );
+    }
+    Ok((notes, usage))
+}
+
+/// A first impression of a paper from images of some of its pages.
+pub async fn skim(
+    llm: &Llm,
+    prompts: &Prompts,
+    metadata: &PaperMetadata,
+    pages: &[Page],
+    vars: &BTreeMap<String, String>,
+    uncertainty: bool,
+) -> Result<(Summary, Usage)> {
+    let mut request = prompt::with_vars(prompts.skim(metadata, pages, vars)?, vars);
+    if uncertainty {
+        request = prompt::with_uncertainty(request);
+    }
+    let completion = llm.complete(&request).await?;
+    let mut summary: Summary = parse_reply(&completion.text);
+    normalize_tags(&mut summary.tags);
+    normalize_terms(&mut summary.terms);
+    if uncertainty {
+        resolve_uncertainty(&mut summary);
+    }
+    Ok((summary, completion.usage))
+}
+
+/// Summarize each chapter, then write an overview from the chapter summaries.
+pub async fn book(llm: &Llm, book: &Book, vars: &BTreeMap<String, String>) -> Result<(BookSummary, Usage)> {
+    let mut usage = Usage::default();
+    let mut chapters = Vec::with_capacity(book.chapters.len());
+    for chapter in &book.chapters {
+        tracing::info!(number = chapter.number, title = %chapter.title, "summarizing chapter");
+        let completion = llm
+            .complete(&prompt::with_vars(
+                prompt::chapter(&book.metadata, &chapter.title, &chapter.text),
+                vars,
+            ))
+            .await?;
+        usage += completion.usage;
+        let reply: Summary = parse_reply(&completion.text);
+        chapters.push(ChapterSummary {
+            number: chapter.number,
+            title: chapter.title.clone(),
+            summary: reply.summary,
+            key_points: reply.key_points,
+        });
+    }
+
+    let digests: Vec<(String, String)> = chapters
+        .iter()
+        .map(|c| (format!("{}. {}", c.number, c.title), c.summary.clone()))
+        .collect();
+    let request = prompt::with_vars(prompt::book_overview(&book.metadata, &digests), vars);
+    let completion = llm.complete(&request).await?;
+    usage += completion.usage;
+    let overview: Summary = parse_reply(&completion.text);
+    let mut tags = overview.tags;
+    normalize_tags(&mut tags);
+
+    Ok((
+        BookSummary {
+            overview: overview.summary,
+            tags,
+            chapters,
+        },
+        usage,
+    ))
+}
+
+/// Parse the model's JSON reply. Models sometimes wrap JSON in a code fence or add prose around
+/// it; when no object can be recovered the whole reply becomes the summary text.
+fn parse_reply(reply: &str) -> Summary {
+    let trimmed = reply.trim();
+    let candidate = llm::json_object(trimmed);
+    serde_json::from_str(candidate).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "model reply was not the requested JSON; using it verbatim");
+        Summary {
+            summary: trimmed.to_string(),
+            ..Summary::default()
+        }
+    })
+}
+
+/// Style the statements the model marked as uncertain. Marks it left unpaired are dropped with a
+/// warning.
+fn resolve_uncertainty(summary: &mut Summary) {
+    let unpaired = summary.resolve_uncertainty();
+    if unpaired > 0 {
+        tracing::warn!(unpaired, "dropped uncertainty marks without a partner");
+    }
+    if summary.uncertain > 0 {
+        tracing::info!(statements = summary.uncertain, "statements marked uncertain");
+    }
+}
+
+/// Cards are written one per line as `question::answer`, the spaced repetition plugin's inline
+/// form: put each side on one line, keep `::` out of them, and drop cards missing a side.
+fn normalize_flashcards(cards: &mut Vec<Flashcard>) {
+    let side = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").replace("::", ":");
+    for card in cards.iter_mut() {
+        card.question = side(&card.question);
+        card.answer = side(&card.answer);
+    }
+    cards.retain(|c| !c.question.is_empty() && !c.answer.is_empty());
+}
+
+/// Terms become note titles and links: put each on one line, and drop terms without a definition
+/// and repeats of a term in another case.
+fn normalize_terms(terms: &mut Vec<Term>) {
+    for term in terms.iter_mut() {
+        term.term = term.term.split_whitespace().collect::<Vec<_>>().join(" ");
+        term.definition = term.definition.trim().to_string();
+        term.link = None;
+    }
+    terms.retain(|t| !t.term.is_empty() && !t.definition.is_empty());
+    let mut seen = std::collections::HashSet::new();
+    terms.retain(|t| seen.insert(t.term.to_lowercase()));
+}
+
+/// Obsidian tags cannot contain spaces and are case-insensitive; keep them tidy and unique.
+fn normalize_tags(tags: &mut Vec<String>) {
+    for tag in tags.iter_mut() {
+        *tag = slug::slugify(tag.trim_start_matches('#'));
+    }
+    tags.retain(|t| !t.is_empty());
+    let mut seen = std::collections::HashSet::new();
+    tags.retain(|t| seen.insert(t.clone()));
+}
@@ -0,0 +1,53 @@
+//! A thread of short numbered posts on each paper, for sharing on Mastodon, Bluesky or a lab
+//! Slack. With `--thread` (`MABEL_THREAD`) each paper note gets one in the vault's `Threads`
+//! folder, made from the summary with the thread template (`mabel template show --thread`) and
+//! rewritten whenever the note is. Each post keeps to `MABEL_THREAD_CHARS`, counted as the
+//! networks count (see [`mabel_core::thread`]).
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::Config,
+    note,
+    paper::PaperMetadata,
+    render::{Renderer, ThreadNote},
+    summarize::Summary,
+    MabelError, Result,
+};
+
+/// Write the thread on the paper whose note is `note_path`, replacing an earlier one.
+pub async fn write(
+    cfg: &Config,
+    renderer: &Renderer,
+    metadata: &PaperMetadata,
+    summary: &Summary,
+    note_path: &Path,
+) -> Result<PathBuf> {
+    let stem = note_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let text = renderer.render_thread(&ThreadNote {
+        metadata,
+        summary,
+        note: &stem,
+        max_chars: cfg.thread_chars as usize,
+        created: cfg.timezone.timestamp(chrono::Utc::now()),
+    })?;
+    let path = thread_path(cfg, &stem);
+    let io_err = |source| {
+        MabelError::Io {
+            path: path.clone(),
+            source,
+        }
+    };
+    tokio::fs::create_dir_all(cfg.threads_dir()).await.map_err(io_err)?;
+    tokio::fs::write(&path, text).await.map_err(io_err)?;
+    Ok(path)
+}
+
+/// The thread on the note with file stem `stem`. Named apart from the note so `[[links]]` to the
+/// note stay unambiguous.
+pub fn thread_path(cfg: &Config, stem: &str) -> PathBuf {
+    cfg.threads_dir().join(format!("{} (thread).md", note::file_stem(stem)))
+}
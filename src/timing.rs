@@ -0,0 +1,185 @@
+//! The run report: where a paper's time and money went. Fetching (the metadata lookup and the PDF
+//! download), PDF extraction and the model's replies are timed as they happen, and the report is
+//! logged when the note is written and kept in its registry record, so GROBID and the built-in
+//! extractor can be compared on real papers (`mabel info` shows it).
+//!
+//! Timings are collected per task, like [`crate::ci::collect`]'s logs: work outside
+//! [`collect`] is not timed.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{llm::Usage, paper::Extractor};
+
+/// What is timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Metadata lookups and PDF downloads
+    Fetch,
+    /// Reading the PDF, with GROBID or the built-in extractor
+    Extract,
+    /// Waiting for the model's replies
+    Llm,
+}
+
+/// The times collected so far.
+#[derive(Clone, Copy, Debug, Default)]
+struct Times {
+    fetch: Duration,
+    extract: Duration,
+    llm: Duration,
+    llm_requests: u32,
+    llm_failures: u32,
+    llm_failed: Duration,
+    extractor: Option<Extractor>,
+}
+
+tokio::task_local! {
+    static TIMES: Arc<Mutex<Times>>;
+}
+
+/// Run `work`, timing the steps it takes on the task it runs on.
+pub async fn collect<T>(work: impl Future<Output = T>) -> T {
+    TIMES.scope(Arc::new(Mutex::new(Times::default())), work).await
+}
+
+/// Run `work` as `step`, adding the time it takes to the run's. `work` is boxed, so timing a step
+/// does not make the futures awaiting it any larger.
+pub fn time<'a, T: 'a>(step: Step, work: impl Future<Output = T> + 'a) -> impl Future<Output = T> + 'a {
+    let work = Box::pin(work);
+    async move {
+        let start = Instant::now();
+        let out = work.await;
+        let elapsed = start.elapsed();
+        with_times(|times| {
+            match step {
+                | Step::Fetch => times.fetch += elapsed,
+                | Step::Extract => times.extract += elapsed,
+                | Step::Llm => times.llm += elapsed,
+            }
+        });
+        out
+    }
+}
+
+/// Run the model request `work`, counting it, and when it fails (after its retries) the time it
+/// took, which says nothing of how fast the model answers.
+pub async fn counted<T>(work: impl Future<Output = crate::Result<T>>) -> crate::Result<T> {
+    let start = Instant::now();
+    let out = work.await;
+    with_times(|times| {
+        times.llm_requests += 1;
+        if out.is_err() {
+            times.llm_failures += 1;
+            times.llm_failed += start.elapsed();
+        }
+    });
+    out
+}
+
+/// Note which extractor read the PDF.
+pub fn extracted_with(extractor: Extractor) {
+    with_times(|times| times.extractor = Some(extractor));
+}
+
+fn with_times(change: impl FnOnce(&mut Times)) {
+    let _ = TIMES.try_with(|times| change(&mut times.lock().unwrap_or_else(PoisonError::into_inner)));
+}
+
+/// Where a paper's time and money went.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub fetch_ms: u64,
+    pub extract_ms: u64,
+    /// Summed over the replies, which may have been waited for side by side
+    pub llm_ms: u64,
+    /// Model requests sent, and how many of them failed
+    #[serde(default)]
+    pub llm_requests: u32,
+    #[serde(default)]
+    pub llm_failures: u32,
+    /// Of `llm_ms`, what the failed requests took
+    #[serde(default)]
+    pub llm_failed_ms: u64,
+    pub tokens_in: u64,
+    pub tokens_out: u64,
+    /// Estimated, in US dollars, when the model's price is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// Which extractor read the PDF, when one did this run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extractor: Option<Extractor>,
+}
+
+impl RunReport {
+    /// The report of the run so far, with the tokens and cost it took.
+    pub fn now(usage: Usage, cost: Option<f64>) -> Self {
+        let mut times = Times::default();
+        with_times(|t| times = *t);
+        let ms = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+        Self {
+            fetch_ms: ms(times.fetch),
+            extract_ms: ms(times.extract),
+            llm_ms: ms(times.llm),
+            llm_requests: times.llm_requests,
+            llm_failures: times.llm_failures,
+            llm_failed_ms: ms(times.llm_failed),
+            tokens_in: usage.prompt_tokens,
+            tokens_out: usage.completion_tokens,
+            cost,
+            extractor: times.extractor,
+        }
+    }
+
+    /// Log the report at info level.
+    pub fn log(&self) {
+        tracing::info!(
+            fetch_ms = self.fetch_ms,
+            extract_ms = self.extract_ms,
+            llm_ms = self.llm_ms,
+            llm_requests = self.llm_requests,
+            llm_failures = self.llm_failures,
+            tokens_in = self.tokens_in,
+            tokens_out = self.tokens_out,
+            cost = self.cost.map(crate::cost::format),
+            extractor = self.extractor.map(extractor_name),
+            "run report"
+        );
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fetch {} ms, extract {} ms{}, model {} ms, {} tokens in, {} out",
+            self.fetch_ms,
+            self.extract_ms,
+            self.extractor
+                .map(|e| format!(" ({})", extractor_name(e)))
+                .unwrap_or_default(),
+            self.llm_ms,
+            self.tokens_in,
+            self.tokens_out
+        )?;
+        if let Some(cost) = self.cost {
+            write!(f, ", {}", crate::cost::format(cost))?;
+        }
+        Ok(())
+    }
+}
+
+fn extractor_name(extractor: Extractor) -> &'static str {
+    match extractor {
+        | Extractor::Jats => "JATS",
+        | Extractor::Epub => "EPUB",
+        | Extractor::Grobid => "GROBID",
+        | Extractor::PdfText => "built-in",
+    }
+}
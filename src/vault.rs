@@ -0,0 +1,339 @@
+//! Reading what is already in the vault: note frontmatter, and finding notes related to a new
+//! paper so its summary can link to them.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use crate::{
+    paper::Reference,
+    render::FrontmatterFields,
+    source::{arxiv::ArxivId, pubmed::PubmedId, ResolvedPaper},
+};
+
+/// Embedding similarity a note needs to count as related to a new paper.
+const MIN_SIMILARITY: f32 = 0.4;
+
+/// The frontmatter fields mabel cares about; anything else is ignored.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Frontmatter {
+    pub title: Option<String>,
+    pub doi: Option<String>,
+    pub arxiv: Option<String>,
+    pub pmid: Option<String>,
+    pub pmcid: Option<String>,
+    pub journal: Option<String>,
+    pub url: Option<String>,
+    pub tags: Vec<String>,
+    /// Reading status the user keeps, e.g. `to-read` or `read`
+    pub status: Option<String>,
+    /// Citation count, as `mabel refresh-citations` last found it
+    pub citations: Option<u64>,
+    /// Altmetric attention score, as `mabel refresh-citations` last found it
+    pub altmetric: Option<u64>,
+}
+
+/// A note found in the vault.
+#[derive(Clone, Debug)]
+pub struct VaultNote {
+    pub path: PathBuf,
+    /// File stem, which is what `[[wikilinks]]` resolve against
+    pub link: String,
+    pub frontmatter: Frontmatter,
+    /// First line of the TL;DR callout, when the note has one
+    pub tldr: Option<String>,
+    /// What had to be repaired or left out to read the frontmatter (see [`frontmatter_repaired`])
+    pub repaired: Vec<String>,
+}
+
+impl VaultNote {
+    pub fn title(&self) -> &str {
+        self.frontmatter.title.as_deref().unwrap_or(&self.link)
+    }
+
+    /// Whether the user marked the note as read, with `status: read` or a `read` tag.
+    pub fn is_read(&self) -> bool {
+        let fm = &self.frontmatter;
+        fm.status.as_deref().is_some_and(|s| s.trim().eq_ignore_ascii_case("read"))
+            || fm.tags.iter().any(|t| t.eq_ignore_ascii_case("read"))
+    }
+}
+
+pub use mabel_core::note::{set_frontmatter_field, split_frontmatter, RelatedNote, Relation};
+
+/// The fields of a note's frontmatter, read through the property names of `[frontmatter]`; `None`
+/// when it is not a mapping.
+pub fn frontmatter(yaml: &str, fields: &FrontmatterFields) -> Option<Frontmatter> {
+    frontmatter_repaired(yaml, fields).map(|(frontmatter, _)| frontmatter)
+}
+
+/// [`frontmatter`], with what had to be repaired or left out to read it. Hand-written frontmatter
+/// is read leniently (see [`mabel_core::note::read_frontmatter`]), and a field of a type mabel does
+/// not expect is ignored, but for ids written as numbers, which are taken as written, and `tags`
+/// given as one string (`tags: ml, papers`), which are split.
+pub fn frontmatter_repaired(yaml: &str, fields: &FrontmatterFields) -> Option<(Frontmatter, Vec<String>)> {
+    let (mut map, mut problems) = mabel_core::note::read_frontmatter(yaml)?;
+    fields.restore(&mut map);
+    // Ids written unquoted read as numbers (`arxiv: 2101.00010`, losing the zero); take them as
+    // written.
+    for field in ["title", "doi", "arxiv", "pmid", "pmcid", "journal", "url", "status"] {
+        let key = serde_yaml::Value::from(field);
+        if !map.get(&key).is_some_and(serde_yaml::Value::is_number) {
+            continue;
+        }
+        let written = fields.name(field).and_then(|property| {
+            yaml.lines()
+                .find_map(|l| l.strip_prefix(property)?.strip_prefix(':'))
+                .map(str::trim)
+        });
+        if let Some(written) = written {
+            map.insert(key, serde_yaml::Value::from(written));
+        }
+    }
+    let tags = serde_yaml::Value::from("tags");
+    if let Some(serde_yaml::Value::String(list)) = map.get(&tags) {
+        let split: Vec<serde_yaml::Value> = list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(|t| t.trim_start_matches('#'))
+            .filter(|t| !t.is_empty())
+            .map(serde_yaml::Value::from)
+            .collect();
+        map.insert(tags, serde_yaml::Value::Sequence(split));
+    }
+    if let Ok(frontmatter) = serde_yaml::from_value(serde_yaml::Value::Mapping(map.clone())) {
+        return Some((frontmatter, problems));
+    }
+    let mut kept = serde_yaml::Mapping::new();
+    for (key, value) in map {
+        let mut one = kept.clone();
+        one.insert(key.clone(), value.clone());
+        if serde_yaml::from_value::<Frontmatter>(serde_yaml::Value::Mapping(one)).is_ok() {
+            kept.insert(key, value);
+        } else {
+            let name = key.as_str().unwrap_or_default();
+            problems.push(format!("{name:?} is not of the type mabel expects; ignored"));
+        }
+    }
+    let frontmatter = serde_yaml::from_value(serde_yaml::Value::Mapping(kept)).unwrap_or_default();
+    Some((frontmatter, problems))
+}
+
+/// Warn that the frontmatter of the note at `path` was read leniently, with what was `repaired`,
+/// before the note is changed.
+pub fn warn_repaired(path: &Path, repaired: &[String]) {
+    if !repaired.is_empty() {
+        tracing::warn!(
+            note = %path.display(),
+            repaired = repaired.join("; "),
+            "the frontmatter is not valid YAML; read it leniently"
+        );
+    }
+}
+
+/// Tell Obsidian the types `[frontmatter]` gives its properties, in the vault's
+/// `.obsidian/types.json`, so the properties editor shows dates as dates and lists as lists. Best
+/// effort, and only in a vault Obsidian has opened; the types of other properties are kept.
+pub fn register_property_types(vault: &Path, fields: &FrontmatterFields) {
+    let dir = vault.join(".obsidian");
+    if fields.types().next().is_none() || !dir.is_dir() {
+        return;
+    }
+    let path = dir.join("types.json");
+    let mut registered = match std::fs::read_to_string(&path) {
+        | Ok(text) => {
+            match serde_json::from_str::<serde_json::Value>(&text) {
+                | Ok(serde_json::Value::Object(map)) => map,
+                | _ => {
+                    tracing::warn!(path = %path.display(), "not a JSON object; leaving the property types alone");
+                    return;
+                }
+            }
+        }
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::Map::new(),
+        | Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "could not read the property types");
+            return;
+        }
+    };
+    let Some(types) = registered
+        .entry("types")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+    else {
+        return;
+    };
+    let mut changed = false;
+    for (name, kind) in fields.types() {
+        if types.get(name).and_then(serde_json::Value::as_str) != Some(kind.as_str()) {
+            types.insert(name.to_string(), kind.as_str().into());
+            changed = true;
+        }
+    }
+    if !changed {
+        return;
+    }
+    let written = serde_json::to_string_pretty(&registered)
+        .map_err(std::io::Error::other)
+        .and_then(|json| std::fs::write(&path, json));
+    if let Err(e) = written {
+        tracing::warn!(path = %path.display(), error = %e, "could not register the property types");
+    }
+}
+
+/// Every Markdown note under `dir` (recursively), with parsed frontmatter. Unreadable files and
+/// broken frontmatter are skipped rather than failing the run.
+pub fn scan(dir: &Path, fields: &FrontmatterFields) -> Vec<VaultNote> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "md"))
+        .filter_map(|e| {
+            let text = std::fs::read_to_string(e.path()).ok()?;
+            let (yaml, body) = split_frontmatter(&text);
+            let (frontmatter, repaired) = yaml.and_then(|y| frontmatter_repaired(y, fields)).unwrap_or_default();
+            if !repaired.is_empty() {
+                // Every run reads the whole vault, so this is only for those looking for it.
+                tracing::debug!(note = %e.path().display(), ?repaired, "frontmatter read leniently");
+            }
+            let tldr = body
+                .lines()
+                .skip_while(|l| !l.trim_start().starts_with("> [!tldr]"))
+                .nth(1)
+                .map(|l| l.trim_start_matches('>').trim().to_string())
+                .filter(|l| !l.is_empty());
+            Some(VaultNote {
+                link: e.path().file_stem()?.to_string_lossy().into_owned(),
+                path: e.into_path(),
+                frontmatter,
+                tldr,
+                repaired,
+            })
+        })
+        .collect()
+}
+
+/// The note on the paper `id` refers to: an arXiv id or PMID/PMCID matched against the note's
+/// frontmatter, otherwise a DOI, file name or title.
+pub fn find<'a>(notes: &'a [VaultNote], id: &str) -> Option<&'a VaultNote> {
+    let id = id.trim();
+    let same = |a: Option<&String>, b: &str| a.is_some_and(|a| a.trim().eq_ignore_ascii_case(b));
+    let arxiv = ArxivId::looks_like(id).then(|| ArxivId::parse(id).ok()).flatten();
+    let pubmed = PubmedId::parse(id).ok();
+    let doi = ["https://doi.org/", "doi:"]
+        .iter()
+        .find_map(|p| id.strip_prefix(p))
+        .unwrap_or(id);
+    let title = normalize_title(id);
+    notes.iter().find(|n| {
+        let fm = &n.frontmatter;
+        match (&arxiv, &pubmed) {
+            | (Some(arxiv), _) => {
+                fm.arxiv
+                    .as_deref()
+                    .and_then(|a| ArxivId::parse(a).ok())
+                    .is_some_and(|a| a.base() == arxiv.base())
+            }
+            | (None, Some(PubmedId::Pmid(pmid))) => same(fm.pmid.as_ref(), pmid),
+            | (None, Some(PubmedId::Pmcid(pmcid))) => same(fm.pmcid.as_ref(), pmcid),
+            | (None, None) => {
+                same(fm.doi.as_ref(), doi) || n.link.eq_ignore_ascii_case(id) || normalize_title(n.title()) == title
+            }
+        }
+    })
+}
+
+/// Vault notes worth showing the model while it summarizes `paper`: first notes on works the
+/// paper cites (matched by DOI, PMID, arXiv id or title), then the notes closest to it. Closeness
+/// is `similarity`, one embedding similarity per note, when there is one, and otherwise the number
+/// of topic tags a note shares with the paper.
+pub fn related(
+    notes: &[VaultNote],
+    paper: &ResolvedPaper,
+    similarity: Option<&[f32]>,
+    limit: usize,
+) -> Vec<RelatedNote> {
+    let references: &[Reference] = paper.structure.as_ref().map_or(&[], |s| &s.references);
+    let own_title = normalize_title(&paper.metadata.title);
+    let keywords: Vec<String> = paper.metadata.keywords.iter().map(slug::slugify).collect();
+
+    let mut out: Vec<(Relation, f32, &VaultNote)> = notes
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| normalize_title(n.title()) != own_title)
+        .filter_map(|(i, n)| {
+            if references.iter().any(|r| cites(r, n)) {
+                return Some((Relation::Cited, f32::INFINITY, n));
+            }
+            let closeness = match similarity {
+                | Some(scores) => scores.get(i).copied().filter(|&s| s >= MIN_SIMILARITY)?,
+                | None => {
+                    let shared = n.frontmatter.tags.iter().filter(|t| keywords.contains(t)).count();
+                    #[allow(clippy::cast_precision_loss)]
+                    (shared > 0).then_some(shared as f32)?
+                }
+            };
+            Some((Relation::Related, closeness, n))
+        })
+        .collect();
+    // Cited first, then the closest.
+    out.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)).then_with(|| a.2.link.cmp(&b.2.link)));
+    out.into_iter()
+        .take(limit)
+        .map(|(relation, _, n)| {
+            RelatedNote {
+                link: n.link.clone(),
+                title: n.title().to_string(),
+                tldr: n.tldr.clone(),
+                relation,
+            }
+        })
+        .collect()
+}
+
+/// The note on `topic`: one whose title or file name is the topic, ignoring case and punctuation.
+pub fn find_topic<'a>(notes: &'a [VaultNote], topic: &str) -> Option<&'a VaultNote> {
+    let topic = normalize_title(topic);
+    if topic.is_empty() {
+        return None;
+    }
+    notes
+        .iter()
+        .find(|n| normalize_title(n.title()) == topic || normalize_title(&n.link) == topic)
+}
+
+fn cites(reference: &Reference, note: &VaultNote) -> bool {
+    let fm = &note.frontmatter;
+    let same = |a: Option<&str>, b: Option<&str>| matches!((a, b), (Some(a), Some(b)) if a.eq_ignore_ascii_case(b));
+    if same(reference.doi.as_deref(), fm.doi.as_deref()) || same(reference.pmid.as_deref(), fm.pmid.as_deref()) {
+        return true;
+    }
+    if let Some(id) = fm.arxiv.as_deref().and_then(|a| ArxivId::parse(a).ok()) {
+        let listed = reference
+            .arxiv
+            .as_deref()
+            .and_then(|a| ArxivId::parse(a).ok())
+            .is_some_and(|r| r.base() == id.base());
+        if listed || reference.raw.contains(id.base()) {
+            return true;
+        }
+    }
+    // Short titles ("Introduction", "Attention") would match too eagerly.
+    reference
+        .title
+        .as_deref()
+        .map(normalize_title)
+        .is_some_and(|t| t.len() >= 20 && t == normalize_title(note.title()))
+}
+
+fn normalize_title(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,67 @@
+//! Outbound webhook: after each note is written, its rendered Markdown and everything it was made
+//! from is sent as a JSON POST to `MABEL_WEBHOOK_URL`, for n8n, Zapier or a script of your own.
+//!
+//! With `MABEL_WEBHOOK_SECRET` set, the request carries `X-Mabel-Signature-256: sha256=<hex>`, the
+//! HMAC-SHA256 of the raw body under the secret, so the receiver can check it came from you.
+
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::Serialize;
+use sha2::Sha256;
+use url::Url;
+
+use crate::{http, paper::PaperMetadata, secret::Secret, Result};
+
+pub const SIGNATURE_HEADER: &str = "X-Mabel-Signature-256";
+pub const EVENT_HEADER: &str = "X-Mabel-Event";
+
+/// The JSON body. `summary` is the paper [`crate::summarize::Summary`] or, for books, the
+/// [`crate::summarize::BookSummary`].
+#[derive(Debug, Serialize)]
+pub struct Payload<'a, S: Serialize> {
+    /// `paper` or `book`
+    pub kind: &'static str,
+    pub title: &'a str,
+    /// Note file, absolute
+    pub path: &'a Path,
+    /// Note file relative to the vault, as Obsidian links it
+    pub note: &'a Path,
+    /// The note as written
+    pub markdown: &'a str,
+    pub metadata: &'a PaperMetadata,
+    pub summary: &'a S,
+    /// What the user passed on the command line
+    pub source: &'a str,
+    pub model: &'a str,
+    pub mode: &'a str,
+    pub created: String,
+}
+
+/// POST `payload` to `url`, signed with `secret` when there is one.
+pub async fn send<S: Serialize>(
+    http: &Client,
+    url: &Url,
+    secret: Option<&Secret>,
+    payload: &Payload<'_, S>,
+) -> Result<()> {
+    let body = serde_json::to_vec(payload)?;
+    let mut req = http
+        .post(url.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, "note");
+    if let Some(secret) = secret {
+        req = req.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+    }
+    let resp = req.body(body).send().await.map_err(|e| http::request_error(url, e))?;
+    http::check_status(resp).await?;
+    Ok(())
+}
+
+/// Hex HMAC-SHA256 of `body` under `secret`.
+fn sign(secret: &Secret, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
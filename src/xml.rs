@@ -0,0 +1,208 @@
+use quick_xml::{escape::resolve_predefined_entity, events::Event, Reader};
+
+use crate::{MabelError, Result};
+
+/// A small owned XML tree.
+///
+/// The documents we consume (JATS, PubMed, TEI, Atom) are a few hundred KB at most, so it is far
+/// simpler to walk a tree than to drive quick-xml's event stream by hand in every extractor.
+/// Namespace prefixes are dropped from element and attribute names.
+#[derive(Clone, Debug, Default)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Direct child elements.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|n| {
+            match n {
+                | Node::Element(e) => Some(e),
+                | Node::Text(_) => None,
+            }
+        })
+    }
+
+    /// Direct child elements with the given name.
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |e| e.name == name)
+    }
+
+    /// First direct child with the given name.
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    /// Follow a path of direct children, e.g. `["front", "article-meta", "title-group"]`.
+    pub fn path(&self, path: &[&str]) -> Option<&Element> {
+        path.iter().try_fold(self, |el, name| el.child(name))
+    }
+
+    /// First descendant (depth-first, excluding `self`) with the given name.
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.elements()
+            .find_map(|e| if e.name == name { Some(e) } else { e.find(name) })
+    }
+
+    /// All descendants with the given name, in document order. Matches are not searched further.
+    pub fn find_all<'a>(&'a self, name: &str) -> Vec<&'a Element> {
+        let mut out = Vec::new();
+        self.collect_named(name, &mut out);
+        out
+    }
+
+    fn collect_named<'a>(&'a self, name: &str, out: &mut Vec<&'a Element>) {
+        for e in self.elements() {
+            if e.name == name {
+                out.push(e);
+            } else {
+                e.collect_named(name, out);
+            }
+        }
+    }
+
+    /// All descendant text with whitespace collapsed.
+    pub fn text(&self) -> String {
+        self.text_without(&[])
+    }
+
+    /// Like [`Element::text`], but skips subtrees rooted at any of `skip`.
+    pub fn text_without(&self, skip: &[&str]) -> String {
+        let mut raw = String::new();
+        self.push_text(skip, &mut raw);
+        collapse_whitespace(&raw)
+    }
+
+    fn push_text(&self, skip: &[&str], out: &mut String) {
+        for n in &self.children {
+            match n {
+                | Node::Text(t) => out.push_str(t),
+                | Node::Element(e) if !skip.contains(&e.name.as_str()) => e.push_text(skip, out),
+                | Node::Element(_) => out.push(' '),
+            }
+        }
+    }
+
+    /// Text of the first descendant named `name`, if present and non-empty.
+    pub fn find_text(&self, name: &str) -> Option<String> {
+        self.find(name).map(Element::text).filter(|t| !t.is_empty())
+    }
+}
+
+/// Parse a whole document and return its root element.
+pub fn parse(xml: &str, context: &'static str) -> Result<Element> {
+    let err = |source: quick_xml::Error| MabelError::Xml { context, source };
+
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Element> = vec![Element::default()];
+
+    loop {
+        match reader.read_event().map_err(err)? {
+            | Event::Start(e) => stack.push(open_element(&e).map_err(err)?),
+            | Event::Empty(e) => {
+                let el = open_element(&e).map_err(err)?;
+                push_node(&mut stack, Node::Element(el));
+            }
+            | Event::End(_) => {
+                if stack.len() > 1 {
+                    let el = stack.pop().unwrap_or_default();
+                    push_node(&mut stack, Node::Element(el));
+                }
+            }
+            | Event::Text(t) => {
+                let text = t.xml_content().map_err(|e| err(e.into()))?;
+                push_text(&mut stack, &text);
+            }
+            | Event::CData(t) => {
+                let text = t.decode().map_err(|e| err(e.into()))?;
+                push_text(&mut stack, &text);
+            }
+            | Event::GeneralRef(r) => {
+                let resolved = match r.resolve_char_ref().map_err(err)? {
+                    | Some(c) => c.to_string(),
+                    | None => {
+                        let name = r.decode().map_err(|e| err(e.into()))?;
+                        match resolve_predefined_entity(&name) {
+                            | Some(s) => s.to_string(),
+                            | None if name == "nbsp" => " ".to_string(),
+                            | None => format!("&{name};"),
+                        }
+                    }
+                };
+                push_text(&mut stack, &resolved);
+            }
+            | Event::Eof => break,
+            | Event::Comment(_) | Event::Decl(_) | Event::PI(_) | Event::DocType(_) => {}
+        }
+    }
+
+    // Unclosed elements are folded into their parents rather than rejected.
+    while stack.len() > 1 {
+        let el = stack.pop().unwrap_or_default();
+        push_node(&mut stack, Node::Element(el));
+    }
+    let document = stack.pop().unwrap_or_default();
+    document
+        .children
+        .into_iter()
+        .find_map(|n| {
+            match n {
+                | Node::Element(e) => Some(e),
+                | Node::Text(_) => None,
+            }
+        })
+        .ok_or_else(|| {
+            MabelError::Extraction {
+                reason: format!("{context}: document has no root element"),
+            }
+        })
+}
+
+fn open_element(e: &quick_xml::events::BytesStart<'_>) -> std::result::Result<Element, quick_xml::Error> {
+    let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+    let mut attrs = Vec::new();
+    for a in e.attributes() {
+        let a = a?;
+        let key = String::from_utf8_lossy(a.key.local_name().as_ref()).into_owned();
+        let value = a.unescape_value()?.into_owned();
+        attrs.push((key, value));
+    }
+    Ok(Element {
+        name,
+        attrs,
+        children: Vec::new(),
+    })
+}
+
+fn push_node(stack: &mut [Element], node: Node) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(node);
+    }
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if let Some(parent) = stack.last_mut() {
+        if let Some(Node::Text(prev)) = parent.children.last_mut() {
+            prev.push_str(text);
+        } else {
+            parent.children.push(Node::Text(text.to_string()));
+        }
+    }
+}
+
+/// Collapse runs of whitespace to a single space and trim the ends.
+pub fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}